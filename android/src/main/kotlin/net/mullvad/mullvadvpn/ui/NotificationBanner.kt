@@ -191,6 +191,12 @@ class NotificationBanner(
                     ParameterGenerationError.CustomTunnelHostResultionError -> {
                         R.string.custom_tunnel_host_resolution_error
                     }
+                    ParameterGenerationError.RetryAttemptsExhausted -> {
+                        R.string.retry_attempts_exhausted
+                    }
+                    ParameterGenerationError.ExcludedDestinationOverlap -> {
+                        R.string.excluded_destination_overlap
+                    }
                 }
             }
             is ErrorStateCause.VpnPermissionDenied -> R.string.vpn_permission_denied_error
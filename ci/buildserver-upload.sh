@@ -1,6 +1,9 @@
 #!/usr/bin/env bash
 
 UPLOAD_DIR="/home/upload/upload"
+# Ed25519 private key, in PEM format, that installers are signed with for the daemon's update
+# downloads. Release builds embed its public half through MULLVAD_UPDATE_PUBLIC_KEY.
+UPDATE_SIGNING_KEY="/home/upload/update-signing-key.pem"
 
 set -eu
 shopt -s nullglob
@@ -24,7 +27,13 @@ while true; do
     rm -f "$f.asc"
     gpg -u A1198702FC3E0A09A9AE5B75D5A1D4F266DE8DDF --pinentry-mode loopback --sign --armor --detach-sign "$f"
     scp -pB "$f.asc" build.mullvad.net:app/$version/ || true
-    yes | rm "$f" "$f_checksum" "$f.asc"
+
+    # The signed message is the version and the installer digest, see mullvad-daemon/src/update.rs
+    rm -f "$f.sig" "$f.sig-message"
+    printf '%s\n%s\n' "$version" "$(sha256sum "$f" | cut -d ' ' -f 1)" > "$f.sig-message"
+    openssl pkeyutl -sign -rawin -inkey "$UPDATE_SIGNING_KEY" -in "$f.sig-message" | base64 -w 0 > "$f.sig"
+    scp -pB "$f.sig" build.mullvad.net:app/$version/ || true
+    yes | rm "$f" "$f_checksum" "$f.asc" "$f.sig" "$f.sig-message"
   done
   for f_checksum in pdb-*.sha256; do
     sleep 1
@@ -282,6 +282,8 @@ const tunnelStateSchema = oneOf(
             'no_matching_bridge_relay',
             'no_wireguard_key',
             'custom_tunnel_host_resultion_error',
+            'retry_attempts_exhausted',
+            'excluded_destination_overlap',
           ),
         }),
       ),
@@ -59,6 +59,16 @@ function getTunnelParameterMessage(err: TunnelParameterError): string {
         'in-app-notifications',
         'Failed to resolve host of custom tunnel. Consider changing the settings',
       );
+    case 'retry_attempts_exhausted':
+      return messages.pgettext(
+        'in-app-notifications',
+        'Unable to connect after several attempts. Will try again shortly.',
+      );
+    case 'excluded_destination_overlap':
+      return messages.pgettext(
+        'in-app-notifications',
+        'An excluded network contains the relay or the tunnel gateway. Change the excluded networks to connect.',
+      );
   }
 }
 
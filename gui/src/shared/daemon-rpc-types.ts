@@ -19,7 +19,9 @@ export type TunnelParameterError =
   | 'no_matching_relay'
   | 'no_matching_bridge_relay'
   | 'no_wireguard_key'
-  | 'custom_tunnel_host_resultion_error';
+  | 'custom_tunnel_host_resultion_error'
+  | 'retry_attempts_exhausted'
+  | 'excluded_destination_overlap';
 
 export type ErrorStateCause =
   | {
@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
 use clap::value_t_or_exit;
 use mullvad_types::account::{AccountToken, VoucherError};
 
@@ -43,21 +46,57 @@ impl Command for Account {
                             .required(true),
                     ),
             )
+            .subcommand(
+                clap::SubCommand::with_name("list")
+                    .about("Display the accounts that have been used on this device"),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("switch")
+                    .about("Change to an account that has been used on this device")
+                    .arg(
+                        clap::Arg::with_name("account")
+                            .help("The label or account token of the account to use")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("label")
+                    .about("Label an account that has been used on this device")
+                    .arg(
+                        clap::Arg::with_name("token")
+                            .help("The Mullvad account token to label")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("label")
+                            .help("The new label. The label is removed if this is omitted"),
+                    ),
+            )
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         if let Some(set_matches) = matches.subcommand_matches("set") {
             let token = value_t_or_exit!(set_matches.value_of("token"), String);
-            self.set(Some(token))
+            self.set(Some(token), format)
         } else if let Some(_matches) = matches.subcommand_matches("unset") {
-            self.set(None)
+            self.set(None, format)
         } else if let Some(_matches) = matches.subcommand_matches("get") {
-            self.get()
+            self.get(format)
         } else if let Some(_matches) = matches.subcommand_matches("create") {
-            self.create()
+            self.create(format)
         } else if let Some(matches) = matches.subcommand_matches("redeem") {
             let voucher = value_t_or_exit!(matches.value_of("voucher"), String);
-            self.redeem_voucher(voucher)
+            self.redeem_voucher(voucher, format)
+        } else if let Some(_matches) = matches.subcommand_matches("list") {
+            self.list(format)
+        } else if let Some(matches) = matches.subcommand_matches("switch") {
+            let account = value_t_or_exit!(matches.value_of("account"), String);
+            self.switch(account, format)
+        } else if let Some(matches) = matches.subcommand_matches("label") {
+            let token = value_t_or_exit!(matches.value_of("token"), String);
+            let label = matches.value_of("label").map(str::to_owned);
+            self.label(token, label, format)
         } else {
             unreachable!("No account command given");
         }
@@ -65,20 +104,30 @@ impl Command for Account {
 }
 
 impl Account {
-    fn set(&self, token: Option<AccountToken>) -> Result<()> {
+    fn set(&self, token: Option<AccountToken>, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_account(token.clone())?;
         if let Some(token) = token {
-            println!("Mullvad account \"{}\" set", token);
+            format.print_message(&format!("Mullvad account \"{}\" set", token));
         } else {
-            println!("Mullvad account removed");
+            format.print_message("Mullvad account removed");
         }
         Ok(())
     }
 
-    fn get(&self) -> Result<()> {
+    fn get(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let settings = rpc.get_settings()?;
+        if format.is_json() {
+            let expiry = match settings.get_account_token() {
+                Some(account_token) => Some(rpc.get_account_data(account_token)?.expiry),
+                None => None,
+            };
+            return format::print_json(&serde_json::json!({
+                "account_token": settings.get_account_token(),
+                "expiry": expiry,
+            }));
+        }
         if let Some(account_token) = settings.get_account_token() {
             println!("Mullvad account: {}", account_token);
             let expiry = rpc.get_account_data(account_token)?;
@@ -89,18 +138,19 @@ impl Account {
         Ok(())
     }
 
-    fn create(&self) -> Result<()> {
+    fn create(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.create_new_account()?;
-        println!("New account created!");
-        self.get()
+        format.print_message("New account created!");
+        self.get(format)
     }
 
-    fn redeem_voucher(&self, mut voucher: String) -> Result<()> {
+    fn redeem_voucher(&self, mut voucher: String, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         voucher.retain(|c| c.is_alphanumeric());
 
         match rpc.submit_voucher(voucher) {
+            Ok(submission) if format.is_json() => format::print_json(&submission),
             Ok(submission) => {
                 println!(
                     "Added {} to the account",
@@ -119,6 +169,70 @@ impl Account {
         }
     }
 
+    fn list(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let entries = rpc.list_accounts()?;
+        if format.is_json() {
+            return format::print_json(&entries);
+        }
+        if entries.is_empty() {
+            println!("No accounts have been used");
+            return Ok(());
+        }
+        let current_account = rpc.get_settings()?.get_account_token();
+        for entry in entries {
+            let marker = if Some(&entry.account) == current_account.as_ref() {
+                "*"
+            } else {
+                " "
+            };
+            match entry.label {
+                Some(label) => println!("{} {} ({})", marker, entry.account, label),
+                None => println!("{} {}", marker, entry.account),
+            }
+            if let Some(last_used) = entry.last_used {
+                println!(
+                    "\tLast used    : {}",
+                    last_used.with_timezone(&chrono::offset::Local)
+                );
+            }
+            if let Some(expiry) = entry.expiry {
+                println!(
+                    "\tExpires at   : {}",
+                    expiry.with_timezone(&chrono::offset::Local)
+                );
+            }
+            if let Some(wireguard_key) = entry.wireguard_key {
+                println!("\tWireGuard key: {}", wireguard_key.key);
+            }
+        }
+        Ok(())
+    }
+
+    fn switch(&self, account: String, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.switch_account(account.clone())?;
+        format.print_message(&format!("Switched to Mullvad account \"{}\"", account));
+        Ok(())
+    }
+
+    fn label(
+        &self,
+        token: AccountToken,
+        label: Option<String>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_account_label(token.clone(), label.clone())?;
+        match label {
+            Some(label) => {
+                format.print_message(&format!("Labeled account \"{}\" as \"{}\"", token, label))
+            }
+            None => format.print_message(&format!("Removed label of account \"{}\"", token)),
+        }
+        Ok(())
+    }
+
     fn format_duration(seconds: u64) -> String {
         let dur = chrono::Duration::seconds(seconds as i64);
         if dur.num_days() > 0 {
@@ -0,0 +1,78 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
+
+use mullvad_types::{api_access::ApiAccessMethod, relay_constraints::Constraint};
+
+pub struct ApiAccess;
+
+impl Command for ApiAccess {
+    fn name(&self) -> &'static str {
+        "api-access"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Control how the Mullvad API is reached")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about(
+                        "Set the method used to reach the API. 'any' rotates among all available \
+                         methods when the API can't be reached",
+                    )
+                    .arg(
+                        clap::Arg::with_name("method")
+                            .required(true)
+                            .possible_values(&["any", "direct", "bridges", "proxy"]),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("get")
+                    .about("Display the current API access method setting"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
+        if let Some(set_matches) = matches.subcommand_matches("set") {
+            let method = match set_matches.value_of("method").unwrap() {
+                "any" => Constraint::Any,
+                "direct" => Constraint::Only(ApiAccessMethod::Direct),
+                "bridges" => Constraint::Only(ApiAccessMethod::Bridges),
+                "proxy" => Constraint::Only(ApiAccessMethod::Proxy),
+                _ => unreachable!(),
+            };
+            self.set(method, format)
+        } else if let Some(_matches) = matches.subcommand_matches("get") {
+            self.get(format)
+        } else {
+            unreachable!("No api-access command given");
+        }
+    }
+}
+
+impl ApiAccess {
+    fn set(&self, method: Constraint<ApiAccessMethod>, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_api_access_method(method)?;
+        format.print_message("Changed API access method setting");
+        Ok(())
+    }
+
+    fn get(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let api_access_method = rpc.get_settings()?.api_access_method;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "api_access_method": api_access_method
+            }));
+        }
+        match api_access_method {
+            Constraint::Any => println!("API access method: any"),
+            Constraint::Only(method) => println!("API access method: {}", method),
+        }
+        Ok(())
+    }
+}
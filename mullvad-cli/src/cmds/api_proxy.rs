@@ -0,0 +1,123 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
+use clap::value_t;
+
+use mullvad_types::api_proxy::{self, ApiProxyProtocol};
+use talpid_types::net::openvpn::ProxyAuth;
+
+use std::net::{IpAddr, SocketAddr};
+
+pub struct ApiProxy;
+
+impl Command for ApiProxy {
+    fn name(&self) -> &'static str {
+        "api-proxy"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Manage the proxy used to reach the Mullvad API")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Set a SOCKS5 or HTTP proxy to reach the API through")
+                    .arg(
+                        clap::Arg::with_name("protocol")
+                            .help("Specifies the protocol spoken by the proxy server")
+                            .required(true)
+                            .index(1)
+                            .possible_values(&["socks5", "http"]),
+                    )
+                    .arg(
+                        clap::Arg::with_name("ip")
+                            .help("Specifies the IP of the proxy server")
+                            .required(true)
+                            .index(2),
+                    )
+                    .arg(
+                        clap::Arg::with_name("port")
+                            .help("Specifies the port the proxy server is listening on")
+                            .required(true)
+                            .index(3),
+                    )
+                    .arg(
+                        clap::Arg::with_name("username")
+                            .help("Specifies the username for proxy authentication")
+                            .requires("password")
+                            .index(4),
+                    )
+                    .arg(
+                        clap::Arg::with_name("password")
+                            .help("Specifies the password for proxy authentication")
+                            .index(5),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("unset")
+                    .about("Stop using a proxy and connect to the API directly"),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display the current API proxy setting"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
+        match matches.subcommand() {
+            ("set", Some(set_matches)) => Self::handle_set(set_matches, format),
+            ("unset", _) => Self::set(None, format),
+            ("get", _) => Self::handle_get(format),
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl ApiProxy {
+    fn handle_set(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let protocol = match matches.value_of("protocol").unwrap() {
+            "socks5" => ApiProxyProtocol::Socks5,
+            "http" => ApiProxyProtocol::Http,
+            _ => unreachable!(),
+        };
+        let ip = value_t!(matches.value_of("ip"), IpAddr).unwrap_or_else(|e| e.exit());
+        let port = value_t!(matches.value_of("port"), u16).unwrap_or_else(|e| e.exit());
+        let auth = match (matches.value_of("username"), matches.value_of("password")) {
+            (Some(username), Some(password)) => Some(ProxyAuth {
+                username: username.to_string(),
+                password: password.to_string(),
+            }),
+            _ => None,
+        };
+
+        Self::set(
+            Some(api_proxy::ApiProxy {
+                protocol,
+                address: SocketAddr::new(ip, port),
+                auth,
+            }),
+            format,
+        )
+    }
+
+    fn set(api_proxy: Option<api_proxy::ApiProxy>, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_api_proxy(api_proxy)?;
+        format.print_message("Updated API proxy setting");
+        Ok(())
+    }
+
+    fn handle_get(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let api_proxy = rpc.get_settings()?.api_proxy;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "api_proxy": api_proxy }));
+        }
+        match api_proxy {
+            Some(api_proxy) => println!("API proxy: {}", api_proxy),
+            None => println!("API proxy: none"),
+        }
+        Ok(())
+    }
+}
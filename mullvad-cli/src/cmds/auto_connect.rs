@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
 use clap::value_t_or_exit;
 
 pub struct AutoConnect;
@@ -28,11 +31,12 @@ impl Command for AutoConnect {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         if let Some(set_matches) = matches.subcommand_matches("set") {
             let auto_connect = value_t_or_exit!(set_matches.value_of("policy"), String);
-            self.set(auto_connect == "on")
+            self.set(auto_connect == "on", format)
         } else if let Some(_matches) = matches.subcommand_matches("get") {
-            self.get()
+            self.get(format)
         } else {
             unreachable!("No auto-connect command given");
         }
@@ -40,16 +44,19 @@ impl Command for AutoConnect {
 }
 
 impl AutoConnect {
-    fn set(&self, auto_connect: bool) -> Result<()> {
+    fn set(&self, auto_connect: bool, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_auto_connect(auto_connect)?;
-        println!("Changed auto-connect sharing setting");
+        format.print_message("Changed auto-connect sharing setting");
         Ok(())
     }
 
-    fn get(&self) -> Result<()> {
+    fn get(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let auto_connect = rpc.get_settings()?.auto_connect;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "auto_connect": auto_connect }));
+        }
         println!("Autoconnect: {}", if auto_connect { "on" } else { "off" });
         Ok(())
     }
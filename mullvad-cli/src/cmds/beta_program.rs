@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Error, Result, PRODUCT_VERSION};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Error, Result, PRODUCT_VERSION,
+};
 use clap::value_t_or_exit;
 
 pub struct BetaProgram;
@@ -25,10 +28,16 @@ impl Command for BetaProgram {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         match matches.subcommand() {
             ("get", Some(_)) => {
                 let mut rpc = new_rpc_client()?;
                 let settings = rpc.get_settings()?;
+                if format.is_json() {
+                    return format::print_json(&serde_json::json!({
+                        "show_beta_releases": settings.show_beta_releases
+                    }));
+                }
                 let enabled_str = if settings.show_beta_releases {
                     "on"
                 } else {
@@ -50,7 +59,7 @@ impl Command for BetaProgram {
                 let mut rpc = new_rpc_client()?;
                 rpc.set_show_beta_releases(enable)?;
 
-                println!("Beta program: {}", enable_str);
+                format.print_message(&format!("Beta program: {}", enable_str));
                 Ok(())
             }
             _ => {
@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
 use clap::value_t_or_exit;
 
 pub struct BlockWhenDisconnected;
@@ -28,11 +31,12 @@ impl Command for BlockWhenDisconnected {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         if let Some(set_matches) = matches.subcommand_matches("set") {
             let block_when_disconnected = value_t_or_exit!(set_matches.value_of("policy"), String);
-            self.set(block_when_disconnected == "on")
+            self.set(block_when_disconnected == "on", format)
         } else if let Some(_matches) = matches.subcommand_matches("get") {
-            self.get()
+            self.get(format)
         } else {
             unreachable!("No block-when-disconnected command given");
         }
@@ -40,16 +44,21 @@ impl Command for BlockWhenDisconnected {
 }
 
 impl BlockWhenDisconnected {
-    fn set(&self, block_when_disconnected: bool) -> Result<()> {
+    fn set(&self, block_when_disconnected: bool, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_block_when_disconnected(block_when_disconnected)?;
-        println!("Changed always require VPN setting");
+        format.print_message("Changed always require VPN setting");
         Ok(())
     }
 
-    fn get(&self) -> Result<()> {
+    fn get(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let block_when_disconnected = rpc.get_settings()?.block_when_disconnected;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "block_when_disconnected": block_when_disconnected
+            }));
+        }
         println!(
             "Network traffic will be {} when the VPN is disconnected",
             if block_when_disconnected {
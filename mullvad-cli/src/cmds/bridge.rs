@@ -1,4 +1,7 @@
-use crate::{location, new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    location, new_rpc_client, Command, Result,
+};
 use clap::value_t;
 
 use mullvad_types::relay_constraints::{BridgeConstraints, BridgeSettings, BridgeState};
@@ -25,10 +28,11 @@ impl Command for Bridge {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         match matches.subcommand() {
-            ("set", Some(set_matches)) => Self::handle_set(set_matches),
-            ("get", _) => Self::handle_get(),
-            ("list", _) => Self::list_bridge_relays(),
+            ("set", Some(set_matches)) => Self::handle_set(set_matches, format),
+            ("get", _) => Self::handle_get(format),
+            ("list", _) => Self::list_bridge_relays(format),
             _ => unreachable!("unhandled command"),
         }
     }
@@ -125,7 +129,7 @@ fn create_set_custom_settings_subcommand() -> clap::App<'static, 'static> {
                 .arg(
                     clap::Arg::with_name("cipher")
                         .help("Specifies the cipher to use")
-                        .default_value("chacha20")
+                        .default_value("chacha20-ietf-poly1305")
                         .possible_values(SHADOWSOCKS_CIPHERS)
                         .index(4),
                 ),
@@ -145,22 +149,28 @@ fn create_set_state_subcommand() -> clap::App<'static, 'static> {
 }
 
 impl Bridge {
-    fn handle_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn handle_set(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         match matches.subcommand() {
             ("location", Some(location_matches)) => {
                 Self::handle_set_bridge_location(location_matches)
             }
             ("custom", Some(custom_matches)) => {
-                Self::handle_bridge_set_custom_settings(custom_matches)
+                Self::handle_bridge_set_custom_settings(custom_matches, format)
             }
             ("state", Some(set_matches)) => Self::handle_set_bridge_state(set_matches),
             _ => unreachable!("unhandled command"),
         }
     }
 
-    fn handle_get() -> Result<()> {
+    fn handle_get(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let settings = rpc.get_settings()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "bridge_state": settings.get_bridge_state(),
+                "bridge_settings": settings.bridge_settings,
+            }));
+        }
         println!("Bridge state - {}", settings.get_bridge_state());
         match settings.bridge_settings {
             BridgeSettings::Custom(proxy) => {
@@ -202,7 +212,10 @@ impl Bridge {
         Ok(())
     }
 
-    fn handle_bridge_set_custom_settings(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn handle_bridge_set_custom_settings(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         if let Some(args) = matches.subcommand_matches("local") {
             let local_port =
                 value_t!(args.value_of("local-port"), u16).unwrap_or_else(|e| e.exit());
@@ -279,7 +292,7 @@ impl Bridge {
             unreachable!("unhandled proxy type");
         }
 
-        println!("proxy details have been updated");
+        format.print_message("proxy details have been updated");
         Ok(())
     }
 
@@ -311,7 +324,7 @@ impl Bridge {
         println!("  cipher: {}", proxy.cipher);
     }
 
-    fn list_bridge_relays() -> Result<()> {
+    fn list_bridge_relays(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let mut locations = rpc.get_relay_locations()?;
 
@@ -343,14 +356,22 @@ impl Bridge {
         locations
             .countries
             .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-        for mut country in locations.countries {
+        for country in &mut locations.countries {
             country
                 .cities
                 .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-            println!("{} ({})", country.name, country.code);
-            for mut city in country.cities {
+            for city in &mut country.cities {
                 city.relays
                     .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+            }
+        }
+        if format.is_json() {
+            return format::print_json(&locations);
+        }
+
+        for country in locations.countries {
+            println!("{} ({})", country.name, country.code);
+            for city in country.cities {
                 println!(
                     "\t{} ({}) @ {:.5}°N, {:.5}°W",
                     city.name, city.code, city.latitude, city.longitude
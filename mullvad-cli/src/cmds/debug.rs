@@ -0,0 +1,232 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Error, Result,
+};
+use clap::value_t_or_exit;
+use mullvad_types::{
+    diagnostics::Diagnostics,
+    log_level::{LogFilter, LogLevel},
+};
+use talpid_types::diagnostics::TunnelDiagnostics;
+
+const LOG_LEVELS: &[&str] = &["off", "error", "warn", "info", "debug", "trace"];
+
+pub struct Debug;
+
+impl Command for Debug {
+    fn name(&self) -> &'static str {
+        "debug"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Debug commands used for troubleshooting")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(clap::SubCommand::with_name("diagnostics").about(
+                "Show what the daemon currently sees: firewall, DNS, routes, network links, \
+                     API address and relay list age",
+            ))
+            .subcommand(create_log_level_subcommand())
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        if let Some(_matches) = matches.subcommand_matches("diagnostics") {
+            self.diagnostics(OutputFormat::from_matches(matches))
+        } else if let Some(log_level_matches) = matches.subcommand_matches("log-level") {
+            let format = OutputFormat::from_matches(log_level_matches);
+            if let Some(set_matches) = log_level_matches.subcommand_matches("set") {
+                self.set_log_level(set_matches, format)
+            } else if let Some(_matches) = log_level_matches.subcommand_matches("get") {
+                self.get_log_level(format)
+            } else {
+                unreachable!("No log-level command given");
+            }
+        } else {
+            unreachable!("No debug command given");
+        }
+    }
+}
+
+fn create_log_level_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("log-level")
+        .about("Control the log level of the running daemon. Changes are lost on restart")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .about("Set the log level, optionally overriding it for individual modules")
+                .arg(
+                    clap::Arg::with_name("level")
+                        .required(true)
+                        .possible_values(LOG_LEVELS),
+                )
+                .arg(
+                    clap::Arg::with_name("module")
+                        .long("module")
+                        .short("m")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .value_name("MODULE=LEVEL")
+                        .validator(|value| parse_module_filter(&value).map(|_| ()))
+                        .help("Log level for a module and its submodules, e.g. talpid_core::firewall=trace"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("get").about("Display the current log level"),
+        )
+}
+
+fn parse_module_filter(value: &str) -> std::result::Result<(String, LogLevel), String> {
+    let mut parts = value.rsplitn(2, '=');
+    let level = parts.next().unwrap_or("");
+    match parts.next() {
+        Some(module) if !module.is_empty() => level
+            .parse()
+            .map(|level| (module.to_owned(), level))
+            .map_err(|error| format!("{}", error)),
+        _ => Err(format!("Expected MODULE=LEVEL, got \"{}\"", value)),
+    }
+}
+
+impl Debug {
+    fn set_log_level(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let level = value_t_or_exit!(matches.value_of("level"), LogLevel);
+        let mut filter = LogFilter::new(level);
+        for value in matches.values_of("module").into_iter().flatten() {
+            let (module, level) = parse_module_filter(value)
+                .map_err(|_| Error::InvalidCommand("Invalid module log level"))?;
+            filter.modules.insert(module, level);
+        }
+
+        let mut rpc = new_rpc_client()?;
+        rpc.set_log_level(filter)?;
+        format.print_message("Changed the log level of the daemon");
+        Ok(())
+    }
+
+    fn get_log_level(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let filter = rpc.get_log_level()?;
+        if format.is_json() {
+            return format::print_json(&filter);
+        }
+        println!("Log level: {}", filter.level);
+        for (module, level) in &filter.modules {
+            println!("\t{}: {}", module, level);
+        }
+        Ok(())
+    }
+
+    fn diagnostics(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let diagnostics = rpc.get_diagnostics()?;
+        if format.is_json() {
+            return format::print_json(&diagnostics);
+        }
+        Self::print_diagnostics(&diagnostics);
+        Ok(())
+    }
+
+    fn print_diagnostics(diagnostics: &Diagnostics) {
+        println!("API");
+        println!("\tAddress        : {}", diagnostics.api.address);
+        println!("\tAddress source : {}", diagnostics.api.address_source);
+        if let Some(updated) = diagnostics.api.address_updated {
+            println!(
+                "\tResolved at    : {}",
+                updated.with_timezone(&chrono::offset::Local)
+            );
+        }
+        println!("\tAccess method  : {}", diagnostics.api.access_method);
+
+        println!("Relay list");
+        println!(
+            "\tUpdated at     : {}",
+            diagnostics
+                .relay_list
+                .last_updated
+                .with_timezone(&chrono::offset::Local)
+        );
+        println!(
+            "\tAge            : {} seconds",
+            diagnostics.relay_list.age_secs
+        );
+
+        match &diagnostics.tunnel {
+            Some(tunnel) => Self::print_tunnel_diagnostics(tunnel),
+            None => println!("The tunnel state machine did not respond"),
+        }
+    }
+
+    fn print_tunnel_diagnostics(tunnel: &TunnelDiagnostics) {
+        println!("Firewall");
+        println!(
+            "\tActive policy  : {}",
+            tunnel.firewall.active_policy.as_deref().unwrap_or("none")
+        );
+        if let Some(tables) = &tunnel.firewall.netfilter_tables {
+            println!("\tNetfilter      : {}", Self::join_or_none(tables));
+        }
+
+        println!("DNS");
+        println!(
+            "\tBackend        : {}",
+            tunnel.dns.backend.as_deref().unwrap_or("none")
+        );
+        println!(
+            "\tServers        : {}",
+            Self::join_or_none(&tunnel.dns.servers)
+        );
+
+        if let Some(default_routes) = &tunnel.routing.default_routes {
+            println!("Default routes");
+            if default_routes.is_empty() {
+                println!("\tnone");
+            }
+            for route in default_routes {
+                println!("\t{}", route);
+            }
+        }
+
+        println!("Offline monitor");
+        println!("\tOffline        : {}", tunnel.offline_monitor.is_offline);
+        if let Some(links) = &tunnel.offline_monitor.links {
+            for link in links {
+                println!(
+                    "\tLink {} ({}): {}, {}",
+                    link.name,
+                    link.index,
+                    if link.provides_connectivity {
+                        "provides connectivity"
+                    } else {
+                        "ignored"
+                    },
+                    if link.has_addresses {
+                        "has addresses"
+                    } else {
+                        "no addresses"
+                    }
+                );
+            }
+        }
+
+        if !tunnel.errors.is_empty() {
+            println!("Errors");
+            for error in &tunnel.errors {
+                println!("\t{}", error.replace('\n', "\n\t"));
+            }
+        }
+    }
+
+    fn join_or_none<T: ToString>(items: &[T]) -> String {
+        if items.is_empty() {
+            "none".to_owned()
+        } else {
+            items
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
+use talpid_types::net::GatewayOptions;
+
+pub struct Gateway;
+
+impl Command for Gateway {
+    fn name(&self) -> &'static str {
+        "gateway"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Share the tunnel with a local network by forwarding and masquerading its \
+                 traffic through the tunnel while connected",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Act as the gateway for the network connected to an interface")
+                    .arg(
+                        clap::Arg::with_name("interface")
+                            .help("The interface connected to the local network")
+                            .required(true),
+                    )
+                    .arg(
+                        clap::Arg::with_name("dns forwarder")
+                            .long("dns-forwarder")
+                            .help("Answer DNS queries from the local network through the tunnel"),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("unset").about("Stop acting as a gateway"))
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display the current gateway setting"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
+        match matches.subcommand() {
+            ("set", Some(set_matches)) => {
+                let gateway = GatewayOptions {
+                    lan_interface: set_matches.value_of("interface").unwrap().to_owned(),
+                    dns_forwarder: set_matches.is_present("dns forwarder"),
+                };
+                self.set(Some(gateway), format)
+            }
+            ("unset", _) => self.set(None, format),
+            ("get", _) => self.get(format),
+            _ => unreachable!("No gateway command given"),
+        }
+    }
+}
+
+impl Gateway {
+    fn set(&self, gateway: Option<GatewayOptions>, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let message = if gateway.is_some() {
+            "Gateway setting has been updated"
+        } else {
+            "Gateway setting has been unset"
+        };
+        rpc.set_gateway(gateway)?;
+        format.print_message(message);
+        Ok(())
+    }
+
+    fn get(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let gateway = rpc.get_settings()?.gateway;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "gateway": gateway }));
+        }
+        match gateway {
+            Some(gateway) => println!("Gateway: {}", gateway),
+            None => println!("Gateway: unset"),
+        }
+        Ok(())
+    }
+}
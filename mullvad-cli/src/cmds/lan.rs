@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
 use clap::value_t_or_exit;
 
 pub struct Lan;
@@ -28,11 +31,12 @@ impl Command for Lan {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         if let Some(set_matches) = matches.subcommand_matches("set") {
             let allow_lan = value_t_or_exit!(set_matches.value_of("policy"), String);
-            self.set(allow_lan == "allow")
+            self.set(allow_lan == "allow", format)
         } else if let Some(_matches) = matches.subcommand_matches("get") {
-            self.get()
+            self.get(format)
         } else {
             unreachable!("No lan command given");
         }
@@ -40,16 +44,19 @@ impl Command for Lan {
 }
 
 impl Lan {
-    fn set(&self, allow_lan: bool) -> Result<()> {
+    fn set(&self, allow_lan: bool, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_allow_lan(allow_lan)?;
-        println!("Changed local network sharing setting");
+        format.print_message("Changed local network sharing setting");
         Ok(())
     }
 
-    fn get(&self) -> Result<()> {
+    fn get(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let allow_lan = rpc.get_settings()?.allow_lan;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "allow_lan": allow_lan }));
+        }
         println!(
             "Local network sharing setting: {}",
             if allow_lan { "allow" } else { "block" }
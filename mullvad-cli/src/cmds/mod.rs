@@ -4,6 +4,12 @@ use std::collections::HashMap;
 mod account;
 pub use self::account::Account;
 
+mod api_access;
+pub use self::api_access::ApiAccess;
+
+mod api_proxy;
+pub use self::api_proxy::ApiProxy;
+
 mod auto_connect;
 pub use self::auto_connect::AutoConnect;
 
@@ -19,9 +25,17 @@ pub use self::bridge::Bridge;
 mod connect;
 pub use self::connect::Connect;
 
+mod debug;
+pub use self::debug::Debug;
+
 mod disconnect;
 pub use self::disconnect::Disconnect;
 
+#[cfg(target_os = "linux")]
+mod gateway;
+#[cfg(target_os = "linux")]
+pub use self::gateway::Gateway;
+
 mod lan;
 pub use self::lan::Lan;
 
@@ -34,12 +48,18 @@ pub use self::relay::Relay;
 mod reset;
 pub use self::reset::Reset;
 
+mod socks_proxy;
+pub use self::socks_proxy::SocksProxy;
+
 mod status;
 pub use self::status::Status;
 
 mod tunnel;
 pub use self::tunnel::Tunnel;
 
+mod update;
+pub use self::update::Update;
+
 mod version;
 pub use self::version::Version;
 
@@ -47,18 +67,25 @@ pub use self::version::Version;
 pub fn get_commands() -> HashMap<&'static str, Box<dyn Command>> {
     let commands: Vec<Box<dyn Command>> = vec![
         Box::new(Account),
+        Box::new(ApiAccess),
+        Box::new(ApiProxy),
         Box::new(AutoConnect),
         Box::new(BetaProgram),
         Box::new(BlockWhenDisconnected),
         Box::new(Bridge),
         Box::new(Connect),
+        Box::new(Debug),
         Box::new(Disconnect),
+        #[cfg(target_os = "linux")]
+        Box::new(Gateway),
         Box::new(Reconnect),
         Box::new(Lan),
         Box::new(Relay),
         Box::new(Reset),
+        Box::new(SocksProxy),
         Box::new(Status),
         Box::new(Tunnel),
+        Box::new(Update),
         Box::new(Version),
     ];
     let mut map = HashMap::new();
@@ -1,8 +1,15 @@
-use crate::{location, new_rpc_client, Command, Error, Result};
+use crate::{
+    format::{self, OutputFormat},
+    location, new_rpc_client, Command, Error, Result,
+};
 use clap::{value_t, values_t};
+use ipnetwork::IpNetwork;
 use std::{
-    io::{self, BufRead},
+    ffi::OsStr,
+    fs,
+    io::{self, BufRead, Write},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::Path,
     str::FromStr,
 };
 
@@ -11,7 +18,7 @@ use mullvad_types::{
         Constraint, OpenVpnConstraints, RelayConstraintsUpdate, RelaySettingsUpdate,
         TunnelProtocol, WireguardConstraints,
     },
-    ConnectionConfig, CustomTunnelEndpoint,
+    ConnectionConfig, CustomTunnelConfigFile, CustomTunnelEndpoint,
 };
 use talpid_types::net::{all_of_the_internet, openvpn, wireguard, Endpoint, TransportProtocol};
 
@@ -75,6 +82,30 @@ impl Command for Relay {
                                         .takes_value(true)
                                         .multiple(true)
                                         .required(false),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("allowed-ip")
+                                        .help("Network to route through the peer. Everything is \
+                                               routed through it by default")
+                                        .long("allowed-ip")
+                                        .takes_value(true)
+                                        .multiple(true)
+                                        .number_of_values(1)
+                                        .required(false),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("preshared-key")
+                                        .help("Read a base64 encoded preshared key from standard \
+                                               input, after the private key")
+                                        .long("preshared-key"),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("keepalive")
+                                        .help("Interval in seconds at which to send keepalive \
+                                               packets to the peer")
+                                        .long("keepalive")
+                                        .takes_value(true)
+                                        .required(false),
                                 ),
                             )
                             .subcommand(clap::SubCommand::with_name("openvpn")
@@ -108,6 +139,36 @@ impl Command for Relay {
                                         .index(5),
                                 )
                             )
+                            .subcommand(clap::SubCommand::with_name("import")
+                                .about("Read the custom relay from a wg-quick style WireGuard \
+                                        configuration or an OpenVPN profile")
+                                .arg(
+                                    clap::Arg::with_name("file")
+                                        .help("Path to the .conf or .ovpn file")
+                                        .required(true)
+                                        .index(1),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("credentials")
+                                        .help("File with the OpenVPN username and password on \
+                                               separate lines, for profiles without an inline \
+                                               <auth-user-pass> block")
+                                        .long("credentials")
+                                        .takes_value(true),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("v4-gateway")
+                                        .help("IPv4 gateway address of a WireGuard tunnel")
+                                        .long("v4-gateway")
+                                        .takes_value(true),
+                                )
+                                .arg(
+                                    clap::Arg::with_name("v6-gateway")
+                                        .help("IPv6 gateway address of a WireGuard tunnel")
+                                        .long("v6-gateway")
+                                        .takes_value(true),
+                                )
+                            )
                     )
                     .subcommand(
                         location::get_subcommand()
@@ -126,6 +187,8 @@ impl Command for Relay {
                             .arg(clap::Arg::with_name("port").required(true).index(2))
                             .arg(
                                 clap::Arg::with_name("transport protocol")
+                                    .help("Transport protocol. For WireGuard, TCP means that \
+                                           the traffic is tunneled over TCP to the relay")
                                     .long("protocol")
                                     .required(false)
                                     .default_value("any")
@@ -144,6 +207,15 @@ impl Command for Relay {
                                 ),
             )
             .subcommand(clap::SubCommand::with_name("get"))
+            .subcommand(
+                clap::SubCommand::with_name("export")
+                    .about("Export the current WireGuard connection as a wg-quick style \
+                            configuration, which includes the private key")
+                    .arg(
+                        clap::Arg::with_name("file")
+                            .help("Path to write the configuration to, instead of printing it"),
+                    ),
+            )
             .subcommand(
                 clap::SubCommand::with_name("list").about("List available countries and cities"),
             )
@@ -154,14 +226,17 @@ impl Command for Relay {
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         if let Some(set_matches) = matches.subcommand_matches("set") {
-            self.set(set_matches)
+            self.set(set_matches, format)
         } else if matches.subcommand_matches("get").is_some() {
-            self.get()
+            self.get(format)
+        } else if let Some(export_matches) = matches.subcommand_matches("export") {
+            self.export(export_matches, format)
         } else if matches.subcommand_matches("list").is_some() {
-            self.list()
+            self.list(format)
         } else if matches.subcommand_matches("update").is_some() {
-            self.update()
+            self.update(format)
         } else {
             unreachable!("No relay command given");
         }
@@ -169,34 +244,123 @@ impl Command for Relay {
 }
 
 impl Relay {
-    fn update_constraints(&self, update: RelaySettingsUpdate) -> Result<()> {
+    fn update_constraints(&self, update: RelaySettingsUpdate, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.update_relay_settings(update)?;
-        println!("Relay constraints updated");
+        format.print_message("Relay constraints updated");
         Ok(())
     }
 
-    fn set(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn set(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         if let Some(custom_matches) = matches.subcommand_matches("custom") {
-            self.set_custom(custom_matches)
+            self.set_custom(custom_matches, format)
         } else if let Some(location_matches) = matches.subcommand_matches("location") {
-            self.set_location(location_matches)
+            self.set_location(location_matches, format)
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel") {
-            self.set_tunnel(tunnel_matches)
+            self.set_tunnel(tunnel_matches, format)
         } else if let Some(tunnel_matches) = matches.subcommand_matches("tunnel-protocol") {
-            self.set_tunnel_protocol(tunnel_matches)
+            self.set_tunnel_protocol(tunnel_matches, format)
         } else {
             unreachable!("No set relay command given");
         }
     }
 
-    fn set_custom(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn set_custom(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         let custom_endpoint = match matches.subcommand() {
             ("openvpn", Some(openvpn_matches)) => Self::read_custom_openvpn_relay(openvpn_matches),
             ("wireguard", Some(wg_matches)) => Self::read_custom_wireguard_relay(wg_matches),
+            ("import", Some(import_matches)) => return self.import_custom(import_matches, format),
             (_unknown_tunnel, _) => unreachable!("No set relay command given"),
         };
-        self.update_constraints(RelaySettingsUpdate::CustomTunnelEndpoint(custom_endpoint))
+        self.update_constraints(
+            RelaySettingsUpdate::CustomTunnelEndpoint(custom_endpoint),
+            format,
+        )
+    }
+
+    fn import_custom(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let path = Path::new(matches.value_of("file").unwrap());
+        let config = fs::read_to_string(path).map_err(Error::ReadInput)?;
+        let config_file = if Self::is_wireguard_config(path, &config) {
+            // The gateway of a WireGuard tunnel is not part of its configuration file.
+            if !matches.is_present("v4-gateway") {
+                return Err(Error::InvalidCommand(
+                    "--v4-gateway is required to import a WireGuard configuration",
+                ));
+            }
+            let (ipv4_gateway, ipv6_gateway) = Self::read_gateways(matches);
+            CustomTunnelConfigFile::Wireguard {
+                config,
+                ipv4_gateway,
+                ipv6_gateway,
+            }
+        } else {
+            let credentials = match matches.value_of("credentials") {
+                Some(credentials_path) => {
+                    Some(fs::read_to_string(credentials_path).map_err(Error::ReadInput)?)
+                }
+                None => None,
+            };
+            CustomTunnelConfigFile::OpenVpn {
+                config,
+                credentials,
+            }
+        };
+
+        let mut rpc = new_rpc_client()?;
+        let ignored_directives = rpc.import_custom_tunnel(config_file)?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "ignored_directives": ignored_directives
+            }));
+        }
+        for directive in ignored_directives {
+            println!("Ignored {}", directive);
+        }
+        println!("Relay constraints updated");
+        Ok(())
+    }
+
+    /// OpenVPN profiles may use the `.conf` extension too, so WireGuard configurations are
+    /// recognized by their `[Interface]` section.
+    fn is_wireguard_config(path: &Path, config: &str) -> bool {
+        if path.extension() == Some(OsStr::new("ovpn")) {
+            return false;
+        }
+        config
+            .lines()
+            .any(|line| line.trim().eq_ignore_ascii_case("[interface]"))
+    }
+
+    fn export(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let config = rpc.export_wireguard_config()?;
+        match matches.value_of("file") {
+            Some(path) => {
+                Self::write_private_file(Path::new(path), &config).map_err(Error::WriteOutput)?;
+                format.print_message(&format!("Wrote the WireGuard configuration to {}", path));
+                Ok(())
+            }
+            None if format.is_json() => format::print_json(&serde_json::json!({
+                "config": config
+            })),
+            None => {
+                print!("{}", config);
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes a file that only the current user can read, since it contains a private key.
+    fn write_private_file(path: &Path, contents: &str) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(path)?.write_all(contents.as_bytes())
     }
 
     fn read_custom_openvpn_relay(matches: &clap::ArgMatches<'_>) -> CustomTunnelEndpoint {
@@ -216,12 +380,7 @@ impl Relay {
         )
     }
 
-    fn read_custom_wireguard_relay(matches: &clap::ArgMatches<'_>) -> CustomTunnelEndpoint {
-        let host = value_t!(matches.value_of("host"), String).unwrap_or_else(|e| e.exit());
-        let port = value_t!(matches.value_of("port"), u16).unwrap_or_else(|e| e.exit());
-        let addresses = values_t!(matches.values_of("addr"), IpAddr).unwrap_or_else(|e| e.exit());
-        let peer_key_str =
-            value_t!(matches.value_of("peer-key"), String).unwrap_or_else(|e| e.exit());
+    fn read_gateways(matches: &clap::ArgMatches<'_>) -> (Ipv4Addr, Option<Ipv6Addr>) {
         let ipv4_gateway =
             value_t!(matches.value_of("v4-gateway"), Ipv4Addr).unwrap_or_else(|e| e.exit());
         let ipv6_gateway = match value_t!(matches.value_of("v6-gateway"), Ipv6Addr) {
@@ -231,13 +390,41 @@ impl Relay {
                 _ => e.exit(),
             },
         };
+        (ipv4_gateway, ipv6_gateway)
+    }
+
+    fn read_custom_wireguard_relay(matches: &clap::ArgMatches<'_>) -> CustomTunnelEndpoint {
+        let host = value_t!(matches.value_of("host"), String).unwrap_or_else(|e| e.exit());
+        let port = value_t!(matches.value_of("port"), u16).unwrap_or_else(|e| e.exit());
+        let addresses = values_t!(matches.values_of("addr"), IpAddr).unwrap_or_else(|e| e.exit());
+        let peer_key_str =
+            value_t!(matches.value_of("peer-key"), String).unwrap_or_else(|e| e.exit());
+        let (ipv4_gateway, ipv6_gateway) = Self::read_gateways(matches);
+        let allowed_ips = if matches.is_present("allowed-ip") {
+            values_t!(matches.values_of("allowed-ip"), IpNetwork).unwrap_or_else(|e| e.exit())
+        } else {
+            all_of_the_internet()
+        };
+        let persistent_keepalive = if matches.is_present("keepalive") {
+            Some(value_t!(matches.value_of("keepalive"), u16).unwrap_or_else(|e| e.exit()))
+        } else {
+            None
+        };
         let mut private_key_str = String::new();
-        println!("Reading private key from standard input");
+        eprintln!("Reading private key from standard input");
         let _ = io::stdin().lock().read_line(&mut private_key_str);
         if private_key_str.trim().is_empty() {
             eprintln!("Expected to read private key from standard input");
         }
         let private_key = Self::validate_wireguard_key(&private_key_str).into();
+        let psk = if matches.is_present("preshared-key") {
+            let mut psk_str = String::new();
+            eprintln!("Reading preshared key from standard input");
+            let _ = io::stdin().lock().read_line(&mut psk_str);
+            Some(Self::validate_wireguard_key(&psk_str).into())
+        } else {
+            None
+        };
         let peer_public_key = Self::validate_wireguard_key(&peer_key_str).into();
 
 
@@ -250,94 +437,119 @@ impl Relay {
                 },
                 peer: wireguard::PeerConfig {
                     public_key: peer_public_key,
-                    allowed_ips: all_of_the_internet(),
+                    allowed_ips,
                     endpoint: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+                    persistent_keepalive,
+                    psk,
                 },
+                additional_peers: vec![],
                 ipv4_gateway,
                 ipv6_gateway,
+                dns_servers: vec![],
+                obfuscation: None,
             }),
         )
     }
 
-    fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
-        let key_bytes = base64::decode(key_str.trim()).unwrap_or_else(|e| {
-            eprintln!("Failed to decode wireguard key: {}", e);
+    pub(crate) fn validate_wireguard_key(key_str: &str) -> [u8; 32] {
+        Self::parse_wireguard_key(key_str).unwrap_or_else(|error| {
+            eprintln!("{}", error);
             std::process::exit(1);
-        });
+        })
+    }
+
+    /// Decodes a base64 encoded WireGuard key. Surrounding whitespace is ignored.
+    pub(crate) fn parse_wireguard_key(key_str: &str) -> std::result::Result<[u8; 32], String> {
+        let key_bytes = base64::decode(key_str.trim())
+            .map_err(|e| format!("Failed to decode wireguard key: {}", e))?;
 
         let mut key = [0u8; 32];
         if key_bytes.len() != 32 {
-            eprintln!(
+            return Err(format!(
                 "Expected key length to be 32 bytes, got {}",
                 key_bytes.len()
-            );
-            std::process::exit(1);
+            ));
         }
 
         key.copy_from_slice(&key_bytes);
-        key
+        Ok(key)
     }
 
-    fn set_location(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn set_location(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         let location_constraint = location::get_constraint(matches);
 
-        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
-            location: Some(location_constraint),
-            ..Default::default()
-        }))
+        self.update_constraints(
+            RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                location: Some(location_constraint),
+                ..Default::default()
+            }),
+            format,
+        )
     }
 
-    fn set_tunnel(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn set_tunnel(&self, matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         let vpn_protocol = matches.value_of("vpn protocol").unwrap();
         let port = parse_port_constraint(matches.value_of("port").unwrap())?;
         let protocol = parse_protocol_constraint(matches.value_of("transport protocol").unwrap());
 
         match vpn_protocol {
-            "wireguard" => {
-                if let Constraint::Only(TransportProtocol::Tcp) = protocol {
-                    return Err(Error::InvalidCommand("WireGuard does not support TCP"));
-                }
-                self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+            "wireguard" => self.update_constraints(
+                RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
                     location: None,
                     tunnel_protocol: None,
-                    wireguard_constraints: Some(WireguardConstraints { port }),
+                    wireguard_constraints: Some(WireguardConstraints {
+                        port,
+                        transport_protocol: protocol,
+                    }),
                     ..Default::default()
-                }))
-            }
-            "openvpn" => {
-                self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                }),
+                format,
+            ),
+            "openvpn" => self.update_constraints(
+                RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
                     location: None,
                     tunnel_protocol: None,
                     openvpn_constraints: Some(OpenVpnConstraints { port, protocol }),
                     ..Default::default()
-                }))
-            }
+                }),
+                format,
+            ),
             _ => unreachable!(),
         }
     }
 
-    fn set_tunnel_protocol(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn set_tunnel_protocol(
+        &self,
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         let tunnel_protocol = match matches.value_of("tunnel protocol").unwrap() {
             "wireguard" => Constraint::Only(TunnelProtocol::Wireguard),
             "openvpn" => Constraint::Only(TunnelProtocol::OpenVpn),
             "any" => Constraint::Any,
             _ => unreachable!(),
         };
-        self.update_constraints(RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
-            tunnel_protocol: Some(tunnel_protocol),
-            ..Default::default()
-        }))
+        self.update_constraints(
+            RelaySettingsUpdate::Normal(RelayConstraintsUpdate {
+                tunnel_protocol: Some(tunnel_protocol),
+                ..Default::default()
+            }),
+            format,
+        )
     }
 
-    fn get(&self) -> Result<()> {
+    fn get(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let constraints = rpc.get_settings()?.get_relay_settings();
+        if format.is_json() {
+            return format::print_json(&constraints);
+        }
         println!("Current constraints: {}", constraints);
 
         Ok(())
     }
 
-    fn list(&self) -> Result<()> {
+    fn list(&self, format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let mut locations = rpc.get_relay_locations()?;
 
@@ -369,14 +581,22 @@ impl Relay {
         locations
             .countries
             .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-        for mut country in locations.countries {
+        for country in &mut locations.countries {
             country
                 .cities
                 .sort_by(|c1, c2| natord::compare_ignore_case(&c1.name, &c2.name));
-            println!("{} ({})", country.name, country.code);
-            for mut city in country.cities {
+            for city in &mut country.cities {
                 city.relays
                     .sort_by(|r1, r2| natord::compare_ignore_case(&r1.hostname, &r2.hostname));
+            }
+        }
+        if format.is_json() {
+            return format::print_json(&locations);
+        }
+
+        for country in locations.countries {
+            println!("{} ({})", country.name, country.code);
+            for city in country.cities {
                 println!(
                     "\t{} ({}) @ {:.5}°N, {:.5}°W",
                     city.name, city.code, city.latitude, city.longitude
@@ -401,9 +621,9 @@ impl Relay {
         Ok(())
     }
 
-    fn update(&self) -> Result<()> {
+    fn update(&self, format: OutputFormat) -> Result<()> {
         new_rpc_client()?.update_relay_locations()?;
-        println!("Updating relay list in the background...");
+        format.print_message("Updating relay list in the background...");
         Ok(())
     }
 }
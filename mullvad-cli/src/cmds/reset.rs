@@ -27,7 +27,7 @@ impl Command for Reset {
 
 impl Reset {
     fn receive_confirmation() -> bool {
-        println!("Are you sure you want to disconnect, log out, delete all settings, logs and cache files for the Mullvad VPN system service? [Yes/No (default)]");
+        eprintln!("Are you sure you want to disconnect, log out, delete all settings, logs and cache files for the Mullvad VPN system service? [Yes/No (default)]");
         loop {
             let mut buf = String::new();
             if let Err(e) = stdin().read_line(&mut buf) {
@@ -37,7 +37,7 @@ impl Reset {
             match buf.trim() {
                 "Yes" => return true,
                 "No" | "no" | "" => return false,
-                _ => println!("Unexpected response. Please enter \"Yes\" or \"No\""),
+                _ => eprintln!("Unexpected response. Please enter \"Yes\" or \"No\""),
             }
         }
     }
@@ -0,0 +1,94 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
+use clap::value_t;
+
+use talpid_types::net::{openvpn::ProxyAuth, proxy::TunnelSocksProxy};
+
+pub struct SocksProxy;
+
+impl Command for SocksProxy {
+    fn name(&self) -> &'static str {
+        "socks-proxy"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about(
+                "Manage the local SOCKS5 proxy that sends its connections through the tunnel. \
+                 It only accepts connections while connected",
+            )
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("set")
+                    .about("Run a SOCKS5 proxy on localhost")
+                    .arg(
+                        clap::Arg::with_name("port")
+                            .help("Specifies the localhost port to listen on")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        clap::Arg::with_name("username")
+                            .help("Specifies the username that clients have to authenticate with")
+                            .requires("password")
+                            .index(2),
+                    )
+                    .arg(
+                        clap::Arg::with_name("password")
+                            .help("Specifies the password that clients have to authenticate with")
+                            .index(3),
+                    ),
+            )
+            .subcommand(clap::SubCommand::with_name("unset").about("Stop running the proxy"))
+            .subcommand(
+                clap::SubCommand::with_name("get").about("Display the current proxy setting"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
+        match matches.subcommand() {
+            ("set", Some(set_matches)) => Self::handle_set(set_matches, format),
+            ("unset", _) => Self::set(None, format),
+            ("get", _) => Self::handle_get(format),
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl SocksProxy {
+    fn handle_set(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let port = value_t!(matches.value_of("port"), u16).unwrap_or_else(|e| e.exit());
+        let auth = match (matches.value_of("username"), matches.value_of("password")) {
+            (Some(username), Some(password)) => Some(ProxyAuth {
+                username: username.to_string(),
+                password: password.to_string(),
+            }),
+            _ => None,
+        };
+
+        Self::set(Some(TunnelSocksProxy { port, auth }), format)
+    }
+
+    fn set(proxy: Option<TunnelSocksProxy>, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_tunnel_socks_proxy(proxy)?;
+        format.print_message("Updated SOCKS5 proxy setting");
+        Ok(())
+    }
+
+    fn handle_get(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let proxy = rpc.get_settings()?.tunnel_socks_proxy;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "socks_proxy": proxy }));
+        }
+        match proxy {
+            Some(proxy) => println!("SOCKS5 proxy: {}", proxy),
+            None => println!("SOCKS5 proxy: none"),
+        }
+        Ok(())
+    }
+}
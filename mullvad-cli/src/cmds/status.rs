@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Error, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Error, Result,
+};
 use futures::{Future, Stream};
 use mullvad_ipc_client::DaemonRpcClient;
 use mullvad_types::{auth_failed::AuthFailed, states::TunnelState, DaemonEvent};
@@ -22,7 +25,10 @@ impl Command for Status {
             )
             .subcommand(
                 clap::SubCommand::with_name("listen")
-                    .about("Listen for VPN tunnel state changes")
+                    .about(
+                        "Listen for VPN tunnel state changes. With --json, every daemon event is \
+                         printed as one line of JSON",
+                    )
                     .arg(
                         clap::Arg::with_name("verbose")
                             .short("v")
@@ -35,6 +41,10 @@ impl Command for Status {
         let mut rpc = new_rpc_client()?;
         let state = rpc.get_state()?;
 
+        if OutputFormat::from_matches(matches).is_json() {
+            return run_json(&mut rpc, state, matches);
+        }
+
         print_state(&state);
         if matches.is_present("location") {
             print_location(&mut rpc)?;
@@ -80,6 +90,11 @@ impl Command for Status {
                             println!("{}", key_event);
                         }
                     }
+                    DaemonEvent::WireguardKeyRotation(rotation_event) => {
+                        if verbose {
+                            println!("{}", rotation_event);
+                        }
+                    }
                 }
             }
         }
@@ -87,6 +102,33 @@ impl Command for Status {
     }
 }
 
+/// Prints the state, and any requested location, as a single JSON object. If listening, every
+/// daemon event is then printed on its own line, starting with the current tunnel state.
+fn run_json(
+    rpc: &mut DaemonRpcClient,
+    state: TunnelState,
+    matches: &clap::ArgMatches<'_>,
+) -> Result<()> {
+    if matches.subcommand_matches("listen").is_none() {
+        let mut status = serde_json::json!({ "state": state });
+        if matches.is_present("location") {
+            status["location"] = serde_json::to_value(rpc.get_current_location()?)
+                .map_err(Error::SerializeOutput)?;
+        }
+        return format::print_json(&status);
+    }
+
+    let subscription = rpc
+        .daemon_event_subscribe()
+        .wait()
+        .map_err(Error::CantSubscribe)?;
+    format::print_json(&DaemonEvent::TunnelState(state))?;
+    for event in subscription.wait() {
+        format::print_json(&event?)?;
+    }
+    Ok(())
+}
+
 fn print_state(state: &TunnelState) {
     use self::TunnelState::*;
     print!("Tunnel status: ");
@@ -1,7 +1,13 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Error, Result,
+};
 use clap::value_t;
+use ipnetwork::IpNetwork;
+use std::io::{self, BufRead};
 
-use mullvad_types::settings::TunnelOptions;
+use mullvad_types::{settings::TunnelOptions, wireguard::RegisteredKey};
+use talpid_types::net::{openvpn::ExtraOption, wireguard::PublicKey};
 
 pub struct Tunnel;
 
@@ -11,19 +17,25 @@ impl Command for Tunnel {
     }
 
     fn clap_subcommand(&self) -> clap::App<'static, 'static> {
-        clap::SubCommand::with_name(self.name())
+        let mut subcommand = clap::SubCommand::with_name(self.name())
             .about("Manage tunnel specific options")
             .setting(clap::AppSettings::SubcommandRequiredElseHelp)
             .subcommand(create_openvpn_subcommand())
             .subcommand(create_wireguard_subcommand())
-            .subcommand(create_ipv6_subcommand())
+            .subcommand(create_ipv6_subcommand());
+        if !cfg!(windows) {
+            subcommand = subcommand.subcommand(create_exclude_subcommand());
+        }
+        subcommand
     }
 
     fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
         match matches.subcommand() {
-            ("openvpn", Some(openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches),
-            ("wireguard", Some(wg_matches)) => Self::handle_wireguard_cmd(wg_matches),
-            ("ipv6", Some(ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches),
+            ("openvpn", Some(openvpn_matches)) => Self::handle_openvpn_cmd(openvpn_matches, format),
+            ("wireguard", Some(wg_matches)) => Self::handle_wireguard_cmd(wg_matches, format),
+            ("ipv6", Some(ipv6_matches)) => Self::handle_ipv6_cmd(ipv6_matches, format),
+            ("exclude", Some(exclude_matches)) => Self::handle_exclude_cmd(exclude_matches, format),
             _ => {
                 unreachable!("unhandled comand");
             }
@@ -32,11 +44,52 @@ impl Command for Tunnel {
 }
 
 fn create_wireguard_subcommand() -> clap::App<'static, 'static> {
-    clap::SubCommand::with_name("wireguard")
+    let mut subcommand = clap::SubCommand::with_name("wireguard")
         .about("Manage options for Wireguard tunnels")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_wireguard_mtu_subcommand())
-        .subcommand(create_wireguard_keys_subcommand())
+        .subcommand(create_wireguard_keys_subcommand());
+    if cfg!(target_os = "linux") {
+        subcommand = subcommand
+            .subcommand(create_wireguard_policy_routing_subcommand())
+            .subcommand(create_wireguard_netns_subcommand());
+    }
+    subcommand
+}
+
+fn create_wireguard_netns_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("netns")
+        .about(
+            "Run the tunnel in a named network namespace, so that only processes in that \
+             namespace use it. Routes, DNS and firewall rules are only applied inside the \
+             namespace",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .about("Set the network namespace. It is created if it doesn't exist")
+                .arg(clap::Arg::with_name("name").required(true)),
+        )
+        .subcommand(clap::SubCommand::with_name("unset").about("Run the tunnel on the host again"))
+}
+
+fn create_wireguard_policy_routing_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("policy-routing")
+        .about(
+            "Route tunnel traffic through a dedicated routing table selected by a firewall mark, \
+             instead of adding routes to the main table",
+        )
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set").arg(
+                clap::Arg::with_name("policy")
+                    .required(true)
+                    .takes_value(true)
+                    .possible_values(&["on", "off"]),
+            ),
+        )
 }
 
 fn create_wireguard_mtu_subcommand() -> clap::App<'static, 'static> {
@@ -56,7 +109,28 @@ fn create_wireguard_keys_subcommand() -> clap::App<'static, 'static> {
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(clap::SubCommand::with_name("check"))
         .subcommand(clap::SubCommand::with_name("regenerate"))
+        .subcommand(
+            clap::SubCommand::with_name("list")
+                .about("List all keys that are registered on the account"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("revoke")
+                .about("Remove a key from the account")
+                .arg(
+                    clap::Arg::with_name("public-key")
+                        .help("The base64 encoded public key to remove")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("import")
+                .about("Use an existing private key, for example one from another device")
+                .arg(clap::Arg::with_name("private-key").help(
+                    "The base64 encoded private key. It is read from standard input if omitted",
+                )),
+        )
         .subcommand(create_wireguard_keys_rotation_interval_subcommand())
+        .subcommand(create_wireguard_keys_rotation_policy_subcommand())
 }
 
 fn create_wireguard_keys_rotation_interval_subcommand() -> clap::App<'static, 'static> {
@@ -70,12 +144,38 @@ fn create_wireguard_keys_rotation_interval_subcommand() -> clap::App<'static, 's
         )
 }
 
+fn create_wireguard_keys_rotation_policy_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("rotation-policy")
+        .about("Manage how automatic key rotation replaces the key")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .arg(
+                    clap::Arg::with_name("grace-period")
+                        .long("grace-period")
+                        .takes_value(true)
+                        .help(
+                            "Minutes to keep the old key after a rotation. The rotation is \
+                             rolled back if the new key has not been used within this time",
+                        ),
+                )
+                .arg(
+                    clap::Arg::with_name("rotate-while-connected")
+                        .long("rotate-while-connected")
+                        .takes_value(true)
+                        .possible_values(&["on", "off"])
+                        .help("Whether the key may be rotated while the tunnel is up"),
+                ),
+        )
+}
 
 fn create_openvpn_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("openvpn")
         .about("Manage options for OpenVPN tunnels")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
         .subcommand(create_openvpn_mssfix_subcommand())
+        .subcommand(create_openvpn_option_subcommand())
 }
 
 fn create_openvpn_mssfix_subcommand() -> clap::App<'static, 'static> {
@@ -89,6 +189,32 @@ fn create_openvpn_mssfix_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_openvpn_option_subcommand() -> clap::App<'static, 'static> {
+    let option_arg = clap::Arg::with_name("option")
+        .help(
+            "The name of the OpenVPN option. One of fragment, sndbuf, rcvbuf, tun-mtu, ping or \
+             ping-exit",
+        )
+        .required(true)
+        .validator(|option| {
+            option
+                .parse::<ExtraOption>()
+                .map(|_| ())
+                .map_err(|error| error.to_string())
+        });
+
+    clap::SubCommand::with_name("option")
+        .about("Configure extra options that are passed to OpenVPN")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("get"))
+        .subcommand(
+            clap::SubCommand::with_name("set")
+                .arg(option_arg.clone())
+                .arg(clap::Arg::with_name("value").required(true)),
+        )
+        .subcommand(clap::SubCommand::with_name("unset").arg(option_arg))
+}
+
 fn create_ipv6_subcommand() -> clap::App<'static, 'static> {
     clap::SubCommand::with_name("ipv6")
         .setting(clap::AppSettings::SubcommandRequiredElseHelp)
@@ -103,41 +229,96 @@ fn create_ipv6_subcommand() -> clap::App<'static, 'static> {
         )
 }
 
+fn create_exclude_subcommand() -> clap::App<'static, 'static> {
+    clap::SubCommand::with_name("exclude")
+        .about("Manage destination networks that are reached outside of the tunnel")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(clap::SubCommand::with_name("list"))
+        .subcommand(
+            clap::SubCommand::with_name("add").arg(
+                clap::Arg::with_name("network")
+                    .help("The network to exclude, in CIDR notation. E.g. 192.0.2.0/24")
+                    .required(true),
+            ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("remove")
+                .arg(clap::Arg::with_name("network").required(true)),
+        )
+        .subcommand(clap::SubCommand::with_name("clear").about("Remove all excluded networks"))
+}
+
 impl Tunnel {
-    fn handle_openvpn_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn handle_openvpn_cmd(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         match matches.subcommand() {
-            ("mssfix", Some(mssfix_matches)) => Self::handle_openvpn_mssfix_cmd(mssfix_matches),
+            ("mssfix", Some(mssfix_matches)) => {
+                Self::handle_openvpn_mssfix_cmd(mssfix_matches, format)
+            }
+            ("option", Some(option_matches)) => match option_matches.subcommand() {
+                ("get", _) => Self::process_openvpn_option_get(format),
+                ("set", Some(matches)) => Self::process_openvpn_option_set(matches, format),
+                ("unset", Some(matches)) => Self::process_openvpn_option_unset(matches, format),
+                _ => unreachable!("unhandled command"),
+            },
             _ => unreachable!("unhandled command"),
         }
     }
 
-    fn handle_openvpn_mssfix_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn handle_openvpn_mssfix_cmd(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         match matches.subcommand() {
-            ("get", Some(_)) => Self::process_openvpn_mssfix_get(),
-            ("unset", Some(_)) => Self::process_openvpn_mssfix_unset(),
-            ("set", Some(set_matches)) => Self::process_openvpn_mssfix_set(set_matches),
+            ("get", Some(_)) => Self::process_openvpn_mssfix_get(format),
+            ("unset", Some(_)) => Self::process_openvpn_mssfix_unset(format),
+            ("set", Some(set_matches)) => Self::process_openvpn_mssfix_set(set_matches, format),
             _ => unreachable!("unhandled command"),
         }
     }
 
-    fn handle_wireguard_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn handle_wireguard_cmd(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         match matches.subcommand() {
             ("mtu", Some(matches)) => match matches.subcommand() {
-                ("get", _) => Self::process_wireguard_mtu_get(),
-                ("set", Some(matches)) => Self::process_wireguard_mtu_set(matches),
-                ("unset", _) => Self::process_wireguard_mtu_unset(),
+                ("get", _) => Self::process_wireguard_mtu_get(format),
+                ("set", Some(matches)) => Self::process_wireguard_mtu_set(matches, format),
+                ("unset", _) => Self::process_wireguard_mtu_unset(format),
+                _ => unreachable!("unhandled command"),
+            },
+
+            ("policy-routing", Some(matches)) => match matches.subcommand() {
+                ("get", _) => Self::process_wireguard_policy_routing_get(format),
+                ("set", Some(matches)) => {
+                    Self::process_wireguard_policy_routing_set(matches, format)
+                }
+                _ => unreachable!("unhandled command"),
+            },
+
+            ("netns", Some(matches)) => match matches.subcommand() {
+                ("get", _) => Self::process_wireguard_netns_get(format),
+                ("set", Some(matches)) => Self::process_wireguard_netns_set(matches, format),
+                ("unset", _) => Self::process_wireguard_netns_unset(format),
                 _ => unreachable!("unhandled command"),
             },
 
             ("key", Some(matches)) => match matches.subcommand() {
-                ("check", _) => Self::process_wireguard_key_check(),
-                ("regenerate", _) => Self::process_wireguard_key_generate(),
+                ("check", _) => Self::process_wireguard_key_check(format),
+                ("regenerate", _) => Self::process_wireguard_key_generate(format),
+                ("list", _) => Self::process_wireguard_key_list(format),
+                ("revoke", Some(matches)) => Self::process_wireguard_key_revoke(matches, format),
+                ("import", Some(matches)) => Self::process_wireguard_key_import(matches, format),
                 ("rotation-interval", Some(matches)) => match matches.subcommand() {
-                    ("get", _) => Self::process_wireguard_rotation_interval_get(),
+                    ("get", _) => Self::process_wireguard_rotation_interval_get(format),
                     ("set", Some(matches)) => {
-                        Self::process_wireguard_rotation_interval_set(matches)
+                        Self::process_wireguard_rotation_interval_set(matches, format)
+                    }
+                    ("reset", _) => Self::process_wireguard_rotation_interval_reset(format),
+                    _ => unreachable!("unhandled command"),
+                },
+                ("rotation-policy", Some(matches)) => match matches.subcommand() {
+                    ("get", _) => Self::process_wireguard_rotation_policy_get(format),
+                    ("set", Some(matches)) => {
+                        Self::process_wireguard_rotation_policy_set(matches, format)
                     }
-                    ("reset", _) => Self::process_wireguard_rotation_interval_reset(),
                     _ => unreachable!("unhandled command"),
                 },
                 _ => unreachable!("unhandled command"),
@@ -147,8 +328,13 @@ impl Tunnel {
         }
     }
 
-    fn process_wireguard_mtu_get() -> Result<()> {
+    fn process_wireguard_mtu_get(format: OutputFormat) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "mtu": tunnel_options.wireguard.mtu
+            }));
+        }
         println!(
             "mtu: {}",
             tunnel_options
@@ -160,24 +346,103 @@ impl Tunnel {
         Ok(())
     }
 
-    fn process_wireguard_mtu_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn process_wireguard_mtu_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         let mtu = value_t!(matches.value_of("mtu"), u16).unwrap_or_else(|e| e.exit());
         let mut rpc = new_rpc_client()?;
         rpc.set_wireguard_mtu(Some(mtu))?;
-        println!("Wireguard MTU has been updated");
+        format.print_message("Wireguard MTU has been updated");
         Ok(())
     }
 
-    fn process_wireguard_mtu_unset() -> Result<()> {
+    fn process_wireguard_mtu_unset(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_wireguard_mtu(None)?;
-        println!("Wireguard MTU has been unset");
+        format.print_message("Wireguard MTU has been unset");
         Ok(())
     }
 
-    fn process_wireguard_key_check() -> Result<()> {
+    fn process_wireguard_netns_get(format: OutputFormat) -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "network_namespace": tunnel_options.wireguard.network_namespace
+            }));
+        }
+        println!(
+            "Network namespace: {}",
+            tunnel_options
+                .wireguard
+                .network_namespace
+                .as_deref()
+                .unwrap_or("unset")
+        );
+        Ok(())
+    }
+
+    fn process_wireguard_netns_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let name = matches.value_of("name").unwrap();
+        let mut rpc = new_rpc_client()?;
+        rpc.set_wireguard_network_namespace(Some(name.to_owned()))?;
+        format.print_message("Wireguard network namespace has been updated");
+        Ok(())
+    }
+
+    fn process_wireguard_netns_unset(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
-        match rpc.get_wireguard_key()? {
+        rpc.set_wireguard_network_namespace(None)?;
+        format.print_message("Wireguard network namespace has been unset");
+        Ok(())
+    }
+
+    fn process_wireguard_policy_routing_get(format: OutputFormat) -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "use_policy_routing": tunnel_options.wireguard.use_policy_routing
+            }));
+        }
+        println!(
+            "Policy routing: {}",
+            if tunnel_options.wireguard.use_policy_routing {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        Ok(())
+    }
+
+    fn process_wireguard_policy_routing_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let enabled = matches.value_of("policy").unwrap() == "on";
+        let mut rpc = new_rpc_client()?;
+        rpc.set_wireguard_policy_routing(enabled)?;
+        format.print_message("Wireguard policy routing setting has been updated");
+        Ok(())
+    }
+
+    fn process_wireguard_key_check(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let key = rpc.get_wireguard_key()?;
+        if format.is_json() {
+            let is_valid = match key {
+                Some(_) => Some(rpc.verify_wireguard_key()?),
+                None => None,
+            };
+            return format::print_json(&serde_json::json!({
+                "key": key,
+                "is_valid": is_valid,
+            }));
+        }
+        match key {
             Some(key) => {
                 println!("Current key    : {}", &key.key);
                 println!(
@@ -196,17 +461,98 @@ impl Tunnel {
         Ok(())
     }
 
-    fn process_wireguard_key_generate() -> Result<()> {
+    fn process_wireguard_key_generate(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let result = rpc
             .generate_wireguard_key()
             .map_err(|e| crate::Error::RpcClientError(e))?;
+        if format.is_json() {
+            return format::print_json(&result);
+        }
         println!("{}", result);
         Ok(())
     }
 
-    fn process_wireguard_rotation_interval_get() -> Result<()> {
+    fn process_wireguard_key_list(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let keys = rpc.list_wireguard_keys()?;
+        if format.is_json() {
+            return format::print_json(&keys);
+        }
+        let current_key = if keys.is_empty() {
+            None
+        } else {
+            rpc.get_wireguard_key()?.map(|key| key.key)
+        };
+        print!("{}", Self::format_key_list(&keys, current_key.as_ref()));
+        Ok(())
+    }
+
+    /// Lists the keys and their addresses, marking the key that is in use with an asterisk.
+    fn format_key_list(keys: &[RegisteredKey], current_key: Option<&PublicKey>) -> String {
+        if keys.is_empty() {
+            return "No keys are registered on the account\n".to_string();
+        }
+        let mut output = String::new();
+        for key in keys {
+            let marker = if Some(&key.pubkey) == current_key {
+                "*"
+            } else {
+                " "
+            };
+            output.push_str(&format!("{} {}\n", marker, key.pubkey));
+            output.push_str(&format!(
+                "\tAddresses: {}, {}\n",
+                key.addresses.ipv4_address, key.addresses.ipv6_address
+            ));
+        }
+        output
+    }
+
+    fn process_wireguard_key_revoke(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let key_str = value_t!(matches.value_of("public-key"), String).unwrap_or_else(|e| e.exit());
+        let public_key = super::Relay::validate_wireguard_key(&key_str).into();
+        let mut rpc = new_rpc_client()?;
+        rpc.revoke_wireguard_key(public_key)?;
+        format.print_message("Removed the key from the account");
+        Ok(())
+    }
+
+    fn process_wireguard_key_import(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let key_str = match matches.value_of("private-key") {
+            Some(key_str) => key_str.to_owned(),
+            None => {
+                let mut key_str = String::new();
+                io::stdin()
+                    .lock()
+                    .read_line(&mut key_str)
+                    .map_err(crate::Error::ReadInput)?;
+                key_str
+            }
+        };
+        let private_key = super::Relay::validate_wireguard_key(&key_str).into();
+        let mut rpc = new_rpc_client()?;
+        let public_key = rpc.import_wireguard_key(private_key)?;
+        if format.is_json() {
+            return format::print_json(&public_key);
+        }
+        println!("Imported key: {}", public_key.key);
+        Ok(())
+    }
+
+    fn process_wireguard_rotation_interval_get(format: OutputFormat) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "automatic_rotation": tunnel_options.wireguard.automatic_rotation
+            }));
+        }
         println!(
             "Rotation interval: {} hour(s)",
             tunnel_options
@@ -218,34 +564,81 @@ impl Tunnel {
         Ok(())
     }
 
-    fn process_wireguard_rotation_interval_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn process_wireguard_rotation_interval_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         let rotate_interval =
             value_t!(matches.value_of("interval"), u32).unwrap_or_else(|e| e.exit());
         let mut rpc = new_rpc_client()?;
         rpc.set_wireguard_rotation_interval(Some(rotate_interval))?;
-        println!("Set key rotation interval: {} hour(s)", rotate_interval);
+        format.print_message(&format!(
+            "Set key rotation interval: {} hour(s)",
+            rotate_interval
+        ));
         Ok(())
     }
 
-    fn process_wireguard_rotation_interval_reset() -> Result<()> {
+    fn process_wireguard_rotation_interval_reset(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_wireguard_rotation_interval(None)?;
-        println!("Set key rotation interval: default");
+        format.print_message("Set key rotation interval: default");
         Ok(())
     }
 
-    fn handle_ipv6_cmd(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn process_wireguard_rotation_policy_get(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let policy = rpc.get_settings()?.key_rotation_policy;
+        if format.is_json() {
+            return format::print_json(&policy);
+        }
+        println!("Grace period          : {} minute(s)", policy.grace_period);
+        println!(
+            "Rotate while connected: {}",
+            if policy.rotate_while_connected {
+                "on"
+            } else {
+                "off"
+            }
+        );
+        Ok(())
+    }
+
+    fn process_wireguard_rotation_policy_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let mut policy = rpc.get_settings()?.key_rotation_policy;
+        if matches.is_present("grace-period") {
+            policy.grace_period =
+                value_t!(matches.value_of("grace-period"), u32).unwrap_or_else(|e| e.exit());
+        }
+        if let Some(rotate_while_connected) = matches.value_of("rotate-while-connected") {
+            policy.rotate_while_connected = rotate_while_connected == "on";
+        }
+        rpc.set_key_rotation_policy(policy)?;
+        format.print_message("Updated key rotation policy");
+        Ok(())
+    }
+
+    fn handle_ipv6_cmd(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         if matches.subcommand_matches("get").is_some() {
-            Self::process_ipv6_get()
+            Self::process_ipv6_get(format)
         } else if let Some(m) = matches.subcommand_matches("set") {
-            Self::process_ipv6_set(m)
+            Self::process_ipv6_set(m, format)
         } else {
             unreachable!("unhandled command");
         }
     }
 
-    fn process_openvpn_mssfix_get() -> Result<()> {
+    fn process_openvpn_mssfix_get(format: OutputFormat) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "mssfix": tunnel_options.openvpn.mssfix
+            }));
+        }
         println!(
             "mssfix: {}",
             tunnel_options
@@ -261,23 +654,137 @@ impl Tunnel {
         Ok(rpc.get_settings()?.tunnel_options)
     }
 
-    fn process_openvpn_mssfix_unset() -> Result<()> {
+    fn process_openvpn_mssfix_unset(format: OutputFormat) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         rpc.set_openvpn_mssfix(None)?;
-        println!("mssfix parameter has been unset");
+        format.print_message("mssfix parameter has been unset");
         Ok(())
     }
 
-    fn process_openvpn_mssfix_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn process_openvpn_mssfix_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
         let new_value = value_t!(matches.value_of("mssfix"), u16).unwrap_or_else(|e| e.exit());
         let mut rpc = new_rpc_client()?;
         rpc.set_openvpn_mssfix(Some(new_value))?;
-        println!("mssfix parameter has been updated");
+        format.print_message("mssfix parameter has been updated");
+        Ok(())
+    }
+
+    fn process_openvpn_option_get(format: OutputFormat) -> Result<()> {
+        let extra_options = Self::get_tunnel_options()?.openvpn.extra_options;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({ "extra_options": extra_options }));
+        }
+        if extra_options.is_empty() {
+            println!("No extra OpenVPN options are set");
+        }
+        for (option, value) in extra_options {
+            println!("{} {}", option, value);
+        }
+        Ok(())
+    }
+
+    fn process_openvpn_option_set(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let option = value_t!(matches.value_of("option"), ExtraOption).unwrap_or_else(|e| e.exit());
+        let value = value_t!(matches.value_of("value"), u32).unwrap_or_else(|e| e.exit());
+        if let Err(error) = option.validate(value) {
+            clap::Error::with_description(&error.to_string(), clap::ErrorKind::InvalidValue).exit();
+        }
+        let mut rpc = new_rpc_client()?;
+        rpc.set_openvpn_extra_option(option, Some(value))?;
+        format.print_message(&format!(
+            "OpenVPN option {} has been set to {}",
+            option, value
+        ));
         Ok(())
     }
 
-    fn process_ipv6_get() -> Result<()> {
+    fn process_openvpn_option_unset(
+        matches: &clap::ArgMatches<'_>,
+        format: OutputFormat,
+    ) -> Result<()> {
+        let option = value_t!(matches.value_of("option"), ExtraOption).unwrap_or_else(|e| e.exit());
+        let mut rpc = new_rpc_client()?;
+        rpc.set_openvpn_extra_option(option, None)?;
+        format.print_message(&format!("OpenVPN option {} has been unset", option));
+        Ok(())
+    }
+
+    fn handle_exclude_cmd(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        match matches.subcommand() {
+            ("list", _) => Self::process_exclude_list(format),
+            ("add", Some(matches)) => Self::process_exclude_add(matches, format),
+            ("remove", Some(matches)) => Self::process_exclude_remove(matches, format),
+            ("clear", _) => Self::process_exclude_clear(format),
+            _ => unreachable!("unhandled command"),
+        }
+    }
+
+    fn process_exclude_list(format: OutputFormat) -> Result<()> {
         let tunnel_options = Self::get_tunnel_options()?;
+        let excluded_destinations = tunnel_options.generic.excluded_destinations;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "excluded_destinations": excluded_destinations
+            }));
+        }
+        if excluded_destinations.is_empty() {
+            println!("No networks are excluded from the tunnel");
+        }
+        for network in excluded_destinations {
+            println!("{}", network);
+        }
+        Ok(())
+    }
+
+    fn process_exclude_add(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let network = value_t!(matches.value_of("network"), IpNetwork).unwrap_or_else(|e| e.exit());
+        let mut excluded_destinations = Self::get_tunnel_options()?.generic.excluded_destinations;
+        if !excluded_destinations.contains(&network) {
+            excluded_destinations.push(network);
+        }
+        let mut rpc = new_rpc_client()?;
+        rpc.set_excluded_destinations(excluded_destinations)?;
+        format.print_message(&format!("Excluded {} from the tunnel", network));
+        Ok(())
+    }
+
+    fn process_exclude_remove(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
+        let network = value_t!(matches.value_of("network"), IpNetwork).unwrap_or_else(|e| e.exit());
+        let mut excluded_destinations = Self::get_tunnel_options()?.generic.excluded_destinations;
+        let num_destinations = excluded_destinations.len();
+        excluded_destinations.retain(|destination| *destination != network);
+        if excluded_destinations.len() == num_destinations {
+            return Err(Error::InvalidCommand("The network is not excluded"));
+        }
+        let mut rpc = new_rpc_client()?;
+        rpc.set_excluded_destinations(excluded_destinations)?;
+        format.print_message(&format!(
+            "{} is no longer excluded from the tunnel",
+            network
+        ));
+        Ok(())
+    }
+
+    fn process_exclude_clear(format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_excluded_destinations(vec![])?;
+        format.print_message("No networks are excluded from the tunnel");
+        Ok(())
+    }
+
+    fn process_ipv6_get(format: OutputFormat) -> Result<()> {
+        let tunnel_options = Self::get_tunnel_options()?;
+        if format.is_json() {
+            return format::print_json(&serde_json::json!({
+                "enable_ipv6": tunnel_options.generic.enable_ipv6
+            }));
+        }
         println!(
             "IPv6: {}",
             if tunnel_options.generic.enable_ipv6 {
@@ -289,12 +796,71 @@ impl Tunnel {
         Ok(())
     }
 
-    fn process_ipv6_set(matches: &clap::ArgMatches<'_>) -> Result<()> {
+    fn process_ipv6_set(matches: &clap::ArgMatches<'_>, format: OutputFormat) -> Result<()> {
         let enabled = matches.value_of("enable").unwrap() == "on";
 
         let mut rpc = new_rpc_client()?;
         rpc.set_enable_ipv6(enabled)?;
-        println!("IPv6 setting has been updated");
+        format.print_message("IPv6 setting has been updated");
         Ok(())
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cmds::Relay;
+    use mullvad_types::wireguard::AssociatedAddresses;
+
+    const PRIVATE_KEY: &str = "mAFHbFJ8/g6nzwlErt5C9AJMC7h8Fd9tXU5e1DmUQn4=";
+
+    fn registered_key(key: [u8; 32], ipv4_address: &str, ipv6_address: &str) -> RegisteredKey {
+        RegisteredKey {
+            pubkey: PublicKey::from(key),
+            addresses: AssociatedAddresses {
+                ipv4_address: ipv4_address.parse().unwrap(),
+                ipv6_address: ipv6_address.parse().unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_key() {
+        let key = Relay::parse_wireguard_key(&format!("{}\n", PRIVATE_KEY)).unwrap();
+        assert_eq!(base64::encode(&key), PRIVATE_KEY);
+    }
+
+    #[test]
+    fn test_parse_malformed_key() {
+        assert!(Relay::parse_wireguard_key("not a key").is_err());
+        assert!(Relay::parse_wireguard_key("").is_err());
+        // Valid base64, but only 31 bytes long
+        assert!(
+            Relay::parse_wireguard_key("mAFHbFJ8/g6nzwlErt5C9AJMC7h8Fd9tXU5e1DmUQg==").is_err()
+        );
+    }
+
+    #[test]
+    fn test_key_list_output() {
+        let keys = vec![
+            registered_key([1; 32], "10.64.0.1/32", "fc00:bbbb:bbbb:bb01::1/128"),
+            registered_key([2; 32], "10.64.0.2/32", "fc00:bbbb:bbbb:bb01::2/128"),
+        ];
+        let current_key = PublicKey::from([2; 32]);
+
+        assert_eq!(
+            Tunnel::format_key_list(&keys, Some(&current_key)),
+            format!(
+                "  {}\n\tAddresses: 10.64.0.1/32, fc00:bbbb:bbbb:bb01::1/128\n\
+                 * {}\n\tAddresses: 10.64.0.2/32, fc00:bbbb:bbbb:bb01::2/128\n",
+                base64::encode(&[1u8; 32]),
+                base64::encode(&[2u8; 32]),
+            )
+        );
+        assert_eq!(
+            Tunnel::format_key_list(&[], None),
+            "No keys are registered on the account\n"
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
+use clap::value_t_or_exit;
+
+pub struct Update;
+
+impl Command for Update {
+    fn name(&self) -> &'static str {
+        "update"
+    }
+
+    fn clap_subcommand(&self) -> clap::App<'static, 'static> {
+        clap::SubCommand::with_name(self.name())
+            .about("Download and install new releases of the app")
+            .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                clap::SubCommand::with_name("download")
+                    .about("Control whether new releases are downloaded in the background")
+                    .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(
+                        clap::SubCommand::with_name("set")
+                            .about("Change the background download setting")
+                            .arg(
+                                clap::Arg::with_name("policy")
+                                    .required(true)
+                                    .possible_values(&["on", "off"]),
+                            ),
+                    )
+                    .subcommand(
+                        clap::SubCommand::with_name("get")
+                            .about("Display the background download setting"),
+                    ),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("pending")
+                    .about("Display the downloaded release that is ready to be installed"),
+            )
+            .subcommand(
+                clap::SubCommand::with_name("apply")
+                    .about("Install the downloaded release. This restarts the daemon"),
+            )
+    }
+
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
+        let format = OutputFormat::from_matches(matches);
+        match matches.subcommand() {
+            ("download", Some(download_matches)) => match download_matches.subcommand() {
+                ("set", Some(set_matches)) => {
+                    let policy = value_t_or_exit!(set_matches.value_of("policy"), String);
+                    self.set_download(policy == "on", format)
+                }
+                ("get", Some(_)) => self.get_download(format),
+                _ => unreachable!("unhandled command"),
+            },
+            ("pending", Some(_)) => self.pending(format),
+            ("apply", Some(_)) => self.apply(format),
+            _ => unreachable!("unhandled command"),
+        }
+    }
+}
+
+impl Update {
+    fn set_download(&self, enabled: bool, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.set_download_updates(enabled)?;
+        format.print_message("Changed update download setting");
+        Ok(())
+    }
+
+    fn get_download(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let download_updates = rpc.get_settings()?.download_updates;
+        if format.is_json() {
+            return format::print_json(
+                &serde_json::json!({ "download_updates": download_updates }),
+            );
+        }
+        println!(
+            "Download updates: {}",
+            if download_updates { "on" } else { "off" }
+        );
+        Ok(())
+    }
+
+    fn pending(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        let pending_update = rpc.get_pending_update()?;
+        if format.is_json() {
+            return format::print_json(&pending_update);
+        }
+        match pending_update {
+            Some(pending_update) => {
+                println!("Pending update: {}", pending_update.version);
+                println!("\tInstaller: {}", pending_update.path.display());
+            }
+            None => println!("No update has been downloaded"),
+        }
+        Ok(())
+    }
+
+    fn apply(&self, format: OutputFormat) -> Result<()> {
+        let mut rpc = new_rpc_client()?;
+        rpc.apply_update()?;
+        format.print_message("Started the installer");
+        Ok(())
+    }
+}
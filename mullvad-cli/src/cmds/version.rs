@@ -1,4 +1,7 @@
-use crate::{new_rpc_client, Command, Result};
+use crate::{
+    format::{self, OutputFormat},
+    new_rpc_client, Command, Result,
+};
 
 pub struct Version;
 
@@ -12,12 +15,10 @@ impl Command for Version {
             .about("Shows current version, and the currently supported versions")
     }
 
-    fn run(&self, _: &clap::ArgMatches<'_>) -> Result<()> {
+    fn run(&self, matches: &clap::ArgMatches<'_>) -> Result<()> {
         let mut rpc = new_rpc_client()?;
         let current_version = rpc.get_current_version()?;
-        println!("Current version: {}", current_version);
         let version_info = rpc.get_version_info()?;
-        println!("\tIs supported: {}", version_info.supported);
 
         let settings = rpc.get_settings()?;
         let is_updated = if settings.show_beta_releases {
@@ -25,6 +26,17 @@ impl Command for Version {
         } else {
             version_info.latest_stable == current_version
         };
+
+        if OutputFormat::from_matches(matches).is_json() {
+            return format::print_json(&serde_json::json!({
+                "current_version": current_version,
+                "is_up_to_date": is_updated,
+                "version_info": version_info,
+            }));
+        }
+
+        println!("Current version: {}", current_version);
+        println!("\tIs supported: {}", version_info.supported);
         println!("\tIs up to date: {}", is_updated);
 
         if version_info.latest_stable != version_info.latest {
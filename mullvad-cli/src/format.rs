@@ -0,0 +1,149 @@
+//! Output formats supported by the CLI. The JSON format is meant to be consumed by scripts, so
+//! the shape of what each command prints must be kept stable.
+
+use crate::{Error, Result};
+use serde::Serialize;
+use std::io::{self, Write};
+
+/// Name of the global argument that selects JSON output.
+pub const JSON_ARG: &str = "json";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable text.
+    Human,
+    /// A single JSON value per line.
+    Json,
+}
+
+impl OutputFormat {
+    /// Returns the format selected by the global `--json` flag. Global arguments are propagated
+    /// to all subcommands, so any level of matches can be passed here.
+    pub fn from_matches(matches: &clap::ArgMatches<'_>) -> Self {
+        if matches.is_present(JSON_ARG) {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Human
+        }
+    }
+
+    /// Returns true if output should be printed as JSON.
+    pub fn is_json(self) -> bool {
+        self == OutputFormat::Json
+    }
+
+    /// Prints an informational message. Nothing is printed in JSON mode, so that setters don't
+    /// produce any output that scripts would have to parse.
+    pub fn print_message(self, message: &str) {
+        if !self.is_json() {
+            println!("{}", message);
+        }
+    }
+}
+
+/// Serializes `value` as a single line of JSON and writes it to stdout. The output is flushed so
+/// that streams of values can be consumed line by line.
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let line = serde_json::to_string(value).map_err(Error::SerializeOutput)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    writeln!(stdout, "{}", line)
+        .and_then(|_| stdout.flush())
+        .map_err(Error::WriteOutput)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{offset::Utc, DateTime};
+    use mullvad_types::{
+        account::AccountData, location::GeoIpLocation, states::TunnelState, DaemonEvent,
+    };
+    use serde_json::{json, Value};
+    use std::net::Ipv4Addr;
+    use talpid_types::net::{Endpoint, TransportProtocol, TunnelEndpoint, TunnelType};
+
+    fn location() -> GeoIpLocation {
+        GeoIpLocation {
+            ipv4: Some(Ipv4Addr::new(185, 213, 154, 68)),
+            ipv6: None,
+            country: "Sweden".to_owned(),
+            city: Some("Gothenburg".to_owned()),
+            latitude: 57.70887,
+            longitude: 11.97456,
+            mullvad_exit_ip: true,
+            hostname: Some("se-got-001".to_owned()),
+            bridge_hostname: None,
+        }
+    }
+
+    fn connected_state() -> TunnelState {
+        TunnelState::Connected {
+            endpoint: TunnelEndpoint {
+                endpoint: Endpoint::new(
+                    Ipv4Addr::new(185, 213, 154, 66),
+                    1194,
+                    TransportProtocol::Udp,
+                ),
+                tunnel_type: TunnelType::OpenVpn,
+                proxy: None,
+                network_namespace: None,
+            },
+            location: Some(location()),
+        }
+    }
+
+    fn assert_snapshot<T: serde::Serialize>(value: &T, snapshot: &str) {
+        let serialized = serde_json::to_value(value).unwrap();
+        let expected: Value = serde_json::from_str(snapshot).unwrap();
+        assert_eq!(
+            serialized,
+            expected,
+            "serialized value differs from snapshot:\n{}",
+            serde_json::to_string_pretty(&serialized).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tunnel_state_schema() {
+        assert_snapshot(
+            &connected_state(),
+            include_str!("../snapshots/tunnel_state_connected.json"),
+        );
+        assert_snapshot(
+            &TunnelState::Disconnected,
+            include_str!("../snapshots/tunnel_state_disconnected.json"),
+        );
+    }
+
+    #[test]
+    fn test_status_schema() {
+        assert_snapshot(
+            &json!({ "state": connected_state(), "location": location() }),
+            include_str!("../snapshots/status.json"),
+        );
+    }
+
+    #[test]
+    fn test_account_schema() {
+        let expiry = DateTime::parse_from_rfc3339("2020-06-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_snapshot(
+            &json!({
+                "account_token": "1234123412341234",
+                "expiry": AccountData { expiry }.expiry,
+            }),
+            include_str!("../snapshots/account.json"),
+        );
+    }
+
+    #[test]
+    fn test_daemon_event_line() {
+        let line = serde_json::to_string(&DaemonEvent::TunnelState(connected_state())).unwrap();
+        assert!(!line.contains('\n'));
+        assert_snapshot(
+            &serde_json::from_str::<Value>(&line).unwrap(),
+            include_str!("../snapshots/daemon_event_tunnel_state.json"),
+        );
+    }
+}
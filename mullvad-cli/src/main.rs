@@ -6,6 +6,7 @@ use std::{collections::HashMap, io};
 use talpid_types::ErrorExt;
 
 mod cmds;
+mod format;
 mod location;
 
 pub const BIN_NAME: &str = "mullvad";
@@ -27,6 +28,15 @@ pub enum Error {
     /// The given command is not correct in some way
     #[error(display = "Invalid command: {}", _0)]
     InvalidCommand(&'static str),
+
+    #[error(display = "Failed to serialize output")]
+    SerializeOutput(#[error(source)] serde_json::Error),
+
+    #[error(display = "Failed to write output")]
+    WriteOutput(#[error(source, no_from)] io::Error),
+
+    #[error(display = "Failed to read input")]
+    ReadInput(#[error(source, no_from)] io::Error),
 }
 
 pub fn new_rpc_client() -> Result<DaemonRpcClient> {
@@ -106,6 +116,12 @@ fn build_cli(commands: &HashMap<&'static str, Box<dyn Command>>) -> clap::App<'s
             clap::AppSettings::DisableHelpSubcommand,
             clap::AppSettings::VersionlessSubcommands,
         ])
+        .arg(
+            clap::Arg::with_name(format::JSON_ARG)
+                .long("json")
+                .global(true)
+                .help("Print output as JSON, one value per line"),
+        )
         .subcommands(commands.values().map(|cmd| cmd.clap_subcommand()))
 }
 
@@ -1,3 +1,4 @@
+use chrono::{offset::Utc, DateTime};
 #[cfg(target_os = "android")]
 use futures::future::{Executor, Future};
 #[cfg(not(target_os = "android"))]
@@ -6,7 +7,10 @@ use futures::{
     sync::oneshot,
 };
 use mullvad_rpc::{rest::MullvadRestHandle, WireguardKeyProxy};
-use mullvad_types::{account::AccountToken, wireguard::WireguardData};
+use mullvad_types::{
+    account::{AccountHistoryEntry, AccountToken},
+    wireguard::WireguardData,
+};
 use std::{
     collections::VecDeque,
     fs,
@@ -29,9 +33,16 @@ pub enum Error {
 
     #[error(display = "Unable to write account history file")]
     Write(#[error(source)] io::Error),
+
+    #[error(display = "The account is not in the account history")]
+    AccountNotFound,
+
+    #[error(display = "The label is already used by another account")]
+    LabelInUse,
 }
 
 static ACCOUNT_HISTORY_FILE: &str = "account-history.json";
+/// Maximum number of unlabeled accounts. Labeled accounts are never evicted.
 static ACCOUNT_HISTORY_LIMIT: usize = 3;
 
 /// A trivial MRU cache of account data
@@ -82,10 +93,7 @@ impl AccountHistory {
                 );
                 Self::try_old_format(&mut reader)?
                     .into_iter()
-                    .map(|account| AccountEntry {
-                        account,
-                        wireguard: None,
-                    })
+                    .map(AccountEntry::new)
                     .collect()
             }
             Ok(accounts) => accounts,
@@ -162,17 +170,54 @@ impl AccountHistory {
         Ok(Some(entry))
     }
 
-    /// Bumps history of an account token. If the account token is not in history, it will be
-    /// added.
+    /// Bumps history of an account token and marks it as used now. If the account token is not in
+    /// history, it will be added.
     pub fn bump_history(&mut self, account: &AccountToken) -> Result<()> {
-        if self.get(account)?.is_none() {
-            let new_entry = AccountEntry {
-                account: account.to_string(),
-                wireguard: None,
-            };
-            self.insert(new_entry)?;
+        let mut entry = self
+            .get(account)?
+            .unwrap_or_else(|| AccountEntry::new(account.to_string()));
+        entry.last_used = Some(Utc::now());
+        self.insert(entry)
+    }
+
+    /// Returns the account that has the given label, or is the given account token.
+    pub fn find(&self, label_or_account: &str) -> Option<AccountToken> {
+        find_entry(&self.accounts, label_or_account).map(|entry| entry.account.clone())
+    }
+
+    /// Sets or removes the label of an account. Labels must be unique.
+    pub fn set_label(&mut self, account: &str, label: Option<String>) -> Result<()> {
+        if let Some(label) = &label {
+            if self
+                .accounts
+                .iter()
+                .any(|entry| entry.account != account && entry.label.as_ref() == Some(label))
+            {
+                return Err(Error::LabelInUse);
+            }
+        }
+        let entry = self
+            .accounts
+            .iter_mut()
+            .find(|entry| entry.account == account)
+            .ok_or(Error::AccountNotFound)?;
+        entry.label = label;
+        self.save_to_disk()
+    }
+
+    /// Caches the expiry of an account, if it is in the history.
+    pub fn set_expiry(&mut self, account: &str, expiry: DateTime<Utc>) -> Result<()> {
+        match self
+            .accounts
+            .iter_mut()
+            .find(|entry| entry.account == account)
+        {
+            Some(entry) if entry.expiry != Some(expiry) => {
+                entry.expiry = Some(expiry);
+                self.save_to_disk()
+            }
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     fn create_remove_wg_key_rpc(
@@ -187,15 +232,9 @@ impl AccountHistory {
 
     /// Always inserts a new entry at the start of the list
     pub fn insert(&mut self, new_entry: AccountEntry) -> Result<()> {
-        self.accounts
-            .retain(|entry| entry.account != new_entry.account);
-
-        self.accounts.push_front(new_entry);
-
-        if self.accounts.len() > ACCOUNT_HISTORY_LIMIT {
-            let last_entry = self.accounts.pop_back().unwrap();
-            if let Some(wg_data) = last_entry.wireguard {
-                let fut = self.create_remove_wg_key_rpc(&last_entry.account, &wg_data);
+        if let Some(evicted_entry) = insert_entry(&mut self.accounts, new_entry) {
+            for wg_data in evicted_entry.wireguard_keys() {
+                let fut = self.create_remove_wg_key_rpc(&evicted_entry.account, wg_data);
                 if let Err(e) = self.tokio_remote.execute(fut) {
                     log::error!("Failed to spawn future to remove WireGuard key: {:?}", e);
                 }
@@ -213,22 +252,34 @@ impl AccountHistory {
             .collect()
     }
 
+    /// Retrieve account history, including labels, timestamps and cached expiry dates.
+    pub fn get_entries(&self) -> Vec<AccountHistoryEntry> {
+        self.accounts
+            .iter()
+            .map(|entry| AccountHistoryEntry {
+                account: entry.account.clone(),
+                label: entry.label.clone(),
+                last_used: entry.last_used,
+                expiry: entry.expiry,
+                wireguard_key: entry.wireguard.as_ref().map(WireguardData::get_public_key),
+            })
+            .collect()
+    }
+
     /// Remove account data
     pub fn remove_account(&mut self, account: &str) -> Result<()> {
-        let entry = self.get(&String::from(account))?;
-        let entry = match entry {
+        let entry = match remove_entry(&mut self.accounts, account) {
             Some(entry) => entry,
             None => return Ok(()),
         };
 
-        if let Some(wg_data) = entry.wireguard {
-            let fut = self.create_remove_wg_key_rpc(account, &wg_data);
+        for wg_data in entry.wireguard_keys() {
+            let fut = self.create_remove_wg_key_rpc(account, wg_data);
             if let Err(e) = self.tokio_remote.execute(fut) {
                 log::error!("Failed to spawn future to remove WireGuard key: {:?}", e);
             }
         }
 
-        let _ = self.accounts.pop_front();
         self.save_to_disk()
     }
 
@@ -242,7 +293,7 @@ impl AccountHistory {
         let mut removal_futures = Vec::with_capacity(ACCOUNT_HISTORY_LIMIT);
 
         for entry in self.accounts.iter() {
-            if let Some(wg_data) = &entry.wireguard {
+            for wg_data in entry.wireguard_keys() {
                 let fut = rpc
                     .remove_wireguard_key(entry.account.clone(), &wg_data.private_key.public_key())
                     .map_err(|e| log::error!("Failed to remove WireGuard key: {}", e));
@@ -278,8 +329,208 @@ impl AccountHistory {
     }
 }
 
+/// Inserts an entry at the start of the list, replacing any previous entry for the same account.
+/// Returns the oldest unlabeled entry if it was evicted to stay within the limit.
+fn insert_entry(
+    accounts: &mut VecDeque<AccountEntry>,
+    new_entry: AccountEntry,
+) -> Option<AccountEntry> {
+    accounts.retain(|entry| entry.account != new_entry.account);
+    accounts.push_front(new_entry);
+
+    let num_unlabeled = accounts
+        .iter()
+        .filter(|entry| entry.label.is_none())
+        .count();
+    if num_unlabeled > ACCOUNT_HISTORY_LIMIT {
+        let last_unlabeled = accounts.iter().rposition(|entry| entry.label.is_none())?;
+        accounts.remove(last_unlabeled)
+    } else {
+        None
+    }
+}
+
+/// Removes the entry of an account from the list and returns it.
+fn remove_entry(accounts: &mut VecDeque<AccountEntry>, account: &str) -> Option<AccountEntry> {
+    let index = accounts.iter().position(|entry| entry.account == account)?;
+    accounts.remove(index)
+}
+
+/// Finds the entry that has the given label, or is for the given account token. Labels take
+/// precedence.
+fn find_entry<'a>(
+    accounts: &'a VecDeque<AccountEntry>,
+    label_or_account: &str,
+) -> Option<&'a AccountEntry> {
+    accounts
+        .iter()
+        .find(|entry| entry.label.as_ref().map(String::as_str) == Some(label_or_account))
+        .or_else(|| {
+            accounts
+                .iter()
+                .find(|entry| entry.account == label_or_account)
+        })
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct AccountEntry {
     pub account: AccountToken,
     pub wireguard: Option<WireguardData>,
+    #[serde(default)]
+    pub label: Option<String>,
+    /// When the account was last set as the current account.
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+    /// Expiry as of the last time it was fetched from the API.
+    #[serde(default)]
+    pub expiry: Option<DateTime<Utc>>,
+    /// Key rotation that was in its grace period the last time the entry was saved.
+    #[serde(default)]
+    pub pending_rotation: Option<PendingKeyRotation>,
+}
+
+/// A key rotation in its grace period. The previous key is still registered on the account, so
+/// that the rotation can be rolled back.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PendingKeyRotation {
+    pub old_data: WireguardData,
+    pub new_key: talpid_types::net::wireguard::PublicKey,
+    pub grace_period_end: DateTime<Utc>,
+}
+
+impl AccountEntry {
+    pub fn new(account: AccountToken) -> Self {
+        AccountEntry {
+            account,
+            wireguard: None,
+            label: None,
+            last_used: None,
+            expiry: None,
+            pending_rotation: None,
+        }
+    }
+
+    /// Forgets the WireGuard key if it is the given key, which was removed from the account.
+    /// Returns true if the key was in use.
+    pub fn forget_revoked_key(
+        &mut self,
+        public_key: &talpid_types::net::wireguard::PublicKey,
+    ) -> bool {
+        let is_current_key = self
+            .wireguard
+            .as_ref()
+            .map(|data| data.private_key.public_key() == *public_key)
+            .unwrap_or(false);
+        if is_current_key {
+            self.wireguard = None;
+        }
+        is_current_key
+    }
+
+    /// Returns the keys of the entry that are registered on the account, including the previous
+    /// key of a pending rotation.
+    fn wireguard_keys(&self) -> impl Iterator<Item = &WireguardData> {
+        self.wireguard.iter().chain(
+            self.pending_rotation
+                .iter()
+                .map(|pending_rotation| &pending_rotation.old_data),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(accounts: &[&str]) -> VecDeque<AccountEntry> {
+        accounts
+            .iter()
+            .map(|account| AccountEntry::new(account.to_string()))
+            .collect()
+    }
+
+    fn tokens(accounts: &VecDeque<AccountEntry>) -> Vec<&str> {
+        accounts
+            .iter()
+            .map(|entry| entry.account.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_history_order_and_limit() {
+        let mut accounts = VecDeque::new();
+        for account in &["1111", "2222", "3333"] {
+            assert!(insert_entry(&mut accounts, AccountEntry::new(account.to_string())).is_none());
+        }
+        assert_eq!(tokens(&accounts), vec!["3333", "2222", "1111"]);
+
+        // Inserting an account that is already in the history moves it to the top
+        assert!(insert_entry(&mut accounts, AccountEntry::new("1111".to_string())).is_none());
+        assert_eq!(tokens(&accounts), vec!["1111", "3333", "2222"]);
+
+        // The least recently used account is evicted
+        let evicted = insert_entry(&mut accounts, AccountEntry::new("4444".to_string()));
+        assert_eq!(evicted.unwrap().account, "2222");
+        assert_eq!(tokens(&accounts), vec!["4444", "1111", "3333"]);
+    }
+
+    #[test]
+    fn test_labeled_accounts_are_kept() {
+        let mut accounts = history(&["3333", "2222", "1111"]);
+        accounts[2].label = Some("work".to_string());
+
+        let evicted = insert_entry(&mut accounts, AccountEntry::new("4444".to_string()));
+        assert!(evicted.is_none());
+        assert_eq!(tokens(&accounts), vec!["4444", "3333", "2222", "1111"]);
+
+        let evicted = insert_entry(&mut accounts, AccountEntry::new("5555".to_string()));
+        assert_eq!(evicted.unwrap().account, "2222");
+        assert_eq!(tokens(&accounts), vec!["5555", "4444", "3333", "1111"]);
+    }
+
+    #[test]
+    fn test_remove_entry() {
+        let mut accounts = history(&["3333", "2222", "1111"]);
+
+        assert_eq!(remove_entry(&mut accounts, "2222").unwrap().account, "2222");
+        assert_eq!(tokens(&accounts), vec!["3333", "1111"]);
+        assert!(remove_entry(&mut accounts, "2222").is_none());
+        assert_eq!(tokens(&accounts), vec!["3333", "1111"]);
+    }
+
+    #[test]
+    fn test_revoke_key_in_use() {
+        let private_key = talpid_types::net::wireguard::PrivateKey::new_from_random();
+        let public_key = private_key.public_key();
+        let mut entry = AccountEntry::new("1111".to_string());
+        entry.wireguard = Some(WireguardData {
+            private_key,
+            addresses: mullvad_types::wireguard::AssociatedAddresses {
+                ipv4_address: "10.64.0.1/32".parse().unwrap(),
+                ipv6_address: "fc00:bbbb:bbbb:bb01::1/128".parse().unwrap(),
+            },
+            created: Utc::now(),
+        });
+
+        // Revoking another key leaves the key in use alone
+        let other_key = talpid_types::net::wireguard::PrivateKey::new_from_random().public_key();
+        assert!(!entry.forget_revoked_key(&other_key));
+        assert!(entry.wireguard.is_some());
+
+        assert!(entry.forget_revoked_key(&public_key));
+        assert!(entry.wireguard.is_none());
+        assert!(!entry.forget_revoked_key(&public_key));
+    }
+
+    #[test]
+    fn test_find_entry() {
+        let mut accounts = history(&["3333", "2222", "1111"]);
+        accounts[1].label = Some("1111".to_string());
+
+        assert_eq!(find_entry(&accounts, "3333").unwrap().account, "3333");
+        // Labels take precedence over account tokens
+        assert_eq!(find_entry(&accounts, "1111").unwrap().account, "2222");
+        assert!(find_entry(&accounts, "work").is_none());
+    }
 }
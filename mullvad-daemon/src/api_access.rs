@@ -0,0 +1,451 @@
+//! Keeps track of how the daemon reaches the Mullvad API, and rotates among the allowed access
+//! methods when the API can't be reached.
+
+use crate::relays::RelaySelector;
+use mullvad_rpc::{ApiConnectionMode, ApiConnectivityEvent, MullvadRpcRuntime};
+#[cfg(not(target_os = "android"))]
+use mullvad_types::api_proxy::{ApiProxy, ApiProxyProtocol};
+use mullvad_types::{
+    api_access::ApiAccessMethod, relay_constraints::Constraint, settings::Settings,
+};
+#[cfg(not(target_os = "android"))]
+use std::net::Ipv4Addr;
+use std::{
+    fs::File,
+    io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+#[cfg(not(target_os = "android"))]
+use talpid_core::proxy::{self, ProxyMonitor};
+use talpid_types::{
+    net::{Endpoint, TransportProtocol},
+    ErrorExt,
+};
+
+const LAST_WORKING_METHOD_FILENAME: &str = "api-access-method.json";
+
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "No API proxy is configured")]
+    NoApiProxy,
+
+    #[error(display = "No bridge is available")]
+    NoBridge,
+
+    #[cfg(target_os = "android")]
+    #[error(display = "Bridges are not supported on this platform")]
+    BridgesNotSupported,
+
+    #[error(display = "Failed to start the bridge proxy")]
+    StartBridgeProxy(#[error(source)] io::Error),
+
+    #[error(display = "Failed to open the API access method cache file")]
+    OpenCache(#[error(source)] io::Error),
+
+    #[error(display = "Failed to serialize the API access method cache")]
+    SerializeCache(#[error(source)] serde_json::Error),
+}
+
+
+/// The route currently used to reach the API.
+pub(crate) struct ApiAccess {
+    cache_path: PathBuf,
+    last_working_method: Option<ApiAccessMethod>,
+    current_method: ApiAccessMethod,
+    mode: ApiConnectionMode,
+    allowed_endpoint: Endpoint,
+    #[cfg(not(target_os = "android"))]
+    bridge_proxy: Option<Box<dyn ProxyMonitor>>,
+}
+
+impl ApiAccess {
+    /// Selects the initial access method, starting with the one that worked last time.
+    pub fn new(
+        cache_dir: &Path,
+        settings: &Settings,
+        relay_selector: &mut RelaySelector,
+        rpc_runtime: &mut MullvadRpcRuntime,
+    ) -> Self {
+        let cache_path = cache_dir.join(LAST_WORKING_METHOD_FILENAME);
+        let last_working_method = match Self::load_last_working_method(&cache_path) {
+            Ok(method) => Some(method),
+            Err(Error::OpenCache(ref error)) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to load last working API access method")
+                );
+                None
+            }
+        };
+
+        let mut api_access = ApiAccess {
+            cache_path,
+            last_working_method,
+            current_method: ApiAccessMethod::Direct,
+            mode: ApiConnectionMode::Direct,
+            allowed_endpoint: Self::tcp_endpoint(rpc_runtime.api_address()),
+            #[cfg(not(target_os = "android"))]
+            bridge_proxy: None,
+        };
+        api_access.reset(settings, relay_selector, rpc_runtime);
+        api_access
+    }
+
+    /// The access method currently used to reach the API.
+    pub fn current_method(&self) -> ApiAccessMethod {
+        self.current_method
+    }
+
+    /// The endpoint that the firewall has to allow for the current route to work.
+    pub fn allowed_endpoint(&self) -> Endpoint {
+        self.allowed_endpoint
+    }
+
+    /// Selects a new access method after the settings changed, starting with the one that
+    /// worked last time.
+    pub fn reset(
+        &mut self,
+        settings: &Settings,
+        relay_selector: &mut RelaySelector,
+        rpc_runtime: &mut MullvadRpcRuntime,
+    ) {
+        let candidates = Self::initial_candidates(settings, self.last_working_method);
+        self.activate_first(candidates, settings, relay_selector, rpc_runtime);
+    }
+
+    /// Handles the result of a connection attempt. Moves on to the next allowed access method if
+    /// the current one failed. Returns whether the route to the API changed.
+    pub fn on_connectivity_event(
+        &mut self,
+        event: ApiConnectivityEvent,
+        settings: &Settings,
+        relay_selector: &mut RelaySelector,
+        rpc_runtime: &mut MullvadRpcRuntime,
+    ) -> bool {
+        if !self.record_result(&event) {
+            return false;
+        }
+
+        log::warn!(
+            "Failed to reach the API using the {} access method",
+            self.current_method
+        );
+        let candidates = Self::candidates_after_failure(settings, self.current_method);
+        let previous_endpoint = self.allowed_endpoint;
+        self.activate_first(candidates, settings, relay_selector, rpc_runtime);
+        self.allowed_endpoint != previous_endpoint
+    }
+
+    /// Remembers the current access method if the API was reached using it. Returns true if the
+    /// current access method failed, so that another one should be tried.
+    fn record_result(&mut self, event: &ApiConnectivityEvent) -> bool {
+        if event.mode != self.mode {
+            // The attempt was made using a previous route.
+            return false;
+        }
+        if !event.reachable {
+            return true;
+        }
+
+        if self.last_working_method != Some(self.current_method) {
+            self.last_working_method = Some(self.current_method);
+            if let Err(error) = self.save_last_working_method() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Unable to save API access method")
+                );
+            }
+        }
+        false
+    }
+
+    fn allowed_methods(settings: &Settings) -> Vec<ApiAccessMethod> {
+        match settings.api_access_method {
+            Constraint::Only(method) => vec![method],
+            Constraint::Any => ApiAccessMethod::ALL
+                .iter()
+                .cloned()
+                .filter(|method| *method != ApiAccessMethod::Proxy || settings.api_proxy.is_some())
+                .collect(),
+        }
+    }
+
+    /// The allowed access methods in the order they are tried in, starting with the one that
+    /// worked last time.
+    fn initial_candidates(
+        settings: &Settings,
+        last_working_method: Option<ApiAccessMethod>,
+    ) -> Vec<ApiAccessMethod> {
+        let mut candidates = Self::allowed_methods(settings);
+        if let Some(position) = candidates
+            .iter()
+            .position(|method| Some(*method) == last_working_method)
+        {
+            candidates.rotate_left(position);
+        }
+        candidates
+    }
+
+    /// The allowed access methods in the order they are tried in, starting with the one after
+    /// the method that failed.
+    fn candidates_after_failure(
+        settings: &Settings,
+        failed_method: ApiAccessMethod,
+    ) -> Vec<ApiAccessMethod> {
+        let mut candidates = Self::allowed_methods(settings);
+        if let Some(position) = candidates
+            .iter()
+            .position(|method| *method == failed_method)
+        {
+            candidates.rotate_left(position + 1);
+        }
+        candidates
+    }
+
+    /// Activates the first candidate that can be used. Falls back to connecting directly if none
+    /// of them can.
+    fn activate_first(
+        &mut self,
+        candidates: Vec<ApiAccessMethod>,
+        settings: &Settings,
+        relay_selector: &mut RelaySelector,
+        rpc_runtime: &mut MullvadRpcRuntime,
+    ) {
+        for method in candidates {
+            match self.activate(method, settings, relay_selector, rpc_runtime) {
+                Ok(()) => return,
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg(&format!(
+                        "Unable to use the {} API access method",
+                        method
+                    ))
+                ),
+            }
+        }
+        log::warn!("No allowed API access method is usable, connecting directly");
+        let _ = self.activate(
+            ApiAccessMethod::Direct,
+            settings,
+            relay_selector,
+            rpc_runtime,
+        );
+    }
+
+    fn activate(
+        &mut self,
+        method: ApiAccessMethod,
+        settings: &Settings,
+        relay_selector: &mut RelaySelector,
+        rpc_runtime: &mut MullvadRpcRuntime,
+    ) -> Result<(), Error> {
+        #[cfg(not(target_os = "android"))]
+        let mut bridge_proxy = None;
+
+        let (mode, allowed_endpoint) = match method {
+            ApiAccessMethod::Direct => (
+                ApiConnectionMode::Direct,
+                Self::tcp_endpoint(rpc_runtime.api_address()),
+            ),
+            ApiAccessMethod::Proxy => {
+                let api_proxy = settings.api_proxy.clone().ok_or(Error::NoApiProxy)?;
+                let endpoint = api_proxy.get_endpoint();
+                (ApiConnectionMode::Proxied(api_proxy), endpoint)
+            }
+            #[cfg(not(target_os = "android"))]
+            ApiAccessMethod::Bridges => {
+                let bridge = relay_selector.get_api_bridge().ok_or(Error::NoBridge)?;
+                let monitor = proxy::start_proxy(&bridge).map_err(Error::StartBridgeProxy)?;
+                let local_proxy = ApiProxy {
+                    protocol: ApiProxyProtocol::Socks5,
+                    address: SocketAddr::new(Ipv4Addr::LOCALHOST.into(), monitor.port()),
+                    auth: None,
+                };
+                bridge_proxy = Some(monitor);
+                (
+                    ApiConnectionMode::Proxied(local_proxy),
+                    bridge.get_endpoint().endpoint,
+                )
+            }
+            #[cfg(target_os = "android")]
+            ApiAccessMethod::Bridges => {
+                let _ = relay_selector;
+                return Err(Error::BridgesNotSupported);
+            }
+        };
+
+        #[cfg(not(target_os = "android"))]
+        self.stop_bridge_proxy();
+        #[cfg(not(target_os = "android"))]
+        {
+            self.bridge_proxy = bridge_proxy;
+        }
+
+        log::info!(
+            "Reaching the API using the {} access method, via {}",
+            method,
+            allowed_endpoint
+        );
+        rpc_runtime.set_connection_mode(mode.clone());
+        self.current_method = method;
+        self.mode = mode;
+        self.allowed_endpoint = allowed_endpoint;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "android"))]
+    fn stop_bridge_proxy(&mut self) {
+        if let Some(mut monitor) = self.bridge_proxy.take() {
+            if let Err(error) = monitor.close_handle().close() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to stop the API bridge proxy")
+                );
+            }
+        }
+    }
+
+    fn tcp_endpoint(address: SocketAddr) -> Endpoint {
+        Endpoint::new(address.ip(), address.port(), TransportProtocol::Tcp)
+    }
+
+    fn load_last_working_method(cache_path: &Path) -> Result<ApiAccessMethod, Error> {
+        let file = File::open(cache_path).map_err(Error::OpenCache)?;
+        serde_json::from_reader(io::BufReader::new(file)).map_err(Error::SerializeCache)
+    }
+
+    fn save_last_working_method(&self) -> Result<(), Error> {
+        log::debug!(
+            "Saving last working API access method to {}",
+            self.cache_path.display()
+        );
+        let file = File::create(&self.cache_path).map_err(Error::OpenCache)?;
+        serde_json::to_writer(io::BufWriter::new(file), &self.current_method)
+            .map_err(Error::SerializeCache)
+    }
+}
+
+#[cfg(not(target_os = "android"))]
+impl Drop for ApiAccess {
+    fn drop(&mut self) {
+        self.stop_bridge_proxy();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mullvad_types::api_proxy::{ApiProxy, ApiProxyProtocol};
+    use std::{fs, process};
+
+    fn settings_with_proxy() -> Settings {
+        let mut settings = Settings::default();
+        settings.api_proxy = Some(ApiProxy {
+            protocol: ApiProxyProtocol::Socks5,
+            address: "192.0.2.1:1080".parse().unwrap(),
+            auth: None,
+        });
+        settings
+    }
+
+    fn api_access(cache_name: &str, method: ApiAccessMethod, mode: ApiConnectionMode) -> ApiAccess {
+        let cache_path =
+            std::env::temp_dir().join(format!("mullvad-{}-{}.json", cache_name, process::id()));
+        let _ = fs::remove_file(&cache_path);
+        ApiAccess {
+            cache_path,
+            last_working_method: None,
+            current_method: method,
+            mode,
+            allowed_endpoint: ApiAccess::tcp_endpoint("192.0.2.2:443".parse().unwrap()),
+            #[cfg(not(target_os = "android"))]
+            bridge_proxy: None,
+        }
+    }
+
+    #[test]
+    fn test_method_order() {
+        use ApiAccessMethod::*;
+
+        // The proxy is skipped unless one is configured
+        let mut settings = Settings::default();
+        assert_eq!(ApiAccess::allowed_methods(&settings), vec![Direct, Bridges]);
+        settings.api_access_method = Constraint::Only(Bridges);
+        assert_eq!(ApiAccess::allowed_methods(&settings), vec![Bridges]);
+
+        let settings = settings_with_proxy();
+        assert_eq!(
+            ApiAccess::allowed_methods(&settings),
+            vec![Proxy, Direct, Bridges]
+        );
+        assert_eq!(
+            ApiAccess::initial_candidates(&settings, None),
+            vec![Proxy, Direct, Bridges]
+        );
+        assert_eq!(
+            ApiAccess::initial_candidates(&settings, Some(Bridges)),
+            vec![Bridges, Proxy, Direct]
+        );
+
+        // A last working method that is no longer allowed is ignored
+        assert_eq!(
+            ApiAccess::initial_candidates(&Settings::default(), Some(Proxy)),
+            vec![Direct, Bridges]
+        );
+    }
+
+    #[test]
+    fn test_next_method_after_failure() {
+        use ApiAccessMethod::*;
+
+        let settings = settings_with_proxy();
+        assert_eq!(
+            ApiAccess::candidates_after_failure(&settings, Proxy),
+            vec![Direct, Bridges, Proxy]
+        );
+        assert_eq!(
+            ApiAccess::candidates_after_failure(&settings, Bridges),
+            vec![Proxy, Direct, Bridges]
+        );
+        assert_eq!(
+            ApiAccess::candidates_after_failure(&Settings::default(), Proxy),
+            vec![Direct, Bridges]
+        );
+
+        let mut api_access = api_access("failure", Direct, ApiConnectionMode::Direct);
+        assert!(api_access.record_result(&ApiConnectivityEvent {
+            mode: ApiConnectionMode::Direct,
+            reachable: false,
+        }));
+
+        // Results from a previous route are ignored
+        assert!(!api_access.record_result(&ApiConnectivityEvent {
+            mode: ApiConnectionMode::Proxied(settings.api_proxy.clone().unwrap()),
+            reachable: false,
+        }));
+        assert_eq!(api_access.last_working_method, None);
+    }
+
+    #[test]
+    fn test_save_working_method() {
+        let mode = ApiConnectionMode::Proxied(settings_with_proxy().api_proxy.unwrap());
+        let mut api_access = api_access("working", ApiAccessMethod::Proxy, mode.clone());
+
+        assert!(!api_access.record_result(&ApiConnectivityEvent {
+            mode,
+            reachable: true,
+        }));
+        assert_eq!(api_access.last_working_method, Some(ApiAccessMethod::Proxy));
+        assert_eq!(
+            ApiAccess::load_last_working_method(&api_access.cache_path).unwrap(),
+            ApiAccessMethod::Proxy
+        );
+
+        fs::remove_file(&api_access.cache_path).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
-use clap::{crate_authors, crate_description, crate_name, App, Arg};
+use clap::{crate_authors, crate_description, crate_name, value_t, App, Arg};
 use log;
+use mullvad_daemon::{logging::LogFormat, metrics::MetricsAddress};
 
 use crate::version;
 
@@ -7,6 +8,9 @@ pub struct Config {
     pub log_level: log::LevelFilter,
     pub log_to_file: bool,
     pub log_stdout_timestamps: bool,
+    pub log_format: LogFormat,
+    pub log_to_journald: bool,
+    pub metrics_address: Option<MetricsAddress>,
     pub run_as_service: bool,
     pub register_service: bool,
 }
@@ -29,6 +33,13 @@ pub fn create_config() -> Config {
     };
     let log_to_file = !matches.is_present("disable_log_to_file");
     let log_stdout_timestamps = !matches.is_present("disable_stdout_timestamps");
+    let log_format = value_t!(matches, "log_format", LogFormat).unwrap_or_else(|e| e.exit());
+    let log_to_journald = cfg!(target_os = "linux") && matches.is_present("log_to_journald");
+    let metrics_address = if matches.is_present("metrics_listen") {
+        Some(value_t!(matches, "metrics_listen", MetricsAddress).unwrap_or_else(|e| e.exit()))
+    } else {
+        None
+    };
 
     let run_as_service = cfg!(windows) && matches.is_present("run_as_service");
     let register_service = cfg!(windows) && matches.is_present("register_service");
@@ -37,6 +48,9 @@ pub fn create_config() -> Config {
         log_level,
         log_to_file,
         log_stdout_timestamps,
+        log_format,
+        log_to_journald,
+        metrics_address,
         run_as_service,
         register_service,
     }
@@ -84,7 +98,32 @@ fn create_app() -> App<'static, 'static> {
             Arg::with_name("disable_stdout_timestamps")
                 .long("disable-stdout-timestamps")
                 .help("Don't log timestamps when logging to stdout, useful when running as a systemd service")
-            );
+            )
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of the log lines written to stdout and the log file"),
+        )
+        .arg(
+            Arg::with_name("metrics_listen")
+                .long("metrics-listen")
+                .takes_value(true)
+                .value_name("ADDRESS")
+                .help("Serve Prometheus metrics on a loopback TCP address, e.g. 127.0.0.1:9180, or on a Unix socket path"),
+        );
+
+    let app = if cfg!(target_os = "linux") {
+        app.arg(
+            Arg::with_name("log_to_journald")
+                .long("log-to-journald")
+                .help("Also send logs to the systemd journal, including structured fields"),
+        )
+    } else {
+        app
+    };
 
     if cfg!(windows) {
         app.arg(
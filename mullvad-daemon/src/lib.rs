@@ -4,19 +4,23 @@
 extern crate serde;
 
 
-mod account_history;
+pub mod account_history;
+mod api_access;
 pub mod exception_logging;
 mod geoip;
 pub mod logging;
 #[cfg(not(target_os = "android"))]
 pub mod management_interface;
+pub mod metrics;
 mod relays;
 #[cfg(not(target_os = "android"))]
 pub mod rpc_uniqueness_check;
 mod settings;
+pub mod update;
 pub mod version;
 mod version_check;
 
+use chrono::{offset::Utc, DateTime};
 use futures::{
     future::{self, Executor},
     stream::Wait,
@@ -26,22 +30,30 @@ use futures::{
     },
     Future, Stream,
 };
+use ipnetwork::IpNetwork;
 use log::{debug, error, info, warn};
-use mullvad_rpc::AccountsProxy;
+use mullvad_rpc::{AccountsProxy, ApiConnectivityEvent};
 use mullvad_types::{
-    account::{AccountData, AccountToken, VoucherSubmission},
+    account::{AccountData, AccountHistoryEntry, AccountToken, VoucherSubmission},
+    api_access::ApiAccessMethod,
+    api_proxy::ApiProxy,
+    diagnostics::{ApiDiagnostics, Diagnostics, RelayListDiagnostics},
     endpoint::MullvadEndpoint,
     location::GeoIpLocation,
+    log_level::LogFilter,
     relay_constraints::{
         BridgeSettings, BridgeState, Constraint, InternalBridgeConstraints, RelaySettings,
         RelaySettingsUpdate,
     },
     relay_list::{Relay, RelayList},
+    retry_strategy::{RetryAttempt, RetryStrategy},
     settings::Settings,
     states::{TargetState, TunnelState},
-    version::{AppVersion, AppVersionInfo},
-    wireguard::KeygenEvent,
+    version::{AppVersion, AppVersionInfo, PendingUpdate},
+    wireguard::{KeyRotationEvent, KeygenEvent, RotationPolicy},
+    ConfigDirective, CustomTunnelConfigFile, ImportError, ImportedTunnel,
 };
+use rand::Rng;
 use settings::SettingsPersister;
 #[cfg(not(target_os = "android"))]
 use std::path::Path;
@@ -50,6 +62,7 @@ use std::{
     io,
     marker::PhantomData,
     mem,
+    net::IpAddr,
     path::PathBuf,
     sync::{mpsc, Arc, Weak},
     thread,
@@ -62,7 +75,10 @@ use talpid_core::{
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
 use talpid_types::{
-    net::{openvpn, TransportProtocol, TunnelParameters, TunnelType},
+    net::{
+        openvpn, proxy::TunnelSocksProxy, GatewayOptions, TransportProtocol, TunnelParameters,
+        TunnelType,
+    },
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
     ErrorExt,
 };
@@ -80,6 +96,9 @@ type BoxFuture<T, E> = Box<dyn Future<Item = T, Error = E> + Send>;
 
 const TUNNEL_STATE_MACHINE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// Shortest grace period of a key rotation that is resumed after the daemon has been restarted.
+const RESUMED_KEY_ROTATION_GRACE_PERIOD: Duration = Duration::from_secs(5 * 60);
+
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
 pub enum Error {
@@ -135,6 +154,31 @@ pub enum Error {
     OpenCachedTargetState(#[error(source)] io::Error),
 }
 
+/// Reasons for rejecting the destinations that should be excluded from the tunnel.
+#[derive(err_derive::Error, Debug)]
+pub enum ExcludedDestinationsError {
+    #[error(display = "{} overlaps the relay address {}", _0, _1)]
+    OverlapsRelay(IpNetwork, IpAddr),
+
+    #[error(display = "{} overlaps the tunnel gateway {}", _0, _1)]
+    OverlapsGateway(IpNetwork, IpAddr),
+
+    #[error(display = "Unable to save settings")]
+    SettingsError(#[error(source)] settings::Error),
+}
+
+/// Reasons for not exporting the current WireGuard connection.
+#[derive(err_derive::Error, Debug)]
+pub enum ExportWireguardConfigError {
+    #[error(display = "There is no WireGuard connection")]
+    NotConnected,
+
+    #[error(
+        display = "The connection is obfuscated, which a WireGuard configuration can't describe"
+    )]
+    Obfuscated,
+}
+
 /// Enum representing commands that can be sent to the daemon.
 pub enum DaemonCommand {
     /// Set target state. Does nothing if the daemon already has the state that is being set.
@@ -162,6 +206,17 @@ pub enum DaemonCommand {
     GetAccountHistory(oneshot::Sender<Vec<AccountToken>>),
     /// Request account history
     RemoveAccountFromHistory(oneshot::Sender<()>, AccountToken),
+    /// Request account history, including labels, timestamps and cached expiry dates
+    ListAccounts(oneshot::Sender<Vec<AccountHistoryEntry>>),
+    /// Set or remove the label of an account in the account history
+    SetAccountLabel(
+        oneshot::Sender<Result<(), account_history::Error>>,
+        AccountToken,
+        Option<String>,
+    ),
+    /// Make the account with the given label, or account token, in the account history the
+    /// current account
+    SwitchAccount(oneshot::Sender<Result<(), account_history::Error>>, String),
     /// Get the list of countries and cities where there are relays.
     GetRelayLocations(oneshot::Sender<RelayList>),
     /// Trigger an asynchronous relay list update. This returns before the relay list is actually
@@ -171,16 +226,36 @@ pub enum DaemonCommand {
     SetAccount(oneshot::Sender<()>, Option<AccountToken>),
     /// Place constraints on the type of tunnel and relay
     UpdateRelaySettings(oneshot::Sender<()>, RelaySettingsUpdate),
+    /// Use a custom tunnel read from a WireGuard or OpenVPN configuration file. Returns the
+    /// directives in the file that have no effect.
+    ImportCustomTunnel(
+        oneshot::Sender<std::result::Result<Vec<ConfigDirective>, ImportError>>,
+        CustomTunnelConfigFile,
+    ),
+    /// Return the current WireGuard connection as a `wg-quick` configuration file
+    ExportWireguardConfig(oneshot::Sender<std::result::Result<String, ExportWireguardConfigError>>),
     /// Set the allow LAN setting.
     SetAllowLan(oneshot::Sender<()>, bool),
+    /// Share the tunnel with a local network, or stop doing so. Only supported on Linux.
+    SetGateway(oneshot::Sender<()>, Option<GatewayOptions>),
+    /// Set the local SOCKS5 proxy that is run through the tunnel, or unset it.
+    SetTunnelSocksProxy(oneshot::Sender<()>, Option<TunnelSocksProxy>),
     /// Set the beta program setting.
     SetShowBetaReleases(oneshot::Sender<()>, bool),
+    /// Set whether new releases should be downloaded in the background.
+    SetDownloadUpdates(oneshot::Sender<()>, bool),
     /// Set the block_when_disconnected setting.
     SetBlockWhenDisconnected(oneshot::Sender<()>, bool),
     /// Set the auto-connect setting.
     SetAutoConnect(oneshot::Sender<()>, bool),
     /// Set the mssfix argument for OpenVPN
     SetOpenVpnMssfix(oneshot::Sender<()>, Option<u16>),
+    /// Set or, if the value is `None`, unset an extra OpenVPN option
+    SetOpenVpnExtraOption(
+        oneshot::Sender<std::result::Result<(), openvpn::ExtraOptionError>>,
+        openvpn::ExtraOption,
+        Option<u32>,
+    ),
     /// Set proxy details for OpenVPN
     SetBridgeSettings(
         oneshot::Sender<std::result::Result<(), settings::Error>>,
@@ -195,8 +270,25 @@ pub enum DaemonCommand {
     SetEnableIpv6(oneshot::Sender<()>, bool),
     /// Set MTU for wireguard tunnels
     SetWireguardMtu(oneshot::Sender<()>, Option<u16>),
+    /// Set if wireguard tunnels should use policy routing instead of the main routing table
+    SetWireguardPolicyRouting(oneshot::Sender<()>, bool),
+    /// Set a network namespace that wireguard tunnels should be moved into, or unset it
+    SetWireguardNetworkNamespace(oneshot::Sender<()>, Option<String>),
+    /// Set the destination networks that are routed outside of the tunnel
+    SetExcludedDestinations(
+        oneshot::Sender<std::result::Result<(), ExcludedDestinationsError>>,
+        Vec<IpNetwork>,
+    ),
     /// Set automatic key rotation interval for wireguard tunnels
     SetWireguardRotationInterval(oneshot::Sender<()>, Option<u32>),
+    /// Set how automatic key rotation replaces the wireguard key
+    SetKeyRotationPolicy(oneshot::Sender<()>, RotationPolicy),
+    /// Set the strategy used when connection attempts fail
+    SetRetryStrategy(oneshot::Sender<()>, RetryStrategy),
+    /// Set a proxy that all API requests are sent through
+    SetApiProxy(oneshot::Sender<()>, Option<ApiProxy>),
+    /// Set how the API may be reached
+    SetApiAccessMethod(oneshot::Sender<()>, Constraint<ApiAccessMethod>),
     /// Get the daemon settings
     GetSettings(oneshot::Sender<Settings>),
     /// Generate new wireguard key
@@ -205,10 +297,34 @@ pub enum DaemonCommand {
     GetWireguardKey(oneshot::Sender<Option<wireguard::PublicKey>>),
     /// Verify if the currently set wireguard key is valid.
     VerifyWireguardKey(oneshot::Sender<bool>),
+    /// List all wireguard keys registered on the current account
+    ListWireguardKeys(oneshot::Sender<Result<Vec<wireguard::RegisteredKey>, wireguard::Error>>),
+    /// Remove a wireguard key from the current account
+    RevokeWireguardKey(
+        oneshot::Sender<Result<(), wireguard::Error>>,
+        talpid_types::net::wireguard::PublicKey,
+    ),
+    /// Use an existing wireguard private key for the current account
+    ImportWireguardKey(
+        oneshot::Sender<Result<wireguard::PublicKey, wireguard::Error>>,
+        wireguard::PrivateKey,
+    ),
     /// Get information about the currently running and latest app versions
     GetVersionInfo(oneshot::Sender<AppVersionInfo>),
     /// Get current version of the app
     GetCurrentVersion(oneshot::Sender<AppVersion>),
+    /// Get the downloaded and verified release that has not been installed yet, if any
+    GetPendingUpdate(oneshot::Sender<Option<PendingUpdate>>),
+    /// Start the installer of the pending update
+    ApplyUpdate(oneshot::Sender<Result<(), update::Error>>),
+    /// Collect diagnostics from the daemon and the tunnel state machine
+    GetDiagnostics(oneshot::Sender<BoxFuture<Diagnostics, ()>>),
+    /// Collect the values exported by the metrics endpoint
+    GetMetrics(oneshot::Sender<BoxFuture<metrics::MetricsSnapshot, ()>>),
+    /// Change the log level filter of the running daemon
+    SetLogFilter(oneshot::Sender<()>, LogFilter),
+    /// Get the log level filter of the running daemon
+    GetLogFilter(oneshot::Sender<LogFilter>),
     /// Remove settings and clear the cache
     #[cfg(not(target_os = "android"))]
     FactoryReset(oneshot::Sender<()>),
@@ -239,13 +355,38 @@ pub(crate) enum InternalDaemonEvent {
             Result<mullvad_types::wireguard::WireguardData, wireguard::Error>,
         ),
     ),
+    /// The wireguard key of an account is old enough to be rotated.
+    KeyRotationDue(AccountToken),
+    /// The new key of a key rotation was pushed, or failed to be pushed, to an account.
+    KeyRotationPushed(
+        AccountToken,
+        Result<mullvad_types::wireguard::WireguardData, wireguard::Error>,
+    ),
+    /// The API answered whether the new key of a key rotation is registered on the account.
+    KeyRotationVerified(talpid_types::net::wireguard::PublicKey, bool),
+    /// The grace period of a key rotation is over.
+    KeyRotationGracePeriodOver(talpid_types::net::wireguard::PublicKey),
+    /// A wireguard key was removed from an account
+    WgKeyRevoked(AccountToken, talpid_types::net::wireguard::PublicKey),
+    /// An existing wireguard key was made usable with an account
+    WgKeyImported(
+        AccountToken,
+        mullvad_types::wireguard::WireguardData,
+        oneshot::Sender<Result<wireguard::PublicKey, wireguard::Error>>,
+    ),
     /// New Account created
     NewAccountEvent(
         AccountToken,
         oneshot::Sender<Result<String, mullvad_rpc::rest::Error>>,
     ),
+    /// The expiry of an account was fetched from the API.
+    NewAccountExpiry(AccountToken, DateTime<Utc>),
     /// The background job fetching new `AppVersionInfo`s got a new info object.
     NewAppVersionInfo(AppVersionInfo),
+    /// The download of a new release finished or failed.
+    UpdateDownloaded(AppVersion, Result<PendingUpdate, update::Error>),
+    /// An attempt to connect to the API succeeded or failed.
+    ApiConnectivity(ApiConnectivityEvent),
 }
 
 impl From<TunnelStateTransition> for InternalDaemonEvent {
@@ -260,6 +401,12 @@ impl From<DaemonCommand> for InternalDaemonEvent {
     }
 }
 
+impl From<ApiConnectivityEvent> for InternalDaemonEvent {
+    fn from(event: ApiConnectivityEvent) -> Self {
+        InternalDaemonEvent::ApiConnectivity(event)
+    }
+}
+
 impl From<AppVersionInfo> for InternalDaemonEvent {
     fn from(command: AppVersionInfo) -> Self {
         InternalDaemonEvent::NewAppVersionInfo(command)
@@ -309,6 +456,27 @@ impl DaemonExecutionState {
     }
 }
 
+/// What happens to the tunnel when the current account changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum AccountChangeAction {
+    /// The tunnel is reconnected, so that it uses the new account and its WireGuard key.
+    Reconnect,
+    /// The tunnel is disconnected, since there is no account to connect with.
+    Disconnect,
+    /// Nothing happens, since the tunnel is not supposed to be up.
+    None,
+}
+
+impl AccountChangeAction {
+    fn new(target_state: TargetState, account_token: Option<&AccountToken>) -> Self {
+        match (account_token, target_state) {
+            (None, _) => AccountChangeAction::Disconnect,
+            (Some(_), TargetState::Secured) => AccountChangeAction::Reconnect,
+            (Some(_), TargetState::Unsecured) => AccountChangeAction::None,
+        }
+    }
+}
+
 pub struct DaemonCommandChannel {
     sender: DaemonCommandSender,
     receiver: UnboundedReceiver<InternalDaemonEvent>,
@@ -424,6 +592,9 @@ pub trait EventListener {
 
     /// Notify clients of a key generation event.
     fn notify_key_event(&self, key_event: KeygenEvent);
+
+    /// Notify clients of a phase of an automatic key rotation.
+    fn notify_key_rotation_event(&self, rotation_event: KeyRotationEvent);
 }
 
 pub struct Daemon<L: EventListener> {
@@ -434,22 +605,36 @@ pub struct Daemon<L: EventListener> {
     rx: Wait<UnboundedReceiver<InternalDaemonEvent>>,
     tx: DaemonEventSender,
     reconnection_loop_tx: Option<mpsc::Sender<()>>,
+    reconnect_backoff_attempt: u32,
     event_listener: L,
     settings: SettingsPersister,
     account_history: account_history::AccountHistory,
     accounts_proxy: AccountsProxy,
     rpc_runtime: mullvad_rpc::MullvadRpcRuntime,
+    api_access: api_access::ApiAccess,
     rpc_handle: mullvad_rpc::rest::MullvadRestHandle,
     wireguard_key_manager: wireguard::KeyManager,
+    key_rotation: Option<wireguard::KeyRotation>,
+    /// Whether a postponed key rotation has been reported since the last rotation started.
+    key_rotation_postponed: bool,
+    /// Public key used by the most recently generated wireguard tunnel parameters.
+    last_wireguard_key: Option<talpid_types::net::wireguard::PublicKey>,
+    /// Connection of the most recently generated wireguard tunnel parameters.
+    last_wireguard_connection: Option<talpid_types::net::wireguard::ConnectionConfig>,
     core_handle: event_loop::CoreHandle,
     relay_selector: relays::RelaySelector,
     last_generated_relay: Option<Relay>,
     last_generated_bridge_relay: Option<Relay>,
     app_version_info: AppVersionInfo,
+    update_downloader: update::UpdateDownloader,
+    pending_update: Option<PendingUpdate>,
+    /// Version of the release that is currently being downloaded, if any.
+    downloading_update: Option<AppVersion>,
     shutdown_callbacks: Vec<Box<dyn FnOnce()>>,
     /// oneshot channel that completes once the tunnel state machine has been shut down
     tunnel_state_machine_shutdown_signal: oneshot::Receiver<()>,
     cache_dir: PathBuf,
+    metrics_counters: metrics::Counters,
 }
 
 impl<L> Daemon<L>
@@ -468,6 +653,12 @@ where
         let (tunnel_state_machine_shutdown_tx, tunnel_state_machine_shutdown_signal) =
             oneshot::channel();
 
+        let mut settings = SettingsPersister::load(&settings_dir);
+
+        if version::is_beta_version() {
+            let _ = settings.set_show_beta_releases(true);
+        }
+
         let mut rpc_runtime = mullvad_rpc::MullvadRpcRuntime::with_cache_dir(&cache_dir)
             .map_err(Error::InitRpcFactory)?;
         let rpc_handle = rpc_runtime.mullvad_rest_handle();
@@ -478,7 +669,7 @@ where
         let on_relay_list_update = move |relay_list: &RelayList| {
             relay_list_listener.notify_relay_list(relay_list.clone());
         };
-        let relay_selector = relays::RelaySelector::new(
+        let mut relay_selector = relays::RelaySelector::new(
             rpc_handle.clone(),
             on_relay_list_update,
             &resource_dir,
@@ -487,6 +678,17 @@ where
 
         let (internal_event_tx, internal_event_rx) = command_channel.destructure();
 
+        let api_connectivity_tx = internal_event_tx.to_specialized_sender();
+        rpc_runtime.set_connectivity_listener(move |event| {
+            let _ = api_connectivity_tx.send(event);
+        });
+        let api_access = api_access::ApiAccess::new(
+            &cache_dir,
+            &settings,
+            &mut relay_selector,
+            &mut rpc_runtime,
+        );
+
         let app_version_info = version_check::load_cache(&cache_dir);
         let version_check_future = version_check::VersionUpdater::new(
             rpc_handle.clone(),
@@ -496,11 +698,9 @@ where
         );
         core_handle.remote.spawn(|_| version_check_future);
 
-        let mut settings = SettingsPersister::load(&settings_dir);
-
-        if version::is_beta_version() {
-            let _ = settings.set_show_beta_releases(true);
-        }
+        let update_downloader =
+            update::UpdateDownloader::new(rpc_runtime.rest_handle(), &cache_dir);
+        let pending_update = update_downloader.load_pending();
 
         let account_history = account_history::AccountHistory::new(
             &cache_dir,
@@ -536,6 +736,9 @@ where
         let tunnel_command_tx = tunnel_state_machine::spawn(
             settings.allow_lan,
             settings.block_when_disconnected,
+            Some(api_access.allowed_endpoint()),
+            settings.gateway.clone(),
+            settings.tunnel_socks_proxy.clone(),
             tunnel_parameters_generator,
             log_dir,
             resource_dir,
@@ -577,24 +780,36 @@ where
             rx: internal_event_rx.wait(),
             tx: internal_event_tx,
             reconnection_loop_tx: None,
+            reconnect_backoff_attempt: 0,
             event_listener,
             settings,
             account_history,
             rpc_runtime,
+            api_access,
             accounts_proxy: AccountsProxy::new(rpc_handle.clone()),
             rpc_handle,
             wireguard_key_manager,
+            key_rotation: None,
+            key_rotation_postponed: false,
+            last_wireguard_key: None,
+            last_wireguard_connection: None,
             core_handle,
             relay_selector,
             last_generated_relay: None,
             last_generated_bridge_relay: None,
             app_version_info,
+            update_downloader,
+            pending_update,
+            downloading_update: None,
             shutdown_callbacks: vec![],
             tunnel_state_machine_shutdown_signal,
             cache_dir,
+            metrics_counters: metrics::Counters::new(),
         };
 
         daemon.ensure_wireguard_keys_for_current_account();
+        daemon.resume_key_rotation();
+        daemon.download_update_if_available();
 
         if let Some(token) = daemon.settings.get_account_token() {
             daemon.wireguard_key_manager.set_rotation_interval(
@@ -684,10 +899,31 @@ where
             Command(command) => self.handle_command(command),
             TriggerShutdown => self.trigger_shutdown_event(),
             WgKeyEvent(key_event) => self.handle_wireguard_key_event(key_event),
+            KeyRotationDue(account_token) => self.handle_key_rotation_due(account_token),
+            KeyRotationPushed(account_token, result) => {
+                self.handle_key_rotation_pushed(account_token, result)
+            }
+            KeyRotationVerified(public_key, is_valid) => {
+                self.handle_key_rotation_verified(public_key, is_valid)
+            }
+            KeyRotationGracePeriodOver(public_key) => {
+                self.handle_key_rotation_grace_period_over(public_key)
+            }
+            WgKeyRevoked(account_token, public_key) => {
+                self.handle_wireguard_key_revoked(account_token, public_key)
+            }
+            WgKeyImported(account_token, data, tx) => {
+                self.handle_wireguard_key_imported(account_token, data, tx)
+            }
             NewAccountEvent(account_token, tx) => self.handle_new_account_event(account_token, tx),
+            NewAccountExpiry(account_token, expiry) => {
+                self.handle_new_account_expiry(account_token, expiry)
+            }
             NewAppVersionInfo(app_version_info) => {
                 self.handle_new_app_version_info(app_version_info)
             }
+            UpdateDownloaded(version, result) => self.handle_update_downloaded(version, result),
+            ApiConnectivity(event) => self.handle_api_connectivity_event(event),
         }
     }
 
@@ -721,6 +957,8 @@ where
 
 
         self.unschedule_reconnect();
+        self.update_log_context(&tunnel_state);
+        self.metrics_counters.tunnel_state_changed(&tunnel_state);
 
         debug!("New tunnel state: {:?}", tunnel_state);
         match tunnel_state {
@@ -738,8 +976,18 @@ where
                     );
                 }
 
-                if let ErrorStateCause::AuthFailed(_) = error_state.cause() {
-                    self.schedule_reconnect(Duration::from_secs(60))
+                match error_state.cause() {
+                    ErrorStateCause::AuthFailed(_)
+                    | ErrorStateCause::TunnelParameterError(
+                        ParameterGenerationError::RetryAttemptsExhausted,
+                    ) => self.schedule_backoff_reconnect(),
+                    _ => (),
+                }
+            }
+            TunnelState::Connected { ref endpoint, .. } => {
+                self.reconnect_backoff_attempt = 0;
+                if endpoint.tunnel_type == TunnelType::Wireguard {
+                    self.complete_key_rotation_after_handshake();
                 }
             }
             _ => {}
@@ -749,34 +997,80 @@ where
         self.event_listener.notify_new_state(tunnel_state);
     }
 
+    fn update_log_context(&self, tunnel_state: &TunnelState) {
+        let relay_hostname = match tunnel_state {
+            TunnelState::Connecting { .. } | TunnelState::Connected { .. } => self
+                .last_generated_relay
+                .as_ref()
+                .map(|relay| relay.hostname.clone()),
+            _ => None,
+        };
+        logging::set_log_context(tunnel_state_name(tunnel_state), relay_hostname);
+    }
+
     fn handle_generate_tunnel_parameters(
         &mut self,
         tunnel_parameters_tx: &mpsc::Sender<Result<TunnelParameters, ParameterGenerationError>>,
         retry_attempt: u32,
     ) {
         if let Some(account_token) = self.settings.get_account_token() {
-            let result = match self.settings.get_relay_settings() {
-                RelaySettings::CustomTunnelEndpoint(custom_relay) => {
-                    self.last_generated_relay = None;
-                    custom_relay
-                        // TODO(emilsp): generate proxy settings for custom tunnels
-                        .to_tunnel_parameters(self.settings.tunnel_options.clone(), None)
-                        .map_err(|e| {
-                            log::error!("Failed to resolve hostname for custom tunnel config: {}", e);
-                            ParameterGenerationError::CustomTunnelHostResultionError
-                        })
-                }
-                RelaySettings::Normal(constraints) => self
-                    .relay_selector
+            let result = if self.settings.retry_strategy.is_exhausted(retry_attempt) {
+                info!(
+                    "Giving up after {} failed connection attempts",
+                    retry_attempt
+                );
+                Err(ParameterGenerationError::RetryAttemptsExhausted)
+            } else {
+                self.generate_tunnel_parameters(account_token, retry_attempt)
+                    .and_then(Self::check_excluded_destination_overlap)
+            };
+            self.last_wireguard_connection = match &result {
+                Ok(TunnelParameters::Wireguard(parameters)) => Some(parameters.connection.clone()),
+                _ => None,
+            };
+            if tunnel_parameters_tx.send(result).is_err() {
+                log::error!("Failed to send tunnel parameters");
+            }
+        } else {
+            error!("No account token configured");
+        }
+    }
+
+    fn generate_tunnel_parameters(
+        &mut self,
+        account_token: AccountToken,
+        retry_attempt: u32,
+    ) -> Result<TunnelParameters, ParameterGenerationError> {
+        match self.settings.get_relay_settings() {
+            RelaySettings::CustomTunnelEndpoint(custom_relay) => {
+                self.last_generated_relay = None;
+                custom_relay
+                    // TODO(emilsp): generate proxy settings for custom tunnels
+                    .to_tunnel_parameters(self.settings.tunnel_options.clone(), None)
+                    .map_err(|e| {
+                        log::error!("Failed to resolve hostname for custom tunnel config: {}", e);
+                        ParameterGenerationError::CustomTunnelHostResultionError
+                    })
+            }
+            RelaySettings::Normal(constraints) => {
+                let wg_key_exists = self
+                    .account_history
+                    .get(&account_token)
+                    .unwrap_or(None)
+                    .and_then(|entry| entry.wireguard)
+                    .is_some();
+                let preferences = self.relay_selector.get_retry_attempt(
+                    &constraints,
+                    self.settings.get_bridge_state(),
+                    &self.settings.retry_strategy,
+                    retry_attempt,
+                    wg_key_exists,
+                );
+                self.relay_selector
                     .get_tunnel_endpoint(
                         &constraints,
                         self.settings.get_bridge_state(),
-                        retry_attempt,
-                        self.account_history
-                            .get(&account_token)
-                            .unwrap_or(None)
-                            .and_then(|entry| entry.wireguard)
-                            .is_some(),
+                        preferences.as_ref(),
                     )
                     .map_err(|_| ParameterGenerationError::NoMatchingRelay)
                     .and_then(|(relay, endpoint)| {
@@ -784,7 +1078,7 @@ where
                             &relay,
                             endpoint,
                             account_token,
-                            retry_attempt,
+                            preferences.as_ref(),
                         );
                         self.last_generated_relay = Some(relay);
                         match result {
@@ -805,13 +1099,25 @@ where
                                 Err(ParameterGenerationError::NoMatchingRelay)
                             }
                         }
-                    }),
-            };
-            if tunnel_parameters_tx.send(result).is_err() {
-                log::error!("Failed to send tunnel parameters");
+                    })
             }
-        } else {
-            error!("No account token configured");
+        }
+    }
+
+    /// Refuses tunnel parameters if an excluded destination would route traffic for one of their
+    /// relays, bridges or gateways outside of the tunnel.
+    fn check_excluded_destination_overlap(
+        parameters: TunnelParameters,
+    ) -> Result<TunnelParameters, ParameterGenerationError> {
+        match parameters.find_excluded_destination_overlap() {
+            Some((destination, address)) => {
+                error!(
+                    "The excluded destination {} overlaps {}, which is used by the tunnel",
+                    destination, address
+                );
+                Err(ParameterGenerationError::ExcludedDestinationOverlap)
+            }
+            None => Ok(parameters),
         }
     }
 
@@ -820,7 +1126,7 @@ where
         relay: &Relay,
         endpoint: MullvadEndpoint,
         account_token: String,
-        retry_attempt: u32,
+        preferences: Option<&RetryAttempt>,
     ) -> Result<TunnelParameters, Error> {
         let tunnel_options = self.settings.tunnel_options.clone();
         let location = relay.location.as_ref().expect("Relay has no location set");
@@ -848,7 +1154,7 @@ where
                                     self.relay_selector.get_auto_proxy_settings(
                                         &bridge_constraints,
                                         location,
-                                        retry_attempt,
+                                        preferences,
                                     )
                                 {
                                     self.last_generated_bridge_relay = Some(bridge_relay);
@@ -864,7 +1170,7 @@ where
                         match self.settings.get_bridge_state() {
                             BridgeState::On => Some(proxy_settings.clone()),
                             BridgeState::Auto => {
-                                if self.relay_selector.should_use_bridge(retry_attempt) {
+                                if relays::RelaySelector::should_use_bridge(preferences) {
                                     Some(proxy_settings.clone())
                                 } else {
                                     None
@@ -891,6 +1197,7 @@ where
                 peer,
                 ipv4_gateway,
                 ipv6_gateway,
+                obfuscation,
             } => {
                 let wg_data = self
                     .account_history
@@ -898,6 +1205,11 @@ where
                     .map_err(Error::AccountHistory)?
                     .and_then(|entry| entry.wireguard)
                     .ok_or(Error::NoKeyAvailable)?;
+                let public_key = wg_data.private_key.public_key();
+                if let Some(key_rotation) = &mut self.key_rotation {
+                    key_rotation.key_used(&public_key);
+                }
+                self.last_wireguard_key = Some(public_key);
                 let tunnel = wireguard::TunnelConfig {
                     private_key: wg_data.private_key,
                     addresses: vec![
@@ -909,8 +1221,11 @@ where
                     connection: wireguard::ConnectionConfig {
                         tunnel,
                         peer,
+                        additional_peers: vec![],
                         ipv4_gateway,
                         ipv6_gateway: Some(ipv6_gateway),
+                        dns_servers: vec![],
+                        obfuscation,
                     },
                     options: tunnel_options.wireguard,
                     generic_options: tunnel_options.generic,
@@ -920,6 +1235,18 @@ where
         }
     }
 
+    /// Schedules a reconnect after a delay given by the backoff of the retry strategy. The delay
+    /// grows for each consecutive reconnect until the tunnel has successfully connected.
+    fn schedule_backoff_reconnect(&mut self) {
+        let delay = self.settings.retry_strategy.backoff.delay(
+            self.reconnect_backoff_attempt,
+            rand::thread_rng().gen_range(-1.0, 1.0),
+        );
+        self.reconnect_backoff_attempt = self.reconnect_backoff_attempt.saturating_add(1);
+        info!("Reconnecting in {} seconds", delay.as_secs());
+        self.schedule_reconnect(delay);
+    }
+
     fn schedule_reconnect(&mut self, delay: Duration) {
         let tunnel_command_tx = self.tx.to_specialized_sender();
         let (tx, rx) = mpsc::channel();
@@ -967,29 +1294,66 @@ where
             RemoveAccountFromHistory(tx, account_token) => {
                 self.on_remove_account_from_history(tx, account_token)
             }
+            ListAccounts(tx) => self.on_list_accounts(tx),
+            SetAccountLabel(tx, account_token, label) => {
+                self.on_set_account_label(tx, account_token, label)
+            }
+            SwitchAccount(tx, label) => self.on_switch_account(tx, label),
             UpdateRelaySettings(tx, update) => self.on_update_relay_settings(tx, update),
+            ImportCustomTunnel(tx, config_file) => self.on_import_custom_tunnel(tx, config_file),
+            ExportWireguardConfig(tx) => self.on_export_wireguard_config(tx),
             SetAllowLan(tx, allow_lan) => self.on_set_allow_lan(tx, allow_lan),
+            SetGateway(tx, gateway) => self.on_set_gateway(tx, gateway),
+            SetTunnelSocksProxy(tx, proxy) => self.on_set_tunnel_socks_proxy(tx, proxy),
             SetShowBetaReleases(tx, enabled) => self.on_set_show_beta_releases(tx, enabled),
+            SetDownloadUpdates(tx, enabled) => self.on_set_download_updates(tx, enabled),
             SetBlockWhenDisconnected(tx, block_when_disconnected) => {
                 self.on_set_block_when_disconnected(tx, block_when_disconnected)
             }
             SetAutoConnect(tx, auto_connect) => self.on_set_auto_connect(tx, auto_connect),
             SetOpenVpnMssfix(tx, mssfix_arg) => self.on_set_openvpn_mssfix(tx, mssfix_arg),
+            SetOpenVpnExtraOption(tx, option, value) => {
+                self.on_set_openvpn_extra_option(tx, option, value)
+            }
             SetBridgeSettings(tx, bridge_settings) => {
                 self.on_set_bridge_settings(tx, bridge_settings)
             }
             SetBridgeState(tx, bridge_state) => self.on_set_bridge_state(tx, bridge_state),
             SetEnableIpv6(tx, enable_ipv6) => self.on_set_enable_ipv6(tx, enable_ipv6),
             SetWireguardMtu(tx, mtu) => self.on_set_wireguard_mtu(tx, mtu),
+            SetWireguardPolicyRouting(tx, enabled) => {
+                self.on_set_wireguard_policy_routing(tx, enabled)
+            }
+            SetWireguardNetworkNamespace(tx, network_namespace) => {
+                self.on_set_wireguard_network_namespace(tx, network_namespace)
+            }
+            SetExcludedDestinations(tx, destinations) => {
+                self.on_set_excluded_destinations(tx, destinations)
+            }
             SetWireguardRotationInterval(tx, interval) => {
                 self.on_set_wireguard_rotation_interval(tx, interval)
             }
+            SetKeyRotationPolicy(tx, policy) => self.on_set_key_rotation_policy(tx, policy),
+            SetRetryStrategy(tx, retry_strategy) => self.on_set_retry_strategy(tx, retry_strategy),
+            SetApiProxy(tx, api_proxy) => self.on_set_api_proxy(tx, api_proxy),
+            SetApiAccessMethod(tx, api_access_method) => {
+                self.on_set_api_access_method(tx, api_access_method)
+            }
             GetSettings(tx) => self.on_get_settings(tx),
             GenerateWireguardKey(tx) => self.on_generate_wireguard_key(tx),
             GetWireguardKey(tx) => self.on_get_wireguard_key(tx),
             VerifyWireguardKey(tx) => self.on_verify_wireguard_key(tx),
+            ListWireguardKeys(tx) => self.on_list_wireguard_keys(tx),
+            RevokeWireguardKey(tx, public_key) => self.on_revoke_wireguard_key(tx, public_key),
+            ImportWireguardKey(tx, private_key) => self.on_import_wireguard_key(tx, private_key),
             GetVersionInfo(tx) => self.on_get_version_info(tx),
             GetCurrentVersion(tx) => self.on_get_current_version(tx),
+            GetPendingUpdate(tx) => self.on_get_pending_update(tx),
+            ApplyUpdate(tx) => self.on_apply_update(tx),
+            GetDiagnostics(tx) => self.on_get_diagnostics(tx),
+            GetMetrics(tx) => self.on_get_metrics(tx),
+            SetLogFilter(tx, log_filter) => self.on_set_log_filter(tx, log_filter),
+            GetLogFilter(tx) => self.on_get_log_filter(tx),
             #[cfg(not(target_os = "android"))]
             FactoryReset(tx) => self.on_factory_reset(tx),
             Shutdown => self.trigger_shutdown_event(),
@@ -1019,16 +1383,14 @@ where
 
         match result {
             Ok(data) => {
+                self.metrics_counters.key_event("new_key");
                 let public_key = data.get_public_key();
                 let mut account_entry = self
                     .account_history
                     .get(&account)
                     .ok()
                     .and_then(|entry| entry)
-                    .unwrap_or_else(|| account_history::AccountEntry {
-                        account: account.clone(),
-                        wireguard: None,
-                    });
+                    .unwrap_or_else(|| account_history::AccountEntry::new(account.clone()));
                 account_entry.wireguard = Some(data);
                 match self.account_history.insert(account_entry) {
                     Ok(_) => self
@@ -1047,10 +1409,12 @@ where
                 }
             }
             Err(wireguard::Error::TooManyKeys) => {
+                self.metrics_counters.key_event("too_many_keys");
                 self.event_listener
                     .notify_key_event(KeygenEvent::TooManyKeys);
             }
             Err(e) => {
+                self.metrics_counters.key_event("failure");
                 log::error!(
                     "{}",
                     e.display_chain_with_msg("Failed to generate wireguard key")
@@ -1061,92 +1425,686 @@ where
         }
     }
 
-    fn handle_new_account_event(
-        &mut self,
-        new_token: AccountToken,
-        tx: oneshot::Sender<Result<String, mullvad_rpc::rest::Error>>,
-    ) {
-        match self.set_account(Some(new_token.clone())) {
-            Ok(_) => {
-                self.set_target_state(TargetState::Unsecured);
-                let _ = tx.send(Ok(new_token));
-            }
-            Err(err) => {
-                log::error!("Failed to save new account - {}", err);
-            }
+    fn handle_key_rotation_due(&mut self, account_token: AccountToken) {
+        if self.key_rotation.is_some()
+            || self.settings.get_account_token() != Some(account_token.clone())
+        {
+            return;
+        }
+        let tunnel_is_up = match self.tunnel_state {
+            TunnelState::Connecting { .. } | TunnelState::Connected { .. } => true,
+            _ => false,
         };
-    }
+        if tunnel_is_up && !self.settings.key_rotation_policy.rotate_while_connected {
+            log::debug!("Postponing key rotation since the tunnel is up");
+            // The rotation is retried every few minutes, so only the first postponement is sent
+            if !self.key_rotation_postponed {
+                self.key_rotation_postponed = true;
+                self.event_listener
+                    .notify_key_rotation_event(KeyRotationEvent::Postponed);
+            }
+            return;
+        }
+        self.key_rotation_postponed = false;
 
-    fn handle_new_app_version_info(&mut self, app_version_info: AppVersionInfo) {
-        self.app_version_info = app_version_info.clone();
-        self.event_listener.notify_app_version(app_version_info);
+        log::info!("Replacing WireGuard key");
+        let daemon_tx = self.tx.clone();
+        let pushed_account_token = account_token.clone();
+        let fut = self
+            .wireguard_key_manager
+            .push_new_key(account_token.clone())
+            .then(move |result| -> Result<(), ()> {
+                let _ = daemon_tx.send(InternalDaemonEvent::KeyRotationPushed(
+                    pushed_account_token,
+                    result,
+                ));
+                Ok(())
+            });
+        match self.core_handle.remote.execute(fut) {
+            Ok(()) => self.key_rotation = Some(wireguard::KeyRotation::Pushing(account_token)),
+            Err(e) => log::error!("Failed to spawn a future to rotate wireguard key: {:?}", e),
+        }
     }
 
-    fn on_set_target_state(
+    /// Starts using the new key of a key rotation. The old key is kept on the account until the
+    /// new key has been used for a handshake, or the grace period is over.
+    fn handle_key_rotation_pushed(
         &mut self,
-        tx: oneshot::Sender<Result<(), ()>>,
-        new_target_state: TargetState,
+        account_token: AccountToken,
+        result: Result<mullvad_types::wireguard::WireguardData, wireguard::Error>,
     ) {
-        if self.state.is_running() {
-            self.set_target_state(new_target_state);
-        } else {
-            warn!("Ignoring target state change request due to shutdown");
+        match self.key_rotation {
+            Some(wireguard::KeyRotation::Pushing(ref rotating_account))
+                if *rotating_account == account_token => {}
+            _ => {
+                if let Ok(new_data) = result {
+                    log::info!("Removing new wireguard key since the key rotation was cancelled");
+                    self.revoke_key_in_background(account_token, new_data.private_key.public_key());
+                }
+                return;
+            }
         }
-        Self::oneshot_send(tx, Ok(()), "target state");
-    }
+        self.key_rotation = None;
 
-    fn on_reconnect(&mut self) {
-        if self.target_state == TargetState::Secured || self.tunnel_state.is_in_error_state() {
-            self.connect_tunnel();
-        } else {
-            debug!("Ignoring reconnect command. Currently not in secured state");
+        let new_data = match result {
+            Ok(new_data) => new_data,
+            Err(wireguard::Error::TooManyKeys) => {
+                log::error!("Account has too many keys, stopping automatic rotation");
+                self.metrics_counters.key_event("too_many_keys");
+                self.wireguard_key_manager.stop_automatic_rotation();
+                self.event_listener
+                    .notify_key_event(KeygenEvent::TooManyKeys);
+                self.event_listener
+                    .notify_key_rotation_event(KeyRotationEvent::Failed);
+                return;
+            }
+            Err(e) => {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Failed to push new wireguard key")
+                );
+                self.metrics_counters.key_event("failure");
+                self.event_listener
+                    .notify_key_rotation_event(KeyRotationEvent::Failed);
+                return;
+            }
+        };
+
+        let new_key = new_data.get_public_key();
+        let mut account_entry = match self.account_history.get(&account_token) {
+            Ok(Some(account_entry)) => account_entry,
+            Ok(None) => {
+                log::error!("Account token set in settings but not in account history");
+                return;
+            }
+            Err(e) => {
+                log::error!("Failed to read account entry from history: {}", e);
+                return;
+            }
+        };
+        let old_data = match account_entry.wireguard.replace(new_data) {
+            Some(old_data) => old_data,
+            None => {
+                log::error!("The key to rotate is missing from the account history");
+                self.revoke_key_in_background(account_token, new_key.key);
+                return;
+            }
+        };
+        let grace_period =
+            Duration::from_secs(60 * u64::from(self.settings.key_rotation_policy.grace_period));
+        // Saved with the new key, so that the rotation can be finished after a restart
+        account_entry.pending_rotation = Some(account_history::PendingKeyRotation {
+            old_data: old_data.clone(),
+            new_key: new_key.key.clone(),
+            grace_period_end: Utc::now()
+                + chrono::Duration::from_std(grace_period)
+                    .unwrap_or_else(|_| chrono::Duration::zero()),
+        });
+        if let Err(e) = self.account_history.insert(account_entry) {
+            log::error!(
+                "{}",
+                e.display_chain_with_msg("Failed to add new wireguard key to account data")
+            );
+            self.revoke_key_in_background(account_token, new_key.key);
+            self.event_listener
+                .notify_key_rotation_event(KeyRotationEvent::Failed);
+            return;
         }
-    }
 
-    fn on_get_state(&self, tx: oneshot::Sender<TunnelState>) {
-        Self::oneshot_send(tx, self.tunnel_state.clone(), "current state");
+        self.metrics_counters.key_event("new_key");
+        self.event_listener
+            .notify_key_event(KeygenEvent::NewKey(new_key.clone()));
+        self.event_listener
+            .notify_key_rotation_event(KeyRotationEvent::Started {
+                old_key: old_data.get_public_key(),
+                new_key: new_key.clone(),
+            });
+
+        self.start_key_rotation_grace_period(account_token, old_data, new_key, grace_period);
+        if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+            self.reconfigure_tunnel();
+        }
     }
 
-    fn on_get_current_location(&mut self, tx: oneshot::Sender<Option<GeoIpLocation>>) {
-        use self::TunnelState::*;
-        let get_location: Box<dyn Future<Item = Option<GeoIpLocation>, Error = ()> + Send> =
-            match &self.tunnel_state {
-                Disconnected => Box::new(self.get_geo_location().map(Some)),
-                Connecting { location, .. } => Box::new(future::result(Ok(location.clone()))),
-                Disconnecting(..) => Box::new(future::result(Ok(self.build_location_from_relay()))),
-                Connected { location, .. } => {
-                    let relay_location = location.clone();
-                    Box::new(
-                        self.get_geo_location()
-                            .map(|fetched_location| GeoIpLocation {
-                                ipv4: fetched_location.ipv4,
-                                ipv6: fetched_location.ipv6,
-                                ..relay_location.unwrap_or(fetched_location)
-                            })
-                            .map(Some),
-                    )
-                }
-                Error(..) => {
-                    // We are not online at all at this stage so no location data is available.
-                    Box::new(future::result(Ok(None)))
+    /// Resumes a key rotation that was in its grace period when the daemon stopped. The grace
+    /// period is restarted with the time that was left, but at least
+    /// `RESUMED_KEY_ROTATION_GRACE_PERIOD`, so that a restored tunnel can use the new key.
+    fn resume_key_rotation(&mut self) {
+        let account_token = match self.settings.get_account_token() {
+            Some(account_token) => account_token,
+            None => return,
+        };
+        let mut account_entry = match self.account_history.get(&account_token) {
+            Ok(Some(account_entry)) => account_entry,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to read account entry from history: {}", e);
+                return;
+            }
+        };
+        let pending_rotation = match account_entry.pending_rotation.clone() {
+            Some(pending_rotation) => pending_rotation,
+            None => return,
+        };
+
+        let new_key = account_entry
+            .wireguard
+            .as_ref()
+            .map(|data| data.get_public_key())
+            .filter(|new_key| new_key.key == pending_rotation.new_key);
+        let new_key = match new_key {
+            Some(new_key) => new_key,
+            None => {
+                log::info!("Removing the old wireguard key of a key rotation that was replaced");
+                account_entry.pending_rotation = None;
+                if let Err(e) = self.account_history.insert(account_entry) {
+                    log::error!(
+                        "{}",
+                        e.display_chain_with_msg("Failed to remove the key rotation")
+                    );
+                    return;
                 }
-            };
+                self.revoke_key_in_background(
+                    account_token,
+                    pending_rotation.old_data.private_key.public_key(),
+                );
+                return;
+            }
+        };
 
-        self.core_handle.remote.spawn(move |_| {
-            get_location.map(|location| Self::oneshot_send(tx, location, "current location"))
-        });
+        log::info!("Resuming interrupted key rotation");
+        let remaining = (pending_rotation.grace_period_end - Utc::now())
+            .to_std()
+            .unwrap_or_default();
+        self.start_key_rotation_grace_period(
+            account_token,
+            pending_rotation.old_data,
+            new_key,
+            std::cmp::max(remaining, RESUMED_KEY_ROTATION_GRACE_PERIOD),
+        );
     }
 
-    fn get_geo_location(&mut self) -> impl Future<Item = GeoIpLocation, Error = ()> {
-        let https_handle = self.rpc_runtime.rest_handle();
+    /// Verifies the new key of a key rotation and starts the grace period timer.
+    fn start_key_rotation_grace_period(
+        &mut self,
+        account_token: AccountToken,
+        old_data: mullvad_types::wireguard::WireguardData,
+        new_key: mullvad_types::wireguard::PublicKey,
+        grace_period: Duration,
+    ) {
+        let daemon_tx = self.tx.clone();
+        let verified_key = new_key.key.clone();
+        let verify_future = self
+            .wireguard_key_manager
+            .verify_wireguard_key(account_token.clone(), new_key.key.clone())
+            .then(move |result| -> Result<(), ()> {
+                match result {
+                    Ok(is_valid) => {
+                        let _ = daemon_tx.send(InternalDaemonEvent::KeyRotationVerified(
+                            verified_key,
+                            is_valid,
+                        ));
+                    }
+                    Err(e) => log::error!(
+                        "{}",
+                        e.display_chain_with_msg("Failed to verify new wireguard key")
+                    ),
+                }
+                Ok(())
+            });
+        if let Err(e) = self.core_handle.remote.execute(verify_future) {
+            log::error!("Failed to spawn a future to verify wireguard key: {:?}", e);
+        }
 
-        geoip::send_location_request(https_handle).map_err(|e| {
-            warn!("Unable to fetch GeoIP location: {}", e.display_chain());
-        })
+        self.spawn_key_rotation_timer(new_key.key.clone(), grace_period);
+        self.key_rotation = Some(wireguard::KeyRotation::grace_period(
+            account_token,
+            old_data,
+            new_key,
+        ));
     }
 
-    fn build_location_from_relay(&self) -> Option<GeoIpLocation> {
-        let relay = self.last_generated_relay.as_ref()?;
+    /// Sends `KeyRotationGracePeriodOver` for `new_key` once the grace period is over.
+    fn spawn_key_rotation_timer(
+        &mut self,
+        new_key: talpid_types::net::wireguard::PublicKey,
+        grace_period: Duration,
+    ) {
+        let daemon_tx = self.tx.clone();
+        let grace_period_future = self
+            .wireguard_key_manager
+            .grace_period_timer(grace_period)
+            .then(move |_| -> Result<(), ()> {
+                let _ = daemon_tx.send(InternalDaemonEvent::KeyRotationGracePeriodOver(new_key));
+                Ok(())
+            });
+        if let Err(e) = self.core_handle.remote.execute(grace_period_future) {
+            log::error!("Failed to spawn key rotation grace period timer: {:?}", e);
+        }
+    }
+
+    fn handle_key_rotation_verified(
+        &mut self,
+        public_key: talpid_types::net::wireguard::PublicKey,
+        is_valid: bool,
+    ) {
+        let action = match &self.key_rotation {
+            Some(key_rotation) => key_rotation.verified(&public_key, is_valid),
+            None => return,
+        };
+        if action == wireguard::RotationAction::RollBack {
+            log::error!("The new wireguard key is not registered on the account");
+            self.roll_back_key_rotation();
+        }
+    }
+
+    /// Ends the grace period of a key rotation. The rotation is rolled back if a tunnel tried to
+    /// use the new key without completing a handshake. If no tunnel has tried to use it yet, for
+    /// example because the tunnel is disconnected, a new grace period is started.
+    fn handle_key_rotation_grace_period_over(
+        &mut self,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let action = match &self.key_rotation {
+            Some(key_rotation) => key_rotation.grace_period_over(&public_key),
+            None => return,
+        };
+        match action {
+            wireguard::RotationAction::RollBack => {
+                log::error!(
+                    "The new wireguard key was not used for a handshake within the grace period"
+                );
+                self.roll_back_key_rotation();
+            }
+            wireguard::RotationAction::ExtendGracePeriod => {
+                log::debug!("Extending the key rotation grace period since the new key is unused");
+                let grace_period = Duration::from_secs(
+                    60 * u64::from(self.settings.key_rotation_policy.grace_period),
+                );
+                self.spawn_key_rotation_timer(public_key, grace_period);
+            }
+            wireguard::RotationAction::Complete | wireguard::RotationAction::Wait => (),
+        }
+    }
+
+    fn complete_key_rotation_after_handshake(&mut self) {
+        let action = match (&self.key_rotation, &self.last_wireguard_key) {
+            (Some(key_rotation), Some(last_key)) => key_rotation.handshake_completed(last_key),
+            _ => return,
+        };
+        if action == wireguard::RotationAction::Complete {
+            self.complete_key_rotation();
+        }
+    }
+
+    fn complete_key_rotation(&mut self) {
+        if let Some(wireguard::KeyRotation::GracePeriod {
+            account,
+            old_data,
+            new_key,
+            ..
+        }) = self.key_rotation.take()
+        {
+            log::info!("Key rotation completed, removing the old wireguard key");
+            self.clear_pending_key_rotation(&account);
+            self.revoke_key_in_background(account.clone(), old_data.private_key.public_key());
+            self.metrics_counters.key_event("rotation_completed");
+            self.event_listener
+                .notify_key_rotation_event(KeyRotationEvent::Completed(new_key));
+            if self.settings.get_account_token() == Some(account.clone()) {
+                self.wireguard_key_manager
+                    .reset_rotation(&mut self.account_history, account);
+            }
+        }
+    }
+
+    /// Uses the old key of a key rotation again and removes the new key from the account. The
+    /// rotation job keeps running for the old key, so rotation is attempted again later.
+    fn roll_back_key_rotation(&mut self) {
+        let (account, old_data, new_key) = match self.key_rotation.take() {
+            Some(wireguard::KeyRotation::GracePeriod {
+                account,
+                old_data,
+                new_key,
+                ..
+            }) => (account, old_data, new_key),
+            _ => return,
+        };
+
+        let mut account_entry = match self.account_history.get(&account) {
+            Ok(Some(account_entry)) => account_entry,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to read account entry from history: {}", e);
+                return;
+            }
+        };
+        let uses_new_key = account_entry
+            .wireguard
+            .as_ref()
+            .map(|data| data.private_key.public_key() == new_key.key)
+            .unwrap_or(false);
+        if !uses_new_key {
+            log::info!("Not rolling back key rotation since the key has been replaced since");
+            self.clear_pending_key_rotation(&account);
+            return;
+        }
+
+        log::info!("Rolling back key rotation to the old wireguard key");
+        let old_key = old_data.get_public_key();
+        account_entry.wireguard = Some(old_data);
+        account_entry.pending_rotation = None;
+        if let Err(e) = self.account_history.insert(account_entry) {
+            log::error!(
+                "{}",
+                e.display_chain_with_msg("Failed to restore the old wireguard key")
+            );
+            return;
+        }
+        self.revoke_key_in_background(account, new_key.key);
+        self.metrics_counters.key_event("rotation_rolled_back");
+        self.event_listener
+            .notify_key_event(KeygenEvent::NewKey(old_key.clone()));
+        self.event_listener
+            .notify_key_rotation_event(KeyRotationEvent::RolledBack(old_key));
+        match self.tunnel_state {
+            TunnelState::Connecting { .. }
+            | TunnelState::Connected { .. }
+            | TunnelState::Error(_) => self.reconfigure_tunnel(),
+            _ => (),
+        }
+    }
+
+    /// Removes the saved state of a key rotation that has been completed or rolled back.
+    fn clear_pending_key_rotation(&mut self, account_token: &AccountToken) {
+        let mut account_entry = match self.account_history.get(account_token) {
+            Ok(Some(account_entry)) => account_entry,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to read account entry from history: {}", e);
+                return;
+            }
+        };
+        if account_entry.pending_rotation.take().is_some() {
+            if let Err(e) = self.account_history.insert(account_entry) {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Failed to remove the key rotation")
+                );
+            }
+        }
+    }
+
+    fn revoke_key_in_background(
+        &mut self,
+        account_token: AccountToken,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let fut = self
+            .wireguard_key_manager
+            .revoke_key(account_token, public_key)
+            .map_err(|e| {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Failed to remove wireguard key from the account")
+                )
+            });
+        if let Err(e) = self.core_handle.remote.execute(fut) {
+            log::error!("Failed to spawn a future to remove wireguard key: {:?}", e);
+        }
+    }
+
+    fn handle_wireguard_key_revoked(
+        &mut self,
+        account_token: AccountToken,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let mut account_entry = match self.account_history.get(&account_token) {
+            Ok(Some(account_entry)) => account_entry,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!("Failed to read account entry from history: {}", e);
+                return;
+            }
+        };
+        if !account_entry.forget_revoked_key(&public_key) {
+            return;
+        }
+
+        log::info!("The wireguard key of the account was revoked");
+        if let Err(e) = self.account_history.insert(account_entry) {
+            log::error!(
+                "{}",
+                e.display_chain_with_msg("Failed to remove revoked wireguard key")
+            );
+            return;
+        }
+        if self.settings.get_account_token() == Some(account_token) {
+            self.ensure_wireguard_keys_for_current_account();
+        }
+    }
+
+    fn handle_wireguard_key_imported(
+        &mut self,
+        account_token: AccountToken,
+        data: mullvad_types::wireguard::WireguardData,
+        tx: oneshot::Sender<Result<wireguard::PublicKey, wireguard::Error>>,
+    ) {
+        if self.settings.get_account_token() != Some(account_token.clone()) {
+            log::info!("Dropping imported wireguard key since account has been changed");
+            Self::oneshot_send(
+                tx,
+                Err(wireguard::Error::NoAccountToken),
+                "import_wireguard_key response",
+            );
+            return;
+        }
+
+        let public_key = data.get_public_key();
+        self.wireguard_key_manager.reset();
+        self.handle_wireguard_key_event((account_token.clone(), Ok(data)));
+        if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+            self.reconfigure_tunnel();
+        }
+        self.wireguard_key_manager.set_rotation_interval(
+            &mut self.account_history,
+            account_token,
+            self.settings
+                .tunnel_options
+                .wireguard
+                .automatic_rotation
+                .map(|hours| Duration::from_secs(60u64 * 60u64 * hours as u64)),
+        );
+        Self::oneshot_send(tx, Ok(public_key), "import_wireguard_key response");
+    }
+
+    fn handle_new_account_event(
+        &mut self,
+        new_token: AccountToken,
+        tx: oneshot::Sender<Result<String, mullvad_rpc::rest::Error>>,
+    ) {
+        match self.set_account(Some(new_token.clone())) {
+            Ok(_) => {
+                self.set_target_state(TargetState::Unsecured);
+                let _ = tx.send(Ok(new_token));
+            }
+            Err(err) => {
+                log::error!("Failed to save new account - {}", err);
+            }
+        };
+    }
+
+    fn handle_new_account_expiry(&mut self, account_token: AccountToken, expiry: DateTime<Utc>) {
+        if let Err(e) = self.account_history.set_expiry(&account_token, expiry) {
+            log::error!(
+                "{}",
+                e.display_chain_with_msg("Failed to cache account expiry")
+            );
+        }
+    }
+
+    fn handle_new_app_version_info(&mut self, app_version_info: AppVersionInfo) {
+        self.app_version_info = app_version_info.clone();
+        self.event_listener.notify_app_version(app_version_info);
+        self.download_update_if_available();
+    }
+
+    fn handle_update_downloaded(
+        &mut self,
+        version: AppVersion,
+        result: Result<PendingUpdate, update::Error>,
+    ) {
+        self.downloading_update = None;
+        match result {
+            Ok(pending_update) => {
+                info!(
+                    "Downloaded version {} to {}",
+                    version,
+                    pending_update.path.display()
+                );
+                self.pending_update = Some(pending_update);
+                // A newer version may have been announced during the download
+                self.download_update_if_available();
+            }
+            // Retried when a new version is announced or the daemon restarts
+            Err(e) => error!(
+                "{}",
+                e.display_chain_with_msg(&format!("Failed to download version {}", version))
+            ),
+        }
+    }
+
+    /// Starts downloading the latest release in the background, if downloads are enabled and it
+    /// is newer than the running version and has not already been downloaded.
+    fn download_update_if_available(&mut self) {
+        if !self.settings.download_updates
+            || !self.update_downloader.is_available()
+            || self.downloading_update.is_some()
+        {
+            return;
+        }
+        let latest_version = if self.settings.show_beta_releases {
+            &self.app_version_info.latest
+        } else {
+            &self.app_version_info.latest_stable
+        };
+        let is_pending = self
+            .pending_update
+            .as_ref()
+            .map(|pending_update| &pending_update.version == latest_version)
+            .unwrap_or(false);
+        if !update::is_newer_version(latest_version) || is_pending {
+            return;
+        }
+
+        let version = latest_version.clone();
+        info!("Downloading version {}", version);
+        self.downloading_update = Some(version.clone());
+
+        let daemon_tx = self.tx.clone();
+        let future = self.update_downloader.download(version.clone()).then(
+            move |result| -> Result<(), ()> {
+                let _ = daemon_tx.send(InternalDaemonEvent::UpdateDownloaded(version, result));
+                Ok(())
+            },
+        );
+        if self.core_handle.remote.execute(future).is_err() {
+            log::error!("Failed to spawn future for downloading an update");
+            self.downloading_update = None;
+        }
+    }
+
+    fn handle_api_connectivity_event(&mut self, event: ApiConnectivityEvent) {
+        let route_changed = self.api_access.on_connectivity_event(
+            event,
+            &self.settings,
+            &mut self.relay_selector,
+            &mut self.rpc_runtime,
+        );
+        if route_changed {
+            self.send_tunnel_command(TunnelCommand::AllowEndpoint(Some(
+                self.api_access.allowed_endpoint(),
+            )));
+        }
+    }
+
+    /// Selects a new route to the API after the API access settings changed.
+    fn reset_api_access(&mut self) {
+        self.api_access.reset(
+            &self.settings,
+            &mut self.relay_selector,
+            &mut self.rpc_runtime,
+        );
+        self.send_tunnel_command(TunnelCommand::AllowEndpoint(Some(
+            self.api_access.allowed_endpoint(),
+        )));
+    }
+
+    fn on_set_target_state(
+        &mut self,
+        tx: oneshot::Sender<Result<(), ()>>,
+        new_target_state: TargetState,
+    ) {
+        if self.state.is_running() {
+            self.set_target_state(new_target_state);
+        } else {
+            warn!("Ignoring target state change request due to shutdown");
+        }
+        Self::oneshot_send(tx, Ok(()), "target state");
+    }
+
+    fn on_reconnect(&mut self) {
+        if self.target_state == TargetState::Secured || self.tunnel_state.is_in_error_state() {
+            self.connect_tunnel();
+        } else {
+            debug!("Ignoring reconnect command. Currently not in secured state");
+        }
+    }
+
+    fn on_get_state(&self, tx: oneshot::Sender<TunnelState>) {
+        Self::oneshot_send(tx, self.tunnel_state.clone(), "current state");
+    }
+
+    fn on_get_current_location(&mut self, tx: oneshot::Sender<Option<GeoIpLocation>>) {
+        use self::TunnelState::*;
+        let get_location: Box<dyn Future<Item = Option<GeoIpLocation>, Error = ()> + Send> =
+            match &self.tunnel_state {
+                Disconnected => Box::new(self.get_geo_location().map(Some)),
+                Connecting { location, .. } => Box::new(future::result(Ok(location.clone()))),
+                Disconnecting(..) => Box::new(future::result(Ok(self.build_location_from_relay()))),
+                Connected { location, .. } => {
+                    let relay_location = location.clone();
+                    Box::new(
+                        self.get_geo_location()
+                            .map(|fetched_location| GeoIpLocation {
+                                ipv4: fetched_location.ipv4,
+                                ipv6: fetched_location.ipv6,
+                                ..relay_location.unwrap_or(fetched_location)
+                            })
+                            .map(Some),
+                    )
+                }
+                Error(..) => {
+                    // We are not online at all at this stage so no location data is available.
+                    Box::new(future::result(Ok(None)))
+                }
+            };
+
+        self.core_handle.remote.spawn(move |_| {
+            get_location.map(|location| Self::oneshot_send(tx, location, "current location"))
+        });
+    }
+
+    fn get_geo_location(&mut self) -> impl Future<Item = GeoIpLocation, Error = ()> {
+        let https_handle = self.rpc_runtime.rest_handle();
+
+        geoip::send_location_request(https_handle).map_err(|e| {
+            warn!("Unable to fetch GeoIP location: {}", e.display_chain());
+        })
+    }
+
+    fn build_location_from_relay(&self) -> Option<GeoIpLocation> {
+        let relay = self.last_generated_relay.as_ref()?;
         let bridge_hostname = self
             .last_generated_bridge_relay
             .as_ref()
@@ -1198,10 +2156,15 @@ where
         tx: oneshot::Sender<BoxFuture<AccountData, mullvad_rpc::rest::Error>>,
         account_token: AccountToken,
     ) {
+        let daemon_tx = self.tx.clone();
         let rpc_call = self
             .accounts_proxy
-            .get_expiry(account_token)
-            .map(|expiry| AccountData { expiry });
+            .get_expiry(account_token.clone())
+            .map(move |expiry| {
+                let _ =
+                    daemon_tx.send(InternalDaemonEvent::NewAccountExpiry(account_token, expiry));
+                AccountData { expiry }
+            });
         Self::oneshot_send(tx, Box::new(rpc_call), "account data")
     }
 
@@ -1238,16 +2201,7 @@ where
         match self.set_account(account_token.clone()) {
             Ok(account_changed) => {
                 if account_changed {
-                    match account_token {
-                        Some(_) => {
-                            info!("Initiating tunnel restart because the account token changed");
-                            self.reconnect_tunnel();
-                        }
-                        None => {
-                            info!("Disconnecting because account token was cleared");
-                            self.set_target_state(TargetState::Unsecured);
-                        }
-                    };
+                    self.apply_account_change(account_token.as_ref());
                 }
                 Self::oneshot_send(tx, (), "set_account response");
             }
@@ -1271,6 +2225,7 @@ where
             }
 
             self.ensure_wireguard_keys_for_current_account();
+            self.key_rotation_postponed = false;
 
             if let Some(token) = account_token {
                 // update automatic rotation
@@ -1281,6 +2236,21 @@ where
         Ok(account_changed)
     }
 
+    /// Reconnects or disconnects the tunnel after the current account changed.
+    fn apply_account_change(&mut self, account_token: Option<&AccountToken>) {
+        match AccountChangeAction::new(self.target_state, account_token) {
+            AccountChangeAction::Reconnect => {
+                info!("Initiating tunnel restart because the account token changed");
+                self.connect_tunnel();
+            }
+            AccountChangeAction::Disconnect => {
+                info!("Disconnecting because account token was cleared");
+                self.set_target_state(TargetState::Unsecured);
+            }
+            AccountChangeAction::None => (),
+        }
+    }
+
     fn on_get_account_history(&mut self, tx: oneshot::Sender<Vec<AccountToken>>) {
         Self::oneshot_send(
             tx,
@@ -1299,6 +2269,61 @@ where
         }
     }
 
+    fn on_list_accounts(&mut self, tx: oneshot::Sender<Vec<AccountHistoryEntry>>) {
+        Self::oneshot_send(
+            tx,
+            self.account_history.get_entries(),
+            "list_accounts response",
+        );
+    }
+
+    fn on_set_account_label(
+        &mut self,
+        tx: oneshot::Sender<Result<(), account_history::Error>>,
+        account_token: AccountToken,
+        label: Option<String>,
+    ) {
+        let result = self.account_history.set_label(&account_token, label);
+        if let Err(e) = &result {
+            log::error!(
+                "{}",
+                e.display_chain_with_msg("Failed to set account label")
+            );
+        }
+        Self::oneshot_send(tx, result, "set_account_label response");
+    }
+
+    /// Switches to an account in the history. Its stored WireGuard key is used, so no new key has
+    /// to be generated before connecting.
+    fn on_switch_account(
+        &mut self,
+        tx: oneshot::Sender<Result<(), account_history::Error>>,
+        label: String,
+    ) {
+        let account_token = match self.account_history.find(&label) {
+            Some(account_token) => account_token,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(account_history::Error::AccountNotFound),
+                    "switch_account response",
+                );
+                return;
+            }
+        };
+        match self.set_account(Some(account_token.clone())) {
+            Ok(account_changed) => {
+                if account_changed {
+                    self.apply_account_change(Some(&account_token));
+                }
+                Self::oneshot_send(tx, Ok(()), "switch_account response");
+            }
+            Err(e) => {
+                log::error!("Failed to switch account - {}", e);
+            }
+        }
+    }
+
     fn on_get_version_info(&mut self, tx: oneshot::Sender<AppVersionInfo>) {
         Self::oneshot_send(
             tx,
@@ -1307,6 +2332,83 @@ where
         );
     }
 
+    fn on_get_diagnostics(&mut self, tx: oneshot::Sender<BoxFuture<Diagnostics, ()>>) {
+        let (address, address_source, address_updated) = self.rpc_runtime.api_address_info();
+        let api = ApiDiagnostics {
+            address,
+            address_source,
+            address_updated: address_updated.map(DateTime::<Utc>::from),
+            access_method: self.api_access.current_method(),
+        };
+
+        let relay_list_updated = self.relay_selector.last_updated();
+        let relay_list = RelayListDiagnostics {
+            last_updated: DateTime::<Utc>::from(relay_list_updated),
+            age_secs: relay_list_updated
+                .elapsed()
+                .map(|age| age.as_secs())
+                .unwrap_or(0),
+        };
+
+        let (tunnel_tx, tunnel_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::GetDiagnostics(tunnel_tx));
+        let diagnostics = tunnel_rx.then(move |tunnel| -> Result<Diagnostics, ()> {
+            Ok(Diagnostics {
+                tunnel: tunnel.ok(),
+                api,
+                relay_list,
+            })
+        });
+        Self::oneshot_send(tx, Box::new(diagnostics), "get_diagnostics response");
+    }
+
+    fn on_get_metrics(&mut self, tx: oneshot::Sender<BoxFuture<metrics::MetricsSnapshot, ()>>) {
+        let key_age = self
+            .settings
+            .get_account_token()
+            .and_then(|account| self.account_history.get(&account).ok()?)
+            .and_then(|account_entry| account_entry.wireguard)
+            .and_then(|wg| {
+                Utc::now()
+                    .signed_duration_since(wg.get_public_key().created)
+                    .to_std()
+                    .ok()
+            });
+        let key_rotation = metrics::KeyRotationMetrics {
+            enabled: self.wireguard_key_manager.is_rotation_running(),
+            interval: self.wireguard_key_manager.rotation_interval(),
+            key_age,
+            events: self.metrics_counters.key_events(),
+        };
+
+        let tunnel_state = tunnel_state_name(&self.tunnel_state);
+        let time_in_state = self.metrics_counters.time_in_state();
+        let reconnects = self.metrics_counters.reconnects();
+        let relay_list_age = self
+            .relay_selector
+            .last_updated()
+            .elapsed()
+            .unwrap_or(Duration::from_secs(0));
+        let api = self.rpc_runtime.api_metrics();
+
+        let (tunnel_tx, tunnel_rx) = oneshot::channel();
+        self.send_tunnel_command(TunnelCommand::GetTunnelStats(tunnel_tx));
+        let snapshot = tunnel_rx.then(
+            move |tunnel_stats| -> Result<metrics::MetricsSnapshot, ()> {
+                Ok(metrics::MetricsSnapshot {
+                    tunnel_state,
+                    time_in_state,
+                    reconnects,
+                    tunnel_stats: tunnel_stats.ok().and_then(|stats| stats),
+                    relay_list_age,
+                    api,
+                    key_rotation,
+                })
+            },
+        );
+        Self::oneshot_send(tx, Box::new(snapshot), "get_metrics response");
+    }
+
     fn on_get_current_version(&mut self, tx: oneshot::Sender<AppVersion>) {
         Self::oneshot_send(
             tx,
@@ -1315,6 +2417,43 @@ where
         );
     }
 
+    fn on_get_pending_update(&mut self, tx: oneshot::Sender<Option<PendingUpdate>>) {
+        Self::oneshot_send(
+            tx,
+            self.pending_update.clone(),
+            "get_pending_update response",
+        );
+    }
+
+    fn on_apply_update(&mut self, tx: oneshot::Sender<Result<(), update::Error>>) {
+        let result = match &self.pending_update {
+            Some(pending_update) => self.update_downloader.apply(pending_update),
+            None => Err(update::Error::NoPendingUpdate),
+        };
+        if let Err(e) = &result {
+            error!("{}", e.display_chain_with_msg("Unable to apply update"));
+        }
+        Self::oneshot_send(tx, result, "apply_update response");
+    }
+
+    fn on_set_log_filter(&mut self, tx: oneshot::Sender<()>, log_filter: LogFilter) {
+        info!(
+            "Changing log level to {}{}",
+            log_filter.level,
+            log_filter
+                .modules
+                .iter()
+                .map(|(module, level)| format!(", {}={}", module, level))
+                .collect::<String>()
+        );
+        logging::set_log_filter(log_filter);
+        Self::oneshot_send(tx, (), "set_log_filter response");
+    }
+
+    fn on_get_log_filter(&mut self, tx: oneshot::Sender<LogFilter>) {
+        Self::oneshot_send(tx, logging::get_log_filter(), "get_log_filter response");
+    }
+
     #[cfg(not(target_os = "android"))]
     fn on_factory_reset(&mut self, tx: oneshot::Sender<()>) {
         let mut failed = false;
@@ -1349,37 +2488,127 @@ where
                 );
                 failed = true;
             }
-            if !failed {
-                Self::oneshot_send(tx, (), "factory_reset response");
+            if !failed {
+                Self::oneshot_send(tx, (), "factory_reset response");
+            }
+        }));
+    }
+
+    fn on_update_relay_settings(&mut self, tx: oneshot::Sender<()>, update: RelaySettingsUpdate) {
+        let save_result = self.settings.update_relay_settings(update);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "update_relay_settings response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    info!("Initiating tunnel restart because the relay settings changed");
+                    self.reconfigure_tunnel();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_import_custom_tunnel(
+        &mut self,
+        tx: oneshot::Sender<std::result::Result<Vec<ConfigDirective>, ImportError>>,
+        config_file: CustomTunnelConfigFile,
+    ) {
+        let imported = match ImportedTunnel::from_config_file(&config_file) {
+            Ok(imported) => imported,
+            Err(error) => {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to import custom tunnel")
+                );
+                Self::oneshot_send(tx, Err(error), "import_custom_tunnel response");
+                return;
+            }
+        };
+
+        let update = RelaySettingsUpdate::CustomTunnelEndpoint(imported.endpoint);
+        match self.settings.update_relay_settings(update) {
+            Ok(settings_changed) => {
+                Self::oneshot_send(
+                    tx,
+                    Ok(imported.ignored_directives),
+                    "import_custom_tunnel response",
+                );
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    info!("Initiating tunnel restart because a custom tunnel was imported");
+                    self.reconfigure_tunnel();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_export_wireguard_config(
+        &self,
+        tx: oneshot::Sender<std::result::Result<String, ExportWireguardConfigError>>,
+    ) {
+        let result = match (
+            self.get_connected_tunnel_type(),
+            &self.last_wireguard_connection,
+        ) {
+            (Some(TunnelType::Wireguard), Some(connection)) => {
+                if connection.obfuscation.is_some() {
+                    Err(ExportWireguardConfigError::Obfuscated)
+                } else {
+                    Ok(mullvad_types::wireguard_config_file(connection))
+                }
+            }
+            _ => Err(ExportWireguardConfigError::NotConnected),
+        };
+        Self::oneshot_send(tx, result, "export_wireguard_config response");
+    }
+
+    fn on_set_allow_lan(&mut self, tx: oneshot::Sender<()>, allow_lan: bool) {
+        let save_result = self.settings.set_allow_lan(allow_lan);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_allow_lan response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
+                }
             }
-        }));
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
     }
 
-    fn on_update_relay_settings(&mut self, tx: oneshot::Sender<()>, update: RelaySettingsUpdate) {
-        let save_result = self.settings.update_relay_settings(update);
+    fn on_set_gateway(&mut self, tx: oneshot::Sender<()>, gateway: Option<GatewayOptions>) {
+        let save_result = self.settings.set_gateway(gateway.clone());
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "update_relay_settings response");
+                Self::oneshot_send(tx, (), "set_gateway response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    info!("Initiating tunnel restart because the relay settings changed");
-                    self.reconnect_tunnel();
+                    self.send_tunnel_command(TunnelCommand::Gateway(gateway));
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
         }
     }
 
-    fn on_set_allow_lan(&mut self, tx: oneshot::Sender<()>, allow_lan: bool) {
-        let save_result = self.settings.set_allow_lan(allow_lan);
+    fn on_set_tunnel_socks_proxy(
+        &mut self,
+        tx: oneshot::Sender<()>,
+        proxy: Option<TunnelSocksProxy>,
+    ) {
+        let save_result = self.settings.set_tunnel_socks_proxy(proxy.clone());
         match save_result {
             Ok(settings_changed) => {
-                Self::oneshot_send(tx, (), "set_allow_lan response");
+                Self::oneshot_send(tx, (), "set_tunnel_socks_proxy response");
                 if settings_changed {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    self.send_tunnel_command(TunnelCommand::AllowLan(allow_lan));
+                    self.send_tunnel_command(TunnelCommand::TunnelSocksProxy(proxy));
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
@@ -1400,6 +2629,21 @@ where
         }
     }
 
+    fn on_set_download_updates(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
+        let save_result = self.settings.set_download_updates(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_download_updates response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.download_update_if_available();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
     fn on_set_block_when_disconnected(
         &mut self,
         tx: oneshot::Sender<()>,
@@ -1449,7 +2693,47 @@ where
                         info!(
                             "Initiating tunnel restart because the OpenVPN mssfix setting changed"
                         );
-                        self.reconnect_tunnel();
+                        self.reconfigure_tunnel();
+                    }
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_openvpn_extra_option(
+        &mut self,
+        tx: oneshot::Sender<std::result::Result<(), openvpn::ExtraOptionError>>,
+        option: openvpn::ExtraOption,
+        value: Option<u32>,
+    ) {
+        let mut tunnel_options = self.settings.tunnel_options.openvpn.clone();
+        match value {
+            Some(value) => tunnel_options.extra_options.insert(option, value),
+            None => tunnel_options.extra_options.remove(&option),
+        };
+        if let Err(error) = tunnel_options.validate_extra_options() {
+            error!(
+                "{}",
+                error.display_chain_with_msg("Rejected OpenVPN option")
+            );
+            Self::oneshot_send(tx, Err(error), "set_openvpn_extra_option response");
+            return;
+        }
+
+        let save_result = self.settings.set_openvpn_extra_option(option, value);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_openvpn_extra_option response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::OpenVpn) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the OpenVPN option {} changed",
+                            option
+                        );
+                        self.reconfigure_tunnel();
                     }
                 }
             }
@@ -1467,7 +2751,7 @@ where
                 if settings_changes {
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
-                    self.reconnect_tunnel();
+                    self.reconfigure_tunnel();
                 };
                 Self::oneshot_send(tx, Ok(()), "set_bridge_settings");
             }
@@ -1493,7 +2777,7 @@ where
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
                     log::info!("Initiating tunnel restart because bridge state changed");
-                    self.reconnect_tunnel();
+                    self.reconfigure_tunnel();
                 }
                 Ok(())
             }
@@ -1518,7 +2802,123 @@ where
                     self.event_listener
                         .notify_settings(self.settings.to_settings());
                     info!("Initiating tunnel restart because the enable IPv6 setting changed");
-                    self.reconnect_tunnel();
+                    self.reconfigure_tunnel();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_wireguard_policy_routing(&mut self, tx: oneshot::Sender<()>, enabled: bool) {
+        let save_result = self.settings.set_wireguard_policy_routing(enabled);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_wireguard_policy_routing response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the WireGuard policy routing \
+                             setting changed"
+                        );
+                        self.reconfigure_tunnel();
+                    }
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_excluded_destinations(
+        &mut self,
+        tx: oneshot::Sender<std::result::Result<(), ExcludedDestinationsError>>,
+        destinations: Vec<IpNetwork>,
+    ) {
+        let save_result = self
+            .check_excluded_destinations(&destinations)
+            .and_then(|()| {
+                self.settings
+                    .set_excluded_destinations(destinations)
+                    .map_err(ExcludedDestinationsError::SettingsError)
+            });
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, Ok(()), "set_excluded_destinations response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    info!("Initiating tunnel restart because the excluded destinations changed");
+                    self.reconfigure_tunnel();
+                }
+            }
+            Err(error) => {
+                error!(
+                    "{}",
+                    error.display_chain_with_msg("Rejected excluded destinations")
+                );
+                Self::oneshot_send(tx, Err(error), "set_excluded_destinations response");
+            }
+        }
+    }
+
+    /// Rejects excluded destinations that cover a known relay or tunnel gateway, to give early
+    /// feedback. The addresses actually used are checked whenever tunnel parameters are
+    /// generated.
+    fn check_excluded_destinations(
+        &self,
+        destinations: &[IpNetwork],
+    ) -> std::result::Result<(), ExcludedDestinationsError> {
+        let (mut relay_addresses, gateway_addresses) =
+            self.relay_selector.get_relay_and_gateway_addresses();
+        if let TunnelState::Connected { endpoint, .. } = &self.tunnel_state {
+            relay_addresses.push(endpoint.endpoint.address.ip());
+        }
+
+        for destination in destinations {
+            if let Some(address) = relay_addresses
+                .iter()
+                .find(|address| destination.contains(**address))
+            {
+                return Err(ExcludedDestinationsError::OverlapsRelay(
+                    *destination,
+                    *address,
+                ));
+            }
+            if let Some(address) = gateway_addresses
+                .iter()
+                .find(|address| destination.contains(**address))
+            {
+                return Err(ExcludedDestinationsError::OverlapsGateway(
+                    *destination,
+                    *address,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn on_set_wireguard_network_namespace(
+        &mut self,
+        tx: oneshot::Sender<()>,
+        network_namespace: Option<String>,
+    ) {
+        let save_result = self
+            .settings
+            .set_wireguard_network_namespace(network_namespace);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_wireguard_network_namespace response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
+                        info!(
+                            "Initiating tunnel restart because the WireGuard network namespace \
+                             setting changed"
+                        );
+                        self.reconfigure_tunnel();
+                    }
                 }
             }
             Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
@@ -1537,7 +2937,7 @@ where
                         info!(
                             "Initiating tunnel restart because the WireGuard MTU setting changed"
                         );
-                        self.reconnect_tunnel();
+                        self.reconfigure_tunnel();
                     }
                 }
             }
@@ -1573,6 +2973,68 @@ where
         }
     }
 
+    fn on_set_key_rotation_policy(&mut self, tx: oneshot::Sender<()>, policy: RotationPolicy) {
+        let save_result = self.settings.set_key_rotation_policy(policy);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_key_rotation_policy response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_retry_strategy(&mut self, tx: oneshot::Sender<()>, retry_strategy: RetryStrategy) {
+        let save_result = self.settings.set_retry_strategy(retry_strategy);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_retry_strategy response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_api_proxy(&mut self, tx: oneshot::Sender<()>, api_proxy: Option<ApiProxy>) {
+        let save_result = self.settings.set_api_proxy(api_proxy);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_api_proxy response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.reset_api_access();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
+    fn on_set_api_access_method(
+        &mut self,
+        tx: oneshot::Sender<()>,
+        api_access_method: Constraint<ApiAccessMethod>,
+    ) {
+        let save_result = self.settings.set_api_access_method(api_access_method);
+        match save_result {
+            Ok(settings_changed) => {
+                Self::oneshot_send(tx, (), "set_api_access_method response");
+                if settings_changed {
+                    self.event_listener
+                        .notify_settings(self.settings.to_settings());
+                    self.reset_api_access();
+                }
+            }
+            Err(e) => error!("{}", e.display_chain_with_msg("Unable to save settings")),
+        }
+    }
+
     fn ensure_wireguard_keys_for_current_account(&mut self) {
         if let Some(account) = self.settings.get_account_token() {
             if self
@@ -1608,10 +3070,7 @@ where
                 .map(|data| {
                     data.unwrap_or_else(|| {
                         log::error!("Account token set in settings but not in account history");
-                        account_history::AccountEntry {
-                            account: account_token.clone(),
-                            wireguard: None,
-                        }
+                        account_history::AccountEntry::new(account_token.clone())
                     })
                 })?;
 
@@ -1632,7 +3091,7 @@ where
                         format!("Failed to add new wireguard key to account data: {}", e)
                     })?;
                     if let Some(TunnelType::Wireguard) = self.get_connected_tunnel_type() {
-                        self.reconnect_tunnel();
+                        self.reconfigure_tunnel();
                     }
                     let keygen_event = KeygenEvent::NewKey(public_key);
                     self.event_listener.notify_key_event(keygen_event.clone());
@@ -1717,6 +3176,107 @@ where
         }
     }
 
+    fn on_list_wireguard_keys(
+        &mut self,
+        tx: oneshot::Sender<Result<Vec<wireguard::RegisteredKey>, wireguard::Error>>,
+    ) {
+        let account = match self.settings.get_account_token() {
+            Some(account) => account,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(wireguard::Error::NoAccountToken),
+                    "list_wireguard_keys response",
+                );
+                return;
+            }
+        };
+
+        let fut = self
+            .wireguard_key_manager
+            .list_keys(account)
+            .then(|result| -> Result<(), ()> {
+                Self::oneshot_send(tx, result, "list_wireguard_keys response");
+                Ok(())
+            });
+        if let Err(e) = self.core_handle.remote.execute(fut) {
+            log::error!("Failed to spawn a future to list wireguard keys: {:?}", e);
+        }
+    }
+
+    /// Removes a key from the current account. If it is the key in use, a new key is generated
+    /// once the old one is gone.
+    fn on_revoke_wireguard_key(
+        &mut self,
+        tx: oneshot::Sender<Result<(), wireguard::Error>>,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) {
+        let account = match self.settings.get_account_token() {
+            Some(account) => account,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(wireguard::Error::NoAccountToken),
+                    "revoke_wireguard_key response",
+                );
+                return;
+            }
+        };
+
+        let daemon_tx = self.tx.clone();
+        let fut = self
+            .wireguard_key_manager
+            .revoke_key(account.clone(), public_key.clone())
+            .then(move |result| -> Result<(), ()> {
+                if result.is_ok() {
+                    let _ = daemon_tx.send(InternalDaemonEvent::WgKeyRevoked(account, public_key));
+                }
+                Self::oneshot_send(tx, result, "revoke_wireguard_key response");
+                Ok(())
+            });
+        if let Err(e) = self.core_handle.remote.execute(fut) {
+            log::error!("Failed to spawn a future to revoke wireguard key: {:?}", e);
+        }
+    }
+
+    /// Replaces the key of the current account with an existing private key. The previous key
+    /// stays registered on the account.
+    fn on_import_wireguard_key(
+        &mut self,
+        tx: oneshot::Sender<Result<wireguard::PublicKey, wireguard::Error>>,
+        private_key: wireguard::PrivateKey,
+    ) {
+        let account = match self.settings.get_account_token() {
+            Some(account) => account,
+            None => {
+                Self::oneshot_send(
+                    tx,
+                    Err(wireguard::Error::NoAccountToken),
+                    "import_wireguard_key response",
+                );
+                return;
+            }
+        };
+
+        let daemon_tx = self.tx.clone();
+        let fut = self
+            .wireguard_key_manager
+            .import_key(account.clone(), private_key)
+            .then(move |result| -> Result<(), ()> {
+                match result {
+                    Ok(data) => {
+                        let _ =
+                            daemon_tx.send(InternalDaemonEvent::WgKeyImported(account, data, tx));
+                    }
+                    Err(e) => Self::oneshot_send(tx, Err(e), "import_wireguard_key response"),
+                }
+                Ok(())
+            });
+        if let Err(e) = self.core_handle.remote.execute(fut) {
+            log::error!("Failed to spawn a future to import wireguard key: {:?}", e);
+        }
+    }
+
     fn on_get_settings(&self, tx: oneshot::Sender<Settings>) {
         Self::oneshot_send(tx, self.settings.to_settings(), "get_settings response");
     }
@@ -1779,9 +3339,10 @@ where
         self.send_tunnel_command(TunnelCommand::Disconnect);
     }
 
-    fn reconnect_tunnel(&mut self) {
+    /// Applies changed settings to the tunnel, if it should be up.
+    fn reconfigure_tunnel(&mut self) {
         if self.target_state == TargetState::Secured {
-            self.connect_tunnel();
+            self.send_tunnel_command(TunnelCommand::Reconfigure);
         }
     }
 
@@ -1861,6 +3422,17 @@ where
     }
 }
 
+/// Name of the tunnel state, as used in logs and metrics.
+fn tunnel_state_name(tunnel_state: &TunnelState) -> &'static str {
+    match tunnel_state {
+        TunnelState::Disconnected => "disconnected",
+        TunnelState::Connecting { .. } => "connecting",
+        TunnelState::Connected { .. } => "connected",
+        TunnelState::Disconnecting(..) => "disconnecting",
+        TunnelState::Error(..) => "error",
+    }
+}
+
 pub struct DaemonShutdownHandle {
     tx: DaemonEventSender,
 }
@@ -1902,3 +3474,34 @@ impl TunnelParametersGenerator for MullvadTunnelParametersGenerator {
         }
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switch_account_while_connected() {
+        let account_token = "1234567890".to_string();
+        assert_eq!(
+            AccountChangeAction::new(TargetState::Secured, Some(&account_token)),
+            AccountChangeAction::Reconnect
+        );
+        assert_eq!(
+            AccountChangeAction::new(TargetState::Unsecured, Some(&account_token)),
+            AccountChangeAction::None
+        );
+    }
+
+    #[test]
+    fn test_clear_account() {
+        assert_eq!(
+            AccountChangeAction::new(TargetState::Secured, None),
+            AccountChangeAction::Disconnect
+        );
+        assert_eq!(
+            AccountChangeAction::new(TargetState::Unsecured, None),
+            AccountChangeAction::Disconnect
+        );
+    }
+}
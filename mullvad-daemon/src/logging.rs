@@ -4,9 +4,14 @@ use fern::{
     Output,
 };
 use log;
-use std::{fmt, io, path::PathBuf};
+use mullvad_types::log_level::{LogFilter, LogLevel};
+use parking_lot::RwLock;
+use std::{fmt, io, path::PathBuf, str::FromStr};
 use talpid_core::logging::rotate_log;
 
+#[cfg(target_os = "linux")]
+mod journald;
+
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
     /// Unable to open log file for writing
@@ -22,6 +27,11 @@ pub enum Error {
 
     #[error(display = "Unable to set logger")]
     SetLoggerError(#[error(source)] log::SetLoggerError),
+
+    /// Unable to connect to the journald socket
+    #[cfg(target_os = "linux")]
+    #[error(display = "Unable to connect to journald")]
+    ConnectJournald(#[error(source)] io::Error),
 }
 
 pub const SILENCED_CRATES: &[&str] = &[
@@ -61,23 +71,121 @@ const LINE_SEPARATOR: &str = "\n";
 const LINE_SEPARATOR: &str = "\r\n";
 
 const DATE_TIME_FORMAT_STR: &str = "[%Y-%m-%d %H:%M:%S%.3f]";
+const JSON_DATE_TIME_FORMAT_STR: &str = "%Y-%m-%dT%H:%M:%S%.3f%:z";
+
+lazy_static::lazy_static! {
+    static ref LOG_FILTER: RwLock<FilterState> =
+        RwLock::new(FilterState::new(LogFilter::new(LogLevel::Info)));
+    static ref LOG_CONTEXT: RwLock<LogContext> = RwLock::new(LogContext::default());
+}
+
+/// Format of log lines written to stdout and the log file.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format: {}", s)),
+        }
+    }
+}
+
+/// The filter requested by the user along with the filter actually applied, which also keeps
+/// noisy dependencies quiet unless the user explicitly asks for their logs.
+struct FilterState {
+    requested: LogFilter,
+    effective: LogFilter,
+}
+
+impl FilterState {
+    fn new(requested: LogFilter) -> Self {
+        let mut effective = LogFilter::new(requested.level);
+        for silenced_crate in SILENCED_CRATES {
+            effective
+                .modules
+                .insert(silenced_crate.to_string(), LogLevel::Warn);
+        }
+        let quieter_level = LogLevel::from(one_level_quieter(requested.level.into()));
+        for silenced_crate in SLIGHTLY_SILENCED_CRATES {
+            effective
+                .modules
+                .insert(silenced_crate.to_string(), quieter_level);
+        }
+        effective.modules.extend(
+            requested
+                .modules
+                .iter()
+                .map(|(module, level)| (module.clone(), *level)),
+        );
+
+        FilterState {
+            requested,
+            effective,
+        }
+    }
+}
+
+/// Daemon state that is attached to every structured log record.
+#[derive(Debug, Default, Clone)]
+struct LogContext {
+    tunnel_state: Option<&'static str>,
+    relay_hostname: Option<String>,
+}
+
+/// Replaces the log filter. Takes effect immediately for all log outputs.
+pub fn set_log_filter(filter: LogFilter) {
+    let state = FilterState::new(filter);
+    log::set_max_level(state.effective.max_level().into());
+    *LOG_FILTER.write() = state;
+}
+
+/// Returns the log filter that is currently in use.
+pub fn get_log_filter() -> LogFilter {
+    LOG_FILTER.read().requested.clone()
+}
+
+/// Updates the tunnel state and relay that are included in structured log records.
+pub fn set_log_context(tunnel_state: &'static str, relay_hostname: Option<String>) {
+    *LOG_CONTEXT.write() = LogContext {
+        tunnel_state: Some(tunnel_state),
+        relay_hostname,
+    };
+}
+
+fn is_enabled(metadata: &log::Metadata<'_>) -> bool {
+    let level: log::LevelFilter = LOG_FILTER
+        .read()
+        .effective
+        .level_for(metadata.target())
+        .into();
+    metadata.level() <= level
+}
 
 pub fn init_logger(
     log_level: log::LevelFilter,
     log_file: Option<&PathBuf>,
     output_timestamp: bool,
+    log_format: LogFormat,
+    log_to_journald: bool,
 ) -> Result<(), Error> {
-    let mut top_dispatcher = fern::Dispatch::new().level(log_level);
-    for silenced_crate in SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, log::LevelFilter::Warn);
-    }
-    for silenced_crate in SLIGHTLY_SILENCED_CRATES {
-        top_dispatcher = top_dispatcher.level_for(*silenced_crate, one_level_quieter(log_level));
-    }
+    let mut top_dispatcher = fern::Dispatch::new()
+        .level(log::LevelFilter::Trace)
+        .filter(is_enabled);
 
     let stdout_formatter = Formatter {
         output_timestamp,
         output_color: true,
+        format: log_format,
     };
     let stdout_dispatcher = fern::Dispatch::new()
         .format(move |out, message, record| stdout_formatter.output_msg(out, message, record))
@@ -89,6 +197,7 @@ pub fn init_logger(
         let file_formatter = Formatter {
             output_timestamp: true,
             output_color: false,
+            format: log_format,
         };
         let f = fern::log_file(log_file).map_err(|source| Error::WriteFile {
             path: log_file.display().to_string(),
@@ -99,6 +208,16 @@ pub fn init_logger(
             .chain(Output::file(f, LINE_SEPARATOR));
         top_dispatcher = top_dispatcher.chain(file_dispatcher);
     }
+    #[cfg(target_os = "linux")]
+    {
+        if log_to_journald {
+            let logger: Box<dyn log::Log> =
+                Box::new(journald::JournaldLogger::new().map_err(Error::ConnectJournald)?);
+            top_dispatcher = top_dispatcher.chain(logger);
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = log_to_journald;
     #[cfg(all(target_os = "android", debug_assertions))]
     {
         use android_logger::{AndroidLogger, Config};
@@ -108,6 +227,8 @@ pub fn init_logger(
         top_dispatcher = top_dispatcher.chain(logger);
     }
     top_dispatcher.apply().map_err(Error::SetLoggerError)?;
+    // Applying the dispatcher resets the max level, so the filter has to be set afterwards.
+    set_log_filter(LogFilter::new(log_level.into()));
     Ok(())
 }
 
@@ -123,10 +244,11 @@ fn one_level_quieter(level: log::LevelFilter) -> log::LevelFilter {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 struct Formatter {
     pub output_timestamp: bool,
     pub output_color: bool,
+    pub format: LogFormat,
 }
 
 impl Formatter {
@@ -152,6 +274,10 @@ impl Formatter {
         message: &fmt::Arguments<'_>,
         record: &log::Record<'_>,
     ) {
+        if self.format == LogFormat::Json {
+            return self.output_json(out, message, record);
+        }
+
         let message = escape_newlines(format!("{}", message));
 
         out.finish(format_args!(
@@ -162,6 +288,28 @@ impl Formatter {
             message,
         ))
     }
+
+    fn output_json(
+        &self,
+        out: fern::FormatCallback<'_>,
+        message: &fmt::Arguments<'_>,
+        record: &log::Record<'_>,
+    ) {
+        let context = LOG_CONTEXT.read().clone();
+        let mut line = serde_json::json!({
+            "timestamp": chrono::Local::now().format(JSON_DATE_TIME_FORMAT_STR).to_string(),
+            "level": record.level().to_string(),
+            "module": record.target(),
+            "message": message.to_string(),
+        });
+        if let Some(tunnel_state) = context.tunnel_state {
+            line["tunnel_state"] = tunnel_state.into();
+        }
+        if let Some(relay_hostname) = context.relay_hostname {
+            line["relay_hostname"] = relay_hostname.into();
+        }
+        out.finish(format_args!("{}", line))
+    }
 }
 
 #[cfg(not(windows))]
@@ -0,0 +1,81 @@
+//! Sends log records to the systemd journal using its native protocol, so that structured fields
+//! can be queried with `journalctl`.
+
+use super::LOG_CONTEXT;
+use std::{io, os::unix::net::UnixDatagram};
+
+const JOURNALD_SOCKET_PATH: &str = "/run/systemd/journal/socket";
+const SYSLOG_IDENTIFIER: &str = "mullvad-daemon";
+
+pub struct JournaldLogger {
+    socket: UnixDatagram,
+}
+
+impl JournaldLogger {
+    pub fn new() -> io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(JOURNALD_SOCKET_PATH)?;
+        Ok(JournaldLogger { socket })
+    }
+}
+
+impl log::Log for JournaldLogger {
+    fn enabled(&self, _metadata: &log::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn log(&self, record: &log::Record<'_>) {
+        let context = LOG_CONTEXT.read().clone();
+        let mut datagram = Vec::new();
+
+        add_field(&mut datagram, "MESSAGE", &record.args().to_string());
+        add_field(&mut datagram, "PRIORITY", priority(record.level()));
+        add_field(&mut datagram, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+        add_field(&mut datagram, "TARGET", record.target());
+        if let Some(module) = record.module_path() {
+            add_field(&mut datagram, "CODE_MODULE", module);
+        }
+        if let Some(file) = record.file() {
+            add_field(&mut datagram, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            add_field(&mut datagram, "CODE_LINE", &line.to_string());
+        }
+        if let Some(tunnel_state) = context.tunnel_state {
+            add_field(&mut datagram, "TUNNEL_STATE", tunnel_state);
+        }
+        if let Some(relay_hostname) = context.relay_hostname {
+            add_field(&mut datagram, "RELAY_HOSTNAME", &relay_hostname);
+        }
+
+        // Records that don't fit in a single datagram are dropped. There is nowhere sensible to
+        // report the failure to, since it would end up in this logger again.
+        let _ = self.socket.send(&datagram);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Priorities as defined by syslog(3).
+fn priority(level: log::Level) -> &'static str {
+    match level {
+        log::Level::Error => "3",
+        log::Level::Warn => "4",
+        log::Level::Info => "6",
+        log::Level::Debug | log::Level::Trace => "7",
+    }
+}
+
+/// Appends a field to the datagram. Values containing newlines must be written with an explicit
+/// little-endian length prefix instead of the `NAME=value` form.
+fn add_field(datagram: &mut Vec<u8>, name: &str, value: &str) {
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value.as_bytes());
+    datagram.push(b'\n');
+}
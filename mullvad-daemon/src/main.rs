@@ -4,7 +4,7 @@ use log::{debug, error, info, warn};
 use mullvad_daemon::{
     logging,
     management_interface::{ManagementInterfaceEventBroadcaster, ManagementInterfaceServer},
-    rpc_uniqueness_check, version, Daemon, DaemonCommandChannel, DaemonCommandSender,
+    metrics, rpc_uniqueness_check, version, Daemon, DaemonCommandChannel, DaemonCommandSender,
 };
 use std::{path::PathBuf, thread, time::Duration};
 use talpid_types::ErrorExt;
@@ -42,6 +42,8 @@ fn init_logging(config: &cli::Config) -> Result<Option<PathBuf>, String> {
         config.log_level,
         log_file.as_ref(),
         config.log_stdout_timestamps,
+        config.log_format,
+        config.log_to_journald,
     )
     .map_err(|e| e.display_chain_with_msg("Unable to initialize logger"))?;
     log_panics::init();
@@ -118,6 +120,10 @@ fn create_daemon(
 
     let command_channel = DaemonCommandChannel::new();
     let event_listener = spawn_management_interface(command_channel.sender())?;
+    if let Some(metrics_address) = &cli::get_config().metrics_address {
+        metrics::spawn_server(metrics_address.clone(), command_channel.sender())
+            .map_err(|e| e.display_chain_with_msg("Unable to start metrics endpoint"))?;
+    }
 
     Daemon::start(
         log_dir,
@@ -1,4 +1,8 @@
-use crate::{BoxFuture, DaemonCommand, DaemonCommandSender, EventListener};
+use crate::{
+    account_history, update, wireguard::Error as WireguardKeyError, BoxFuture, DaemonCommand,
+    DaemonCommandSender, EventListener, ExcludedDestinationsError,
+};
+use ipnetwork::IpNetwork;
 use jsonrpc_core::{
     futures::{future, sync, Future},
     Error, ErrorCode, MetaIoHandler, Metadata,
@@ -9,13 +13,18 @@ use jsonrpc_pubsub::{PubSubHandler, PubSubMetadata, Session, SubscriptionId};
 use mullvad_paths;
 use mullvad_rpc::{rest::Error as RestError, StatusCode};
 use mullvad_types::{
-    account::{AccountData, AccountToken, VoucherSubmission},
+    account::{AccountData, AccountHistoryEntry, AccountToken, VoucherSubmission},
+    api_access::ApiAccessMethod,
+    api_proxy::ApiProxy,
+    diagnostics::Diagnostics,
     location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
+    log_level::LogFilter,
+    relay_constraints::{BridgeSettings, BridgeState, Constraint, RelaySettingsUpdate},
     relay_list::RelayList,
+    retry_strategy::RetryStrategy,
     settings::Settings,
     states::{TargetState, TunnelState},
-    version, wireguard, DaemonEvent,
+    version, wireguard, ConfigDirective, CustomTunnelConfigFile, DaemonEvent,
 };
 use parking_lot::RwLock;
 use std::{
@@ -23,12 +32,26 @@ use std::{
     sync::Arc,
 };
 use talpid_ipc;
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{openvpn, proxy::TunnelSocksProxy, GatewayOptions},
+    ErrorExt,
+};
 use uuid;
 
 pub const INVALID_VOUCHER_CODE: i64 = -400;
 pub const VOUCHER_USED_ALREADY_CODE: i64 = -401;
 pub const INVALID_ACCOUNT_CODE: i64 = -200;
+pub const ACCOUNT_NOT_IN_HISTORY_CODE: i64 = -201;
+pub const ACCOUNT_LABEL_IN_USE_CODE: i64 = -202;
+pub const NO_ACCOUNT_CODE: i64 = -900;
+pub const WIREGUARD_KEY_NOT_FOUND_CODE: i64 = -300;
+pub const TOO_MANY_WIREGUARD_KEYS_CODE: i64 = -301;
+pub const NO_PENDING_UPDATE_CODE: i64 = -500;
+pub const APPLY_UPDATE_FAILED_CODE: i64 = -501;
+pub const INVALID_EXCLUDED_DESTINATION_CODE: i64 = -600;
+pub const INVALID_OPENVPN_OPTION_CODE: i64 = -700;
+pub const INVALID_CUSTOM_TUNNEL_CONFIG_CODE: i64 = -800;
+pub const NO_WIREGUARD_CONNECTION_CODE: i64 = -801;
 
 
 build_rpc_trait! {
@@ -70,14 +93,45 @@ build_rpc_trait! {
             Self::Metadata, RelaySettingsUpdate
             ) -> BoxFuture<(), Error>;
 
+        /// Use a custom tunnel read from a WireGuard or OpenVPN configuration file. Returns the
+        /// directives in the file that have no effect
+        #[rpc(meta, name = "import_custom_tunnel")]
+        fn import_custom_tunnel(
+            &self,
+            Self::Metadata,
+            CustomTunnelConfigFile
+        ) -> BoxFuture<Vec<ConfigDirective>, Error>;
+
+        /// Returns the current WireGuard connection as a wg-quick configuration file
+        #[rpc(meta, name = "export_wireguard_config")]
+        fn export_wireguard_config(&self, Self::Metadata) -> BoxFuture<String, Error>;
+
         /// Set if the client should allow communication with the LAN while in secured state.
         #[rpc(meta, name = "set_allow_lan")]
         fn set_allow_lan(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
 
+        /// Share the tunnel with a local network while connected, or stop doing so.
+        /// Only supported on Linux.
+        #[rpc(meta, name = "set_gateway")]
+        fn set_gateway(&self, Self::Metadata, Option<GatewayOptions>) -> BoxFuture<(), Error>;
+
+        /// Set the local SOCKS5 proxy whose connections are sent through the tunnel while
+        /// connected, or unset it.
+        #[rpc(meta, name = "set_tunnel_socks_proxy")]
+        fn set_tunnel_socks_proxy(
+            &self,
+            Self::Metadata,
+            Option<TunnelSocksProxy>
+        ) -> BoxFuture<(), Error>;
+
         /// Set whether to enable the beta program.
         #[rpc(meta, name = "set_show_beta_releases")]
         fn set_show_beta_releases(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
 
+        /// Set whether new releases should be downloaded and verified in the background.
+        #[rpc(meta, name = "set_download_updates")]
+        fn set_download_updates(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
+
         /// Set if the client should allow network communication when in the disconnected state.
         #[rpc(meta, name = "set_block_when_disconnected")]
         fn set_block_when_disconnected(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
@@ -126,10 +180,37 @@ build_rpc_trait! {
         #[rpc(meta, name = "remove_account_from_history")]
         fn remove_account_from_history(&self, Self::Metadata, AccountToken) -> BoxFuture<(), Error>;
 
+        /// Get the accounts in the account history with their labels, last use and cached expiry
+        #[rpc(meta, name = "list_accounts")]
+        fn list_accounts(&self, Self::Metadata) -> BoxFuture<Vec<AccountHistoryEntry>, Error>;
+
+        /// Set or remove the label of an account in the account history
+        #[rpc(meta, name = "set_account_label")]
+        fn set_account_label(
+            &self,
+            Self::Metadata,
+            AccountToken,
+            Option<String>
+        ) -> BoxFuture<(), Error>;
+
+        /// Switch to the account in the account history that has the given label or account token,
+        /// reusing its stored WireGuard key
+        #[rpc(meta, name = "switch_account")]
+        fn switch_account(&self, Self::Metadata, String) -> BoxFuture<(), Error>;
+
         /// Sets openvpn's mssfix parameter
         #[rpc(meta, name = "set_openvpn_mssfix")]
         fn set_openvpn_mssfix(&self, Self::Metadata, Option<u16>) -> BoxFuture<(), Error>;
 
+        /// Sets or, if the value is null, unsets an extra OpenVPN option
+        #[rpc(meta, name = "set_openvpn_extra_option")]
+        fn set_openvpn_extra_option(
+            &self,
+            Self::Metadata,
+            openvpn::ExtraOption,
+            Option<u32>
+        ) -> BoxFuture<(), Error>;
+
         /// Sets proxy details for OpenVPN
         #[rpc(meta, name = "set_bridge_settings")]
         fn set_bridge_settings(&self, Self::Metadata, BridgeSettings) -> BoxFuture<(), Error>;
@@ -146,10 +227,38 @@ build_rpc_trait! {
         #[rpc(meta, name = "set_wireguard_mtu")]
         fn set_wireguard_mtu(&self, Self::Metadata, Option<u16>) -> BoxFuture<(), Error>;
 
+        /// Set if wireguard tunnels should use policy routing. Only has an effect on Linux
+        #[rpc(meta, name = "set_wireguard_policy_routing")]
+        fn set_wireguard_policy_routing(&self, Self::Metadata, bool) -> BoxFuture<(), Error>;
+
+        /// Set a network namespace to run wireguard tunnels in. Only has an effect on Linux
+        #[rpc(meta, name = "set_wireguard_network_namespace")]
+        fn set_wireguard_network_namespace(&self, Self::Metadata, Option<String>) -> BoxFuture<(), Error>;
+
+        /// Set the destination networks that are routed outside of the tunnel
+        #[rpc(meta, name = "set_excluded_destinations")]
+        fn set_excluded_destinations(&self, Self::Metadata, Vec<IpNetwork>) -> BoxFuture<(), Error>;
+
         /// Set automatic key rotation interval for wireguard tunnels
         #[rpc(meta, name = "set_wireguard_rotation_interval")]
         fn set_wireguard_rotation_interval(&self, Self::Metadata, Option<u32>) -> BoxFuture<(), Error>;
 
+        /// Set how automatic key rotation replaces the wireguard key
+        #[rpc(meta, name = "set_key_rotation_policy")]
+        fn set_key_rotation_policy(&self, Self::Metadata, wireguard::RotationPolicy) -> BoxFuture<(), Error>;
+
+        /// Set the strategy used when connection attempts fail
+        #[rpc(meta, name = "set_retry_strategy")]
+        fn set_retry_strategy(&self, Self::Metadata, RetryStrategy) -> BoxFuture<(), Error>;
+
+        /// Set a proxy that all API requests are sent through
+        #[rpc(meta, name = "set_api_proxy")]
+        fn set_api_proxy(&self, Self::Metadata, Option<ApiProxy>) -> BoxFuture<(), Error>;
+
+        /// Set how the API may be reached
+        #[rpc(meta, name = "set_api_access_method")]
+        fn set_api_access_method(&self, Self::Metadata, Constraint<ApiAccessMethod>) -> BoxFuture<(), Error>;
+
         /// Returns the current daemon settings
         #[rpc(meta, name = "get_settings")]
         fn get_settings(&self, Self::Metadata) -> BoxFuture<Settings, Error>;
@@ -166,6 +275,18 @@ build_rpc_trait! {
         #[rpc(meta, name = "verify_wireguard_key")]
         fn verify_wireguard_key(&self, Self::Metadata) -> BoxFuture<bool, Error>;
 
+        /// List all wireguard keys registered on the current account
+        #[rpc(meta, name = "list_wireguard_keys")]
+        fn list_wireguard_keys(&self, Self::Metadata) -> BoxFuture<Vec<wireguard::RegisteredKey>, Error>;
+
+        /// Remove a wireguard key from the current account
+        #[rpc(meta, name = "revoke_wireguard_key")]
+        fn revoke_wireguard_key(&self, Self::Metadata, talpid_types::net::wireguard::PublicKey) -> BoxFuture<(), Error>;
+
+        /// Use an existing wireguard private key for the current account
+        #[rpc(meta, name = "import_wireguard_key")]
+        fn import_wireguard_key(&self, Self::Metadata, talpid_types::net::wireguard::PrivateKey) -> BoxFuture<wireguard::PublicKey, Error>;
+
         /// Retreive version of the app
         #[rpc(meta, name = "get_current_version")]
         fn get_current_version(&self, Self::Metadata) -> BoxFuture<String, Error>;
@@ -174,6 +295,26 @@ build_rpc_trait! {
         #[rpc(meta, name = "get_version_info")]
         fn get_version_info(&self, Self::Metadata) -> BoxFuture<version::AppVersionInfo, Error>;
 
+        /// Retrieve the downloaded release that is ready to be installed, if any
+        #[rpc(meta, name = "get_pending_update")]
+        fn get_pending_update(&self, Self::Metadata) -> BoxFuture<Option<version::PendingUpdate>, Error>;
+
+        /// Verify the pending update again and start its installer, which replaces the daemon
+        #[rpc(meta, name = "apply_update")]
+        fn apply_update(&self, Self::Metadata) -> BoxFuture<(), Error>;
+
+        /// Collect a structured report of what the daemon and its subsystems currently see
+        #[rpc(meta, name = "get_diagnostics")]
+        fn get_diagnostics(&self, Self::Metadata) -> BoxFuture<Diagnostics, Error>;
+
+        /// Change the log level of the running daemon. The change is not persisted
+        #[rpc(meta, name = "set_log_level")]
+        fn set_log_level(&self, Self::Metadata, LogFilter) -> BoxFuture<(), Error>;
+
+        /// Returns the log level of the running daemon
+        #[rpc(meta, name = "get_log_level")]
+        fn get_log_level(&self, Self::Metadata) -> BoxFuture<LogFilter, Error>;
+
         /// Remove all configuration and cache files
         #[rpc(meta, name = "factory_reset")]
         fn factory_reset(&self, Self::Metadata) -> BoxFuture<(), Error>;
@@ -271,6 +412,11 @@ impl EventListener for ManagementInterfaceEventBroadcaster {
         log::debug!("Broadcasting new wireguard key event");
         self.notify(DaemonEvent::WireguardKey(key_event));
     }
+
+    fn notify_key_rotation_event(&self, rotation_event: wireguard::KeyRotationEvent) {
+        log::debug!("Broadcasting new key rotation event");
+        self.notify(DaemonEvent::WireguardKeyRotation(rotation_event));
+    }
 }
 
 impl ManagementInterfaceEventBroadcaster {
@@ -326,6 +472,39 @@ impl ManagementInterface {
             _ => Error::internal_error(),
         }
     }
+
+    /// Converts an error from managing wireguard keys into a JSONRPC error for the JSONRPC client.
+    fn map_wireguard_key_error(error: WireguardKeyError) -> Error {
+        let code = match error {
+            WireguardKeyError::NoAccountToken => NO_ACCOUNT_CODE,
+            WireguardKeyError::KeyNotFound => WIREGUARD_KEY_NOT_FOUND_CODE,
+            WireguardKeyError::TooManyKeys => TOO_MANY_WIREGUARD_KEYS_CODE,
+            WireguardKeyError::RestError(error) => return Self::map_rest_account_error(error),
+            error => {
+                log::error!("{}", error.display_chain());
+                return Error::internal_error();
+            }
+        };
+        Error {
+            code: ErrorCode::from(code),
+            message: error.to_string(),
+            data: None,
+        }
+    }
+
+    /// Converts an account history error into a JSONRPC error for the JSONRPC client.
+    fn map_account_history_error(error: account_history::Error) -> Error {
+        let code = match error {
+            account_history::Error::AccountNotFound => ACCOUNT_NOT_IN_HISTORY_CODE,
+            account_history::Error::LabelInUse => ACCOUNT_LABEL_IN_USE_CODE,
+            _ => return Error::internal_error(),
+        };
+        Error {
+            code: ErrorCode::from(code),
+            message: error.to_string(),
+            data: None,
+        }
+    }
 }
 
 impl ManagementInterfaceApi for ManagementInterface {
@@ -461,6 +640,42 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn import_custom_tunnel(
+        &self,
+        _: Self::Metadata,
+        config_file: CustomTunnelConfigFile,
+    ) -> BoxFuture<Vec<ConfigDirective>, Error> {
+        log::debug!("import_custom_tunnel");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ImportCustomTunnel(tx, config_file))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| {
+                result.map_err(|error| Error {
+                    code: ErrorCode::ServerError(INVALID_CUSTOM_TUNNEL_CONFIG_CODE),
+                    message: error.to_string(),
+                    data: None,
+                })
+            });
+        Box::new(future)
+    }
+
+    fn export_wireguard_config(&self, _: Self::Metadata) -> BoxFuture<String, Error> {
+        log::debug!("export_wireguard_config");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ExportWireguardConfig(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| {
+                result.map_err(|error| Error {
+                    code: ErrorCode::ServerError(NO_WIREGUARD_CONNECTION_CODE),
+                    message: error.to_string(),
+                    data: None,
+                })
+            });
+        Box::new(future)
+    }
+
     fn set_allow_lan(&self, _: Self::Metadata, allow_lan: bool) -> BoxFuture<(), Error> {
         log::debug!("set_allow_lan({})", allow_lan);
         let (tx, rx) = sync::oneshot::channel();
@@ -470,6 +685,32 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn set_gateway(
+        &self,
+        _: Self::Metadata,
+        gateway: Option<GatewayOptions>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_gateway({:?})", gateway);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetGateway(tx, gateway))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_tunnel_socks_proxy(
+        &self,
+        _: Self::Metadata,
+        proxy: Option<TunnelSocksProxy>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_tunnel_socks_proxy");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetTunnelSocksProxy(tx, proxy))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn set_show_beta_releases(&self, _: Self::Metadata, enabled: bool) -> BoxFuture<(), Error> {
         log::debug!("set_show_beta_releases({})", enabled);
         let (tx, rx) = sync::oneshot::channel();
@@ -479,6 +720,15 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn set_download_updates(&self, _: Self::Metadata, enabled: bool) -> BoxFuture<(), Error> {
+        log::debug!("set_download_updates({})", enabled);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetDownloadUpdates(tx, enabled))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn set_block_when_disconnected(
         &self,
         _: Self::Metadata,
@@ -513,7 +763,7 @@ impl ManagementInterfaceApi for ManagementInterface {
             .and_then(|result| match result {
                 Ok(()) => future::ok(()),
                 Err(()) => future::err(Error {
-                    code: ErrorCode::ServerError(-900),
+                    code: ErrorCode::ServerError(NO_ACCOUNT_CODE),
                     message: "No account token configured".to_owned(),
                     data: None,
                 }),
@@ -586,6 +836,40 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn list_accounts(&self, _: Self::Metadata) -> BoxFuture<Vec<AccountHistoryEntry>, Error> {
+        log::debug!("list_accounts");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ListAccounts(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_account_label(
+        &self,
+        _: Self::Metadata,
+        account_token: AccountToken,
+        label: Option<String>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_account_label({:?})", label);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetAccountLabel(tx, account_token, label))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_account_history_error));
+        Box::new(future)
+    }
+
+    fn switch_account(&self, _: Self::Metadata, label: String) -> BoxFuture<(), Error> {
+        log::debug!("switch_account");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SwitchAccount(tx, label))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_account_history_error));
+        Box::new(future)
+    }
+
     fn set_openvpn_mssfix(&self, _: Self::Metadata, mssfix: Option<u16>) -> BoxFuture<(), Error> {
         log::debug!("set_openvpn_mssfix({:?})", mssfix);
         let (tx, rx) = sync::oneshot::channel();
@@ -596,6 +880,28 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn set_openvpn_extra_option(
+        &self,
+        _: Self::Metadata,
+        option: openvpn::ExtraOption,
+        value: Option<u32>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_openvpn_extra_option({}, {:?})", option, value);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetOpenVpnExtraOption(tx, option, value))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| {
+                result.map_err(|error| Error {
+                    code: ErrorCode::ServerError(INVALID_OPENVPN_OPTION_CODE),
+                    message: error.to_string(),
+                    data: None,
+                })
+            });
+
+        Box::new(future)
+    }
+
     fn set_bridge_settings(
         &self,
         _: Self::Metadata,
@@ -646,6 +952,63 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    /// Set if wireguard tunnels should use policy routing
+    fn set_wireguard_policy_routing(
+        &self,
+        _: Self::Metadata,
+        enabled: bool,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_wireguard_policy_routing({})", enabled);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetWireguardPolicyRouting(tx, enabled))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    /// Set the destination networks that are routed outside of the tunnel
+    fn set_excluded_destinations(
+        &self,
+        _: Self::Metadata,
+        destinations: Vec<IpNetwork>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_excluded_destinations({:?})", destinations);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetExcludedDestinations(tx, destinations))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| match result {
+                Ok(()) => future::ok(()),
+                Err(error @ ExcludedDestinationsError::OverlapsRelay(..))
+                | Err(error @ ExcludedDestinationsError::OverlapsGateway(..)) => {
+                    future::err(Error {
+                        code: ErrorCode::ServerError(INVALID_EXCLUDED_DESTINATION_CODE),
+                        message: error.to_string(),
+                        data: None,
+                    })
+                }
+                Err(_) => future::err(Error::internal_error()),
+            });
+        Box::new(future)
+    }
+
+    /// Set a network namespace to run wireguard tunnels in
+    fn set_wireguard_network_namespace(
+        &self,
+        _: Self::Metadata,
+        network_namespace: Option<String>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_wireguard_network_namespace({:?})", network_namespace);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetWireguardNetworkNamespace(
+                tx,
+                network_namespace,
+            ))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     /// Set automatic key rotation interval for wireguard tunnels
     fn set_wireguard_rotation_interval(
         &self,
@@ -660,6 +1023,62 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn set_key_rotation_policy(
+        &self,
+        _: Self::Metadata,
+        policy: wireguard::RotationPolicy,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_key_rotation_policy({:?})", policy);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetKeyRotationPolicy(tx, policy))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_retry_strategy(
+        &self,
+        _: Self::Metadata,
+        retry_strategy: RetryStrategy,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_retry_strategy({:?})", retry_strategy);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetRetryStrategy(tx, retry_strategy))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_api_proxy(
+        &self,
+        _: Self::Metadata,
+        api_proxy: Option<ApiProxy>,
+    ) -> BoxFuture<(), Error> {
+        // Avoid logging the proxy credentials
+        match api_proxy {
+            Some(ref api_proxy) => log::debug!("set_api_proxy({})", api_proxy),
+            None => log::debug!("set_api_proxy(None)"),
+        }
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetApiProxy(tx, api_proxy))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn set_api_access_method(
+        &self,
+        _: Self::Metadata,
+        api_access_method: Constraint<ApiAccessMethod>,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("set_api_access_method({:?})", api_access_method);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetApiAccessMethod(tx, api_access_method))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn get_settings(&self, _: Self::Metadata) -> BoxFuture<Settings, Error> {
         log::debug!("get_settings");
         let (tx, rx) = sync::oneshot::channel();
@@ -702,6 +1121,47 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn list_wireguard_keys(
+        &self,
+        _: Self::Metadata,
+    ) -> BoxFuture<Vec<wireguard::RegisteredKey>, Error> {
+        log::debug!("list_wireguard_keys");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ListWireguardKeys(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
+    fn revoke_wireguard_key(
+        &self,
+        _: Self::Metadata,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) -> BoxFuture<(), Error> {
+        log::debug!("revoke_wireguard_key");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::RevokeWireguardKey(tx, public_key))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
+    fn import_wireguard_key(
+        &self,
+        _: Self::Metadata,
+        private_key: talpid_types::net::wireguard::PrivateKey,
+    ) -> BoxFuture<wireguard::PublicKey, Error> {
+        log::debug!("import_wireguard_key");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ImportWireguardKey(tx, private_key))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| result.map_err(Self::map_wireguard_key_error));
+        Box::new(future)
+    }
+
     fn get_current_version(&self, _: Self::Metadata) -> BoxFuture<String, Error> {
         log::debug!("get_current_version");
         let (tx, rx) = sync::oneshot::channel();
@@ -722,6 +1182,71 @@ impl ManagementInterfaceApi for ManagementInterface {
         Box::new(future)
     }
 
+    fn get_pending_update(
+        &self,
+        _: Self::Metadata,
+    ) -> BoxFuture<Option<version::PendingUpdate>, Error> {
+        log::debug!("get_pending_update");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetPendingUpdate(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+
+        Box::new(future)
+    }
+
+    fn apply_update(&self, _: Self::Metadata) -> BoxFuture<(), Error> {
+        log::debug!("apply_update");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::ApplyUpdate(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|result| match result {
+                Ok(()) => future::ok(()),
+                Err(update::Error::NoPendingUpdate) => future::err(Error {
+                    code: ErrorCode::ServerError(NO_PENDING_UPDATE_CODE),
+                    message: "No update has been downloaded".to_owned(),
+                    data: None,
+                }),
+                Err(error) => future::err(Error {
+                    code: ErrorCode::ServerError(APPLY_UPDATE_FAILED_CODE),
+                    message: error.display_chain(),
+                    data: None,
+                }),
+            });
+
+        Box::new(future)
+    }
+
+    fn get_diagnostics(&self, _: Self::Metadata) -> BoxFuture<Diagnostics, Error> {
+        log::debug!("get_diagnostics");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetDiagnostics(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()))
+            .and_then(|diagnostics_future| diagnostics_future.map_err(|_| Error::internal_error()));
+
+        Box::new(future)
+    }
+
+    fn set_log_level(&self, _: Self::Metadata, log_filter: LogFilter) -> BoxFuture<(), Error> {
+        log::debug!("set_log_level({:?})", log_filter);
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::SetLogFilter(tx, log_filter))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
+    fn get_log_level(&self, _: Self::Metadata) -> BoxFuture<LogFilter, Error> {
+        log::debug!("get_log_level");
+        let (tx, rx) = sync::oneshot::channel();
+        let future = self
+            .send_command_to_daemon(DaemonCommand::GetLogFilter(tx))
+            .and_then(|_| rx.map_err(|_| Error::internal_error()));
+        Box::new(future)
+    }
+
     fn factory_reset(&self, _: Self::Metadata) -> BoxFuture<(), Error> {
         #[cfg(not(target_os = "android"))]
         {
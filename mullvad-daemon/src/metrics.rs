@@ -0,0 +1,519 @@
+//! Opt-in endpoint that exports the state of the daemon in the Prometheus text format, for
+//! monitoring the VPN health of unattended machines.
+
+use crate::{DaemonCommand, DaemonCommandSender};
+use futures::{sync::oneshot, Future};
+use mullvad_rpc::{ApiMetrics, LATENCY_BUCKETS};
+use mullvad_types::states::TunnelState;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+#[cfg(unix)]
+use std::{
+    os::unix::{fs::PermissionsExt, net::UnixListener},
+    path::PathBuf,
+};
+use talpid_core::tunnel::wireguard::stats::Stats;
+use talpid_types::{tunnel::ErrorStateCause, ErrorExt};
+use tokio_timer::Timer;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for the daemon to collect the metrics before responding with an error.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+const TUNNEL_STATES: &[&str] = &[
+    "disconnected",
+    "connecting",
+    "connected",
+    "disconnecting",
+    "error",
+];
+
+#[derive(err_derive::Error, Debug)]
+pub enum Error {
+    #[error(display = "Failed to listen on {}", _0)]
+    Listen(MetricsAddress, #[error(source, no_from)] io::Error),
+
+    #[error(
+        display = "The metrics endpoint must listen on a loopback address, not {}",
+        _0
+    )]
+    NotLoopback(SocketAddr),
+
+    #[cfg(unix)]
+    #[error(display = "Failed to set the permissions of {}", _0)]
+    SetPermissions(String, #[error(source)] io::Error),
+}
+
+/// Where the metrics endpoint listens for scrapes.
+#[derive(Debug, Clone)]
+pub enum MetricsAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for MetricsAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(address) = s.parse::<SocketAddr>() {
+            if !address.ip().is_loopback() {
+                return Err(format!(
+                    "The metrics endpoint must listen on a loopback address, not {}",
+                    address
+                ));
+            }
+            return Ok(MetricsAddress::Tcp(address));
+        }
+        #[cfg(unix)]
+        {
+            if s.starts_with('/') {
+                return Ok(MetricsAddress::Unix(PathBuf::from(s)));
+            }
+        }
+        Err(format!("Invalid metrics address: {}", s))
+    }
+}
+
+impl fmt::Display for MetricsAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetricsAddress::Tcp(address) => address.fmt(f),
+            #[cfg(unix)]
+            MetricsAddress::Unix(path) => path.display().fmt(f),
+        }
+    }
+}
+
+/// Counters that the daemon updates as events happen.
+pub(crate) struct Counters {
+    tunnel_state_entered: Instant,
+    reconnects: BTreeMap<&'static str, u64>,
+    key_events: BTreeMap<&'static str, u64>,
+}
+
+impl Counters {
+    pub fn new() -> Self {
+        Counters {
+            tunnel_state_entered: Instant::now(),
+            reconnects: BTreeMap::new(),
+            key_events: BTreeMap::new(),
+        }
+    }
+
+    pub fn tunnel_state_changed(&mut self, tunnel_state: &TunnelState) {
+        self.tunnel_state_entered = Instant::now();
+        if let TunnelState::Error(error_state) = tunnel_state {
+            *self
+                .reconnects
+                .entry(error_cause_label(error_state.cause()))
+                .or_insert(0) += 1;
+        }
+    }
+
+    pub fn key_event(&mut self, event: &'static str) {
+        *self.key_events.entry(event).or_insert(0) += 1;
+    }
+
+    pub fn time_in_state(&self) -> Duration {
+        self.tunnel_state_entered.elapsed()
+    }
+
+    pub fn reconnects(&self) -> BTreeMap<&'static str, u64> {
+        self.reconnects.clone()
+    }
+
+    pub fn key_events(&self) -> BTreeMap<&'static str, u64> {
+        self.key_events.clone()
+    }
+}
+
+fn error_cause_label(cause: &ErrorStateCause) -> &'static str {
+    match cause {
+        ErrorStateCause::AuthFailed(_) => "auth_failed",
+        ErrorStateCause::Ipv6Unavailable => "ipv6_unavailable",
+        ErrorStateCause::SetFirewallPolicyError => "set_firewall_policy_error",
+        ErrorStateCause::SetDnsError => "set_dns_error",
+        ErrorStateCause::StartTunnelError => "start_tunnel_error",
+        ErrorStateCause::TunnelParameterError(_) => "tunnel_parameter_error",
+        ErrorStateCause::IsOffline => "is_offline",
+        ErrorStateCause::TapAdapterProblem => "tap_adapter_problem",
+        #[cfg(target_os = "android")]
+        ErrorStateCause::VpnPermissionDenied => "vpn_permission_denied",
+    }
+}
+
+/// Values of all metrics at the time of a scrape.
+pub struct MetricsSnapshot {
+    pub tunnel_state: &'static str,
+    pub time_in_state: Duration,
+    /// Number of times the tunnel failed and entered the error state, by cause.
+    pub reconnects: BTreeMap<&'static str, u64>,
+    /// Traffic statistics, if the current tunnel is a WireGuard tunnel.
+    pub tunnel_stats: Option<Stats>,
+    pub relay_list_age: Duration,
+    pub api: ApiMetrics,
+    pub key_rotation: KeyRotationMetrics,
+}
+
+pub struct KeyRotationMetrics {
+    pub enabled: bool,
+    pub interval: Duration,
+    /// Age of the WireGuard key of the current account, if it has one.
+    pub key_age: Option<Duration>,
+    /// Number of key events by kind: new keys, failures, too many keys and the outcome of
+    /// rotations.
+    pub events: BTreeMap<&'static str, u64>,
+}
+
+impl MetricsSnapshot {
+    /// Formats the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        self.render_tunnel(&mut out);
+        self.render_api(&mut out);
+        self.render_key_rotation(&mut out);
+        out
+    }
+
+    fn render_tunnel(&self, out: &mut String) {
+        header(
+            out,
+            "mullvad_tunnel_state",
+            "gauge",
+            "Current tunnel state, 1 for the active state",
+        );
+        for state in TUNNEL_STATES {
+            let value = if *state == self.tunnel_state { 1 } else { 0 };
+            let _ = writeln!(out, "mullvad_tunnel_state{{state=\"{}\"}} {}", state, value);
+        }
+
+        header(
+            out,
+            "mullvad_tunnel_state_duration_seconds",
+            "gauge",
+            "Time spent in the current tunnel state",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_tunnel_state_duration_seconds {}",
+            self.time_in_state.as_secs_f64()
+        );
+
+        header(
+            out,
+            "mullvad_tunnel_reconnects_total",
+            "counter",
+            "Number of times the tunnel failed and entered the error state, by cause",
+        );
+        for (cause, count) in &self.reconnects {
+            let _ = writeln!(
+                out,
+                "mullvad_tunnel_reconnects_total{{cause=\"{}\"}} {}",
+                cause, count
+            );
+        }
+
+        if let Some(stats) = &self.tunnel_stats {
+            if let Some(handshake_age) = stats
+                .last_handshake_time
+                .and_then(|time| time.elapsed().ok())
+            {
+                header(
+                    out,
+                    "mullvad_wireguard_handshake_age_seconds",
+                    "gauge",
+                    "Time since the last WireGuard handshake",
+                );
+                let _ = writeln!(
+                    out,
+                    "mullvad_wireguard_handshake_age_seconds {}",
+                    handshake_age.as_secs_f64()
+                );
+            }
+
+            header(
+                out,
+                "mullvad_wireguard_received_bytes_total",
+                "counter",
+                "Bytes received through the current WireGuard tunnel",
+            );
+            let _ = writeln!(
+                out,
+                "mullvad_wireguard_received_bytes_total {}",
+                stats.rx_bytes
+            );
+            header(
+                out,
+                "mullvad_wireguard_transmitted_bytes_total",
+                "counter",
+                "Bytes sent through the current WireGuard tunnel",
+            );
+            let _ = writeln!(
+                out,
+                "mullvad_wireguard_transmitted_bytes_total {}",
+                stats.tx_bytes
+            );
+        }
+
+        header(
+            out,
+            "mullvad_relay_list_age_seconds",
+            "gauge",
+            "Time since the relay list was last updated",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_relay_list_age_seconds {}",
+            self.relay_list_age.as_secs()
+        );
+    }
+
+    fn render_api(&self, out: &mut String) {
+        header(
+            out,
+            "mullvad_api_request_duration_seconds",
+            "histogram",
+            "Latency of requests to the Mullvad API",
+        );
+        let mut cumulative_count = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(self.api.latency_counts.iter()) {
+            cumulative_count += count;
+            let _ = writeln!(
+                out,
+                "mullvad_api_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative_count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "mullvad_api_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.api.requests
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_api_request_duration_seconds_sum {}",
+            self.api.latency_sum.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_api_request_duration_seconds_count {}",
+            self.api.requests
+        );
+
+        header(
+            out,
+            "mullvad_api_request_errors_total",
+            "counter",
+            "Number of failed requests to the Mullvad API, by kind of failure",
+        );
+        for (kind, count) in &self.api.errors {
+            let _ = writeln!(
+                out,
+                "mullvad_api_request_errors_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+    }
+
+    fn render_key_rotation(&self, out: &mut String) {
+        header(
+            out,
+            "mullvad_wireguard_key_rotation_enabled",
+            "gauge",
+            "Whether automatic WireGuard key rotation is running",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_wireguard_key_rotation_enabled {}",
+            if self.key_rotation.enabled { 1 } else { 0 }
+        );
+
+        header(
+            out,
+            "mullvad_wireguard_key_rotation_interval_seconds",
+            "gauge",
+            "Age at which the WireGuard key is automatically replaced",
+        );
+        let _ = writeln!(
+            out,
+            "mullvad_wireguard_key_rotation_interval_seconds {}",
+            self.key_rotation.interval.as_secs()
+        );
+
+        if let Some(key_age) = self.key_rotation.key_age {
+            header(
+                out,
+                "mullvad_wireguard_key_age_seconds",
+                "gauge",
+                "Age of the WireGuard key of the current account",
+            );
+            let _ = writeln!(
+                out,
+                "mullvad_wireguard_key_age_seconds {}",
+                key_age.as_secs()
+            );
+        }
+
+        header(
+            out,
+            "mullvad_wireguard_key_events_total",
+            "counter",
+            "Number of WireGuard key generation and rotation results, by outcome",
+        );
+        for (event, count) in &self.key_rotation.events {
+            let _ = writeln!(
+                out,
+                "mullvad_wireguard_key_events_total{{event=\"{}\"}} {}",
+                event, count
+            );
+        }
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Starts serving metrics on `address` in a background thread. Only loopback addresses and
+/// Unix sockets are accepted, since the metrics reveal the state of the tunnel.
+pub fn spawn_server(
+    address: MetricsAddress,
+    command_sender: DaemonCommandSender,
+) -> Result<(), Error> {
+    match &address {
+        MetricsAddress::Tcp(socket_address) => {
+            if !socket_address.ip().is_loopback() {
+                return Err(Error::NotLoopback(*socket_address));
+            }
+            let listener = TcpListener::bind(socket_address)
+                .map_err(|error| Error::Listen(address.clone(), error))?;
+            thread::spawn(move || {
+                let timer = Timer::default();
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| {
+                        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+                        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+                        handle_connection(stream, &command_sender, &timer)
+                    });
+                    log_connection_error(result);
+                }
+            });
+        }
+        #[cfg(unix)]
+        MetricsAddress::Unix(path) => {
+            let _ = std::fs::remove_file(path);
+            let listener =
+                UnixListener::bind(path).map_err(|error| Error::Listen(address.clone(), error))?;
+            // Only the user running the daemon may scrape the metrics.
+            std::fs::set_permissions(path, PermissionsExt::from_mode(0o600))
+                .map_err(|error| Error::SetPermissions(path.display().to_string(), error))?;
+            thread::spawn(move || {
+                let timer = Timer::default();
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| {
+                        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+                        stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+                        handle_connection(stream, &command_sender, &timer)
+                    });
+                    log_connection_error(result);
+                }
+            });
+        }
+    }
+    log::info!("Metrics endpoint listening on {}", address);
+    Ok(())
+}
+
+fn log_connection_error(result: io::Result<()>) {
+    if let Err(error) = result {
+        log::debug!(
+            "{}",
+            error.display_chain_with_msg("Failed to serve metrics request")
+        );
+    }
+}
+
+/// Serves a single HTTP request. Only `GET /metrics` is supported.
+fn handle_connection(
+    mut stream: impl Read + Write,
+    command_sender: &DaemonCommandSender,
+    timer: &Timer,
+) -> io::Result<()> {
+    let request = read_request_head(&mut stream)?;
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line
+        .next()
+        .unwrap_or("")
+        .split('?')
+        .next()
+        .unwrap_or("");
+
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => match get_snapshot(command_sender, timer) {
+            Some(snapshot) => ("200 OK", snapshot.render()),
+            None => (
+                "503 Service Unavailable",
+                "The daemon did not respond\n".to_owned(),
+            ),
+        },
+        ("GET", _) => ("404 Not Found", "Not found\n".to_owned()),
+        _ => ("405 Method Not Allowed", "Method not allowed\n".to_owned()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn read_request_head(stream: &mut impl Read) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        if request.len() > MAX_REQUEST_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Request is too large",
+            ));
+        }
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Asks the daemon for the current metrics. Returns `None` if the daemon doesn't respond within
+/// `SNAPSHOT_TIMEOUT`, so that a busy daemon doesn't block the endpoint.
+fn get_snapshot(command_sender: &DaemonCommandSender, timer: &Timer) -> Option<MetricsSnapshot> {
+    let (tx, rx) = oneshot::channel();
+    command_sender.send(DaemonCommand::GetMetrics(tx)).ok()?;
+    let snapshot = rx.map_err(|_| ()).and_then(|snapshot| snapshot);
+    let timeout = timer
+        .sleep(SNAPSHOT_TIMEOUT)
+        .then(|_| Err::<MetricsSnapshot, ()>(()));
+    snapshot
+        .select(timeout)
+        .wait()
+        .ok()
+        .map(|(snapshot, _)| snapshot)
+}
@@ -12,6 +12,7 @@ use mullvad_types::{
         OpenVpnConstraints, RelayConstraints, TunnelProtocol, WireguardConstraints,
     },
     relay_list::{OpenVpnEndpointData, Relay, RelayList, RelayTunnels, WireguardEndpointData},
+    retry_strategy::{RetryAttempt, RetryStrategy},
 };
 use parking_lot::Mutex;
 use std::{
@@ -203,33 +204,103 @@ impl RelaySelector {
         self.parsed_relays.lock().locations().clone()
     }
 
-    /// Returns a random relay and relay endpoint matching the given constraints and with
-    /// preferences applied.
-    pub fn get_tunnel_endpoint(
-        &mut self,
+    /// Returns when the relay list in use was fetched from the API.
+    pub fn last_updated(&self) -> SystemTime {
+        self.parsed_relays.lock().last_updated()
+    }
+
+    /// Returns the addresses of all known relays, and the tunnel gateways of their WireGuard
+    /// endpoints.
+    pub fn get_relay_and_gateway_addresses(&self) -> (Vec<IpAddr>, Vec<IpAddr>) {
+        let parsed_relays = self.parsed_relays.lock();
+        let mut relay_addresses = vec![];
+        let mut gateway_addresses = vec![];
+        for relay in parsed_relays.relays() {
+            relay_addresses.push(IpAddr::from(relay.ipv4_addr_in));
+            if let Some(ipv6_addr_in) = relay.ipv6_addr_in {
+                relay_addresses.push(IpAddr::from(ipv6_addr_in));
+            }
+            for endpoint in &relay.tunnels.wireguard {
+                gateway_addresses.push(IpAddr::from(endpoint.ipv4_gateway));
+                gateway_addresses.push(IpAddr::from(endpoint.ipv6_gateway));
+            }
+        }
+        gateway_addresses.sort();
+        gateway_addresses.dedup();
+        (relay_addresses, gateway_addresses)
+    }
+
+    /// Returns the preferences from the retry strategy that apply to the given retry attempt.
+    /// Attempts that conflict with the relay constraints, the bridge state or the availability
+    /// of WireGuard are skipped.
+    pub fn get_retry_attempt(
+        &self,
         relay_constraints: &RelayConstraints,
         bridge_state: &BridgeState,
+        retry_strategy: &RetryStrategy,
         retry_attempt: u32,
         wg_key_exists: bool,
-    ) -> Result<(Relay, MullvadEndpoint), Error> {
-        let preferred_constraints = self.preferred_constraints(
+    ) -> Option<RetryAttempt> {
+        let wireguard_available = cfg!(not(target_os = "windows"))
+            && wg_key_exists
+            && self.location_supports_wireguard(&relay_constraints.location);
+
+        let attempt = Self::retry_attempt(
             relay_constraints,
             bridge_state,
+            retry_strategy,
             retry_attempt,
-            wg_key_exists,
+            wireguard_available,
         );
+        if let Some(ref attempt) = attempt {
+            debug!("Preferring {} for retry attempt {}", attempt, retry_attempt);
+        }
+        attempt
+    }
+
+    fn retry_attempt(
+        relay_constraints: &RelayConstraints,
+        bridge_state: &BridgeState,
+        retry_strategy: &RetryStrategy,
+        retry_attempt: u32,
+        wireguard_available: bool,
+    ) -> Option<RetryAttempt> {
+        if *bridge_state == BridgeState::On {
+            // Bridges are always used, and `preferred_constraints` selects the only tunnel type
+            // that they support.
+            return None;
+        }
+
+        retry_strategy
+            .schedule(&relay_constraints.tunnel_protocol, wireguard_available)
+            .attempt(retry_attempt, |attempt| {
+                match (&relay_constraints.tunnel_protocol, &attempt.tunnel_protocol) {
+                    (Constraint::Only(tunnel_protocol), attempt_protocol) => {
+                        tunnel_protocol == attempt_protocol
+                    }
+                    (Constraint::Any, TunnelProtocol::Wireguard) => wireguard_available,
+                    (Constraint::Any, TunnelProtocol::OpenVpn) => true,
+                }
+            })
+            .cloned()
+    }
+
+    /// Returns a random relay and relay endpoint matching the given constraints and with
+    /// preferences applied.
+    pub fn get_tunnel_endpoint(
+        &mut self,
+        relay_constraints: &RelayConstraints,
+        bridge_state: &BridgeState,
+        preferences: Option<&RetryAttempt>,
+    ) -> Result<(Relay, MullvadEndpoint), Error> {
+        let preferred_constraints =
+            Self::preferred_constraints(relay_constraints, bridge_state, preferences);
         if let Some((relay, endpoint)) = self.get_tunnel_endpoint_internal(&preferred_constraints) {
-            debug!(
-                "Relay matched on highest preference for retry attempt {}",
-                retry_attempt
-            );
+            debug!("Relay matched on highest preference");
             Ok((relay, endpoint))
         } else if let Some((relay, endpoint)) = self.get_tunnel_endpoint_internal(relay_constraints)
         {
-            debug!(
-                "Relay matched on second preference for retry attempt {}",
-                retry_attempt
-            );
+            debug!("Relay matched on second preference");
             Ok((relay, endpoint))
         } else {
             warn!("No relays matching {}", relay_constraints);
@@ -237,82 +308,48 @@ impl RelaySelector {
         }
     }
 
+    /// Applies the preferences of a retry attempt to the given constraints, without changing
+    /// any constraints that are explicitly specified.
     fn preferred_constraints(
-        &self,
         original_constraints: &RelayConstraints,
         bridge_state: &BridgeState,
-        retry_attempt: u32,
-        wg_key_exists: bool,
+        preferences: Option<&RetryAttempt>,
     ) -> RelayConstraints {
-        let (preferred_port, preferred_protocol, preferred_tunnel) =
-            if *bridge_state != BridgeState::On {
-                self.preferred_tunnel_constraints(
-                    retry_attempt,
-                    &original_constraints.location,
-                    wg_key_exists,
-                )
-            } else {
-                (
-                    Constraint::Any,
-                    TransportProtocol::Tcp,
-                    TunnelProtocol::OpenVpn,
-                )
-            };
-
+        let mut relay_constraints = original_constraints.clone();
 
-        let mut relay_constraints = RelayConstraints {
-            location: original_constraints.location.clone(),
-            tunnel_protocol: original_constraints.tunnel_protocol.clone(),
-            wireguard_constraints: original_constraints.wireguard_constraints,
-            ..Default::default()
-        };
-        // Highest priority preference. Where we prefer OpenVPN using UDP. But without changing
-        // any constraints that are explicitly specified.
-        match original_constraints.tunnel_protocol {
-            // If no tunnel protocol is selected, use preferred constraints
-            Constraint::Any => {
-                if original_constraints.openvpn_constraints.port.is_any()
-                    && original_constraints.openvpn_constraints.protocol.is_any()
-                    || *bridge_state == BridgeState::On
-                {
-                    relay_constraints.openvpn_constraints = OpenVpnConstraints {
-                        port: preferred_port,
-                        protocol: Constraint::Only(preferred_protocol),
-                    };
-                } else {
-                    relay_constraints.openvpn_constraints =
-                        original_constraints.openvpn_constraints;
+        if let Some(preferences) = preferences {
+            if relay_constraints.tunnel_protocol.is_any() {
+                relay_constraints.tunnel_protocol =
+                    Constraint::Only(preferences.tunnel_protocol.clone());
+            }
+            match preferences.tunnel_protocol {
+                TunnelProtocol::OpenVpn => {
+                    let openvpn_constraints = &mut relay_constraints.openvpn_constraints;
+                    if openvpn_constraints.port.is_any() && openvpn_constraints.protocol.is_any() {
+                        openvpn_constraints.port = preferences.port;
+                        openvpn_constraints.protocol = preferences.transport_protocol;
+                    }
                 }
-
-                if relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port = preferred_port;
+                TunnelProtocol::Wireguard => {
+                    let wireguard_constraints = &mut relay_constraints.wireguard_constraints;
+                    if wireguard_constraints.port.is_any()
+                        && wireguard_constraints.transport_protocol.is_any()
+                    {
+                        wireguard_constraints.port = preferences.port;
+                        wireguard_constraints.transport_protocol = preferences.transport_protocol;
+                    }
                 }
-
-                relay_constraints.tunnel_protocol = Constraint::Only(preferred_tunnel);
             }
-            Constraint::Only(TunnelProtocol::OpenVpn) => {
-                let openvpn_constraints = &mut relay_constraints.openvpn_constraints;
-                *openvpn_constraints = original_constraints.openvpn_constraints;
-                if *bridge_state == BridgeState::On && openvpn_constraints.protocol.is_any() {
-                    // FIXME: This is temporary while talpid-core only supports TCP proxies
-                    openvpn_constraints.protocol = Constraint::Only(TransportProtocol::Tcp);
-                } else if openvpn_constraints.port.is_any() && openvpn_constraints.protocol.is_any()
-                {
-                    let (preferred_port, preferred_protocol) =
-                        Self::preferred_openvpn_constraints(retry_attempt);
-                    openvpn_constraints.port = preferred_port;
-                    openvpn_constraints.protocol = Constraint::Only(preferred_protocol);
-                }
+        }
+
+        if *bridge_state == BridgeState::On {
+            if relay_constraints.tunnel_protocol.is_any() {
+                relay_constraints.tunnel_protocol = Constraint::Only(TunnelProtocol::OpenVpn);
             }
-            Constraint::Only(TunnelProtocol::Wireguard) => {
-                relay_constraints.wireguard_constraints =
-                    original_constraints.wireguard_constraints;
-                // This ensures that if after the first 2 failed attempts the daemon does not
-                // connect, then afterwards 2 of each 4 successive attempts will try to connect on
-                // port 53.
-                if retry_attempt % 4 > 1 && relay_constraints.wireguard_constraints.port.is_any() {
-                    relay_constraints.wireguard_constraints.port = Constraint::Only(53);
-                }
+            if relay_constraints.openvpn_constraints.protocol.is_any() {
+                // FIXME: This is temporary while talpid-core only supports TCP proxies
+                relay_constraints.openvpn_constraints.protocol =
+                    Constraint::Only(TransportProtocol::Tcp);
             }
         }
 
@@ -323,9 +360,9 @@ impl RelaySelector {
         &mut self,
         bridge_constraints: &InternalBridgeConstraints,
         location: &Location,
-        retry_attempt: u32,
+        preferences: Option<&RetryAttempt>,
     ) -> Option<(ProxySettings, Relay)> {
-        if !self.should_use_bridge(retry_attempt) {
+        if !Self::should_use_bridge(preferences) {
             return None;
         }
 
@@ -337,14 +374,10 @@ impl RelaySelector {
         self.get_proxy_settings(bridge_constraints, location)
     }
 
-    pub fn should_use_bridge(&self, retry_attempt: u32) -> bool {
-        // shouldn't use a bridge for the first 3 times
-        retry_attempt > 3 &&
-            // i.e. 4th and 5th with bridge, 6th & 7th without
-            // The test is to see whether the current _couple of connections_ is even or not.
-            // | retry_attempt                | 4 | 5 | 6 | 7 | 8 | 9 |
-            // | (retry_attempt % 4) < 2      | t | t | f | f | t | t |
-            (retry_attempt % 4) < 2
+    pub fn should_use_bridge(preferences: Option<&RetryAttempt>) -> bool {
+        preferences
+            .map(|preferences| preferences.use_bridge)
+            .unwrap_or(false)
     }
 
     pub fn get_proxy_settings(
@@ -374,73 +407,32 @@ impl RelaySelector {
         })
     }
 
-    /// Returns preferred constraints
-    #[allow(unused_variables)]
-    fn preferred_tunnel_constraints(
-        &self,
-        retry_attempt: u32,
-        location_constraint: &Constraint<LocationConstraint>,
-        wg_key_exists: bool,
-    ) -> (Constraint<u16>, TransportProtocol, TunnelProtocol) {
-        #[cfg(not(target_os = "windows"))]
-        {
-            let location_supports_wireguard =
-                self.parsed_relays.lock().relays().iter().any(|relay| {
-                    relay.active
-                        && !relay.tunnels.wireguard.is_empty()
-                        && Self::relay_matches_location(relay, &location_constraint)
-                });
-            // If location does not support WireGuard, defer to preferred OpenVPN tunnel
-            // constraints
-            if !location_supports_wireguard || !wg_key_exists {
-                let (preferred_port, preferred_protocol) =
-                    Self::preferred_openvpn_constraints(retry_attempt);
-                return (preferred_port, preferred_protocol, TunnelProtocol::OpenVpn);
-            }
-
-
-            // Try out WireGuard in the first two connection attempts, first with any port,
-            // afterwards on port 53. Afterwards, connect through OpenVPN alternating between UDP
-            // on any port twice and TCP on port 443 once.
-            match retry_attempt {
-                0 if location_supports_wireguard => (
-                    Constraint::Any,
-                    TransportProtocol::Udp,
-                    TunnelProtocol::Wireguard,
-                ),
-                1 => (
-                    Constraint::Only(53),
-                    TransportProtocol::Udp,
-                    TunnelProtocol::Wireguard,
-                ),
-                _ => {
-                    let (preferred_port, preferred_protocol) =
-                        Self::preferred_openvpn_constraints(retry_attempt - 2);
-                    (preferred_port, preferred_protocol, TunnelProtocol::OpenVpn)
-                }
-            }
-        }
-
-        #[cfg(target_os = "windows")]
-        {
-            let (preferred_port, preferred_protocol) =
-                Self::preferred_openvpn_constraints(retry_attempt);
-
+    /// Picks a random Shadowsocks bridge over TCP, for reaching the API on networks where it's
+    /// blocked.
+    pub fn get_api_bridge(&mut self) -> Option<ProxySettings> {
+        let constraints = InternalBridgeConstraints {
+            location: Constraint::Any,
+            transport_protocol: Constraint::Only(TransportProtocol::Tcp),
+        };
+        let matching_relays: Vec<Relay> = self
+            .parsed_relays
+            .lock()
+            .relays()
+            .iter()
+            .filter(|relay| relay.active)
+            .filter_map(|relay| Self::matching_bridge_relay(relay, &constraints))
+            .collect();
 
-            (preferred_port, preferred_protocol, TunnelProtocol::OpenVpn)
-        }
+        let relay = self.pick_random_relay(&matching_relays)?.clone();
+        self.pick_random_bridge(&relay)
     }
 
-    fn preferred_openvpn_constraints(retry_attempt: u32) -> (Constraint<u16>, TransportProtocol) {
-        // Prefer UDP by default. But if that has failed a couple of times, then try TCP port
-        // 443, which works for many with UDP problems. After that, just alternate
-        // between protocols.
-        match retry_attempt {
-            0 | 1 => (Constraint::Any, TransportProtocol::Udp),
-            2 | 3 => (Constraint::Only(443), TransportProtocol::Tcp),
-            attempt if attempt % 2 == 0 => (Constraint::Any, TransportProtocol::Udp),
-            _ => (Constraint::Any, TransportProtocol::Tcp),
-        }
+    fn location_supports_wireguard(&self, location: &Constraint<LocationConstraint>) -> bool {
+        self.parsed_relays.lock().relays().iter().any(|relay| {
+            relay.active
+                && !relay.tunnels.wireguard.is_empty()
+                && Self::relay_matches_location(relay, location)
+        })
     }
 
 
@@ -699,16 +691,24 @@ impl RelaySelector {
         data: WireguardEndpointData,
         constraints: WireguardConstraints,
     ) -> Option<MullvadEndpoint> {
-        let port = self.get_port_for_wireguard_relay(&data, constraints)?;
+        let (port, obfuscation) = if constraints.use_udp2tcp() {
+            let port = self.get_udp2tcp_port_for_wireguard_relay(&data, constraints)?;
+            (port, Some(wireguard::ObfuscationType::Udp2Tcp))
+        } else {
+            (self.get_port_for_wireguard_relay(&data, constraints)?, None)
+        };
         let peer_config = wireguard::PeerConfig {
             public_key: data.public_key,
             endpoint: SocketAddr::new(host, port),
             allowed_ips: all_of_the_internet(),
+            persistent_keepalive: None,
+            psk: None,
         };
         Some(MullvadEndpoint::Wireguard {
             peer: peer_config,
             ipv4_gateway: data.ipv4_gateway,
             ipv6_gateway: data.ipv6_gateway,
+            obfuscation,
         })
     }
 
@@ -752,6 +752,23 @@ impl RelaySelector {
         }
     }
 
+    fn get_udp2tcp_port_for_wireguard_relay(
+        &mut self,
+        data: &WireguardEndpointData,
+        constraints: WireguardConstraints,
+    ) -> Option<u16> {
+        match constraints.port {
+            Constraint::Any => data.udp2tcp_ports.choose(&mut self.rng).cloned(),
+            Constraint::Only(port) => {
+                if data.udp2tcp_ports.contains(&port) {
+                    Some(port)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
     /// Try to read the relays from disk, preferring the newer ones.
     fn read_relays_from_disk(
         cache_path: &Path,
@@ -894,3 +911,173 @@ impl RelayListUpdater {
         serde_json::to_writer_pretty(io::BufWriter::new(file), relays).map_err(Error::Serialize)
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Describes the tunnel that is preferred for each of the first `attempts` retry attempts.
+    fn schedule(
+        relay_constraints: &RelayConstraints,
+        bridge_state: BridgeState,
+        wireguard_available: bool,
+        attempts: u32,
+    ) -> Vec<String> {
+        let retry_strategy = RetryStrategy::default();
+        (0..attempts)
+            .map(|retry_attempt| {
+                let preferences = RelaySelector::retry_attempt(
+                    relay_constraints,
+                    &bridge_state,
+                    &retry_strategy,
+                    retry_attempt,
+                    wireguard_available,
+                );
+                let constraints = RelaySelector::preferred_constraints(
+                    relay_constraints,
+                    &bridge_state,
+                    preferences.as_ref(),
+                );
+                let mut description = match constraints.tunnel_protocol {
+                    Constraint::Only(TunnelProtocol::Wireguard) => {
+                        format!("WireGuard over {}", constraints.wireguard_constraints)
+                    }
+                    Constraint::Only(TunnelProtocol::OpenVpn) => {
+                        format!("OpenVPN over {}", constraints.openvpn_constraints)
+                    }
+                    Constraint::Any => "Any tunnel protocol".to_string(),
+                };
+                if bridge_state == BridgeState::Auto
+                    && RelaySelector::should_use_bridge(preferences.as_ref())
+                {
+                    description.push_str(" via bridge");
+                }
+                description
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_default_schedule() {
+        assert_eq!(
+            schedule(&RelayConstraints::default(), BridgeState::Auto, true, 14),
+            vec![
+                "WireGuard over any port over UDP",
+                "WireGuard over port 53 over UDP",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over port 443 over TCP via bridge",
+                "OpenVPN over port 443 over TCP via bridge",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over TCP",
+                "OpenVPN over any port over UDP via bridge",
+                "OpenVPN over any port over TCP via bridge",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over TCP",
+                "OpenVPN over any port over UDP via bridge",
+                "OpenVPN over any port over TCP via bridge",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schedule_without_wireguard() {
+        let expected = vec![
+            "OpenVPN over any port over UDP",
+            "OpenVPN over any port over UDP",
+            "OpenVPN over port 443 over TCP",
+            "OpenVPN over port 443 over TCP",
+            "OpenVPN over any port over UDP via bridge",
+            "OpenVPN over any port over TCP via bridge",
+            "OpenVPN over any port over UDP",
+            "OpenVPN over any port over TCP",
+            "OpenVPN over any port over UDP via bridge",
+            "OpenVPN over any port over TCP via bridge",
+            "OpenVPN over any port over UDP",
+            "OpenVPN over any port over TCP",
+        ];
+        assert_eq!(
+            schedule(&RelayConstraints::default(), BridgeState::Auto, false, 12),
+            expected
+        );
+
+        let openvpn_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelProtocol::OpenVpn),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(
+            schedule(&openvpn_constraints, BridgeState::Auto, true, 12),
+            expected
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "windows")]
+    fn test_default_schedule() {
+        assert_eq!(
+            schedule(&RelayConstraints::default(), BridgeState::Auto, false, 12),
+            vec![
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over port 443 over TCP",
+                "OpenVPN over port 443 over TCP",
+                "OpenVPN over any port over UDP via bridge",
+                "OpenVPN over any port over TCP via bridge",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over TCP",
+                "OpenVPN over any port over UDP via bridge",
+                "OpenVPN over any port over TCP via bridge",
+                "OpenVPN over any port over UDP",
+                "OpenVPN over any port over TCP",
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_os = "windows"))]
+    fn test_wireguard_schedule() {
+        let wireguard_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelProtocol::Wireguard),
+            ..RelayConstraints::default()
+        };
+        assert_eq!(
+            schedule(&wireguard_constraints, BridgeState::Auto, true, 8),
+            vec![
+                "WireGuard over any port over UDP",
+                "WireGuard over any port over UDP",
+                "WireGuard over port 53 over UDP",
+                "WireGuard over port 53 over UDP",
+                "WireGuard over any port over UDP",
+                "WireGuard over any port over UDP",
+                "WireGuard over port 53 over UDP",
+                "WireGuard over port 53 over UDP",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schedule_keeps_explicit_constraints() {
+        let relay_constraints = RelayConstraints {
+            tunnel_protocol: Constraint::Only(TunnelProtocol::OpenVpn),
+            openvpn_constraints: OpenVpnConstraints {
+                port: Constraint::Only(1194),
+                protocol: Constraint::Only(TransportProtocol::Udp),
+            },
+            ..RelayConstraints::default()
+        };
+        let schedule = schedule(&relay_constraints, BridgeState::Off, true, 8);
+        assert!(schedule
+            .iter()
+            .all(|attempt| attempt == "OpenVPN over port 1194 over UDP"));
+    }
+
+    #[test]
+    fn test_schedule_with_bridge() {
+        assert_eq!(
+            schedule(&RelayConstraints::default(), BridgeState::On, true, 4),
+            vec!["OpenVPN over any port over TCP"; 4]
+        );
+    }
+}
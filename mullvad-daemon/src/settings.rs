@@ -1,7 +1,12 @@
+use ipnetwork::IpNetwork;
 use log::{debug, error, info};
 use mullvad_types::{
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettingsUpdate},
+    api_access::ApiAccessMethod,
+    api_proxy::ApiProxy,
+    relay_constraints::{BridgeSettings, BridgeState, Constraint, RelaySettingsUpdate},
+    retry_strategy::RetryStrategy,
     settings::Settings,
+    wireguard::RotationPolicy,
 };
 use std::{
     fs::{self, File},
@@ -9,7 +14,10 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
 };
-use talpid_types::ErrorExt;
+use talpid_types::{
+    net::{openvpn, proxy::TunnelSocksProxy, GatewayOptions},
+    ErrorExt,
+};
 
 #[cfg(windows)]
 use talpid_core::logging::windows::log_sink;
@@ -66,6 +74,8 @@ impl SettingsPersister {
                 Self::update_field(&mut settings.tunnel_options.generic.enable_ipv6, true);
         }
 
+        should_save |= Self::check_bridge_settings(&mut settings);
+
         let mut persister = SettingsPersister { settings, path };
 
         if should_save {
@@ -80,6 +90,29 @@ impl SettingsPersister {
         persister
     }
 
+    /// Custom Shadowsocks bridges may use a stream cipher from before only AEAD ciphers were
+    /// supported. Since the cipher has to match the one used by the server, it can't be migrated
+    /// automatically. Such a bridge is removed from the settings, and the default bridge settings
+    /// are used instead. Returns true if the settings were changed.
+    fn check_bridge_settings(settings: &mut Settings) -> bool {
+        if let BridgeSettings::Custom(openvpn::ProxySettings::Shadowsocks(ref proxy)) =
+            settings.bridge_settings
+        {
+            if !openvpn::SHADOWSOCKS_CIPHERS.contains(&proxy.cipher.as_str()) {
+                error!(
+                    "Removing the custom Shadowsocks bridge {}, since its cipher {} is no longer \
+                     supported. Configure it again with one of the supported ciphers: {}",
+                    proxy.peer,
+                    proxy.cipher,
+                    openvpn::SHADOWSOCKS_CIPHERS.join(", ")
+                );
+                settings.bridge_settings = Settings::default().bridge_settings;
+                return true;
+            }
+        }
+        false
+    }
+
     fn load_settings(path: &Path) -> (Settings, bool) {
         Self::load_settings_from_file(path)
             .or_else(|error| match error {
@@ -182,6 +215,20 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    pub fn set_gateway(&mut self, gateway: Option<GatewayOptions>) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.gateway, gateway);
+        self.update(should_save)
+    }
+
+    pub fn set_tunnel_socks_proxy(
+        &mut self,
+        tunnel_socks_proxy: Option<TunnelSocksProxy>,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.tunnel_socks_proxy, tunnel_socks_proxy);
+        self.update(should_save)
+    }
+
     pub fn set_block_when_disconnected(
         &mut self,
         block_when_disconnected: bool,
@@ -206,6 +253,21 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    /// Sets or, if `value` is `None`, removes an extra OpenVPN option. The value is expected to
+    /// have been validated already.
+    pub fn set_openvpn_extra_option(
+        &mut self,
+        option: openvpn::ExtraOption,
+        value: Option<u32>,
+    ) -> Result<bool, Error> {
+        let extra_options = &mut self.settings.tunnel_options.openvpn.extra_options;
+        let should_save = match value {
+            Some(value) => extra_options.insert(option, value) != Some(value),
+            None => extra_options.remove(&option).is_some(),
+        };
+        self.update(should_save)
+    }
+
     pub fn set_enable_ipv6(&mut self, enable_ipv6: bool) -> Result<bool, Error> {
         let should_save = Self::update_field(
             &mut self.settings.tunnel_options.generic.enable_ipv6,
@@ -214,11 +276,41 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    pub fn set_excluded_destinations(
+        &mut self,
+        excluded_destinations: Vec<IpNetwork>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.generic.excluded_destinations,
+            excluded_destinations,
+        );
+        self.update(should_save)
+    }
+
     pub fn set_wireguard_mtu(&mut self, mtu: Option<u16>) -> Result<bool, Error> {
         let should_save = Self::update_field(&mut self.settings.tunnel_options.wireguard.mtu, mtu);
         self.update(should_save)
     }
 
+    pub fn set_wireguard_policy_routing(&mut self, enabled: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.wireguard.use_policy_routing,
+            enabled,
+        );
+        self.update(should_save)
+    }
+
+    pub fn set_wireguard_network_namespace(
+        &mut self,
+        network_namespace: Option<String>,
+    ) -> Result<bool, Error> {
+        let should_save = Self::update_field(
+            &mut self.settings.tunnel_options.wireguard.network_namespace,
+            network_namespace,
+        );
+        self.update(should_save)
+    }
+
     pub fn set_wireguard_rotation_interval(
         &mut self,
         automatic_rotation: Option<u32>,
@@ -230,12 +322,26 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    pub fn set_key_rotation_policy(
+        &mut self,
+        key_rotation_policy: RotationPolicy,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.key_rotation_policy, key_rotation_policy);
+        self.update(should_save)
+    }
+
     pub fn set_show_beta_releases(&mut self, show_beta_releases: bool) -> Result<bool, Error> {
         let should_save =
             Self::update_field(&mut self.settings.show_beta_releases, show_beta_releases);
         self.update(should_save)
     }
 
+    pub fn set_download_updates(&mut self, download_updates: bool) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.download_updates, download_updates);
+        self.update(should_save)
+    }
+
     pub fn set_bridge_settings(&mut self, bridge_settings: BridgeSettings) -> Result<bool, Error> {
         let should_save = Self::update_field(&mut self.settings.bridge_settings, bridge_settings);
         self.update(should_save)
@@ -246,6 +352,25 @@ impl SettingsPersister {
         self.update(should_save)
     }
 
+    pub fn set_retry_strategy(&mut self, retry_strategy: RetryStrategy) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.retry_strategy, retry_strategy);
+        self.update(should_save)
+    }
+
+    pub fn set_api_proxy(&mut self, api_proxy: Option<ApiProxy>) -> Result<bool, Error> {
+        let should_save = Self::update_field(&mut self.settings.api_proxy, api_proxy);
+        self.update(should_save)
+    }
+
+    pub fn set_api_access_method(
+        &mut self,
+        api_access_method: Constraint<ApiAccessMethod>,
+    ) -> Result<bool, Error> {
+        let should_save =
+            Self::update_field(&mut self.settings.api_access_method, api_access_method);
+        self.update(should_save)
+    }
+
     fn update_field<T: Eq>(field: &mut T, new_value: T) -> bool {
         if *field != new_value {
             *field = new_value;
@@ -322,3 +447,39 @@ mod ffi {
         ) -> WinUtilMigrationStatus;
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shadowsocks_bridge(cipher: &str) -> BridgeSettings {
+        BridgeSettings::Custom(openvpn::ProxySettings::Shadowsocks(
+            openvpn::ShadowsocksProxySettings {
+                peer: "192.0.2.1:443".parse().unwrap(),
+                password: "mullvad".to_string(),
+                cipher: cipher.to_string(),
+            },
+        ))
+    }
+
+    #[test]
+    fn test_removed_shadowsocks_cipher_is_rejected() {
+        let mut settings = Settings::default();
+        settings.bridge_settings = shadowsocks_bridge("rc4-md5");
+        assert!(SettingsPersister::check_bridge_settings(&mut settings));
+        assert_eq!(
+            settings.bridge_settings,
+            Settings::default().bridge_settings
+        );
+    }
+
+    #[test]
+    fn test_supported_shadowsocks_cipher_is_kept() {
+        let bridge_settings = shadowsocks_bridge("aes-256-gcm");
+        let mut settings = Settings::default();
+        settings.bridge_settings = bridge_settings.clone();
+        assert!(!SettingsPersister::check_bridge_settings(&mut settings));
+        assert_eq!(settings.bridge_settings, bridge_settings);
+    }
+}
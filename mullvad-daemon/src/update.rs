@@ -0,0 +1,683 @@
+//! Downloads new releases of the app in the background and verifies their signatures, so that
+//! they can be installed through the management interface on machines nobody is watching.
+//!
+//! Each installer is published with a detached Ed25519 signature, made by the build server when
+//! the release is uploaded (see `ci/buildserver-upload.sh`). The signed message is the version
+//! and the SHA-256 digest of the installer, so an installer can't be passed off as another
+//! release. The public key is not part of the source tree: release builds get it from the
+//! `MULLVAD_UPDATE_PUBLIC_KEY` environment variable at compile time, and builds without it never
+//! download updates.
+
+use crate::version::PRODUCT_VERSION;
+use futures::{future, Future};
+use mullvad_rpc::rest::{self, RequestServiceHandle, RestRequest};
+use mullvad_types::version::{AppVersion, PendingUpdate};
+use ring::{
+    digest,
+    signature::{UnparsedPublicKey, ED25519},
+};
+use std::{
+    env,
+    fmt::Write,
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+    time::Duration,
+};
+use talpid_types::ErrorExt;
+
+/// Where installers and their signatures are downloaded from.
+const RELEASE_URL: &str = "https://mullvad.net/media/app";
+/// Base64 encoded Ed25519 key that all installers must be signed with. Set by the release build.
+const SIGNING_KEY: Option<&str> = option_env!("MULLVAD_UPDATE_PUBLIC_KEY");
+
+const UPDATE_DIR: &str = "updates";
+const PENDING_UPDATE_FILENAME: &str = "pending-update.json";
+/// Extension of the detached, base64 encoded signature that is published next to each installer.
+const SIGNATURE_EXTENSION: &str = "sig";
+
+const INSTALLER_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Updates are not available for this platform")]
+    UnsupportedPlatform,
+
+    #[error(display = "This build has no key to verify updates with")]
+    NoSigningKey,
+
+    #[error(display = "Refusing to download malformed version \"{}\"", _0)]
+    InvalidVersion(AppVersion),
+
+    #[error(display = "Refusing to download version {} since it is not newer", _0)]
+    NotNewer(AppVersion),
+
+    #[error(display = "Failed to download {}", _0)]
+    Download(String, #[error(source)] rest::Error),
+
+    #[error(display = "The signature is not valid base64")]
+    ParseSignature(#[error(source)] base64::DecodeError),
+
+    #[error(display = "The installer is not signed by the release signing key")]
+    InvalidSignature,
+
+    #[error(display = "Failed to write the downloaded update to the cache")]
+    WriteUpdate(#[error(source)] io::Error),
+
+    #[error(display = "Failed to read the downloaded update")]
+    ReadUpdate(#[error(source)] io::Error),
+
+    #[error(display = "Failure in serialization of the pending update")]
+    Serialize(#[error(source)] serde_json::Error),
+
+    #[error(display = "No update has been downloaded")]
+    NoPendingUpdate,
+
+    #[error(display = "Failed to start the installer")]
+    RunInstaller(#[error(source)] io::Error),
+}
+
+/// Where releases are downloaded from, and the key they must be signed with.
+struct UpdateSource {
+    base_url: String,
+    signing_key: Vec<u8>,
+}
+
+impl UpdateSource {
+    /// Returns the official release source, or `None` if this build has no signing key. Debug
+    /// builds can download from a local stand-in instead, by setting `MULLVAD_UPDATE_URL` and
+    /// `MULLVAD_UPDATE_SIGNING_KEY`.
+    fn from_env() -> Option<Self> {
+        let signing_key = match Self::signing_key_override() {
+            Some(signing_key) => signing_key,
+            None => base64::decode(SIGNING_KEY?.trim())
+                .map_err(|_| log::error!("Invalid release signing key"))
+                .ok()?,
+        };
+        let base_url = Self::url_override().unwrap_or_else(|| RELEASE_URL.to_owned());
+        Some(UpdateSource {
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            signing_key,
+        })
+    }
+
+    #[cfg(debug_assertions)]
+    fn url_override() -> Option<String> {
+        let url = env::var("MULLVAD_UPDATE_URL").ok()?;
+        log::warn!("Downloading updates from {}", url);
+        Some(url)
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn url_override() -> Option<String> {
+        None
+    }
+
+    #[cfg(debug_assertions)]
+    fn signing_key_override() -> Option<Vec<u8>> {
+        let key = env::var("MULLVAD_UPDATE_SIGNING_KEY").ok()?;
+        match base64::decode(key.trim()) {
+            Ok(key) => Some(key),
+            Err(_) => {
+                log::error!("Ignoring malformed MULLVAD_UPDATE_SIGNING_KEY");
+                None
+            }
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    fn signing_key_override() -> Option<Vec<u8>> {
+        None
+    }
+
+    fn verify(&self, version: &str, installer: &[u8], signature: &[u8]) -> Result<(), Error> {
+        let signature = base64::decode(String::from_utf8_lossy(signature).trim())
+            .map_err(Error::ParseSignature)?;
+        UnparsedPublicKey::new(&ED25519, &self.signing_key)
+            .verify(&signed_message(version, installer), &signature)
+            .map_err(|_| Error::InvalidSignature)
+    }
+}
+
+/// Returns the message that is signed for a release: the version and the hex encoded SHA-256
+/// digest of the installer, each followed by a newline.
+fn signed_message(version: &str, installer: &[u8]) -> Vec<u8> {
+    let mut message = format!("{}\n", version);
+    for byte in digest::digest(&digest::SHA256, installer).as_ref() {
+        let _ = write!(message, "{:02x}", byte);
+    }
+    message.push('\n');
+    message.into_bytes()
+}
+
+pub(crate) struct UpdateDownloader {
+    service: RequestServiceHandle,
+    source: Option<Arc<UpdateSource>>,
+    update_dir: PathBuf,
+}
+
+impl UpdateDownloader {
+    pub fn new(service: RequestServiceHandle, cache_dir: &Path) -> Self {
+        let source = UpdateSource::from_env();
+        if source.is_none() {
+            log::info!("Updates will not be downloaded since this build has no signing key");
+        }
+        Self::with_source(service, source, cache_dir)
+    }
+
+    fn with_source(
+        service: RequestServiceHandle,
+        source: Option<UpdateSource>,
+        cache_dir: &Path,
+    ) -> Self {
+        UpdateDownloader {
+            service,
+            source: source.map(Arc::new),
+            update_dir: cache_dir.join(UPDATE_DIR),
+        }
+    }
+
+    /// Returns whether this build can verify, and thus download, updates.
+    pub fn is_available(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Downloads the installer of `version` for this platform and its signature. Both are stored
+    /// in the cache, replacing any earlier update, once the signature has been verified.
+    pub fn download(
+        &self,
+        version: AppVersion,
+    ) -> Box<dyn Future<Item = PendingUpdate, Error = Error> + Send> {
+        self.download_newer_than(version, PRODUCT_VERSION)
+    }
+
+    fn download_newer_than(
+        &self,
+        version: AppVersion,
+        current_version: &str,
+    ) -> Box<dyn Future<Item = PendingUpdate, Error = Error> + Send> {
+        let source = match &self.source {
+            Some(source) => source.clone(),
+            None => return Box::new(future::err(Error::NoSigningKey)),
+        };
+        if !is_valid_version(&version) {
+            return Box::new(future::err(Error::InvalidVersion(version)));
+        }
+        if !is_newer(&version, current_version) {
+            return Box::new(future::err(Error::NotNewer(version)));
+        }
+        let installer_name = match installer_name(&version) {
+            Some(installer_name) => installer_name,
+            None => return Box::new(future::err(Error::UnsupportedPlatform)),
+        };
+
+        let installer_url = format!("{}/{}", source.base_url, installer_name);
+        let signature_url = format!("{}.{}", installer_url, SIGNATURE_EXTENSION);
+        let update_dir = self.update_dir.clone();
+
+        let future = fetch(&self.service, installer_url)
+            .join(fetch(&self.service, signature_url))
+            .and_then(move |(installer, signature)| {
+                source.verify(&version, &installer, &signature)?;
+                store_update(
+                    &update_dir,
+                    version,
+                    &installer_name,
+                    &installer,
+                    &signature,
+                )
+            });
+        Box::new(future)
+    }
+
+    /// Loads the update stored by an earlier download, unless it is not newer than the running
+    /// version or its signature no longer matches.
+    pub fn load_pending(&self) -> Option<PendingUpdate> {
+        self.load_pending_newer_than(PRODUCT_VERSION)
+    }
+
+    fn load_pending_newer_than(&self, current_version: &str) -> Option<PendingUpdate> {
+        let path = self.update_dir.join(PENDING_UPDATE_FILENAME);
+        let update: PendingUpdate = match fs::read(&path) {
+            Ok(contents) => match serde_json::from_slice(&contents) {
+                Ok(update) => update,
+                Err(error) => {
+                    log::error!(
+                        "{}",
+                        Error::Serialize(error).display_chain_with_msg("Discarding pending update")
+                    );
+                    self.remove_update();
+                    return None;
+                }
+            },
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return None,
+            Err(error) => {
+                log::error!(
+                    "{}",
+                    Error::ReadUpdate(error)
+                        .display_chain_with_msg("Failed to load pending update")
+                );
+                return None;
+            }
+        };
+
+        if !is_newer(&update.version, current_version) {
+            log::debug!("Removing update {} since it is not newer", update.version);
+            self.remove_update();
+            return None;
+        }
+        if let Err(error) = self.verify(&update) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Discarding pending update")
+            );
+            self.remove_update();
+            return None;
+        }
+        Some(update)
+    }
+
+    /// Verifies the signature of the stored installer again and starts it. The installer stops
+    /// and replaces the running daemon.
+    pub fn apply(&self, update: &PendingUpdate) -> Result<(), Error> {
+        self.verify(update)?;
+        log::info!("Installing version {}", update.version);
+        installer_command(&update.path)?
+            .spawn()
+            .map(|_| ())
+            .map_err(Error::RunInstaller)
+    }
+
+    fn verify(&self, update: &PendingUpdate) -> Result<(), Error> {
+        let source = self.source.as_ref().ok_or(Error::NoSigningKey)?;
+        let installer = fs::read(&update.path).map_err(Error::ReadUpdate)?;
+        let signature = fs::read(signature_path(&update.path)).map_err(Error::ReadUpdate)?;
+        source.verify(&update.version, &installer, &signature)
+    }
+
+    fn remove_update(&self) {
+        if let Err(error) = fs::remove_dir_all(&self.update_dir) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove downloaded update")
+            );
+        }
+    }
+}
+
+fn fetch(
+    service: &RequestServiceHandle,
+    url: String,
+) -> impl Future<Item = Vec<u8>, Error = Error> {
+    let future_service = service.clone();
+    let future_url = url.clone();
+    let future = async move {
+        let mut request = RestRequest::get(&future_url)?;
+        request.set_timeout(INSTALLER_DOWNLOAD_TIMEOUT);
+        let response = future_service.request(request).await?;
+        let response = rest::parse_rest_response(response, rest::StatusCode::OK).await?;
+        rest::read_body(response).await
+    };
+    service
+        .compat_spawn(future)
+        .map_err(move |error| Error::Download(url, error))
+}
+
+fn store_update(
+    update_dir: &Path,
+    version: AppVersion,
+    installer_name: &str,
+    installer: &[u8],
+    signature: &[u8],
+) -> Result<PendingUpdate, Error> {
+    if let Err(error) = fs::remove_dir_all(update_dir) {
+        if error.kind() != io::ErrorKind::NotFound {
+            return Err(Error::WriteUpdate(error));
+        }
+    }
+    fs::create_dir_all(update_dir).map_err(Error::WriteUpdate)?;
+
+    let path = update_dir.join(installer_name);
+    fs::write(&path, installer).map_err(Error::WriteUpdate)?;
+    fs::write(signature_path(&path), signature).map_err(Error::WriteUpdate)?;
+
+    let update = PendingUpdate { version, path };
+    let contents = serde_json::to_vec_pretty(&update).map_err(Error::Serialize)?;
+    fs::write(update_dir.join(PENDING_UPDATE_FILENAME), contents).map_err(Error::WriteUpdate)?;
+    Ok(update)
+}
+
+fn signature_path(installer_path: &Path) -> PathBuf {
+    let mut path = installer_path.as_os_str().to_owned();
+    path.push(".");
+    path.push(SIGNATURE_EXTENSION);
+    PathBuf::from(path)
+}
+
+/// The version ends up in URLs and file names, so only allow characters that versions are made of.
+fn is_valid_version(version: &str) -> bool {
+    !version.is_empty()
+        && !version.starts_with('.')
+        && version
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+/// Returns whether `version` is a later release than the running version.
+pub fn is_newer_version(version: &str) -> bool {
+    is_newer(version, PRODUCT_VERSION)
+}
+
+/// Returns whether `version` is a later release than `current_version`. Versions that can't be
+/// parsed are never newer, so development builds don't replace themselves.
+fn is_newer(version: &str, current_version: &str) -> bool {
+    match (parse_version(version), parse_version(current_version)) {
+        (Some(version), Some(current_version)) => version > current_version,
+        _ => false,
+    }
+}
+
+/// Parses a version like `2020.5` or `2020.5-beta1` into a tuple that sorts in release order.
+/// Betas sort before the release they precede.
+fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = version.splitn(2, '-');
+    let mut release = parts.next()?.split('.');
+    let year = parse_number(release.next()?)?;
+    let number = parse_number(release.next()?)?;
+    if release.next().is_some() {
+        return None;
+    }
+    let beta = match parts.next() {
+        Some(suffix) if suffix.starts_with("beta") => parse_number(&suffix["beta".len()..])?,
+        Some(_) => return None,
+        None => u32::max_value(),
+    };
+    Some((year, number, beta))
+}
+
+fn parse_number(number: &str) -> Option<u32> {
+    if number.is_empty() || !number.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+#[cfg(target_os = "linux")]
+fn installer_name(version: &str) -> Option<String> {
+    let uses_dpkg = Path::new("/usr/bin/dpkg").exists();
+    let (arch, extension) = match (env::consts::ARCH, uses_dpkg) {
+        ("x86_64", true) => ("amd64", "deb"),
+        ("x86_64", false) => ("x86_64", "rpm"),
+        ("aarch64", true) => ("arm64", "deb"),
+        ("aarch64", false) => ("aarch64", "rpm"),
+        _ => return None,
+    };
+    Some(format!("MullvadVPN-{}_{}.{}", version, arch, extension))
+}
+
+#[cfg(target_os = "macos")]
+fn installer_name(version: &str) -> Option<String> {
+    Some(format!("MullvadVPN-{}.pkg", version))
+}
+
+#[cfg(windows)]
+fn installer_name(version: &str) -> Option<String> {
+    Some(format!("MullvadVPN-{}.exe", version))
+}
+
+#[cfg(target_os = "android")]
+fn installer_name(_version: &str) -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn installer_command(path: &Path) -> Result<Command, Error> {
+    // The package scripts stop the daemon service, which would also kill the installer if it ran
+    // in the same unit. So it is started in a transient unit of its own.
+    let mut command = Command::new("systemd-run");
+    command.args(&["--collect", "--unit=mullvad-update", "--"]);
+    if path.extension().map(|extension| extension == "deb") == Some(true) {
+        command.args(&["dpkg", "-i"]);
+    } else {
+        command.args(&["rpm", "-U"]);
+    }
+    command.arg(path);
+    Ok(command)
+}
+
+#[cfg(target_os = "macos")]
+fn installer_command(path: &Path) -> Result<Command, Error> {
+    let mut command = Command::new("/usr/sbin/installer");
+    command.arg("-pkg").arg(path).args(&["-target", "/"]);
+    Ok(command)
+}
+
+#[cfg(windows)]
+fn installer_command(path: &Path) -> Result<Command, Error> {
+    let mut command = Command::new(path);
+    command.arg("/S");
+    Ok(command)
+}
+
+#[cfg(target_os = "android")]
+fn installer_command(_path: &Path) -> Result<Command, Error> {
+    Err(Error::UnsupportedPlatform)
+}
+
+#[cfg(all(test, not(target_os = "android")))]
+mod tests {
+    use super::*;
+    use ring::{
+        rand::SystemRandom,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+    use std::{
+        collections::HashMap,
+        io::{BufRead, BufReader, Write},
+        net::{Ipv4Addr, SocketAddr, TcpListener},
+        thread,
+    };
+
+    fn generate_key_pair() -> Ed25519KeyPair {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+        Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap()
+    }
+
+    fn source(key_pair: &Ed25519KeyPair, base_url: String) -> UpdateSource {
+        UpdateSource {
+            base_url,
+            signing_key: key_pair.public_key().as_ref().to_vec(),
+        }
+    }
+
+    fn sign(key_pair: &Ed25519KeyPair, version: &str, installer: &[u8]) -> Vec<u8> {
+        base64::encode(key_pair.sign(&signed_message(version, installer)).as_ref()).into_bytes()
+    }
+
+    /// Serves `files` over plain HTTP on a loopback address, as a stand-in for the release server.
+    fn serve(files: HashMap<String, Vec<u8>>) -> SocketAddr {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut request_line = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                if reader.read_line(&mut request_line).is_err() {
+                    continue;
+                }
+                let mut header = String::new();
+                while reader
+                    .read_line(&mut header)
+                    .map(|n| n > 2)
+                    .unwrap_or(false)
+                {
+                    header.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or("");
+                let (status, body) = match files.get(path) {
+                    Some(body) => ("200 OK", &body[..]),
+                    None => ("404 Not Found", &[][..]),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        address
+    }
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("mullvad-update-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_version_order() {
+        assert!(is_newer("2020.5", "2020.4"));
+        assert!(is_newer("2020.10", "2020.9"));
+        assert!(is_newer("2021.1", "2020.9"));
+        assert!(is_newer("2020.5-beta1", "2020.4"));
+        assert!(is_newer("2020.5-beta2", "2020.5-beta1"));
+        assert!(is_newer("2020.5", "2020.5-beta2"));
+
+        assert!(!is_newer("2020.5", "2020.5"));
+        assert!(!is_newer("2020.4", "2020.5"));
+        assert!(!is_newer("2020.5-beta1", "2020.5"));
+        assert!(!is_newer("2020.4", "2020.5-beta1"));
+        assert!(!is_newer("2019.9", "2020.1"));
+    }
+
+    #[test]
+    fn test_malformed_versions_are_never_newer() {
+        for version in &[
+            "",
+            "2021",
+            "2021.",
+            "2021.1.1",
+            "2021.1-dev",
+            "2021.+1",
+            "2021.1-beta",
+        ] {
+            assert!(!is_newer(version, "2020.5"), "{} is newer", version);
+            assert!(!is_newer("2021.1", version), "{} is older", version);
+        }
+    }
+
+    #[test]
+    fn test_signature() {
+        let key_pair = generate_key_pair();
+        let source = source(&key_pair, String::new());
+        let installer = b"installer for 2020.6";
+        let signature = sign(&key_pair, "2020.6", installer);
+
+        assert!(source.verify("2020.6", installer, &signature).is_ok());
+        assert!(matches!(
+            source.verify("2020.6", b"tampered installer", &signature),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            source.verify("2020.7", installer, &signature),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            source.verify("2020.6", installer, b"not base64!"),
+            Err(Error::ParseSignature(_))
+        ));
+
+        let other_signature = sign(&generate_key_pair(), "2020.6", installer);
+        assert!(matches!(
+            source.verify("2020.6", installer, &other_signature),
+            Err(Error::InvalidSignature)
+        ));
+    }
+
+    #[test]
+    fn test_download() {
+        let key_pair = generate_key_pair();
+        let installer = b"installer for 2020.6".to_vec();
+        let old_installer = b"installer for 2020.4".to_vec();
+        let name = installer_name("2020.6").unwrap();
+        let replayed_name = installer_name("2020.7").unwrap();
+
+        let mut files = HashMap::new();
+        files.insert(format!("/{}", name), installer.clone());
+        files.insert(
+            format!("/{}.{}", name, SIGNATURE_EXTENSION),
+            sign(&key_pair, "2020.6", &installer),
+        );
+        // An old installer and its valid signature, published as a newer version
+        files.insert(format!("/{}", replayed_name), old_installer.clone());
+        files.insert(
+            format!("/{}.{}", replayed_name, SIGNATURE_EXTENSION),
+            sign(&key_pair, "2020.4", &old_installer),
+        );
+        let address = serve(files);
+
+        let mut runtime = mullvad_rpc::MullvadRpcRuntime::new().unwrap();
+        let cache_dir = cache_dir("download");
+        let downloader = UpdateDownloader::with_source(
+            runtime.rest_handle(),
+            Some(source(&key_pair, format!("http://{}", address))),
+            &cache_dir,
+        );
+
+        assert!(matches!(
+            downloader
+                .download_newer_than("2020.5".to_owned(), "2020.5")
+                .wait(),
+            Err(Error::NotNewer(_))
+        ));
+        assert!(matches!(
+            downloader
+                .download_newer_than("2020.7".to_owned(), "2020.5")
+                .wait(),
+            Err(Error::InvalidSignature)
+        ));
+        assert!(matches!(
+            downloader
+                .download_newer_than("2020.8".to_owned(), "2020.5")
+                .wait(),
+            Err(Error::Download(..))
+        ));
+        assert_eq!(downloader.load_pending_newer_than("2020.5"), None);
+
+        let update = downloader
+            .download_newer_than("2020.6".to_owned(), "2020.5")
+            .wait()
+            .unwrap();
+        assert_eq!(update.version, "2020.6");
+        assert_eq!(fs::read(&update.path).unwrap(), installer);
+        assert_eq!(downloader.load_pending_newer_than("2020.5"), Some(update));
+
+        // Discarded once the update, or a later version, is installed
+        assert_eq!(downloader.load_pending_newer_than("2020.6"), None);
+        assert!(!cache_dir.join(UPDATE_DIR).exists());
+
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[test]
+    fn test_download_without_signing_key() {
+        let mut runtime = mullvad_rpc::MullvadRpcRuntime::new().unwrap();
+        let downloader =
+            UpdateDownloader::with_source(runtime.rest_handle(), None, &cache_dir("no-key"));
+        assert!(!downloader.is_available());
+        assert!(matches!(
+            downloader
+                .download_newer_than("2020.6".to_owned(), "2020.5")
+                .wait(),
+            Err(Error::NoSigningKey)
+        ));
+    }
+}
@@ -33,12 +33,128 @@ pub enum Error {
     RestError(#[error(source)] mullvad_rpc::rest::Error),
     #[error(display = "Account already has maximum number of keys")]
     TooManyKeys,
+    #[error(display = "The key is not registered on the account")]
+    KeyNotFound,
+    #[error(display = "No account token is set")]
+    NoAccountToken,
     #[error(display = "Failed to create rotation timer")]
     RotationScheduleError(#[error(source)] tokio_timer::TimerError),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// State of an automatic key rotation.
+pub(crate) enum KeyRotation {
+    /// A new key is being pushed to the account.
+    Pushing(AccountToken),
+    /// The new key is used for new tunnels, but the old key is still registered on the account in
+    /// case the rotation has to be rolled back.
+    GracePeriod {
+        account: AccountToken,
+        old_data: WireguardData,
+        new_key: PublicKey,
+        /// Whether a tunnel has been set up with the new key during the current grace period.
+        new_key_used: bool,
+    },
+}
+
+/// What to do with a key rotation in its grace period after something happened to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RotationAction {
+    /// Remove the old key, since the new key works.
+    Complete,
+    /// Use the old key again and remove the new key.
+    RollBack,
+    /// Start a new grace period, since no tunnel has tried to use the new key yet.
+    ExtendGracePeriod,
+    /// Keep waiting.
+    Wait,
+}
+
+impl KeyRotation {
+    /// Starts the grace period of a rotation whose new key has been pushed.
+    pub fn grace_period(
+        account: AccountToken,
+        old_data: WireguardData,
+        new_key: PublicKey,
+    ) -> Self {
+        KeyRotation::GracePeriod {
+            account,
+            old_data,
+            new_key,
+            new_key_used: false,
+        }
+    }
+
+    fn is_new_key(&self, key: &talpid_types::net::wireguard::PublicKey) -> bool {
+        match self {
+            KeyRotation::GracePeriod { new_key, .. } => new_key.key == *key,
+            KeyRotation::Pushing(_) => false,
+        }
+    }
+
+    /// Called when a tunnel is set up with `key`.
+    pub fn key_used(&mut self, key: &talpid_types::net::wireguard::PublicKey) {
+        if let KeyRotation::GracePeriod {
+            new_key,
+            new_key_used,
+            ..
+        } = self
+        {
+            if new_key.key == *key {
+                *new_key_used = true;
+            }
+        }
+    }
+
+    /// Called when a tunnel using `key` has completed a handshake.
+    pub fn handshake_completed(
+        &self,
+        key: &talpid_types::net::wireguard::PublicKey,
+    ) -> RotationAction {
+        if self.is_new_key(key) {
+            RotationAction::Complete
+        } else {
+            RotationAction::Wait
+        }
+    }
+
+    /// Called when the API has reported whether `key` is registered on the account.
+    pub fn verified(
+        &self,
+        key: &talpid_types::net::wireguard::PublicKey,
+        is_valid: bool,
+    ) -> RotationAction {
+        if self.is_new_key(key) && !is_valid {
+            RotationAction::RollBack
+        } else {
+            RotationAction::Wait
+        }
+    }
+
+    /// Called when the grace period for `key` is over. Rotations are completed as soon as the new
+    /// key has been used for a handshake, so if a tunnel tried to use the new key it did not work.
+    pub fn grace_period_over(
+        &self,
+        key: &talpid_types::net::wireguard::PublicKey,
+    ) -> RotationAction {
+        match self {
+            KeyRotation::GracePeriod {
+                new_key,
+                new_key_used,
+                ..
+            } if new_key.key == *key => {
+                if *new_key_used {
+                    RotationAction::RollBack
+                } else {
+                    RotationAction::ExtendGracePeriod
+                }
+            }
+            _ => RotationAction::Wait,
+        }
+    }
+}
+
 pub struct KeyManager {
     daemon_tx: DaemonEventSender,
     http_handle: MullvadRestHandle,
@@ -100,6 +216,16 @@ impl KeyManager {
         self.reset_rotation(account_history, account_token);
     }
 
+    /// Returns true if automatic key rotation is scheduled
+    pub fn is_rotation_running(&self) -> bool {
+        self.abort_scheduler_tx.is_some()
+    }
+
+    /// Returns the key age after which automatic rotation replaces the key
+    pub fn rotation_interval(&self) -> Duration {
+        self.auto_rotation_interval
+    }
+
     /// Stop current key generation
     pub fn reset(&mut self) {
         if let Some(job) = self.current_job.take() {
@@ -166,6 +292,84 @@ impl KeyManager {
             })
     }
 
+    /// Pushes a new key to the account without removing any of the keys that are already
+    /// registered.
+    pub fn push_new_key(
+        &self,
+        account: AccountToken,
+    ) -> impl Future<Item = WireguardData, Error = Error> {
+        let mut push_future = self.push_future_generator(account, PrivateKey::new_from_random());
+        push_future().map_err(Self::map_rpc_error)
+    }
+
+    /// Returns a future that resolves once the grace period of a key rotation is over.
+    pub fn grace_period_timer(
+        &self,
+        grace_period: Duration,
+    ) -> impl Future<Item = (), Error = Error> {
+        tokio_timer::wheel()
+            .build()
+            .sleep(grace_period)
+            .map_err(Error::RotationScheduleError)
+    }
+
+    /// Lists all keys that are registered on the account.
+    pub fn list_keys(
+        &self,
+        account: AccountToken,
+    ) -> impl Future<Item = Vec<RegisteredKey>, Error = Error> {
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        rpc.list_wireguard_keys(account)
+            .map_err(Self::map_rpc_error)
+    }
+
+    /// Removes a key from the account. Tunnels using the key stop working.
+    pub fn revoke_key(
+        &self,
+        account: AccountToken,
+        key: talpid_types::net::wireguard::PublicKey,
+    ) -> impl Future<Item = (), Error = Error> {
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        rpc.remove_wireguard_key(account, &key)
+            .map_err(|err| match err {
+                mullvad_rpc::rest::Error::ApiError(status, _code)
+                    if status == mullvad_rpc::StatusCode::NOT_FOUND =>
+                {
+                    Error::KeyNotFound
+                }
+                err => Self::map_rpc_error(err),
+            })
+    }
+
+    /// Makes an existing private key usable with the account. Keys that are already registered on
+    /// the account, for example by another device, keep their addresses. Other keys are pushed.
+    pub fn import_key(
+        &self,
+        account: AccountToken,
+        private_key: PrivateKey,
+    ) -> impl Future<Item = WireguardData, Error = Error> {
+        let mut rpc = mullvad_rpc::WireguardKeyProxy::new(self.http_handle.clone());
+        let public_key = private_key.public_key();
+        rpc.get_wireguard_key(account.clone(), &public_key)
+            .then(
+                move |response| -> Box<dyn Future<Item = _, Error = _> + Send> {
+                    match response {
+                        Err(mullvad_rpc::rest::Error::ApiError(status, _code))
+                            if status == mullvad_rpc::StatusCode::NOT_FOUND =>
+                        {
+                            Box::new(rpc.push_wg_key(account, public_key))
+                        }
+                        response => Box::new(futures::future::result(response)),
+                    }
+                },
+            )
+            .map_err(Self::map_rpc_error)
+            .map(move |addresses| WireguardData {
+                private_key,
+                addresses,
+                created: Utc::now(),
+            })
+    }
 
     /// Generate a new private key asynchronously. The new keys will be sent to the daemon channel.
     pub fn generate_key_async(&mut self, account: AccountToken) -> Result<()> {
@@ -306,83 +510,34 @@ impl KeyManager {
             .for_each(|_| Ok(()))
     }
 
-    fn next_automatic_rotation(
-        daemon_tx: DaemonEventSender,
-        http_handle: MullvadRestHandle,
-        public_key: PublicKey,
-        rotation_interval_secs: u64,
-        account_token: AccountToken,
-    ) -> impl Future<Item = PublicKey, Error = Error> + Send {
-        let expiration_timer =
-            Self::create_rotation_check(public_key.clone(), rotation_interval_secs);
-        let account_token_copy = account_token.clone();
-
-        expiration_timer
-            .and_then(move |_| {
-                log::info!("Replacing WireGuard key");
-
-                let private_key = PrivateKey::new_from_random();
-                Self::replace_key_rpc(http_handle, account_token, public_key, private_key)
-            })
-            .then(move |rpc_result| {
-                match rpc_result {
-                    Ok(data) => {
-                        // Update account data
-                        let _ = daemon_tx.send(InternalDaemonEvent::WgKeyEvent((
-                            account_token_copy,
-                            Ok(data.clone()),
-                        )));
-                        Ok(data.get_public_key())
-                    }
-                    Err(Error::TooManyKeys) => {
-                        let _ = daemon_tx.send(InternalDaemonEvent::WgKeyEvent((
-                            account_token_copy,
-                            Err(Error::TooManyKeys),
-                        )));
-                        Err(Error::TooManyKeys)
-                    }
-                    Err(unknown_err) => Err(unknown_err),
-                }
-            })
-    }
-
+    /// Waits until the key is older than the rotation interval and then tells the daemon that the
+    /// key is due for rotation. The daemon is reminded every `AUTOMATIC_ROTATION_RETRY_DELAY`
+    /// until the job is restarted with a new key.
     fn create_automatic_rotation(
         daemon_tx: DaemonEventSender,
-        http_handle: MullvadRestHandle,
         public_key: PublicKey,
         rotation_interval_secs: u64,
         account_token: AccountToken,
     ) -> impl Future<Item = (), Error = ()> + Send {
-        tokio_timer::wheel()
-            .build()
-            .interval(AUTOMATIC_ROTATION_RETRY_DELAY)
-            .map_err(Error::RotationScheduleError)
-            .fold(public_key, move |old_public_key, _| {
-                let fut = Self::next_automatic_rotation(
-                    daemon_tx.clone(),
-                    http_handle.clone(),
-                    old_public_key.clone(),
-                    rotation_interval_secs,
-                    account_token.clone(),
-                );
-                fut.then(|result| match result {
-                    Ok(new_public_key) => Ok(new_public_key),
-                    Err(Error::TooManyKeys) => {
-                        log::error!("Account has too many keys, stopping automatic rotation");
-                        Err(Error::TooManyKeys)
-                    }
-                    Err(e) => {
-                        log::error!(
-                            "{}. Retrying in {} seconds",
-                            e.display_chain_with_msg("Key rotation failed:"),
-                            AUTOMATIC_ROTATION_RETRY_DELAY.as_secs(),
-                        );
-                        Ok(old_public_key)
-                    }
-                })
+        Self::create_rotation_check(public_key, rotation_interval_secs)
+            .and_then(move |_| {
+                let _ = daemon_tx.send(InternalDaemonEvent::KeyRotationDue(account_token.clone()));
+                tokio_timer::wheel()
+                    .build()
+                    .interval(AUTOMATIC_ROTATION_RETRY_DELAY)
+                    .map_err(Error::RotationScheduleError)
+                    .for_each(move |_| {
+                        let _ = daemon_tx
+                            .send(InternalDaemonEvent::KeyRotationDue(account_token.clone()));
+                        Ok(())
+                    })
+            })
+            .map_err(|e| {
+                log::error!(
+                    "{}",
+                    e.display_chain_with_msg("Automatic key rotation failed")
+                )
             })
-            .map_err(|_| ())
-            .map(|_| ())
     }
 
     fn run_automatic_rotation(&mut self, account_token: AccountToken, public_key: PublicKey) {
@@ -397,7 +552,6 @@ impl KeyManager {
         // Schedule cancellable series of repeating rotation tasks
         let fut = Self::create_automatic_rotation(
             self.daemon_tx.clone(),
-            self.http_handle.clone(),
             public_key,
             self.auto_rotation_interval.as_secs(),
             account_token,
@@ -410,7 +564,7 @@ impl KeyManager {
         self.abort_scheduler_tx = Some(cancel_handle);
     }
 
-    fn stop_automatic_rotation(&mut self) {
+    pub fn stop_automatic_rotation(&mut self) {
         if let Some(cancel_handle) = self.abort_scheduler_tx.take() {
             log::info!("Stopping automatic key rotation");
             cancel_handle.cancel();
@@ -468,3 +622,87 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rotation() -> (KeyRotation, talpid_types::net::wireguard::PublicKey) {
+        let old_data = WireguardData {
+            private_key: PrivateKey::new_from_random(),
+            addresses: AssociatedAddresses {
+                ipv4_address: "10.64.0.1/32".parse().unwrap(),
+                ipv6_address: "fc00:bbbb:bbbb:bb01::1/128".parse().unwrap(),
+            },
+            created: Utc::now(),
+        };
+        let new_key = PrivateKey::new_from_random().public_key();
+        let rotation = KeyRotation::grace_period(
+            "1111".to_string(),
+            old_data,
+            PublicKey {
+                key: new_key.clone(),
+                created: Utc::now(),
+            },
+        );
+        (rotation, new_key)
+    }
+
+    #[test]
+    fn test_complete_after_handshake_with_new_key() {
+        let (mut rotation, new_key) = rotation();
+        let old_key = PrivateKey::new_from_random().public_key();
+
+        assert_eq!(rotation.handshake_completed(&old_key), RotationAction::Wait);
+        rotation.key_used(&new_key);
+        assert_eq!(
+            rotation.handshake_completed(&new_key),
+            RotationAction::Complete
+        );
+    }
+
+    #[test]
+    fn test_grace_period_extended_while_new_key_unused() {
+        let (mut rotation, new_key) = rotation();
+
+        // The tunnel was down or only tried the old key during the grace period
+        rotation.key_used(&PrivateKey::new_from_random().public_key());
+        assert_eq!(
+            rotation.grace_period_over(&new_key),
+            RotationAction::ExtendGracePeriod
+        );
+    }
+
+    #[test]
+    fn test_roll_back_when_new_key_fails() {
+        let (mut rotation, new_key) = rotation();
+
+        rotation.key_used(&new_key);
+        assert_eq!(
+            rotation.grace_period_over(&new_key),
+            RotationAction::RollBack
+        );
+    }
+
+    #[test]
+    fn test_verification() {
+        let (rotation, new_key) = rotation();
+        let other_key = PrivateKey::new_from_random().public_key();
+
+        assert_eq!(rotation.verified(&new_key, true), RotationAction::Wait);
+        assert_eq!(rotation.verified(&other_key, false), RotationAction::Wait);
+        assert_eq!(rotation.verified(&new_key, false), RotationAction::RollBack);
+    }
+
+    #[test]
+    fn test_events_for_stale_keys_are_ignored() {
+        let (rotation, _) = rotation();
+        let stale_key = PrivateKey::new_from_random().public_key();
+
+        assert_eq!(rotation.grace_period_over(&stale_key), RotationAction::Wait);
+        assert_eq!(
+            KeyRotation::Pushing("1111".to_string()).grace_period_over(&stale_key),
+            RotationAction::Wait
+        );
+    }
+}
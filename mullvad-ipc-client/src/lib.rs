@@ -1,17 +1,25 @@
 #![deny(rust_2018_idioms)]
 
 use futures::sync::oneshot;
+use ipnetwork::IpNetwork;
 use jsonrpc_client_core::{Client, ClientHandle, Future};
 use jsonrpc_client_ipc::IpcTransport;
 use mullvad_types::{
-    account::{AccountData, AccountToken, VoucherSubmission},
+    account::{AccountData, AccountHistoryEntry, AccountToken, VoucherSubmission},
+    api_access::ApiAccessMethod,
+    api_proxy::ApiProxy,
+    diagnostics::Diagnostics,
     location::GeoIpLocation,
-    relay_constraints::{BridgeSettings, BridgeState, RelaySettings, RelaySettingsUpdate},
+    log_level::LogFilter,
+    relay_constraints::{
+        BridgeSettings, BridgeState, Constraint, RelaySettings, RelaySettingsUpdate,
+    },
     relay_list::RelayList,
+    retry_strategy::RetryStrategy,
     settings::{Settings, TunnelOptions},
     states::TunnelState,
-    version::AppVersionInfo,
-    wireguard, DaemonEvent,
+    version::{AppVersionInfo, PendingUpdate},
+    wireguard, ConfigDirective, CustomTunnelConfigFile, DaemonEvent,
 };
 use serde::{Deserialize, Serialize};
 use std::{io, path::Path, thread};
@@ -123,10 +131,28 @@ impl DaemonRpcClient {
         self.call("set_allow_lan", &[allow_lan])
     }
 
+    pub fn set_gateway(
+        &mut self,
+        gateway: Option<talpid_types::net::GatewayOptions>,
+    ) -> Result<()> {
+        self.call("set_gateway", &[gateway])
+    }
+
+    pub fn set_tunnel_socks_proxy(
+        &mut self,
+        proxy: Option<talpid_types::net::proxy::TunnelSocksProxy>,
+    ) -> Result<()> {
+        self.call("set_tunnel_socks_proxy", &[proxy])
+    }
+
     pub fn set_show_beta_releases(&mut self, enabled: bool) -> Result<()> {
         self.call("set_show_beta_releases", &[enabled])
     }
 
+    pub fn set_download_updates(&mut self, enabled: bool) -> Result<()> {
+        self.call("set_download_updates", &[enabled])
+    }
+
     pub fn set_block_when_disconnected(&mut self, block_when_disconnected: bool) -> Result<()> {
         self.call("set_block_when_disconnected", &[block_when_disconnected])
     }
@@ -187,14 +213,68 @@ impl DaemonRpcClient {
         self.call("verify_wireguard_key", &NO_ARGS)
     }
 
+    pub fn list_wireguard_keys(&mut self) -> Result<Vec<wireguard::RegisteredKey>> {
+        self.call("list_wireguard_keys", &NO_ARGS)
+    }
+
+    pub fn revoke_wireguard_key(
+        &mut self,
+        public_key: talpid_types::net::wireguard::PublicKey,
+    ) -> Result<()> {
+        self.call("revoke_wireguard_key", &[public_key])
+    }
+
+    pub fn import_wireguard_key(
+        &mut self,
+        private_key: talpid_types::net::wireguard::PrivateKey,
+    ) -> Result<wireguard::PublicKey> {
+        self.call("import_wireguard_key", &[private_key])
+    }
+
+    pub fn get_diagnostics(&mut self) -> Result<Diagnostics> {
+        self.call("get_diagnostics", &NO_ARGS)
+    }
+
+    pub fn set_log_level(&mut self, log_filter: LogFilter) -> Result<()> {
+        self.call("set_log_level", &[log_filter])
+    }
+
+    pub fn get_log_level(&mut self) -> Result<LogFilter> {
+        self.call("get_log_level", &NO_ARGS)
+    }
+
     pub fn get_version_info(&mut self) -> Result<AppVersionInfo> {
         self.call("get_version_info", &NO_ARGS)
     }
 
+    pub fn get_pending_update(&mut self) -> Result<Option<PendingUpdate>> {
+        self.call("get_pending_update", &NO_ARGS)
+    }
+
+    pub fn apply_update(&mut self) -> Result<()> {
+        self.call("apply_update", &NO_ARGS)
+    }
+
     pub fn set_account(&mut self, account: Option<AccountToken>) -> Result<()> {
         self.call("set_account", &[account])
     }
 
+    pub fn list_accounts(&mut self) -> Result<Vec<AccountHistoryEntry>> {
+        self.call("list_accounts", &NO_ARGS)
+    }
+
+    pub fn set_account_label(
+        &mut self,
+        account: AccountToken,
+        label: Option<String>,
+    ) -> Result<()> {
+        self.call("set_account_label", &(account, label))
+    }
+
+    pub fn switch_account(&mut self, label: String) -> Result<()> {
+        self.call("switch_account", &[label])
+    }
+
     pub fn set_enable_ipv6(&mut self, enabled: bool) -> Result<()> {
         self.call("set_enable_ipv6", &[enabled])
     }
@@ -203,14 +283,56 @@ impl DaemonRpcClient {
         self.call("set_wireguard_mtu", &[mtu])
     }
 
+    pub fn set_wireguard_policy_routing(&mut self, enabled: bool) -> Result<()> {
+        self.call("set_wireguard_policy_routing", &[enabled])
+    }
+
+    pub fn set_excluded_destinations(&mut self, destinations: Vec<IpNetwork>) -> Result<()> {
+        self.call("set_excluded_destinations", &[destinations])
+    }
+
+    pub fn set_wireguard_network_namespace(
+        &mut self,
+        network_namespace: Option<String>,
+    ) -> Result<()> {
+        self.call("set_wireguard_network_namespace", &[network_namespace])
+    }
+
     pub fn set_wireguard_rotation_interval(&mut self, interval: Option<u32>) -> Result<()> {
         self.call("set_wireguard_rotation_interval", &[interval])
     }
 
+    pub fn set_key_rotation_policy(&mut self, policy: wireguard::RotationPolicy) -> Result<()> {
+        self.call("set_key_rotation_policy", &[policy])
+    }
+
+    pub fn set_retry_strategy(&mut self, retry_strategy: RetryStrategy) -> Result<()> {
+        self.call("set_retry_strategy", &[retry_strategy])
+    }
+
+    pub fn set_api_proxy(&mut self, api_proxy: Option<ApiProxy>) -> Result<()> {
+        self.call("set_api_proxy", &[api_proxy])
+    }
+
+    pub fn set_api_access_method(
+        &mut self,
+        api_access_method: Constraint<ApiAccessMethod>,
+    ) -> Result<()> {
+        self.call("set_api_access_method", &[api_access_method])
+    }
+
     pub fn set_openvpn_mssfix(&mut self, mssfix: Option<u16>) -> Result<()> {
         self.call("set_openvpn_mssfix", &[mssfix])
     }
 
+    pub fn set_openvpn_extra_option(
+        &mut self,
+        option: talpid_types::net::openvpn::ExtraOption,
+        value: Option<u32>,
+    ) -> Result<()> {
+        self.call("set_openvpn_extra_option", &(option, value))
+    }
+
     pub fn set_bridge_settings(&mut self, settings: BridgeSettings) -> Result<()> {
         self.call("set_bridge_settings", &[settings])
     }
@@ -235,6 +357,17 @@ impl DaemonRpcClient {
         self.call("update_relay_settings", &[update])
     }
 
+    pub fn import_custom_tunnel(
+        &mut self,
+        config_file: CustomTunnelConfigFile,
+    ) -> Result<Vec<ConfigDirective>> {
+        self.call("import_custom_tunnel", &[config_file])
+    }
+
+    pub fn export_wireguard_config(&mut self) -> Result<String> {
+        self.call("export_wireguard_config", &NO_ARGS)
+    }
+
     pub fn call<A, O>(&mut self, method: &'static str, args: &A) -> Result<O>
     where
         A: Serialize + Send + 'static,
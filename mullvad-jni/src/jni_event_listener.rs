@@ -7,8 +7,11 @@ use jnix::{
 };
 use mullvad_daemon::EventListener;
 use mullvad_types::{
-    relay_list::RelayList, settings::Settings, states::TunnelState, version::AppVersionInfo,
-    wireguard::KeygenEvent,
+    relay_list::RelayList,
+    settings::Settings,
+    states::TunnelState,
+    version::AppVersionInfo,
+    wireguard::{KeyRotationEvent, KeygenEvent},
 };
 use std::{sync::mpsc, thread};
 use talpid_types::ErrorExt;
@@ -63,6 +66,10 @@ impl EventListener for JniEventListener {
     fn notify_app_version(&self, app_version_info: AppVersionInfo) {
         let _ = self.0.send(Event::AppVersionInfo(app_version_info));
     }
+
+    fn notify_key_rotation_event(&self, _rotation_event: KeyRotationEvent) {
+        // The app learns about new keys through key events
+    }
 }
 
 struct JniEventHandler<'env> {
@@ -141,8 +141,14 @@ fn start_logging(log_dir: &Path) -> Result<(), String> {
 fn initialize_logging(log_dir: &Path) -> Result<(), String> {
     let log_file = log_dir.join(LOG_FILENAME);
 
-    logging::init_logger(log::LevelFilter::Debug, Some(&log_file), true)
-        .map_err(|error| error.display_chain_with_msg("Failed to start logger"))?;
+    logging::init_logger(
+        log::LevelFilter::Debug,
+        Some(&log_file),
+        true,
+        logging::LogFormat::Text,
+        false,
+    )
+    .map_err(|error| error.display_chain_with_msg("Failed to start logger"))?;
     exception_logging::enable();
     log_panics::init();
     version::log_version();
@@ -887,7 +893,14 @@ pub extern "system" fn Java_net_mullvad_mullvadvpn_dataproxy_MullvadProblemRepor
     let output_path_string = String::from_java(&env, outputPath);
     let output_path = Path::new(&output_path_string);
 
-    match mullvad_problem_report::collect_report(&[], output_path, Vec::new(), log_dir) {
+    match mullvad_problem_report::collect_report(
+        &[],
+        output_path,
+        Vec::new(),
+        Vec::new(),
+        mullvad_problem_report::ReportFormat::Text,
+        log_dir,
+    ) {
         Ok(()) => JNI_TRUE,
         Err(error) => {
             log::error!(
@@ -0,0 +1,431 @@
+//! A local stand-in for the Mullvad API. Serves the REST endpoints used by `mullvad-rpc` over plain
+//! HTTP on a loopback address, so that the daemon can be tested end-to-end without reaching the
+//! real API. Accounts, keys, vouchers and the relay list are kept in memory, and failures can be
+//! scripted per route.
+//!
+//! A debug build of the daemon is pointed at the server by setting `MULLVAD_API_ADDR` to
+//! [`MockApiServer::address`].
+
+#![deny(rust_2018_idioms)]
+
+use chrono::{offset::Utc, DateTime};
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+use hyper::service::{make_service_fn, service_fn};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    convert::Infallible,
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+pub use hyper::StatusCode;
+
+mod relay_list;
+mod routes;
+pub use crate::routes::Route;
+
+/// The number of keys an account may have unless changed with [`MockApiServer::set_key_limit`].
+pub const DEFAULT_KEY_LIMIT: usize = 5;
+
+#[derive(err_derive::Error, Debug)]
+#[error(no_from)]
+pub enum Error {
+    #[error(display = "Failed to create a tokio runtime for the mock API server")]
+    RuntimeError(#[error(source)] io::Error),
+
+    #[error(display = "Failed to bind the mock API server")]
+    BindError(#[error(source)] hyper::Error),
+}
+
+/// How a request to a route should fail instead of being handled.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Failure {
+    /// Respond with a status code and an API error code, like the real API does.
+    Api(StatusCode, String),
+    /// Respond with a status code and an empty body.
+    Status(StatusCode),
+    /// Wait before handling the request as usual. Useful to trigger client timeouts.
+    Delay(Duration),
+}
+
+/// A request that was received by the server.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    /// The route the request matched, if any.
+    pub route: Option<Route>,
+    /// The account token the request was authenticated with, if any.
+    pub account: Option<String>,
+}
+
+/// A problem report that was submitted to the server.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize)]
+pub struct ProblemReport {
+    pub address: String,
+    pub message: String,
+    pub log: String,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// The response to version checks.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct VersionInfo {
+    pub supported: bool,
+    pub latest: String,
+    pub latest_stable: Option<String>,
+    pub latest_beta: String,
+}
+
+impl Default for VersionInfo {
+    fn default() -> Self {
+        VersionInfo {
+            supported: true,
+            latest: "2020.5".to_owned(),
+            latest_stable: Some("2020.5".to_owned()),
+            latest_beta: "2020.5".to_owned(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Account {
+    expiry: DateTime<Utc>,
+    keys: Vec<RegisteredKey>,
+}
+
+#[derive(Clone, Debug)]
+struct RegisteredKey {
+    pubkey: String,
+    index: u32,
+}
+
+#[derive(Debug)]
+struct Voucher {
+    seconds: u64,
+    used: bool,
+}
+
+#[derive(Debug)]
+struct State {
+    accounts: HashMap<String, Account>,
+    vouchers: HashMap<String, Voucher>,
+    key_limit: usize,
+    next_account_number: u64,
+    next_key_index: u32,
+    version_info: VersionInfo,
+    relay_list: serde_json::Value,
+    failures: HashMap<Route, VecDeque<Failure>>,
+    outage: bool,
+    requests: Vec<RecordedRequest>,
+    problem_reports: Vec<ProblemReport>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            accounts: HashMap::new(),
+            vouchers: HashMap::new(),
+            key_limit: DEFAULT_KEY_LIMIT,
+            next_account_number: 1,
+            next_key_index: 0,
+            version_info: VersionInfo::default(),
+            relay_list: relay_list::default_relay_list(),
+            failures: HashMap::new(),
+            outage: false,
+            requests: Vec::new(),
+            problem_reports: Vec::new(),
+        }
+    }
+}
+
+/// A mock API server running on its own thread. The server is stopped when this is dropped.
+pub struct MockApiServer {
+    address: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+    server_thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MockApiServer {
+    /// Starts a server listening on a random port on the loopback interface.
+    pub fn spawn() -> Result<Self, Error> {
+        let state = Arc::new(Mutex::new(State::default()));
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .map_err(Error::RuntimeError)?;
+
+        let service_state = state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    routes::handle_request(state.clone(), request)
+                }))
+            }
+        });
+
+        let bind_address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0);
+        let server = runtime
+            .enter(|| hyper::Server::try_bind(&bind_address))
+            .map_err(Error::BindError)?
+            .serve(make_service);
+        let address = server.local_addr();
+
+        // Open connections are spawned on the runtime, so they are closed when it is dropped at
+        // the end of the thread, rather than waited for.
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let server_thread = thread::spawn(move || {
+            runtime.block_on(async move {
+                if let Either::Left((Err(error), _)) =
+                    future::select(Box::pin(server), shutdown_rx).await
+                {
+                    eprintln!("Mock API server failed: {}", error);
+                }
+            })
+        });
+
+        Ok(MockApiServer {
+            address,
+            state,
+            shutdown_tx: Some(shutdown_tx),
+            server_thread: Some(server_thread),
+        })
+    }
+
+    /// Returns the address the server is listening on.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Adds an account, or changes the expiry of an existing one.
+    pub fn add_account(&self, token: &str, expiry: DateTime<Utc>) {
+        self.state()
+            .accounts
+            .entry(token.to_owned())
+            .or_insert_with(|| Account {
+                expiry,
+                keys: Vec::new(),
+            })
+            .expiry = expiry;
+    }
+
+    /// Returns the expiry of an account, if it exists.
+    pub fn account_expiry(&self, token: &str) -> Option<DateTime<Utc>> {
+        self.state()
+            .accounts
+            .get(token)
+            .map(|account| account.expiry)
+    }
+
+    /// Returns the base64 encoded WireGuard keys registered on an account, oldest first.
+    pub fn wireguard_keys(&self, token: &str) -> Vec<String> {
+        self.state()
+            .accounts
+            .get(token)
+            .map(|account| account.keys.iter().map(|key| key.pubkey.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Sets how many WireGuard keys each account may have.
+    pub fn set_key_limit(&self, limit: usize) {
+        self.state().key_limit = limit;
+    }
+
+    /// Adds a voucher that adds the given amount of time to an account when submitted.
+    pub fn add_voucher(&self, code: &str, time: Duration) {
+        self.state().vouchers.insert(
+            code.to_owned(),
+            Voucher {
+                seconds: time.as_secs(),
+                used: false,
+            },
+        );
+    }
+
+    /// Sets the response to version checks.
+    pub fn set_version_info(&self, version_info: VersionInfo) {
+        self.state().version_info = version_info;
+    }
+
+    /// Sets the relay list, in the format used by the API.
+    pub fn set_relay_list(&self, relay_list: serde_json::Value) {
+        self.state().relay_list = relay_list;
+    }
+
+    /// Makes the next `count` requests to `route` fail with `failure`. Failures added for the same
+    /// route are used in the order they were added.
+    pub fn fail_next(&self, route: Route, failure: Failure, count: usize) {
+        let mut state = self.state();
+        let failures = state.failures.entry(route).or_default();
+        for _ in 0..count {
+            failures.push_back(failure.clone());
+        }
+    }
+
+    /// Removes all scripted failures that have not been used yet.
+    pub fn clear_failures(&self) {
+        self.state().failures.clear();
+    }
+
+    /// Simulates an outage. While enabled, every request is answered with
+    /// `503 Service Unavailable`.
+    pub fn set_outage(&self, outage: bool) {
+        self.state().outage = outage;
+    }
+
+    /// Returns all requests received so far, in order.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.state().requests.clone()
+    }
+
+    /// Returns the number of requests received so far that matched `route`.
+    pub fn request_count(&self, route: Route) -> usize {
+        self.state()
+            .requests
+            .iter()
+            .filter(|request| request.route == Some(route))
+            .count()
+    }
+
+    /// Returns all problem reports submitted so far.
+    pub fn problem_reports(&self) -> Vec<ProblemReport> {
+        self.state().problem_reports.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("Mock API server state lock poisoned")
+    }
+}
+
+impl Drop for MockApiServer {
+    fn drop(&mut self) {
+        if let Some(shutdown_tx) = self.shutdown_tx.take() {
+            let _ = shutdown_tx.send(());
+        }
+        if let Some(server_thread) = self.server_thread.take() {
+            let _ = server_thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hyper::{header, Body, Method, Request};
+    use serde_json::json;
+
+    fn send(
+        server: &MockApiServer,
+        method: Method,
+        path: &str,
+        account: Option<&str>,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let mut runtime = tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        let mut request = Request::builder().method(method).uri(format!(
+            "http://{}/app/v1{}",
+            server.address(),
+            path
+        ));
+        if let Some(account) = account {
+            request = request.header(header::AUTHORIZATION, format!("Token {}", account));
+        }
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+        let request = request.body(Body::from(body)).unwrap();
+
+        runtime.block_on(async move {
+            let response = hyper::Client::new().request(request).await.unwrap();
+            let status = response.status();
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            let body = serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null);
+            (status, body)
+        })
+    }
+
+    #[test]
+    fn test_key_limit() {
+        let server = MockApiServer::spawn().unwrap();
+        server.add_account("1234", Utc::now());
+        server.set_key_limit(1);
+
+        let push = |pubkey| {
+            send(
+                &server,
+                Method::POST,
+                "/wireguard-keys",
+                Some("1234"),
+                Some(json!({ "pubkey": pubkey })),
+            )
+        };
+        assert_eq!(push("first").0, StatusCode::CREATED);
+        assert_eq!(
+            push("second"),
+            (
+                StatusCode::BAD_REQUEST,
+                json!({ "code": "KEY_LIMIT_REACHED" })
+            )
+        );
+
+        let (status, _) = send(
+            &server,
+            Method::POST,
+            "/replace-wireguard-key",
+            Some("1234"),
+            Some(json!({ "old": "first", "new": "second" })),
+        );
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(server.wireguard_keys("1234"), vec!["second".to_owned()]);
+    }
+
+    #[test]
+    fn test_scripted_failures() {
+        let server = MockApiServer::spawn().unwrap();
+        server.add_account("1234", Utc::now());
+        server.fail_next(
+            Route::GetAccount,
+            Failure::Api(StatusCode::INTERNAL_SERVER_ERROR, "MOCK_ERROR".to_owned()),
+            1,
+        );
+
+        let (status, body) = send(&server, Method::GET, "/me", Some("1234"), None);
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!({ "code": "MOCK_ERROR" }));
+
+        let (status, body) = send(&server, Method::GET, "/me", Some("1234"), None);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["token"], "1234");
+        assert_eq!(server.request_count(Route::GetAccount), 2);
+    }
+
+    #[test]
+    fn test_outage_and_unknown_account() {
+        let server = MockApiServer::spawn().unwrap();
+
+        let (status, _) = send(&server, Method::GET, "/me", Some("1234"), None);
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        server.set_outage(true);
+        let (status, _) = send(&server, Method::GET, "/relays", None, None);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        server.set_outage(false);
+        let (status, _) = send(&server, Method::GET, "/relays", None, None);
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,39 @@
+use serde_json::json;
+
+/// Returns a relay list with a single OpenVPN relay in Gothenburg, matching the relay list that
+/// `mullvad-tests` places in the resource directory of the daemon.
+pub fn default_relay_list() -> serde_json::Value {
+    json!({
+        "locations": {
+            "se-got": {
+                "city": "Gothenburg",
+                "country": "Sweden",
+                "latitude": 57.70887,
+                "longitude": 11.97456
+            }
+        },
+        "openvpn": {
+            "ports": [ { "port": 1000, "protocol": "udp" } ],
+            "relays": [{
+                "hostname": "fakehost",
+                "active": true,
+                "owned": true,
+                "location": "se-got",
+                "provider": "Mock",
+                "ipv4_addr_in": "192.168.0.100",
+                "weight": 100,
+                "include_in_country": true
+            }]
+        },
+        "wireguard": {
+            "port_ranges": [ [53, 53] ],
+            "ipv4_gateway": "10.64.0.1",
+            "ipv6_gateway": "fc00:bbbb:bbbb:bb01::1",
+            "relays": []
+        },
+        "bridge": {
+            "shadowsocks": [],
+            "relays": []
+        }
+    })
+}
@@ -0,0 +1,331 @@
+use crate::{Account, Failure, ProblemReport, RecordedRequest, RegisteredKey, State};
+use chrono::{offset::Utc, Duration as ChronoDuration};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use serde_json::json;
+use std::{
+    convert::Infallible,
+    net::{Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+};
+
+/// All paths served by the mock start with this, like the paths of the real API.
+const PATH_PREFIX: &str = "/app/v1";
+
+const INVALID_ACCOUNT: &str = "INVALID_ACCOUNT";
+const INVALID_VOUCHER: &str = "INVALID_VOUCHER";
+const VOUCHER_USED: &str = "VOUCHER_USED";
+const MISSING_ARGUMENT: &str = "MISSING_ARGUMENT";
+const KEY_LIMIT_REACHED: &str = "KEY_LIMIT_REACHED";
+const SERVICE_UNAVAILABLE: &str = "SERVICE_UNAVAILABLE";
+
+/// An endpoint of the API, used to script failures and inspect requests.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Route {
+    /// `GET /v1/me`
+    GetAccount,
+    /// `POST /v1/accounts`
+    CreateAccount,
+    /// `POST /v1/submit-voucher`
+    SubmitVoucher,
+    /// `POST /v1/www-auth-token`
+    WwwAuthToken,
+    /// `POST /v1/wireguard-keys`
+    PushWireguardKey,
+    /// `GET /v1/wireguard-keys`
+    ListWireguardKeys,
+    /// `GET /v1/wireguard-keys/{pubkey}`
+    GetWireguardKey,
+    /// `DELETE /v1/wireguard-keys/{pubkey}`
+    RemoveWireguardKey,
+    /// `POST /v1/replace-wireguard-key`
+    ReplaceWireguardKey,
+    /// `GET /v1/releases/{platform}/{version}`
+    AppVersion,
+    /// `POST /v1/problem-report`
+    ProblemReport,
+    /// `GET /v1/relays`
+    RelayList,
+}
+
+impl Route {
+    /// Finds the route for a request, along with the public key in the path of key routes.
+    fn parse(method: &Method, path: &str) -> Option<(Route, Option<String>)> {
+        let path = path.strip_prefix(PATH_PREFIX)?;
+        let route = match (method, path) {
+            (&Method::GET, "/me") => Route::GetAccount,
+            (&Method::POST, "/accounts") => Route::CreateAccount,
+            (&Method::POST, "/submit-voucher") => Route::SubmitVoucher,
+            (&Method::POST, "/www-auth-token") => Route::WwwAuthToken,
+            (&Method::POST, "/wireguard-keys") => Route::PushWireguardKey,
+            (&Method::GET, "/wireguard-keys") => Route::ListWireguardKeys,
+            (&Method::POST, "/replace-wireguard-key") => Route::ReplaceWireguardKey,
+            (&Method::POST, "/problem-report") => Route::ProblemReport,
+            (&Method::GET, "/relays") => Route::RelayList,
+            (&Method::GET, path) if path.starts_with("/releases/") => Route::AppVersion,
+            (method, path) => {
+                let pubkey = path.strip_prefix("/wireguard-keys/")?;
+                let pubkey = urlencoding::decode(pubkey).ok()?;
+                return match *method {
+                    Method::GET => Some((Route::GetWireguardKey, Some(pubkey))),
+                    Method::DELETE => Some((Route::RemoveWireguardKey, Some(pubkey))),
+                    _ => None,
+                };
+            }
+        };
+        Some((route, None))
+    }
+}
+
+pub(crate) async fn handle_request(
+    shared_state: Arc<Mutex<State>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let parsed_route = Route::parse(request.method(), request.uri().path());
+    let route = parsed_route.as_ref().map(|(route, _)| *route);
+    let account = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Token "))
+        .map(str::to_owned);
+
+    let failure = {
+        let mut state = lock(&shared_state);
+        state.requests.push(RecordedRequest {
+            method: request.method().to_string(),
+            path: request.uri().path().to_owned(),
+            route,
+            account: account.clone(),
+        });
+
+        if state.outage {
+            Some(Failure::Api(
+                StatusCode::SERVICE_UNAVAILABLE,
+                SERVICE_UNAVAILABLE.to_owned(),
+            ))
+        } else {
+            route.and_then(|route| {
+                state
+                    .failures
+                    .get_mut(&route)
+                    .and_then(|failures| failures.pop_front())
+            })
+        }
+    };
+
+    match failure {
+        Some(Failure::Api(status, code)) => return Ok(error_response(status, &code)),
+        Some(Failure::Status(status)) => return Ok(empty_response(status)),
+        Some(Failure::Delay(delay)) => tokio::time::delay_for(delay).await,
+        None => (),
+    }
+
+    let (route, pubkey) = match parsed_route {
+        Some(parsed_route) => parsed_route,
+        None => return Ok(empty_response(StatusCode::NOT_FOUND)),
+    };
+    let body = match hyper::body::to_bytes(request.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return Ok(empty_response(StatusCode::BAD_REQUEST)),
+    };
+
+    let mut state = lock(&shared_state);
+    let response = match route {
+        Route::CreateAccount => create_account(&mut state),
+        Route::AppVersion => json_response(StatusCode::OK, &state.version_info),
+        Route::RelayList => json_response(StatusCode::OK, &state.relay_list),
+        Route::ProblemReport => match serde_json::from_slice::<ProblemReport>(&body) {
+            Ok(report) => {
+                state.problem_reports.push(report);
+                empty_response(StatusCode::NO_CONTENT)
+            }
+            Err(_) => error_response(StatusCode::BAD_REQUEST, MISSING_ARGUMENT),
+        },
+        route => {
+            let token = match account {
+                Some(ref token) if state.accounts.contains_key(token) => token.clone(),
+                _ => return Ok(error_response(StatusCode::UNAUTHORIZED, INVALID_ACCOUNT)),
+            };
+            handle_account_request(&mut state, route, &token, pubkey, &body)
+        }
+    };
+    Ok(response)
+}
+
+/// Handles requests to routes that require an existing account.
+fn handle_account_request(
+    state: &mut State,
+    route: Route,
+    token: &str,
+    pubkey: Option<String>,
+    body: &[u8],
+) -> Response<Body> {
+    match route {
+        Route::GetAccount => {
+            let expiry = state.accounts[token].expiry;
+            json_response(
+                StatusCode::OK,
+                &json!({ "token": token, "expires": expiry }),
+            )
+        }
+        Route::SubmitVoucher => submit_voucher(state, token, body),
+        Route::WwwAuthToken => json_response(
+            StatusCode::OK,
+            &json!({ "auth_token": format!("mock-auth-token-{}", token) }),
+        ),
+        Route::PushWireguardKey => match json_field(body, "pubkey") {
+            Some(pubkey) => push_key(state, token, pubkey, None),
+            None => error_response(StatusCode::BAD_REQUEST, MISSING_ARGUMENT),
+        },
+        Route::ReplaceWireguardKey => match (json_field(body, "old"), json_field(body, "new")) {
+            (Some(old), Some(new)) => push_key(state, token, new, Some(old)),
+            _ => error_response(StatusCode::BAD_REQUEST, MISSING_ARGUMENT),
+        },
+        Route::ListWireguardKeys => {
+            let keys: Vec<_> = state.accounts[token].keys.iter().map(key_json).collect();
+            json_response(StatusCode::OK, &keys)
+        }
+        Route::GetWireguardKey => {
+            let pubkey = pubkey.unwrap_or_default();
+            match state.accounts[token]
+                .keys
+                .iter()
+                .find(|key| key.pubkey == pubkey)
+            {
+                Some(key) => json_response(StatusCode::OK, &key_json(key)),
+                None => empty_response(StatusCode::NOT_FOUND),
+            }
+        }
+        Route::RemoveWireguardKey => {
+            let pubkey = pubkey.unwrap_or_default();
+            let keys = &mut state.accounts.get_mut(token).unwrap().keys;
+            match keys.iter().position(|key| key.pubkey == pubkey) {
+                Some(index) => {
+                    keys.remove(index);
+                    empty_response(StatusCode::NO_CONTENT)
+                }
+                None => empty_response(StatusCode::NOT_FOUND),
+            }
+        }
+        Route::CreateAccount | Route::AppVersion | Route::RelayList | Route::ProblemReport => {
+            unreachable!("Route does not require an account")
+        }
+    }
+}
+
+fn create_account(state: &mut State) -> Response<Body> {
+    let token = format!("{:016}", state.next_account_number);
+    state.next_account_number += 1;
+
+    let expiry = Utc::now();
+    state.accounts.insert(
+        token.clone(),
+        Account {
+            expiry,
+            keys: Vec::new(),
+        },
+    );
+    json_response(
+        StatusCode::CREATED,
+        &json!({ "token": token, "expires": expiry }),
+    )
+}
+
+fn submit_voucher(state: &mut State, token: &str, body: &[u8]) -> Response<Body> {
+    let code = match json_field(body, "voucher_code") {
+        Some(code) => code,
+        None => return error_response(StatusCode::BAD_REQUEST, MISSING_ARGUMENT),
+    };
+    let seconds = match state.vouchers.get_mut(&code) {
+        Some(voucher) if voucher.used => {
+            return error_response(StatusCode::BAD_REQUEST, VOUCHER_USED)
+        }
+        Some(voucher) => {
+            voucher.used = true;
+            voucher.seconds
+        }
+        None => return error_response(StatusCode::BAD_REQUEST, INVALID_VOUCHER),
+    };
+
+    let account = state.accounts.get_mut(token).unwrap();
+    account.expiry = account.expiry.max(Utc::now()) + ChronoDuration::seconds(seconds as i64);
+    json_response(
+        StatusCode::OK,
+        &json!({ "time_added": seconds, "new_expiry": account.expiry }),
+    )
+}
+
+/// Registers `pubkey` on the account, replacing `old_pubkey` if given. Pushing a key that is
+/// already registered returns its addresses again.
+fn push_key(
+    state: &mut State,
+    token: &str,
+    pubkey: String,
+    old_pubkey: Option<String>,
+) -> Response<Body> {
+    let key_limit = state.key_limit;
+    let key_index = state.next_key_index;
+    let keys = &mut state.accounts.get_mut(token).unwrap().keys;
+
+    if let Some(old_pubkey) = old_pubkey {
+        keys.retain(|key| key.pubkey != old_pubkey);
+    }
+    if let Some(key) = keys.iter().find(|key| key.pubkey == pubkey) {
+        return json_response(StatusCode::CREATED, &key_json(key));
+    }
+    if keys.len() >= key_limit {
+        return error_response(StatusCode::BAD_REQUEST, KEY_LIMIT_REACHED);
+    }
+
+    let key = RegisteredKey {
+        pubkey,
+        index: key_index,
+    };
+    let response = json_response(StatusCode::CREATED, &key_json(&key));
+    keys.push(key);
+    state.next_key_index += 1;
+    response
+}
+
+/// Serializes a key the way the API does, with the tunnel addresses assigned to it.
+fn key_json(key: &RegisteredKey) -> serde_json::Value {
+    let ipv4_address = Ipv4Addr::from(u32::from(Ipv4Addr::new(10, 64, 0, 2)) + key.index);
+    let ipv6_address = Ipv6Addr::from(
+        u128::from("fc00:bbbb:bbbb:bb01::2".parse::<Ipv6Addr>().unwrap()) + u128::from(key.index),
+    );
+    json!({
+        "pubkey": key.pubkey,
+        "ipv4_address": format!("{}/32", ipv4_address),
+        "ipv6_address": format!("{}/128", ipv6_address),
+    })
+}
+
+fn json_field(body: &[u8], field: &str) -> Option<String> {
+    let body: serde_json::Value = serde_json::from_slice(body).ok()?;
+    body.get(field)?.as_str().map(str::to_owned)
+}
+
+fn json_response<T: serde::Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            serde_json::to_vec(body).expect("Failed to serialize mock API response"),
+        ))
+        .expect("Failed to build mock API response")
+}
+
+fn error_response(status: StatusCode, code: &str) -> Response<Body> {
+    json_response(status, &json!({ "code": code }))
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .expect("Failed to build mock API response")
+}
+
+fn lock(state: &Mutex<State>) -> std::sync::MutexGuard<'_, State> {
+    state.lock().expect("Mock API server state lock poisoned")
+}
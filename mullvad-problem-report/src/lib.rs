@@ -3,6 +3,7 @@
 use futures01::Future;
 use lazy_static::lazy_static;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use std::{
     borrow::Cow,
     cmp::min,
@@ -11,6 +12,7 @@ use std::{
     fs::{self, File},
     io::{self, BufWriter, Read, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 use talpid_types::ErrorExt;
 
@@ -23,6 +25,29 @@ const EXTRA_BYTES: usize = 32 * 1024;
 /// Fit five logs plus some system information in the report.
 const REPORT_MAX_SIZE: usize = (5 * LOG_MAX_READ_BYTES) + EXTRA_BYTES;
 
+/// Size budget for all logs in a bundle. Logs that don't fit are truncated, keeping their end.
+const BUNDLE_LOGS_MAX_SIZE: usize = 5 * LOG_MAX_READ_BYTES;
+/// Size budget for the settings snapshot in a bundle.
+const BUNDLE_SETTINGS_MAX_SIZE: usize = 64 * 1024;
+
+/// Bundle file describing the system and the logs in the bundle.
+const BUNDLE_METADATA_FILE: &str = "metadata.json";
+/// Bundle file containing the settings snapshot.
+const BUNDLE_SETTINGS_FILE: &str = "settings.json";
+/// Bundle directory containing one file per log.
+const BUNDLE_LOGS_DIR: &str = "logs";
+
+/// Name of the settings file in the daemon settings directory.
+const SETTINGS_FILE: &str = "settings.json";
+/// Settings whose values are always removed from the settings snapshot.
+const SECRET_SETTINGS_KEYS: &[&str] = &[
+    "account_token",
+    "private_key",
+    "psk",
+    "password",
+    "username",
+];
+
 
 /// Field delimeter in generated problem report
 const LOG_DELIMITER: &str = "====================";
@@ -67,6 +92,24 @@ pub enum Error {
 
     #[error(display = "Error during RPC call")]
     SendRpcError(#[error(source)] mullvad_rpc::rest::Error),
+
+    #[error(display = "Failed to read the redaction rules at {}", path)]
+    ReadRedactionRulesError {
+        path: String,
+        #[error(source)]
+        source: io::Error,
+    },
+
+    #[error(display = "Invalid redaction rule on line {} of {}", line, path)]
+    InvalidRedactionRuleError {
+        path: String,
+        line: usize,
+        #[error(source)]
+        source: regex::Error,
+    },
+
+    #[error(display = "Failed to write the redaction diff")]
+    WriteDiffError(#[error(source)] io::Error),
 }
 
 /// These are errors that can happen during problem report collection.
@@ -87,6 +130,16 @@ pub enum LogError {
     #[error(display = "Error reading the contents of log file: {}", path)]
     ReadLogError { path: String },
 
+    #[error(display = "Unable to get settings directory")]
+    GetSettingsDir(#[error(source, no_from)] mullvad_paths::Error),
+
+    #[error(display = "Failed to read the settings file: {}", path)]
+    ReadSettings {
+        path: String,
+        #[error(source)]
+        source: io::Error,
+    },
+
     #[cfg(any(target_os = "linux", target_os = "macos"))]
     #[error(display = "No home directory for current user")]
     NoHomeDir,
@@ -96,13 +149,99 @@ pub enum LogError {
     NoLocalAppDataDir,
 }
 
+/// How a collected problem report is stored.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReportFormat {
+    /// A single text file with the system information followed by all logs.
+    Text,
+    /// A tar archive with one file per log, a metadata JSON file and a snapshot of the settings
+    /// with secrets removed. Each section has its own size budget.
+    Bundle,
+}
+
+/// Reads custom redaction rules from a file. Every line that isn't empty or starts with `#` is a
+/// regular expression, and everything it matches is redacted from the report.
+pub fn load_redaction_rules(path: &Path) -> Result<Vec<Regex>, Error> {
+    let content = fs::read_to_string(path).map_err(|source| Error::ReadRedactionRulesError {
+        path: path.display().to_string(),
+        source,
+    })?;
+    parse_redaction_rules(&content).map_err(|(line, source)| Error::InvalidRedactionRuleError {
+        path: path.display().to_string(),
+        line,
+        source,
+    })
+}
+
+/// Parses redaction rules. Returns the line number of the first invalid rule on failure.
+fn parse_redaction_rules(content: &str) -> Result<Vec<Regex>, (usize, regex::Error)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| Regex::new(line).map_err(|error| (line_number, error)))
+        .collect()
+}
+
 pub fn collect_report(
     extra_logs: &[&Path],
     output_path: &Path,
     redact_custom_strings: Vec<String>,
+    redact_custom_patterns: Vec<Regex>,
+    format: ReportFormat,
+    #[cfg(target_os = "android")] android_log_dir: &Path,
+) -> Result<(), Error> {
+    let mut problem_report = build_report(
+        extra_logs,
+        redact_custom_strings,
+        redact_custom_patterns,
+        #[cfg(target_os = "android")]
+        android_log_dir,
+    );
+
+    let result = match format {
+        ReportFormat::Text => write_problem_report(&output_path, &problem_report),
+        ReportFormat::Bundle => {
+            problem_report.add_settings();
+            write_problem_report_bundle(&output_path, &problem_report)
+        }
+    };
+    result.map_err(|source| Error::WriteReportError {
+        path: output_path.display().to_string(),
+        source,
+    })
+}
+
+/// Collects the same logs as `collect_report`, and writes every line that redaction changes to
+/// `output`, both before and after redaction.
+pub fn write_redaction_diff(
+    extra_logs: &[&Path],
+    redact_custom_strings: Vec<String>,
+    redact_custom_patterns: Vec<Regex>,
+    output: impl Write,
     #[cfg(target_os = "android")] android_log_dir: &Path,
 ) -> Result<(), Error> {
+    let problem_report = build_report(
+        extra_logs,
+        redact_custom_strings,
+        redact_custom_patterns,
+        #[cfg(target_os = "android")]
+        android_log_dir,
+    );
+    problem_report
+        .write_redaction_diff(output)
+        .map_err(Error::WriteDiffError)
+}
+
+fn build_report(
+    extra_logs: &[&Path],
+    redact_custom_strings: Vec<String>,
+    redact_custom_patterns: Vec<Regex>,
+    #[cfg(target_os = "android")] android_log_dir: &Path,
+) -> ProblemReport {
     let mut problem_report = ProblemReport::new(redact_custom_strings);
+    problem_report.add_redaction_rules(redact_custom_patterns);
 
     let daemon_logs_dir = {
         #[cfg(target_os = "android")]
@@ -160,11 +299,7 @@ pub fn collect_report(
     }
 
     problem_report.add_logs(extra_logs);
-
-    write_problem_report(&output_path, &problem_report).map_err(|source| Error::WriteReportError {
-        path: output_path.display().to_string(),
-        source,
-    })
+    problem_report
 }
 
 /// Returns an iterator over all files in the given directory that has the `.log` extension.
@@ -247,21 +382,18 @@ fn write_logcat_to_file(log_dir: &Path) -> Result<PathBuf, io::Error> {
         .map(|_| logcat_path)
 }
 
+/// Returns the content of a collected report, in either format, exactly as `send_problem_report`
+/// would send it.
+pub fn read_problem_report(report_path: &Path) -> Result<String, Error> {
+    load_report(report_path).map(|(_metadata, content)| content)
+}
+
 pub fn send_problem_report(
     user_email: &str,
     user_message: &str,
     report_path: &Path,
 ) -> Result<(), Error> {
-    let report_content = normalize_newlines(
-        read_file_lossy(report_path, REPORT_MAX_SIZE).map_err(|source| {
-            Error::ReadProblemReportError {
-                path: report_path.display().to_string(),
-                source,
-            }
-        })?,
-    );
-    let metadata =
-        ProblemReport::parse_metadata(&report_content).unwrap_or_else(|| metadata::collect());
+    let (metadata, report_content) = load_report(report_path)?;
 
     let mut rpc_manager =
         mullvad_rpc::MullvadRpcRuntime::new().map_err(Error::CreateRpcClientError)?;
@@ -273,21 +405,180 @@ pub fn send_problem_report(
         .map_err(Error::SendRpcError)
 }
 
-fn write_problem_report(path: &Path, problem_report: &ProblemReport) -> io::Result<()> {
+/// Reads the metadata and the content to send from a collected report.
+fn load_report(report_path: &Path) -> Result<(BTreeMap<String, String>, String), Error> {
+    let read_report = || -> io::Result<_> {
+        if is_bundle(report_path)? {
+            let (metadata, content) = read_bundle(report_path)?;
+            Ok((metadata, normalize_newlines(content)))
+        } else {
+            let content = normalize_newlines(read_file_lossy(report_path, REPORT_MAX_SIZE)?);
+            let metadata =
+                ProblemReport::parse_metadata(&content).unwrap_or_else(|| metadata::collect());
+            Ok((metadata, content))
+        }
+    };
+    read_report().map_err(|source| Error::ReadProblemReportError {
+        path: report_path.display().to_string(),
+        source,
+    })
+}
+
+fn create_report_file(path: &Path) -> io::Result<File> {
     let file = File::create(path)?;
     let mut permissions = file.metadata()?.permissions();
     permissions.set_readonly(true);
     file.set_permissions(permissions)?;
+    Ok(file)
+}
+
+fn write_problem_report(path: &Path, problem_report: &ProblemReport) -> io::Result<()> {
+    let file = create_report_file(path)?;
     problem_report.write_to(BufWriter::new(file))?;
     Ok(())
 }
 
+fn write_problem_report_bundle(path: &Path, problem_report: &ProblemReport) -> io::Result<()> {
+    let file = create_report_file(path)?;
+    problem_report.write_bundle_to(BufWriter::new(file))
+}
+
+/// Describes the contents of a bundle. Stored in the bundle as `BUNDLE_METADATA_FILE`.
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleManifest {
+    metadata: BTreeMap<String, String>,
+    logs: Vec<BundleLog>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleLog {
+    /// Redacted path of the log, or a description of the error that replaced it.
+    label: String,
+    /// Path of the log within the bundle.
+    file: String,
+    /// Whether the beginning of the log was removed to fit the size budget.
+    truncated: bool,
+}
+
+/// Returns true if the file at `path` is a tar archive.
+fn is_bundle(path: &Path) -> io::Result<bool> {
+    let mut header = [0u8; 262];
+    match File::open(path)?.read_exact(&mut header) {
+        Ok(()) => Ok(&header[257..] == b"ustar"),
+        Err(ref error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Reads a bundle and renders it in the text report format. Returns the metadata and the text.
+fn read_bundle(path: &Path) -> io::Result<(BTreeMap<String, String>, String)> {
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(File::open(path)?);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        (&mut entry)
+            .take(REPORT_MAX_SIZE as u64)
+            .read_to_end(&mut content)?;
+        files.insert(name, String::from_utf8_lossy(&content).into_owned());
+    }
+
+    let manifest: BundleManifest = files
+        .get(BUNDLE_METADATA_FILE)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The bundle contains no metadata file",
+            )
+        })
+        .and_then(|manifest| serde_json::from_str(manifest).map_err(io::Error::from))?;
+
+    let mut logs = Vec::with_capacity(manifest.logs.len() + 1);
+    for log in manifest.logs {
+        let content = files.remove(&log.file).unwrap_or_default();
+        logs.push((log.label, content));
+    }
+    if let Some(settings) = files.remove(BUNDLE_SETTINGS_FILE) {
+        logs.push((BUNDLE_SETTINGS_FILE.to_owned(), settings));
+    }
+
+    let mut report = Vec::new();
+    write_text_report(&mut report, &manifest.metadata, &logs)?;
+    Ok((
+        manifest.metadata,
+        String::from_utf8_lossy(&report).into_owned(),
+    ))
+}
+
+fn append_bundle_file<W: Write>(
+    bundle: &mut tar::Builder<W>,
+    path: &str,
+    content: &[u8],
+) -> io::Result<()> {
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    bundle.append_data(&mut header, path, content)
+}
+
+/// Returns the longest suffix of `text` that is at most `max_bytes` long.
+fn tail_within_budget(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut start = text.len() - max_bytes;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+/// Writes a report in the text format. The logs are written in order after the metadata.
+fn write_text_report<W: Write>(
+    mut output: W,
+    metadata: &BTreeMap<String, String>,
+    logs: &[(String, String)],
+) -> io::Result<()> {
+    // IMPORTANT: Make sure this implementation stays in sync with `parse_metadata` below.
+    write_line!(output, "System information:")?;
+    for (key, value) in metadata {
+        write_line!(output, "{}: {}", key, value)?;
+    }
+    // Write empty line to separate metadata from first log
+    write_line!(output)?;
+    for &(ref label, ref content) in logs {
+        write_line!(output, "{}", LOG_DELIMITER)?;
+        write_line!(output, "Log: {}", label)?;
+        write_line!(output, "{}", LOG_DELIMITER)?;
+        output.write_all(content.as_bytes())?;
+        write_line!(output)?;
+    }
+    Ok(())
+}
+
+/// A log, or an error that replaced it. Both are stored unredacted.
+#[derive(Debug)]
+struct LogEntry {
+    label: String,
+    /// Name of the file the entry is stored in within a bundle.
+    file_name: String,
+    content: String,
+}
+
 #[derive(Debug)]
 struct ProblemReport {
     metadata: BTreeMap<String, String>,
-    logs: Vec<(String, String)>,
+    logs: Vec<LogEntry>,
     log_paths: HashSet<PathBuf>,
+    settings: Option<String>,
     redact_custom_strings: Vec<String>,
+    redact_custom_patterns: Vec<Regex>,
 }
 
 impl ProblemReport {
@@ -300,10 +591,18 @@ impl ProblemReport {
             metadata: metadata::collect(),
             logs: Vec::new(),
             log_paths: HashSet::new(),
+            settings: None,
             redact_custom_strings,
+            redact_custom_patterns: Vec::new(),
         }
     }
 
+    /// Redact everything matching any of `patterns` from the report, in addition to the
+    /// built-in redaction.
+    pub fn add_redaction_rules(&mut self, patterns: Vec<Regex>) {
+        self.redact_custom_patterns.extend(patterns);
+    }
+
     /// Attach some file logs to this report. This method adds the error chain instead of the log
     /// contents if an error occurs while reading one of the log files.
     pub fn add_logs<I>(&mut self, paths: I)
@@ -321,31 +620,65 @@ impl ProblemReport {
     pub fn add_log(&mut self, path: &Path) {
         let expanded_path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
         if self.log_paths.insert(expanded_path.clone()) {
-            let redacted_path = self.redact(&expanded_path.to_string_lossy());
-            let content = self.redact(&read_file_lossy(path, LOG_MAX_READ_BYTES).unwrap_or_else(
-                |error| {
-                    error.display_chain_with_msg(&format!(
-                        "Error reading the contents of log file: {}",
-                        expanded_path.display()
-                    ))
-                },
-            ));
-            self.logs.push((redacted_path, content));
+            let content = read_file_lossy(path, LOG_MAX_READ_BYTES).unwrap_or_else(|error| {
+                error.display_chain_with_msg(&format!(
+                    "Error reading the contents of log file: {}",
+                    expanded_path.display()
+                ))
+            });
+            let file_name = expanded_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| "log".to_owned());
+            self.logs.push(LogEntry {
+                label: expanded_path.to_string_lossy().into_owned(),
+                file_name,
+                content,
+            });
             println!("Adding {}", expanded_path.display());
         }
     }
 
     /// Attach an error to the report.
     pub fn add_error(&mut self, message: &'static str, error: &impl ErrorExt) {
-        let redacted_error = self.redact(&error.display_chain());
-        self.logs.push((message.to_string(), redacted_error));
+        self.logs.push(LogEntry {
+            label: message.to_string(),
+            file_name: "error.txt".to_owned(),
+            content: error.display_chain(),
+        });
+    }
+
+    /// Attach a snapshot of the daemon settings. Secrets are removed when the report is written.
+    /// Attaches an error instead if the settings can't be read.
+    pub fn add_settings(&mut self) {
+        let settings = mullvad_paths::get_settings_dir()
+            .map_err(LogError::GetSettingsDir)
+            .and_then(|settings_dir| {
+                let path = settings_dir.join(SETTINGS_FILE);
+                fs::read_to_string(&path).map_err(|source| LogError::ReadSettings {
+                    path: path.display().to_string(),
+                    source,
+                })
+            });
+        match settings {
+            Ok(settings) => self.settings = Some(settings),
+            Err(error) => self.add_error("Unable to read the settings", &error),
+        }
     }
 
     fn redact(&self, input: &str) -> String {
         let out1 = Self::redact_account_number(input);
         let out2 = Self::redact_home_dir(&out1);
         let out3 = Self::redact_network_info(&out2);
-        self.redact_custom_strings(&out3).to_string()
+        let out4 = self.redact_custom_strings(&out3);
+        self.redact_custom_patterns(&out4).to_string()
+    }
+
+    fn redacted_logs(&self) -> Vec<(String, String)> {
+        self.logs
+            .iter()
+            .map(|entry| (self.redact(&entry.label), self.redact(&entry.content)))
+            .collect()
     }
 
     fn redact_account_number(input: &str) -> Cow<'_, str> {
@@ -388,20 +721,119 @@ impl ProblemReport {
         out
     }
 
-    fn write_to<W: Write>(&self, mut output: W) -> io::Result<()> {
-        // IMPORTANT: Make sure this implementation stays in sync with `parse_metadata` below.
-        write_line!(output, "System information:")?;
-        for (key, value) in &self.metadata {
-            write_line!(output, "{}: {}", key, value)?;
+    fn redact_custom_patterns<'a>(&self, input: &'a str) -> Cow<'a, str> {
+        let mut out = Cow::from(input);
+        for pattern in &self.redact_custom_patterns {
+            out = pattern.replace_all(&out, "[REDACTED]").into_owned().into()
         }
-        // Write empty line to separate metadata from first log
-        write_line!(output)?;
-        for &(ref label, ref content) in &self.logs {
-            write_line!(output, "{}", LOG_DELIMITER)?;
-            write_line!(output, "Log: {}", label)?;
-            write_line!(output, "{}", LOG_DELIMITER)?;
-            output.write_all(content.as_bytes())?;
-            write_line!(output)?;
+        out
+    }
+
+    /// Removes secrets from the settings and redacts all remaining strings. Returns the settings
+    /// as pretty printed JSON, or a description of the failure if they can't be parsed.
+    fn redact_settings(&self, settings: &str) -> String {
+        match serde_json::from_str::<serde_json::Value>(settings) {
+            Ok(mut settings) => {
+                self.redact_settings_value(&mut settings);
+                serde_json::to_string_pretty(&settings)
+                    .unwrap_or_else(|error| format!("Unable to serialize the settings: {}", error))
+            }
+            Err(error) => format!("Unable to parse the settings: {}", error),
+        }
+    }
+
+    fn redact_settings_value(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    if SECRET_SETTINGS_KEYS.contains(&key.as_str()) && !value.is_null() {
+                        *value = serde_json::Value::String("[REDACTED]".to_owned());
+                    } else {
+                        self.redact_settings_value(value);
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    self.redact_settings_value(value);
+                }
+            }
+            serde_json::Value::String(string) => *string = self.redact(string),
+            _ => (),
+        }
+    }
+
+    fn write_to<W: Write>(&self, output: W) -> io::Result<()> {
+        write_text_report(output, &self.metadata, &self.redacted_logs())
+    }
+
+    /// Writes the report as a tar archive. The logs and the settings snapshot are limited to
+    /// their own size budgets.
+    fn write_bundle_to<W: Write>(&self, output: W) -> io::Result<()> {
+        let mut files = Vec::with_capacity(self.logs.len() + 2);
+        let mut logs = Vec::with_capacity(self.logs.len());
+        let mut logs_budget = BUNDLE_LOGS_MAX_SIZE;
+        for (index, (label, content)) in self.redacted_logs().into_iter().enumerate() {
+            let kept_content = tail_within_budget(&content, logs_budget);
+            logs_budget -= kept_content.len();
+
+            let file = format!(
+                "{}/{:02}-{}",
+                BUNDLE_LOGS_DIR, index, self.logs[index].file_name
+            );
+            logs.push(BundleLog {
+                label,
+                file: file.clone(),
+                truncated: kept_content.len() < content.len(),
+            });
+            files.push((file, kept_content.as_bytes().to_vec()));
+        }
+
+        if let Some(ref settings) = self.settings {
+            let mut settings = self.redact_settings(settings);
+            if settings.len() > BUNDLE_SETTINGS_MAX_SIZE {
+                settings = format!(
+                    "The settings exceed the size budget of {} bytes",
+                    BUNDLE_SETTINGS_MAX_SIZE
+                );
+            }
+            files.push((BUNDLE_SETTINGS_FILE.to_owned(), settings.into_bytes()));
+        }
+
+        let manifest = BundleManifest {
+            metadata: self.metadata.clone(),
+            logs,
+        };
+        let manifest = serde_json::to_vec_pretty(&manifest).map_err(io::Error::from)?;
+
+        let mut bundle = tar::Builder::new(output);
+        append_bundle_file(&mut bundle, BUNDLE_METADATA_FILE, &manifest)?;
+        for (path, content) in files {
+            append_bundle_file(&mut bundle, &path, &content)?;
+        }
+        bundle.into_inner()?.flush()
+    }
+
+    /// Writes every line that redaction changes, before and after redaction, so that the user
+    /// can review what is removed from the report.
+    fn write_redaction_diff<W: Write>(&self, mut output: W) -> io::Result<()> {
+        for entry in &self.logs {
+            let mut wrote_header = false;
+            for (index, line) in entry.content.lines().enumerate() {
+                let redacted_line = self.redact(line);
+                if redacted_line == line {
+                    continue;
+                }
+                if !wrote_header {
+                    write_line!(output, "{}", LOG_DELIMITER)?;
+                    write_line!(output, "Log: {}", entry.label)?;
+                    write_line!(output, "{}", LOG_DELIMITER)?;
+                    wrote_header = true;
+                }
+                write_line!(output, "@@ line {} @@", index + 1)?;
+                write_line!(output, "-{}", line)?;
+                write_line!(output, "+{}", redacted_line)?;
+            }
         }
         Ok(())
     }
@@ -409,7 +841,7 @@ impl ProblemReport {
     /// Tries to parse out the metadata map from a string that is supposed to be a report written by
     /// this struct.
     pub fn parse_metadata(report: &str) -> Option<BTreeMap<String, String>> {
-        // IMPORTANT: Make sure this implementation stays in sync with `write_to` above.
+        // IMPORTANT: Make sure this implementation stays in sync with `write_text_report` above.
         const PATTERN: &str = ": ";
         let mut lines = report.lines();
         if lines.next() != Some("System information:") {
@@ -592,6 +1024,98 @@ mod tests {
         assert_eq!(input, res);
     }
 
+    #[test]
+    fn redacts_custom_patterns() {
+        let mut report = ProblemReport::new(vec![]);
+        report.add_redaction_rules(parse_redaction_rules("secret-[a-z]+").unwrap());
+        let actual = report.redact("pre secret-value post");
+        assert_eq!("pre [REDACTED] post", actual);
+    }
+
+    #[test]
+    fn parses_redaction_rules() {
+        let rules = parse_redaction_rules("# comment\n\n  user-\\d+  \nhost-[a-z]+\n").unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].as_str(), "user-\\d+");
+
+        let (line, _) = parse_redaction_rules("valid\n# comment\n(invalid\n").unwrap_err();
+        assert_eq!(line, 3);
+    }
+
+    #[test]
+    fn redacts_secrets_from_settings() {
+        let report = ProblemReport::new(vec![]);
+        let settings = r#"{
+            "account_token": "1234",
+            "bridge_settings": {
+                "custom": { "peer": "1.2.3.4:443", "password": "hunter2" }
+            },
+            "wireguard": null
+        }"#;
+        let redacted: serde_json::Value =
+            serde_json::from_str(&report.redact_settings(settings)).unwrap();
+        assert_eq!(redacted["account_token"], "[REDACTED]");
+        assert_eq!(
+            redacted["bridge_settings"]["custom"]["password"],
+            "[REDACTED]"
+        );
+        assert_eq!(
+            redacted["bridge_settings"]["custom"]["peer"],
+            "[REDACTED]:443"
+        );
+        assert!(redacted["wireguard"].is_null());
+    }
+
+    #[test]
+    fn truncates_to_budget_at_char_boundary() {
+        assert_eq!(tail_within_budget("abc", 5), "abc");
+        assert_eq!(tail_within_budget("abcdef", 2), "ef");
+        assert_eq!(tail_within_budget("aåb", 3), "åb");
+        assert_eq!(tail_within_budget("aåb", 2), "b");
+    }
+
+    #[test]
+    fn writes_redaction_diff() {
+        let mut report = ProblemReport::new(vec![]);
+        report.logs.push(LogEntry {
+            label: "test.log".to_owned(),
+            file_name: "test.log".to_owned(),
+            content: "kept\nconnecting to 1.2.3.4\n".to_owned(),
+        });
+        let mut diff = Vec::new();
+        report.write_redaction_diff(&mut diff).unwrap();
+        let diff = String::from_utf8(diff).unwrap();
+        assert!(diff.contains("@@ line 2 @@"));
+        assert!(diff.contains("-connecting to 1.2.3.4"));
+        assert!(diff.contains("+connecting to [REDACTED]"));
+        assert!(!diff.contains("kept"));
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let mut report = ProblemReport::new(vec![]);
+        report.logs.push(LogEntry {
+            label: "daemon.log".to_owned(),
+            file_name: "daemon.log".to_owned(),
+            content: "connected to 1.2.3.4".to_owned(),
+        });
+        report.settings = Some(r#"{"account_token": "hunter2token"}"#.to_owned());
+
+        let path = std::env::temp_dir().join(format!("{}.tar", uuid::Uuid::new_v4()));
+        report
+            .write_bundle_to(File::create(&path).unwrap())
+            .unwrap();
+        assert!(is_bundle(&path).unwrap());
+        let (metadata, content) = read_bundle(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(metadata, report.metadata);
+        assert!(content.contains("Log: daemon.log"));
+        assert!(content.contains("connected to [REDACTED]"));
+        assert!(!content.contains("hunter2token"));
+        assert_eq!(ProblemReport::parse_metadata(&content), Some(metadata));
+    }
+
     #[test]
     fn parse_metadata() {
         let report = ProblemReport::new(Vec::new());
@@ -1,8 +1,17 @@
 #![deny(rust_2018_idioms)]
 
 use clap::{crate_authors, crate_name};
-use mullvad_problem_report::{collect_report, metadata, send_problem_report, Error};
-use std::{env, path::Path, process};
+use mullvad_problem_report::{
+    collect_report, load_redaction_rules, metadata, read_problem_report, send_problem_report,
+    write_redaction_diff, Error, ReportFormat,
+};
+use regex::Regex;
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+    process,
+};
 use talpid_types::ErrorExt;
 
 
@@ -40,20 +49,33 @@ fn run() -> Result<(), Error> {
                         .required(true),
                 )
                 .arg(
-                    clap::Arg::with_name("extra_logs")
-                        .help("Paths to additional log files to be included.")
-                        .multiple(true)
-                        .value_name("EXTRA LOGS")
+                    clap::Arg::with_name("format")
+                        .help(
+                            "The format of the report. A bundle is a tar archive with one file \
+                             per log, a metadata file and the settings with secrets removed.",
+                        )
+                        .long("format")
                         .takes_value(true)
-                        .required(false),
+                        .possible_values(&["text", "bundle"])
+                        .default_value("text"),
                 )
+                .args(&redaction_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("diff")
+                .about("Show every log line that is changed by redaction, before and after")
+                .args(&redaction_args()),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("view")
+                .about("Show a collected report exactly as it would be sent")
                 .arg(
-                    clap::Arg::with_name("redact")
-                        .help("List of words and expressions to remove from the report")
-                        .long("redact")
-                        .value_name("PHRASE")
-                        .multiple(true)
-                        .takes_value(true),
+                    clap::Arg::with_name("report")
+                        .long("report")
+                        .short("r")
+                        .help("The path to previously collected report file.")
+                        .takes_value(true)
+                        .required(true),
                 ),
         )
         .subcommand(
@@ -88,15 +110,21 @@ fn run() -> Result<(), Error> {
     let matches = app.get_matches();
 
     if let Some(collect_matches) = matches.subcommand_matches("collect") {
-        let redact_custom_strings = collect_matches
-            .values_of_lossy("redact")
-            .unwrap_or_else(Vec::new);
-        let extra_logs = collect_matches
-            .values_of_os("extra_logs")
-            .map(|os_values| os_values.map(Path::new).collect())
-            .unwrap_or_else(Vec::new);
+        let (redact_custom_strings, redact_custom_patterns) = get_redaction(collect_matches)?;
+        let extra_logs = get_extra_logs(collect_matches);
         let output_path = Path::new(collect_matches.value_of_os("output").unwrap());
-        collect_report(&extra_logs, output_path, redact_custom_strings)?;
+        let format = match collect_matches.value_of("format").unwrap() {
+            "text" => ReportFormat::Text,
+            "bundle" => ReportFormat::Bundle,
+            _ => unreachable!(),
+        };
+        collect_report(
+            &extra_logs,
+            output_path,
+            redact_custom_strings,
+            redact_custom_patterns,
+            format,
+        )?;
 
         let expanded_output_path = output_path
             .canonicalize()
@@ -109,6 +137,26 @@ fn run() -> Result<(), Error> {
         println!("Send the problem report to support via the send subcommand. See:");
         println!(" $ {} send --help", env::args().next().unwrap());
         Ok(())
+    } else if let Some(diff_matches) = matches.subcommand_matches("diff") {
+        let (redact_custom_strings, redact_custom_patterns) = get_redaction(diff_matches)?;
+        let extra_logs = get_extra_logs(diff_matches);
+        let stdout = io::stdout();
+        write_redaction_diff(
+            &extra_logs,
+            redact_custom_strings,
+            redact_custom_patterns,
+            stdout.lock(),
+        )
+    } else if let Some(view_matches) = matches.subcommand_matches("view") {
+        let report_path = Path::new(view_matches.value_of_os("report").unwrap());
+        let content = read_problem_report(report_path)?;
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        // Ignore write errors, such as when the output is piped to a pager that exits early.
+        let _ = stdout
+            .write_all(content.as_bytes())
+            .and_then(|_| stdout.flush());
+        Ok(())
     } else if let Some(send_matches) = matches.subcommand_matches("send") {
         let report_path = Path::new(send_matches.value_of_os("report").unwrap());
         let user_email = send_matches.value_of("email").unwrap_or("");
@@ -118,3 +166,46 @@ fn run() -> Result<(), Error> {
         unreachable!("No sub command given");
     }
 }
+
+/// Arguments selecting the logs to include and what to redact from them.
+fn redaction_args() -> [clap::Arg<'static, 'static>; 3] {
+    [
+        clap::Arg::with_name("extra_logs")
+            .help("Paths to additional log files to be included.")
+            .multiple(true)
+            .value_name("EXTRA LOGS")
+            .takes_value(true)
+            .required(false),
+        clap::Arg::with_name("redact")
+            .help("List of words and expressions to remove from the report")
+            .long("redact")
+            .value_name("PHRASE")
+            .multiple(true)
+            .takes_value(true),
+        clap::Arg::with_name("redact_rules")
+            .help(
+                "Path to a file with one regular expression per line. Everything matching any \
+                 of them is removed from the report. Empty lines and lines starting with # are \
+                 ignored.",
+            )
+            .long("redact-rules")
+            .value_name("PATH")
+            .takes_value(true),
+    ]
+}
+
+fn get_redaction(matches: &clap::ArgMatches<'_>) -> Result<(Vec<String>, Vec<Regex>), Error> {
+    let redact_custom_strings = matches.values_of_lossy("redact").unwrap_or_else(Vec::new);
+    let redact_custom_patterns = match matches.value_of_os("redact_rules") {
+        Some(path) => load_redaction_rules(Path::new(path))?,
+        None => Vec::new(),
+    };
+    Ok((redact_custom_strings, redact_custom_patterns))
+}
+
+fn get_extra_logs<'a>(matches: &'a clap::ArgMatches<'_>) -> Vec<&'a Path> {
+    matches
+        .values_of_os("extra_logs")
+        .map(|os_values| os_values.map(Path::new).collect())
+        .unwrap_or_else(Vec::new)
+}
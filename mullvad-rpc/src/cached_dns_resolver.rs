@@ -1,4 +1,5 @@
 use log::{debug, info, warn};
+use mullvad_types::diagnostics::ApiAddressSource;
 use std::{
     fs::{self, File},
     io::{self, Write},
@@ -83,6 +84,7 @@ pub struct CachedDnsResolver<R: DnsResolver = SystemDnsResolver> {
     dns_resolver: R,
     cache_file: Option<PathBuf>,
     cached_address: IpAddr,
+    address_source: ApiAddressSource,
     last_updated: SystemTime,
 }
 
@@ -99,9 +101,13 @@ impl<R: DnsResolver> CachedDnsResolver<R> {
         cache_file: Option<PathBuf>,
         fallback_address: IpAddr,
     ) -> Self {
-        let (cached_address, last_updated) = match &cache_file {
+        let (cached_address, address_source, last_updated) = match &cache_file {
             Some(cache_file) => Self::load_initial_cached_address(&cache_file, fallback_address),
-            None => (fallback_address, EXPIRED_CACHE_TIMESTAMP),
+            None => (
+                fallback_address,
+                ApiAddressSource::Fallback,
+                EXPIRED_CACHE_TIMESTAMP,
+            ),
         };
 
         CachedDnsResolver {
@@ -109,10 +115,22 @@ impl<R: DnsResolver> CachedDnsResolver<R> {
             dns_resolver,
             cache_file,
             cached_address,
+            address_source,
             last_updated,
         }
     }
 
+    /// Returns the current address, where it came from and when it was last updated, without
+    /// trying to resolve it again.
+    pub fn current_address(&self) -> (IpAddr, ApiAddressSource, Option<SystemTime>) {
+        let last_updated = if self.last_updated == EXPIRED_CACHE_TIMESTAMP {
+            None
+        } else {
+            Some(self.last_updated)
+        };
+        (self.cached_address, self.address_source, last_updated)
+    }
+
     pub fn resolve(&mut self) -> IpAddr {
         if let Ok(cache_age) = self.last_updated.elapsed() {
             if cache_age > MAX_CACHE_AGE {
@@ -129,13 +147,21 @@ impl<R: DnsResolver> CachedDnsResolver<R> {
     fn load_initial_cached_address(
         cache_file: &Path,
         fallback_address: IpAddr,
-    ) -> (IpAddr, SystemTime) {
+    ) -> (IpAddr, ApiAddressSource, SystemTime) {
         match Self::load_from_file(cache_file) {
             Ok(previously_cached_address) => match Self::read_file_modification_time(cache_file) {
-                Ok(last_updated) => (previously_cached_address, last_updated),
+                Ok(last_updated) => (
+                    previously_cached_address,
+                    ApiAddressSource::Cache,
+                    last_updated,
+                ),
                 Err(error) => {
                     warn!("Failed to read modification time of file: {}", error);
-                    (previously_cached_address, EXPIRED_CACHE_TIMESTAMP)
+                    (
+                        previously_cached_address,
+                        ApiAddressSource::Cache,
+                        EXPIRED_CACHE_TIMESTAMP,
+                    )
                 }
             },
             Err(error) => {
@@ -145,7 +171,11 @@ impl<R: DnsResolver> CachedDnsResolver<R> {
                         "Failed to load previously cached IP address, using fallback"
                     )
                 );
-                (fallback_address, EXPIRED_CACHE_TIMESTAMP)
+                (
+                    fallback_address,
+                    ApiAddressSource::Fallback,
+                    EXPIRED_CACHE_TIMESTAMP,
+                )
             }
         }
     }
@@ -175,6 +205,7 @@ impl<R: DnsResolver> CachedDnsResolver<R> {
 
                 debug!("Updating DNS cache for {} with {}", self.hostname, address);
                 self.cached_address = address;
+                self.address_source = ApiAddressSource::Dns;
                 self.last_updated = SystemTime::now();
 
                 if let Err(error) = self.update_cache_file() {
@@ -347,6 +378,34 @@ mod tests {
         assert_eq!(address, fallback_address);
     }
 
+    #[test]
+    fn reports_address_source() {
+        let (_temp_dir, cache_dir) = create_test_dirs();
+        let cached_address = "80.10.20.30".parse().unwrap();
+        let mock_address = "90.168.1.206".parse().unwrap();
+
+        let cache = create_cached_dns_resolver(MockDnsResolver::that_fails(), &cache_dir, None);
+        let (_, source, last_updated) = cache.current_address();
+        assert_eq!(source, ApiAddressSource::Fallback);
+        assert!(last_updated.is_none());
+
+        let cache_file_path = write_address(&cache_dir, cached_address);
+        let cache = create_cached_dns_resolver(MockDnsResolver::that_fails(), &cache_dir, None);
+        let (address, source, _) = cache.current_address();
+        assert_eq!((address, source), (cached_address, ApiAddressSource::Cache));
+
+        make_file_old(&cache_file_path);
+        let mut cache = create_cached_dns_resolver(
+            MockDnsResolver::with_address(mock_address),
+            &cache_dir,
+            None,
+        );
+        cache.resolve();
+        let (address, source, last_updated) = cache.current_address();
+        assert_eq!((address, source), (mock_address, ApiAddressSource::Dns));
+        assert!(last_updated.is_some());
+    }
+
     #[test]
     fn ignores_private_ip() {
         let (_temp_dir, cache_dir) = create_test_dirs();
@@ -1,3 +1,4 @@
+use crate::proxy::{ApiConnectionMode, ApiConnectivityEvent, ApiConnectivityListener};
 use http::uri::Scheme;
 use hyper::{client::HttpConnector, service::Service, Uri};
 use hyper_rustls::MaybeHttpsStream;
@@ -7,8 +8,9 @@ use std::{
     io::{self, BufReader},
     pin::Pin,
     str,
-    sync::Arc,
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 use tokio_rustls::rustls;
 use webpki::DNSNameRef;
@@ -18,12 +20,24 @@ const OLD_ROOT_CERT: &[u8] = include_bytes!("../old_le_root_cert.pem");
 // New LetsEncrypt root certificate
 const NEW_ROOT_CERT: &[u8] = include_bytes!("../new_le_root_cert.pem");
 
+/// How long to wait for a connection, including any proxy and TLS handshakes, to be established.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// A Connector for the `https` scheme.
 #[derive(Clone)]
 pub struct HttpsConnectorWithSni {
     sni_hostname: Option<String>,
     http: HttpConnector,
     tls: Arc<rustls::ClientConfig>,
+    connection_mode: Arc<Mutex<ConnectionModeState>>,
+    report_connectivity: bool,
+}
+
+/// Connection mode state shared between all clones of a connector.
+#[derive(Default)]
+struct ConnectionModeState {
+    mode: ApiConnectionMode,
+    listener: Option<ApiConnectivityListener>,
 }
 
 impl HttpsConnectorWithSni {
@@ -72,6 +86,24 @@ impl HttpsConnectorWithSni {
     pub fn set_sni_hostname(&mut self, hostname: Option<String>) {
         self.sni_hostname = hostname;
     }
+
+    /// Configure how connections are established. The mode is shared between all clones of this
+    /// connector.
+    pub fn set_connection_mode(&self, mode: ApiConnectionMode) {
+        self.connection_mode.lock().unwrap().mode = mode;
+    }
+
+    /// Set a listener that is told whether each connection attempt succeeded. The listener is
+    /// shared between all clones of this connector, but only invoked by clones that have
+    /// connectivity reporting enabled.
+    pub fn set_connectivity_listener(&self, listener: ApiConnectivityListener) {
+        self.connection_mode.lock().unwrap().listener = Some(listener);
+    }
+
+    /// Enable or disable reporting of connection attempts to the connectivity listener.
+    pub fn set_report_connectivity(&mut self, report_connectivity: bool) {
+        self.report_connectivity = report_connectivity;
+    }
 }
 
 impl From<(HttpConnector, rustls::ClientConfig)> for HttpsConnectorWithSni {
@@ -80,6 +112,8 @@ impl From<(HttpConnector, rustls::ClientConfig)> for HttpsConnectorWithSni {
             sni_hostname: None,
             http: args.0,
             tls: Arc::new(args.1),
+            connection_mode: Arc::new(Mutex::new(ConnectionModeState::default())),
+            report_connectivity: false,
         }
     }
 }
@@ -110,9 +144,22 @@ impl Service<Uri> for HttpsConnectorWithSni {
             .ok_or_else(|| {
                 io::Error::new(io::ErrorKind::InvalidInput, "invalid url, missing host")
             });
+        let mode = self.connection_mode.lock().unwrap().mode.clone();
+        let connection_mode = self.connection_mode.clone();
+        let report_connectivity = self.report_connectivity;
 
 
         let fut = async move {
+            #[cfg(debug_assertions)]
+            {
+                if uri.scheme() == Some(&Scheme::HTTP) && is_loopback(&uri) {
+                    let connection = http
+                        .call(uri)
+                        .await
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+                    return Ok(MaybeHttpsStream::Http(connection));
+                }
+            }
             if uri.scheme() != Some(&Scheme::HTTPS) {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
@@ -122,12 +169,42 @@ impl Service<Uri> for HttpsConnectorWithSni {
             let hostname = sni_hostname?;
             let host = DNSNameRef::try_from_ascii_str(&hostname)
                 .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid hostname"))?;
-            let connection = http
-                .call(uri)
+            let connect = async {
+                let connection = match &mode {
+                    ApiConnectionMode::Proxied(proxy) => {
+                        let uri_host = uri.host().ok_or_else(|| {
+                            io::Error::new(io::ErrorKind::InvalidInput, "invalid url, missing host")
+                        })?;
+                        let uri_host = uri_host.trim_start_matches('[').trim_end_matches(']');
+                        let port = uri.port_u16().unwrap_or(443);
+                        crate::proxy::connect(proxy, uri_host, port).await?
+                    }
+                    ApiConnectionMode::Direct => http
+                        .call(uri.clone())
+                        .await
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?,
+                };
+                tls_connector.connect(host, connection).await
+            };
+            let result = tokio::time::timeout(CONNECT_TIMEOUT, connect)
                 .await
-                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
-            let tls_connection = tls_connector.connect(host, connection).await?;
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "timed out connecting to the API",
+                    ))
+                });
+
+            if report_connectivity {
+                if let Some(listener) = &connection_mode.lock().unwrap().listener {
+                    listener(ApiConnectivityEvent {
+                        mode,
+                        reachable: result.is_ok(),
+                    });
+                }
+            }
 
+            let tls_connection = result?;
             Ok(MaybeHttpsStream::Https(tls_connection))
         };
 
@@ -136,6 +213,22 @@ impl Service<Uri> for HttpsConnectorWithSni {
     }
 }
 
+/// Returns true if the URI points to the local host. Debug builds allow plain HTTP to such
+/// addresses, so that a local stand-in for the API can be used for testing.
+#[cfg(debug_assertions)]
+fn is_loopback(uri: &Uri) -> bool {
+    match uri.host() {
+        Some("localhost") => true,
+        Some(host) => host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+            .map(|address| address.is_loopback())
+            .unwrap_or(false),
+        None => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::HttpsConnectorWithSni;
@@ -144,4 +237,16 @@ mod test {
     fn test_cert_loading() {
         let _certs = HttpsConnectorWithSni::read_cert_store();
     }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn test_is_loopback() {
+        use super::is_loopback;
+
+        assert!(is_loopback(&"http://127.0.0.1:8080/v1".parse().unwrap()));
+        assert!(is_loopback(&"http://[::1]/v1".parse().unwrap()));
+        assert!(is_loopback(&"http://localhost/v1".parse().unwrap()));
+        assert!(!is_loopback(&"http://193.138.218.78/v1".parse().unwrap()));
+        assert!(!is_loopback(&"http://api.mullvad.net/v1".parse().unwrap()));
+    }
 }
@@ -5,12 +5,14 @@ use futures01::future::Future as Future01;
 use hyper::Method;
 use mullvad_types::{
     account::{AccountToken, VoucherSubmission},
+    diagnostics::ApiAddressSource,
     version::{AppVersion, AppVersionInfo},
 };
 use std::{
     collections::BTreeMap,
-    net::{IpAddr, Ipv4Addr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::Path,
+    time::SystemTime,
 };
 use talpid_types::net::wireguard;
 
@@ -18,12 +20,19 @@ use talpid_types::net::wireguard;
 pub mod event_loop;
 pub mod rest;
 
+mod metrics;
+use crate::metrics::MetricsRecorder;
+pub use crate::metrics::{ApiMetrics, LATENCY_BUCKETS};
+
 mod cached_dns_resolver;
 use crate::cached_dns_resolver::CachedDnsResolver;
 
 mod https_client_with_sni;
 use crate::https_client_with_sni::HttpsConnectorWithSni;
 
+mod proxy;
+pub use crate::proxy::{ApiConnectionMode, ApiConnectivityEvent, ApiConnectivityListener};
+
 mod relay_list;
 pub use hyper::StatusCode;
 pub use relay_list::RelayListProxy;
@@ -31,14 +40,21 @@ pub use relay_list::RelayListProxy;
 
 const API_HOST: &str = "api.mullvad.net";
 pub const API_IP_CACHE_FILENAME: &str = "api-ip-address.txt";
+const API_PORT: u16 = 443;
 const API_IP: IpAddr = IpAddr::V4(Ipv4Addr::new(193, 138, 218, 78));
 
+/// Environment variable that points all API requests at a plain HTTP server on a loopback
+/// address, such as `mullvad-mock-api`. Only read in debug builds.
+pub const API_ADDRESS_OVERRIDE_VAR: &str = "MULLVAD_API_ADDR";
+
 
 /// A type that helps with the creation of RPC connections.
 pub struct MullvadRpcRuntime {
     cached_dns_resolver: CachedDnsResolver,
     https_connector: HttpsConnectorWithSni,
     runtime: tokio::runtime::Runtime,
+    metrics: MetricsRecorder,
+    api_address_override: Option<SocketAddr>,
 }
 
 #[derive(err_derive::Error, Debug)]
@@ -56,6 +72,8 @@ impl MullvadRpcRuntime {
             cached_dns_resolver: CachedDnsResolver::new(API_HOST.to_owned(), None, API_IP),
             runtime: event_loop::create_runtime()?,
             https_connector: HttpsConnectorWithSni::new(),
+            metrics: MetricsRecorder::default(),
+            api_address_override: api_address_override(),
         })
     }
 
@@ -71,36 +89,124 @@ impl MullvadRpcRuntime {
             cached_dns_resolver,
             runtime: event_loop::create_runtime()?,
             https_connector,
+            metrics: MetricsRecorder::default(),
+            api_address_override: api_address_override(),
         })
     }
 
+    /// Sets how connections are established. Applies to all request services created by this
+    /// runtime, including existing ones.
+    pub fn set_connection_mode(&self, mode: ApiConnectionMode) {
+        self.https_connector.set_connection_mode(mode);
+    }
+
+    /// Sets a listener that is told whether connections to the API could be established using the
+    /// current connection mode.
+    pub fn set_connectivity_listener(
+        &self,
+        listener: impl Fn(ApiConnectivityEvent) + Send + 'static,
+    ) {
+        self.https_connector
+            .set_connectivity_listener(Box::new(listener));
+    }
+
+    /// Returns the address that direct connections to the API are made to.
+    pub fn api_address(&mut self) -> SocketAddr {
+        if let Some(address) = self.api_address_override {
+            return address;
+        }
+        SocketAddr::new(self.cached_dns_resolver.resolve(), API_PORT)
+    }
+
+    /// Returns the address that direct connections to the API are made to, where it came from and
+    /// when it was last resolved. Unlike `api_address`, this never resolves the address again.
+    pub fn api_address_info(&self) -> (SocketAddr, ApiAddressSource, Option<SystemTime>) {
+        if let Some(address) = self.api_address_override {
+            return (address, ApiAddressSource::Override, None);
+        }
+        let (address, source, last_updated) = self.cached_dns_resolver.current_address();
+        (SocketAddr::new(address, API_PORT), source, last_updated)
+    }
+
     /// Creates a new request service and returns a handle to it.
-    fn new_request_service(&mut self, sni_hostname: Option<String>) -> rest::RequestServiceHandle {
+    fn new_request_service(
+        &mut self,
+        sni_hostname: Option<String>,
+        report_connectivity: bool,
+        record_metrics: bool,
+    ) -> rest::RequestServiceHandle {
         let mut https_connector = self.https_connector.clone();
         https_connector.set_sni_hostname(sni_hostname);
+        https_connector.set_report_connectivity(report_connectivity);
 
-        let service = rest::RequestService::new(https_connector, self.runtime.handle().clone());
+        let metrics = if record_metrics {
+            Some(self.metrics.clone())
+        } else {
+            None
+        };
+
+        let service =
+            rest::RequestService::new(https_connector, self.runtime.handle().clone(), metrics);
         let handle = service.handle();
         self.runtime.spawn(service.into_future());
         handle
     }
 
+    /// Returns latency and error statistics for the requests made to the Mullvad API.
+    pub fn api_metrics(&self) -> ApiMetrics {
+        self.metrics.snapshot()
+    }
+
     /// Returns a request factory initialized to create requests for the master API
     pub fn mullvad_rest_handle(&mut self) -> rest::MullvadRestHandle {
-        let service = self.new_request_service(Some(API_HOST.to_owned()));
-        let ip = self.cached_dns_resolver.resolve();
-        let factory =
-            rest::RequestFactory::new(API_HOST.to_owned(), Some(ip), Some("app".to_owned()));
+        let service = self.new_request_service(Some(API_HOST.to_owned()), true, true);
+        let factory = match self.api_address_override {
+            Some(address) => rest::RequestFactory::plain_http(
+                API_HOST.to_owned(),
+                address,
+                Some("app".to_owned()),
+            ),
+            None => {
+                let ip = self.cached_dns_resolver.resolve();
+                rest::RequestFactory::new(API_HOST.to_owned(), Some(ip), Some("app".to_owned()))
+            }
+        };
 
         rest::MullvadRestHandle { service, factory }
     }
 
     /// Returns a new request service handle
     pub fn rest_handle(&mut self) -> rest::RequestServiceHandle {
-        self.new_request_service(None)
+        self.new_request_service(None, false, false)
+    }
+}
+
+/// Reads the API address override from the environment. Only loopback addresses are accepted,
+/// since requests to the override are not encrypted.
+#[cfg(debug_assertions)]
+fn api_address_override() -> Option<SocketAddr> {
+    let value = std::env::var(API_ADDRESS_OVERRIDE_VAR).ok()?;
+    match value.parse::<SocketAddr>() {
+        Ok(address) if address.ip().is_loopback() => {
+            log::warn!("Sending all API requests to {}", address);
+            Some(address)
+        }
+        _ => {
+            log::error!(
+                "Ignoring {}, not a loopback socket address: {}",
+                API_ADDRESS_OVERRIDE_VAR,
+                value
+            );
+            None
+        }
     }
 }
 
+#[cfg(not(debug_assertions))]
+fn api_address_override() -> Option<SocketAddr> {
+    None
+}
+
 impl Drop for MullvadRpcRuntime {
     fn drop(&mut self) {
         if let Ok(runtime) = event_loop::create_runtime() {
@@ -414,6 +520,26 @@ impl WireguardKeyProxy {
             .compat_spawn(async move { rest::deserialize_body(request.await?).await })
     }
 
+    pub fn list_wireguard_keys(
+        &mut self,
+        account_token: AccountToken,
+    ) -> impl Future01<Item = Vec<mullvad_types::wireguard::RegisteredKey>, Error = rest::Error>
+    {
+        let service = self.handle.service.clone();
+
+        let request = rest::send_request(
+            &self.handle.factory,
+            service,
+            &"/v1/wireguard-keys",
+            Method::GET,
+            Some(account_token),
+            StatusCode::OK,
+        );
+        self.handle
+            .service
+            .compat_spawn(async move { rest::deserialize_body(request.await?).await })
+    }
+
     pub fn remove_wireguard_key(
         &mut self,
         account_token: AccountToken,
@@ -0,0 +1,106 @@
+use crate::rest::{Error, Response};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Upper bounds, in seconds, of the buckets that request latencies are counted in.
+pub const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Statistics for the requests sent to the Mullvad API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ApiMetrics {
+    /// Number of finished requests per latency bucket. The last count is for requests that were
+    /// slower than the largest bucket bound.
+    pub latency_counts: [u64; LATENCY_BUCKETS.len() + 1],
+    /// Sum of the latencies of all finished requests.
+    pub latency_sum: Duration,
+    /// Number of finished requests, successful or not.
+    pub requests: u64,
+    /// Number of failed requests by kind of failure.
+    pub errors: BTreeMap<&'static str, u64>,
+}
+
+impl ApiMetrics {
+    fn record(&mut self, latency: Duration, result: &Result<Response, Error>) {
+        let latency_secs = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| latency_secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.latency_counts[bucket] += 1;
+        self.latency_sum += latency;
+        self.requests += 1;
+
+        if let Some(kind) = error_kind(result) {
+            *self.errors.entry(kind).or_insert(0) += 1;
+        }
+    }
+}
+
+fn error_kind(result: &Result<Response, Error>) -> Option<&'static str> {
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            if status.is_client_error() || status.is_server_error() {
+                Some("http_status")
+            } else {
+                None
+            }
+        }
+        Err(Error::TimeoutError(_)) => Some("timeout"),
+        Err(Error::Cancelled(_)) => Some("cancelled"),
+        Err(Error::HyperError(_)) => Some("network"),
+        Err(_) => Some("other"),
+    }
+}
+
+/// Collects `ApiMetrics` from all request services that share it.
+#[derive(Clone, Default)]
+pub(crate) struct MetricsRecorder(Arc<Mutex<ApiMetrics>>);
+
+impl MetricsRecorder {
+    pub fn record(&self, latency: Duration, result: &Result<Response, Error>) {
+        if let Ok(mut metrics) = self.0.lock() {
+            metrics.record(latency, result);
+        }
+    }
+
+    pub fn snapshot(&self) -> ApiMetrics {
+        self.0
+            .lock()
+            .map(|metrics| metrics.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn response(status: u16) -> Result<Response, Error> {
+        Ok(hyper::Response::builder()
+            .status(status)
+            .body(hyper::Body::empty())
+            .unwrap())
+    }
+
+    #[test]
+    fn counts_latencies_and_errors() {
+        let recorder = MetricsRecorder::default();
+        recorder.record(Duration::from_millis(20), &response(200));
+        recorder.record(Duration::from_millis(300), &response(500));
+        recorder.record(Duration::from_secs(60), &Err(Error::SendError));
+
+        let metrics = recorder.snapshot();
+        assert_eq!(metrics.requests, 3);
+        assert_eq!(metrics.latency_counts[0], 1);
+        assert_eq!(metrics.latency_counts[3], 1);
+        assert_eq!(metrics.latency_counts[LATENCY_BUCKETS.len()], 1);
+        assert_eq!(metrics.latency_sum, Duration::from_millis(60_320));
+        assert_eq!(metrics.errors.get("http_status"), Some(&1));
+        assert_eq!(metrics.errors.get("other"), Some(&1));
+        assert_eq!(metrics.errors.len(), 2);
+    }
+}
@@ -0,0 +1,309 @@
+//! Establishes connections through the proxy in [`ApiProxy`], so that API requests can reach the
+//! API from networks where it's not reachable directly.
+
+use mullvad_types::api_proxy::{ApiProxy, ApiProxyProtocol};
+use std::{io, net::IpAddr};
+use talpid_types::net::openvpn::ProxyAuth;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTHENTICATION: u8 = 0;
+const SOCKS_USERNAME_PASSWORD: u8 = 2;
+const SOCKS_USERNAME_PASSWORD_VERSION: u8 = 1;
+const SOCKS_COMMAND_CONNECT: u8 = 1;
+const SOCKS_ADDRESS_TYPE_IPV4: u8 = 1;
+const SOCKS_ADDRESS_TYPE_DOMAIN: u8 = 3;
+const SOCKS_ADDRESS_TYPE_IPV6: u8 = 4;
+const SOCKS_REPLY_SUCCEEDED: u8 = 0;
+
+/// Largest HTTP response header accepted from a proxy.
+const MAX_HTTP_RESPONSE_SIZE: usize = 8 * 1024;
+
+
+/// How connections to the API are established.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ApiConnectionMode {
+    /// Connect directly to the API.
+    Direct,
+    /// Connect through a SOCKS5 or HTTP proxy.
+    Proxied(ApiProxy),
+}
+
+impl Default for ApiConnectionMode {
+    fn default() -> Self {
+        ApiConnectionMode::Direct
+    }
+}
+
+/// Tells whether a connection to the API could be established using a given connection mode.
+#[derive(Debug, Clone)]
+pub struct ApiConnectivityEvent {
+    pub mode: ApiConnectionMode,
+    pub reachable: bool,
+}
+
+/// Callback receiving an [`ApiConnectivityEvent`] for every attempt to connect to the API.
+pub type ApiConnectivityListener = Box<dyn Fn(ApiConnectivityEvent) + Send>;
+
+
+/// Connects to `host`:`port` through `proxy`. The returned stream is connected to the
+/// destination once the proxy has accepted the request.
+pub async fn connect(proxy: &ApiProxy, host: &str, port: u16) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(proxy.address).await?;
+    stream.set_nodelay(true)?;
+    match proxy.protocol {
+        ApiProxyProtocol::Socks5 => {
+            socks5_connect(&mut stream, host, port, proxy.auth.as_ref()).await?
+        }
+        ApiProxyProtocol::Http => {
+            http_connect(&mut stream, host, port, proxy.auth.as_ref()).await?
+        }
+    }
+    Ok(stream)
+}
+
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<()> {
+    let method = if auth.is_some() {
+        SOCKS_USERNAME_PASSWORD
+    } else {
+        SOCKS_NO_AUTHENTICATION
+    };
+    stream.write_all(&[SOCKS_VERSION, 1, method]).await?;
+
+    let mut response = [0u8; 2];
+    stream.read_exact(&mut response).await?;
+    if response[0] != SOCKS_VERSION || response[1] != method {
+        return Err(proxy_error(
+            "SOCKS5 proxy rejected the authentication method",
+        ));
+    }
+
+    if let Some(auth) = auth {
+        let mut request = vec![SOCKS_USERNAME_PASSWORD_VERSION];
+        push_socks_string(&mut request, &auth.username)?;
+        push_socks_string(&mut request, &auth.password)?;
+        stream.write_all(&request).await?;
+
+        stream.read_exact(&mut response).await?;
+        if response[1] != SOCKS_REPLY_SUCCEEDED {
+            return Err(proxy_error("SOCKS5 proxy rejected the credentials"));
+        }
+    }
+
+    let mut request = vec![SOCKS_VERSION, SOCKS_COMMAND_CONNECT, 0];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(address)) => {
+            request.push(SOCKS_ADDRESS_TYPE_IPV4);
+            request.extend_from_slice(&address.octets());
+        }
+        Ok(IpAddr::V6(address)) => {
+            request.push(SOCKS_ADDRESS_TYPE_IPV6);
+            request.extend_from_slice(&address.octets());
+        }
+        Err(_) => {
+            request.push(SOCKS_ADDRESS_TYPE_DOMAIN);
+            push_socks_string(&mut request, host)?;
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[1] != SOCKS_REPLY_SUCCEEDED {
+        return Err(proxy_error(&format!(
+            "SOCKS5 proxy failed to connect, reply code {}",
+            reply[1]
+        )));
+    }
+    // Skip the address that the proxy bound to, followed by the port.
+    let bound_address_len = match reply[3] {
+        SOCKS_ADDRESS_TYPE_IPV4 => 4,
+        SOCKS_ADDRESS_TYPE_IPV6 => 16,
+        SOCKS_ADDRESS_TYPE_DOMAIN => usize::from(stream.read_u8().await?),
+        _ => return Err(proxy_error("SOCKS5 proxy replied with an invalid address")),
+    };
+    let mut bound_address = vec![0u8; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(())
+}
+
+fn push_socks_string(buffer: &mut Vec<u8>, value: &str) -> io::Result<()> {
+    if value.is_empty() || value.len() > 255 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "SOCKS5 strings must be between 1 and 255 bytes long",
+        ));
+    }
+    buffer.push(value.len() as u8);
+    buffer.extend_from_slice(value.as_bytes());
+    Ok(())
+}
+
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: Option<&ProxyAuth>,
+) -> io::Result<()> {
+    let authority = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(address)) => format!("[{}]:{}", address, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut request = format!(
+        "CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n",
+        authority = authority
+    );
+    if let Some(auth) = auth {
+        let credentials = base64::encode(&format!("{}:{}", auth.username, auth.password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read one byte at a time, so that nothing beyond the response header is consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE_SIZE {
+            return Err(proxy_error("HTTP proxy response is too large"));
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or("");
+    let status_code = status_line.split_whitespace().nth(1);
+    if status_code != Some("200") {
+        return Err(proxy_error(&format!(
+            "HTTP proxy failed to connect: {}",
+            status_line
+        )));
+    }
+    Ok(())
+}
+
+fn proxy_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, message)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, SocketAddr};
+    use tokio::net::TcpListener;
+
+    fn run<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new()
+            .basic_scheduler()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    fn proxy(protocol: ApiProxyProtocol, address: SocketAddr) -> ApiProxy {
+        ApiProxy {
+            protocol,
+            address,
+            auth: Some(ProxyAuth {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_socks5_connect() {
+        run(async {
+            let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let proxy = proxy(ApiProxyProtocol::Socks5, listener.local_addr().unwrap());
+
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut greeting = [0u8; 3];
+                stream.read_exact(&mut greeting).await.unwrap();
+                assert_eq!(greeting, [5, 1, 2]);
+                stream.write_all(&[5, 2]).await.unwrap();
+
+                let mut auth = [0u8; 11];
+                stream.read_exact(&mut auth).await.unwrap();
+                assert_eq!(&auth, b"\x01\x04user\x04pass");
+                stream.write_all(&[1, 0]).await.unwrap();
+
+                let mut request = [0u8; 10];
+                stream.read_exact(&mut request).await.unwrap();
+                assert_eq!(request, [5, 1, 0, 1, 193, 138, 218, 78, 1, 187]);
+                stream
+                    .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0, b'o', b'k'])
+                    .await
+                    .unwrap();
+            });
+
+            let mut stream = connect(&proxy, "193.138.218.78", 443).await.unwrap();
+            let mut data = [0u8; 2];
+            stream.read_exact(&mut data).await.unwrap();
+            assert_eq!(&data, b"ok");
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_http_connect() {
+        run(async {
+            let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let proxy = proxy(ApiProxyProtocol::Http, listener.local_addr().unwrap());
+
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let expected = "CONNECT api.mullvad.net:443 HTTP/1.1\r\n\
+                                Host: api.mullvad.net:443\r\n\
+                                Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n";
+                let mut request = vec![0u8; expected.len()];
+                stream.read_exact(&mut request).await.unwrap();
+                assert_eq!(String::from_utf8(request).unwrap(), expected);
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\nok")
+                    .await
+                    .unwrap();
+            });
+
+            let mut stream = connect(&proxy, "api.mullvad.net", 443).await.unwrap();
+            let mut data = [0u8; 2];
+            stream.read_exact(&mut data).await.unwrap();
+            assert_eq!(&data, b"ok");
+            server.await.unwrap();
+        });
+    }
+
+    #[test]
+    fn test_http_connect_rejected() {
+        run(async {
+            let mut listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            let mut proxy = proxy(ApiProxyProtocol::Http, listener.local_addr().unwrap());
+            proxy.auth = None;
+
+            let server = tokio::spawn(async move {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 64];
+                let _ = stream.read(&mut buffer).await.unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                    .await
+                    .unwrap();
+            });
+
+            assert!(connect(&proxy, "api.mullvad.net", 443).await.is_err());
+            server.await.unwrap();
+        });
+    }
+}
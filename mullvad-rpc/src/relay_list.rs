@@ -149,11 +149,14 @@ impl ServerRelayList {
         } = wireguard;
 
         let wireguard_endpoint_data =
-            |public_key: wireguard::PublicKey| relay_list::WireguardEndpointData {
-                port_ranges: port_ranges.clone(),
-                ipv4_gateway,
-                ipv6_gateway,
-                public_key,
+            |public_key: wireguard::PublicKey, udp2tcp_ports: Vec<u16>| {
+                relay_list::WireguardEndpointData {
+                    port_ranges: port_ranges.clone(),
+                    ipv4_gateway,
+                    ipv6_gateway,
+                    public_key,
+                    udp2tcp_ports,
+                }
             };
 
         for mut wireguard_relay in relays {
@@ -180,15 +183,17 @@ impl ServerRelayList {
                             .iter_mut()
                             .find(|r| r.hostname == wireguard_relay.relay.hostname)
                         {
-                            Some(relay) => relay
-                                .tunnels
-                                .wireguard
-                                .push(wireguard_endpoint_data(wireguard_relay.public_key)),
+                            Some(relay) => relay.tunnels.wireguard.push(wireguard_endpoint_data(
+                                wireguard_relay.public_key,
+                                wireguard_relay.udp2tcp_ports,
+                            )),
                             None => {
                                 let mut relay = relay(wireguard_relay.relay, location);
                                 relay.ipv6_addr_in = Some(wireguard_relay.ipv6_addr_in);
-                                relay.tunnels.wireguard =
-                                    vec![wireguard_endpoint_data(wireguard_relay.public_key)];
+                                relay.tunnels.wireguard = vec![wireguard_endpoint_data(
+                                    wireguard_relay.public_key,
+                                    wireguard_relay.udp2tcp_ports,
+                                )];
                                 city.relays.push(relay);
                             }
                         };
@@ -338,6 +343,8 @@ struct WireGuardRelay {
     relay: Relay,
     ipv6_addr_in: Ipv6Addr,
     public_key: wireguard::PublicKey,
+    #[serde(default)]
+    udp2tcp_ports: Vec<u16>,
 }
 
 #[derive(Debug, serde::Deserialize)]
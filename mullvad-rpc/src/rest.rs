@@ -1,3 +1,4 @@
+use crate::metrics::MetricsRecorder;
 use futures::{
     channel::{mpsc, oneshot},
     future::{self, Either},
@@ -11,7 +12,14 @@ use hyper::{
     header::{self, HeaderValue},
     Method, Uri,
 };
-use std::{collections::BTreeMap, future::Future, mem, net::IpAddr, str::FromStr, time::Duration};
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    mem,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, Instant},
+};
 use tokio::runtime::Handle;
 
 pub use hyper::StatusCode;
@@ -69,11 +77,17 @@ pub(crate) struct RequestService<C> {
     handle: Handle,
     next_id: u64,
     in_flight_requests: BTreeMap<u64, CancelHandle>,
+    metrics: Option<MetricsRecorder>,
 }
 
 impl<C: Connect + Clone + Send + Sync + 'static> RequestService<C> {
-    /// Constructs a new request service.
-    pub fn new(connector: C, handle: Handle) -> RequestService<C> {
+    /// Constructs a new request service. If `metrics` is given, the latency and outcome of every
+    /// request is recorded in it.
+    pub fn new(
+        connector: C,
+        handle: Handle,
+        metrics: Option<MetricsRecorder>,
+    ) -> RequestService<C> {
         let client = Self::new_client(connector.clone());
 
         let (command_tx, command_rx) = mpsc::channel(1);
@@ -85,6 +99,7 @@ impl<C: Connect + Clone + Send + Sync + 'static> RequestService<C> {
             next_id: 0,
             connector,
             handle,
+            metrics,
         }
     }
 
@@ -106,6 +121,8 @@ impl<C: Connect + Clone + Send + Sync + 'static> RequestService<C> {
                 let id = self.id();
                 let mut tx = self.command_tx.clone();
                 let timeout = request.timeout();
+                let metrics = self.metrics.clone();
+                let start = Instant::now();
 
                 let (request_future, cancel_handle) = Cancellable::new(
                     self.client
@@ -122,6 +139,9 @@ impl<C: Connect + Clone + Send + Sync + 'static> RequestService<C> {
                     .map_err(Error::TimeoutError);
 
                     let response = flatten_result(flatten_result(response));
+                    if let Some(metrics) = metrics {
+                        metrics.record(start.elapsed(), &response);
+                    }
 
                     if completion_tx.send(response).is_err() {
                         log::trace!(
@@ -328,6 +348,8 @@ pub struct ErrorResponse {
 pub struct RequestFactory {
     host: String,
     address: Option<IpAddr>,
+    port: Option<u16>,
+    scheme: &'static str,
     path_prefix: Option<String>,
 }
 
@@ -337,6 +359,20 @@ impl RequestFactory {
         Self {
             host,
             address,
+            port: None,
+            scheme: "https",
+            path_prefix,
+        }
+    }
+
+    /// Creates a factory for requests sent over plain HTTP to `address`. The connector only
+    /// permits this for loopback addresses, such as a mock API server used in tests.
+    pub fn plain_http(host: String, address: SocketAddr, path_prefix: Option<String>) -> Self {
+        Self {
+            host,
+            address: Some(address.ip()),
+            port: Some(address.port()),
+            scheme: "http",
             path_prefix,
         }
     }
@@ -391,12 +427,13 @@ impl RequestFactory {
     }
 
     fn get_uri(&self, path: &str) -> Result<Uri> {
-        let host: &dyn std::fmt::Display = &self
-            .address
-            .map(|addr| addr.to_string())
-            .unwrap_or_else(|| self.host.clone());
+        let host = match (self.address, self.port) {
+            (Some(addr), Some(port)) => SocketAddr::new(addr, port).to_string(),
+            (Some(addr), None) => addr.to_string(),
+            (None, _) => self.host.clone(),
+        };
         let prefix = self.path_prefix.as_ref().map(AsRef::as_ref).unwrap_or("");
-        let uri = format!("https://{}/{}{}", host, prefix, path);
+        let uri = format!("{}://{}/{}{}", self.scheme, host, prefix, path);
         hyper::Uri::from_str(&uri).map_err(Error::UriError)
     }
 }
@@ -490,7 +527,13 @@ pub fn post_request_with_json<B: serde::Serialize>(
 }
 
 
-pub async fn deserialize_body<T: serde::de::DeserializeOwned>(mut response: Response) -> Result<T> {
+pub async fn deserialize_body<T: serde::de::DeserializeOwned>(response: Response) -> Result<T> {
+    let body = read_body(response).await?;
+    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+}
+
+/// Reads the entire body of a response.
+pub async fn read_body(mut response: Response) -> Result<Vec<u8>> {
     let body_length: usize = response
         .headers()
         .get(header::CONTENT_LENGTH)
@@ -502,8 +545,7 @@ pub async fn deserialize_body<T: serde::de::DeserializeOwned>(mut response: Resp
     while let Some(chunk) = response.body_mut().next().await {
         body.extend(&chunk?);
     }
-
-    serde_json::from_slice(&body).map_err(Error::DeserializeError)
+    Ok(body)
 }
 
 pub async fn parse_rest_response(
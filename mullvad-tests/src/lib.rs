@@ -5,6 +5,7 @@ use futures::sync::oneshot;
 use jsonrpc_client_core::{Future, Transport};
 use jsonrpc_client_ipc::IpcTransport;
 use mullvad_ipc_client::DaemonRpcClient;
+use mullvad_mock_api::MockApiServer;
 use notify::{RawEvent, RecommendedWatcher, RecursiveMode, Watcher};
 use std::{
     cmp,
@@ -18,6 +19,7 @@ use std::{
 use tempfile::TempDir;
 use tokio::reactor::Handle;
 
+pub use mullvad_mock_api as mock_api;
 pub use notify::op::{self as watch_event, Op as WatchEvent};
 
 
@@ -259,16 +261,21 @@ pub struct DaemonRunner {
 
 impl DaemonRunner {
     pub fn spawn() -> Self {
-        Self::spawn_internal()
+        Self::spawn_internal(None)
     }
 
-    fn spawn_internal() -> Self {
+    /// Spawns a daemon that sends all API requests to `mock_api` instead of the real API.
+    pub fn spawn_with_mock_api(mock_api: &MockApiServer) -> Self {
+        Self::spawn_internal(Some(mock_api))
+    }
+
+    fn spawn_internal(mock_api: Option<&MockApiServer>) -> Self {
         let (temp_dir, cache_dir, resource_dir, settings_dir) = prepare_test_dirs();
         let mock_openvpn_args_file = temp_dir.path().join(MOCK_OPENVPN_ARGS_FILE);
 
         let rpc_socket_path = temp_dir.path().join("rpc_socket");
 
-        let expression = duct::cmd!(DAEMON_EXECUTABLE_PATH, "-v", "--disable-log-to-file")
+        let mut expression = duct::cmd!(DAEMON_EXECUTABLE_PATH, "-v", "--disable-log-to-file")
             .dir("..")
             .env("MULLVAD_CACHE_DIR", cache_dir)
             .env("MULLVAD_RPC_SOCKET_PATH", rpc_socket_path.clone())
@@ -277,6 +284,9 @@ impl DaemonRunner {
             .env("MOCK_OPENVPN_ARGS_FILE", mock_openvpn_args_file.clone())
             .stdout_null()
             .stderr_null();
+        if let Some(mock_api) = mock_api {
+            expression = expression.env("MULLVAD_API_ADDR", mock_api.address().to_string());
+        }
 
 
         let process = expression.start().expect("Failed to start daemon");
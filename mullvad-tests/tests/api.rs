@@ -0,0 +1,57 @@
+#![cfg(feature = "integration-tests")]
+
+use chrono::{offset::Utc, Duration};
+use mullvad_tests::{
+    mock_api::{MockApiServer, Route},
+    DaemonRunner,
+};
+use mullvad_types::wireguard::KeygenEvent;
+
+const ACCOUNT: &str = "1234567890123456";
+
+#[test]
+fn reports_key_limit() {
+    let mock_api = MockApiServer::spawn().unwrap();
+    mock_api.add_account(ACCOUNT, Utc::now() + Duration::days(30));
+    mock_api.set_key_limit(0);
+
+    let mut daemon = DaemonRunner::spawn_with_mock_api(&mock_api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+    rpc_client.set_account(Some(ACCOUNT.to_owned())).unwrap();
+
+    let event = rpc_client.generate_wireguard_key().unwrap();
+
+    assert!(matches!(event, KeygenEvent::TooManyKeys));
+    assert!(mock_api.wireguard_keys(ACCOUNT).is_empty());
+}
+
+#[test]
+fn reports_expired_account() {
+    let expiry = Utc::now() - Duration::days(1);
+    let mock_api = MockApiServer::spawn().unwrap();
+    mock_api.add_account(ACCOUNT, expiry);
+
+    let mut daemon = DaemonRunner::spawn_with_mock_api(&mock_api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    let account_data = rpc_client.get_account_data(ACCOUNT.to_owned()).unwrap();
+
+    assert_eq!(account_data.expiry.timestamp(), expiry.timestamp());
+    assert!(account_data.expiry < Utc::now());
+}
+
+#[test]
+fn recovers_from_api_outage() {
+    let mock_api = MockApiServer::spawn().unwrap();
+    mock_api.add_account(ACCOUNT, Utc::now() + Duration::days(30));
+    mock_api.set_outage(true);
+
+    let mut daemon = DaemonRunner::spawn_with_mock_api(&mock_api);
+    let mut rpc_client = daemon.rpc_client().unwrap();
+
+    assert!(rpc_client.get_account_data(ACCOUNT.to_owned()).is_err());
+
+    mock_api.set_outage(false);
+    assert!(rpc_client.get_account_data(ACCOUNT.to_owned()).is_ok());
+    assert!(mock_api.request_count(Route::GetAccount) >= 2);
+}
@@ -186,6 +186,8 @@ fn get_default_endpoint() -> TunnelEndpoint {
             protocol: TransportProtocol::Udp,
         },
         tunnel_type: TunnelType::OpenVpn,
+        proxy: None,
+        network_namespace: None,
     }
 }
 
@@ -1,3 +1,4 @@
+use crate::wireguard;
 use chrono::{offset::Utc, DateTime};
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
@@ -15,6 +16,21 @@ pub struct AccountData {
     pub expiry: DateTime<Utc>,
 }
 
+/// An account in the account history of the daemon.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountHistoryEntry {
+    pub account: AccountToken,
+    /// Name that the account can be switched to by. Labeled accounts are never evicted from the
+    /// history.
+    pub label: Option<String>,
+    /// When the account was last set as the current account.
+    pub last_used: Option<DateTime<Utc>>,
+    /// Expiry as of the last time it was fetched from the API.
+    pub expiry: Option<DateTime<Utc>>,
+    /// Public key of the WireGuard key that is stored for the account, if any.
+    pub wireguard_key: Option<wireguard::PublicKey>,
+}
+
 /// Data structure that's returned from successful invocation of the mullvad API's
 /// `/v1/submit-voucher` RPC.
 #[derive(Deserialize, Serialize, Debug)]
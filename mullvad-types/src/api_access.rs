@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+
+/// A way for the daemon to reach the Mullvad API. When the API can't be reached, the daemon
+/// rotates among the allowed methods until one of them works.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiAccessMethod {
+    /// Connect to the API directly.
+    Direct,
+    /// Connect through a Shadowsocks bridge from the relay list.
+    Bridges,
+    /// Connect through the proxy configured in the `api_proxy` setting.
+    Proxy,
+}
+
+impl ApiAccessMethod {
+    /// All methods, in the order that they are tried in.
+    pub const ALL: [ApiAccessMethod; 3] = [
+        ApiAccessMethod::Proxy,
+        ApiAccessMethod::Direct,
+        ApiAccessMethod::Bridges,
+    ];
+}
+
+impl fmt::Display for ApiAccessMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiAccessMethod::Direct => "direct".fmt(f),
+            ApiAccessMethod::Bridges => "bridges".fmt(f),
+            ApiAccessMethod::Proxy => "proxy".fmt(f),
+        }
+    }
+}
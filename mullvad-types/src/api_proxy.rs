@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr};
+use talpid_types::net::{openvpn::ProxyAuth, Endpoint, TransportProtocol};
+
+
+/// Proxy server that all requests to the Mullvad API are sent through, for networks where the
+/// API can't be reached directly.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
+pub struct ApiProxy {
+    pub protocol: ApiProxyProtocol,
+    pub address: SocketAddr,
+    pub auth: Option<ProxyAuth>,
+}
+
+impl ApiProxy {
+    /// The endpoint that the firewall has to allow for the proxy to be reachable.
+    pub fn get_endpoint(&self) -> Endpoint {
+        Endpoint {
+            address: self.address,
+            protocol: TransportProtocol::Tcp,
+        }
+    }
+}
+
+impl fmt::Display for ApiProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} proxy at {}", self.protocol, self.address)?;
+        if let Some(ref auth) = self.auth {
+            write!(f, " as {}", auth.username)?;
+        }
+        Ok(())
+    }
+}
+
+/// Protocols that can be used to talk to an [`ApiProxy`].
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiProxyProtocol {
+    /// SOCKS5 (RFC 1928), with optional username/password authentication (RFC 1929).
+    Socks5,
+    /// HTTP proxy supporting the `CONNECT` method, with optional basic authentication.
+    Http,
+}
+
+impl fmt::Display for ApiProxyProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiProxyProtocol::Socks5 => "SOCKS5".fmt(f),
+            ApiProxyProtocol::Http => "HTTP".fmt(f),
+        }
+    }
+}
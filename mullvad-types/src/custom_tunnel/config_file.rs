@@ -0,0 +1,898 @@
+//! Conversion between custom tunnels and the configuration files of other WireGuard and OpenVPN
+//! clients, i.e. `wg-quick` style `.conf` files and OpenVPN `.ovpn` profiles.
+
+use super::{ConnectionConfig, CustomTunnelEndpoint};
+use ipnetwork::IpNetwork;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fmt::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+use talpid_types::net::{openvpn, wireguard, Endpoint, TransportProtocol};
+
+/// Port used by OpenVPN when neither `remote` nor `port` sets one.
+const DEFAULT_OPENVPN_PORT: u16 = 1194;
+
+/// The data channel cipher that the daemon always uses for OpenVPN.
+const OPENVPN_CIPHER: &str = "AES-256-CBC";
+
+
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ImportError {
+    #[error(display = "Line {} is not a valid directive", _0)]
+    InvalidLine(usize),
+
+    #[error(display = "Invalid value for {} on line {}", _1, _0)]
+    InvalidValue(usize, String),
+
+    #[error(display = "The configuration has no {} directive", _0)]
+    MissingDirective(&'static str),
+
+    #[error(display = "The inline <{}> block is not closed", _0)]
+    UnclosedBlock(String),
+
+    #[error(
+        display = "The configuration has no IPv4 DNS server. DNS requests may only be sent to \
+                   the DNS servers of the tunnel"
+    )]
+    MissingIpv4DnsServer,
+
+    #[error(
+        display = "The OpenVPN configuration has no inline <auth-user-pass> block, and no \
+                   credentials were given"
+    )]
+    MissingCredentials,
+
+    #[error(
+        display = "The endpoint on line {} must be an IP address, only the first peer may use a \
+                   hostname",
+        _0
+    )]
+    PeerEndpointNotAnIp(usize),
+
+    #[error(display = "Only one peer is supported on this platform")]
+    MultiplePeersUnsupported,
+
+    #[error(display = "Unsupported directives: {}", _0)]
+    UnsupportedDirectives(ConfigDirectives),
+}
+
+/// A directive in a configuration file, identified by its name and line number.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfigDirective {
+    pub line: usize,
+    pub name: String,
+}
+
+impl ConfigDirective {
+    fn new(line: usize, name: &str) -> Self {
+        ConfigDirective {
+            line,
+            name: name.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigDirective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (line {})", self.name, self.line)
+    }
+}
+
+/// A list of directives that is displayed as a comma separated list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigDirectives(pub Vec<ConfigDirective>);
+
+impl fmt::Display for ConfigDirectives {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, directive) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str(", ")?;
+            }
+            directive.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A configuration file to import a custom tunnel from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomTunnelConfigFile {
+    /// The contents of a `wg-quick` style `.conf` file. The gateways of the tunnel are not part
+    /// of the file, so they are given separately.
+    Wireguard {
+        config: String,
+        ipv4_gateway: Ipv4Addr,
+        ipv6_gateway: Option<Ipv6Addr>,
+    },
+    /// The contents of an OpenVPN profile. `credentials` is the contents of a file with the
+    /// username and password on separate lines, for profiles without an inline
+    /// `<auth-user-pass>` block.
+    OpenVpn {
+        config: String,
+        credentials: Option<String>,
+    },
+}
+
+/// A custom tunnel read from a configuration file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedTunnel {
+    pub endpoint: CustomTunnelEndpoint,
+    /// Directives that were understood but have no effect, usually because the daemon always
+    /// sets them itself.
+    pub ignored_directives: Vec<ConfigDirective>,
+}
+
+impl ImportedTunnel {
+    /// Parses a configuration file. Directives that would change the behavior of the tunnel in
+    /// ways the daemon can't honor, such as running scripts or using client certificates, are
+    /// refused and reported all at once.
+    pub fn from_config_file(file: &CustomTunnelConfigFile) -> Result<Self, ImportError> {
+        match file {
+            CustomTunnelConfigFile::Wireguard {
+                config,
+                ipv4_gateway,
+                ipv6_gateway,
+            } => parse_wireguard_config(config, *ipv4_gateway, *ipv6_gateway),
+            CustomTunnelConfigFile::OpenVpn {
+                config,
+                credentials,
+            } => parse_openvpn_config(config, credentials.as_deref()),
+        }
+    }
+}
+
+/// Returns the lines of a `wg-quick` style configuration file with their line numbers, without
+/// comments and surrounding whitespace. Empty lines are left out.
+fn wireguard_config_lines(config: &str) -> impl Iterator<Item = (usize, &str)> {
+    config
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let line = match line.find('#') {
+                Some(comment_start) => &line[..comment_start],
+                None => line,
+            };
+            (index + 1, line.trim())
+        })
+        .filter(|(_, line)| !line.is_empty())
+}
+
+fn parse_key(line: usize, name: &str, value: &str) -> Result<[u8; 32], ImportError> {
+    let invalid_value = || ImportError::InvalidValue(line, name.to_owned());
+    let bytes = base64::decode(value).map_err(|_| invalid_value())?;
+    if bytes.len() != 32 {
+        return Err(invalid_value());
+    }
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&bytes);
+    Ok(key)
+}
+
+/// Splits `host:port`, where an IPv6 host is enclosed in brackets.
+fn parse_host_and_port(value: &str) -> Option<(String, u16)> {
+    let separator = value.rfind(':')?;
+    let port = value[separator + 1..].parse().ok()?;
+    let host = value[..separator]
+        .trim_start_matches('[')
+        .trim_end_matches(']');
+    if host.is_empty() {
+        return None;
+    }
+    Some((host.to_owned(), port))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WireguardSection {
+    None,
+    Interface,
+    Peer,
+    /// A section that is not supported, and has been reported as such.
+    Unsupported,
+}
+
+/// The directives of a `[Peer]` section.
+#[derive(Default)]
+struct WireguardPeerSection {
+    public_key: Option<[u8; 32]>,
+    psk: Option<[u8; 32]>,
+    endpoint: Option<(usize, String, u16)>,
+    allowed_ips: Option<Vec<IpNetwork>>,
+    persistent_keepalive: Option<u16>,
+}
+
+impl WireguardPeerSection {
+    fn into_peer_config(self) -> Result<(String, wireguard::PeerConfig), ImportError> {
+        let public_key = self
+            .public_key
+            .ok_or(ImportError::MissingDirective("PublicKey"))?;
+        let (_, host, port) = self
+            .endpoint
+            .ok_or(ImportError::MissingDirective("Endpoint"))?;
+        let allowed_ips = self
+            .allowed_ips
+            .ok_or(ImportError::MissingDirective("AllowedIPs"))?;
+        let peer = wireguard::PeerConfig {
+            public_key: public_key.into(),
+            allowed_ips,
+            endpoint: SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), port),
+            persistent_keepalive: self.persistent_keepalive,
+            psk: self.psk.map(wireguard::PresharedKey::from),
+        };
+        Ok((host, peer))
+    }
+}
+
+fn parse_wireguard_config(
+    config: &str,
+    ipv4_gateway: Ipv4Addr,
+    ipv6_gateway: Option<Ipv6Addr>,
+) -> Result<ImportedTunnel, ImportError> {
+    let mut section = WireguardSection::None;
+    let mut private_key = None;
+    let mut addresses = vec![];
+    let mut dns_servers = vec![];
+    let mut peers: Vec<WireguardPeerSection> = vec![];
+    let mut ignored_directives = vec![];
+    let mut unsupported_directives = vec![];
+
+    for (line, content) in wireguard_config_lines(config) {
+        if content.starts_with('[') {
+            section = match content.to_lowercase().as_str() {
+                "[interface]" => WireguardSection::Interface,
+                "[peer]" => {
+                    peers.push(WireguardPeerSection::default());
+                    WireguardSection::Peer
+                }
+                _ => {
+                    unsupported_directives.push(ConfigDirective::new(line, content));
+                    WireguardSection::Unsupported
+                }
+            };
+            continue;
+        }
+
+        let separator = content.find('=').ok_or(ImportError::InvalidLine(line))?;
+        let name = content[..separator].trim();
+        let value = content[separator + 1..].trim();
+        let invalid_value = || ImportError::InvalidValue(line, name.to_owned());
+        let peer = match section {
+            WireguardSection::None => return Err(ImportError::InvalidLine(line)),
+            WireguardSection::Unsupported => continue,
+            WireguardSection::Interface => None,
+            WireguardSection::Peer => peers.last_mut(),
+        };
+
+        match (peer, name.to_lowercase().as_str()) {
+            (None, "privatekey") => {
+                private_key = Some(parse_key(line, name, value)?);
+            }
+            (None, "address") => {
+                for address in value.split(',') {
+                    let network: IpNetwork = address.trim().parse().map_err(|_| invalid_value())?;
+                    addresses.push(network.ip());
+                }
+            }
+            (None, "dns") => {
+                for server in value.split(',') {
+                    dns_servers.push(
+                        server
+                            .trim()
+                            .parse::<IpAddr>()
+                            .map_err(|_| invalid_value())?,
+                    );
+                }
+            }
+            (None, "listenport") | (None, "mtu") => {
+                ignored_directives.push(ConfigDirective::new(line, name));
+            }
+            (Some(peer), "publickey") => {
+                peer.public_key = Some(parse_key(line, name, value)?);
+            }
+            (Some(peer), "presharedkey") => {
+                peer.psk = Some(parse_key(line, name, value)?);
+            }
+            (Some(peer), "endpoint") => {
+                let (host, port) = parse_host_and_port(value).ok_or_else(invalid_value)?;
+                peer.endpoint = Some((line, host, port));
+            }
+            (Some(peer), "allowedips") => {
+                let mut networks = vec![];
+                for network in value.split(',') {
+                    networks.push(network.trim().parse().map_err(|_| invalid_value())?);
+                }
+                peer.allowed_ips = Some(networks);
+            }
+            (Some(peer), "persistentkeepalive") => {
+                // wg-quick accepts "off" as well as 0 to disable keepalive.
+                peer.persistent_keepalive = match value {
+                    "off" | "0" => None,
+                    _ => Some(value.parse().map_err(|_| invalid_value())?),
+                };
+            }
+            _ => unsupported_directives.push(ConfigDirective::new(line, name)),
+        }
+    }
+
+    if !unsupported_directives.is_empty() {
+        return Err(ImportError::UnsupportedDirectives(ConfigDirectives(
+            unsupported_directives,
+        )));
+    }
+
+    let private_key = private_key.ok_or(ImportError::MissingDirective("PrivateKey"))?;
+    // The firewall on Windows only allows traffic to a single peer endpoint.
+    if cfg!(windows) && peers.len() > 1 {
+        return Err(ImportError::MultiplePeersUnsupported);
+    }
+    let mut peers = peers.into_iter();
+    let (host, peer) = peers
+        .next()
+        .ok_or(ImportError::MissingDirective("[Peer]"))?
+        .into_peer_config()?;
+    // Only the host of the first peer is resolved when connecting, so the other peers must be
+    // given by IP.
+    let mut additional_peers = vec![];
+    for section in peers {
+        let endpoint_line = section.endpoint.as_ref().map(|(line, ..)| *line);
+        let (host, mut peer) = section.into_peer_config()?;
+        let ip: IpAddr = host
+            .parse()
+            .map_err(|_| ImportError::PeerEndpointNotAnIp(endpoint_line.unwrap_or_default()))?;
+        peer.endpoint.set_ip(ip);
+        additional_peers.push(peer);
+    }
+    if addresses.is_empty() {
+        return Err(ImportError::MissingDirective("Address"));
+    }
+    // DNS requests are only allowed to the DNS servers of the tunnel, and an IPv4 server is
+    // needed since IPv6 may be disabled.
+    if !dns_servers.iter().any(IpAddr::is_ipv4) {
+        return Err(ImportError::MissingIpv4DnsServer);
+    }
+
+    let config = ConnectionConfig::Wireguard(wireguard::ConnectionConfig {
+        tunnel: wireguard::TunnelConfig {
+            private_key: private_key.into(),
+            addresses,
+        },
+        peer,
+        additional_peers,
+        ipv4_gateway,
+        ipv6_gateway,
+        dns_servers,
+        obfuscation: None,
+    });
+    Ok(ImportedTunnel {
+        endpoint: CustomTunnelEndpoint::new(host, config),
+        ignored_directives,
+    })
+}
+
+/// OpenVPN directives that the daemon always sets itself, or that only affect scripts and
+/// platform integration that the daemon handles on its own.
+static IGNORED_OPENVPN_DIRECTIVES: &[&str] = &[
+    "client",
+    "tls-client",
+    "nobind",
+    "persist-key",
+    "persist-tun",
+    "resolv-retry",
+    "verb",
+    "mute",
+    "mute-replay-warnings",
+    "remote-cert-tls",
+    "remote-random",
+    "ping",
+    "ping-restart",
+    "ping-exit",
+    "ping-timer-rem",
+    "sndbuf",
+    "rcvbuf",
+    "fast-io",
+    "tun-ipv6",
+    "auth-nocache",
+    "explicit-exit-notify",
+    "tls-cipher",
+    "tls-ciphersuites",
+    "tls-version-min",
+    "redirect-gateway",
+    "block-outside-dns",
+    "script-security",
+    "up",
+    "down",
+];
+
+fn parse_openvpn_config(
+    config: &str,
+    credentials: Option<&str>,
+) -> Result<ImportedTunnel, ImportError> {
+    let mut remote: Option<(String, Option<u16>, Option<TransportProtocol>)> = None;
+    let mut port = None;
+    let mut protocol = None;
+    let mut uses_user_pass = false;
+    let mut inline_credentials = None;
+    let mut ignored_directives = vec![];
+    let mut unsupported_directives = vec![];
+
+    let mut lines = config
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    while let Some((line, content)) = lines.next() {
+        let content = content.trim();
+        // OpenVPN only treats lines that start with `#` or `;` as comments.
+        if content.is_empty() || content.starts_with(&['#', ';'][..]) {
+            continue;
+        }
+        if content.starts_with('<') && content.ends_with('>') {
+            let tag = content[1..content.len() - 1].to_owned();
+            let closing_tag = format!("</{}>", tag);
+            // The contents of a block are kept as they are, since they may be credentials.
+            let mut block = vec![];
+            loop {
+                match lines.next() {
+                    Some((_, block_line)) if block_line.trim() == closing_tag => break,
+                    Some((_, block_line)) => block.push(block_line),
+                    None => return Err(ImportError::UnclosedBlock(tag)),
+                }
+            }
+            match tag.as_str() {
+                "auth-user-pass" => inline_credentials = Some(block.join("\n")),
+                // Other blocks hold keys and certificates, which the daemon doesn't use. In
+                // particular, the servers are always verified against the built-in CA.
+                _ => unsupported_directives.push(ConfigDirective::new(line, content)),
+            }
+            continue;
+        }
+
+        let mut arguments = content.split_whitespace();
+        let name = arguments.next().unwrap_or_default();
+        let arguments: Vec<&str> = arguments.collect();
+        let invalid_value = || ImportError::InvalidValue(line, name.to_owned());
+
+        match name {
+            "remote" => {
+                if remote.is_some() {
+                    // Only the first remote is used.
+                    ignored_directives.push(ConfigDirective::new(line, name));
+                    continue;
+                }
+                let host = arguments.first().ok_or_else(invalid_value)?;
+                let remote_port = match arguments.get(1) {
+                    Some(remote_port) => Some(remote_port.parse().map_err(|_| invalid_value())?),
+                    None => None,
+                };
+                let remote_protocol = match arguments.get(2) {
+                    Some(remote_protocol) => {
+                        Some(parse_openvpn_protocol(remote_protocol).ok_or_else(invalid_value)?)
+                    }
+                    None => None,
+                };
+                remote = Some((host.to_string(), remote_port, remote_protocol));
+            }
+            "port" | "rport" => {
+                let value = arguments.first().ok_or_else(invalid_value)?;
+                port = Some(value.parse().map_err(|_| invalid_value())?);
+            }
+            "proto" => {
+                let value = arguments.first().ok_or_else(invalid_value)?;
+                protocol = Some(parse_openvpn_protocol(value).ok_or_else(invalid_value)?);
+            }
+            "auth-user-pass" => uses_user_pass = true,
+            // The daemon always uses the same data channel cipher.
+            "cipher" | "data-ciphers" | "ncp-ciphers" => {
+                let ciphers = arguments.first().ok_or_else(invalid_value)?;
+                if ciphers
+                    .split(':')
+                    .any(|cipher| cipher.eq_ignore_ascii_case(OPENVPN_CIPHER))
+                {
+                    ignored_directives.push(ConfigDirective::new(line, name));
+                } else {
+                    unsupported_directives.push(ConfigDirective::new(line, name));
+                }
+            }
+            "dev" => match arguments.first() {
+                Some(device) if device.starts_with("tun") => {
+                    ignored_directives.push(ConfigDirective::new(line, name))
+                }
+                _ => unsupported_directives.push(ConfigDirective::new(line, name)),
+            },
+            _ if IGNORED_OPENVPN_DIRECTIVES.contains(&name) => {
+                ignored_directives.push(ConfigDirective::new(line, name));
+            }
+            _ => unsupported_directives.push(ConfigDirective::new(line, name)),
+        }
+    }
+
+    if !unsupported_directives.is_empty() {
+        return Err(ImportError::UnsupportedDirectives(ConfigDirectives(
+            unsupported_directives,
+        )));
+    }
+
+    let (host, remote_port, remote_protocol) =
+        remote.ok_or(ImportError::MissingDirective("remote"))?;
+    if !uses_user_pass {
+        return Err(ImportError::MissingDirective("auth-user-pass"));
+    }
+    let credentials = inline_credentials
+        .as_deref()
+        .or(credentials)
+        .ok_or(ImportError::MissingCredentials)?;
+    let mut credential_lines = credentials.lines();
+    let username = credential_lines
+        .next()
+        .filter(|username| !username.is_empty())
+        .ok_or(ImportError::MissingCredentials)?;
+    let password = credential_lines.next().unwrap_or_default();
+
+    let port = remote_port.or(port).unwrap_or(DEFAULT_OPENVPN_PORT);
+    let protocol = remote_protocol
+        .or(protocol)
+        .unwrap_or(TransportProtocol::Udp);
+    let config = ConnectionConfig::OpenVpn(openvpn::ConnectionConfig {
+        endpoint: Endpoint::new(Ipv4Addr::UNSPECIFIED, port, protocol),
+        username: username.to_owned(),
+        password: password.to_owned(),
+    });
+    Ok(ImportedTunnel {
+        endpoint: CustomTunnelEndpoint::new(host, config),
+        ignored_directives,
+    })
+}
+
+fn parse_openvpn_protocol(protocol: &str) -> Option<TransportProtocol> {
+    match protocol {
+        "udp" | "udp4" | "udp6" => Some(TransportProtocol::Udp),
+        "tcp" | "tcp4" | "tcp6" | "tcp-client" | "tcp4-client" | "tcp6-client" => {
+            Some(TransportProtocol::Tcp)
+        }
+        _ => None,
+    }
+}
+
+/// Returns a `wg-quick` style configuration file for a WireGuard connection. The gateways are
+/// not part of the file, and are used as DNS servers unless the connection has its own.
+pub fn wireguard_config_file(connection: &wireguard::ConnectionConfig) -> String {
+    let addresses: Vec<String> = connection
+        .tunnel
+        .addresses
+        .iter()
+        .map(|address| match address {
+            IpAddr::V4(address) => format!("{}/32", address),
+            IpAddr::V6(address) => format!("{}/128", address),
+        })
+        .collect();
+    let dns_servers: Vec<String> = connection
+        .get_dns_servers()
+        .iter()
+        .map(IpAddr::to_string)
+        .collect();
+
+    let mut config = String::new();
+    // Writing to a `String` can't fail.
+    let _ = writeln!(config, "[Interface]");
+    let _ = writeln!(config, "PrivateKey = {}", connection.tunnel.private_key);
+    let _ = writeln!(config, "Address = {}", addresses.join(", "));
+    let _ = writeln!(config, "DNS = {}", dns_servers.join(", "));
+    for peer in connection.peers() {
+        let allowed_ips: Vec<String> = peer.allowed_ips.iter().map(IpNetwork::to_string).collect();
+        let _ = writeln!(config);
+        let _ = writeln!(config, "[Peer]");
+        let _ = writeln!(config, "PublicKey = {}", peer.public_key);
+        if let Some(psk) = &peer.psk {
+            let _ = writeln!(config, "PresharedKey = {}", psk);
+        }
+        let _ = writeln!(config, "AllowedIPs = {}", allowed_ips.join(", "));
+        let _ = writeln!(config, "Endpoint = {}", peer.endpoint);
+        if let Some(keepalive) = peer.persistent_keepalive {
+            let _ = writeln!(config, "PersistentKeepalive = {}", keepalive);
+        }
+    }
+    config
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use talpid_types::net::TunnelParameters;
+
+    const PRIVATE_KEY: &str = "mPue6Xt0pdz4NRAhfQSp/SLKo7kV7DW+2zvBq0N9iUI=";
+    const PUBLIC_KEY: &str = "RzYvaDUgFH6EvQazHrc4aopmWlwFgTz+trK/cFSNhzs=";
+    const PRESHARED_KEY: &str = "nbSbOEY/NS4Xc1GJXo1JfsDPnr5KFkZcd0EqMgTa+oQ=";
+    const GATEWAY: Ipv4Addr = Ipv4Addr::new(10, 64, 0, 1);
+
+    fn wireguard_config(extra_lines: &str) -> String {
+        format!(
+            "[Interface]\n\
+             PrivateKey = {}\n\
+             Address = 10.99.0.2/32, fc00:bbbb:bbbb:bb01::2/128\n\
+             DNS = 192.0.2.53\n\
+             {}\n\
+             [Peer]\n\
+             PublicKey = {}\n\
+             AllowedIPs = 0.0.0.0/0, ::0/0 # Everything\n\
+             Endpoint = se-got-wg-001.example.com:51820\n",
+            PRIVATE_KEY, extra_lines, PUBLIC_KEY
+        )
+    }
+
+    #[test]
+    fn test_parse_wireguard_config() {
+        let imported =
+            parse_wireguard_config(&wireguard_config("ListenPort = 51820"), GATEWAY, None).unwrap();
+        assert_eq!(
+            imported.ignored_directives,
+            vec![ConfigDirective::new(5, "ListenPort")]
+        );
+        assert_eq!(
+            imported.endpoint.to_string(),
+            format!(
+                "WireGuard relay - 0.0.0.0:51820 with public key {}",
+                PUBLIC_KEY
+            )
+        );
+        match imported.endpoint.config {
+            ConnectionConfig::Wireguard(connection) => {
+                assert_eq!(connection.tunnel.addresses.len(), 2);
+                assert_eq!(connection.peer.allowed_ips.len(), 2);
+                assert_eq!(connection.ipv4_gateway, GATEWAY);
+                assert_eq!(connection.ipv6_gateway, None);
+                assert_eq!(
+                    connection.dns_servers,
+                    vec![IpAddr::from(Ipv4Addr::new(192, 0, 2, 53))]
+                );
+            }
+            ConnectionConfig::OpenVpn(_) => panic!("Expected a WireGuard config"),
+        }
+    }
+
+    #[test]
+    fn test_wireguard_ipv4_dns_server_is_required() {
+        let config = wireguard_config("").replace("DNS = 192.0.2.53", "DNS = 2001:db8::53");
+        assert_eq!(
+            parse_wireguard_config(&config, GATEWAY, None),
+            Err(ImportError::MissingIpv4DnsServer)
+        );
+        let config = wireguard_config("").replace("DNS = 192.0.2.53\n", "");
+        assert_eq!(
+            parse_wireguard_config(&config, GATEWAY, None),
+            Err(ImportError::MissingIpv4DnsServer)
+        );
+    }
+
+    #[test]
+    fn test_refuse_wireguard_scripts() {
+        let config = wireguard_config("PostUp = iptables -A FORWARD\nTable = off");
+        assert_eq!(
+            parse_wireguard_config(&config, GATEWAY, None),
+            Err(ImportError::UnsupportedDirectives(ConfigDirectives(vec![
+                ConfigDirective::new(5, "PostUp"),
+                ConfigDirective::new(6, "Table"),
+            ])))
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_parse_wireguard_config_with_multiple_peers() {
+        let config = format!(
+            "{}PersistentKeepalive = 25\n\
+             [Peer]\n\
+             PublicKey = {}\n\
+             PresharedKey = {}\n\
+             AllowedIPs = 10.100.0.0/16\n\
+             Endpoint = [2001:db8::1]:51821\n\
+             PersistentKeepalive = off\n",
+            wireguard_config(""),
+            PRIVATE_KEY,
+            PRESHARED_KEY
+        );
+        let imported = parse_wireguard_config(&config, GATEWAY, None).unwrap();
+        let connection = match imported.endpoint.config {
+            ConnectionConfig::Wireguard(connection) => connection,
+            ConnectionConfig::OpenVpn(_) => panic!("Expected a WireGuard config"),
+        };
+        assert_eq!(connection.peer.persistent_keepalive, Some(25));
+        assert_eq!(
+            connection.additional_peers,
+            vec![wireguard::PeerConfig {
+                public_key: parse_key(0, "", PRIVATE_KEY).unwrap().into(),
+                allowed_ips: vec!["10.100.0.0/16".parse().unwrap()],
+                endpoint: "[2001:db8::1]:51821".parse().unwrap(),
+                persistent_keepalive: None,
+                psk: Some(parse_key(0, "", PRESHARED_KEY).unwrap().into()),
+            }]
+        );
+
+        let config = format!(
+            "{}[Peer]\n\
+             PublicKey = {}\n\
+             AllowedIPs = 10.100.0.0/16\n\
+             Endpoint = mesh.example.com:51821\n",
+            wireguard_config(""),
+            PRIVATE_KEY
+        );
+        assert_eq!(
+            parse_wireguard_config(&config, GATEWAY, None),
+            Err(ImportError::PeerEndpointNotAnIp(13))
+        );
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_refuse_multiple_peers() {
+        let config = format!(
+            "{}[Peer]\n\
+             PublicKey = {}\n\
+             AllowedIPs = 10.100.0.0/16\n\
+             Endpoint = 192.0.2.2:51821\n",
+            wireguard_config(""),
+            PRIVATE_KEY
+        );
+        assert_eq!(
+            parse_wireguard_config(&config, GATEWAY, None),
+            Err(ImportError::MultiplePeersUnsupported)
+        );
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_wireguard_config_round_trip() {
+        let config = format!(
+            "{}PersistentKeepalive = 25\n\
+             [Peer]\n\
+             PublicKey = {}\n\
+             PresharedKey = {}\n\
+             AllowedIPs = 10.100.0.0/16\n\
+             Endpoint = 192.0.2.2:51821\n",
+            wireguard_config(""),
+            PRIVATE_KEY,
+            PRESHARED_KEY
+        );
+        let imported = parse_wireguard_config(&config, GATEWAY, None).unwrap();
+        let mut connection = match imported.endpoint.config {
+            ConnectionConfig::Wireguard(connection) => connection,
+            ConnectionConfig::OpenVpn(_) => panic!("Expected a WireGuard config"),
+        };
+        connection.peer.endpoint = "192.0.2.1:51820".parse().unwrap();
+
+        let exported =
+            parse_wireguard_config(&wireguard_config_file(&connection), GATEWAY, None).unwrap();
+        assert_eq!(exported.endpoint.host, "192.0.2.1");
+        assert_eq!(
+            exported.endpoint.config,
+            ConnectionConfig::Wireguard(wireguard::ConnectionConfig {
+                peer: wireguard::PeerConfig {
+                    endpoint: "0.0.0.0:51820".parse().unwrap(),
+                    ..connection.peer.clone()
+                },
+                ..connection
+            })
+        );
+    }
+
+    #[test]
+    fn test_preshared_key_in_tunnel_parameters() {
+        let config = format!("{}PresharedKey = {}\n", wireguard_config(""), PRESHARED_KEY);
+        let mut endpoint = parse_wireguard_config(&config, GATEWAY, None)
+            .unwrap()
+            .endpoint;
+        endpoint.host = "192.0.2.1".to_owned();
+
+        let parameters = endpoint
+            .to_tunnel_parameters(crate::settings::TunnelOptions::default(), None)
+            .unwrap();
+        let connection = match parameters {
+            TunnelParameters::Wireguard(parameters) => parameters.connection,
+            TunnelParameters::OpenVpn(_) => panic!("Expected WireGuard parameters"),
+        };
+        assert_eq!(connection.peer.endpoint, "192.0.2.1:51820".parse().unwrap());
+        assert_eq!(
+            connection.peer.psk,
+            Some(parse_key(0, "", PRESHARED_KEY).unwrap().into())
+        );
+    }
+
+    #[test]
+    fn test_preshared_key_in_settings() {
+        let config = format!("{}PresharedKey = {}\n", wireguard_config(""), PRESHARED_KEY);
+        let endpoint = parse_wireguard_config(&config, GATEWAY, None)
+            .unwrap()
+            .endpoint;
+
+        let mut saved = serde_json::to_value(&endpoint).unwrap();
+        assert_eq!(
+            saved["config"]["wireguard"]["peer"]["psk"],
+            serde_json::json!(PRESHARED_KEY)
+        );
+        let loaded: CustomTunnelEndpoint = serde_json::from_value(saved.clone()).unwrap();
+        assert_eq!(loaded, endpoint);
+
+        // Settings saved before preshared keys were supported have no key
+        saved["config"]["wireguard"]["peer"]
+            .as_object_mut()
+            .unwrap()
+            .remove("psk");
+        let loaded: CustomTunnelEndpoint = serde_json::from_value(saved).unwrap();
+        match loaded.config {
+            ConnectionConfig::Wireguard(connection) => assert_eq!(connection.peer.psk, None),
+            ConnectionConfig::OpenVpn(_) => panic!("Expected a WireGuard config"),
+        }
+    }
+
+    #[test]
+    fn test_parse_openvpn_config() {
+        let config = "client\n\
+                      dev tun\n\
+                      proto tcp\n\
+                      remote se-got-001.example.com 443\n\
+                      remote se-got-002.example.com 443\n\
+                      ; A comment\n\
+                      auth-user-pass\n\
+                      cipher AES-256-CBC\n\
+                      <auth-user-pass>\n\
+                      1234567890\n\
+                      #m; \n\
+                      </auth-user-pass>\n";
+        let imported = parse_openvpn_config(config, None).unwrap();
+        assert_eq!(
+            imported.endpoint,
+            CustomTunnelEndpoint::new(
+                "se-got-001.example.com".to_owned(),
+                ConnectionConfig::OpenVpn(openvpn::ConnectionConfig {
+                    endpoint: Endpoint::new(Ipv4Addr::UNSPECIFIED, 443, TransportProtocol::Tcp),
+                    username: "1234567890".to_owned(),
+                    password: "#m; ".to_owned(),
+                })
+            )
+        );
+        assert_eq!(
+            imported.ignored_directives,
+            vec![
+                ConfigDirective::new(1, "client"),
+                ConfigDirective::new(2, "dev"),
+                ConfigDirective::new(5, "remote"),
+                ConfigDirective::new(8, "cipher"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_refuse_openvpn_credentials_and_scripts() {
+        let config = "remote 192.0.2.1\n\
+                      auth-user-pass\n\
+                      cert client.crt\n\
+                      <tls-crypt>\n\
+                      </tls-crypt>\n\
+                      route-up /tmp/script.sh\n\
+                      ca ca.crt\n\
+                      <ca>\n\
+                      </ca>\n\
+                      cipher AES-128-GCM\n";
+        assert_eq!(
+            parse_openvpn_config(config, Some("user\npass")),
+            Err(ImportError::UnsupportedDirectives(ConfigDirectives(vec![
+                ConfigDirective::new(3, "cert"),
+                ConfigDirective::new(4, "<tls-crypt>"),
+                ConfigDirective::new(6, "route-up"),
+                ConfigDirective::new(7, "ca"),
+                ConfigDirective::new(8, "<ca>"),
+                ConfigDirective::new(10, "cipher"),
+            ])))
+        );
+    }
+
+    #[test]
+    fn test_openvpn_credentials_are_required() {
+        let config = "remote 192.0.2.1\nauth-user-pass credentials.txt\n";
+        assert_eq!(
+            parse_openvpn_config(config, None),
+            Err(ImportError::MissingCredentials)
+        );
+        assert!(parse_openvpn_config(config, Some("user\npass\n")).is_ok());
+    }
+}
@@ -8,6 +8,9 @@ use std::{
 };
 use talpid_types::net::{openvpn, wireguard, Endpoint, TunnelParameters};
 
+mod config_file;
+pub use self::config_file::*;
+
 
 #[derive(err_derive::Error, Debug)]
 pub enum Error {
@@ -79,11 +82,21 @@ impl fmt::Display for CustomTunnelEndpoint {
                 config.endpoint.address.port(),
                 config.endpoint.protocol
             ),
-            ConnectionConfig::Wireguard(connection) => write!(
-                f,
-                "WireGuard relay - {} with public key {}",
-                connection.peer.endpoint, connection.peer.public_key
-            ),
+            ConnectionConfig::Wireguard(connection) => {
+                write!(
+                    f,
+                    "WireGuard relay - {} with public key {}",
+                    connection.peer.endpoint, connection.peer.public_key
+                )?;
+                for peer in &connection.additional_peers {
+                    write!(
+                        f,
+                        ", peer {} with public key {}",
+                        peer.endpoint, peer.public_key
+                    )?;
+                }
+                Ok(())
+            }
         }
     }
 }
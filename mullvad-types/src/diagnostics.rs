@@ -0,0 +1,61 @@
+use crate::api_access::ApiAccessMethod;
+use chrono::{offset::Utc, DateTime};
+use serde::{Deserialize, Serialize};
+use std::{fmt, net::SocketAddr};
+use talpid_types::diagnostics::TunnelDiagnostics;
+
+
+/// Structured report of what the daemon and its subsystems currently see, for debugging issues
+/// without having to read the daemon log.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostics {
+    /// Diagnostics from the tunnel state machine, or `None` if it didn't respond.
+    pub tunnel: Option<TunnelDiagnostics>,
+    pub api: ApiDiagnostics,
+    pub relay_list: RelayListDiagnostics,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ApiDiagnostics {
+    /// The address that direct connections to the API are made to.
+    pub address: SocketAddr,
+    /// Where the API address came from.
+    pub address_source: ApiAddressSource,
+    /// When the API address was last resolved, if it is known.
+    pub address_updated: Option<DateTime<Utc>>,
+    /// The access method currently used to reach the API.
+    pub access_method: ApiAccessMethod,
+}
+
+/// Where the address used to reach the API came from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiAddressSource {
+    /// The address built into the app, used when nothing else is available.
+    Fallback,
+    /// The address loaded from the cache file on startup.
+    Cache,
+    /// The address resolved through DNS since the daemon started.
+    Dns,
+    /// A local address set through `MULLVAD_API_ADDR` in a debug build.
+    Override,
+}
+
+impl fmt::Display for ApiAddressSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiAddressSource::Fallback => "built-in fallback".fmt(f),
+            ApiAddressSource::Cache => "cache".fmt(f),
+            ApiAddressSource::Dns => "DNS".fmt(f),
+            ApiAddressSource::Override => "override".fmt(f),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RelayListDiagnostics {
+    /// When the relay list in use was fetched from the API.
+    pub last_updated: DateTime<Utc>,
+    /// The age of the relay list in seconds.
+    pub age_secs: u64,
+}
@@ -16,6 +16,7 @@ pub enum MullvadEndpoint {
         peer: wireguard::PeerConfig,
         ipv4_gateway: Ipv4Addr,
         ipv6_gateway: Ipv6Addr,
+        obfuscation: Option<wireguard::ObfuscationType>,
     },
 }
 
@@ -24,10 +25,15 @@ impl MullvadEndpoint {
     pub fn to_endpoint(&self) -> Endpoint {
         match self {
             MullvadEndpoint::OpenVpn(endpoint) => *endpoint,
-            MullvadEndpoint::Wireguard { peer, .. } => Endpoint::new(
+            MullvadEndpoint::Wireguard {
+                peer, obfuscation, ..
+            } => Endpoint::new(
                 peer.endpoint.ip(),
                 peer.endpoint.port(),
-                TransportProtocol::Udp,
+                match obfuscation {
+                    Some(wireguard::ObfuscationType::Udp2Tcp) => TransportProtocol::Tcp,
+                    None => TransportProtocol::Udp,
+                },
             ),
         }
     }
@@ -1,11 +1,16 @@
 #![deny(rust_2018_idioms)]
 
 pub mod account;
+pub mod api_access;
+pub mod api_proxy;
 pub mod auth_failed;
+pub mod diagnostics;
 pub mod endpoint;
 pub mod location;
+pub mod log_level;
 pub mod relay_constraints;
 pub mod relay_list;
+pub mod retry_strategy;
 pub mod settings;
 pub mod states;
 pub mod version;
@@ -32,4 +37,7 @@ pub enum DaemonEvent {
 
     /// Key event
     WireguardKey(wireguard::KeygenEvent),
+
+    /// Automatic key rotation event
+    WireguardKeyRotation(wireguard::KeyRotationEvent),
 }
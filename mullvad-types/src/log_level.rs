@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+
+/// Verbosity of the daemon log.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogLevel::Off => "off".fmt(f),
+            LogLevel::Error => "error".fmt(f),
+            LogLevel::Warn => "warn".fmt(f),
+            LogLevel::Info => "info".fmt(f),
+            LogLevel::Debug => "debug".fmt(f),
+            LogLevel::Trace => "trace".fmt(f),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = ParseLogLevelError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        log::LevelFilter::from_str(s)
+            .map(LogLevel::from)
+            .map_err(|_| ParseLogLevelError(s.to_owned()))
+    }
+}
+
+/// Returned when a string is not a valid log level.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseLogLevelError(String);
+
+impl fmt::Display for ParseLogLevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid log level: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseLogLevelError {}
+
+impl From<log::LevelFilter> for LogLevel {
+    fn from(level: log::LevelFilter) -> Self {
+        match level {
+            log::LevelFilter::Off => LogLevel::Off,
+            log::LevelFilter::Error => LogLevel::Error,
+            log::LevelFilter::Warn => LogLevel::Warn,
+            log::LevelFilter::Info => LogLevel::Info,
+            log::LevelFilter::Debug => LogLevel::Debug,
+            log::LevelFilter::Trace => LogLevel::Trace,
+        }
+    }
+}
+
+impl From<LogLevel> for log::LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Log level filter that can be changed while the daemon is running. Module filters apply to the
+/// named module and all of its submodules, and the most specific one wins.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct LogFilter {
+    /// Level used for all modules without a more specific filter.
+    pub level: LogLevel,
+    /// Levels for individual modules, keyed by module path, e.g. `talpid_core::firewall`.
+    #[serde(default)]
+    pub modules: BTreeMap<String, LogLevel>,
+}
+
+impl LogFilter {
+    pub fn new(level: LogLevel) -> Self {
+        LogFilter {
+            level,
+            modules: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the level of the most specific module filter matching `target`, if any.
+    pub fn module_level(&self, target: &str) -> Option<LogLevel> {
+        self.modules
+            .iter()
+            .filter(|(module, _)| is_within_module(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+    }
+
+    /// Returns the level that applies to log records with the given target.
+    pub fn level_for(&self, target: &str) -> LogLevel {
+        self.module_level(target).unwrap_or(self.level)
+    }
+
+    /// Returns the most verbose level of any filter.
+    pub fn max_level(&self) -> LogLevel {
+        self.modules
+            .values()
+            .cloned()
+            .chain(std::iter::once(self.level))
+            .max()
+            .unwrap_or(self.level)
+    }
+}
+
+fn is_within_module(target: &str, module: &str) -> bool {
+    target.starts_with(module)
+        && (target.len() == module.len() || target[module.len()..].starts_with("::"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_most_specific_module_wins() {
+        let mut filter = LogFilter::new(LogLevel::Info);
+        filter
+            .modules
+            .insert("talpid_core".to_owned(), LogLevel::Warn);
+        filter
+            .modules
+            .insert("talpid_core::firewall".to_owned(), LogLevel::Trace);
+
+        assert_eq!(filter.level_for("mullvad_daemon"), LogLevel::Info);
+        assert_eq!(filter.level_for("talpid_core"), LogLevel::Warn);
+        assert_eq!(filter.level_for("talpid_core::dns"), LogLevel::Warn);
+        assert_eq!(
+            filter.level_for("talpid_core::firewall::linux"),
+            LogLevel::Trace
+        );
+        assert_eq!(filter.level_for("talpid_core_extra"), LogLevel::Info);
+        assert_eq!(filter.max_level(), LogLevel::Trace);
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!("debug".parse::<LogLevel>(), Ok(LogLevel::Debug));
+        assert_eq!("WARN".parse::<LogLevel>(), Ok(LogLevel::Warn));
+        assert!("loud".parse::<LogLevel>().is_err());
+    }
+}
@@ -321,19 +321,40 @@ impl Match<OpenVpnEndpointData> for OpenVpnConstraints {
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Deserialize, Serialize)]
 pub struct WireguardConstraints {
     pub port: Constraint<u16>,
+    /// Set to TCP to wrap the WireGuard traffic in a TCP stream, for networks that block UDP.
+    /// Any currently implies UDP.
+    #[serde(default)]
+    pub transport_protocol: Constraint<TransportProtocol>,
+}
+
+impl WireguardConstraints {
+    /// Returns true if the traffic should be obfuscated using UDP-over-TCP.
+    pub fn use_udp2tcp(&self) -> bool {
+        self.transport_protocol == Constraint::Only(TransportProtocol::Tcp)
+    }
 }
 
 impl fmt::Display for WireguardConstraints {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self.port {
-            Constraint::Any => write!(f, "any port"),
-            Constraint::Only(port) => write!(f, "port {}", port),
+            Constraint::Any => write!(f, "any port")?,
+            Constraint::Only(port) => write!(f, "port {}", port)?,
         }
+        if let Constraint::Only(protocol) = self.transport_protocol {
+            write!(f, " over {}", protocol)?;
+        }
+        Ok(())
     }
 }
 
 impl Match<WireguardEndpointData> for WireguardConstraints {
     fn matches(&self, endpoint: &WireguardEndpointData) -> bool {
+        if self.use_udp2tcp() {
+            return match self.port {
+                Constraint::Any => !endpoint.udp2tcp_ports.is_empty(),
+                Constraint::Only(port) => endpoint.udp2tcp_ports.contains(&port),
+            };
+        }
         match self.port {
             Constraint::Any => true,
             Constraint::Only(port) => endpoint
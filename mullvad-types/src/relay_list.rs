@@ -139,13 +139,17 @@ pub struct WireguardEndpointData {
     pub ipv6_gateway: Ipv6Addr,
     /// The peer's public key
     pub public_key: wireguard::PublicKey,
+    /// TCP ports on which the relay accepts WireGuard traffic wrapped in a TCP stream. Empty if
+    /// the relay does not support UDP-over-TCP obfuscation.
+    #[serde(default)]
+    pub udp2tcp_ports: Vec<u16>,
 }
 
 impl fmt::Display for WireguardEndpointData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         write!(
             f,
-            "gateways {} - {} port_ranges {{ {} }} public_key {}",
+            "gateways {} - {} port_ranges {{ {} }} udp2tcp_ports {{ {} }} public_key {}",
             self.ipv4_gateway,
             self.ipv6_gateway,
             self.port_ranges
@@ -153,6 +157,11 @@ impl fmt::Display for WireguardEndpointData {
                 .map(|range| format!("[{} - {}]", range.0, range.1))
                 .collect::<Vec<_>>()
                 .join(","),
+            self.udp2tcp_ports
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
             self.public_key,
         )
     }
@@ -0,0 +1,504 @@
+//! When changing relay selection, please verify if `docs/relay-selector.md` needs to be
+//! updated as well.
+
+use crate::relay_constraints::{Constraint, TunnelProtocol};
+use serde::{Deserialize, Serialize};
+use std::{fmt, time::Duration};
+use talpid_types::net::TransportProtocol;
+
+
+/// Describes which tunnel endpoints the daemon should prefer for consecutive connection attempts,
+/// how long to wait before trying again after giving up, and when to give up.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RetryStrategy {
+    /// Schedule used when both WireGuard and OpenVPN can be used.
+    pub any_tunnel: RetrySchedule,
+    /// Schedule used when the tunnel protocol is constrained to OpenVPN, or when WireGuard
+    /// can't be used.
+    pub openvpn: RetrySchedule,
+    /// Schedule used when the tunnel protocol is constrained to WireGuard.
+    pub wireguard: RetrySchedule,
+    /// Delay used when the daemon schedules a new connection after the tunnel has failed.
+    pub backoff: Backoff,
+    /// Number of consecutive connection attempts after which the tunnel enters the error state.
+    /// `None` means the daemon will keep retrying indefinitely.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for RetryStrategy {
+    fn default() -> Self {
+        RetryStrategy {
+            any_tunnel: RetrySchedule::default_any_tunnel(),
+            openvpn: RetrySchedule::default_openvpn(),
+            wireguard: RetrySchedule::default_wireguard(),
+            backoff: Backoff::default(),
+            max_attempts: None,
+        }
+    }
+}
+
+impl RetryStrategy {
+    /// Returns the schedule to use given the tunnel protocol constraint and whether WireGuard
+    /// can be used at all.
+    pub fn schedule(
+        &self,
+        tunnel_protocol: &Constraint<TunnelProtocol>,
+        wireguard_available: bool,
+    ) -> &RetrySchedule {
+        match tunnel_protocol {
+            Constraint::Only(TunnelProtocol::OpenVpn) => &self.openvpn,
+            Constraint::Only(TunnelProtocol::Wireguard) => &self.wireguard,
+            Constraint::Any if wireguard_available => &self.any_tunnel,
+            Constraint::Any => &self.openvpn,
+        }
+    }
+
+    /// Returns true if the given retry attempt exceeds the maximum number of attempts.
+    pub fn is_exhausted(&self, retry_attempt: u32) -> bool {
+        self.max_attempts
+            .map(|max_attempts| retry_attempt >= max_attempts)
+            .unwrap_or(false)
+    }
+}
+
+/// Preferences for consecutive connection attempts.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RetrySchedule {
+    /// Preferences for each connection attempt, in order. Once the list is exhausted, it is
+    /// repeated from `repeat_from`. Attempts that conflict with the user's relay constraints
+    /// are skipped.
+    pub attempts: Vec<RetryAttempt>,
+    /// Index of the first attempt that is repeated once the list of attempts is exhausted. If
+    /// none of the attempts from this index onwards apply, the whole list is repeated.
+    #[serde(default)]
+    pub repeat_from: usize,
+}
+
+impl RetrySchedule {
+    /// Tries WireGuard twice, first on any port and then on port 53. After that OpenVPN is used,
+    /// first over UDP, then over TCP on port 443, and from then on alternating between UDP and
+    /// TCP. Every other pair of OpenVPN attempts goes through a bridge if the bridge state is set
+    /// to auto.
+    fn default_any_tunnel() -> Self {
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::wireguard(Constraint::Any),
+                RetryAttempt::wireguard(Constraint::Only(53)),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Only(443), true),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Only(443), true),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, true),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Any, true),
+            ],
+            repeat_from: 6,
+        }
+    }
+
+    /// Uses OpenVPN over UDP twice, then over TCP on port 443 twice, and from then on alternates
+    /// between UDP and TCP. Every other pair of attempts after the first four goes through a
+    /// bridge if the bridge state is set to auto.
+    fn default_openvpn() -> Self {
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Only(443), false),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Only(443), false),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, true),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Any, true),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Any, false),
+            ],
+            repeat_from: 4,
+        }
+    }
+
+    /// Uses WireGuard on any port twice and then on port 53 twice.
+    fn default_wireguard() -> Self {
+        RetrySchedule {
+            attempts: vec![
+                RetryAttempt::wireguard(Constraint::Any),
+                RetryAttempt::wireguard(Constraint::Any),
+                RetryAttempt::wireguard(Constraint::Only(53)),
+                RetryAttempt::wireguard(Constraint::Only(53)),
+            ],
+            repeat_from: 0,
+        }
+    }
+
+    /// Returns the preferences to use for the given retry attempt, only taking into account the
+    /// attempts for which `filter` returns true. Returns `None` if no attempt passes the filter.
+    pub fn attempt(
+        &self,
+        retry_attempt: u32,
+        filter: impl Fn(&RetryAttempt) -> bool,
+    ) -> Option<&RetryAttempt> {
+        let candidates: Vec<(usize, &RetryAttempt)> = self
+            .attempts
+            .iter()
+            .enumerate()
+            .filter(|(_, attempt)| filter(attempt))
+            .collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        let retry_attempt = retry_attempt as usize;
+        if retry_attempt < candidates.len() {
+            return Some(candidates[retry_attempt].1);
+        }
+        let repeat_start = candidates
+            .iter()
+            .position(|(index, _)| *index >= self.repeat_from)
+            .unwrap_or(0);
+        let repeated = &candidates[repeat_start..];
+        Some(repeated[(retry_attempt - candidates.len()) % repeated.len()].1)
+    }
+
+    /// Returns an endless iterator over the preferences used for consecutive retry attempts,
+    /// only taking into account the attempts for which `filter` returns true.
+    pub fn sequence<'a>(
+        &'a self,
+        filter: impl Fn(&RetryAttempt) -> bool + 'a,
+    ) -> impl Iterator<Item = &'a RetryAttempt> + 'a {
+        (0..)
+            .map(move |retry_attempt| self.attempt(retry_attempt, &filter))
+            .take_while(Option::is_some)
+            .flatten()
+    }
+}
+
+/// Preferred endpoint properties for a single connection attempt. These never override
+/// constraints that the user has explicitly specified.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+pub struct RetryAttempt {
+    pub tunnel_protocol: TunnelProtocol,
+    /// Transport protocol to prefer. For WireGuard, TCP means that the UDP traffic is wrapped in
+    /// a TCP stream.
+    pub transport_protocol: Constraint<TransportProtocol>,
+    pub port: Constraint<u16>,
+    /// Whether to connect through a bridge when the bridge state is set to auto.
+    pub use_bridge: bool,
+}
+
+impl RetryAttempt {
+    pub fn wireguard(port: Constraint<u16>) -> Self {
+        RetryAttempt {
+            tunnel_protocol: TunnelProtocol::Wireguard,
+            transport_protocol: Constraint::Only(TransportProtocol::Udp),
+            port,
+            use_bridge: false,
+        }
+    }
+
+    pub fn openvpn(protocol: TransportProtocol, port: Constraint<u16>, use_bridge: bool) -> Self {
+        RetryAttempt {
+            tunnel_protocol: TunnelProtocol::OpenVpn,
+            transport_protocol: Constraint::Only(protocol),
+            port,
+            use_bridge,
+        }
+    }
+}
+
+impl fmt::Display for RetryAttempt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} over ", self.tunnel_protocol)?;
+        match self.port {
+            Constraint::Any => write!(f, "any port")?,
+            Constraint::Only(port) => write!(f, "port {}", port)?,
+        }
+        match (&self.tunnel_protocol, self.transport_protocol) {
+            (TunnelProtocol::OpenVpn, Constraint::Only(protocol))
+            | (TunnelProtocol::Wireguard, Constraint::Only(protocol @ TransportProtocol::Tcp)) => {
+                write!(f, "/{}", protocol)?
+            }
+            _ => (),
+        }
+        if self.use_bridge {
+            write!(f, " via bridge")?;
+        }
+        Ok(())
+    }
+}
+
+/// Exponential backoff with jitter.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Backoff {
+    /// Delay before the first retry, in seconds.
+    pub initial_delay_secs: u64,
+    /// Upper bound of the delay, in seconds. Jitter may push the delay slightly above it.
+    pub max_delay_secs: u64,
+    /// Factor by which the delay grows for each consecutive retry.
+    pub multiplier: u32,
+    /// Maximum deviation from the computed delay, as a percentage of the delay.
+    pub jitter_percent: u8,
+}
+
+/// By default, the daemon waits one minute before reconnecting.
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial_delay_secs: 60,
+            max_delay_secs: 60,
+            multiplier: 1,
+            jitter_percent: 0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay to use for the given (zero-indexed) retry. `jitter_sample` should be a
+    /// random value in the range `[-1.0, 1.0]`, and it scales the jitter that is applied.
+    pub fn delay(&self, retry: u32, jitter_sample: f64) -> Duration {
+        let base_ms = self
+            .initial_delay_secs
+            .saturating_mul(1000)
+            .saturating_mul(u64::from(self.multiplier).saturating_pow(retry))
+            .min(self.max_delay_secs.saturating_mul(1000));
+        let jitter_fraction = f64::from(self.jitter_percent.min(100)) / 100.0;
+        let jitter_ms = base_ms as f64 * jitter_fraction * jitter_sample.max(-1.0).min(1.0);
+        Duration::from_millis((base_ms as f64 + jitter_ms).max(0.0) as u64)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn any_attempt(_: &RetryAttempt) -> bool {
+        true
+    }
+
+    #[test]
+    fn test_default_sequence() {
+        let strategy = RetryStrategy::default();
+        let sequence: Vec<String> = strategy
+            .any_tunnel
+            .sequence(any_attempt)
+            .take(14)
+            .map(ToString::to_string)
+            .collect();
+
+        assert_eq!(
+            sequence,
+            vec![
+                "WireGuard over any port",
+                "WireGuard over port 53",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over port 443/TCP via bridge",
+                "OpenVPN over port 443/TCP via bridge",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/TCP",
+                "OpenVPN over any port/UDP via bridge",
+                "OpenVPN over any port/TCP via bridge",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/TCP",
+                "OpenVPN over any port/UDP via bridge",
+                "OpenVPN over any port/TCP via bridge",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_single_protocol_sequences() {
+        let strategy = RetryStrategy::default();
+
+        // Bridges are used on attempts 4, 5, 8, 9, and so on
+        let openvpn: Vec<String> = strategy
+            .openvpn
+            .sequence(any_attempt)
+            .take(12)
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            openvpn,
+            vec![
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over port 443/TCP",
+                "OpenVPN over port 443/TCP",
+                "OpenVPN over any port/UDP via bridge",
+                "OpenVPN over any port/TCP via bridge",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/TCP",
+                "OpenVPN over any port/UDP via bridge",
+                "OpenVPN over any port/TCP via bridge",
+                "OpenVPN over any port/UDP",
+                "OpenVPN over any port/TCP",
+            ]
+        );
+
+        let wireguard: Vec<Constraint<u16>> = strategy
+            .wireguard
+            .sequence(any_attempt)
+            .take(8)
+            .map(|attempt| attempt.port)
+            .collect();
+        assert_eq!(
+            wireguard,
+            vec![
+                Constraint::Any,
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Only(53),
+                Constraint::Any,
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Only(53),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_schedule_selection() {
+        let strategy = RetryStrategy::default();
+
+        assert_eq!(
+            strategy.schedule(&Constraint::Any, true),
+            &strategy.any_tunnel
+        );
+        assert_eq!(
+            strategy.schedule(&Constraint::Any, false),
+            &strategy.openvpn
+        );
+        for &wireguard_available in &[true, false] {
+            assert_eq!(
+                strategy.schedule(
+                    &Constraint::Only(TunnelProtocol::OpenVpn),
+                    wireguard_available
+                ),
+                &strategy.openvpn
+            );
+            assert_eq!(
+                strategy.schedule(
+                    &Constraint::Only(TunnelProtocol::Wireguard),
+                    wireguard_available
+                ),
+                &strategy.wireguard
+            );
+        }
+    }
+
+    #[test]
+    fn test_repeat_from() {
+        let schedule = RetrySchedule {
+            attempts: vec![
+                RetryAttempt::wireguard(Constraint::Any),
+                RetryAttempt::openvpn(TransportProtocol::Udp, Constraint::Any, false),
+                RetryAttempt::wireguard(Constraint::Only(53)),
+                RetryAttempt::openvpn(TransportProtocol::Tcp, Constraint::Any, false),
+            ],
+            repeat_from: 2,
+        };
+
+        let ports: Vec<Constraint<u16>> = schedule
+            .sequence(any_attempt)
+            .take(8)
+            .map(|attempt| attempt.port)
+            .collect();
+        assert_eq!(
+            ports,
+            vec![
+                Constraint::Any,
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Any,
+            ]
+        );
+
+        // Only the second OpenVPN attempt is repeated
+        let openvpn_only: Vec<Constraint<TransportProtocol>> = schedule
+            .sequence(|attempt| attempt.tunnel_protocol == TunnelProtocol::OpenVpn)
+            .take(4)
+            .map(|attempt| attempt.transport_protocol)
+            .collect();
+        assert_eq!(
+            openvpn_only,
+            vec![
+                Constraint::Only(TransportProtocol::Udp),
+                Constraint::Only(TransportProtocol::Tcp),
+                Constraint::Only(TransportProtocol::Tcp),
+                Constraint::Only(TransportProtocol::Tcp)
+            ]
+        );
+
+        // If none of the attempts from `repeat_from` onwards apply, all of them are repeated
+        let schedule = RetrySchedule {
+            repeat_from: 4,
+            ..schedule
+        };
+        let wireguard_only: Vec<Constraint<u16>> = schedule
+            .sequence(|attempt| attempt.tunnel_protocol == TunnelProtocol::Wireguard)
+            .take(4)
+            .map(|attempt| attempt.port)
+            .collect();
+        assert_eq!(
+            wireguard_only,
+            vec![
+                Constraint::Any,
+                Constraint::Only(53),
+                Constraint::Any,
+                Constraint::Only(53)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attempt_matches_sequence() {
+        let schedule = RetryStrategy::default().any_tunnel;
+        let filter = |attempt: &RetryAttempt| attempt.tunnel_protocol == TunnelProtocol::OpenVpn;
+
+        for (retry_attempt, expected) in schedule.sequence(filter).take(20).enumerate() {
+            assert_eq!(
+                schedule.attempt(retry_attempt as u32, filter),
+                Some(expected)
+            );
+        }
+        assert_eq!(schedule.attempt(0, |_| false), None);
+        assert_eq!(schedule.sequence(|_| false).next(), None);
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let mut strategy = RetryStrategy::default();
+        assert!(!strategy.is_exhausted(u32::max_value()));
+
+        strategy.max_attempts = Some(3);
+        assert!(!strategy.is_exhausted(2));
+        assert!(strategy.is_exhausted(3));
+    }
+
+    #[test]
+    fn test_backoff() {
+        let backoff = Backoff {
+            initial_delay_secs: 1,
+            max_delay_secs: 10,
+            multiplier: 2,
+            jitter_percent: 50,
+        };
+
+        let delays: Vec<u64> = (0..6)
+            .map(|retry| backoff.delay(retry, 0.0).as_secs())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8, 10, 10]);
+
+        assert_eq!(backoff.delay(2, 1.0), Duration::from_secs(6));
+        assert_eq!(backoff.delay(2, -1.0), Duration::from_secs(2));
+        assert_eq!(backoff.delay(2, 5.0), Duration::from_secs(6));
+        assert_eq!(
+            backoff.delay(u32::max_value(), 0.0),
+            Duration::from_secs(10)
+        );
+    }
+}
@@ -70,7 +70,14 @@ impl super::SettingsMigration for Migration {
                 block_when_disconnected: old.block_when_disconnected,
                 auto_connect: old.auto_connect,
                 tunnel_options: old.tunnel_options,
+                key_rotation_policy: Default::default(),
                 show_beta_releases: false,
+                download_updates: false,
+                retry_strategy: Default::default(),
+                api_proxy: None,
+                api_access_method: Default::default(),
+                gateway: None,
+                tunnel_socks_proxy: None,
                 settings_version: super::SettingsVersion::V2,
             }),
             VersionedSettings::V2(new) => VersionedSettings::V2(new),
@@ -1,13 +1,21 @@
-use crate::relay_constraints::{
-    BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
-    RelayConstraints, RelaySettings, RelaySettingsUpdate,
+use crate::{
+    api_access::ApiAccessMethod,
+    api_proxy::ApiProxy,
+    relay_constraints::{
+        BridgeConstraints, BridgeSettings, BridgeState, Constraint, LocationConstraint,
+        RelayConstraints, RelaySettings, RelaySettingsUpdate,
+    },
+    retry_strategy::RetryStrategy,
+    wireguard::RotationPolicy,
 };
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use log::{debug, info};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use talpid_types::net::{openvpn, wireguard, GenericTunnelOptions};
+use talpid_types::net::{
+    openvpn, proxy::TunnelSocksProxy, wireguard, GatewayOptions, GenericTunnelOptions,
+};
 
 mod migrations;
 
@@ -47,8 +55,31 @@ pub struct Settings {
     /// Options that should be applied to tunnels of a specific type regardless of where the relays
     /// might be located.
     pub tunnel_options: TunnelOptions,
+    /// How automatic rotation replaces the wireguard key.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub key_rotation_policy: RotationPolicy,
     /// Whether to notify users of beta updates.
     pub show_beta_releases: bool,
+    /// Whether to download and verify new releases in the background, so that they can be
+    /// installed through the management interface.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub download_updates: bool,
+    /// Which endpoints to try, and how to pace reconnection attempts, when connecting fails.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub retry_strategy: RetryStrategy,
+    /// Proxy used by the `proxy` API access method, if any.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub api_proxy: Option<ApiProxy>,
+    /// How to reach the API. `Any` rotates among all available methods when the API can't be
+    /// reached.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub api_access_method: Constraint<ApiAccessMethod>,
+    /// Share the tunnel with a local network by acting as its gateway. Only supported on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub gateway: Option<GatewayOptions>,
+    /// Local SOCKS5 proxy whose connections are sent through the tunnel while connected.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    pub tunnel_socks_proxy: Option<TunnelSocksProxy>,
     /// Specifies settings schema version
     #[cfg_attr(target_os = "android", jnix(skip))]
     settings_version: migrations::SettingsVersion,
@@ -70,7 +101,14 @@ impl Default for Settings {
             block_when_disconnected: false,
             auto_connect: false,
             tunnel_options: TunnelOptions::default(),
+            key_rotation_policy: RotationPolicy::default(),
             show_beta_releases: false,
+            download_updates: false,
+            retry_strategy: RetryStrategy::default(),
+            api_proxy: None,
+            api_access_method: Constraint::Any,
+            gateway: None,
+            tunnel_socks_proxy: None,
             settings_version: migrations::SettingsVersion::V2,
         }
     }
@@ -174,10 +212,13 @@ impl Default for TunnelOptions {
             wireguard: wireguard::TunnelOptions {
                 mtu: None,
                 automatic_rotation: None,
+                use_policy_routing: false,
+                network_namespace: None,
             },
             generic: GenericTunnelOptions {
                 // Enable IPv6 be default on Android
                 enable_ipv6: cfg!(target_os = "android"),
+                excluded_destinations: vec![],
             },
         }
     }
@@ -1,6 +1,7 @@
 #[cfg(target_os = "android")]
 use jnix::IntoJava;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 /// AppVersionInfo represents the current stable and the current latest release versions of the
 /// Mullvad VPN app.
@@ -25,3 +26,13 @@ pub struct AppVersionInfo {
 }
 
 pub type AppVersion = String;
+
+/// A release that has been downloaded and whose signature has been verified, but which has not
+/// been installed yet.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PendingUpdate {
+    /// Version of the downloaded release
+    pub version: AppVersion,
+    /// Path to the installer
+    pub path: PathBuf,
+}
@@ -43,6 +43,80 @@ pub struct AssociatedAddresses {
     pub ipv6_address: ipnetwork::Ipv6Network,
 }
 
+/// A public key that is registered on an account, together with the addresses that were assigned
+/// to it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RegisteredKey {
+    pub pubkey: wireguard::PublicKey,
+    #[serde(flatten)]
+    pub addresses: AssociatedAddresses,
+}
+
+/// Controls how automatic key rotation replaces the key of an account.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RotationPolicy {
+    /// Minutes to keep the old key registered after a rotation. The rotation is rolled back if a
+    /// tunnel tried the new key without completing a handshake within this time. The grace period
+    /// starts over while no tunnel has tried the new key.
+    pub grace_period: u32,
+    /// Whether to rotate the key while a tunnel is up. Otherwise rotation waits until the tunnel
+    /// is disconnected.
+    pub rotate_while_connected: bool,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> Self {
+        RotationPolicy {
+            grace_period: 15,
+            rotate_while_connected: false,
+        }
+    }
+}
+
+/// Event that is emitted for each phase of an automatic key rotation.
+#[serde(rename_all = "snake_case")]
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum KeyRotationEvent {
+    /// The key is due for rotation, but a tunnel is up and the policy does not allow rotating
+    /// while connected. Only sent once until the rotation starts.
+    Postponed,
+    /// A new key has been pushed and is used for new tunnels. The old key is kept until the new
+    /// one has been used for a handshake.
+    Started {
+        old_key: PublicKey,
+        new_key: PublicKey,
+    },
+    /// The new key has been used for a handshake and the old key has been removed.
+    Completed(PublicKey),
+    /// The new key did not work, so the old key is used again.
+    RolledBack(PublicKey),
+    /// A new key could not be pushed. The current key is kept.
+    Failed,
+}
+
+impl fmt::Display for KeyRotationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            KeyRotationEvent::Postponed => {
+                write!(f, "Key rotation postponed until the tunnel is disconnected")
+            }
+            KeyRotationEvent::Started { old_key, new_key } => write!(
+                f,
+                "Rotating wireguard key {} to {}",
+                old_key.key, new_key.key
+            ),
+            KeyRotationEvent::Completed(new_key) => {
+                write!(f, "Key rotation to {} completed", new_key.key)
+            }
+            KeyRotationEvent::RolledBack(old_key) => {
+                write!(f, "Key rotation rolled back to {}", old_key.key)
+            }
+            KeyRotationEvent::Failed => write!(f, "Failed to rotate wireguard key"),
+        }
+    }
+}
+
 /// Event that is emitted when the daemon has finished generating a key.
 #[serde(rename_all = "snake_case")]
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -21,4 +21,8 @@ impl super::DnsMonitorT for DnsMonitor {
     fn reset(&mut self) -> Result<(), Self::Error> {
         Ok(())
     }
+
+    fn backend(&self) -> Option<String> {
+        Some("VpnService".to_owned())
+    }
 }
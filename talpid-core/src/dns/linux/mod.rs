@@ -1,10 +1,12 @@
 mod network_manager;
+mod network_namespace;
 mod resolvconf;
 mod static_resolv_conf;
 mod systemd_resolved;
 
 use self::{
-    network_manager::NetworkManager, resolvconf::Resolvconf, static_resolv_conf::StaticResolvConf,
+    network_manager::NetworkManager, network_namespace::NetworkNamespaceResolvConf,
+    resolvconf::Resolvconf, static_resolv_conf::StaticResolvConf,
     systemd_resolved::SystemdResolved,
 };
 use std::{env, fmt, net::IpAddr, path::Path};
@@ -33,6 +35,10 @@ pub enum Error {
     #[error(display = "Error in static /etc/resolv.conf DNS monitor")]
     StaticResolvConf(#[error(source)] static_resolv_conf::Error),
 
+    /// Error while setting DNS in a network namespace
+    #[error(display = "Error while setting DNS in a network namespace")]
+    NetworkNamespace(#[error(source)] network_namespace::Error),
+
     /// No suitable DNS monitor implementation detected
     #[error(display = "No suitable DNS monitor implementation detected")]
     NoDnsMonitor,
@@ -64,6 +70,24 @@ impl super::DnsMonitorT for DnsMonitor {
         }
         Ok(())
     }
+
+    fn backend(&self) -> Option<String> {
+        self.inner.as_ref().map(ToString::to_string)
+    }
+}
+
+impl DnsMonitor {
+    /// Sets DNS for the given network namespace only, instead of for the whole system.
+    pub fn set_in_network_namespace(&mut self, namespace: &str, servers: &[IpAddr]) -> Result<()> {
+        use super::DnsMonitorT;
+
+        self.reset()?;
+        let mut inner =
+            DnsMonitorHolder::NetworkNamespace(NetworkNamespaceResolvConf::new(namespace)?);
+        inner.set("", servers)?;
+        self.inner = Some(inner);
+        Ok(())
+    }
 }
 
 pub enum DnsMonitorHolder {
@@ -71,6 +95,7 @@ pub enum DnsMonitorHolder {
     NetworkManager(NetworkManager),
     Resolvconf(Resolvconf),
     StaticResolvConf(StaticResolvConf),
+    NetworkNamespace(NetworkNamespaceResolvConf),
 }
 
 impl fmt::Display for DnsMonitorHolder {
@@ -81,6 +106,7 @@ impl fmt::Display for DnsMonitorHolder {
             StaticResolvConf(..) => "/etc/resolv.conf",
             SystemdResolved(..) => "systemd-resolved",
             NetworkManager(..) => "network manager",
+            NetworkNamespace(..) => "network namespace resolv.conf",
         };
         f.write_str(name)
     }
@@ -121,6 +147,7 @@ impl DnsMonitorHolder {
                 systemd_resolved.set_dns(interface, &servers)?
             }
             NetworkManager(ref mut network_manager) => network_manager.set_dns(servers)?,
+            NetworkNamespace(ref mut network_namespace) => network_namespace.set_dns(servers)?,
         }
         Ok(())
     }
@@ -132,6 +159,7 @@ impl DnsMonitorHolder {
             StaticResolvConf(ref mut static_resolv_conf) => static_resolv_conf.reset()?,
             SystemdResolved(ref mut systemd_resolved) => systemd_resolved.reset()?,
             NetworkManager(ref mut network_manager) => network_manager.reset()?,
+            NetworkNamespace(ref mut network_namespace) => network_namespace.reset()?,
         }
         Ok(())
     }
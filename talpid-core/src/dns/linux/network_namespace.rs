@@ -0,0 +1,53 @@
+use crate::netns::{self, NetworkNamespace};
+use resolv_conf::{Config, ScopedIp};
+use std::{io, net::IpAddr};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(err_derive::Error, Debug)]
+pub enum Error {
+    #[error(display = "Invalid network namespace")]
+    InvalidNamespace(#[error(source)] netns::Error),
+
+    #[error(display = "Failed to write to {}", _0)]
+    WriteResolvConf(String, #[error(source)] io::Error),
+
+    #[error(display = "Failed to remove {}", _0)]
+    RemoveResolvConf(String, #[error(source)] io::Error),
+}
+
+/// Sets DNS inside a network namespace only, through the resolv.conf that `ip netns exec` mounts
+/// over /etc/resolv.conf. The DNS settings of the host are left alone.
+pub struct NetworkNamespaceResolvConf {
+    namespace: NetworkNamespace,
+}
+
+impl NetworkNamespaceResolvConf {
+    pub fn new(namespace: &str) -> Result<Self> {
+        Ok(NetworkNamespaceResolvConf {
+            namespace: NetworkNamespace::new(namespace).map_err(Error::InvalidNamespace)?,
+        })
+    }
+
+    pub fn set_dns(&mut self, servers: &[IpAddr]) -> Result<()> {
+        let mut config = Config::new();
+        config.nameservers = servers
+            .iter()
+            .map(|&address| ScopedIp::from(address))
+            .collect();
+
+        self.namespace
+            .write_resolv_conf(&config.to_string())
+            .map_err(|error| Error::WriteResolvConf(self.resolv_conf_path(), error))
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        self.namespace
+            .remove_resolv_conf()
+            .map_err(|error| Error::RemoveResolvConf(self.resolv_conf_path(), error))
+    }
+
+    fn resolv_conf_path(&self) -> String {
+        self.namespace.resolv_conf_path().display().to_string()
+    }
+}
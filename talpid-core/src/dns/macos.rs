@@ -200,6 +200,10 @@ impl super::DnsMonitorT for DnsMonitor {
         }
         Ok(())
     }
+
+    fn backend(&self) -> Option<String> {
+        Some("SystemConfiguration".to_owned())
+    }
 }
 
 impl DnsMonitor {
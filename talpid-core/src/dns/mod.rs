@@ -1,4 +1,5 @@
 use std::{net::IpAddr, path::Path};
+use talpid_types::diagnostics::DnsDiagnostics;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -21,6 +22,7 @@ pub use self::imp::Error;
 /// Sets and monitors system DNS settings. Makes sure the desired DNS servers are being used.
 pub struct DnsMonitor {
     inner: imp::DnsMonitor,
+    servers: Vec<IpAddr>,
 }
 
 impl DnsMonitor {
@@ -28,6 +30,7 @@ impl DnsMonitor {
     pub fn new(cache_dir: impl AsRef<Path>) -> Result<Self, Error> {
         Ok(DnsMonitor {
             inner: imp::DnsMonitor::new(cache_dir)?,
+            servers: Vec::new(),
         })
     }
 
@@ -41,14 +44,52 @@ impl DnsMonitor {
                 .collect::<Vec<String>>()
                 .join(", ")
         );
-        self.inner.set(interface, servers)
+        self.servers.clear();
+        self.inner.set(interface, servers)?;
+        self.servers = servers.to_vec();
+        Ok(())
+    }
+
+    /// Set DNS to the given servers for processes in the named network namespace, leaving the
+    /// system DNS settings alone.
+    #[cfg(target_os = "linux")]
+    pub fn set_in_network_namespace(
+        &mut self,
+        namespace: &str,
+        servers: &[IpAddr],
+    ) -> Result<(), Error> {
+        log::info!(
+            "Setting DNS servers in network namespace {} to {}",
+            namespace,
+            servers
+                .iter()
+                .map(|ip| ip.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        );
+        self.servers.clear();
+        self.inner.set_in_network_namespace(namespace, servers)?;
+        self.servers = servers.to_vec();
+        Ok(())
     }
 
     /// Reset system DNS settings to what it was before being set by this instance.
     pub fn reset(&mut self) -> Result<(), Error> {
         log::info!("Resetting DNS");
+        self.servers.clear();
         self.inner.reset()
     }
+
+    /// Describes the DNS servers currently set and the system integration used to set them.
+    pub fn diagnostics(&self) -> DnsDiagnostics {
+        if self.servers.is_empty() {
+            return DnsDiagnostics::default();
+        }
+        DnsDiagnostics {
+            backend: self.inner.backend(),
+            servers: self.servers.clone(),
+        }
+    }
 }
 
 trait DnsMonitorT: Sized {
@@ -59,4 +100,7 @@ trait DnsMonitorT: Sized {
     fn set(&mut self, interface: &str, servers: &[IpAddr]) -> Result<(), Self::Error>;
 
     fn reset(&mut self) -> Result<(), Self::Error>;
+
+    /// Name of the system integration used to set DNS.
+    fn backend(&self) -> Option<String>;
 }
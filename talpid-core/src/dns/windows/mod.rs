@@ -83,6 +83,10 @@ impl super::DnsMonitorT for DnsMonitor {
     fn reset(&mut self) -> Result<(), Error> {
         Ok(())
     }
+
+    fn backend(&self) -> Option<String> {
+        Some("WinDns".to_owned())
+    }
 }
 
 fn ip_to_widestring(ip: &IpAddr) -> WideCString {
@@ -1,20 +1,27 @@
 use super::{FirewallArguments, FirewallPolicy, FirewallT};
-use crate::tunnel;
+use crate::{
+    netns::{self, NetworkNamespace},
+    tunnel,
+};
 use ipnetwork::IpNetwork;
 use lazy_static::lazy_static;
 use libc;
 use nftnl::{
     self,
     expr::{self, Payload, Verdict},
-    nft_expr, table, Batch, Chain, FinalizedBatch, ProtoFamily, Rule, Table,
+    nft_expr, table, Batch, Chain, ChainType, FinalizedBatch, ProtoFamily, Rule, Table,
 };
 use std::{
+    collections::HashSet,
     env,
     ffi::{CStr, CString},
     io,
     net::{IpAddr, Ipv4Addr},
 };
-use talpid_types::net::{Endpoint, TransportProtocol};
+use talpid_types::{
+    net::{Endpoint, GatewayOptions, TransportProtocol},
+    ErrorExt,
+};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -49,6 +56,10 @@ pub enum Error {
         _0
     )]
     LookupIfaceIndexError(String, #[error(source)] crate::linux::IfaceIndexLookupError),
+
+    /// Unable to create or enter the network namespace that the rules are applied in.
+    #[error(display = "Unable to apply rules in network namespace")]
+    NetworkNamespaceError(#[error(source)] netns::Error),
 }
 
 lazy_static! {
@@ -57,6 +68,8 @@ lazy_static! {
     static ref TABLE_NAME: CString = CString::new("mullvad").unwrap();
     static ref IN_CHAIN_NAME: CString = CString::new("in").unwrap();
     static ref OUT_CHAIN_NAME: CString = CString::new("out").unwrap();
+    static ref FORWARD_CHAIN_NAME: CString = CString::new("forward").unwrap();
+    static ref NAT_CHAIN_NAME: CString = CString::new("nat").unwrap();
 
     /// Allows controlling whether firewall rules should have packet counters or not from an env
     /// variable. Useful for debugging the rules.
@@ -77,9 +90,14 @@ enum End {
     Dst,
 }
 
+/// Priority of the chain that masquerades forwarded traffic, the standard source NAT priority.
+const NAT_CHAIN_PRIORITY: i32 = 100;
+
 /// The Linux implementation for the firewall and DNS.
 pub struct Firewall {
     table_name: CString,
+    network_namespace: Option<NetworkNamespace>,
+    gateway: Option<GatewayOptions>,
 }
 
 impl FirewallT for Firewall {
@@ -88,33 +106,78 @@ impl FirewallT for Firewall {
     fn new(_args: FirewallArguments) -> Result<Self> {
         Ok(Firewall {
             table_name: TABLE_NAME.clone(),
+            network_namespace: None,
+            gateway: None,
         })
     }
 
     fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<()> {
-        let table = Table::new(&self.table_name, ProtoFamily::Inet);
-        let batch = PolicyBatch::new(&table).finalize(&policy)?;
-        self.send_and_process(&batch)?;
-        self.verify_tables(&[&TABLE_NAME])
+        self.in_network_namespace(|| {
+            let table = Table::new(&self.table_name, ProtoFamily::Inet);
+            let batch = PolicyBatch::new(&table, self.gateway.as_ref()).finalize(&policy)?;
+            self.send_and_process(&batch)?;
+            self.verify_tables(&[&TABLE_NAME])
+        })
     }
 
     fn reset_policy(&mut self) -> Result<()> {
-        let table = Table::new(&self.table_name, ProtoFamily::Inet);
-        let batch = {
-            let mut batch = Batch::new();
-            // Our batch will add and remove the table even though the goal is just to remove it.
-            // This because only removing it throws a strange error if the table does not exist.
-            batch.add(&table, nftnl::MsgType::Add);
-            batch.add(&table, nftnl::MsgType::Del);
-            batch.finalize()
-        };
+        self.in_network_namespace(|| {
+            let table = Table::new(&self.table_name, ProtoFamily::Inet);
+            let batch = {
+                let mut batch = Batch::new();
+                // Our batch will add and remove the table even though the goal is just to remove
+                // it. This because only removing it throws a strange error if the table does not
+                // exist.
+                batch.add(&table, nftnl::MsgType::Add);
+                batch.add(&table, nftnl::MsgType::Del);
+                batch.finalize()
+            };
 
-        log::debug!("Removing table and chain from netfilter");
-        self.send_and_process(&batch)
+            log::debug!("Removing table and chain from netfilter");
+            self.send_and_process(&batch)
+        })
     }
 }
 
 impl Firewall {
+    /// Makes future policies apply inside the named network namespace, or in the current one if
+    /// `None`. The namespace is created if it doesn't exist. Rules applied in the previous
+    /// location are removed.
+    pub fn set_network_namespace(&mut self, name: Option<&str>) -> Result<bool> {
+        if self.network_namespace.as_ref().map(NetworkNamespace::name) == name {
+            return Ok(false);
+        }
+        let network_namespace = match name {
+            Some(name) => {
+                Some(NetworkNamespace::create_or_open(name).map_err(Error::NetworkNamespaceError)?)
+            }
+            None => None,
+        };
+
+        if let Err(error) = self.reset_policy() {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove rules from previous namespace")
+            );
+        }
+        self.network_namespace = network_namespace;
+        Ok(true)
+    }
+
+    /// Makes future policies forward traffic from a local network through the tunnel while
+    /// connected, and drop all other forwarded traffic. Forwarding is left alone if `None`.
+    pub fn set_gateway(&mut self, gateway: Option<GatewayOptions>) {
+        self.gateway = gateway;
+    }
+
+    /// Runs `f` inside the network namespace that rules are applied in.
+    fn in_network_namespace<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        match self.network_namespace {
+            Some(ref namespace) => namespace.enter(f).map_err(Error::NetworkNamespaceError)?,
+            None => f(),
+        }
+    }
+
     fn send_and_process(&self, batch: &FinalizedBatch) -> Result<()> {
         let socket = mnl::Socket::new(mnl::Bus::Netfilter).map_err(Error::NetlinkOpenError)?;
         socket.send_all(batch).map_err(Error::NetlinkSendError)?;
@@ -135,7 +198,32 @@ impl Firewall {
         Ok(())
     }
 
+    /// Returns the names of all netfilter tables.
+    pub fn list_tables(&self) -> Result<Vec<String>> {
+        let mut tables = self
+            .in_network_namespace(|| self.get_tables())?
+            .into_iter()
+            .map(|table| table.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        tables.sort();
+        Ok(tables)
+    }
+
     fn verify_tables(&self, expected_tables: &[&CStr]) -> Result<()> {
+        let table_set = self.get_tables()?;
+        for expected_table in expected_tables {
+            if !table_set.contains(*expected_table) {
+                log::error!(
+                    "Expected '{}' netfilter table to be set, but it is not",
+                    expected_table.to_string_lossy()
+                );
+                return Err(Error::NetfilterTableNotSetError);
+            }
+        }
+        Ok(())
+    }
+
+    fn get_tables(&self) -> Result<HashSet<CString>> {
         let socket = mnl::Socket::new(mnl::Bus::Netfilter).map_err(Error::NetlinkOpenError)?;
         let portid = socket.portid();
         let seq = 0;
@@ -145,7 +233,7 @@ impl Firewall {
             .send(&get_tables_msg)
             .map_err(Error::NetlinkSendError)?;
 
-        let mut table_set = std::collections::HashSet::new();
+        let mut table_set = HashSet::new();
         let mut msg_buffer = vec![0; nftnl::nft_nlmsg_maxsize() as usize];
 
         while let Some(message) = Self::socket_recv(&socket, &mut msg_buffer)? {
@@ -159,17 +247,7 @@ impl Firewall {
                 mnl::CbResult::Ok => log::trace!("cb_run OK"),
             }
         }
-
-        for expected_table in expected_tables {
-            if !table_set.contains(*expected_table) {
-                log::error!(
-                    "Expected '{}' netfilter table to be set, but it is not",
-                    expected_table.to_string_lossy()
-                );
-                return Err(Error::NetfilterTableNotSetError);
-            }
-        }
-        Ok(())
+        Ok(table_set)
     }
 
     fn socket_recv<'a>(socket: &mnl::Socket, buf: &'a mut [u8]) -> Result<Option<&'a [u8]>> {
@@ -187,12 +265,15 @@ struct PolicyBatch<'a> {
     batch: Batch,
     in_chain: Chain<'a>,
     out_chain: Chain<'a>,
+    forward_chain: Option<Chain<'a>>,
+    nat_chain: Option<Chain<'a>>,
+    gateway: Option<&'a GatewayOptions>,
 }
 
 impl<'a> PolicyBatch<'a> {
     /// Bootstrap a new nftnl message batch object and add the initial messages creating the
-    /// table and chains.
-    pub fn new(table: &'a Table) -> Self {
+    /// table and chains. The forward and NAT chains are only created when acting as a gateway.
+    pub fn new(table: &'a Table, gateway: Option<&'a GatewayOptions>) -> Self {
         let mut batch = Batch::new();
         let mut out_chain = Chain::new(&*OUT_CHAIN_NAME, table);
         let mut in_chain = Chain::new(&*IN_CHAIN_NAME, table);
@@ -208,10 +289,29 @@ impl<'a> PolicyBatch<'a> {
         batch.add(&out_chain, nftnl::MsgType::Add);
         batch.add(&in_chain, nftnl::MsgType::Add);
 
+        let (forward_chain, nat_chain) = if gateway.is_some() {
+            let mut forward_chain = Chain::new(&*FORWARD_CHAIN_NAME, table);
+            forward_chain.set_hook(nftnl::Hook::Forward, 0);
+            forward_chain.set_policy(nftnl::Policy::Drop);
+            batch.add(&forward_chain, nftnl::MsgType::Add);
+
+            let mut nat_chain = Chain::new(&*NAT_CHAIN_NAME, table);
+            nat_chain.set_type(ChainType::Nat);
+            nat_chain.set_hook(nftnl::Hook::PostRouting, NAT_CHAIN_PRIORITY);
+            batch.add(&nat_chain, nftnl::MsgType::Add);
+
+            (Some(forward_chain), Some(nat_chain))
+        } else {
+            (None, None)
+        };
+
         PolicyBatch {
             batch,
             in_chain,
             out_chain,
+            forward_chain,
+            nat_chain,
+            gateway,
         }
     }
 
@@ -345,35 +445,56 @@ impl<'a> PolicyBatch<'a> {
     fn add_policy_specific_rules(&mut self, policy: &FirewallPolicy) -> Result<()> {
         let allow_lan = match policy {
             FirewallPolicy::Connecting {
-                peer_endpoint,
+                peer_endpoints,
                 pingable_hosts,
                 allow_lan,
+                allowed_endpoint,
             } => {
                 self.add_allow_icmp_pingable_hosts(&pingable_hosts);
-                self.add_allow_endpoint_rules(peer_endpoint);
+                for endpoint in peer_endpoints {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
+                if let Some(endpoint) = allowed_endpoint {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
                 // Important to block DNS after allow relay rule (so the relay can operate
                 // over port 53) but before allow LAN (so DNS does not leak to the LAN)
                 self.add_drop_dns_rule();
                 *allow_lan
             }
             FirewallPolicy::Connected {
-                peer_endpoint,
+                peer_endpoints,
                 tunnel,
                 allow_lan,
+                allowed_endpoint,
+                excluded_destinations,
             } => {
-                self.add_allow_endpoint_rules(peer_endpoint);
+                for endpoint in peer_endpoints {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
+                if let Some(endpoint) = allowed_endpoint {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
                 self.add_allow_dns_rules(tunnel, TransportProtocol::Udp)?;
                 self.add_allow_dns_rules(tunnel, TransportProtocol::Tcp)?;
-                // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
-                // can't leak to the wrong IPs in the tunnel or on the LAN.
+                // Important to block DNS *before* we allow the tunnel, LAN and excluded
+                // destinations. So DNS can't leak to the wrong IPs.
                 self.add_drop_dns_rule();
                 self.add_allow_tunnel_rules(tunnel)?;
+                self.add_allow_excluded_destination_rules(excluded_destinations);
+                self.add_gateway_rules(tunnel)?;
                 if *allow_lan {
                     self.add_block_cve_2019_14899(tunnel);
                 }
                 *allow_lan
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+            } => {
+                if let Some(endpoint) = allowed_endpoint {
+                    self.add_allow_endpoint_rules(endpoint);
+                }
                 // Important to drop DNS before allowing LAN (to stop DNS leaking to the LAN)
                 self.add_drop_dns_rule();
                 *allow_lan
@@ -434,11 +555,10 @@ impl<'a> PolicyBatch<'a> {
         tunnel: &tunnel::TunnelMetadata,
         protocol: TransportProtocol,
     ) -> Result<()> {
-        // allow DNS traffic to the tunnel gateway(s)
-        self.add_allow_dns_rule(&tunnel.interface, protocol, tunnel.ipv4_gateway.into())?;
-        if let Some(ipv6_gateway) = tunnel.ipv6_gateway {
-            self.add_allow_dns_rule(&tunnel.interface, protocol, ipv6_gateway.into())?;
-        };
+        // allow DNS traffic to the DNS servers of the tunnel
+        for dns_server in &tunnel.dns_servers {
+            self.add_allow_dns_rule(&tunnel.interface, protocol, *dns_server)?;
+        }
         Ok(())
     }
 
@@ -505,6 +625,88 @@ impl<'a> PolicyBatch<'a> {
         }
     }
 
+    fn add_allow_excluded_destination_rules(&mut self, excluded_destinations: &[IpNetwork]) {
+        for net in excluded_destinations {
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_net(&mut out_rule, End::Dst, *net);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_net(&mut in_rule, End::Src, *net);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+        }
+    }
+
+    /// Allows and masquerades IPv4 traffic from the gateway's LAN interface out through the
+    /// tunnel, and the replies back. Since the forward chain drops everything else, no forwarded
+    /// traffic can leak outside the tunnel. If the LAN interface doesn't exist, nothing is
+    /// forwarded.
+    fn add_gateway_rules(&mut self, tunnel: &tunnel::TunnelMetadata) -> Result<()> {
+        let (gateway, forward_chain, nat_chain) =
+            match (self.gateway, &self.forward_chain, &self.nat_chain) {
+                (Some(gateway), Some(forward_chain), Some(nat_chain)) => {
+                    (gateway, forward_chain, nat_chain)
+                }
+                _ => return Ok(()),
+            };
+        let lan_interface = &gateway.lan_interface[..];
+        if let Err(error) = crate::linux::iface_index(lan_interface) {
+            log::warn!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Not forwarding traffic from missing interface {}",
+                    lan_interface
+                ))
+            );
+            return Ok(());
+        }
+
+        let mut out_rule = Rule::new(forward_chain);
+        check_iface(&mut out_rule, Direction::In, lan_interface)?;
+        check_iface(&mut out_rule, Direction::Out, &tunnel.interface)?;
+        check_l3proto(&mut out_rule, Ipv4Addr::UNSPECIFIED.into());
+        add_verdict(&mut out_rule, &Verdict::Accept);
+        self.batch.add(&out_rule, nftnl::MsgType::Add);
+
+        let mut in_rule = Rule::new(forward_chain);
+        check_iface(&mut in_rule, Direction::In, &tunnel.interface)?;
+        check_iface(&mut in_rule, Direction::Out, lan_interface)?;
+        in_rule.add_expr(&nft_expr!(ct state));
+        let allowed_states =
+            (nftnl::expr::ct::States::ESTABLISHED | nftnl::expr::ct::States::RELATED).bits();
+        in_rule.add_expr(&nft_expr!(bitwise mask allowed_states, xor 0u32));
+        in_rule.add_expr(&nft_expr!(cmp != 0u32));
+        add_verdict(&mut in_rule, &Verdict::Accept);
+        self.batch.add(&in_rule, nftnl::MsgType::Add);
+
+        let mut masquerade_rule = Rule::new(nat_chain);
+        check_iface(&mut masquerade_rule, Direction::In, lan_interface)?;
+        check_iface(&mut masquerade_rule, Direction::Out, &tunnel.interface)?;
+        check_l3proto(&mut masquerade_rule, Ipv4Addr::UNSPECIFIED.into());
+        if *ADD_COUNTERS {
+            masquerade_rule.add_expr(&nft_expr!(counter));
+        }
+        masquerade_rule.add_expr(&nft_expr!(masquerade));
+        self.batch.add(&masquerade_rule, nftnl::MsgType::Add);
+
+        if gateway.dns_forwarder {
+            let mut in_rule = Rule::new(&self.in_chain);
+            check_iface(&mut in_rule, Direction::In, lan_interface)?;
+            check_port(&mut in_rule, TransportProtocol::Udp, End::Dst, 53);
+            add_verdict(&mut in_rule, &Verdict::Accept);
+            self.batch.add(&in_rule, nftnl::MsgType::Add);
+
+            let mut out_rule = Rule::new(&self.out_chain);
+            check_iface(&mut out_rule, Direction::Out, lan_interface)?;
+            check_port(&mut out_rule, TransportProtocol::Udp, End::Src, 53);
+            add_verdict(&mut out_rule, &Verdict::Accept);
+            self.batch.add(&out_rule, nftnl::MsgType::Add);
+        }
+        Ok(())
+    }
+
     fn add_allow_lan_rules(&mut self) {
         // LAN -> LAN
         for net in &*super::ALLOWED_LAN_NETS {
@@ -659,3 +861,74 @@ fn add_verdict(rule: &mut Rule<'_>, verdict: &expr::Verdict) {
     }
     rule.add_expr(verdict);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Netlink message type of `NFT_MSG_NEWCHAIN` in the nftables subsystem.
+    const NEWCHAIN_MESSAGE_TYPE: u16 = (10 << 8) | 3;
+    const NFTA_CHAIN_NAME: u16 = 3;
+    const NFGENMSG_LEN: usize = 4;
+
+    /// Returns the names of the chains that `batch` creates.
+    fn created_chains(batch: &FinalizedBatch) -> Vec<String> {
+        let mut chains = vec![];
+        for mut messages in batch {
+            while messages.len() >= 16 {
+                let length =
+                    u32::from_ne_bytes([messages[0], messages[1], messages[2], messages[3]])
+                        as usize;
+                let message_type = u16::from_ne_bytes([messages[4], messages[5]]);
+                if message_type == NEWCHAIN_MESSAGE_TYPE {
+                    chains.extend(chain_name(&messages[16 + NFGENMSG_LEN..length]));
+                }
+                messages = &messages[align(length).min(messages.len())..];
+            }
+        }
+        chains
+    }
+
+    fn chain_name(mut attributes: &[u8]) -> Option<String> {
+        while attributes.len() >= 4 {
+            let length = u16::from_ne_bytes([attributes[0], attributes[1]]) as usize;
+            let kind = u16::from_ne_bytes([attributes[2], attributes[3]]);
+            if kind == NFTA_CHAIN_NAME {
+                let name = CStr::from_bytes_with_nul(&attributes[4..length]).ok()?;
+                return Some(name.to_string_lossy().into_owned());
+            }
+            attributes = &attributes[align(length).min(attributes.len())..];
+        }
+        None
+    }
+
+    fn align(length: usize) -> usize {
+        (length + 3) & !3
+    }
+
+    fn blocked_policy() -> FirewallPolicy {
+        FirewallPolicy::Blocked {
+            allow_lan: false,
+            allowed_endpoint: None,
+        }
+    }
+
+    #[test]
+    fn test_gateway_chains_only_created_for_gateway() {
+        let table = Table::new(&*TABLE_NAME, ProtoFamily::Inet);
+
+        let batch = PolicyBatch::new(&table, None)
+            .finalize(&blocked_policy())
+            .unwrap();
+        assert_eq!(created_chains(&batch), vec!["out", "in"]);
+
+        let gateway = GatewayOptions {
+            lan_interface: "eth1".to_owned(),
+            dns_forwarder: true,
+        };
+        let batch = PolicyBatch::new(&table, Some(&gateway))
+            .finalize(&blocked_policy())
+            .unwrap();
+        assert_eq!(created_chains(&batch), vec!["out", "in", "forward", "nat"]);
+    }
+}
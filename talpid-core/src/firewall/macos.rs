@@ -87,11 +87,18 @@ impl Firewall {
     ) -> Result<Vec<pfctl::FilterRule>> {
         match policy {
             FirewallPolicy::Connecting {
-                peer_endpoint,
+                peer_endpoints,
                 allow_lan,
                 pingable_hosts,
+                allowed_endpoint,
             } => {
-                let mut rules = vec![self.get_allow_relay_rule(peer_endpoint)?];
+                let mut rules = vec![];
+                for endpoint in peer_endpoints {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
+                if let Some(endpoint) = allowed_endpoint {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
                 rules.extend(self.get_allow_pingable_hosts(&pingable_hosts)?);
                 if allow_lan {
                     // Important to block DNS after allow relay rule (so the relay can operate
@@ -102,34 +109,15 @@ impl Firewall {
                 Ok(rules)
             }
             FirewallPolicy::Connected {
-                peer_endpoint,
+                peer_endpoints,
                 tunnel,
                 allow_lan,
+                allowed_endpoint,
+                excluded_destinations,
             } => {
                 let mut rules = vec![];
-                let allow_tcp_dns_to_relay_rule = self
-                    .create_rule_builder(FilterRuleAction::Pass)
-                    .direction(pfctl::Direction::Out)
-                    .quick(true)
-                    .interface(&tunnel.interface)
-                    .proto(pfctl::Proto::Tcp)
-                    .keep_state(pfctl::StatePolicy::Keep)
-                    .tcp_flags(Self::get_tcp_flags())
-                    .to(pfctl::Endpoint::new(tunnel.ipv4_gateway, 53))
-                    .build()?;
-                rules.push(allow_tcp_dns_to_relay_rule);
-                let allow_udp_dns_to_relay_rule = self
-                    .create_rule_builder(FilterRuleAction::Pass)
-                    .direction(pfctl::Direction::Out)
-                    .quick(true)
-                    .interface(&tunnel.interface)
-                    .proto(pfctl::Proto::Udp)
-                    .to(pfctl::Endpoint::new(tunnel.ipv4_gateway, 53))
-                    .build()?;
-                rules.push(allow_udp_dns_to_relay_rule);
-
-                if let Some(ipv6_gateway) = tunnel.ipv6_gateway {
-                    let v6_dns_rule_tcp = self
+                for dns_server in &tunnel.dns_servers {
+                    let allow_tcp_dns_rule = self
                         .create_rule_builder(FilterRuleAction::Pass)
                         .direction(pfctl::Direction::Out)
                         .quick(true)
@@ -137,27 +125,35 @@ impl Firewall {
                         .proto(pfctl::Proto::Tcp)
                         .keep_state(pfctl::StatePolicy::Keep)
                         .tcp_flags(Self::get_tcp_flags())
-                        .to(pfctl::Endpoint::new(ipv6_gateway, 53))
+                        .to(pfctl::Endpoint::new(*dns_server, 53))
                         .build()?;
-                    rules.push(v6_dns_rule_tcp);
-                    let v6_dns_rule_udp = self
+                    rules.push(allow_tcp_dns_rule);
+                    let allow_udp_dns_rule = self
                         .create_rule_builder(FilterRuleAction::Pass)
                         .direction(pfctl::Direction::Out)
                         .quick(true)
                         .interface(&tunnel.interface)
                         .proto(pfctl::Proto::Udp)
-                        .to(pfctl::Endpoint::new(ipv6_gateway, 53))
+                        .to(pfctl::Endpoint::new(*dns_server, 53))
                         .build()?;
-                    rules.push(v6_dns_rule_udp);
+                    rules.push(allow_udp_dns_rule);
                 }
 
-                rules.push(self.get_allow_relay_rule(peer_endpoint)?);
+                for endpoint in peer_endpoints {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
+                if let Some(endpoint) = allowed_endpoint {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
 
                 // Important to block DNS *before* we allow the tunnel and allow LAN. So DNS
                 // can't leak to the wrong IPs in the tunnel or on the LAN.
                 rules.append(&mut self.get_block_dns_rules()?);
 
                 rules.push(self.get_allow_tunnel_rule(tunnel.interface.as_str())?);
+                rules.append(
+                    &mut self.get_allow_excluded_destination_rules(&excluded_destinations)?,
+                );
 
                 if allow_lan {
                     rules.append(&mut self.get_allow_lan_rules()?);
@@ -165,8 +161,14 @@ impl Firewall {
 
                 Ok(rules)
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+            } => {
                 let mut rules = Vec::new();
+                if let Some(endpoint) = allowed_endpoint {
+                    rules.push(self.get_allow_relay_rule(endpoint)?);
+                }
                 if allow_lan {
                     // Important to block DNS before allow LAN (so DNS does not leak to the LAN)
                     rules.append(&mut self.get_block_dns_rules()?);
@@ -264,6 +266,30 @@ impl Firewall {
         Ok(vec![lo0_rule])
     }
 
+    fn get_allow_excluded_destination_rules(
+        &self,
+        excluded_destinations: &[IpNetwork],
+    ) -> Result<Vec<pfctl::FilterRule>> {
+        let mut rules = vec![];
+        for net in excluded_destinations {
+            let mut rule_builder = self.create_rule_builder(FilterRuleAction::Pass);
+            rule_builder.quick(true);
+            let allow_out = rule_builder
+                .direction(pfctl::Direction::Out)
+                .from(pfctl::Ip::Any)
+                .to(pfctl::Ip::from(*net))
+                .build()?;
+            let allow_in = rule_builder
+                .direction(pfctl::Direction::In)
+                .from(pfctl::Ip::from(*net))
+                .to(pfctl::Ip::Any)
+                .build()?;
+            rules.push(allow_out);
+            rules.push(allow_in);
+        }
+        Ok(rules)
+    }
+
     fn get_allow_lan_rules(&self) -> Result<Vec<pfctl::FilterRule>> {
         let mut rules = vec![];
         for net in &*super::ALLOWED_LAN_NETS {
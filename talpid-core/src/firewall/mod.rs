@@ -7,7 +7,9 @@ use std::fmt;
 use std::net::IpAddr;
 #[cfg(unix)]
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
-use talpid_types::net::Endpoint;
+#[cfg(target_os = "linux")]
+use talpid_types::net::GatewayOptions;
+use talpid_types::{diagnostics::FirewallDiagnostics, net::Endpoint};
 
 
 #[cfg(target_os = "macos")]
@@ -86,28 +88,36 @@ const DHCPV6_CLIENT_PORT: u16 = 546;
 pub enum FirewallPolicy {
     /// Allow traffic only to server
     Connecting {
-        /// The peer endpoint that should be allowed.
-        peer_endpoint: Endpoint,
+        /// The peer endpoints that should be allowed.
+        peer_endpoints: Vec<Endpoint>,
         /// Hosts that should be pingable whilst connecting.
         pingable_hosts: Vec<IpAddr>,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Extra endpoint that should be allowed, such as a proxy for API traffic.
+        allowed_endpoint: Option<Endpoint>,
     },
 
     /// Allow traffic only to server and over tunnel interface
     Connected {
-        /// The peer endpoint that should be allowed.
-        peer_endpoint: Endpoint,
+        /// The peer endpoints that should be allowed.
+        peer_endpoints: Vec<Endpoint>,
         /// Metadata about the tunnel and tunnel interface.
         tunnel: crate::tunnel::TunnelMetadata,
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Extra endpoint that should be allowed, such as a proxy for API traffic.
+        allowed_endpoint: Option<Endpoint>,
+        /// Destination networks that are reached outside of the tunnel.
+        excluded_destinations: Vec<ipnetwork::IpNetwork>,
     },
 
     /// Block all network traffic in and out from the computer.
     Blocked {
         /// Flag setting if communication with LAN networks should be possible.
         allow_lan: bool,
+        /// Extra endpoint that should be allowed, such as a proxy for API traffic.
+        allowed_endpoint: Option<Endpoint>,
     },
 }
 
@@ -115,52 +125,98 @@ impl fmt::Display for FirewallPolicy {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             FirewallPolicy::Connecting {
-                peer_endpoint,
+                peer_endpoints,
                 pingable_hosts,
                 allow_lan,
-            } => write!(
-                f,
-                "Connecting to {} with gateways {}, {} LAN",
-                peer_endpoint,
-                pingable_hosts
-                    .iter()
-                    .map(ToString::to_string)
-                    .collect::<Vec<String>>()
-                    .join(","),
-                if *allow_lan { "Allowing" } else { "Blocking" }
-            ),
+                allowed_endpoint,
+            } => {
+                write!(
+                    f,
+                    "Connecting to {} with gateways {}, {} LAN",
+                    format_endpoints(peer_endpoints),
+                    pingable_hosts
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>()
+                        .join(","),
+                    if *allow_lan { "Allowing" } else { "Blocking" }
+                )?;
+                write_allowed_endpoint(f, allowed_endpoint)
+            }
             FirewallPolicy::Connected {
-                peer_endpoint,
+                peer_endpoints,
                 tunnel,
                 allow_lan,
-            } => write!(
-                f,
-                "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}), {} LAN",
-                peer_endpoint,
-                tunnel.interface,
-                tunnel
-                    .ips
-                    .iter()
-                    .map(|ip| ip.to_string())
-                    .collect::<Vec<_>>()
-                    .join(","),
-                tunnel.ipv4_gateway,
-                tunnel.ipv6_gateway,
-                if *allow_lan { "Allowing" } else { "Blocking" }
-            ),
-            FirewallPolicy::Blocked { allow_lan } => write!(
-                f,
-                "Blocked, {} LAN",
-                if *allow_lan { "Allowing" } else { "Blocking" }
-            ),
+                allowed_endpoint,
+                excluded_destinations,
+            } => {
+                write!(
+                    f,
+                    "Connected to {} over \"{}\" (ip: {}, v4 gw: {}, v6 gw: {:?}), {} LAN",
+                    format_endpoints(peer_endpoints),
+                    tunnel.interface,
+                    tunnel
+                        .ips
+                        .iter()
+                        .map(|ip| ip.to_string())
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    tunnel.ipv4_gateway,
+                    tunnel.ipv6_gateway,
+                    if *allow_lan { "Allowing" } else { "Blocking" }
+                )?;
+                write_allowed_endpoint(f, allowed_endpoint)?;
+                if !excluded_destinations.is_empty() {
+                    write!(
+                        f,
+                        ", excluding {}",
+                        excluded_destinations
+                            .iter()
+                            .map(|network| network.to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    )?;
+                }
+                Ok(())
+            }
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+            } => {
+                write!(
+                    f,
+                    "Blocked, {} LAN",
+                    if *allow_lan { "Allowing" } else { "Blocking" }
+                )?;
+                write_allowed_endpoint(f, allowed_endpoint)
+            }
         }
     }
 }
 
+fn format_endpoints(endpoints: &[Endpoint]) -> String {
+    endpoints
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn write_allowed_endpoint(
+    f: &mut fmt::Formatter<'_>,
+    allowed_endpoint: &Option<Endpoint>,
+) -> fmt::Result {
+    match allowed_endpoint {
+        Some(endpoint) => write!(f, ", allowing {}", endpoint),
+        None => Ok(()),
+    }
+}
+
 /// Manages network security of the computer/device. Can apply and enforce firewall policies
 /// by manipulating the OS firewall and DNS settings.
 pub struct Firewall {
     inner: imp::Firewall,
+    active_policy: Option<FirewallPolicy>,
 }
 
 /// Arguments required when first initializing the firewall.
@@ -174,8 +230,17 @@ pub struct FirewallArguments {
 impl Firewall {
     /// Returns a new `Firewall`, ready to apply policies.
     pub fn new(args: FirewallArguments) -> Result<Self, Error> {
+        let active_policy = if args.initialize_blocked {
+            Some(FirewallPolicy::Blocked {
+                allow_lan: args.allow_lan.unwrap_or(false),
+                allowed_endpoint: None,
+            })
+        } else {
+            None
+        };
         Ok(Firewall {
             inner: imp::Firewall::new(args)?,
+            active_policy,
         })
     }
 
@@ -183,15 +248,53 @@ impl Firewall {
     /// until this method is called again with another policy, or until `reset_policy` is called.
     pub fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Error> {
         log::info!("Applying firewall policy: {}", policy);
-        self.inner.apply_policy(policy)
+        self.active_policy = None;
+        self.inner.apply_policy(policy.clone())?;
+        self.active_policy = Some(policy);
+        Ok(())
     }
 
     /// Resets/removes any currently enforced `FirewallPolicy`. Returns the system to the same state
     /// it had before any policy was applied through this `Firewall` instance.
     pub fn reset_policy(&mut self) -> Result<(), Error> {
         log::info!("Resetting firewall policy");
+        self.active_policy = None;
         self.inner.reset_policy()
     }
+
+    /// Applies future policies inside the named network namespace instead of on the host, or
+    /// on the host again if `None`. Any policy enforced in the previous location is removed.
+    #[cfg(target_os = "linux")]
+    pub fn set_network_namespace(&mut self, name: Option<&str>) -> Result<(), Error> {
+        if self.inner.set_network_namespace(name)? {
+            log::info!(
+                "Firewall policies are applied in network namespace: {}",
+                name.unwrap_or("none")
+            );
+            self.active_policy = None;
+        }
+        Ok(())
+    }
+
+    /// Makes future policies forward traffic from a local network through the tunnel when
+    /// connected. Takes effect the next time a policy is applied.
+    #[cfg(target_os = "linux")]
+    pub fn set_gateway(&mut self, gateway: Option<GatewayOptions>) {
+        self.inner.set_gateway(gateway);
+    }
+
+    /// Describes the currently enforced policy and, on Linux, which netfilter tables exist.
+    pub fn diagnostics(&self) -> Result<FirewallDiagnostics, Error> {
+        #[cfg(target_os = "linux")]
+        let netfilter_tables = Some(self.inner.list_tables()?);
+        #[cfg(not(target_os = "linux"))]
+        let netfilter_tables = None;
+
+        Ok(FirewallDiagnostics {
+            active_policy: self.active_policy.as_ref().map(ToString::to_string),
+            netfilter_tables,
+        })
+    }
 }
 
 /// Abstract firewall interaction trait. Used by the OS specific implementations.
@@ -5,7 +5,7 @@ use std::{net::IpAddr, ptr};
 use self::winfw::*;
 use super::{FirewallArguments, FirewallPolicy, FirewallT};
 use crate::winnet;
-use log::{debug, error, trace};
+use log::{debug, error, trace, warn};
 use talpid_types::net::Endpoint;
 use widestring::WideCString;
 
@@ -29,6 +29,10 @@ pub enum Error {
     #[error(display = "Failed to apply connected firewall policy")]
     ApplyingConnectedPolicy,
 
+    /// The firewall module only allows traffic to a single peer endpoint
+    #[error(display = "Expected a single peer endpoint, got {}", _0)]
+    PeerEndpointCount(usize),
+
     /// Failure to apply firewall _blocked_ policy
     #[error(display = "Failed to apply blocked firewall policy")]
     ApplyingBlockedPolicy,
@@ -77,30 +81,42 @@ impl FirewallT for Firewall {
     fn apply_policy(&mut self, policy: FirewallPolicy) -> Result<(), Self::Error> {
         match policy {
             FirewallPolicy::Connecting {
-                peer_endpoint,
+                peer_endpoints,
                 pingable_hosts,
                 allow_lan,
+                allowed_endpoint,
             } => {
+                let peer_endpoint = single_peer_endpoint(&peer_endpoints)?;
                 let cfg = &WinFwSettings::new(allow_lan);
                 // TODO: Determine interface alias at runtime
                 self.set_connecting_state(
-                    &peer_endpoint,
+                    peer_endpoint,
                     &cfg,
                     "wg-mullvad".to_string(),
                     &pingable_hosts,
+                    allowed_endpoint.as_ref(),
                 )
             }
             FirewallPolicy::Connected {
-                peer_endpoint,
+                peer_endpoints,
                 tunnel,
                 allow_lan,
+                allowed_endpoint,
+                excluded_destinations,
             } => {
+                let peer_endpoint = single_peer_endpoint(&peer_endpoints)?;
+                if !excluded_destinations.is_empty() {
+                    warn!("Excluded destinations are not allowed by the firewall on Windows");
+                }
                 let cfg = &WinFwSettings::new(allow_lan);
-                self.set_connected_state(&peer_endpoint, &cfg, &tunnel)
+                self.set_connected_state(peer_endpoint, &cfg, &tunnel, allowed_endpoint.as_ref())
             }
-            FirewallPolicy::Blocked { allow_lan } => {
+            FirewallPolicy::Blocked {
+                allow_lan,
+                allowed_endpoint,
+            } => {
                 let cfg = &WinFwSettings::new(allow_lan);
-                self.set_blocked_state(&cfg)
+                self.set_blocked_state(&cfg, allowed_endpoint.as_ref())
             }
         }
     }
@@ -111,6 +127,15 @@ impl FirewallT for Firewall {
     }
 }
 
+/// The firewall module only allows a single relay, so tunnels with several peer endpoints are
+/// refused rather than having all but one of their peers blocked.
+fn single_peer_endpoint(peer_endpoints: &[Endpoint]) -> Result<&Endpoint, Error> {
+    match peer_endpoints {
+        [peer_endpoint] => Ok(peer_endpoint),
+        _ => Err(Error::PeerEndpointCount(peer_endpoints.len())),
+    }
+}
+
 impl Drop for Firewall {
     fn drop(&mut self) {
         if unsafe {
@@ -132,6 +157,7 @@ impl Firewall {
         winfw_settings: &WinFwSettings,
         _tunnel_iface_alias: String,
         pingable_hosts: &Vec<IpAddr>,
+        allowed_endpoint: Option<&Endpoint>,
     ) -> Result<(), Error> {
         trace!("Applying 'connecting' firewall policy");
         let ip_str = Self::widestring_ip(endpoint.address.ip());
@@ -143,10 +169,18 @@ impl Firewall {
             protocol: WinFwProt::from(endpoint.protocol),
         };
 
+        let allowed_endpoint = allowed_endpoint.map(WinFwAllowedEndpoint::new);
+        let allowed_endpoint_ptr = WinFwAllowedEndpoint::as_ptr(&allowed_endpoint);
+
         if pingable_hosts.is_empty() {
             unsafe {
-                return WinFw_ApplyPolicyConnecting(winfw_settings, &winfw_relay, ptr::null())
-                    .into_result();
+                return WinFw_ApplyPolicyConnecting(
+                    winfw_settings,
+                    &winfw_relay,
+                    allowed_endpoint_ptr,
+                    ptr::null(),
+                )
+                .into_result();
             }
         }
 
@@ -166,7 +200,13 @@ impl Firewall {
         };
 
         unsafe {
-            WinFw_ApplyPolicyConnecting(winfw_settings, &winfw_relay, &pingable_hosts).into_result()
+            WinFw_ApplyPolicyConnecting(
+                winfw_settings,
+                &winfw_relay,
+                allowed_endpoint_ptr,
+                &pingable_hosts,
+            )
+            .into_result()
         }
     }
 
@@ -180,13 +220,22 @@ impl Firewall {
         endpoint: &Endpoint,
         winfw_settings: &WinFwSettings,
         tunnel_metadata: &crate::tunnel::TunnelMetadata,
+        allowed_endpoint: Option<&Endpoint>,
     ) -> Result<(), Error> {
         trace!("Applying 'connected' firewall policy");
         let ip_str = Self::widestring_ip(endpoint.address.ip());
-        let v4_gateway = Self::widestring_ip(tunnel_metadata.ipv4_gateway.into());
-        let v6_gateway = tunnel_metadata
-            .ipv6_gateway
-            .map(|v6_ip| Self::widestring_ip(v6_ip.into()));
+        // The firewall module allows DNS to one server per address family.
+        let v4_dns_server = tunnel_metadata
+            .dns_servers
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .map(|ip| Self::widestring_ip(*ip))
+            .ok_or(Error::ApplyingConnectedPolicy)?;
+        let v6_dns_server = tunnel_metadata
+            .dns_servers
+            .iter()
+            .find(|ip| ip.is_ipv6())
+            .map(|ip| Self::widestring_ip(*ip));
 
         let tunnel_alias =
             WideCString::new(tunnel_metadata.interface.encode_utf16().collect::<Vec<_>>()).unwrap();
@@ -207,26 +256,66 @@ impl Firewall {
             debug!("Network interface metrics were not changed");
         }
 
-        let v6_gateway_ptr = match &v6_gateway {
+        let v6_dns_server_ptr = match &v6_dns_server {
             Some(v6_ip) => v6_ip.as_ptr(),
             None => ptr::null(),
         };
 
+        let allowed_endpoint = allowed_endpoint.map(WinFwAllowedEndpoint::new);
+
         unsafe {
             WinFw_ApplyPolicyConnected(
                 winfw_settings,
                 &winfw_relay,
+                WinFwAllowedEndpoint::as_ptr(&allowed_endpoint),
                 tunnel_alias.as_ptr(),
-                v4_gateway.as_ptr(),
-                v6_gateway_ptr,
+                v4_dns_server.as_ptr(),
+                v6_dns_server_ptr,
             )
             .into_result()
         }
     }
 
-    fn set_blocked_state(&mut self, winfw_settings: &WinFwSettings) -> Result<(), Error> {
+    fn set_blocked_state(
+        &mut self,
+        winfw_settings: &WinFwSettings,
+        allowed_endpoint: Option<&Endpoint>,
+    ) -> Result<(), Error> {
         trace!("Applying 'blocked' firewall policy");
-        unsafe { WinFw_ApplyPolicyBlocked(winfw_settings).into_result() }
+        let allowed_endpoint = allowed_endpoint.map(WinFwAllowedEndpoint::new);
+        unsafe {
+            WinFw_ApplyPolicyBlocked(
+                winfw_settings,
+                WinFwAllowedEndpoint::as_ptr(&allowed_endpoint),
+            )
+            .into_result()
+        }
+    }
+}
+
+/// Endpoint passed to winfw, together with the string that it points to.
+struct WinFwAllowedEndpoint {
+    _ip: WideCString,
+    endpoint: WinFwEndpoint,
+}
+
+impl WinFwAllowedEndpoint {
+    fn new(endpoint: &Endpoint) -> Self {
+        let ip = Firewall::widestring_ip(endpoint.address.ip());
+        // The string is heap allocated, so the pointer stays valid when `ip` is moved.
+        let endpoint = WinFwEndpoint {
+            ip: ip.as_ptr(),
+            port: endpoint.address.port(),
+            protocol: WinFwProt::from(endpoint.protocol),
+        };
+        WinFwAllowedEndpoint { _ip: ip, endpoint }
+    }
+
+    fn as_ptr(endpoint: &Option<Self>) -> *const WinFwEndpoint {
+        match endpoint {
+            Some(endpoint) => &endpoint.endpoint,
+            None => ptr::null(),
+        }
     }
 }
 
@@ -245,6 +334,13 @@ mod winfw {
         pub protocol: WinFwProt,
     }
 
+    #[repr(C)]
+    pub struct WinFwEndpoint {
+        pub ip: *const libc::wchar_t,
+        pub port: u16,
+        pub protocol: WinFwProt,
+    }
+
     #[repr(u8)]
     #[derive(Clone, Copy)]
     pub enum WinFwProt {
@@ -322,6 +418,7 @@ mod winfw {
         pub fn WinFw_ApplyPolicyConnecting(
             settings: &WinFwSettings,
             relay: &WinFwRelay,
+            allowed_endpoint: *const WinFwEndpoint,
             pingable_hosts: *const WinFwPingableHosts,
         ) -> ApplyConnectingResult;
 
@@ -329,13 +426,17 @@ mod winfw {
         pub fn WinFw_ApplyPolicyConnected(
             settings: &WinFwSettings,
             relay: &WinFwRelay,
+            allowedEndpoint: *const WinFwEndpoint,
             tunnelIfaceAlias: *const libc::wchar_t,
-            v4Gateway: *const libc::wchar_t,
-            v6Gateway: *const libc::wchar_t,
+            v4DnsHost: *const libc::wchar_t,
+            v6DnsHost: *const libc::wchar_t,
         ) -> ApplyConnectedResult;
 
         #[link_name = "WinFw_ApplyPolicyBlocked"]
-        pub fn WinFw_ApplyPolicyBlocked(settings: &WinFwSettings) -> ApplyBlockedResult;
+        pub fn WinFw_ApplyPolicyBlocked(
+            settings: &WinFwSettings,
+            allowedEndpoint: *const WinFwEndpoint,
+        ) -> ApplyBlockedResult;
 
         #[link_name = "WinFw_Reset"]
         pub fn WinFw_Reset() -> ResettingPolicyResult;
@@ -0,0 +1,535 @@
+use nix::{
+    ifaddrs::getifaddrs,
+    poll::{poll, PollFd, PollFlags},
+    sys::socket::SockAddr,
+};
+use std::{
+    collections::HashMap,
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    os::unix::io::AsRawFd,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use talpid_types::{net::GatewayOptions, ErrorExt};
+
+/// Kernel setting that controls whether IPv4 packets are forwarded between interfaces.
+const IPV4_FORWARD_PATH: &str = "/proc/sys/net/ipv4/ip_forward";
+
+const DNS_PORT: u16 = 53;
+/// Largest DNS message that is relayed. Larger responses are truncated by the resolver anyway.
+const MAX_DNS_MESSAGE_SIZE: usize = 4096;
+/// How often the forwarder checks whether it should stop.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for a resolver to answer a query before asking the next one.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+/// Size of the DNS message header, which starts with the transaction ID.
+const DNS_HEADER_SIZE: usize = 12;
+/// Largest number of queries that are relayed at the same time. Further queries are dropped, and
+/// retried by the clients.
+const MAX_PENDING_QUERIES: usize = 256;
+
+/// Errors that can happen when starting gateway mode.
+#[derive(Debug, err_derive::Error)]
+#[error(no_from)]
+pub enum Error {
+    /// Failed to read or change whether the kernel forwards IPv4 packets.
+    #[error(display = "Failed to configure IPv4 forwarding")]
+    IpForwarding(#[error(source)] io::Error),
+
+    /// Failed to list the addresses of the network interfaces.
+    #[error(display = "Failed to list interface addresses")]
+    ListAddresses(#[error(source)] nix::Error),
+
+    /// The LAN interface has no IPv4 address for the DNS forwarder to listen on.
+    #[error(display = "No IPv4 address found on interface {}", _0)]
+    NoLanAddress(String),
+
+    /// Failed to bind the DNS forwarder socket.
+    #[error(display = "Failed to bind DNS forwarder to {}", _0)]
+    BindDnsForwarder(SocketAddr, #[error(source)] io::Error),
+}
+
+/// Forwards traffic from a local network through the tunnel for as long as it's alive. The
+/// firewall rules that allow and masquerade the forwarded traffic are managed by the firewall.
+pub struct Gateway {
+    previous_ip_forward: String,
+    _dns_forwarder: Option<DnsForwarder>,
+}
+
+impl Gateway {
+    /// Enables IPv4 forwarding and, if requested, starts answering DNS queries on the LAN
+    /// interface by relaying them to `dns_servers`, in order of preference.
+    pub fn start(options: &GatewayOptions, dns_servers: &[IpAddr]) -> Result<Self, Error> {
+        let dns_forwarder = if options.dns_forwarder {
+            let lan_address = lan_ipv4_address(&options.lan_interface)?;
+            Some(DnsForwarder::start(
+                SocketAddr::new(lan_address.into(), DNS_PORT),
+                dns_servers
+                    .iter()
+                    .map(|server| SocketAddr::new(*server, DNS_PORT))
+                    .collect(),
+            )?)
+        } else {
+            None
+        };
+
+        let previous_ip_forward =
+            fs::read_to_string(IPV4_FORWARD_PATH).map_err(Error::IpForwarding)?;
+        fs::write(IPV4_FORWARD_PATH, "1").map_err(Error::IpForwarding)?;
+        log::info!("Gateway mode started, {}", options);
+
+        Ok(Gateway {
+            previous_ip_forward,
+            _dns_forwarder: dns_forwarder,
+        })
+    }
+}
+
+impl Drop for Gateway {
+    fn drop(&mut self) {
+        if let Err(error) = fs::write(IPV4_FORWARD_PATH, self.previous_ip_forward.trim()) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to restore IPv4 forwarding setting")
+            );
+        }
+        log::info!("Gateway mode stopped");
+    }
+}
+
+/// Relays DNS queries received on the LAN to the resolvers used by the tunnel, and the responses
+/// back. A single thread relays all queries through one upstream socket per address family. The
+/// transaction ID of each query is replaced while it's pending, so responses can't be confused
+/// between clients.
+struct DnsForwarder {
+    stop: Arc<AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl DnsForwarder {
+    fn start(listen_address: SocketAddr, resolvers: Vec<SocketAddr>) -> Result<Self, Error> {
+        let mut relay = DnsRelay::new(listen_address, resolvers)?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let thread_stop = stop.clone();
+        let thread = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Acquire) {
+                if let Err(error) = relay.poll() {
+                    log::error!(
+                        "{}",
+                        error.display_chain_with_msg("DNS forwarder failed to relay queries")
+                    );
+                    break;
+                }
+            }
+            log::debug!("DNS forwarder on {} stopped", listen_address);
+        });
+
+        Ok(DnsForwarder {
+            stop,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for DnsForwarder {
+    /// Stops the forwarder and waits for its sockets to be closed, so that the DNS port can be
+    /// bound again right away. This takes at most `POLL_INTERVAL`.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("DNS forwarder thread panicked");
+            }
+        }
+    }
+}
+
+/// A query that has been relayed to a resolver and not answered yet.
+struct PendingQuery {
+    client: SocketAddr,
+    client_id: [u8; 2],
+    /// The query as it was relayed, for resending it to the next resolver.
+    query: Vec<u8>,
+    resolver: usize,
+    deadline: Instant,
+}
+
+struct DnsRelay {
+    listen_socket: UdpSocket,
+    ipv4_socket: UdpSocket,
+    ipv6_socket: Option<UdpSocket>,
+    resolvers: Vec<SocketAddr>,
+    pending: HashMap<u16, PendingQuery>,
+    next_id: u16,
+}
+
+impl DnsRelay {
+    fn new(listen_address: SocketAddr, resolvers: Vec<SocketAddr>) -> Result<Self, Error> {
+        let listen_socket = bind_nonblocking(listen_address)
+            .map_err(|error| Error::BindDnsForwarder(listen_address, error))?;
+        let ipv4_address = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0);
+        let ipv4_socket = bind_nonblocking(ipv4_address)
+            .map_err(|error| Error::BindDnsForwarder(ipv4_address, error))?;
+        let ipv6_socket = if resolvers.iter().any(SocketAddr::is_ipv6) {
+            let ipv6_address = SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0);
+            Some(
+                bind_nonblocking(ipv6_address)
+                    .map_err(|error| Error::BindDnsForwarder(ipv6_address, error))?,
+            )
+        } else {
+            None
+        };
+
+        log::debug!(
+            "DNS forwarder relaying queries from {} to {}",
+            listen_address,
+            resolvers
+                .iter()
+                .map(|resolver| resolver.ip().to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        );
+        Ok(DnsRelay {
+            listen_socket,
+            ipv4_socket,
+            ipv6_socket,
+            resolvers,
+            pending: HashMap::new(),
+            next_id: 0,
+        })
+    }
+
+    /// Waits for at most `POLL_INTERVAL` and relays whatever arrived in the meantime.
+    fn poll(&mut self) -> io::Result<()> {
+        let mut fds = vec![
+            PollFd::new(self.listen_socket.as_raw_fd(), PollFlags::POLLIN),
+            PollFd::new(self.ipv4_socket.as_raw_fd(), PollFlags::POLLIN),
+        ];
+        if let Some(socket) = &self.ipv6_socket {
+            fds.push(PollFd::new(socket.as_raw_fd(), PollFlags::POLLIN));
+        }
+        match poll(&mut fds, POLL_INTERVAL.as_millis() as libc::c_int) {
+            Ok(_) | Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => (),
+            Err(error) => return Err(io::Error::new(io::ErrorKind::Other, error)),
+        }
+
+        let mut buffer = [0u8; MAX_DNS_MESSAGE_SIZE];
+        while let Some((length, client)) = receive(&self.listen_socket, &mut buffer)? {
+            self.relay_query(client, &buffer[..length]);
+        }
+        self.relay_responses(&mut buffer, false)?;
+        self.relay_responses(&mut buffer, true)?;
+        self.retry_expired_queries();
+        Ok(())
+    }
+
+    fn relay_responses(&mut self, buffer: &mut [u8], ipv6: bool) -> io::Result<()> {
+        loop {
+            let socket = match (ipv6, &self.ipv6_socket) {
+                (false, _) => &self.ipv4_socket,
+                (true, Some(socket)) => socket,
+                (true, None) => return Ok(()),
+            };
+            match receive(socket, buffer)? {
+                Some((length, resolver)) => self.relay_response(resolver, &mut buffer[..length]),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    fn relay_query(&mut self, client: SocketAddr, query: &[u8]) {
+        if query.len() < DNS_HEADER_SIZE {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING_QUERIES {
+            log::debug!(
+                "Dropping DNS query from {}: too many pending queries",
+                client
+            );
+            return;
+        }
+        let id = self.allocate_id();
+        let mut relayed_query = query.to_vec();
+        relayed_query[..2].copy_from_slice(&id.to_be_bytes());
+        let pending_query = PendingQuery {
+            client,
+            client_id: [query[0], query[1]],
+            query: relayed_query,
+            resolver: 0,
+            deadline: Instant::now() + UPSTREAM_TIMEOUT,
+        };
+        self.send_to_resolver(&pending_query);
+        self.pending.insert(id, pending_query);
+    }
+
+    fn relay_response(&mut self, resolver: SocketAddr, response: &mut [u8]) {
+        if response.len() < DNS_HEADER_SIZE {
+            return;
+        }
+        let id = u16::from_be_bytes([response[0], response[1]]);
+        let pending_query = match self.pending.get(&id) {
+            // Only accept the response from the resolver that was asked.
+            Some(query) if self.resolvers[query.resolver] == resolver => {
+                self.pending.remove(&id).unwrap()
+            }
+            _ => return,
+        };
+        response[..2].copy_from_slice(&pending_query.client_id);
+        if let Err(error) = self.listen_socket.send_to(response, pending_query.client) {
+            log::debug!(
+                "{}",
+                error.display_chain_with_msg(&format!(
+                    "Failed to relay DNS response to {}",
+                    pending_query.client
+                ))
+            );
+        }
+    }
+
+    /// Sends queries that a resolver hasn't answered in time to the next resolver, and gives up
+    /// on those that no resolver has answered.
+    fn retry_expired_queries(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<u16> = self
+            .pending
+            .iter()
+            .filter(|(_, query)| query.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            let mut query = self.pending.remove(&id).unwrap();
+            query.resolver += 1;
+            if query.resolver < self.resolvers.len() {
+                query.deadline = now + UPSTREAM_TIMEOUT;
+                self.send_to_resolver(&query);
+                self.pending.insert(id, query);
+            } else {
+                log::debug!("No resolver answered a DNS query from {}", query.client);
+            }
+        }
+    }
+
+    fn send_to_resolver(&self, query: &PendingQuery) {
+        let resolver = self.resolvers[query.resolver];
+        let socket = match (resolver, &self.ipv6_socket) {
+            (SocketAddr::V6(_), Some(socket)) => socket,
+            _ => &self.ipv4_socket,
+        };
+        if let Err(error) = socket.send_to(&query.query, resolver) {
+            log::debug!(
+                "{}",
+                error.display_chain_with_msg(&format!("Failed to relay DNS query to {}", resolver))
+            );
+        }
+    }
+
+    fn allocate_id(&mut self) -> u16 {
+        // There are far fewer pending queries than IDs, so a free one is always found.
+        loop {
+            let id = self.next_id;
+            self.next_id = self.next_id.wrapping_add(1);
+            if !self.pending.contains_key(&id) {
+                return id;
+            }
+        }
+    }
+}
+
+fn bind_nonblocking(address: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Receives a datagram from a non-blocking socket, or returns `None` if there is none.
+fn receive(socket: &UdpSocket, buffer: &mut [u8]) -> io::Result<Option<(usize, SocketAddr)>> {
+    match socket.recv_from(buffer) {
+        Ok(received) => Ok(Some(received)),
+        Err(ref error) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+        // ICMP errors for earlier datagrams are reported on the next receive.
+        Err(ref error) if error.kind() == io::ErrorKind::ConnectionRefused => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn lan_ipv4_address(interface: &str) -> Result<Ipv4Addr, Error> {
+    getifaddrs()
+        .map_err(Error::ListAddresses)?
+        .filter(|address| address.interface_name == interface)
+        .filter_map(|address| match address.address {
+            Some(SockAddr::Inet(inet)) => match inet.to_std() {
+                SocketAddr::V4(address) => Some(*address.ip()),
+                SocketAddr::V6(_) => None,
+            },
+            _ => None,
+        })
+        .next()
+        .ok_or_else(|| Error::NoLanAddress(interface.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_socket() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+    }
+
+    fn relay(resolvers: &[&UdpSocket]) -> DnsRelay {
+        DnsRelay::new(
+            "127.0.0.1:0".parse().unwrap(),
+            resolvers
+                .iter()
+                .map(|resolver| resolver.local_addr().unwrap())
+                .collect(),
+        )
+        .unwrap()
+    }
+
+    fn query(id: u16) -> Vec<u8> {
+        let mut query = id.to_be_bytes().to_vec();
+        query.extend_from_slice(&[1, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0xca, 0xfe]);
+        query
+    }
+
+    fn receive_message(socket: &UdpSocket) -> (Vec<u8>, SocketAddr) {
+        let mut buffer = [0u8; MAX_DNS_MESSAGE_SIZE];
+        let (length, sender) = socket.recv_from(&mut buffer).unwrap();
+        (buffer[..length].to_vec(), sender)
+    }
+
+    fn expire_pending_queries(relay: &mut DnsRelay) {
+        for query in relay.pending.values_mut() {
+            query.deadline = Instant::now();
+        }
+        relay.retry_expired_queries();
+    }
+
+    #[test]
+    fn test_transaction_id_rewriting() {
+        let resolver = local_socket();
+        let client = local_socket();
+        let mut relay = relay(&[&resolver]);
+        let listen_address = relay.listen_socket.local_addr().unwrap();
+
+        // Two clients using the same transaction ID get different IDs upstream
+        client.send_to(&query(0xabcd), listen_address).unwrap();
+        relay.poll().unwrap();
+        relay.relay_query("127.0.0.1:1".parse().unwrap(), &query(0xabcd));
+        let (first, relay_address) = receive_message(&resolver);
+        let (second, _) = receive_message(&resolver);
+        assert_ne!(first[..2], second[..2]);
+        assert_eq!(first[2..], query(0xabcd)[2..]);
+        assert_eq!(relay.pending.len(), 2);
+
+        // The response gets the ID of the client back
+        let mut response = first.clone();
+        response[2] |= 0x80;
+        resolver.send_to(&response, relay_address).unwrap();
+        relay.poll().unwrap();
+        let (relayed_response, sender) = receive_message(&client);
+        assert_eq!(sender, listen_address);
+        assert_eq!(relayed_response[..2], [0xab, 0xcd]);
+        assert_eq!(relayed_response[2..], response[2..]);
+        assert_eq!(relay.pending.len(), 1);
+    }
+
+    #[test]
+    fn test_responses_from_other_hosts_are_ignored() {
+        let resolver = local_socket();
+        let other_host = local_socket();
+        let mut relay = relay(&[&resolver]);
+
+        relay.relay_query("127.0.0.1:1".parse().unwrap(), &query(1));
+        let (relayed_query, _) = receive_message(&resolver);
+        let id = u16::from_be_bytes([relayed_query[0], relayed_query[1]]);
+
+        relay.relay_response(other_host.local_addr().unwrap(), &mut relayed_query.clone());
+        assert!(relay.pending.contains_key(&id));
+        relay.relay_response(
+            resolver.local_addr().unwrap(),
+            &mut query(id.wrapping_add(1)),
+        );
+        assert!(relay.pending.contains_key(&id));
+        relay.relay_response(resolver.local_addr().unwrap(), &mut relayed_query.clone());
+        assert!(relay.pending.is_empty());
+    }
+
+    #[test]
+    fn test_pending_query_limit() {
+        let resolver = local_socket();
+        let mut relay = relay(&[&resolver]);
+        let client = "127.0.0.1:1".parse().unwrap();
+
+        relay.relay_query(client, &query(1)[..DNS_HEADER_SIZE - 1]);
+        assert!(relay.pending.is_empty());
+
+        for id in 0..=MAX_PENDING_QUERIES {
+            relay.relay_query(client, &query(id as u16));
+        }
+        assert_eq!(relay.pending.len(), MAX_PENDING_QUERIES);
+
+        // IDs that are still pending are skipped when allocating new ones
+        relay.pending.remove(&7);
+        relay.next_id = 0;
+        relay.relay_query(client, &query(1));
+        assert_eq!(relay.pending.len(), MAX_PENDING_QUERIES);
+        assert!(relay.pending.contains_key(&7));
+    }
+
+    #[test]
+    fn test_resolver_fallback() {
+        let first_resolver = local_socket();
+        let second_resolver = local_socket();
+        let mut relay = relay(&[&first_resolver, &second_resolver]);
+
+        relay.relay_query("127.0.0.1:1".parse().unwrap(), &query(1));
+        let (relayed_query, _) = receive_message(&first_resolver);
+
+        // Queries that are not yet expired are left alone
+        relay.retry_expired_queries();
+        assert_eq!(relay.pending.len(), 1);
+
+        expire_pending_queries(&mut relay);
+        let (retried_query, _) = receive_message(&second_resolver);
+        assert_eq!(retried_query, relayed_query);
+        let pending_query = relay.pending.values().next().unwrap();
+        assert_eq!(pending_query.resolver, 1);
+        assert!(pending_query.deadline > Instant::now());
+
+        // A late response from the first resolver is no longer accepted
+        let mut response = relayed_query.clone();
+        relay.relay_response(first_resolver.local_addr().unwrap(), &mut response);
+        assert_eq!(relay.pending.len(), 1);
+
+        // The query is dropped once no resolver has answered it
+        expire_pending_queries(&mut relay);
+        assert!(relay.pending.is_empty());
+    }
+
+    #[test]
+    fn test_forwarder_releases_port_when_dropped() {
+        let resolver = local_socket();
+        let listen_address = local_socket().local_addr().unwrap();
+
+        let forwarder =
+            DnsForwarder::start(listen_address, vec![resolver.local_addr().unwrap()]).unwrap();
+        assert!(UdpSocket::bind(listen_address).is_err());
+        // Wait until the forwarder is relaying queries
+        local_socket().send_to(&query(1), listen_address).unwrap();
+        receive_message(&resolver);
+
+        drop(forwarder);
+        UdpSocket::bind(listen_address).unwrap();
+    }
+}
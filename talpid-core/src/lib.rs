@@ -20,6 +20,9 @@ pub mod routing;
 
 mod offline;
 
+/// Subtraction of IP networks from one another.
+mod ipnetwork_sub;
+
 /// Working with processes.
 pub mod process;
 
@@ -42,8 +45,8 @@ pub mod dns;
 pub mod tunnel_state_machine;
 
 #[cfg(not(target_os = "android"))]
-/// Internal code for managing bundled proxy software.
-mod proxy;
+/// Proxies run by the daemon, for tunnels and for reaching the API.
+pub mod proxy;
 
 #[cfg(not(target_os = "android"))]
 mod mktemp;
@@ -52,5 +55,13 @@ mod mktemp;
 #[cfg(target_os = "linux")]
 mod linux;
 
+/// Management of named network namespaces that tunnels can be moved into.
+#[cfg(target_os = "linux")]
+mod netns;
+
+/// Forwarding of traffic from a local network through the tunnel.
+#[cfg(target_os = "linux")]
+mod gateway;
+
 /// A pair of functions to monitor and establish connectivity with ICMP
 mod ping_monitor;
@@ -0,0 +1,293 @@
+use nix::sched::{setns, CloneFlags};
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+};
+
+/// Directory where `ip netns` keeps the bind mounts of named network namespaces.
+const NETNS_RUN_DIR: &str = "/var/run/netns";
+/// Directory where `ip netns exec` looks for namespace specific configuration files.
+const NETNS_ETC_DIR: &str = "/etc/netns";
+/// First line of the files written by `write_with_backup`, to tell them apart from files that
+/// should be backed up.
+const GENERATED_HEADER: &str = "# Generated by Mullvad VPN\n";
+
+/// Errors that can happen when working with network namespaces.
+#[derive(Debug, err_derive::Error)]
+#[error(no_from)]
+pub enum Error {
+    /// The name can't be used as the name of a network namespace.
+    #[error(display = "Invalid network namespace name: {}", _0)]
+    InvalidName(String),
+
+    /// Failed to run `ip`.
+    #[error(display = "Failed to run ip to manage network namespace {}", _0)]
+    RunIpError(String, #[error(source)] io::Error),
+
+    /// `ip` exited with an error.
+    #[error(display = "Failed to manage network namespace: {}", _0)]
+    IpError(String),
+
+    /// Failed to open the network namespace.
+    #[error(display = "Failed to open network namespace {}", _0)]
+    OpenNamespace(String, #[error(source)] io::Error),
+
+    /// Failed to switch the current thread into the network namespace.
+    #[error(display = "Failed to enter network namespace {}", _0)]
+    EnterNamespace(String, #[error(source)] nix::Error),
+}
+
+/// A named network namespace, as managed by `ip netns`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct NetworkNamespace {
+    name: String,
+}
+
+impl NetworkNamespace {
+    /// Opens the named network namespace, creating it if it doesn't exist yet.
+    pub fn create_or_open(name: &str) -> Result<Self, Error> {
+        let namespace = Self::new(name)?;
+        if !namespace.path().exists() {
+            log::debug!("Creating network namespace {}", name);
+            namespace.run_ip(&["netns", "add", name])?;
+        }
+        namespace.ip(&["link", "set", "dev", "lo", "up"])?;
+        Ok(namespace)
+    }
+
+    /// Refers to the named network namespace without creating it. The name is passed to `ip` and
+    /// used as a file name, so it may only contain ASCII letters, digits, `_`, `.` and `-`, and
+    /// may not start with `-`.
+    pub fn new(name: &str) -> Result<Self, Error> {
+        let valid_characters = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-');
+        if !valid_characters
+            || name.is_empty()
+            || name.starts_with('-')
+            || name == "."
+            || name == ".."
+        {
+            return Err(Error::InvalidName(name.to_owned()));
+        }
+        Ok(NetworkNamespace {
+            name: name.to_owned(),
+        })
+    }
+
+    /// Returns the name of the namespace.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the path to the resolv.conf used by processes started with `ip netns exec`.
+    pub fn resolv_conf_path(&self) -> PathBuf {
+        Path::new(NETNS_ETC_DIR)
+            .join(&self.name)
+            .join("resolv.conf")
+    }
+
+    /// Moves a network interface from the current namespace into this one. The interface loses
+    /// its addresses and is brought down when moved.
+    pub fn move_link(&self, interface: &str) -> Result<(), Error> {
+        self.run_ip(&["link", "set", "dev", interface, "netns", &self.name])
+    }
+
+    /// Runs `ip` with the given arguments inside the namespace.
+    pub fn ip(&self, args: &[&str]) -> Result<(), Error> {
+        let mut ns_args = vec!["-n", &self.name];
+        ns_args.extend_from_slice(args);
+        self.run_ip(&ns_args)
+    }
+
+    /// Returns a command that runs `program` inside the namespace.
+    pub fn exec_cmd(&self, program: &str, args: &[String]) -> duct::Expression {
+        let mut ns_args = vec![
+            "netns".to_owned(),
+            "exec".to_owned(),
+            self.name.clone(),
+            program.to_owned(),
+        ];
+        ns_args.extend_from_slice(args);
+        duct::cmd("ip", ns_args)
+    }
+
+    /// Runs `f` on the current thread after switching it into the namespace. The thread is
+    /// switched back to its original namespace before returning, even if `f` panics.
+    pub fn enter<T>(&self, f: impl FnOnce() -> T) -> Result<T, Error> {
+        let original = File::open("/proc/thread-self/ns/net")
+            .map_err(|error| Error::OpenNamespace("of the current thread".to_owned(), error))?;
+        let namespace = File::open(self.path())
+            .map_err(|error| Error::OpenNamespace(self.name.clone(), error))?;
+
+        setns(namespace.as_raw_fd(), CloneFlags::CLONE_NEWNET)
+            .map_err(|error| Error::EnterNamespace(self.name.clone(), error))?;
+        let _guard = RestoreNamespaceGuard { original };
+        Ok(f())
+    }
+
+    /// Writes a resolv.conf that is only used inside the namespace. A resolv.conf that was already
+    /// set up for the namespace is moved aside and restored by `remove_resolv_conf`.
+    pub fn write_resolv_conf(&self, contents: &str) -> io::Result<()> {
+        write_with_backup(&self.resolv_conf_path(), contents)
+    }
+
+    /// Removes the resolv.conf written by `write_resolv_conf` and restores the one it replaced.
+    /// If there was none, the directory is removed as well if it's empty.
+    pub fn remove_resolv_conf(&self) -> io::Result<()> {
+        let path = self.resolv_conf_path();
+        if restore_backup(&path)? {
+            return Ok(());
+        }
+        if let Some(dir) = path.parent() {
+            let _ = fs::remove_dir(dir);
+        }
+        Ok(())
+    }
+
+    fn path(&self) -> PathBuf {
+        Path::new(NETNS_RUN_DIR).join(&self.name)
+    }
+
+    fn run_ip(&self, args: &[&str]) -> Result<(), Error> {
+        log::trace!("Running ip {}", args.join(" "));
+
+        let output = duct::cmd("ip", args)
+            .stdout_null()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .map_err(|error| Error::RunIpError(self.name.clone(), error))?;
+        if !output.status.success() {
+            return Err(Error::IpError(format!(
+                "ip {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".mullvadbackup");
+    PathBuf::from(backup_path)
+}
+
+/// Writes `contents` to `path`, after moving any existing file at `path` to a backup. A file that
+/// was written by an earlier call, for example before a crash, is overwritten instead.
+fn write_with_backup(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    match fs::read_to_string(path) {
+        Ok(existing) if existing.starts_with(GENERATED_HEADER) => (),
+        Ok(_) => {
+            log::debug!("Backing up {}", path.display());
+            fs::rename(path, backup_path(path))?;
+        }
+        Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+        Err(error) => return Err(error),
+    }
+    fs::write(path, format!("{}{}", GENERATED_HEADER, contents))
+}
+
+/// Removes the file at `path` and moves its backup back in place, if there is one. Returns whether
+/// a backup was restored.
+fn restore_backup(path: &Path) -> io::Result<bool> {
+    let backup_path = backup_path(path);
+    if backup_path.exists() {
+        log::debug!("Restoring {} from backup", path.display());
+        fs::rename(&backup_path, path)?;
+        return Ok(true);
+    }
+    match fs::remove_file(path) {
+        Ok(()) => Ok(false),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Switches the current thread back into its original network namespace when dropped.
+struct RestoreNamespaceGuard {
+    original: File,
+}
+
+impl Drop for RestoreNamespaceGuard {
+    fn drop(&mut self) {
+        if let Err(error) = setns(self.original.as_raw_fd(), CloneFlags::CLONE_NEWNET) {
+            // Anything else that runs on this thread would end up in the wrong namespace, where
+            // it could send traffic through the tunnel or around the firewall.
+            log::error!(
+                "Failed to return to the original network namespace: {}",
+                error
+            );
+            std::process::abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{backup_path, restore_backup, write_with_backup, NetworkNamespace};
+    use std::fs;
+
+    #[test]
+    fn test_valid_names() {
+        for name in &["mullvad", "ns-1", "ns_1.test", "a..b", "NS2"] {
+            assert!(NetworkNamespace::new(name).is_ok(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        for name in &[
+            "", ".", "..", "-n", "--all", "a/b", "a b", "a\0b", "ns\n", "nå",
+        ] {
+            assert!(NetworkNamespace::new(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_resolv_conf_without_existing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("mullvad").join("resolv.conf");
+
+        write_with_backup(&path, "nameserver 10.64.0.1\n").unwrap();
+        write_with_backup(&path, "nameserver 10.64.0.2\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Generated by Mullvad VPN\nnameserver 10.64.0.2\n"
+        );
+        assert!(!backup_path(&path).exists());
+
+        assert!(!restore_backup(&path).unwrap());
+        assert!(!path.exists());
+        assert!(!restore_backup(&path).unwrap());
+    }
+
+    #[test]
+    fn test_existing_resolv_conf_is_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("resolv.conf");
+        fs::write(&path, "nameserver 192.168.1.1\n").unwrap();
+
+        write_with_backup(&path, "nameserver 10.64.0.1\n").unwrap();
+        // Writing again, or after a crash, must not replace the backup with our own file
+        write_with_backup(&path, "nameserver 10.64.0.2\n").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Generated by Mullvad VPN\nnameserver 10.64.0.2\n"
+        );
+
+        assert!(restore_backup(&path).unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "nameserver 192.168.1.1\n"
+        );
+        assert!(!backup_path(&path).exists());
+    }
+}
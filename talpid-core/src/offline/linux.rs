@@ -12,6 +12,7 @@ use rtnetlink::{
     Handle,
 };
 use std::{collections::BTreeSet, io, sync::Weak};
+use talpid_types::diagnostics::NetworkLink;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -63,6 +64,10 @@ impl MonitorHandle {
             }
         }
     }
+
+    pub fn links(&mut self) -> Result<Vec<NetworkLink>> {
+        self.runtime.block_on(list_links(&self.handle))
+    }
 }
 
 pub fn spawn_monitor(sender: Weak<UnboundedSender<TunnelCommand>>) -> Result<MonitorHandle> {
@@ -151,6 +156,46 @@ async fn check_offline_state(handle: &Handle) -> Result<bool> {
     Ok(true)
 }
 
+async fn list_links(handle: &Handle) -> Result<Vec<NetworkLink>> {
+    let mut link_request = handle.link().get().execute();
+    let mut links = Vec::new();
+    while let Some(link) = link_request
+        .try_next()
+        .await
+        .map_err(failure::Fail::compat)
+        .map_err(Error::GetLinksError)?
+    {
+        links.push(NetworkLink {
+            index: link.header.index,
+            name: link_name(&link).unwrap_or_default(),
+            provides_connectivity: link_provides_connectivity(&link),
+            has_addresses: false,
+        });
+    }
+
+    let mut address_request = handle.address().get().execute();
+    while let Some(address) = address_request
+        .try_next()
+        .await
+        .map_err(failure::Fail::compat)
+        .map_err(Error::GetAddressesError)?
+    {
+        if let Some(link) = links
+            .iter_mut()
+            .find(|link| link.index == address.header.index)
+        {
+            link.has_addresses = true;
+        }
+    }
+    Ok(links)
+}
+
+fn link_name(link: &LinkMessage) -> Option<String> {
+    link.nlas.iter().find_map(|nla| match nla {
+        LinkNla::IfName(name) => Some(name.clone()),
+        _ => None,
+    })
+}
 
 // TODO: Improve by allowing bridge links to provide connectivity, will require route checking.
 fn link_provides_connectivity(link: &LinkMessage) -> bool {
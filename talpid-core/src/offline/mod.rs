@@ -3,6 +3,7 @@ use futures01::sync::mpsc::UnboundedSender;
 use std::sync::Weak;
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
+use talpid_types::diagnostics::NetworkLink;
 
 #[cfg(target_os = "macos")]
 #[path = "macos.rs"]
@@ -28,6 +29,19 @@ impl MonitorHandle {
     pub fn is_offline(&mut self) -> bool {
         self.0.is_offline()
     }
+
+    /// Returns the network links that the monitor bases its decision on, if the platform
+    /// implementation exposes them.
+    pub fn links(&mut self) -> Result<Option<Vec<NetworkLink>>, Error> {
+        #[cfg(target_os = "linux")]
+        {
+            self.0.links().map(Some)
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            Ok(None)
+        }
+    }
 }
 
 pub fn spawn_monitor(
@@ -1,3 +1,5 @@
+#[cfg(target_os = "linux")]
+use crate::netns::NetworkNamespace;
 use std::{io, net::Ipv4Addr};
 
 #[derive(err_derive::Error, Debug)]
@@ -14,6 +16,8 @@ pub struct Pinger {
     addr: Ipv4Addr,
     interface_name: String,
     processes: Vec<duct::Handle>,
+    #[cfg(target_os = "linux")]
+    network_namespace: Option<NetworkNamespace>,
 }
 
 impl Pinger {
@@ -22,15 +26,36 @@ impl Pinger {
             processes: vec![],
             addr,
             interface_name,
+            #[cfg(target_os = "linux")]
+            network_namespace: None,
         })
     }
 
+    /// Sends the pings from within the given network namespace, where the interface lives.
+    #[cfg(target_os = "linux")]
+    pub fn set_network_namespace(&mut self, network_namespace: Option<NetworkNamespace>) {
+        self.network_namespace = network_namespace;
+    }
+
     // Send an ICMP packet without waiting for a reply
     pub fn send_icmp(&mut self) -> Result<(), Error> {
         self.try_deplete_process_list();
 
-        let cmd = ping_cmd(self.addr, 1, &self.interface_name);
-        let handle = cmd.start().map_err(Error::PingError)?;
+        let args = ping_args(self.addr, 1, &self.interface_name);
+        #[cfg(target_os = "linux")]
+        let cmd = match self.network_namespace {
+            Some(ref namespace) => namespace.exec_cmd("ping", &args),
+            None => duct::cmd("ping", args),
+        };
+        #[cfg(not(target_os = "linux"))]
+        let cmd = duct::cmd("ping", args);
+
+        let handle = cmd
+            .stdin_null()
+            .stdout_null()
+            .unchecked()
+            .start()
+            .map_err(Error::PingError)?;
         self.processes.push(handle);
         Ok(())
     }
@@ -57,7 +82,7 @@ impl Drop for Pinger {
 }
 
 
-fn ping_cmd(ip: Ipv4Addr, timeout_secs: u16, interface: &str) -> duct::Expression {
+fn ping_args(ip: Ipv4Addr, timeout_secs: u16, interface: &str) -> Vec<String> {
     let mut args = vec!["-n", "-i", "1"];
 
     let timeout_flag = if cfg!(target_os = "linux") || cfg!(target_os = "android") {
@@ -84,8 +109,5 @@ fn ping_cmd(ip: Ipv4Addr, timeout_secs: u16, interface: &str) -> duct::Expressio
     let ip = ip.to_string();
     args.push(&ip);
 
-    duct::cmd("ping", args)
-        .stdin_null()
-        .stdout_null()
-        .unchecked()
+    args.into_iter().map(String::from).collect()
 }
@@ -10,7 +10,10 @@ use std::{
     fmt, io,
     path::{Path, PathBuf},
 };
-use talpid_types::net;
+use talpid_types::net::{
+    self,
+    openvpn::{ExtraOption, ExtraOptionError, DEFAULT_PING, DEFAULT_PING_EXIT},
+};
 
 static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--client"],
@@ -20,8 +23,6 @@ static BASE_ARGUMENTS: &[&[&str]] = &[
     &["--dev", "tun"],
     #[cfg(windows)]
     &["--dev-type", "tun"],
-    &["--ping", "4"],
-    &["--ping-exit", "25"],
     &["--connect-timeout", "30"],
     &["--connect-retry", "0", "0"],
     &["--connect-retry-max", "1"],
@@ -231,6 +232,9 @@ impl OpenVpnCommand {
             args.push(OsString::from(mssfix.to_string()));
         }
 
+        args.extend(self.ping_arguments().iter().map(OsString::from));
+        args.extend(self.extra_option_arguments().iter().map(OsString::from));
+
         if !self.enable_ipv6 {
             args.push(OsString::from("--pull-filter"));
             args.push(OsString::from("ignore"));
@@ -271,6 +275,64 @@ impl OpenVpnCommand {
         args
     }
 
+    /// Returns the `--ping` and `--ping-exit` arguments. Both fall back to their defaults if the
+    /// extra options would make the tunnel time out before a ping is sent.
+    fn ping_arguments(&self) -> Vec<String> {
+        let options = &self.tunnel_options.extra_options;
+        let valid_value = |option: ExtraOption, default: u32| {
+            options
+                .get(&option)
+                .copied()
+                .filter(|value| option.validate(*value).is_ok())
+                .unwrap_or(default)
+        };
+        let mut ping = valid_value(ExtraOption::Ping, DEFAULT_PING);
+        let mut ping_exit = valid_value(ExtraOption::PingExit, DEFAULT_PING_EXIT);
+        if ping >= ping_exit {
+            log::error!(
+                "Ignoring extra OpenVPN ping options: {}",
+                ExtraOptionError::PingNotBelowPingExit { ping, ping_exit }
+            );
+            ping = DEFAULT_PING;
+            ping_exit = DEFAULT_PING_EXIT;
+        }
+        vec![
+            "--ping".to_owned(),
+            ping.to_string(),
+            "--ping-exit".to_owned(),
+            ping_exit.to_string(),
+        ]
+    }
+
+    /// Returns the extra options in the tunnel options, except for those passed by
+    /// `ping_arguments`. Options with values that are not allowed are left out, in case the
+    /// settings were modified by hand, and so is `--fragment` for tunnels over TCP, which OpenVPN
+    /// refuses to start with.
+    fn extra_option_arguments(&self) -> Vec<String> {
+        let is_tcp = self
+            .remote
+            .map(|endpoint| endpoint.protocol == net::TransportProtocol::Tcp)
+            .unwrap_or(false);
+        let mut args = vec![];
+        for (option, value) in &self.tunnel_options.extra_options {
+            if let Err(error) = option.validate(*value) {
+                log::error!("Ignoring extra OpenVPN option: {}", error);
+                continue;
+            }
+            match option {
+                ExtraOption::Ping | ExtraOption::PingExit => (),
+                ExtraOption::Fragment if is_tcp => {
+                    log::warn!("Ignoring {}, since the tunnel runs over TCP", option)
+                }
+                _ => {
+                    args.push(option.to_string());
+                    args.push(value.to_string());
+                }
+            }
+        }
+        args
+    }
+
     fn remote_arguments(&self) -> Vec<String> {
         let mut args: Vec<String> = vec![];
         if let Some(ref endpoint) = self.remote {
@@ -420,7 +482,7 @@ impl StoppableProcess for OpenVpnProcHandle {
 mod tests {
     use super::OpenVpnCommand;
     use std::{ffi::OsString, net::Ipv4Addr};
-    use talpid_types::net::{Endpoint, TransportProtocol};
+    use talpid_types::net::{openvpn, Endpoint, TransportProtocol};
 
     #[test]
     fn passes_one_remote() {
@@ -447,4 +509,75 @@ mod tests {
         assert!(testee_args.contains(&OsString::from("123")));
         assert!(testee_args.contains(&OsString::from("cde")));
     }
+
+    #[test]
+    fn passes_extra_options_last() {
+        let mut tunnel_options = openvpn::TunnelOptions::default();
+        tunnel_options
+            .extra_options
+            .insert(openvpn::ExtraOption::Ping, 10);
+        let testee_args = OpenVpnCommand::new("")
+            .tunnel_options(&tunnel_options)
+            .get_arguments();
+
+        let last_ping = testee_args
+            .iter()
+            .rposition(|arg| arg == "--ping")
+            .expect("Missing --ping argument");
+        assert_eq!(testee_args[last_ping + 1], OsString::from("10"));
+    }
+
+    #[test]
+    fn skips_invalid_extra_options() {
+        let mut tunnel_options = openvpn::TunnelOptions::default();
+        tunnel_options
+            .extra_options
+            .insert(openvpn::ExtraOption::TunMtu, 100);
+        let testee_args = OpenVpnCommand::new("")
+            .tunnel_options(&tunnel_options)
+            .get_arguments();
+        assert!(!testee_args.contains(&OsString::from("--tun-mtu")));
+    }
+
+    #[test]
+    fn skips_fragment_over_tcp() {
+        let mut tunnel_options = openvpn::TunnelOptions::default();
+        tunnel_options
+            .extra_options
+            .insert(openvpn::ExtraOption::Fragment, 1300);
+        for (protocol, expect_fragment) in &[
+            (TransportProtocol::Udp, true),
+            (TransportProtocol::Tcp, false),
+        ] {
+            let remote = Endpoint::new(Ipv4Addr::new(127, 0, 0, 1), 3333, *protocol);
+            let testee_args = OpenVpnCommand::new("")
+                .remote(remote)
+                .tunnel_options(&tunnel_options)
+                .get_arguments();
+            assert_eq!(
+                testee_args.contains(&OsString::from("--fragment")),
+                *expect_fragment
+            );
+        }
+    }
+
+    #[test]
+    fn skips_ping_not_below_ping_exit() {
+        let mut tunnel_options = openvpn::TunnelOptions::default();
+        tunnel_options
+            .extra_options
+            .insert(openvpn::ExtraOption::Ping, 30);
+        let testee_args = OpenVpnCommand::new("")
+            .tunnel_options(&tunnel_options)
+            .get_arguments();
+
+        let ping = testee_args.iter().position(|arg| arg == "--ping").unwrap();
+        assert_eq!(testee_args[ping + 1], OsString::from("4"));
+        let ping_exit = testee_args
+            .iter()
+            .position(|arg| arg == "--ping-exit")
+            .unwrap();
+        assert_eq!(testee_args[ping_exit + 1], OsString::from("25"));
+        assert_eq!(testee_args.iter().filter(|arg| *arg == "--ping").count(), 1);
+    }
 }
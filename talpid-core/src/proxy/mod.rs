@@ -1,16 +1,30 @@
 mod shadowsocks;
+mod socks;
+mod tunnel_socks;
+mod udp2tcp;
 
 pub use std::io::Result;
 
-use self::shadowsocks::ShadowsocksProxyMonitor;
-use std::{fmt, path::PathBuf, sync::mpsc};
-use talpid_types::net::openvpn;
-
+use self::{
+    shadowsocks::ShadowsocksProxyMonitor, tunnel_socks::TunnelSocksProxyMonitor,
+    udp2tcp::Udp2TcpProxyMonitor,
+};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::mpsc,
+};
+use talpid_types::net::{openvpn, proxy::TunnelSocksProxy};
+
+/// How a proxy stopped running.
 pub enum WaitResult {
+    /// The proxy stopped without being asked to.
     UnexpectedExit(String),
+    /// The proxy was shut down through its close handle.
     ProperShutdown,
 }
 
+/// Handle to a running proxy.
 pub trait ProxyMonitor: Send {
     /// Create a handle than can be used to ask the proxy service to shut down.
     fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle>;
@@ -28,7 +42,9 @@ impl fmt::Debug for dyn ProxyMonitor {
     }
 }
 
+/// Handle used to shut down a running proxy.
 pub trait ProxyMonitorCloseHandle: Send {
+    /// Ask the proxy to shut down.
     fn close(self: Box<Self>) -> Result<()>;
 }
 
@@ -73,18 +89,9 @@ impl ProxyMonitorCloseHandle for NoopProxyMonitorCloseHandle {
     }
 }
 
-/// Variables that define the environment to help
-/// proxy implementations find their way around.
-/// TODO: Move struct to wider scope and use more generic name.
-pub struct ProxyResourceData {
-    pub resource_dir: PathBuf,
-    pub log_dir: Option<PathBuf>,
-}
-
-pub fn start_proxy(
-    settings: &openvpn::ProxySettings,
-    resource_data: &ProxyResourceData,
-) -> Result<Box<dyn ProxyMonitor>> {
+/// Starts the proxy client described by `settings`, if it's one that is managed by us. The proxy
+/// listens for SOCKS5 connections on the returned port.
+pub fn start_proxy(settings: &openvpn::ProxySettings) -> Result<Box<dyn ProxyMonitor>> {
     match settings {
         openvpn::ProxySettings::Local(local_settings) => {
             // These are generic proxy settings with the proxy client not managed by us.
@@ -96,8 +103,26 @@ pub fn start_proxy(
                 remote_settings.address.port(),
             )?))
         }
-        openvpn::ProxySettings::Shadowsocks(ss_settings) => Ok(Box::new(
-            ShadowsocksProxyMonitor::start(ss_settings, resource_data)?,
-        )),
+        openvpn::ProxySettings::Shadowsocks(ss_settings) => {
+            Ok(Box::new(ShadowsocksProxyMonitor::start(ss_settings)?))
+        }
     }
 }
+
+/// Starts a proxy that forwards UDP datagrams sent to the returned port over a TCP stream to
+/// `peer`. Used to obfuscate WireGuard traffic.
+pub fn start_udp2tcp_proxy(peer: SocketAddr) -> Result<Box<dyn ProxyMonitor>> {
+    Ok(Box::new(Udp2TcpProxyMonitor::start(peer)?))
+}
+
+/// Starts a SOCKS5 server on localhost whose outgoing connections are made through the tunnel
+/// `interface`, with the address `tunnel_ips`. The proxy is closed when the monitor is dropped.
+pub fn start_tunnel_socks_proxy(
+    settings: &TunnelSocksProxy,
+    interface: &str,
+    tunnel_ips: &[IpAddr],
+) -> Result<Box<dyn ProxyMonitor>> {
+    Ok(Box::new(TunnelSocksProxyMonitor::start(
+        settings, interface, tunnel_ips,
+    )?))
+}
@@ -0,0 +1,340 @@
+//! Shadowsocks AEAD ciphers and the framing of encrypted TCP streams. A stream starts with a
+//! random salt, used to derive a session key, followed by chunks that each consist of the
+//! encrypted payload length and the encrypted payload.
+
+use ring::{
+    aead::{self, Aad, LessSafeKey, Nonce, UnboundKey, NONCE_LEN},
+    hkdf,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::io::{self, Read, Write};
+
+/// Largest payload that fits in a single chunk.
+pub const MAX_PAYLOAD_SIZE: usize = 0x3FFF;
+const LENGTH_SIZE: usize = 2;
+const SUBKEY_INFO: &[u8] = b"ss-subkey";
+
+
+/// The ciphers that are supported by the Shadowsocks client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherKind {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20IetfPoly1305,
+}
+
+impl CipherKind {
+    /// Returns the cipher corresponding to a name in
+    /// [`talpid_types::net::openvpn::SHADOWSOCKS_CIPHERS`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "aes-128-gcm" => Some(CipherKind::Aes128Gcm),
+            "aes-256-gcm" => Some(CipherKind::Aes256Gcm),
+            "chacha20-ietf-poly1305" => Some(CipherKind::ChaCha20IetfPoly1305),
+            _ => None,
+        }
+    }
+
+    fn algorithm(self) -> &'static aead::Algorithm {
+        match self {
+            CipherKind::Aes128Gcm => &aead::AES_128_GCM,
+            CipherKind::Aes256Gcm => &aead::AES_256_GCM,
+            CipherKind::ChaCha20IetfPoly1305 => &aead::CHACHA20_POLY1305,
+        }
+    }
+
+    /// Length of the keys, which is also the length of the salt.
+    pub fn key_len(self) -> usize {
+        self.algorithm().key_len()
+    }
+
+    fn tag_len(self) -> usize {
+        self.algorithm().tag_len()
+    }
+}
+
+/// Derives the master key from a password, like OpenSSL's `EVP_BytesToKey` with MD5 and no salt.
+pub fn derive_key(password: &[u8], key_len: usize) -> Vec<u8> {
+    use md5::{Digest, Md5};
+
+    let mut key = Vec::with_capacity(key_len + 16);
+    let mut previous_digest: Vec<u8> = Vec::new();
+    while key.len() < key_len {
+        let mut hasher = Md5::new();
+        hasher.input(&previous_digest);
+        hasher.input(password);
+        previous_digest = hasher.result().to_vec();
+        key.extend_from_slice(&previous_digest);
+    }
+    key.truncate(key_len);
+    key
+}
+
+struct KeyLen(usize);
+
+impl hkdf::KeyType for KeyLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+/// Encrypts or decrypts consecutive chunks of a single stream using the session key.
+struct SessionCipher {
+    key: LessSafeKey,
+    nonce: [u8; NONCE_LEN],
+}
+
+impl SessionCipher {
+    fn new(kind: CipherKind, master_key: &[u8], salt: &[u8]) -> Self {
+        let mut subkey = vec![0u8; kind.key_len()];
+        hkdf::Salt::new(hkdf::HKDF_SHA1_FOR_LEGACY_USE_ONLY, salt)
+            .extract(master_key)
+            .expand(&[SUBKEY_INFO], KeyLen(subkey.len()))
+            .and_then(|okm| okm.fill(&mut subkey))
+            .expect("Subkey length is valid for HKDF-SHA1");
+        let key = UnboundKey::new(kind.algorithm(), &subkey).expect("Subkey has correct length");
+
+        SessionCipher {
+            key: LessSafeKey::new(key),
+            nonce: [0u8; NONCE_LEN],
+        }
+    }
+
+    /// Returns the current nonce and increments it as a little endian integer.
+    fn next_nonce(&mut self) -> Nonce {
+        let nonce = Nonce::assume_unique_for_key(self.nonce);
+        for byte in self.nonce.iter_mut() {
+            *byte = byte.wrapping_add(1);
+            if *byte != 0 {
+                break;
+            }
+        }
+        nonce
+    }
+
+    fn seal(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let mut in_out = data.to_vec();
+        let nonce = self.next_nonce();
+        self.key
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
+            .expect("Chunk is too large to encrypt");
+        out.extend_from_slice(&in_out);
+    }
+
+    fn open<'a>(&mut self, in_out: &'a mut [u8]) -> io::Result<&'a mut [u8]> {
+        let nonce = self.next_nonce();
+        self.key
+            .open_in_place(nonce, Aad::empty(), in_out)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt chunk"))
+    }
+}
+
+/// Writer that encrypts everything written to it before passing it on to `inner`.
+pub struct EncryptedWriter<W: Write> {
+    inner: W,
+    cipher: SessionCipher,
+    /// Salt that has not been sent yet. It is sent along with the first chunk.
+    pending_salt: Option<Vec<u8>>,
+    tag_len: usize,
+}
+
+impl<W: Write> EncryptedWriter<W> {
+    /// Creates a writer using a randomly generated salt.
+    pub fn new(inner: W, kind: CipherKind, master_key: &[u8]) -> io::Result<Self> {
+        let mut salt = vec![0u8; kind.key_len()];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "Failed to generate salt"))?;
+        Ok(Self::with_salt(inner, kind, master_key, salt))
+    }
+
+    fn with_salt(inner: W, kind: CipherKind, master_key: &[u8], salt: Vec<u8>) -> Self {
+        EncryptedWriter {
+            inner,
+            cipher: SessionCipher::new(kind, master_key, &salt),
+            pending_salt: Some(salt),
+            tag_len: kind.tag_len(),
+        }
+    }
+}
+
+impl<W: Write> Write for EncryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let payload = &buf[..buf.len().min(MAX_PAYLOAD_SIZE)];
+
+        let mut chunk = Vec::with_capacity(LENGTH_SIZE + payload.len() + 2 * self.tag_len);
+        if let Some(salt) = self.pending_salt.take() {
+            chunk.extend_from_slice(&salt);
+        }
+        self.cipher
+            .seal(&(payload.len() as u16).to_be_bytes(), &mut chunk);
+        self.cipher.seal(payload, &mut chunk);
+
+        self.inner.write_all(&chunk)?;
+        Ok(payload.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that decrypts everything read from `inner`.
+pub struct DecryptedReader<R: Read> {
+    inner: R,
+    kind: CipherKind,
+    master_key: Vec<u8>,
+    /// Created once the salt has been read from the stream.
+    cipher: Option<SessionCipher>,
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> DecryptedReader<R> {
+    pub fn new(inner: R, kind: CipherKind, master_key: &[u8]) -> Self {
+        DecryptedReader {
+            inner,
+            kind,
+            master_key: master_key.to_vec(),
+            cipher: None,
+            buffer: Vec::new(),
+            position: 0,
+        }
+    }
+
+    /// Reads and decrypts the next chunk into the buffer. Returns false if the stream ended
+    /// cleanly before the chunk.
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        let tag_len = self.kind.tag_len();
+
+        let cipher = match self.cipher {
+            Some(ref mut cipher) => cipher,
+            None => {
+                let mut salt = vec![0u8; self.kind.key_len()];
+                if !read_exact_or_eof(&mut self.inner, &mut salt)? {
+                    return Ok(false);
+                }
+                self.cipher
+                    .get_or_insert(SessionCipher::new(self.kind, &self.master_key, &salt))
+            }
+        };
+
+        let mut length = vec![0u8; LENGTH_SIZE + tag_len];
+        if !read_exact_or_eof(&mut self.inner, &mut length)? {
+            return Ok(false);
+        }
+        let length = cipher.open(&mut length)?;
+        let payload_len = usize::from(u16::from_be_bytes([length[0], length[1]]));
+        if payload_len > MAX_PAYLOAD_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Chunk exceeds maximum payload size",
+            ));
+        }
+
+        self.buffer.resize(payload_len + tag_len, 0);
+        self.inner.read_exact(&mut self.buffer)?;
+        cipher.open(&mut self.buffer)?;
+        self.buffer.truncate(payload_len);
+        self.position = 0;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DecryptedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position >= self.buffer.len() {
+            if !self.read_chunk()? {
+                return Ok(0);
+            }
+        }
+        let size = buf.len().min(self.buffer.len() - self.position);
+        buf[..size].copy_from_slice(&self.buffer[self.position..self.position + size]);
+        self.position += size;
+        Ok(size)
+    }
+}
+
+/// Like `Read::read_exact`, but returns false instead of failing if the stream ends before
+/// anything has been read.
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(size) => read += size,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+            Err(error) => return Err(error),
+        }
+    }
+    Ok(true)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello" encrypted using aes-128-gcm, the password "password" and an all-zero salt.
+    const KNOWN_CIPHERTEXT: &str = "00000000000000000000000000000000\
+                                    cec4816a581f7e487fe825ae7ed6bd47f734\
+                                    61f44c2747d63f5417a03f1d0acba9cd38d12241ef";
+
+    #[test]
+    fn test_derive_key() {
+        assert_eq!(
+            hex::encode(derive_key(b"password", 16)),
+            "5f4dcc3b5aa765d61d8327deb882cf99"
+        );
+        assert_eq!(
+            hex::encode(derive_key(b"password", 32)),
+            "5f4dcc3b5aa765d61d8327deb882cf992b95990a9151374abd8ff8c5a7a0fe08"
+        );
+    }
+
+    #[test]
+    fn test_known_ciphertext() {
+        let master_key = derive_key(b"password", 16);
+        let mut ciphertext = Vec::new();
+        EncryptedWriter::with_salt(
+            &mut ciphertext,
+            CipherKind::Aes128Gcm,
+            &master_key,
+            vec![0u8; 16],
+        )
+        .write_all(b"hello")
+        .unwrap();
+
+        assert_eq!(hex::encode(&ciphertext), KNOWN_CIPHERTEXT);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for &kind in &[
+            CipherKind::Aes128Gcm,
+            CipherKind::Aes256Gcm,
+            CipherKind::ChaCha20IetfPoly1305,
+        ] {
+            let master_key = derive_key(b"secret", kind.key_len());
+            let data: Vec<u8> = (0..3 * MAX_PAYLOAD_SIZE).map(|i| i as u8).collect();
+
+            let mut ciphertext = Vec::new();
+            EncryptedWriter::new(&mut ciphertext, kind, &master_key)
+                .unwrap()
+                .write_all(&data)
+                .unwrap();
+
+            let mut plaintext = Vec::new();
+            DecryptedReader::new(&ciphertext[..], kind, &master_key)
+                .read_to_end(&mut plaintext)
+                .unwrap();
+            assert_eq!(plaintext, data);
+
+            let wrong_key = derive_key(b"wrong", kind.key_len());
+            assert!(DecryptedReader::new(&ciphertext[..], kind, &wrong_key)
+                .read_to_end(&mut Vec::new())
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Shadowsocks client running inside the daemon. It exposes a local SOCKS5 server that OpenVPN
+//! connects through, and forwards each connection to the Shadowsocks server in encrypted form.
+
+pub use std::io::Result;
+
+use self::cipher::{CipherKind, DecryptedReader, EncryptedWriter, MAX_PAYLOAD_SIZE};
+use super::{socks, ProxyMonitor, ProxyMonitorCloseHandle, WaitResult};
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use talpid_types::{net::openvpn::ShadowsocksProxySettings, ErrorExt};
+
+mod cipher;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest number of connections that are proxied at the same time. Each one uses two threads.
+const MAX_CONNECTIONS: usize = 128;
+
+
+/// Open connections, so that they can be torn down when the proxy is closed.
+type Connections = Arc<Mutex<HashMap<u64, TcpStream>>>;
+
+pub struct ShadowsocksProxyMonitor {
+    local_addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+    connections: Connections,
+    exit_rx: mpsc::Receiver<io::Error>,
+}
+
+impl ShadowsocksProxyMonitor {
+    pub fn start(settings: &ShadowsocksProxySettings) -> Result<Self> {
+        let cipher = CipherKind::from_name(&settings.cipher).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported Shadowsocks cipher: {}", settings.cipher),
+            )
+        })?;
+        let master_key = Arc::new(cipher::derive_key(
+            settings.password.as_bytes(),
+            cipher.key_len(),
+        ));
+
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+        let local_addr = listener.local_addr()?;
+        log::debug!(
+            "Shadowsocks proxy to {} listening on {}",
+            settings.peer,
+            local_addr
+        );
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let (exit_tx, exit_rx) = mpsc::channel();
+
+        let peer = settings.peer;
+        let accept_closed = closed.clone();
+        let accept_connections = connections.clone();
+        thread::spawn(move || {
+            let error = Self::accept_connections(
+                listener,
+                peer,
+                cipher,
+                master_key,
+                &accept_closed,
+                accept_connections,
+            );
+            let _ = exit_tx.send(error);
+        });
+
+        Ok(ShadowsocksProxyMonitor {
+            local_addr,
+            closed,
+            connections,
+            exit_rx,
+        })
+    }
+
+    fn accept_connections(
+        listener: TcpListener,
+        peer: SocketAddr,
+        cipher: CipherKind,
+        master_key: Arc<Vec<u8>>,
+        closed: &AtomicBool,
+        connections: Connections,
+    ) -> io::Error {
+        let mut next_id = 0u64;
+        loop {
+            let client = match listener.accept() {
+                Ok((client, _)) => client,
+                Err(error) => return error,
+            };
+            if closed.load(Ordering::SeqCst) {
+                return io::Error::new(io::ErrorKind::Other, "Proxy was closed");
+            }
+
+            let id = next_id;
+            next_id += 1;
+            {
+                let mut connections = connections.lock().expect("Connections lock poisoned");
+                if connections.len() >= MAX_CONNECTIONS {
+                    log::debug!("Shadowsocks proxy refused a connection: too many connections");
+                    continue;
+                }
+                match client.try_clone() {
+                    Ok(client_clone) => {
+                        connections.insert(id, client_clone);
+                    }
+                    Err(error) => return error,
+                }
+            }
+
+            let master_key = master_key.clone();
+            let connections = connections.clone();
+            thread::spawn(move || {
+                if let Err(error) = Self::handle_client(client, peer, cipher, &master_key) {
+                    log::debug!(
+                        "{}",
+                        error.display_chain_with_msg("Shadowsocks connection failed")
+                    );
+                }
+                connections
+                    .lock()
+                    .expect("Connections lock poisoned")
+                    .remove(&id);
+            });
+        }
+    }
+
+    fn handle_client(
+        mut client: TcpStream,
+        peer: SocketAddr,
+        cipher: CipherKind,
+        master_key: &[u8],
+    ) -> io::Result<()> {
+        let destination =
+            socks::accept_connect_within(&mut client, None, socks::HANDSHAKE_TIMEOUT)?;
+        let server = match TcpStream::connect_timeout(&peer, CONNECT_TIMEOUT) {
+            Ok(server) => server,
+            Err(error) => {
+                let _ = socks::send_connect_result(&mut client, false);
+                return Err(error);
+            }
+        };
+        socks::send_connect_result(&mut client, true)?;
+        client.set_nodelay(true)?;
+        server.set_nodelay(true)?;
+
+        let mut writer = EncryptedWriter::new(server.try_clone()?, cipher, master_key)?;
+        writer.write_all(&destination)?;
+
+        let mut client_reader = client.try_clone()?;
+        let server_clone = server.try_clone()?;
+        let upload = thread::spawn(move || {
+            let result = copy_chunks(&mut client_reader, &mut writer);
+            let _ = server_clone.shutdown(Shutdown::Write);
+            result
+        });
+
+        let download_result = io::copy(
+            &mut DecryptedReader::new(server.try_clone()?, cipher, master_key),
+            &mut client,
+        );
+        let _ = client.shutdown(Shutdown::Write);
+        if download_result.is_err() {
+            // Make sure that the upload thread does not linger.
+            let _ = client.shutdown(Shutdown::Both);
+            let _ = server.shutdown(Shutdown::Both);
+        }
+
+        let upload_result = upload
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "Upload panicked")));
+        download_result.and(upload_result).map(|_| ())
+    }
+}
+
+/// Copies everything from `reader` to `writer`, passing on each read as a separate write so
+/// that data is sent as soon as it is available.
+fn copy_chunks(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+    let mut buffer = vec![0u8; MAX_PAYLOAD_SIZE];
+    loop {
+        let size = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(size) => size,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        writer.write_all(&buffer[..size])?;
+    }
+}
+
+impl ProxyMonitor for ShadowsocksProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(ShadowsocksProxyMonitorCloseHandle {
+            local_addr: self.local_addr,
+            closed: self.closed.clone(),
+            connections: self.connections.clone(),
+        })
+    }
+
+    fn wait(self: Box<Self>) -> Result<WaitResult> {
+        let result = self.exit_rx.recv();
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(WaitResult::ProperShutdown);
+        }
+        match result {
+            Ok(error) => Ok(WaitResult::UnexpectedExit(error.to_string())),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Shadowsocks proxy exited without a result",
+            )),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.local_addr.port()
+    }
+}
+
+pub struct ShadowsocksProxyMonitorCloseHandle {
+    local_addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+    connections: Connections,
+}
+
+impl ProxyMonitorCloseHandle for ShadowsocksProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> Result<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        for (_, connection) in self
+            .connections
+            .lock()
+            .expect("Connections lock poisoned")
+            .drain()
+        {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+        // Wake up the thread blocked on accepting connections, so it notices that it's closed.
+        TcpStream::connect(self.local_addr).map(|_| ())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stand-in for a Shadowsocks server. Accepts a single connection, checks the requested
+    /// destination and echoes everything back.
+    fn spawn_echo_server(
+        cipher: CipherKind,
+        password: &str,
+        expected_destination: Vec<u8>,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let master_key = cipher::derive_key(password.as_bytes(), cipher.key_len());
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = DecryptedReader::new(stream.try_clone().unwrap(), cipher, &master_key);
+            let mut writer = EncryptedWriter::new(stream, cipher, &master_key).unwrap();
+
+            let mut destination = vec![0u8; expected_destination.len()];
+            reader.read_exact(&mut destination).unwrap();
+            assert_eq!(destination, expected_destination);
+
+            copy_chunks(&mut reader, &mut writer).unwrap();
+        });
+
+        address
+    }
+
+    #[test]
+    fn test_connect_through_proxy() {
+        // IPv4 address 10.0.0.1, port 1194.
+        let destination = vec![1, 10, 0, 0, 1, 0x04, 0xaa];
+        let peer = spawn_echo_server(CipherKind::Aes256Gcm, "mullvad", destination.clone());
+        let mut proxy = ShadowsocksProxyMonitor::start(&ShadowsocksProxySettings {
+            peer,
+            password: "mullvad".to_string(),
+            cipher: "aes-256-gcm".to_string(),
+        })
+        .unwrap();
+
+        let mut client =
+            TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port())).unwrap();
+        client.write_all(&[5, 1, 0]).unwrap();
+        let mut method = [0u8; 2];
+        client.read_exact(&mut method).unwrap();
+        assert_eq!(method, [5, 0]);
+
+        client.write_all(&[5, 1, 0]).unwrap();
+        client.write_all(&destination).unwrap();
+        let mut reply = [0u8; 10];
+        client.read_exact(&mut reply).unwrap();
+        assert_eq!(reply[..2], [5, 0]);
+
+        client.write_all(b"hello").unwrap();
+        let mut response = [0u8; 5];
+        client.read_exact(&mut response).unwrap();
+        assert_eq!(&response, b"hello");
+
+        proxy.close_handle().close().unwrap();
+        assert!(match Box::new(proxy).wait().unwrap() {
+            WaitResult::ProperShutdown => true,
+            WaitResult::UnexpectedExit(_) => false,
+        });
+        assert_eq!(client.read(&mut response).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_connection_limit() {
+        let mut proxy = ShadowsocksProxyMonitor::start(&ShadowsocksProxySettings {
+            peer: "192.0.2.1:443".parse().unwrap(),
+            password: "mullvad".to_string(),
+            cipher: "aes-256-gcm".to_string(),
+        })
+        .unwrap();
+        let proxy_addr = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port());
+
+        // These connections are kept open while waiting for the SOCKS5 handshake.
+        let open_clients: Vec<TcpStream> = (0..MAX_CONNECTIONS)
+            .map(|_| TcpStream::connect(proxy_addr).unwrap())
+            .collect();
+
+        let mut refused_client = TcpStream::connect(proxy_addr).unwrap();
+        refused_client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut buffer = [0u8; 1];
+        assert_eq!(refused_client.read(&mut buffer).unwrap(), 0);
+
+        proxy.close_handle().close().unwrap();
+        drop(open_clients);
+    }
+
+    #[test]
+    fn test_unsupported_cipher() {
+        assert!(ShadowsocksProxyMonitor::start(&ShadowsocksProxySettings {
+            peer: "10.0.0.1:443".parse().unwrap(),
+            password: "mullvad".to_string(),
+            cipher: "rc4-md5".to_string(),
+        })
+        .is_err());
+    }
+}
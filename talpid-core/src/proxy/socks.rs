@@ -0,0 +1,324 @@
+//! The parts of a SOCKS5 server (RFC 1928) needed to accept `CONNECT` requests, with optional
+//! username/password authentication (RFC 1929).
+
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+use talpid_types::net::openvpn::ProxyAuth;
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const USERNAME_PASSWORD_AUTHENTICATION: u8 = 2;
+const USERNAME_PASSWORD_VERSION: u8 = 1;
+const AUTHENTICATION_SUCCEEDED: u8 = 0;
+const AUTHENTICATION_FAILED: u8 = 1;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const COMMAND_CONNECT: u8 = 1;
+const ADDRESS_TYPE_IPV4: u8 = 1;
+const ADDRESS_TYPE_DOMAIN: u8 = 3;
+const ADDRESS_TYPE_IPV6: u8 = 4;
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_GENERAL_FAILURE: u8 = 1;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 8;
+
+/// How long a client may take to complete the handshake and send its request.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+
+/// Performs the SOCKS5 handshake and reads a `CONNECT` request. If `auth` is given, clients
+/// have to authenticate with those credentials. Returns the requested destination in the SOCKS5
+/// address format, which is also the format that Shadowsocks uses.
+pub fn accept_connect(
+    stream: &mut (impl Read + Write),
+    auth: Option<&ProxyAuth>,
+) -> io::Result<Vec<u8>> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    if header[0] != SOCKS_VERSION {
+        return Err(invalid_data("Unsupported SOCKS version"));
+    }
+    let mut methods = vec![0u8; usize::from(header[1])];
+    stream.read_exact(&mut methods)?;
+    let method = match auth {
+        Some(_) => USERNAME_PASSWORD_AUTHENTICATION,
+        None => NO_AUTHENTICATION,
+    };
+    if !methods.contains(&method) {
+        stream.write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])?;
+        return Err(invalid_data(
+            "Client doesn't support the required authentication",
+        ));
+    }
+    stream.write_all(&[SOCKS_VERSION, method])?;
+    if let Some(auth) = auth {
+        authenticate(stream, auth)?;
+    }
+
+    let mut request = [0u8; 4];
+    stream.read_exact(&mut request)?;
+    if request[0] != SOCKS_VERSION {
+        return Err(invalid_data("Unsupported SOCKS version"));
+    }
+    if request[1] != COMMAND_CONNECT {
+        send_reply(stream, REPLY_COMMAND_NOT_SUPPORTED)?;
+        return Err(invalid_data("Unsupported SOCKS command"));
+    }
+
+    let address_type = request[3];
+    let mut address = vec![address_type];
+    let address_len = match address_type {
+        ADDRESS_TYPE_IPV4 => 4,
+        ADDRESS_TYPE_IPV6 => 16,
+        ADDRESS_TYPE_DOMAIN => {
+            let mut domain_len = [0u8; 1];
+            stream.read_exact(&mut domain_len)?;
+            address.push(domain_len[0]);
+            usize::from(domain_len[0])
+        }
+        _ => {
+            send_reply(stream, REPLY_ADDRESS_TYPE_NOT_SUPPORTED)?;
+            return Err(invalid_data("Unsupported SOCKS address type"));
+        }
+    };
+    // Address followed by the port.
+    let start = address.len();
+    address.resize(start + address_len + 2, 0);
+    stream.read_exact(&mut address[start..])?;
+
+    Ok(address)
+}
+
+/// Runs [`accept_connect`] on a TCP connection, and gives up if the client hasn't sent its
+/// request within `timeout`, so that idle clients can't hold on to connections.
+pub fn accept_connect_within(
+    stream: &mut TcpStream,
+    auth: Option<&ProxyAuth>,
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let mut deadline_stream = DeadlineStream {
+        stream,
+        deadline: Instant::now() + timeout,
+    };
+    let result = accept_connect(&mut deadline_stream, auth);
+    deadline_stream.stream.set_read_timeout(None)?;
+    result
+}
+
+/// TCP stream whose reads fail once a deadline has passed.
+struct DeadlineStream<'a> {
+    stream: &'a mut TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "SOCKS handshake timed out",
+            ));
+        }
+        self.stream.set_read_timeout(Some(self.deadline - now))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// Reads the username and password sent by the client and checks them against `auth`.
+fn authenticate(stream: &mut (impl Read + Write), auth: &ProxyAuth) -> io::Result<()> {
+    let mut version = [0u8; 1];
+    stream.read_exact(&mut version)?;
+    if version[0] != USERNAME_PASSWORD_VERSION {
+        return Err(invalid_data("Unsupported authentication version"));
+    }
+    let username = read_short_string(stream)?;
+    let password = read_short_string(stream)?;
+
+    if username == auth.username.as_bytes() && password == auth.password.as_bytes() {
+        stream.write_all(&[USERNAME_PASSWORD_VERSION, AUTHENTICATION_SUCCEEDED])
+    } else {
+        stream.write_all(&[USERNAME_PASSWORD_VERSION, AUTHENTICATION_FAILED])?;
+        Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Invalid SOCKS credentials",
+        ))
+    }
+}
+
+/// Reads a string prefixed by its length as a single byte.
+fn read_short_string(stream: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0u8; 1];
+    stream.read_exact(&mut len)?;
+    let mut string = vec![0u8; usize::from(len[0])];
+    stream.read_exact(&mut string)?;
+    Ok(string)
+}
+
+/// Returns the socket addresses of a destination in the SOCKS5 address format, as returned by
+/// `accept_connect`. Domain names are resolved by the system resolver.
+pub fn resolve_destination(destination: &[u8]) -> io::Result<Vec<SocketAddr>> {
+    let port_at = destination
+        .len()
+        .checked_sub(2)
+        .ok_or_else(|| invalid_data("Truncated SOCKS address"))?;
+    let port = u16::from_be_bytes([destination[port_at], destination[port_at + 1]]);
+    let address = &destination[1..port_at];
+
+    match destination[0] {
+        ADDRESS_TYPE_IPV4 if address.len() == 4 => {
+            let ip = Ipv4Addr::new(address[0], address[1], address[2], address[3]);
+            Ok(vec![SocketAddr::new(ip.into(), port)])
+        }
+        ADDRESS_TYPE_IPV6 if address.len() == 16 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(address);
+            Ok(vec![SocketAddr::new(Ipv6Addr::from(octets).into(), port)])
+        }
+        ADDRESS_TYPE_DOMAIN if !address.is_empty() => {
+            let domain = std::str::from_utf8(&address[1..])
+                .map_err(|_| invalid_data("Invalid SOCKS domain name"))?;
+            Ok((domain, port).to_socket_addrs()?.collect())
+        }
+        _ => Err(invalid_data("Invalid SOCKS address")),
+    }
+}
+
+/// Tells the client whether the connection to the destination could be established.
+pub fn send_connect_result(stream: &mut impl Write, succeeded: bool) -> io::Result<()> {
+    send_reply(
+        stream,
+        if succeeded {
+            REPLY_SUCCEEDED
+        } else {
+            REPLY_GENERAL_FAILURE
+        },
+    )
+}
+
+fn send_reply(stream: &mut impl Write, reply: u8) -> io::Result<()> {
+    // The bound address is not known to the client anyway, so report an unspecified one.
+    stream.write_all(&[SOCKS_VERSION, reply, 0, ADDRESS_TYPE_IPV4, 0, 0, 0, 0, 0, 0])
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Stream that reads what a client sent, and records what the server replies.
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl MockStream {
+        fn new(input: &[u8]) -> Self {
+            MockStream {
+                input: Cursor::new(input.to_vec()),
+                output: vec![],
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn auth() -> ProxyAuth {
+        ProxyAuth {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_accept_connect_with_auth() {
+        let mut stream = MockStream::new(&[
+            5, 1, 2, // Username/password authentication
+            1, 4, b'u', b's', b'e', b'r', 4, b'p', b'a', b's', b's', // Credentials
+            5, 1, 0, 1, 10, 0, 0, 1, 0, 80, // Connect to 10.0.0.1:80
+        ]);
+        let destination = accept_connect(&mut stream, Some(&auth())).unwrap();
+        assert_eq!(destination, [1, 10, 0, 0, 1, 0, 80]);
+        assert_eq!(stream.output, [5, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_accept_connect_wrong_password() {
+        let mut stream = MockStream::new(&[
+            5, 1, 2, // Username/password authentication
+            1, 4, b'u', b's', b'e', b'r', 4, b'p', b'a', b's', b'x', // Credentials
+        ]);
+        let error = accept_connect(&mut stream, Some(&auth())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(stream.output, [5, 2, 1, 1]);
+    }
+
+    #[test]
+    fn test_accept_connect_requires_auth() {
+        let mut stream = MockStream::new(&[5, 1, 0]);
+        assert!(accept_connect(&mut stream, Some(&auth())).is_err());
+        assert_eq!(stream.output, [5, NO_ACCEPTABLE_METHODS]);
+    }
+
+    #[test]
+    fn test_accept_connect_timeout() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut stream, _) = listener.accept().unwrap();
+
+        // The client sends the start of a handshake, and then nothing.
+        client.write_all(&[5, 1]).unwrap();
+        let error =
+            accept_connect_within(&mut stream, None, Duration::from_millis(100)).unwrap_err();
+        assert!(
+            error.kind() == io::ErrorKind::TimedOut || error.kind() == io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn test_resolve_destination() {
+        assert_eq!(
+            resolve_destination(&[1, 10, 0, 0, 1, 0x04, 0xaa]).unwrap(),
+            vec!["10.0.0.1:1194".parse::<SocketAddr>().unwrap()]
+        );
+        let mut ipv6 = vec![4];
+        ipv6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        ipv6.extend_from_slice(&[0, 53]);
+        assert_eq!(
+            resolve_destination(&ipv6).unwrap(),
+            vec!["[::1]:53".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(resolve_destination(&[1, 10, 0]).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+//! SOCKS5 server on localhost whose outgoing connections are made through the tunnel interface,
+//! so that applications can use the tunnel without all traffic of the machine going through it.
+
+pub use std::io::Result;
+
+use super::{socks, ProxyMonitor, ProxyMonitorCloseHandle, WaitResult};
+use socket2::{Domain, Protocol, Socket, Type};
+#[cfg(target_os = "linux")]
+use std::ffi::CString;
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use talpid_types::{
+    net::{openvpn::ProxyAuth, proxy::TunnelSocksProxy},
+    ErrorExt,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest number of connections that are proxied at the same time. Each one uses two threads.
+const MAX_CONNECTIONS: usize = 128;
+
+/// Open connections, so that they can be torn down when the proxy is closed.
+type Connections = Arc<Mutex<HashMap<u64, TcpStream>>>;
+
+/// The tunnel that outgoing connections are made through.
+struct Tunnel {
+    #[cfg(target_os = "linux")]
+    interface: CString,
+    #[cfg(not(target_os = "linux"))]
+    ips: Vec<IpAddr>,
+}
+
+pub struct TunnelSocksProxyMonitor {
+    local_addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+    connections: Connections,
+    exit_rx: mpsc::Receiver<io::Error>,
+}
+
+impl TunnelSocksProxyMonitor {
+    /// Starts accepting connections on the port in `settings`, and makes the outgoing
+    /// connections through `interface`. On platforms where sockets can't be bound to an
+    /// interface, they are bound to the tunnel IP in `tunnel_ips` instead.
+    #[cfg_attr(target_os = "linux", allow(unused_variables))]
+    pub fn start(
+        settings: &TunnelSocksProxy,
+        interface: &str,
+        tunnel_ips: &[IpAddr],
+    ) -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let tunnel = Tunnel {
+            interface: CString::new(interface)
+                .map_err(|error| io::Error::new(io::ErrorKind::InvalidInput, error))?,
+        };
+        #[cfg(not(target_os = "linux"))]
+        let tunnel = Tunnel {
+            ips: tunnel_ips.to_vec(),
+        };
+        let tunnel = Arc::new(tunnel);
+
+        let listener =
+            TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), settings.port))?;
+        let local_addr = listener.local_addr()?;
+        log::debug!(
+            "SOCKS5 proxy through {} listening on {}",
+            interface,
+            local_addr
+        );
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let (exit_tx, exit_rx) = mpsc::channel();
+
+        let auth = Arc::new(settings.auth.clone());
+        let accept_closed = closed.clone();
+        let accept_connections = connections.clone();
+        thread::spawn(move || {
+            let error = Self::accept_connections(
+                listener,
+                tunnel,
+                auth,
+                &accept_closed,
+                accept_connections,
+            );
+            let _ = exit_tx.send(error);
+        });
+
+        Ok(TunnelSocksProxyMonitor {
+            local_addr,
+            closed,
+            connections,
+            exit_rx,
+        })
+    }
+
+    fn accept_connections(
+        listener: TcpListener,
+        tunnel: Arc<Tunnel>,
+        auth: Arc<Option<ProxyAuth>>,
+        closed: &AtomicBool,
+        connections: Connections,
+    ) -> io::Error {
+        let mut next_id = 0u64;
+        loop {
+            let client = match listener.accept() {
+                Ok((client, _)) => client,
+                Err(error) => return error,
+            };
+            if closed.load(Ordering::SeqCst) {
+                return io::Error::new(io::ErrorKind::Other, "Proxy was closed");
+            }
+
+            let id = next_id;
+            next_id += 1;
+            {
+                let mut connections = connections.lock().expect("Connections lock poisoned");
+                if connections.len() >= MAX_CONNECTIONS {
+                    log::debug!("SOCKS5 proxy refused a connection: too many connections");
+                    continue;
+                }
+                match client.try_clone() {
+                    Ok(client_clone) => {
+                        connections.insert(id, client_clone);
+                    }
+                    Err(error) => return error,
+                }
+            }
+
+            let tunnel = tunnel.clone();
+            let auth = auth.clone();
+            let connections = connections.clone();
+            thread::spawn(move || {
+                if let Err(error) = Self::handle_client(client, &tunnel, (*auth).as_ref()) {
+                    log::debug!(
+                        "{}",
+                        error.display_chain_with_msg("SOCKS5 proxy connection failed")
+                    );
+                }
+                connections
+                    .lock()
+                    .expect("Connections lock poisoned")
+                    .remove(&id);
+            });
+        }
+    }
+
+    fn handle_client(
+        mut client: TcpStream,
+        tunnel: &Tunnel,
+        auth: Option<&ProxyAuth>,
+    ) -> io::Result<()> {
+        let destination =
+            socks::accept_connect_within(&mut client, auth, socks::HANDSHAKE_TIMEOUT)?;
+        let server = match socks::resolve_destination(&destination)
+            .and_then(|addresses| connect_through_tunnel(&addresses, tunnel))
+        {
+            Ok(server) => server,
+            Err(error) => {
+                let _ = socks::send_connect_result(&mut client, false);
+                return Err(error);
+            }
+        };
+        socks::send_connect_result(&mut client, true)?;
+        client.set_nodelay(true)?;
+        server.set_nodelay(true)?;
+
+        let mut client_reader = client.try_clone()?;
+        let mut server_writer = server.try_clone()?;
+        let upload = thread::spawn(move || {
+            let result = io::copy(&mut client_reader, &mut server_writer);
+            let _ = server_writer.shutdown(Shutdown::Write);
+            result
+        });
+
+        let download_result = io::copy(&mut server.try_clone()?, &mut client);
+        let _ = client.shutdown(Shutdown::Write);
+        if download_result.is_err() {
+            // Make sure that the upload thread does not linger.
+            let _ = client.shutdown(Shutdown::Both);
+            let _ = server.shutdown(Shutdown::Both);
+        }
+
+        let upload_result = upload
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::Other, "Upload panicked")));
+        download_result.and(upload_result).map(|_| ())
+    }
+}
+
+/// Connects to the first of `addresses` that can be reached through the tunnel.
+fn connect_through_tunnel(addresses: &[SocketAddr], tunnel: &Tunnel) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::AddrNotAvailable,
+        "The destination has no addresses",
+    );
+    for address in addresses {
+        match connect_to(*address, tunnel) {
+            Ok(stream) => return Ok(stream),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+fn connect_to(address: SocketAddr, tunnel: &Tunnel) -> io::Result<TcpStream> {
+    let domain = match address {
+        SocketAddr::V4(_) => Domain::ipv4(),
+        SocketAddr::V6(_) => Domain::ipv6(),
+    };
+    let socket = Socket::new(domain, Type::stream(), Some(Protocol::tcp()))?;
+
+    // Makes all traffic on the socket go through the tunnel, regardless of the routing table.
+    #[cfg(target_os = "linux")]
+    socket.bind_device(Some(&tunnel.interface))?;
+    #[cfg(not(target_os = "linux"))]
+    {
+        let source = tunnel
+            .ips
+            .iter()
+            .find(|ip| ip.is_ipv4() == address.is_ipv4())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::AddrNotAvailable,
+                    "The tunnel has no address of the same family as the destination",
+                )
+            })?;
+        socket.bind(&SocketAddr::new(*source, 0).into())?;
+    }
+
+    socket.connect_timeout(&address.into(), CONNECT_TIMEOUT)?;
+    Ok(socket.into_tcp_stream())
+}
+
+impl ProxyMonitor for TunnelSocksProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(TunnelSocksProxyMonitorCloseHandle {
+            local_addr: self.local_addr,
+            closed: self.closed.clone(),
+            connections: self.connections.clone(),
+        })
+    }
+
+    fn wait(self: Box<Self>) -> Result<WaitResult> {
+        let result = self.exit_rx.recv();
+        if self.closed.load(Ordering::SeqCst) {
+            return Ok(WaitResult::ProperShutdown);
+        }
+        match result {
+            Ok(error) => Ok(WaitResult::UnexpectedExit(error.to_string())),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "SOCKS5 proxy exited without a result",
+            )),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.local_addr.port()
+    }
+}
+
+impl Drop for TunnelSocksProxyMonitor {
+    /// Closes the proxy, since it must not outlive the tunnel it sends traffic through.
+    fn drop(&mut self) {
+        if let Err(error) = self.close_handle().close() {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to close SOCKS5 proxy")
+            );
+        }
+    }
+}
+
+pub struct TunnelSocksProxyMonitorCloseHandle {
+    local_addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+    connections: Connections,
+}
+
+impl ProxyMonitorCloseHandle for TunnelSocksProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> Result<()> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        for (_, connection) in self
+            .connections
+            .lock()
+            .expect("Connections lock poisoned")
+            .drain()
+        {
+            let _ = connection.shutdown(Shutdown::Both);
+        }
+        // Wake up the thread blocked on accepting connections, so it notices that it's closed.
+        TcpStream::connect(self.local_addr).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[cfg(target_os = "linux")]
+    const LOOPBACK_INTERFACE: &str = "lo";
+    #[cfg(not(target_os = "linux"))]
+    const LOOPBACK_INTERFACE: &str = "lo0";
+
+    fn start_proxy() -> TunnelSocksProxyMonitor {
+        let settings = TunnelSocksProxy {
+            port: 0,
+            auth: None,
+        };
+        TunnelSocksProxyMonitor::start(&settings, LOOPBACK_INTERFACE, &[Ipv4Addr::LOCALHOST.into()])
+            .unwrap()
+    }
+
+    fn is_proper_shutdown(proxy: TunnelSocksProxyMonitor) -> bool {
+        match Box::new(proxy).wait().unwrap() {
+            WaitResult::ProperShutdown => true,
+            WaitResult::UnexpectedExit(_) => false,
+        }
+    }
+
+    #[test]
+    fn test_proxy_connection() {
+        let server = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let server_port = server.local_addr().unwrap().port().to_be_bytes();
+        let mut proxy = start_proxy();
+        assert_ne!(proxy.port(), 0);
+
+        let mut client =
+            TcpStream::connect(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port())).unwrap();
+        client.write_all(&[5, 1, 0]).unwrap();
+        client
+            .write_all(&[5, 1, 0, 1, 127, 0, 0, 1, server_port[0], server_port[1]])
+            .unwrap();
+        let mut replies = [0u8; 12];
+        client.read_exact(&mut replies).unwrap();
+        assert_eq!(replies[..4], [5, 0, 5, 0]);
+
+        let (mut server_stream, _) = server.accept().unwrap();
+        client.write_all(b"ping").unwrap();
+        let mut message = [0u8; 4];
+        server_stream.read_exact(&mut message).unwrap();
+        assert_eq!(&message, b"ping");
+
+        // Closing the proxy tears down the open connections.
+        proxy.close_handle().close().unwrap();
+        assert_eq!(client.read(&mut message).unwrap(), 0);
+        assert!(is_proper_shutdown(proxy));
+    }
+
+    #[test]
+    fn test_refuses_connections_after_close() {
+        let mut proxy = start_proxy();
+        let address = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port());
+        proxy.close_handle().close().unwrap();
+        assert!(is_proper_shutdown(proxy));
+
+        let error = TcpStream::connect(address).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionRefused);
+    }
+}
@@ -0,0 +1,220 @@
+//! Forwards UDP datagrams received on a local socket over a TCP stream, and vice versa. This is
+//! used to get WireGuard traffic through networks that block UDP. Each datagram is prefixed by
+//! its length, encoded as a big endian `u16`.
+
+pub use std::io::Result;
+
+use super::{ProxyMonitor, ProxyMonitorCloseHandle, WaitResult};
+use std::{
+    io::{self, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often the UDP forwarding thread checks whether the proxy has been closed.
+const UDP_READ_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_DATAGRAM_SIZE: usize = u16::max_value() as usize;
+
+pub struct Udp2TcpProxyMonitor {
+    tcp_stream: TcpStream,
+    closed: Arc<AtomicBool>,
+    exit_rx: mpsc::Receiver<io::Error>,
+    port: u16,
+}
+
+impl Udp2TcpProxyMonitor {
+    /// Connects to the relay-side endpoint `peer` and starts forwarding datagrams sent to the
+    /// returned monitor's local port.
+    pub fn start(peer: SocketAddr) -> Result<Self> {
+        let udp_socket = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0))?;
+        udp_socket.set_read_timeout(Some(UDP_READ_TIMEOUT))?;
+        let port = udp_socket.local_addr()?.port();
+
+        let tcp_stream = TcpStream::connect_timeout(&peer, CONNECT_TIMEOUT)?;
+        tcp_stream.set_nodelay(true)?;
+        log::debug!("Forwarding UDP on port {} over TCP to {}", port, peer);
+
+        let closed = Arc::new(AtomicBool::new(false));
+        let local_peer = Arc::new(Mutex::new(None));
+        let (exit_tx, exit_rx) = mpsc::channel();
+
+        {
+            let udp_socket = udp_socket.try_clone()?;
+            let tcp_stream = tcp_stream.try_clone()?;
+            let local_peer = local_peer.clone();
+            let closed = closed.clone();
+            let exit_tx = exit_tx.clone();
+            thread::spawn(move || {
+                let error = Self::forward_udp_to_tcp(udp_socket, tcp_stream, local_peer, &closed);
+                let _ = exit_tx.send(error);
+            });
+        }
+        {
+            let tcp_stream = tcp_stream.try_clone()?;
+            thread::spawn(move || {
+                let error = Self::forward_tcp_to_udp(tcp_stream, udp_socket, local_peer);
+                let _ = exit_tx.send(error);
+            });
+        }
+
+        Ok(Udp2TcpProxyMonitor {
+            tcp_stream,
+            closed,
+            exit_rx,
+            port,
+        })
+    }
+
+    fn forward_udp_to_tcp(
+        udp_socket: UdpSocket,
+        mut tcp_stream: TcpStream,
+        local_peer: Arc<Mutex<Option<SocketAddr>>>,
+        closed: &AtomicBool,
+    ) -> io::Error {
+        let mut buffer = vec![0u8; 2 + MAX_DATAGRAM_SIZE];
+        loop {
+            let (size, source) = match udp_socket.recv_from(&mut buffer[2..]) {
+                Ok(result) => result,
+                Err(error)
+                    if error.kind() == io::ErrorKind::WouldBlock
+                        || error.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if closed.load(Ordering::SeqCst) {
+                        return io::Error::new(io::ErrorKind::Other, "Proxy was closed");
+                    }
+                    continue;
+                }
+                Err(error) => return error,
+            };
+            *local_peer.lock().expect("Local peer lock poisoned") = Some(source);
+
+            buffer[..2].copy_from_slice(&(size as u16).to_be_bytes());
+            if let Err(error) = tcp_stream.write_all(&buffer[..2 + size]) {
+                return error;
+            }
+        }
+    }
+
+    fn forward_tcp_to_udp(
+        mut tcp_stream: TcpStream,
+        udp_socket: UdpSocket,
+        local_peer: Arc<Mutex<Option<SocketAddr>>>,
+    ) -> io::Error {
+        let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        loop {
+            let mut header = [0u8; 2];
+            if let Err(error) = tcp_stream.read_exact(&mut header) {
+                return error;
+            }
+            let size = usize::from(u16::from_be_bytes(header));
+            if let Err(error) = tcp_stream.read_exact(&mut buffer[..size]) {
+                return error;
+            }
+
+            // Datagrams arriving before anything has been sent cannot be delivered anywhere.
+            let destination = *local_peer.lock().expect("Local peer lock poisoned");
+            if let Some(destination) = destination {
+                if let Err(error) = udp_socket.send_to(&buffer[..size], destination) {
+                    return error;
+                }
+            }
+        }
+    }
+}
+
+impl ProxyMonitor for Udp2TcpProxyMonitor {
+    fn close_handle(&mut self) -> Box<dyn ProxyMonitorCloseHandle> {
+        Box::new(Udp2TcpProxyMonitorCloseHandle {
+            tcp_stream: self.tcp_stream.try_clone(),
+            closed: self.closed.clone(),
+        })
+    }
+
+    fn wait(self: Box<Self>) -> Result<WaitResult> {
+        let result = self.exit_rx.recv();
+        let was_closed = self.closed.swap(true, Ordering::SeqCst);
+        // Stop the remaining forwarding thread.
+        let _ = self.tcp_stream.shutdown(Shutdown::Both);
+
+        match result {
+            _ if was_closed => Ok(WaitResult::ProperShutdown),
+            Ok(error) => Ok(WaitResult::UnexpectedExit(error.to_string())),
+            Err(_) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "Forwarding threads exited without a result",
+            )),
+        }
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for Udp2TcpProxyMonitor {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+        let _ = self.tcp_stream.shutdown(Shutdown::Both);
+    }
+}
+
+pub struct Udp2TcpProxyMonitorCloseHandle {
+    tcp_stream: io::Result<TcpStream>,
+    closed: Arc<AtomicBool>,
+}
+
+impl ProxyMonitorCloseHandle for Udp2TcpProxyMonitorCloseHandle {
+    fn close(self: Box<Self>) -> Result<()> {
+        if !self.closed.swap(true, Ordering::SeqCst) {
+            match self.tcp_stream {
+                Ok(tcp_stream) => tcp_stream.shutdown(Shutdown::Both),
+                Err(error) => Err(error),
+            }
+        } else {
+            Ok(())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_forwarding() {
+        let listener = TcpListener::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        let mut proxy = Udp2TcpProxyMonitor::start(listener.local_addr().unwrap()).unwrap();
+        let (mut relay_stream, _) = listener.accept().unwrap();
+
+        let client = UdpSocket::bind(SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0)).unwrap();
+        client
+            .send_to(
+                b"ping",
+                SocketAddr::new(Ipv4Addr::LOCALHOST.into(), proxy.port()),
+            )
+            .unwrap();
+
+        let mut frame = [0u8; 6];
+        relay_stream.read_exact(&mut frame).unwrap();
+        assert_eq!(&frame, b"\x00\x04ping");
+
+        relay_stream.write_all(b"\x00\x04pong").unwrap();
+        let mut datagram = [0u8; 16];
+        let size = client.recv(&mut datagram).unwrap();
+        assert_eq!(&datagram[..size], b"pong");
+
+        proxy.close_handle().close().unwrap();
+        assert!(match Box::new(proxy).wait().unwrap() {
+            WaitResult::ProperShutdown => true,
+            WaitResult::UnexpectedExit(_) => false,
+        });
+    }
+}
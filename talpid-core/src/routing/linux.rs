@@ -5,7 +5,7 @@ use talpid_types::ErrorExt;
 use ipnetwork::IpNetwork;
 use std::{
     collections::{BTreeMap, HashSet},
-    io,
+    fs, io,
     net::IpAddr,
     thread,
 };
@@ -20,6 +20,7 @@ use futures::{
 
 
 use netlink_packet_route::{
+    constants::{NLM_F_ACK, NLM_F_CREATE, NLM_F_EXCL, NLM_F_REQUEST},
     link::{nlas::Nla as LinkNla, LinkMessage},
     route::{nlas::Nla as RouteNla, RouteHeader, RouteMessage},
     rtnl::{
@@ -39,6 +40,31 @@ use libc::{AF_INET, AF_INET6};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Priority of the rule that makes non-default routes in the main table take precedence over the
+/// tunnel table. Lower values are evaluated first, and the main table rule has priority 32766.
+const SUPPRESS_RULE_PRIORITY: u32 = 32760;
+/// Priority of the rule that sends all traffic without the tunnel firewall mark to the tunnel
+/// table.
+const TUNNEL_RULE_PRIORITY: u32 = 32761;
+
+/// Lets packets with a firewall mark pass the reverse path filter, which would otherwise drop the
+/// replies to the tunnel socket since they arrive on an interface that isn't the best route back.
+const SRC_VALID_MARK_PATH: &str = "/proc/sys/net/ipv4/conf/all/src_valid_mark";
+const DISABLE_IPV6_PATH: &str = "/proc/sys/net/ipv6/conf/all/disable_ipv6";
+
+// Rule messages from `linux/rtnetlink.h` and `linux/fib_rules.h`. `netlink-packet-route` 0.2 can't
+// represent rules, so they are built by hand.
+const RTM_NEWRULE: u16 = 32;
+const RTM_DELRULE: u16 = 33;
+const NLMSG_ERROR: u16 = 2;
+const NLMSG_HEADER_LEN: usize = 16;
+const FR_ACT_TO_TBL: u8 = 1;
+const FIB_RULE_INVERT: u32 = 0x2;
+const FRA_PRIORITY: u16 = 6;
+const FRA_FWMARK: u16 = 10;
+const FRA_SUPPRESS_PREFIXLEN: u16 = 14;
+const FRA_TABLE: u16 = 15;
+
 /// Errors that can happen in the Linux routing integration
 #[derive(err_derive::Error, Debug)]
 #[error(no_from)]
@@ -67,6 +93,9 @@ pub enum Error {
     #[error(display = "Unknown device index - {}", _0)]
     UnknownDeviceIndex(u32),
 
+    #[error(display = "Failed to change routing policy rule {:?}", _0)]
+    PolicyRuleError(PolicyRule, #[error(source)] io::Error),
+
     #[error(display = "Shutting down route manager")]
     Shutdown,
 }
@@ -133,6 +162,155 @@ struct RequiredDefaultRoute {
     destination: IpNetwork,
 }
 
+/// Routing policy rules in the style of wg-quick. All traffic without the firewall mark is looked
+/// up in the tunnel table, except for destinations that have a non-default route in the main
+/// table, such as the local network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PolicyRouting {
+    fwmark: u32,
+    table_id: u8,
+}
+
+impl PolicyRouting {
+    fn rules(&self, ipv6: bool) -> Vec<PolicyRule> {
+        let mut families = vec![AF_INET as u8];
+        if ipv6 {
+            families.push(AF_INET6 as u8);
+        }
+        families
+            .into_iter()
+            .flat_map(|family| {
+                vec![
+                    PolicyRule {
+                        family,
+                        priority: SUPPRESS_RULE_PRIORITY,
+                        table: u32::from(RT_TABLE_MAIN),
+                        fwmark: None,
+                        invert: false,
+                        suppress_prefixlen: Some(0),
+                    },
+                    PolicyRule {
+                        family,
+                        priority: TUNNEL_RULE_PRIORITY,
+                        table: u32::from(self.table_id),
+                        fwmark: Some(self.fwmark),
+                        invert: true,
+                        suppress_prefixlen: None,
+                    },
+                ]
+            })
+            .collect()
+    }
+}
+
+/// A routing policy rule, like the ones managed with `ip rule`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PolicyRule {
+    family: u8,
+    priority: u32,
+    table: u32,
+    /// Firewall mark that packets must have, or must not have if `invert` is set.
+    fwmark: Option<u32>,
+    invert: bool,
+    /// Ignores routes in the table with a prefix length less than or equal to this.
+    suppress_prefixlen: Option<u32>,
+}
+
+impl PolicyRule {
+    /// Serializes a netlink request of `message_type` for the rule.
+    fn message(&self, message_type: u16, flags: u16) -> Vec<u8> {
+        let mut message = Vec::with_capacity(64);
+        // struct nlmsghdr. The length is set once the message is complete.
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&message_type.to_ne_bytes());
+        message.extend_from_slice(&flags.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        // struct fib_rule_hdr. The table is set as an attribute since it may not fit in a byte.
+        message.extend_from_slice(&[self.family, 0, 0, 0, 0, 0, 0, FR_ACT_TO_TBL]);
+        let rule_flags = if self.invert { FIB_RULE_INVERT } else { 0 };
+        message.extend_from_slice(&rule_flags.to_ne_bytes());
+
+        let mut push_attribute = |kind: u16, value: u32| {
+            message.extend_from_slice(&8u16.to_ne_bytes());
+            message.extend_from_slice(&kind.to_ne_bytes());
+            message.extend_from_slice(&value.to_ne_bytes());
+        };
+        push_attribute(FRA_PRIORITY, self.priority);
+        push_attribute(FRA_TABLE, self.table);
+        if let Some(fwmark) = self.fwmark {
+            push_attribute(FRA_FWMARK, fwmark);
+        }
+        if let Some(suppress_prefixlen) = self.suppress_prefixlen {
+            push_attribute(FRA_SUPPRESS_PREFIXLEN, suppress_prefixlen);
+        }
+
+        let length = message.len() as u32;
+        message[..4].copy_from_slice(&length.to_ne_bytes());
+        message
+    }
+}
+
+/// Sends a request to the kernel over a new rtnetlink socket and waits for it to be acknowledged.
+fn send_netlink_request(request: &[u8]) -> io::Result<()> {
+    let socket = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+    if socket < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let socket = NetlinkSocket(socket);
+
+    let sent = unsafe { libc::send(socket.0, request.as_ptr() as *const _, request.len(), 0) };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut buffer = [0u8; 4096];
+    loop {
+        let received =
+            unsafe { libc::recv(socket.0, buffer.as_mut_ptr() as *mut _, buffer.len(), 0) };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let response = &buffer[..received as usize];
+        if response.len() < NLMSG_HEADER_LEN + 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "truncated netlink response",
+            ));
+        }
+        if u16::from_ne_bytes([response[4], response[5]]) != NLMSG_ERROR {
+            continue;
+        }
+        // An acknowledgement is an error message with an error code of zero
+        let mut code = [0u8; 4];
+        code.copy_from_slice(&response[NLMSG_HEADER_LEN..NLMSG_HEADER_LEN + 4]);
+        return match i32::from_ne_bytes(code) {
+            0 => Ok(()),
+            code => Err(io::Error::from_raw_os_error(-code)),
+        };
+    }
+}
+
+struct NetlinkSocket(libc::c_int);
+
+impl Drop for NetlinkSocket {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.0) };
+    }
+}
+
+fn is_ipv6_enabled() -> bool {
+    fs::read_to_string(DISABLE_IPV6_PATH)
+        .map(|disable_ipv6| disable_ipv6.trim() == "0")
+        .unwrap_or(false)
+}
+
 pub struct RouteManagerImplInner {
     handle: Handle,
     messages: UnboundedReceiver<(NetlinkMessage<RtnlMessage>, SocketAddr)>,
@@ -146,6 +324,12 @@ pub struct RouteManagerImplInner {
     default_routes: HashSet<Route>,
     best_default_node_v4: Option<Node>,
     best_default_node_v6: Option<Node>,
+
+    // currently added routing policy rules
+    policy_routing: Option<PolicyRouting>,
+    policy_rules: Vec<PolicyRule>,
+    // value of src_valid_mark before policy routing was enabled
+    saved_src_valid_mark: Option<String>,
 }
 
 impl RouteManagerImplInner {
@@ -175,6 +359,10 @@ impl RouteManagerImplInner {
             default_routes: HashSet::new(),
             best_default_node_v4: None,
             best_default_node_v6: None,
+
+            policy_routing: None,
+            policy_rules: vec![],
+            saved_src_valid_mark: None,
         };
 
         monitor.default_routes = monitor.get_default_routes().await?;
@@ -403,7 +591,70 @@ impl RouteManagerImplInner {
             .map(|route| route.node)
     }
 
+    fn enable_policy_routing(&mut self, policy_routing: PolicyRouting) -> Result<()> {
+        if self.policy_routing == Some(policy_routing) {
+            return Ok(());
+        }
+        self.disable_policy_routing();
+        self.enable_src_valid_mark();
+
+        // Set before adding the rules, so that partially added rules are removed on cleanup.
+        self.policy_routing = Some(policy_routing);
+        for rule in policy_routing.rules(is_ipv6_enabled()) {
+            let flags = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL;
+            match send_netlink_request(&rule.message(RTM_NEWRULE, flags)) {
+                // Left behind if the daemon stopped without cleaning up
+                Err(ref error) if error.raw_os_error() == Some(libc::EEXIST) => (),
+                Err(error) => return Err(Error::PolicyRuleError(rule, error)),
+                Ok(()) => (),
+            }
+            self.policy_rules.push(rule);
+        }
+        Ok(())
+    }
+
+    fn disable_policy_routing(&mut self) {
+        self.policy_routing = None;
+        for rule in self.policy_rules.drain(..) {
+            let flags = NLM_F_REQUEST | NLM_F_ACK;
+            if let Err(error) = send_netlink_request(&rule.message(RTM_DELRULE, flags)) {
+                log::debug!("{}", Error::PolicyRuleError(rule, error).display_chain());
+            }
+        }
+        self.restore_src_valid_mark();
+    }
+
+    fn enable_src_valid_mark(&mut self) {
+        match fs::read_to_string(SRC_VALID_MARK_PATH) {
+            Ok(ref value) if value.trim() == "1" => (),
+            Ok(value) => match fs::write(SRC_VALID_MARK_PATH, "1") {
+                Ok(()) => self.saved_src_valid_mark = Some(value.trim().to_owned()),
+                Err(error) => log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to enable src_valid_mark")
+                ),
+            },
+            Err(error) => log::warn!(
+                "{}",
+                error.display_chain_with_msg("Failed to read src_valid_mark")
+            ),
+        }
+    }
+
+    fn restore_src_valid_mark(&mut self) {
+        if let Some(value) = self.saved_src_valid_mark.take() {
+            if let Err(error) = fs::write(SRC_VALID_MARK_PATH, value) {
+                log::warn!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to restore src_valid_mark")
+                );
+            }
+        }
+    }
+
     async fn cleanup_routes(&mut self) {
+        self.disable_policy_routing();
+
         for required_route in &self.required_default_routes {
             let best_node = if required_route.destination.is_ipv4() {
                 self.best_default_node_v4.clone()
@@ -419,35 +670,58 @@ impl RouteManagerImplInner {
             let route =
                 Route::new(best_node, required_route.destination).table(required_route.table_id);
             if let Err(e) = self.delete_route(&route).await {
-                if let Error::NetlinkError(err) = &e {
-                    if let rtnetlink::ErrorKind::NetlinkError(msg) = err.get_ref().kind() {
-                        // -3 means that the route doesn't exist anymore anyway
-                        if msg.code == -3 {
-                            continue;
-                        }
-                    }
+                if !is_route_missing(&e) {
+                    log::error!("Failed to remove route - {} - {}", route, e);
                 }
-                log::error!("Failed to remove route - {} - {}", route, e);
             }
         }
         self.required_default_routes.clear();
 
         for route in self.added_routes.drain().collect::<Vec<_>>().iter() {
             if let Err(e) = self.delete_route(&route).await {
-                if let Error::NetlinkError(err) = &e {
-                    if let rtnetlink::ErrorKind::NetlinkError(msg) = err.get_ref().kind() {
-                        // -3 means that the route doesn't exist anymore anyway
-                        if msg.code == -3 {
-                            continue;
+                if !is_route_missing(&e) {
+                    log::error!("Failed to remove route - {} - {}", route, e);
+                }
+            }
+        }
+    }
+
+    async fn remove_required_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<()> {
+        for required_route in routes {
+            let route = match required_route.node {
+                NetNode::RealNode(node) => {
+                    Route::new(node, required_route.prefix).table(required_route.table_id)
+                }
+                NetNode::DefaultNode => {
+                    self.required_default_routes.remove(&RequiredDefaultRoute {
+                        table_id: required_route.table_id,
+                        destination: required_route.prefix,
+                    });
+                    let best_node = if required_route.prefix.is_ipv4() {
+                        self.best_default_node_v4.clone()
+                    } else {
+                        self.best_default_node_v6.clone()
+                    };
+                    match best_node {
+                        Some(node) => {
+                            Route::new(node, required_route.prefix).table(required_route.table_id)
                         }
+                        None => continue,
+                    }
+                }
+            };
+
+            if self.added_routes.remove(&route) {
+                if let Err(e) = self.delete_route(&route).await {
+                    if !is_route_missing(&e) {
+                        return Err(e);
                     }
                 }
-                log::error!("Failed to remove route - {} - {}", route, e);
             }
         }
+        Ok(())
     }
 
-
     pub async fn into_future(
         mut self,
         mut manage_rx: UnboundedReceiver<RouteManagerCommand>,
@@ -483,10 +757,28 @@ impl RouteManagerImplInner {
                     let _ = result_rx.send(Ok(()));
                 }
             }
+            RouteManagerCommand::RemoveRoutes(routes, result_tx) => {
+                log::debug!("Removing routes: {:?}", routes);
+                let _ = result_tx.send(self.remove_required_routes(routes).await);
+            }
             RouteManagerCommand::ClearRoutes => {
                 log::debug!("Clearing routes");
                 self.cleanup_routes().await;
             }
+            RouteManagerCommand::EnablePolicyRouting(fwmark, table_id, result_tx) => {
+                log::debug!(
+                    "Routing traffic without fwmark {:#x} through table {}",
+                    fwmark,
+                    table_id
+                );
+                let _ =
+                    result_tx.send(self.enable_policy_routing(PolicyRouting { fwmark, table_id }));
+            }
+            RouteManagerCommand::GetDefaultRoutes(result_tx) => {
+                let mut routes = self.default_routes.iter().collect::<Vec<_>>();
+                routes.sort_by_key(|route| route.metric.unwrap_or(0));
+                let _ = result_tx.send(routes.into_iter().map(ToString::to_string).collect());
+            }
         }
         Ok(())
     }
@@ -753,6 +1045,17 @@ impl Drop for RouteManagerImplInner {
     }
 }
 
+/// Returns whether `error` means that the route to be deleted doesn't exist anymore.
+fn is_route_missing(error: &Error) -> bool {
+    if let Error::NetlinkError(err) = error {
+        if let rtnetlink::ErrorKind::NetlinkError(msg) = err.get_ref().kind() {
+            // -3 means that the route doesn't exist anymore anyway
+            return msg.code == -3;
+        }
+    }
+    false
+}
+
 fn ip_to_bytes(addr: IpAddr) -> Vec<u8> {
     match addr {
         IpAddr::V4(addr) => addr.octets().to_vec(),
@@ -765,6 +1068,103 @@ mod test {
     use super::*;
     use std::collections::HashSet;
 
+    // Requests as sent by `ip rule`, on a little-endian host. The kernel adds the rules below as
+    // "32760: from all lookup main suppress_prefixlength 0" and
+    // "32761: not from all fwmark 0x6d6f6c65 lookup 52".
+    #[rustfmt::skip]
+    const SUPPRESS_RULE_MESSAGE: [u8; 52] = [
+        // nlmsghdr: length, type, flags, sequence number, port id
+        52, 0, 0, 0, 32, 0, 0x05, 0x06, 0, 0, 0, 0, 0, 0, 0, 0,
+        // fib_rule_hdr: family, dst_len, src_len, tos, table, reserved, action, flags
+        2, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0,
+        // FRA_PRIORITY
+        8, 0, 6, 0, 0xf8, 0x7f, 0, 0,
+        // FRA_TABLE
+        8, 0, 15, 0, 254, 0, 0, 0,
+        // FRA_SUPPRESS_PREFIXLEN
+        8, 0, 14, 0, 0, 0, 0, 0,
+    ];
+    #[rustfmt::skip]
+    const TUNNEL_RULE_MESSAGE: [u8; 52] = [
+        52, 0, 0, 0, 32, 0, 0x05, 0x06, 0, 0, 0, 0, 0, 0, 0, 0,
+        10, 0, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0,
+        8, 0, 6, 0, 0xf9, 0x7f, 0, 0,
+        8, 0, 15, 0, 52, 0, 0, 0,
+        // FRA_FWMARK
+        8, 0, 10, 0, 0x65, 0x6c, 0x6f, 0x6d,
+    ];
+
+    const NEW_RULE_FLAGS: u16 = NLM_F_REQUEST | NLM_F_ACK | NLM_F_CREATE | NLM_F_EXCL;
+
+    fn policy_routing() -> PolicyRouting {
+        PolicyRouting {
+            fwmark: 0x6d6f6c65,
+            table_id: 52,
+        }
+    }
+
+    #[test]
+    #[cfg(target_endian = "little")]
+    fn test_rule_message_encoding() {
+        let rules = policy_routing().rules(true);
+        assert_eq!(rules.len(), 4);
+        assert_eq!(
+            rules[0].message(RTM_NEWRULE, NEW_RULE_FLAGS),
+            SUPPRESS_RULE_MESSAGE.to_vec()
+        );
+        assert_eq!(rules[3].family, AF_INET6 as u8);
+        assert_eq!(
+            rules[3].message(RTM_NEWRULE, NEW_RULE_FLAGS),
+            TUNNEL_RULE_MESSAGE.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_rule_message_header() {
+        let rule = policy_routing().rules(false)[1];
+        let message = rule.message(RTM_DELRULE, NLM_F_REQUEST | NLM_F_ACK);
+
+        let length = u32::from_ne_bytes([message[0], message[1], message[2], message[3]]);
+        assert_eq!(length as usize, message.len());
+        assert_eq!(u16::from_ne_bytes([message[4], message[5]]), RTM_DELRULE);
+        assert_eq!(
+            u16::from_ne_bytes([message[6], message[7]]),
+            NLM_F_REQUEST | NLM_F_ACK
+        );
+        assert_eq!(message[NLMSG_HEADER_LEN], AF_INET as u8);
+        assert_eq!(message[NLMSG_HEADER_LEN + 7], FR_ACT_TO_TBL);
+        let rule_flags = &message[NLMSG_HEADER_LEN + 8..NLMSG_HEADER_LEN + 12];
+        assert_eq!(rule_flags, &FIB_RULE_INVERT.to_ne_bytes()[..]);
+    }
+
+    #[test]
+    fn test_rule_message_attributes() {
+        for rule in policy_routing().rules(true) {
+            let message = rule.message(RTM_NEWRULE, NEW_RULE_FLAGS);
+            let mut attributes = vec![];
+            // Attributes start after the 12 byte fib_rule_hdr and are aligned to 4 bytes
+            let mut offset = NLMSG_HEADER_LEN + 12;
+            while offset < message.len() {
+                assert_eq!(offset % 4, 0);
+                let length = u16::from_ne_bytes([message[offset], message[offset + 1]]) as usize;
+                let kind = u16::from_ne_bytes([message[offset + 2], message[offset + 3]]);
+                assert_eq!(length, 8);
+                let mut value = [0u8; 4];
+                value.copy_from_slice(&message[offset + 4..offset + 8]);
+                attributes.push((kind, u32::from_ne_bytes(value)));
+                offset += (length + 3) & !3;
+            }
+            assert_eq!(offset, message.len());
+
+            let mut expected = vec![(FRA_PRIORITY, rule.priority), (FRA_TABLE, rule.table)];
+            expected.extend(rule.fwmark.map(|fwmark| (FRA_FWMARK, fwmark)));
+            expected.extend(
+                rule.suppress_prefixlen
+                    .map(|prefixlen| (FRA_SUPPRESS_PREFIXLEN, prefixlen)),
+            );
+            assert_eq!(attributes, expected);
+        }
+    }
 
     /// Tests if dropping inside a tokio runtime panics
     #[test]
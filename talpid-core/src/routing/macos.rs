@@ -137,6 +137,29 @@ impl RouteManagerImpl {
         Ok(())
     }
 
+    fn remove_required_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<()> {
+        for route in routes {
+            let route = match route.node {
+                NetNode::RealNode(node) => Route::new(node, route.prefix),
+                NetNode::DefaultNode => {
+                    self.default_destinations.remove(&route.prefix);
+                    match (&self.v4_gateway, &self.v6_gateway, route.prefix.is_ipv4()) {
+                        (Some(gateway), _, true) | (_, Some(gateway), false) => {
+                            Route::new(gateway.clone(), route.prefix)
+                        }
+                        _ => continue,
+                    }
+                }
+            };
+
+            if self.applied_routes.remove(&route) {
+                Self::delete_route(route.prefix).wait()?;
+            }
+        }
+
+        Ok(())
+    }
+
     // Retrieves the node that's currently used to reach 0.0.0.0/0
     // Arguments can be either -inet or -inet6
     fn get_default_node_cmd(
@@ -336,6 +359,11 @@ impl Future for RouteManagerImpl {
                             let _ = result_tx.send(Ok(()));
                         }
                     }
+                    RouteManagerCommand::RemoveRoutes(routes, result_tx) => {
+                        self.manage_rx = Some(manage_rx);
+                        log::debug!("Removing routes: {:?}", routes);
+                        let _ = result_tx.send(self.remove_required_routes(routes));
+                    }
                     RouteManagerCommand::ClearRoutes => {
                         self.manage_rx = Some(manage_rx);
                         log::debug!("Clearing routes");
@@ -17,6 +17,15 @@ use netlink_packet_route::rtnl::constants::RT_TABLE_MAIN;
 
 pub use imp::{Error, RouteManager};
 
+/// Firewall mark set on the socket of a WireGuard tunnel when policy routing is used. Traffic
+/// without this mark is routed through [`TUNNEL_TABLE_ID`].
+#[cfg(target_os = "linux")]
+pub const TUNNEL_FWMARK: u32 = 0x6d6f6c65;
+
+/// Routing table holding the tunnel routes when policy routing is used.
+#[cfg(target_os = "linux")]
+pub const TUNNEL_TABLE_ID: u8 = 0x6d;
+
 /// A netowrk route with a specific network node, destinaiton and an optional metric.
 #[derive(Debug, Hash, Eq, PartialEq, Clone)]
 struct Route {
@@ -49,7 +49,15 @@ pub enum RouteManagerCommand {
         HashSet<RequiredRoute>,
         oneshot::Sender<Result<(), PlatformError>>,
     ),
+    RemoveRoutes(
+        HashSet<RequiredRoute>,
+        oneshot::Sender<Result<(), PlatformError>>,
+    ),
     ClearRoutes,
+    #[cfg(target_os = "linux")]
+    EnablePolicyRouting(u32, u8, oneshot::Sender<Result<(), PlatformError>>),
+    #[cfg(target_os = "linux")]
+    GetDefaultRoutes(oneshot::Sender<Vec<String>>),
     Shutdown(oneshot::Sender<()>),
 }
 
@@ -135,6 +143,75 @@ impl RouteManager {
         }
     }
 
+    /// Removes routes previously applied in [`RouteManager::new`] or
+    /// [`RouteManager::add_routes`]. Routes that aren't applied are ignored.
+    pub fn remove_routes(&mut self, routes: HashSet<RequiredRoute>) -> Result<(), Error> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::RemoveRoutes(routes, result_tx))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
+
+            match result_rx.wait() {
+                Ok(result) => result.map_err(Error::PlatformError),
+                Err(error) => {
+                    log::trace!(
+                        "{}",
+                        error.display_chain_with_msg("oneshot channel is closed")
+                    );
+                    Ok(())
+                }
+            }
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
+    /// Adds routing policy rules that send all traffic without `fwmark` to the routing table
+    /// `table_id`, while still letting more specific routes in the main table apply. The rules are
+    /// removed together with the routes, in [`RouteManager::clear_routes`] or when the route
+    /// manager stops.
+    #[cfg(target_os = "linux")]
+    pub fn enable_policy_routing(&mut self, fwmark: u32, table_id: u8) -> Result<(), Error> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::EnablePolicyRouting(
+                    fwmark, table_id, result_tx,
+                ))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
+            match result_rx.wait() {
+                Ok(result) => result.map_err(Error::PlatformError),
+                Err(_) => Err(Error::RouteManagerDown),
+            }
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
+    /// Returns the default routes currently seen by the route manager, most preferred first.
+    #[cfg(target_os = "linux")]
+    pub fn get_default_routes(&mut self) -> Result<Vec<String>, Error> {
+        if let Some(tx) = &self.manage_tx {
+            let (result_tx, result_rx) = oneshot::channel();
+            if tx
+                .unbounded_send(RouteManagerCommand::GetDefaultRoutes(result_tx))
+                .is_err()
+            {
+                return Err(Error::RouteManagerDown);
+            }
+            result_rx.wait().map_err(|_| Error::RouteManagerDown)
+        } else {
+            Err(Error::RouteManagerDown)
+        }
+    }
+
     /// Removes all routes previously applied in [`RouteManager::new`] or
     /// [`RouteManager::add_routes`].
     pub fn clear_routes(&mut self) -> Result<(), Error> {
@@ -11,6 +11,9 @@ pub enum Error {
     /// Failure to add routes
     #[error(display = "Failed to add routes")]
     AddRoutesFailed,
+    /// Failure to remove routes
+    #[error(display = "Failed to remove routes")]
+    DeleteRoutesFailed,
     /// Failure to clear routes
     #[error(display = "Failed to clear applied routes")]
     ClearRoutesFailed,
@@ -80,7 +83,25 @@ impl RouteManager {
 
     /// Applies the given routes until [`RouteManager::stop`] is called.
     pub fn add_routes(&self, routes: HashSet<RequiredRoute>) -> Result<()> {
-        let routes: Vec<_> = routes
+        if winnet::routing_manager_add_routes(&Self::convert_routes(&routes)) {
+            Ok(())
+        } else {
+            Err(Error::AddRoutesFailed)
+        }
+    }
+
+    /// Removes routes previously applied in [`RouteManager::new`] or
+    /// [`RouteManager::add_routes`].
+    pub fn remove_routes(&self, routes: HashSet<RequiredRoute>) -> Result<()> {
+        if winnet::routing_manager_delete_routes(&Self::convert_routes(&routes)) {
+            Ok(())
+        } else {
+            Err(Error::DeleteRoutesFailed)
+        }
+    }
+
+    fn convert_routes(routes: &HashSet<RequiredRoute>) -> Vec<winnet::WinNetRoute> {
+        routes
             .iter()
             .map(|route| {
                 let destination = winnet::WinNetIpNetwork::from(route.prefix);
@@ -91,13 +112,7 @@ impl RouteManager {
                     }
                 }
             })
-            .collect();
-
-        if winnet::routing_manager_add_routes(&routes) {
-            Ok(())
-        } else {
-            Err(Error::AddRoutesFailed)
-        }
+            .collect()
     }
 
     /// Removes all routes previously applied in [`RouteManager::new`] or
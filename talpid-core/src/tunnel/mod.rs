@@ -1,4 +1,6 @@
 use self::tun_provider::TunProvider;
+#[cfg(not(target_os = "android"))]
+use crate::routing::{NetNode, RequiredRoute};
 use crate::{logging, routing::RouteManager};
 #[cfg(not(target_os = "android"))]
 use std::collections::HashMap;
@@ -58,6 +60,11 @@ pub enum Error {
     #[error(display = "Failed while listening for events from the OpenVPN tunnel")]
     OpenVpnTunnelMonitoringError(#[error(source)] openvpn::Error),
 
+    /// Failed to route the excluded destinations outside of the OpenVPN tunnel.
+    #[cfg(not(target_os = "android"))]
+    #[error(display = "Failed to route excluded destinations outside of the tunnel")]
+    ExcludedRoutesError(#[error(source)] crate::routing::Error),
+
     /// There was an error listening for events from the Wireguard tunnel
     #[error(display = "Failed while listening for events from the Wireguard tunnel")]
     WireguardTunnelMonitoringError(#[error(source)] wireguard::Error),
@@ -86,6 +93,10 @@ pub struct TunnelMetadata {
     pub ipv4_gateway: Ipv4Addr,
     /// The IP to the IPv6 default gateway on the tunnel interface.
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// The DNS servers to use inside the tunnel, in order of preference.
+    pub dns_servers: Vec<IpAddr>,
+    /// The network namespace that the tunnel interface lives in, if not the host's.
+    pub network_namespace: Option<String>,
 }
 
 #[cfg(not(target_os = "android"))]
@@ -121,11 +132,16 @@ impl TunnelEvent {
                         .parse()
                         .expect("V6 Tunnel gateway IP not in valid format")
                 });
+                // The OpenVPN servers resolve DNS on the gateway.
+                let mut dns_servers = vec![IpAddr::V4(ipv4_gateway)];
+                dns_servers.extend(ipv6_gateway.map(IpAddr::V6));
                 Some(TunnelEvent::Up(TunnelMetadata {
                     interface,
                     ips,
                     ipv4_gateway,
                     ipv6_gateway,
+                    dns_servers,
+                    network_namespace: None,
                 }))
             }
             openvpn_plugin::EventType::RoutePredown => Some(TunnelEvent::Down),
@@ -160,7 +176,7 @@ impl TunnelMonitor {
         match tunnel_parameters {
             #[cfg(not(target_os = "android"))]
             TunnelParameters::OpenVpn(config) => {
-                Self::start_openvpn_tunnel(&config, log_file, resource_dir, on_event)
+                Self::start_openvpn_tunnel(&config, log_file, resource_dir, on_event, route_manager)
             }
             #[cfg(target_os = "android")]
             TunnelParameters::OpenVpn(_) => Err(Error::UnsupportedPlatform),
@@ -204,10 +220,23 @@ impl TunnelMonitor {
         log: Option<PathBuf>,
         resource_dir: &Path,
         on_event: L,
+        route_manager: &mut RouteManager,
     ) -> Result<Self>
     where
         L: Fn(TunnelEvent) + Send + Sync + 'static,
     {
+        // The routes pushed by the server are more general than these, so excluded destinations
+        // are reached through the default route rather than the tunnel.
+        let excluded_routes = config
+            .generic_options
+            .excluded_destinations
+            .iter()
+            .map(|network| RequiredRoute::new(*network, NetNode::DefaultNode))
+            .collect();
+        route_manager
+            .add_routes(excluded_routes)
+            .map_err(Error::ExcludedRoutesError)?;
+
         let monitor = openvpn::OpenVpnMonitor::start(on_event, config, log, resource_dir)?;
         Ok(TunnelMonitor {
             monitor: InternalTunnelMonitor::OpenVpn(monitor),
@@ -295,6 +324,26 @@ impl TunnelMonitor {
         self.monitor.close_handle()
     }
 
+    /// Returns a handle for reading traffic statistics of the tunnel. Only WireGuard tunnels
+    /// provide statistics.
+    pub fn stats_handle(&self) -> Option<wireguard::StatsHandle> {
+        match &self.monitor {
+            #[cfg(not(target_os = "android"))]
+            InternalTunnelMonitor::OpenVpn(_) => None,
+            InternalTunnelMonitor::Wireguard(tun) => Some(tun.stats_handle()),
+        }
+    }
+
+    /// Returns a handle for reconfiguring the tunnel while it's running. Only WireGuard tunnels
+    /// can be reconfigured.
+    pub fn config_handle(&self) -> Option<wireguard::ConfigHandle> {
+        match &self.monitor {
+            #[cfg(not(target_os = "android"))]
+            InternalTunnelMonitor::OpenVpn(_) => None,
+            InternalTunnelMonitor::Wireguard(tun) => Some(tun.config_handle()),
+        }
+    }
+
     /// Consumes the monitor and blocks until the tunnel exits or there is an error.
     pub fn wait(self) -> Result<()> {
         self.monitor.wait().map_err(Error::from)
@@ -5,7 +5,7 @@ use crate::{
         openvpn::{OpenVpnCommand, OpenVpnProcHandle},
         stoppable_process::StoppableProcess,
     },
-    proxy::{self, ProxyMonitor, ProxyResourceData},
+    proxy::{self, ProxyMonitor},
 };
 use std::{
     collections::HashMap,
@@ -172,18 +172,7 @@ impl OpenVpnMonitor<OpenVpnCommand> {
             }
         };
 
-        let log_dir: Option<PathBuf> = if let Some(ref log_path) = log_path {
-            Some(log_path.parent().expect("log_path has no parent").into())
-        } else {
-            None
-        };
-
-        let proxy_resources = proxy::ProxyResourceData {
-            resource_dir: resource_dir.to_path_buf(),
-            log_dir,
-        };
-
-        let proxy_monitor = Self::start_proxy(&params.proxy, &proxy_resources)?;
+        let proxy_monitor = Self::start_proxy(&params.proxy)?;
 
         let cmd = Self::create_openvpn_cmd(
             params,
@@ -391,11 +380,9 @@ impl<C: OpenVpnBuilder + 'static> OpenVpnMonitor<C> {
     /// Starts a proxy service, as applicable.
     fn start_proxy(
         proxy_settings: &Option<openvpn::ProxySettings>,
-        proxy_resources: &ProxyResourceData,
     ) -> Result<Option<Box<dyn ProxyMonitor>>> {
         if let Some(ref settings) = proxy_settings {
-            let proxy_monitor =
-                proxy::start_proxy(settings, proxy_resources).map_err(Error::StartProxyError)?;
+            let proxy_monitor = proxy::start_proxy(settings).map_err(Error::StartProxyError)?;
             return Ok(Some(proxy_monitor));
         }
         Ok(None)
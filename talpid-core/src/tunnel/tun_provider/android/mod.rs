@@ -1,7 +1,5 @@
-mod ipnetwork_sub;
-
-use self::ipnetwork_sub::IpNetworkSub;
 use super::TunConfig;
+use crate::ipnetwork_sub::IpNetworkSub;
 use ipnetwork::IpNetwork;
 use jnix::{
     jni::{
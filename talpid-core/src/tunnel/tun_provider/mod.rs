@@ -59,6 +59,11 @@ pub struct TunConfig {
     /// Maximum Transmission Unit in the tunnel.
     #[cfg_attr(target_os = "android", jnix(map = "|mtu| mtu as i32"))]
     pub mtu: u16,
+
+    /// Network namespace that the tunnel interface will be moved into. The interface is then left
+    /// unconfigured until it has been moved.
+    #[cfg(target_os = "linux")]
+    pub network_namespace: Option<String>,
 }
 
 #[cfg(target_os = "android")]
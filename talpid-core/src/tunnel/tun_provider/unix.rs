@@ -1,4 +1,6 @@
 use super::TunConfig;
+#[cfg(target_os = "linux")]
+use crate::netns::{self, NetworkNamespace};
 use crate::network_interface::{self, NetworkInterface, TunnelDevice};
 use std::{net::IpAddr, ops::Deref};
 
@@ -17,6 +19,16 @@ pub enum Error {
     /// Failure to set the tunnel device as up.
     #[error(display = "Failed to set the tunnel device as up")]
     SetUp(#[cause] network_interface::Error),
+
+    /// Failure to move the tunnel device into a network namespace.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to move the tunnel device into a network namespace")]
+    MoveToNetworkNamespace(#[cause] netns::Error),
+
+    /// Failure to configure the tunnel device inside a network namespace.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to configure the tunnel device inside a network namespace")]
+    ConfigureInNetworkNamespace(#[cause] netns::Error),
 }
 
 /// Factory of tunnel devices on Unix systems.
@@ -30,6 +42,12 @@ impl UnixTunProvider {
     pub fn get_tun(&mut self, config: TunConfig) -> Result<UnixTun, Error> {
         let mut tunnel_device = TunnelDevice::new().map_err(Error::CreateTunnelDevice)?;
 
+        // The configuration is lost when the device is moved, so it's applied in the namespace
+        #[cfg(target_os = "linux")]
+        if config.network_namespace.is_some() {
+            return Ok(UnixTun(tunnel_device));
+        }
+
         for ip in config.addresses.iter() {
             tunnel_device
                 .set_ip(*ip)
@@ -52,6 +70,31 @@ impl UnixTun {
     pub fn interface_name(&self) -> &str {
         self.get_name()
     }
+
+    /// Moves the device into a network namespace and assigns the addresses and MTU to it there.
+    /// The file descriptor and any sockets that were opened before remain in the current
+    /// namespace.
+    #[cfg(target_os = "linux")]
+    pub fn move_to_network_namespace(
+        &self,
+        namespace: &NetworkNamespace,
+        addresses: &[IpAddr],
+        mtu: u16,
+    ) -> Result<(), Error> {
+        let name = self.interface_name();
+        namespace
+            .move_link(name)
+            .map_err(Error::MoveToNetworkNamespace)?;
+
+        for ip in addresses {
+            namespace
+                .ip(&["addr", "add", &ip.to_string(), "dev", name])
+                .map_err(Error::ConfigureInNetworkNamespace)?;
+        }
+        namespace
+            .ip(&["link", "set", "dev", name, "mtu", &mtu.to_string(), "up"])
+            .map_err(Error::ConfigureInNetworkNamespace)
+    }
 }
 
 impl Deref for UnixTun {
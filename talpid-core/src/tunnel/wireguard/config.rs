@@ -1,11 +1,15 @@
+use crate::ipnetwork_sub::IpNetworkSub;
+use ipnetwork::IpNetwork;
 use std::{
     borrow::Cow,
+    collections::HashSet,
     ffi::CString,
-    net::{Ipv4Addr, Ipv6Addr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
 };
 use talpid_types::net::{wireguard, GenericTunnelOptions};
 
 /// Config required to set up a single WireGuard tunnel
+#[derive(Clone)]
 pub struct Config {
     /// Contains tunnel endpoint specific config
     pub tunnel: wireguard::TunnelConfig,
@@ -15,8 +19,20 @@ pub struct Config {
     pub ipv4_gateway: Ipv4Addr,
     /// IPv6 gateway
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// DNS servers to use inside the tunnel, in order of preference
+    pub dns_servers: Vec<IpAddr>,
     /// Maximum transmission unit for the tunnel
     pub mtu: u16,
+    /// Obfuscation to wrap the traffic to the peers in
+    pub obfuscation: Option<wireguard::ObfuscationType>,
+    /// Destination networks that are routed outside of the tunnel
+    pub excluded_destinations: Vec<IpNetwork>,
+    /// Firewall mark to set on the tunnel socket. Set when policy routing is used.
+    #[cfg(target_os = "linux")]
+    pub fwmark: Option<u32>,
+    /// Network namespace to move the tunnel interface into
+    #[cfg(target_os = "linux")]
+    pub network_namespace: Option<String>,
 }
 
 const DEFAULT_MTU: u16 = 1380;
@@ -41,10 +57,10 @@ impl Config {
     /// Constructs a Config from parameters
     pub fn from_parameters(params: &wireguard::TunnelParameters) -> Result<Config, Error> {
         let tunnel = params.connection.tunnel.clone();
-        let peer = vec![params.connection.peer.clone()];
+        let peers = params.connection.peers().cloned().collect();
         Self::new(
             tunnel,
-            peer,
+            peers,
             &params.connection,
             &params.options,
             &params.generic_options,
@@ -63,12 +79,21 @@ impl Config {
             return Err(Error::NoPeersSuppliedError);
         }
         let mtu = wg_options.mtu.unwrap_or(DEFAULT_MTU);
+        let excluded_destinations = &generic_options.excluded_destinations;
         for peer in &mut peers {
             peer.allowed_ips = peer
                 .allowed_ips
                 .iter()
                 .cloned()
                 .filter(|ip| ip.is_ipv4() || generic_options.enable_ipv6)
+                .flat_map(|ip| {
+                    let same_family = excluded_destinations
+                        .iter()
+                        .filter(move |excluded| excluded.is_ipv4() == ip.is_ipv4())
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    ip.sub_all(same_family)
+                })
                 .collect();
             if peer.allowed_ips.is_empty() {
                 return Err(Error::InvalidPeerIpError);
@@ -89,24 +114,103 @@ impl Config {
         } else {
             None
         };
+        let dns_servers = Self::dns_servers(connection_config, generic_options);
 
         Ok(Config {
             tunnel,
             peers,
             ipv4_gateway: connection_config.ipv4_gateway,
             ipv6_gateway,
+            dns_servers,
             mtu,
+            obfuscation: connection_config.obfuscation,
+            excluded_destinations: excluded_destinations.clone(),
+            #[cfg(target_os = "linux")]
+            fwmark: if wg_options.use_policy_routing {
+                Some(crate::routing::TUNNEL_FWMARK)
+            } else {
+                None
+            },
+            #[cfg(target_os = "linux")]
+            network_namespace: wg_options.network_namespace.clone(),
         })
     }
 
+    /// Returns the DNS servers of `connection_config` that the tunnel can reach.
+    fn dns_servers(
+        connection_config: &wireguard::ConnectionConfig,
+        generic_options: &GenericTunnelOptions,
+    ) -> Vec<IpAddr> {
+        let dns_servers: Vec<IpAddr> = connection_config
+            .get_dns_servers()
+            .into_iter()
+            .filter(|ip| ip.is_ipv4() || generic_options.enable_ipv6)
+            .collect();
+        // The firewall on Windows only allows DNS to one server per address family.
+        #[cfg(windows)]
+        let dns_servers: Vec<IpAddr> = {
+            let ipv4 = dns_servers.iter().find(|ip| ip.is_ipv4()).cloned();
+            let ipv6 = dns_servers.iter().find(|ip| ip.is_ipv6()).cloned();
+            ipv4.into_iter().chain(ipv6).collect()
+        };
+        dns_servers
+    }
+
+    /// Returns true if a running tunnel has to be restarted to use `new_config`. Otherwise, only
+    /// the private key and peers differ, and the tunnel can be reconfigured in place.
+    pub fn requires_restart(&self, new_config: &Config) -> bool {
+        // Routes are set up for the allowed IPs when the tunnel starts.
+        let allowed_ips = |config: &Config| -> HashSet<IpNetwork> {
+            config
+                .peers
+                .iter()
+                .flat_map(|peer| peer.allowed_ips.iter().cloned())
+                .collect()
+        };
+        #[cfg(target_os = "linux")]
+        {
+            if self.fwmark != new_config.fwmark
+                || self.network_namespace != new_config.network_namespace
+            {
+                return true;
+            }
+        }
+        // The obfuscation proxies are bound to the peer endpoints.
+        self.obfuscation.is_some()
+            || new_config.obfuscation.is_some()
+            || self.tunnel.addresses != new_config.tunnel.addresses
+            || self.ipv4_gateway != new_config.ipv4_gateway
+            || self.ipv6_gateway != new_config.ipv6_gateway
+            || self.dns_servers != new_config.dns_servers
+            || self.mtu != new_config.mtu
+            || self.excluded_destinations != new_config.excluded_destinations
+            || allowed_ips(self) != allowed_ips(new_config)
+    }
+
     /// Returns a CString with the appropriate config for WireGuard-go
-    // TODO: Consider outputting both overriding and additive configs
     pub fn to_userspace_format(&self) -> CString {
+        self.userspace_format(true)
+    }
+
+    /// Returns a CString that replaces the private key and peers of a running WireGuard-go
+    /// tunnel. Unlike [`Self::to_userspace_format`], it leaves the sockets of the tunnel alone.
+    pub fn to_userspace_reconfiguration_format(&self) -> CString {
+        self.userspace_format(false)
+    }
+
+    fn userspace_format(&self, bind_sockets: bool) -> CString {
         // the order of insertion matters, public key entry denotes a new peer entry
         let mut wg_conf = WgConfigBuffer::new();
-        wg_conf
-            .add("private_key", self.tunnel.private_key.to_bytes().as_ref())
-            .add("listen_port", "0");
+        wg_conf.add("private_key", self.tunnel.private_key.to_bytes().as_ref());
+
+        if bind_sockets {
+            wg_conf.add("listen_port", "0");
+
+            #[cfg(target_os = "linux")]
+            if let Some(fwmark) = self.fwmark {
+                wg_conf.add("fwmark", fwmark.to_string().as_str());
+            }
+        }
 
         wg_conf.add("replace_peers", "true");
 
@@ -115,6 +219,15 @@ impl Config {
                 .add("public_key", peer.public_key.as_bytes().as_ref())
                 .add("endpoint", peer.endpoint.to_string().as_str())
                 .add("replace_allowed_ips", "true");
+            if let Some(psk) = &peer.psk {
+                wg_conf.add("preshared_key", psk.as_bytes().as_ref());
+            }
+            if let Some(keepalive) = peer.persistent_keepalive {
+                wg_conf.add(
+                    "persistent_keepalive_interval",
+                    keepalive.to_string().as_str(),
+                );
+            }
             for addr in &peer.allowed_ips {
                 wg_conf.add("allowed_ip", addr.to_string().as_str());
             }
@@ -174,3 +287,159 @@ impl WgConfigBuffer {
         self.buf
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use talpid_types::net::wireguard::{PresharedKey, PrivateKey};
+
+    const PRESHARED_KEY: [u8; 32] = [
+        0x9d, 0xb4, 0x9b, 0x38, 0x46, 0x3f, 0x35, 0x2e, 0x17, 0x73, 0x51, 0x89, 0x5e, 0x8d, 0x49,
+        0x7e, 0xc0, 0xcf, 0x9e, 0xbe, 0x4a, 0x16, 0x46, 0x5c, 0x77, 0x41, 0x2a, 0x32, 0x04, 0xda,
+        0xfa, 0x84,
+    ];
+
+    fn peer(endpoint: &str, psk: Option<PresharedKey>) -> wireguard::PeerConfig {
+        wireguard::PeerConfig {
+            public_key: PrivateKey::new_from_random().public_key(),
+            allowed_ips: vec!["0.0.0.0/0".parse().unwrap()],
+            endpoint: endpoint.parse().unwrap(),
+            persistent_keepalive: None,
+            psk,
+        }
+    }
+
+    fn parameters(peers: Vec<wireguard::PeerConfig>) -> wireguard::TunnelParameters {
+        let mut peers = peers.into_iter();
+        wireguard::TunnelParameters {
+            connection: wireguard::ConnectionConfig {
+                tunnel: wireguard::TunnelConfig {
+                    private_key: PrivateKey::new_from_random(),
+                    addresses: vec!["10.64.0.2".parse().unwrap()],
+                },
+                peer: peers.next().unwrap(),
+                additional_peers: peers.collect(),
+                ipv4_gateway: "10.64.0.1".parse().unwrap(),
+                ipv6_gateway: None,
+                dns_servers: vec![],
+                obfuscation: None,
+            },
+            options: wireguard::TunnelOptions {
+                mtu: None,
+                automatic_rotation: None,
+                use_policy_routing: false,
+                network_namespace: None,
+            },
+            generic_options: GenericTunnelOptions {
+                enable_ipv6: false,
+                excluded_destinations: vec![],
+            },
+        }
+    }
+
+    /// Returns the lines of the userspace configuration that describe the peer at `endpoint`.
+    fn peer_section(config: &CString, endpoint: &str) -> Vec<String> {
+        let config = config.to_str().unwrap();
+        let mut sections = config.split("public_key=").skip(1);
+        sections
+            .find(|section| section.contains(&format!("\nendpoint={}\n", endpoint)))
+            .unwrap()
+            .lines()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_preshared_key_in_userspace_format() {
+        let config = Config::from_parameters(&parameters(vec![
+            peer("192.0.2.1:51820", Some(PresharedKey::from(PRESHARED_KEY))),
+            peer("192.0.2.2:51820", None),
+        ]))
+        .unwrap();
+        let expected_line = format!("preshared_key={}", hex::encode(PRESHARED_KEY));
+
+        for userspace_config in &[
+            config.to_userspace_format(),
+            config.to_userspace_reconfiguration_format(),
+        ] {
+            assert!(peer_section(userspace_config, "192.0.2.1:51820").contains(&expected_line));
+            assert!(!peer_section(userspace_config, "192.0.2.2:51820")
+                .iter()
+                .any(|line| line.starts_with("preshared_key=")));
+        }
+    }
+
+    #[test]
+    fn test_preshared_key_change_is_reconfigured_in_place() {
+        let old_config =
+            Config::from_parameters(&parameters(vec![peer("192.0.2.1:51820", None)])).unwrap();
+        let mut new_config = old_config.clone();
+        new_config.peers[0].psk = Some(PresharedKey::from(PRESHARED_KEY));
+
+        assert!(!old_config.requires_restart(&new_config));
+    }
+
+    fn config() -> Config {
+        Config::from_parameters(&parameters(vec![peer("192.0.2.1:51820", None)])).unwrap()
+    }
+
+    #[test]
+    fn test_same_config_is_reconfigured_in_place() {
+        let config = config();
+
+        assert!(!config.requires_restart(&config.clone()));
+    }
+
+    #[test]
+    fn test_key_and_peer_change_is_reconfigured_in_place() {
+        let old_config = config();
+        let new_config = Config::from_parameters(&parameters(vec![
+            peer("192.0.2.2:51820", None),
+            peer("192.0.2.3:51820", None),
+        ]))
+        .unwrap();
+
+        assert_ne!(
+            old_config.tunnel.private_key.to_bytes(),
+            new_config.tunnel.private_key.to_bytes()
+        );
+        assert!(!old_config.requires_restart(&new_config));
+    }
+
+    #[test]
+    fn test_interface_change_requires_restart() {
+        let old_config = config();
+        let changes: Vec<fn(&mut Config)> = vec![
+            |config| config.tunnel.addresses = vec!["10.64.0.3".parse().unwrap()],
+            |config| config.ipv4_gateway = "10.64.0.2".parse().unwrap(),
+            |config| config.ipv6_gateway = Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap()),
+            |config| config.dns_servers = vec!["192.0.2.53".parse().unwrap()],
+            |config| config.mtu = 1280,
+            |config| config.excluded_destinations = vec!["192.0.2.0/24".parse().unwrap()],
+            |config| config.peers[0].allowed_ips = vec!["10.0.0.0/8".parse().unwrap()],
+            |config| config.obfuscation = Some(wireguard::ObfuscationType::Udp2Tcp),
+            #[cfg(target_os = "linux")]
+            |config| config.fwmark = Some(0x6d6f6c65),
+            #[cfg(target_os = "linux")]
+            |config| config.network_namespace = Some("mullvad".to_string()),
+        ];
+
+        for (index, change) in changes.into_iter().enumerate() {
+            let mut new_config = old_config.clone();
+            change(&mut new_config);
+            assert!(
+                old_config.requires_restart(&new_config),
+                "change {} doesn't require a restart",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn test_obfuscated_tunnel_requires_restart() {
+        let mut old_config = config();
+        old_config.obfuscation = Some(wireguard::ObfuscationType::Udp2Tcp);
+
+        assert!(old_config.requires_restart(&old_config.clone()));
+    }
+}
@@ -92,6 +92,15 @@ impl ConnectivityMonitor {
         })
     }
 
+    /// Checks connectivity from within the given network namespace.
+    #[cfg(target_os = "linux")]
+    pub(super) fn set_network_namespace(
+        &mut self,
+        network_namespace: Option<crate::netns::NetworkNamespace>,
+    ) {
+        self.pinger.set_network_namespace(network_namespace);
+    }
+
     // checks if the tunnel has ever worked. Intended to check if a connection to a tunnel is
     // successfull at the start of a connection.
     pub(super) fn establish_connectivity(&mut self) -> Result<bool, Error> {
@@ -211,6 +220,12 @@ impl ConnState {
 
     /// Returns true if incoming traffic counters incremented
     pub fn update(&mut self, now: Instant, new_stats: Stats) -> bool {
+        // The counters start over when the peers of the tunnel are replaced
+        if new_stats.rx_bytes < self.stats().rx_bytes || new_stats.tx_bytes < self.stats().tx_bytes
+        {
+            self.set_stats(Stats::default());
+        }
+
         match self {
             ConnState::Connecting {
                 start,
@@ -261,6 +276,20 @@ impl ConnState {
             }
         }
     }
+    fn stats(&self) -> &Stats {
+        match self {
+            ConnState::Connecting { stats, .. } | ConnState::Connected { stats, .. } => stats,
+        }
+    }
+
+    fn set_stats(&mut self, new_stats: Stats) {
+        match self {
+            ConnState::Connecting { stats, .. } | ConnState::Connected { stats, .. } => {
+                *stats = new_stats
+            }
+        }
+    }
+
     // check if last time data was received is too long ago
     pub fn rx_timed_out(&self) -> bool {
         match self {
@@ -340,6 +369,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
 
@@ -363,6 +393,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
 
@@ -385,6 +416,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 0,
+                last_handshake_time: None,
             },
         );
 
@@ -394,6 +426,7 @@ mod test {
             Stats {
                 rx_bytes: 1,
                 tx_bytes: 1,
+                last_handshake_time: None,
             },
         );
 
@@ -401,4 +434,33 @@ mod test {
         assert!(conn_state.rx_timed_out());
         assert!(!conn_state.traffic_timed_out());
     }
+
+    /// Test if ConnState::Connected counts traffic after the counters start over, as they do when
+    /// the peers of the tunnel are replaced
+    #[test]
+    fn test_conn_state_counts_traffic_after_counter_reset() {
+        let start = Instant::now().checked_sub(Duration::from_secs(2)).unwrap();
+        let mut conn_state = ConnState::new(start, Default::default());
+        conn_state.update(
+            start,
+            Stats {
+                rx_bytes: 1000,
+                tx_bytes: 1000,
+                last_handshake_time: None,
+            },
+        );
+
+        let rx_incremented = conn_state.update(
+            Instant::now(),
+            Stats {
+                rx_bytes: 10,
+                tx_bytes: 10,
+                last_handshake_time: None,
+            },
+        );
+
+        assert!(rx_incremented);
+        assert!(conn_state.connected());
+        assert!(!conn_state.rx_timed_out());
+    }
 }
@@ -2,19 +2,30 @@ use self::config::Config;
 #[cfg(not(windows))]
 use super::tun_provider;
 use super::{tun_provider::TunProvider, TunnelEvent, TunnelMetadata};
-use crate::routing::{self, RequiredRoute};
+#[cfg(target_os = "linux")]
+use crate::netns::NetworkNamespace;
+use crate::{
+    proxy::{self, ProxyMonitor, ProxyMonitorCloseHandle},
+    routing::{self, RequiredRoute},
+};
 use std::{
     collections::HashSet,
     path::Path,
-    sync::{mpsc, Arc, Mutex},
+    sync::{mpsc, Arc, Mutex, Weak},
 };
 use talpid_types::ErrorExt;
+#[cfg(not(target_os = "android"))]
+use {
+    std::net::{Ipv4Addr, SocketAddr},
+    talpid_types::net::wireguard::ObfuscationType,
+};
 
 /// WireGuard config data-types
 pub mod config;
 mod connectivity_check;
 mod logging;
-mod stats;
+/// Traffic statistics of WireGuard tunnels
+pub mod stats;
 mod wireguard_go;
 
 use self::wireguard_go::WgGoTunnel;
@@ -39,6 +50,28 @@ pub enum Error {
     /// Failed to setup connectivity monitor
     #[error(display = "Connectivity monitor failed")]
     ConnectivityMonitorError(#[error(source)] connectivity_check::Error),
+
+    /// Failed to start the obfuscation proxy
+    #[error(display = "Failed to start obfuscation proxy")]
+    StartObfuscatorError(#[error(source)] std::io::Error),
+
+    /// The obfuscation proxy exited unexpectedly
+    #[error(display = "Obfuscation proxy exited unexpectedly: {}", _0)]
+    ObfuscatorExited(String),
+
+    /// Obfuscation is not supported on this platform
+    #[cfg(target_os = "android")]
+    #[error(display = "Obfuscation is not supported on this platform")]
+    ObfuscationNotSupported,
+
+    /// Failed to set up routes in the network namespace of the tunnel
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set up routes in the network namespace of the tunnel")]
+    NetworkNamespaceError(#[error(source)] crate::netns::Error),
+
+    /// The tunnel has already been closed
+    #[error(display = "The tunnel has been closed")]
+    TunnelClosedError,
 }
 
 
@@ -51,6 +84,8 @@ pub struct WireguardMonitor {
     close_msg_sender: mpsc::Sender<CloseMsg>,
     close_msg_receiver: mpsc::Receiver<CloseMsg>,
     pinger_stop_sender: mpsc::Sender<()>,
+    /// Close handles for the obfuscation proxies, one for each obfuscated peer
+    obfuscator_close_handles: Vec<Box<dyn ProxyMonitorCloseHandle>>,
 }
 
 impl WireguardMonitor {
@@ -62,16 +97,47 @@ impl WireguardMonitor {
         tun_provider: &mut TunProvider,
         route_manager: &mut routing::RouteManager,
     ) -> Result<WireguardMonitor> {
+        let (tunnel_config, mut obfuscators) = Self::start_obfuscators(config)?;
+        let obfuscator_close_handles = obfuscators
+            .iter_mut()
+            .map(|obfuscator| obfuscator.close_handle())
+            .collect();
+
         let tunnel = Box::new(WgGoTunnel::start_tunnel(
-            &config,
+            &tunnel_config,
             log_path,
             tun_provider,
             Self::get_tunnel_routes(config),
         )?);
         let iface_name = tunnel.get_interface_name().to_string();
-        route_manager
-            .add_routes(Self::get_routes(&iface_name, &config))
-            .map_err(Error::SetupRoutingError)?;
+
+        #[cfg(target_os = "linux")]
+        let network_namespace = match config.network_namespace {
+            Some(ref name) => {
+                let namespace =
+                    NetworkNamespace::new(name).map_err(Error::NetworkNamespaceError)?;
+                Self::add_network_namespace_routes(&namespace, &iface_name, &config)?;
+                Some(namespace)
+            }
+            None => None,
+        };
+        #[cfg(target_os = "linux")]
+        let use_host_routes = network_namespace.is_none();
+        #[cfg(not(target_os = "linux"))]
+        let use_host_routes = true;
+
+        if use_host_routes {
+            route_manager
+                .add_routes(Self::get_routes(&iface_name, &config))
+                .map_err(Error::SetupRoutingError)?;
+
+            #[cfg(target_os = "linux")]
+            if let Some(fwmark) = config.fwmark {
+                route_manager
+                    .enable_policy_routing(fwmark, routing::TUNNEL_TABLE_ID)
+                    .map_err(Error::SetupRoutingError)?;
+            }
+        }
 
         #[cfg(target_os = "windows")]
         route_manager
@@ -86,8 +152,22 @@ impl WireguardMonitor {
             close_msg_sender,
             close_msg_receiver,
             pinger_stop_sender: pinger_tx,
+            obfuscator_close_handles,
         };
 
+        for obfuscator in obfuscators {
+            let close_sender = monitor.close_msg_sender.clone();
+            std::thread::spawn(move || {
+                let reason = match obfuscator.wait() {
+                    Ok(proxy::WaitResult::ProperShutdown) => return,
+                    Ok(proxy::WaitResult::UnexpectedExit(reason)) => reason,
+                    Err(error) => error.to_string(),
+                };
+                log::error!("Obfuscation proxy exited unexpectedly: {}", reason);
+                let _ = close_sender.send(CloseMsg::ObfuscatorExit(reason));
+            });
+        }
+
         let metadata = Self::tunnel_metadata(&iface_name, &config);
         let gateway = config.ipv4_gateway;
         let close_sender = monitor.close_msg_sender.clone();
@@ -97,6 +177,8 @@ impl WireguardMonitor {
             Arc::downgrade(&monitor.tunnel),
             pinger_rx,
         )?;
+        #[cfg(target_os = "linux")]
+        connectivity_monitor.set_network_namespace(network_namespace);
 
         std::thread::spawn(move || {
             match connectivity_monitor.establish_connectivity() {
@@ -132,10 +214,25 @@ impl WireguardMonitor {
         }
     }
 
+    /// Returns a handle for reading traffic statistics of the tunnel
+    pub fn stats_handle(&self) -> StatsHandle {
+        StatsHandle {
+            tunnel: Arc::downgrade(&self.tunnel),
+        }
+    }
+
+    /// Returns a handle for reconfiguring the running tunnel
+    pub fn config_handle(&self) -> ConfigHandle {
+        ConfigHandle {
+            tunnel: Arc::downgrade(&self.tunnel),
+        }
+    }
+
     /// Blocks the current thread until tunnel disconnects
     pub fn wait(mut self) -> Result<()> {
         let wait_result = match self.close_msg_receiver.recv() {
             Ok(CloseMsg::PingErr) => Err(Error::TimeoutError),
+            Ok(CloseMsg::ObfuscatorExit(reason)) => Err(Error::ObfuscatorExited(reason)),
             Ok(CloseMsg::Stop) => Ok(()),
             Err(_) => Ok(()),
         };
@@ -143,6 +240,10 @@ impl WireguardMonitor {
         let _ = self.pinger_stop_sender.send(());
 
         self.stop_tunnel();
+        Self::close_obfuscators(std::mem::replace(
+            &mut self.obfuscator_close_handles,
+            Vec::new(),
+        ));
 
         (self.event_callback)(TunnelEvent::Down);
         wait_result
@@ -161,6 +262,46 @@ impl WireguardMonitor {
         }
     }
 
+    /// Starts an obfuscation proxy for each peer if the config specifies obfuscation. Returns
+    /// the config to hand to the tunnel, where such peers are replaced with their local proxy.
+    #[cfg(not(target_os = "android"))]
+    fn start_obfuscators(config: &Config) -> Result<(Config, Vec<Box<dyn ProxyMonitor>>)> {
+        let mut tunnel_config = config.clone();
+        let mut obfuscators = Vec::new();
+
+        if let Some(ObfuscationType::Udp2Tcp) = config.obfuscation {
+            for peer in &mut tunnel_config.peers {
+                log::info!("Tunneling WireGuard traffic to {} over TCP", peer.endpoint);
+                let obfuscator = proxy::start_udp2tcp_proxy(peer.endpoint)
+                    .map_err(Error::StartObfuscatorError)?;
+                peer.endpoint = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), obfuscator.port());
+                obfuscators.push(obfuscator);
+            }
+        }
+
+        Ok((tunnel_config, obfuscators))
+    }
+
+    /// The obfuscation proxy would have to bypass the tunnel, which is not implemented on Android.
+    #[cfg(target_os = "android")]
+    fn start_obfuscators(config: &Config) -> Result<(Config, Vec<Box<dyn ProxyMonitor>>)> {
+        if config.obfuscation.is_some() {
+            return Err(Error::ObfuscationNotSupported);
+        }
+        Ok((config.clone(), Vec::new()))
+    }
+
+    fn close_obfuscators(close_handles: Vec<Box<dyn ProxyMonitorCloseHandle>>) {
+        for close_handle in close_handles {
+            if let Err(error) = close_handle.close() {
+                log::error!(
+                    "{}",
+                    error.display_chain_with_msg("Failed to close obfuscation proxy")
+                );
+            }
+        }
+    }
+
     fn get_tunnel_routes(config: &Config) -> impl Iterator<Item = ipnetwork::IpNetwork> + '_ {
         config
             .peers
@@ -181,28 +322,112 @@ impl WireguardMonitor {
     }
 
     fn get_routes(iface_name: &str, config: &Config) -> HashSet<RequiredRoute> {
+        #[cfg(target_os = "linux")]
+        if config.fwmark.is_some() {
+            return Self::get_policy_routes(iface_name, config);
+        }
+
         let node = routing::Node::device(iface_name.to_string());
         let mut routes: HashSet<RequiredRoute> = Self::get_tunnel_routes(config)
             .map(|network| RequiredRoute::new(network, node.clone()))
+            .chain(Self::get_excluded_routes(config))
             .collect();
 
         // route endpoints with specific routes
-        for peer in config.peers.iter() {
-            routes.insert(RequiredRoute::new(
-                peer.endpoint.ip().into(),
-                routing::NetNode::DefaultNode,
-            ));
+        routes.extend(Self::get_endpoint_routes(config));
+
+        routes
+    }
+
+    /// Returns routes that send the traffic to the peers through the default route.
+    fn get_endpoint_routes(config: &Config) -> impl Iterator<Item = RequiredRoute> + '_ {
+        config.peers.iter().map(|peer| {
+            RequiredRoute::new(peer.endpoint.ip().into(), routing::NetNode::DefaultNode)
+        })
+    }
+
+    /// Returns the routes to the peers of `config` that [`WireguardMonitor::start`] adds.
+    fn get_peer_routes(config: &Config) -> HashSet<RequiredRoute> {
+        #[cfg(target_os = "linux")]
+        {
+            if config.network_namespace.is_some() {
+                return HashSet::new();
+            }
+            if config.fwmark.is_some() {
+                if config.obfuscation.is_none() {
+                    return HashSet::new();
+                }
+                return Self::get_endpoint_routes(config)
+                    .map(|route| route.table(routing::TUNNEL_TABLE_ID))
+                    .collect();
+            }
+        }
+        Self::get_endpoint_routes(config).collect()
+    }
+
+    /// Returns the routes to add to the tunnel table when policy routing is used. The tunnel socket
+    /// is marked, so it bypasses the table and needs no route to the relay. Traffic from an
+    /// obfuscation proxy isn't marked though, so relays behind one are routed through the default
+    /// route from within the tunnel table.
+    #[cfg(target_os = "linux")]
+    fn get_policy_routes(iface_name: &str, config: &Config) -> HashSet<RequiredRoute> {
+        let node = routing::Node::device(iface_name.to_string());
+        let mut routes: HashSet<RequiredRoute> = config
+            .peers
+            .iter()
+            .flat_map(|peer| peer.allowed_ips.iter())
+            .map(|network| {
+                RequiredRoute::new(*network, node.clone()).table(routing::TUNNEL_TABLE_ID)
+            })
+            .chain(Self::get_excluded_routes(config))
+            .collect();
+
+        if config.obfuscation.is_some() {
+            routes.extend(
+                Self::get_endpoint_routes(config)
+                    .map(|route| route.table(routing::TUNNEL_TABLE_ID)),
+            );
         }
 
         routes
     }
 
+    /// Routes the allowed IPs of all peers through the tunnel interface inside its network
+    /// namespace. The tunnel interface is the only way out of the namespace, so there is no route
+    /// to the relays or to excluded destinations there.
+    #[cfg(target_os = "linux")]
+    fn add_network_namespace_routes(
+        namespace: &NetworkNamespace,
+        iface_name: &str,
+        config: &Config,
+    ) -> Result<()> {
+        for network in config.peers.iter().flat_map(|peer| peer.allowed_ips.iter()) {
+            namespace
+                .ip(&["route", "add", &network.to_string(), "dev", iface_name])
+                .map_err(Error::NetworkNamespaceError)?;
+        }
+        Ok(())
+    }
+
+    /// Returns routes that send traffic to excluded destinations through the default route.
+    fn get_excluded_routes(config: &Config) -> impl Iterator<Item = RequiredRoute> + '_ {
+        config
+            .excluded_destinations
+            .iter()
+            .map(|network| RequiredRoute::new(*network, routing::NetNode::DefaultNode))
+    }
+
     fn tunnel_metadata(interface_name: &str, config: &Config) -> TunnelMetadata {
         TunnelMetadata {
             interface: interface_name.to_string(),
             ips: config.tunnel.addresses.clone(),
             ipv4_gateway: config.ipv4_gateway,
             ipv6_gateway: config.ipv6_gateway,
+            dns_servers: config.dns_servers.clone(),
+            #[cfg(target_os = "linux")]
+            network_namespace: config.network_namespace.clone(),
+            #[cfg(not(target_os = "linux"))]
+            network_namespace: None,
         }
     }
 }
@@ -210,6 +435,7 @@ impl WireguardMonitor {
 enum CloseMsg {
     Stop,
     PingErr,
+    ObfuscatorExit(String),
 }
 
 /// Close handle for a WireGuard tunnel.
@@ -227,10 +453,76 @@ impl CloseHandle {
     }
 }
 
+/// Handle for reading traffic statistics of a running WireGuard tunnel.
+#[derive(Clone)]
+pub struct StatsHandle {
+    tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+}
+
+impl StatsHandle {
+    /// Returns the current statistics, or `None` if the tunnel has been closed.
+    pub fn get(&self) -> Option<std::result::Result<stats::Stats, TunnelError>> {
+        self.tunnel
+            .upgrade()?
+            .lock()
+            .ok()?
+            .as_ref()
+            .map(|tunnel| tunnel.get_tunnel_stats())
+    }
+}
+
+/// Handle for reconfiguring a running WireGuard tunnel.
+#[derive(Clone)]
+pub struct ConfigHandle {
+    tunnel: Weak<Mutex<Option<Box<dyn Tunnel>>>>,
+}
+
+impl ConfigHandle {
+    /// Replaces the private key and peers of the running tunnel with those in `config`, without
+    /// bringing the tunnel down. The rest of `config` must match the running tunnel, see
+    /// [`Config::requires_restart`]. Routes to the new peer endpoints are added, the routes to the
+    /// previous ones are removed with [`ConfigHandle::remove_previous_peer_routes`].
+    pub fn set_config(
+        &self,
+        config: &Config,
+        route_manager: &mut routing::RouteManager,
+    ) -> Result<()> {
+        route_manager
+            .add_routes(WireguardMonitor::get_peer_routes(config))
+            .map_err(Error::SetupRoutingError)?;
+
+        let tunnel = self.tunnel.upgrade().ok_or(Error::TunnelClosedError)?;
+        let tunnel = tunnel.lock().expect("Tunnel lock poisoned");
+        tunnel
+            .as_ref()
+            .ok_or(Error::TunnelClosedError)?
+            .set_config(config)
+            .map_err(Error::TunnelError)
+    }
+
+    /// Removes the routes to the peers of `previous_config` that aren't peers of `config`.
+    pub fn remove_previous_peer_routes(
+        &self,
+        previous_config: &Config,
+        config: &Config,
+        route_manager: &mut routing::RouteManager,
+    ) -> Result<()> {
+        let routes = WireguardMonitor::get_peer_routes(config);
+        let previous_routes = WireguardMonitor::get_peer_routes(previous_config)
+            .into_iter()
+            .filter(|route| !routes.contains(route))
+            .collect();
+        route_manager
+            .remove_routes(previous_routes)
+            .map_err(Error::SetupRoutingError)
+    }
+}
+
 pub(crate) trait Tunnel: Send {
     fn get_interface_name(&self) -> &str;
     fn stop(self: Box<Self>) -> std::result::Result<(), TunnelError>;
     fn get_tunnel_stats(&self) -> std::result::Result<stats::Stats, TunnelError>;
+    fn set_config(&self, config: &Config) -> std::result::Result<(), TunnelError>;
 }
 
 /// Errors to be returned from WireGuard implementations, namely implementers of the Tunnel trait
@@ -267,6 +559,10 @@ pub enum TunnelError {
     #[error(display = "Failed to get config of WireGuard tunnel")]
     GetConfigError,
 
+    /// Error whilst trying to reconfigure a running WireGuard tunnel
+    #[error(display = "Failed to set config of WireGuard tunnel")]
+    SetConfigError,
+
     /// Failed to duplicate tunnel file descriptor for wireguard-go
     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "android"))]
     #[error(display = "Failed to duplicate tunnel file descriptor for wireguard-go")]
@@ -277,6 +573,11 @@ pub enum TunnelError {
     #[error(display = "Failed to create tunnel device")]
     SetupTunnelDeviceError(#[error(source)] tun_provider::Error),
 
+    /// Failed to create or open the network namespace for the tunnel device.
+    #[cfg(target_os = "linux")]
+    #[error(display = "Failed to set up network namespace for the tunnel device")]
+    NetworkNamespaceError(#[error(source)] crate::netns::Error),
+
     /// Failed to configure Wireguard sockets to bypass the tunnel.
     #[cfg(target_os = "android")]
     #[error(display = "Failed to configure Wireguard sockets to bypass the tunnel")]
@@ -1,3 +1,5 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
 #[derive(err_derive::Error, Debug, PartialEq)]
 pub enum Error {
     #[error(display = "Failed to parse integer from string \"_0\"")]
@@ -7,17 +9,22 @@ pub enum Error {
     KeyNotFoundError,
 }
 
-/// Contains bytes sent and received through a tunnel
+/// Contains bytes sent and received through a tunnel. When the tunnel has several peers, the
+/// counters are summed over all of them.
 #[derive(Default, Debug, PartialEq, Clone, Copy)]
 pub struct Stats {
     pub tx_bytes: u64,
     pub rx_bytes: u64,
+    /// Time of the most recent handshake with any peer, if there has been one.
+    pub last_handshake_time: Option<SystemTime>,
 }
 
 impl Stats {
     pub fn parse_config_str(config: &str) -> Result<Self, Error> {
         let mut tx_bytes = None;
         let mut rx_bytes = None;
+        let mut handshake_sec: u64 = 0;
+        let mut last_handshake_time = None;
 
         // parts iterates over keys and values
         let parts = config.split('\n').filter_map(|line| {
@@ -30,20 +37,25 @@ impl Stats {
         for (key, value) in parts {
             match key {
                 "rx_bytes" => {
-                    rx_bytes = Some(
-                        value
-                            .trim()
-                            .parse()
-                            .map_err(|err| Error::IntParseError(value.to_string(), err))?,
-                    );
+                    rx_bytes = Some(rx_bytes.unwrap_or(0) + parse_int::<u64>(value)?);
                 }
                 "tx_bytes" => {
-                    tx_bytes = Some(
-                        value
-                            .trim()
-                            .parse()
-                            .map_err(|err| Error::IntParseError(value.to_string(), err))?,
-                    );
+                    tx_bytes = Some(tx_bytes.unwrap_or(0) + parse_int::<u64>(value)?);
+                }
+                "last_handshake_time_sec" => {
+                    handshake_sec = parse_int(value)?;
+                }
+                "last_handshake_time_nsec" => {
+                    let handshake_nsec: u32 = parse_int(value)?;
+                    // A handshake time of zero means that no handshake has taken place yet
+                    if handshake_sec != 0 || handshake_nsec != 0 {
+                        let handshake_time =
+                            UNIX_EPOCH + Duration::new(handshake_sec, handshake_nsec);
+                        last_handshake_time = last_handshake_time
+                            .map(|time| std::cmp::max(time, handshake_time))
+                            .or(Some(handshake_time));
+                    }
+                    handshake_sec = 0;
                 }
 
                 _ => continue,
@@ -51,16 +63,28 @@ impl Stats {
         }
 
         match (tx_bytes, rx_bytes) {
-            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self { tx_bytes, rx_bytes }),
+            (Some(tx_bytes), Some(rx_bytes)) => Ok(Self {
+                tx_bytes,
+                rx_bytes,
+                last_handshake_time,
+            }),
             _ => Err(Error::KeyNotFoundError),
         }
     }
 }
 
+fn parse_int<T: std::str::FromStr<Err = std::num::ParseIntError>>(value: &str) -> Result<T, Error> {
+    value
+        .trim()
+        .parse()
+        .map_err(|err| Error::IntParseError(value.to_string(), err))
+}
+
 
 #[cfg(test)]
 mod test {
     use super::{Error, Stats};
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_parsing() {
@@ -69,6 +93,31 @@ mod test {
         let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
         assert_eq!(stats.rx_bytes, 2396);
         assert_eq!(stats.tx_bytes, 2740);
+        assert_eq!(
+            stats.last_handshake_time,
+            Some(UNIX_EPOCH + Duration::new(1578420649, 369416131))
+        );
+    }
+
+    #[test]
+    fn test_parsing_multiple_peers() {
+        let valid_input = "protocol_version=1\npublic_key=0000000000000000000000000000000000000000000000000000000000000000\nlast_handshake_time_sec=1578420649\nlast_handshake_time_nsec=369416131\ntx_bytes=2740\nrx_bytes=2396\npublic_key=1111111111111111111111111111111111111111111111111111111111111111\nlast_handshake_time_sec=1578420700\nlast_handshake_time_nsec=0\ntx_bytes=100\nrx_bytes=4\npublic_key=2222222222222222222222222222222222222222222222222222222222222222\nlast_handshake_time_sec=0\nlast_handshake_time_nsec=0\ntx_bytes=0\nrx_bytes=0\n";
+
+        let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
+        assert_eq!(stats.rx_bytes, 2400);
+        assert_eq!(stats.tx_bytes, 2840);
+        assert_eq!(
+            stats.last_handshake_time,
+            Some(UNIX_EPOCH + Duration::new(1578420700, 0))
+        );
+    }
+
+    #[test]
+    fn test_parsing_no_handshake() {
+        let valid_input = "protocol_version=1\nlast_handshake_time_sec=0\nlast_handshake_time_nsec=0\ntx_bytes=0\nrx_bytes=0\n";
+
+        let stats = Stats::parse_config_str(valid_input).expect("Failed to parse valid input");
+        assert_eq!(stats.last_handshake_time, None);
     }
 
     #[test]
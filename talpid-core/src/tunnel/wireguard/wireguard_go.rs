@@ -15,15 +15,15 @@ use zeroize::Zeroize;
 use std::ffi::CString;
 
 #[cfg(target_os = "android")]
-use crate::tunnel::tun_provider;
+use {crate::tunnel::tun_provider, std::net::IpAddr};
+
+#[cfg(target_os = "linux")]
+use crate::netns::NetworkNamespace;
 
 #[cfg(not(target_os = "windows"))]
 use {
     crate::tunnel::tun_provider::{Tun, TunConfig},
-    std::{
-        net::IpAddr,
-        os::unix::io::{AsRawFd, RawFd},
-    },
+    std::os::unix::io::{AsRawFd, RawFd},
 };
 
 type Result<T> = std::result::Result<T, TunnelError>;
@@ -103,12 +103,26 @@ impl WgGoTunnel {
         Self::bypass_tunnel_sockets(&mut tunnel_device, handle)
             .map_err(TunnelError::BypassError)?;
 
-        Ok(WgGoTunnel {
+        let tunnel = WgGoTunnel {
             interface_name,
             handle: Some(handle),
             _tunnel_device: tunnel_device,
             _logging_context: logging_context,
-        })
+        };
+
+        // wireguard-go has opened its sockets in the current namespace, and keeps using them
+        // after the device has been moved
+        #[cfg(target_os = "linux")]
+        if let Some(ref name) = config.network_namespace {
+            let namespace = NetworkNamespace::create_or_open(name)
+                .map_err(TunnelError::NetworkNamespaceError)?;
+            tunnel
+                ._tunnel_device
+                .move_to_network_namespace(&namespace, &config.tunnel.addresses, config.mtu)
+                .map_err(TunnelError::SetupTunnelDeviceError)?;
+        }
+
+        Ok(tunnel)
     }
 
     #[cfg(target_os = "windows")]
@@ -188,16 +202,15 @@ impl WgGoTunnel {
 
     #[cfg(not(target_os = "windows"))]
     fn create_tunnel_config(config: &Config, routes: impl Iterator<Item = IpNetwork>) -> TunConfig {
-        let mut dns_servers = vec![IpAddr::V4(config.ipv4_gateway)];
-        dns_servers.extend(config.ipv6_gateway.map(IpAddr::V6));
-
         TunConfig {
             addresses: config.tunnel.addresses.clone(),
-            dns_servers,
+            dns_servers: config.dns_servers.clone(),
             routes: routes.collect(),
             #[cfg(target_os = "android")]
             required_routes: Self::create_required_routes(config),
             mtu: config.mtu,
+            #[cfg(target_os = "linux")]
+            network_namespace: config.network_namespace.clone(),
         }
     }
 
@@ -209,6 +222,12 @@ impl WgGoTunnel {
         required_routes.extend(config.ipv6_gateway.map(|address| {
             IpNetwork::new(IpAddr::V6(address), 128).expect("Invalid IPv6 network prefix")
         }));
+        for dns_server in &config.dns_servers {
+            let network = IpNetwork::from(*dns_server);
+            if !required_routes.contains(&network) {
+                required_routes.push(network);
+            }
+        }
 
         required_routes
     }
@@ -307,6 +326,16 @@ impl Tunnel for WgGoTunnel {
         result
     }
 
+    fn set_config(&self, config: &Config) -> Result<()> {
+        let handle = self.handle.ok_or(TunnelError::SetConfigError)?;
+        let wg_config_str = config.to_userspace_reconfiguration_format();
+        let status = unsafe { wgSetConfig(handle, wg_config_str.as_ptr()) };
+        if status < 0 {
+            return Err(TunnelError::SetConfigError);
+        }
+        Ok(())
+    }
+
     fn stop(mut self: Box<Self>) -> Result<()> {
         self.stop_tunnel()
     }
@@ -361,6 +390,10 @@ extern "C" {
     // Returns the file descriptor of the tunnel IPv4 socket.
     fn wgGetConfig(handle: i32) -> *mut std::os::raw::c_char;
 
+    // Replaces the private key and peers of a running tunnel. Returns a negative value on
+    // failure.
+    fn wgSetConfig(handle: i32, settings: *const c_char) -> i32;
+
     // Frees a pointer allocated by the go runtime - useful to free return value of wgGetConfig
     fn wgFreePtr(ptr: *mut c_void);
 
@@ -1,18 +1,25 @@
 use super::{
-    AfterDisconnect, ConnectingState, DisconnectingState, ErrorState, EventConsequence,
-    SharedTunnelStateValues, TunnelCommand, TunnelState, TunnelStateTransition, TunnelStateWrapper,
+    tunnel_stats, AfterDisconnect, ConnectingState, DisconnectingState, ErrorState,
+    EventConsequence, SharedTunnelStateValues, Stats, TunnelCommand, TunnelState,
+    TunnelStateTransition, TunnelStateWrapper,
 };
+#[cfg(target_os = "linux")]
+use crate::gateway::Gateway;
+#[cfg(not(target_os = "android"))]
+use crate::proxy::{self, ProxyMonitor};
 use crate::{
     firewall::FirewallPolicy,
-    tunnel::{CloseHandle, TunnelEvent, TunnelMetadata},
+    tunnel::{wireguard, CloseHandle, TunnelEvent, TunnelMetadata},
 };
 use futures01::{
     sync::{mpsc, oneshot},
     Async, Future, Stream,
 };
+use ipnetwork::IpNetwork;
+use std::net::IpAddr;
 use talpid_types::{
     net::{Endpoint, TunnelParameters},
-    tunnel::ErrorStateCause,
+    tunnel::{ErrorStateCause, ParameterGenerationError},
     ErrorExt,
 };
 
@@ -22,6 +29,8 @@ pub struct ConnectedStateBootstrap {
     pub tunnel_parameters: TunnelParameters,
     pub tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     pub close_handle: Option<CloseHandle>,
+    pub stats_handle: Option<wireguard::StatsHandle>,
+    pub config_handle: Option<wireguard::ConfigHandle>,
 }
 
 /// The tunnel is up and working.
@@ -31,6 +40,14 @@ pub struct ConnectedState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     close_handle: Option<CloseHandle>,
+    stats_handle: Option<wireguard::StatsHandle>,
+    config_handle: Option<wireguard::ConfigHandle>,
+    /// Forwarding for the local network that the tunnel is shared with, if any.
+    #[cfg(target_os = "linux")]
+    gateway: Option<Gateway>,
+    /// Local SOCKS5 proxy whose connections go through the tunnel, if any.
+    #[cfg(not(target_os = "android"))]
+    tunnel_socks_proxy: Option<Box<dyn ProxyMonitor>>,
 }
 
 impl ConnectedState {
@@ -41,48 +58,147 @@ impl ConnectedState {
             tunnel_parameters: bootstrap.tunnel_parameters,
             tunnel_close_event: bootstrap.tunnel_close_event,
             close_handle: bootstrap.close_handle,
+            stats_handle: bootstrap.stats_handle,
+            config_handle: bootstrap.config_handle,
+            #[cfg(target_os = "linux")]
+            gateway: None,
+            #[cfg(not(target_os = "android"))]
+            tunnel_socks_proxy: None,
         }
     }
 
+    fn tunnel_stats(&self) -> Option<Stats> {
+        tunnel_stats(&self.stats_handle)
+    }
+
     fn set_firewall_policy(
         &self,
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), crate::firewall::Error> {
         // If a proxy is specified we need to pass it on as the peer endpoint.
-        let peer_endpoint = self.get_endpoint_from_params();
+        let peer_endpoints = self.get_endpoints_from_params();
+        self.apply_firewall_policy(shared_values, peer_endpoints)
+    }
 
+    fn apply_firewall_policy(
+        &self,
+        shared_values: &mut SharedTunnelStateValues,
+        peer_endpoints: Vec<Endpoint>,
+    ) -> Result<(), crate::firewall::Error> {
         let policy = FirewallPolicy::Connected {
-            peer_endpoint,
+            peer_endpoints,
             tunnel: self.metadata.clone(),
             allow_lan: shared_values.allow_lan,
+            allowed_endpoint: shared_values.allowed_endpoint,
+            excluded_destinations: self
+                .tunnel_parameters
+                .get_generic_options()
+                .excluded_destinations
+                .clone(),
         };
         shared_values.firewall.apply_policy(policy)
     }
 
-    fn get_endpoint_from_params(&self) -> Endpoint {
+    /// Returns an excluded destination that covers a gateway or DNS server of the tunnel. The
+    /// gateway of an OpenVPN tunnel is pushed by the server, so it can't be checked any earlier.
+    fn find_excluded_gateway(&self) -> Option<(IpNetwork, IpAddr)> {
+        let mut gateways = vec![IpAddr::from(self.metadata.ipv4_gateway)];
+        if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
+            gateways.push(IpAddr::from(ipv6_gateway));
+        }
+        gateways.extend(self.metadata.dns_servers.iter().cloned());
+        self.tunnel_parameters
+            .get_generic_options()
+            .find_excluded_destination(&gateways)
+    }
+
+    fn get_endpoints_from_params(&self) -> Vec<Endpoint> {
         match self.tunnel_parameters {
             TunnelParameters::OpenVpn(ref params) => match params.proxy {
-                Some(ref proxy_settings) => proxy_settings.get_endpoint().endpoint,
-                None => params.config.endpoint,
+                Some(ref proxy_settings) => vec![proxy_settings.get_endpoint().endpoint],
+                None => vec![params.config.endpoint],
             },
-            TunnelParameters::Wireguard(ref params) => params.connection.get_endpoint(),
+            TunnelParameters::Wireguard(ref params) => params.connection.get_endpoints(),
         }
     }
 
+    /// Returns the resolvers that DNS queries are sent to, in order of preference.
+    fn dns_servers(&self) -> Vec<IpAddr> {
+        self.metadata.dns_servers.clone()
+    }
+
     fn set_dns(
         &self,
         shared_values: &mut SharedTunnelStateValues,
     ) -> Result<(), crate::dns::Error> {
-        let mut dns_ips = vec![self.metadata.ipv4_gateway.into()];
-        if let Some(ipv6_gateway) = self.metadata.ipv6_gateway {
-            dns_ips.push(ipv6_gateway.into());
-        };
+        let dns_ips = self.dns_servers();
+
+        #[cfg(target_os = "linux")]
+        if let Some(ref namespace) = self.metadata.network_namespace {
+            return shared_values
+                .dns_monitor
+                .set_in_network_namespace(namespace, &dns_ips);
+        }
 
         shared_values
             .dns_monitor
             .set(&self.metadata.interface, &dns_ips)
     }
 
+    /// Starts forwarding traffic from the configured local network, replacing any previous
+    /// forwarding. Failing to do so is not fatal, since the firewall drops all forwarded traffic
+    /// unless it's allowed through the tunnel.
+    #[cfg(target_os = "linux")]
+    fn start_gateway(&mut self, shared_values: &SharedTunnelStateValues) {
+        self.gateway = None;
+
+        let options = match shared_values.gateway {
+            Some(ref options) => options,
+            None => return,
+        };
+        if self.metadata.network_namespace.is_some() {
+            log::warn!("Gateway mode is not supported for tunnels in a network namespace");
+            return;
+        }
+
+        match Gateway::start(options, &self.dns_servers()) {
+            Ok(gateway) => self.gateway = Some(gateway),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start gateway mode")
+            ),
+        }
+    }
+
+    /// Starts the local SOCKS5 proxy, replacing any previous one. A proxy that can't be started
+    /// only affects the applications using it, so the tunnel is kept up.
+    #[cfg(not(target_os = "android"))]
+    fn start_tunnel_socks_proxy(&mut self, shared_values: &SharedTunnelStateValues) {
+        self.tunnel_socks_proxy = None;
+
+        let settings = match shared_values.tunnel_socks_proxy {
+            Some(ref settings) => settings,
+            None => return,
+        };
+        #[cfg(target_os = "linux")]
+        if self.metadata.network_namespace.is_some() {
+            log::warn!("The SOCKS5 proxy is not supported for tunnels in a network namespace");
+            return;
+        }
+
+        match proxy::start_tunnel_socks_proxy(
+            settings,
+            &self.metadata.interface,
+            &self.metadata.ips,
+        ) {
+            Ok(monitor) => self.tunnel_socks_proxy = Some(monitor),
+            Err(error) => log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to start SOCKS5 proxy")
+            ),
+        }
+    }
+
     fn reset_dns(shared_values: &mut SharedTunnelStateValues) {
         if let Err(error) = shared_values.dns_monitor.reset() {
             log::error!("{}", error.display_chain_with_msg("Unable to reset DNS"));
@@ -112,7 +228,7 @@ impl ConnectedState {
     }
 
     fn handle_commands(
-        self,
+        #[cfg_attr(target_os = "android", allow(unused_mut))] mut self,
         commands: &mut mpsc::UnboundedReceiver<TunnelCommand>,
         shared_values: &mut SharedTunnelStateValues,
     ) -> EventConsequence<Self> {
@@ -140,6 +256,52 @@ impl ConnectedState {
                     }
                 }
             }
+            Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                if shared_values.allowed_endpoint != endpoint {
+                    shared_values.allowed_endpoint = endpoint;
+                    if let Err(error) = self.set_firewall_policy(shared_values) {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg(
+                                "Failed to apply firewall policy for connected state"
+                            )
+                        );
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+                        );
+                    }
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::Gateway(gateway)) => {
+                if shared_values.gateway != gateway {
+                    shared_values.set_gateway(gateway);
+                    if let Err(error) = self.set_firewall_policy(shared_values) {
+                        log::error!(
+                            "{}",
+                            error.display_chain_with_msg(
+                                "Failed to apply firewall policy for connected state"
+                            )
+                        );
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+                        );
+                    }
+                    #[cfg(target_os = "linux")]
+                    self.start_gateway(shared_values);
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                if shared_values.tunnel_socks_proxy != proxy {
+                    shared_values.tunnel_socks_proxy = proxy;
+                    #[cfg(not(target_os = "android"))]
+                    self.start_tunnel_socks_proxy(shared_values);
+                }
+                SameState(self)
+            }
             Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
@@ -158,15 +320,114 @@ impl ConnectedState {
             Ok(TunnelCommand::Connect) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
+            Ok(TunnelCommand::Reconfigure) => self.reconfigure_or_reconnect(shared_values),
             Ok(TunnelCommand::Disconnect) | Err(_) => {
                 self.disconnect(shared_values, AfterDisconnect::Nothing)
             }
             Ok(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                let _ = tx.send(shared_values.diagnostics());
+                SameState(self)
+            }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(self.tunnel_stats());
+                SameState(self)
+            }
         }
     }
 
+    /// Switches the running WireGuard tunnel over to new tunnel parameters in place when only the
+    /// key and peers change, so that traffic isn't blocked while reconnecting. The tunnel is
+    /// reconnected if anything else changes, or if reconfiguring it fails.
+    fn reconfigure_or_reconnect(
+        mut self,
+        shared_values: &mut SharedTunnelStateValues,
+    ) -> EventConsequence<Self> {
+        let (config_handle, current_config) = match (&self.config_handle, &self.tunnel_parameters) {
+            (Some(config_handle), TunnelParameters::Wireguard(params)) => {
+                match wireguard::config::Config::from_parameters(params) {
+                    Ok(config) => (config_handle.clone(), config),
+                    Err(_) => return self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
+                }
+            }
+            _ => return self.disconnect(shared_values, AfterDisconnect::Reconnect(0)),
+        };
+
+        let new_parameters = match shared_values.tunnel_parameters_generator.generate(0) {
+            Ok(TunnelParameters::Wireguard(params)) => params,
+            Ok(TunnelParameters::OpenVpn(_)) | Err(_) => {
+                return self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
+            }
+        };
+        let new_config = match wireguard::config::Config::from_parameters(&new_parameters) {
+            Ok(config) if !current_config.requires_restart(&config) => config,
+            _ => {
+                log::debug!("The tunnel interface changes, reconnecting");
+                return self.disconnect(shared_values, AfterDisconnect::Reconnect(0));
+            }
+        };
+
+        log::info!(
+            "Reconfiguring the tunnel to connect to {}",
+            new_parameters.connection.get_endpoint()
+        );
+
+        let (switching_endpoints, new_endpoints) = peer_endpoint_transition(
+            self.get_endpoints_from_params(),
+            new_parameters.connection.get_endpoints(),
+        );
+        if let Err(error) = self.apply_firewall_policy(shared_values, switching_endpoints) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to apply firewall policy for connected state")
+            );
+            return self.disconnect(
+                shared_values,
+                AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+            );
+        }
+
+        if let Err(error) = config_handle.set_config(&new_config, &mut shared_values.route_manager)
+        {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to reconfigure the tunnel, reconnecting")
+            );
+            return self.disconnect(shared_values, AfterDisconnect::Reconnect(0));
+        }
+        self.tunnel_parameters = new_parameters.into();
+
+        if let Err(error) = self.apply_firewall_policy(shared_values, new_endpoints) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to apply firewall policy for connected state")
+            );
+            return self.disconnect(
+                shared_values,
+                AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+            );
+        }
+
+        if let Err(error) = config_handle.remove_previous_peer_routes(
+            &current_config,
+            &new_config,
+            &mut shared_values.route_manager,
+        ) {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to remove routes to the previous peers")
+            );
+        }
+
+        let tunnel_endpoint = self.tunnel_parameters.get_tunnel_endpoint();
+        EventConsequence::NewState((
+            TunnelStateWrapper::from(self),
+            TunnelStateTransition::Connected(tunnel_endpoint),
+        ))
+    }
+
     fn handle_tunnel_events(
         mut self,
         shared_values: &mut SharedTunnelStateValues,
@@ -210,6 +471,22 @@ impl ConnectedState {
     }
 }
 
+/// Returns the peer endpoints to allow in the firewall while a tunnel switches over from the
+/// `current` to the `new` peers, and the ones to allow once it has switched over. Peers are only
+/// removed from the firewall after the tunnel stops using them.
+fn peer_endpoint_transition(
+    current: Vec<Endpoint>,
+    new: Vec<Endpoint>,
+) -> (Vec<Endpoint>, Vec<Endpoint>) {
+    let mut switching = current;
+    for endpoint in &new {
+        if !switching.contains(endpoint) {
+            switching.push(*endpoint);
+        }
+    }
+    (switching, new)
+}
+
 impl TunnelState for ConnectedState {
     type Bootstrap = ConnectedStateBootstrap;
 
@@ -218,10 +495,27 @@ impl TunnelState for ConnectedState {
         shared_values: &mut SharedTunnelStateValues,
         bootstrap: Self::Bootstrap,
     ) -> (TunnelStateWrapper, TunnelStateTransition) {
-        let connected_state = ConnectedState::from(bootstrap);
+        #[cfg_attr(target_os = "android", allow(unused_mut))]
+        let mut connected_state = ConnectedState::from(bootstrap);
         let tunnel_endpoint = connected_state.tunnel_parameters.get_tunnel_endpoint();
 
-        if let Err(error) = connected_state.set_firewall_policy(shared_values) {
+        if let Some((destination, gateway)) = connected_state.find_excluded_gateway() {
+            log::error!(
+                "The excluded destination {} overlaps the tunnel gateway {}",
+                destination,
+                gateway
+            );
+            DisconnectingState::enter(
+                shared_values,
+                (
+                    connected_state.close_handle,
+                    connected_state.tunnel_close_event,
+                    AfterDisconnect::Block(ErrorStateCause::TunnelParameterError(
+                        ParameterGenerationError::ExcludedDestinationOverlap,
+                    )),
+                ),
+            )
+        } else if let Err(error) = connected_state.set_firewall_policy(shared_values) {
             log::error!(
                 "{}",
                 error.display_chain_with_msg("Failed to apply firewall policy for connected state")
@@ -248,6 +542,11 @@ impl TunnelState for ConnectedState {
                 ),
             )
         } else {
+            #[cfg(target_os = "linux")]
+            connected_state.start_gateway(shared_values);
+            #[cfg(not(target_os = "android"))]
+            connected_state.start_tunnel_socks_proxy(shared_values);
+
             (
                 TunnelStateWrapper::from(connected_state),
                 TunnelStateTransition::Connected(tunnel_endpoint),
@@ -265,3 +564,45 @@ impl TunnelState for ConnectedState {
             .or_else(Self::handle_tunnel_close_event, shared_values)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::peer_endpoint_transition;
+    use std::net::Ipv4Addr;
+    use talpid_types::net::{Endpoint, TransportProtocol};
+
+    fn endpoint(last_octet: u8) -> Endpoint {
+        Endpoint::new(
+            Ipv4Addr::new(185, 65, 135, last_octet),
+            51820,
+            TransportProtocol::Udp,
+        )
+    }
+
+    #[test]
+    fn test_firewall_is_widened_before_it_is_narrowed() {
+        let (switching, switched) = peer_endpoint_transition(vec![endpoint(1)], vec![endpoint(2)]);
+
+        assert_eq!(switching, vec![endpoint(1), endpoint(2)]);
+        assert_eq!(switched, vec![endpoint(2)]);
+    }
+
+    #[test]
+    fn test_firewall_keeps_shared_peers() {
+        let (switching, switched) = peer_endpoint_transition(
+            vec![endpoint(1), endpoint(2)],
+            vec![endpoint(2), endpoint(3)],
+        );
+
+        assert_eq!(switching, vec![endpoint(1), endpoint(2), endpoint(3)]);
+        assert_eq!(switched, vec![endpoint(2), endpoint(3)]);
+    }
+
+    #[test]
+    fn test_firewall_unchanged_for_same_peers() {
+        let (switching, switched) = peer_endpoint_transition(vec![endpoint(1)], vec![endpoint(1)]);
+
+        assert_eq!(switching, vec![endpoint(1)]);
+        assert_eq!(switched, vec![endpoint(1)]);
+    }
+}
@@ -1,13 +1,14 @@
 use super::{
-    AfterDisconnect, ConnectedState, ConnectedStateBootstrap, DisconnectingState, ErrorState,
-    EventConsequence, SharedTunnelStateValues, TunnelCommand, TunnelState, TunnelStateTransition,
-    TunnelStateWrapper,
+    tunnel_stats, AfterDisconnect, ConnectedState, ConnectedStateBootstrap, DisconnectingState,
+    ErrorState, EventConsequence, SharedTunnelStateValues, Stats, TunnelCommand, TunnelState,
+    TunnelStateTransition, TunnelStateWrapper,
 };
 use crate::{
     firewall::FirewallPolicy,
     routing::RouteManager,
     tunnel::{
-        self, tun_provider::TunProvider, CloseHandle, TunnelEvent, TunnelMetadata, TunnelMonitor,
+        self, tun_provider::TunProvider, wireguard, CloseHandle, TunnelEvent, TunnelMetadata,
+        TunnelMonitor,
     },
 };
 use futures01::{
@@ -40,6 +41,8 @@ pub struct ConnectingState {
     tunnel_parameters: TunnelParameters,
     tunnel_close_event: Option<oneshot::Receiver<Option<ErrorStateCause>>>,
     close_handle: Option<CloseHandle>,
+    stats_handle: Option<wireguard::StatsHandle>,
+    config_handle: Option<wireguard::ConfigHandle>,
     retry_attempt: u32,
 }
 
@@ -49,17 +52,25 @@ impl ConnectingState {
         params: &TunnelParameters,
     ) -> Result<(), crate::firewall::Error> {
         let proxy = &get_openvpn_proxy_settings(&params);
-        let endpoint = params.get_tunnel_endpoint().endpoint;
-
-        let peer_endpoint = match proxy {
-            Some(proxy_settings) => proxy_settings.get_endpoint().endpoint,
-            None => endpoint,
+        let tunnel_endpoint = params.get_tunnel_endpoint();
+        let endpoint = tunnel_endpoint.endpoint;
+
+        #[cfg(target_os = "linux")]
+        shared_values
+            .firewall
+            .set_network_namespace(tunnel_endpoint.network_namespace.as_deref())?;
+
+        let peer_endpoints = match (proxy, params) {
+            (Some(proxy_settings), _) => vec![proxy_settings.get_endpoint().endpoint],
+            (None, TunnelParameters::Wireguard(params)) => params.connection.get_endpoints(),
+            (None, TunnelParameters::OpenVpn(_)) => vec![endpoint],
         };
 
         let policy = FirewallPolicy::Connecting {
-            peer_endpoint,
+            peer_endpoints,
             pingable_hosts: gateway_list_from_params(params),
             allow_lan: shared_values.allow_lan,
+            allowed_endpoint: shared_values.allowed_endpoint,
         };
         shared_values.firewall.apply_policy(policy)
     }
@@ -86,6 +97,8 @@ impl ConnectingState {
             route_manager,
         )?;
         let close_handle = Some(monitor.close_handle());
+        let stats_handle = monitor.stats_handle();
+        let config_handle = monitor.config_handle();
         let tunnel_close_event = Self::spawn_tunnel_monitor_wait_thread(monitor);
 
         Ok(ConnectingState {
@@ -93,6 +106,8 @@ impl ConnectingState {
             tunnel_parameters: parameters,
             tunnel_close_event,
             close_handle,
+            stats_handle,
+            config_handle,
             retry_attempt,
         })
     }
@@ -166,9 +181,15 @@ impl ConnectingState {
             tunnel_parameters: self.tunnel_parameters,
             tunnel_close_event: self.tunnel_close_event,
             close_handle: self.close_handle,
+            stats_handle: self.stats_handle,
+            config_handle: self.config_handle,
         }
     }
 
+    fn tunnel_stats(&self) -> Option<Stats> {
+        tunnel_stats(&self.stats_handle)
+    }
+
     fn reset_routes(shared_values: &mut SharedTunnelStateValues) {
         #[cfg(windows)]
         shared_values.route_manager.clear_default_route_callbacks();
@@ -220,6 +241,50 @@ impl ConnectingState {
                     }
                 }
             }
+            Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                if shared_values.allowed_endpoint != endpoint {
+                    shared_values.allowed_endpoint = endpoint;
+                    if let Err(error) =
+                        Self::set_firewall_policy(shared_values, &self.tunnel_parameters)
+                    {
+                        error!(
+                            "{}",
+                            error.display_chain_with_msg(
+                                "Failed to apply firewall policy for connecting state"
+                            )
+                        );
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+                        );
+                    }
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::Gateway(gateway)) => {
+                if shared_values.gateway != gateway {
+                    shared_values.set_gateway(gateway);
+                    if let Err(error) =
+                        Self::set_firewall_policy(shared_values, &self.tunnel_parameters)
+                    {
+                        error!(
+                            "{}",
+                            error.display_chain_with_msg(
+                                "Failed to apply firewall policy for connecting state"
+                            )
+                        );
+                        return self.disconnect(
+                            shared_values,
+                            AfterDisconnect::Block(ErrorStateCause::SetFirewallPolicyError),
+                        );
+                    }
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                shared_values.tunnel_socks_proxy = proxy;
+                SameState(self)
+            }
             Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
@@ -235,7 +300,7 @@ impl ConnectingState {
                     SameState(self)
                 }
             }
-            Ok(TunnelCommand::Connect) => {
+            Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
                 self.disconnect(shared_values, AfterDisconnect::Reconnect(0))
             }
             Ok(TunnelCommand::Disconnect) | Err(_) => {
@@ -244,6 +309,14 @@ impl ConnectingState {
             Ok(TunnelCommand::Block(reason)) => {
                 self.disconnect(shared_values, AfterDisconnect::Block(reason))
             }
+            Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                let _ = tx.send(shared_values.diagnostics());
+                SameState(self)
+            }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(self.tunnel_stats());
+                SameState(self)
+            }
         }
     }
 
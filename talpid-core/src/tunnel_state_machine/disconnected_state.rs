@@ -14,6 +14,7 @@ impl DisconnectedState {
         let result = if shared_values.block_when_disconnected {
             let policy = FirewallPolicy::Blocked {
                 allow_lan: shared_values.allow_lan,
+                allowed_endpoint: shared_values.allowed_endpoint,
             };
             shared_values.firewall.apply_policy(policy).map_err(|e| {
                 e.display_chain_with_msg(
@@ -69,6 +70,24 @@ impl TunnelState for DisconnectedState {
                 }
                 SameState(self)
             }
+            Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                if shared_values.allowed_endpoint != endpoint {
+                    shared_values.allowed_endpoint = endpoint;
+                    Self::set_firewall_policy(shared_values);
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::Gateway(gateway)) => {
+                if shared_values.gateway != gateway {
+                    shared_values.set_gateway(gateway);
+                    Self::set_firewall_policy(shared_values);
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                shared_values.tunnel_socks_proxy = proxy;
+                SameState(self)
+            }
             Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 if shared_values.block_when_disconnected != block_when_disconnected {
                     shared_values.block_when_disconnected = block_when_disconnected;
@@ -80,8 +99,18 @@ impl TunnelState for DisconnectedState {
                 shared_values.is_offline = is_offline;
                 SameState(self)
             }
-            Ok(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
+            Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
+                NewState(ConnectingState::enter(shared_values, 0))
+            }
             Ok(TunnelCommand::Block(reason)) => NewState(ErrorState::enter(shared_values, reason)),
+            Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                let _ = tx.send(shared_values.diagnostics());
+                SameState(self)
+            }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(None);
+                SameState(self)
+            }
             Ok(_) => SameState(self),
             Err(_) => Finished,
         }
@@ -35,15 +35,37 @@ impl DisconnectingState {
                     let _ = shared_values.set_allow_lan(allow_lan);
                     AfterDisconnect::Nothing
                 }
+                Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                    shared_values.allowed_endpoint = endpoint;
+                    AfterDisconnect::Nothing
+                }
+                Ok(TunnelCommand::Gateway(gateway)) => {
+                    shared_values.set_gateway(gateway);
+                    AfterDisconnect::Nothing
+                }
+                Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                    shared_values.tunnel_socks_proxy = proxy;
+                    AfterDisconnect::Nothing
+                }
                 Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Nothing
                 }
+                Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics());
+                    AfterDisconnect::Nothing
+                }
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Nothing
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     AfterDisconnect::Nothing
                 }
-                Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
+                Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
+                    AfterDisconnect::Reconnect(0)
+                }
                 Ok(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
                 _ => AfterDisconnect::Nothing,
             },
@@ -52,10 +74,30 @@ impl DisconnectingState {
                     let _ = shared_values.set_allow_lan(allow_lan);
                     AfterDisconnect::Block(reason)
                 }
+                Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                    shared_values.allowed_endpoint = endpoint;
+                    AfterDisconnect::Block(reason)
+                }
+                Ok(TunnelCommand::Gateway(gateway)) => {
+                    shared_values.set_gateway(gateway);
+                    AfterDisconnect::Block(reason)
+                }
+                Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                    shared_values.tunnel_socks_proxy = proxy;
+                    AfterDisconnect::Block(reason)
+                }
                 Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Block(reason)
                 }
+                Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics());
+                    AfterDisconnect::Block(reason)
+                }
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Block(reason)
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if !is_offline && reason == ErrorStateCause::IsOffline {
@@ -64,7 +106,9 @@ impl DisconnectingState {
                         AfterDisconnect::Block(reason)
                     }
                 }
-                Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(0),
+                Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
+                    AfterDisconnect::Reconnect(0)
+                }
                 Ok(TunnelCommand::Disconnect) => AfterDisconnect::Nothing,
                 Ok(TunnelCommand::Block(new_reason)) => AfterDisconnect::Block(new_reason),
                 Err(_) => AfterDisconnect::Block(reason),
@@ -74,10 +118,30 @@ impl DisconnectingState {
                     let _ = shared_values.set_allow_lan(allow_lan);
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                    shared_values.allowed_endpoint = endpoint;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Ok(TunnelCommand::Gateway(gateway)) => {
+                    shared_values.set_gateway(gateway);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                    shared_values.tunnel_socks_proxy = proxy;
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                     shared_values.block_when_disconnected = block_when_disconnected;
                     AfterDisconnect::Reconnect(retry_attempt)
                 }
+                Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                    let _ = tx.send(shared_values.diagnostics());
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
+                Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                    let _ = tx.send(None);
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Ok(TunnelCommand::IsOffline(is_offline)) => {
                     shared_values.is_offline = is_offline;
                     if is_offline {
@@ -86,7 +150,9 @@ impl DisconnectingState {
                         AfterDisconnect::Reconnect(retry_attempt)
                     }
                 }
-                Ok(TunnelCommand::Connect) => AfterDisconnect::Reconnect(retry_attempt),
+                Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
+                    AfterDisconnect::Reconnect(retry_attempt)
+                }
                 Ok(TunnelCommand::Disconnect) | Err(_) => AfterDisconnect::Nothing,
                 Ok(TunnelCommand::Block(reason)) => AfterDisconnect::Block(reason),
             },
@@ -19,6 +19,7 @@ impl ErrorState {
     fn set_firewall_policy(shared_values: &mut SharedTunnelStateValues) -> bool {
         let policy = FirewallPolicy::Blocked {
             allow_lan: shared_values.allow_lan,
+            allowed_endpoint: shared_values.allowed_endpoint,
         };
 
         match shared_values.firewall.apply_policy(policy) {
@@ -88,6 +89,24 @@ impl TunnelState for ErrorState {
                     SameState(self)
                 }
             }
+            Ok(TunnelCommand::AllowEndpoint(endpoint)) => {
+                if shared_values.allowed_endpoint != endpoint {
+                    shared_values.allowed_endpoint = endpoint;
+                    Self::set_firewall_policy(shared_values);
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::Gateway(gateway)) => {
+                if shared_values.gateway != gateway {
+                    shared_values.set_gateway(gateway);
+                    Self::set_firewall_policy(shared_values);
+                }
+                SameState(self)
+            }
+            Ok(TunnelCommand::TunnelSocksProxy(proxy)) => {
+                shared_values.tunnel_socks_proxy = proxy;
+                SameState(self)
+            }
             Ok(TunnelCommand::BlockWhenDisconnected(block_when_disconnected)) => {
                 shared_values.block_when_disconnected = block_when_disconnected;
                 SameState(self)
@@ -100,11 +119,21 @@ impl TunnelState for ErrorState {
                     SameState(self)
                 }
             }
-            Ok(TunnelCommand::Connect) => NewState(ConnectingState::enter(shared_values, 0)),
+            Ok(TunnelCommand::Connect) | Ok(TunnelCommand::Reconfigure) => {
+                NewState(ConnectingState::enter(shared_values, 0))
+            }
             Ok(TunnelCommand::Disconnect) | Err(_) => {
                 NewState(DisconnectedState::enter(shared_values, ()))
             }
             Ok(TunnelCommand::Block(reason)) => NewState(ErrorState::enter(shared_values, reason)),
+            Ok(TunnelCommand::GetDiagnostics(tx)) => {
+                let _ = tx.send(shared_values.diagnostics());
+                SameState(self)
+            }
+            Ok(TunnelCommand::GetTunnelStats(tx)) => {
+                let _ = tx.send(None);
+                SameState(self)
+            }
         }
     }
 }
@@ -20,7 +20,10 @@ use crate::{
     mpsc::Sender,
     offline,
     routing::RouteManager,
-    tunnel::tun_provider::TunProvider,
+    tunnel::{
+        tun_provider::TunProvider,
+        wireguard::{self, stats::Stats},
+    },
 };
 
 use futures01::{
@@ -37,7 +40,8 @@ use std::{
 #[cfg(target_os = "android")]
 use talpid_types::android::AndroidContext;
 use talpid_types::{
-    net::TunnelParameters,
+    diagnostics::{OfflineMonitorDiagnostics, TunnelDiagnostics},
+    net::{proxy::TunnelSocksProxy, Endpoint, GatewayOptions, TunnelParameters},
     tunnel::{ErrorStateCause, ParameterGenerationError, TunnelStateTransition},
     ErrorExt,
 };
@@ -75,6 +79,9 @@ pub enum Error {
 pub fn spawn(
     allow_lan: bool,
     block_when_disconnected: bool,
+    allowed_endpoint: Option<Endpoint>,
+    gateway: Option<GatewayOptions>,
+    tunnel_socks_proxy: Option<TunnelSocksProxy>,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     log_dir: Option<PathBuf>,
     resource_dir: PathBuf,
@@ -105,7 +112,11 @@ pub fn spawn(
         match create_event_loop(
             allow_lan,
             block_when_disconnected,
+            allowed_endpoint,
+            gateway,
+            tunnel_socks_proxy,
             is_offline,
+            offline_monitor,
             tunnel_parameters_generator,
             tun_provider,
             log_dir,
@@ -133,7 +144,6 @@ pub fn spawn(
                     .expect("Failed to send startup error");
             }
         }
-        std::mem::drop(offline_monitor);
     });
 
     startup_result_rx
@@ -145,7 +155,11 @@ pub fn spawn(
 fn create_event_loop(
     allow_lan: bool,
     block_when_disconnected: bool,
+    allowed_endpoint: Option<Endpoint>,
+    gateway: Option<GatewayOptions>,
+    tunnel_socks_proxy: Option<TunnelSocksProxy>,
     is_offline: bool,
+    offline_monitor: offline::MonitorHandle,
     tunnel_parameters_generator: impl TunnelParametersGenerator,
     tun_provider: TunProvider,
     log_dir: Option<PathBuf>,
@@ -159,7 +173,11 @@ fn create_event_loop(
     let state_machine = TunnelStateMachine::new(
         allow_lan,
         block_when_disconnected,
+        allowed_endpoint,
+        gateway,
+        tunnel_socks_proxy,
         is_offline,
+        offline_monitor,
         tunnel_parameters_generator,
         tun_provider,
         log_dir,
@@ -188,16 +206,45 @@ fn create_event_loop(
 pub enum TunnelCommand {
     /// Enable or disable LAN access in the firewall.
     AllowLan(bool),
+    /// Set an endpoint that the firewall should allow traffic to, such as an API proxy.
+    AllowEndpoint(Option<Endpoint>),
+    /// Share the tunnel with a local network while connected, or stop doing so. Only supported
+    /// on Linux.
+    Gateway(Option<GatewayOptions>),
+    /// Set the local SOCKS5 proxy that is run through the tunnel while connected, or unset it.
+    TunnelSocksProxy(Option<TunnelSocksProxy>),
     /// Enable or disable the block_when_disconnected feature.
     BlockWhenDisconnected(bool),
     /// Notify the state machine of the connectivity of the device.
     IsOffline(bool),
-    /// Open tunnel connection.
+    /// Open tunnel connection. An open tunnel is reconnected.
     Connect,
+    /// Apply changed tunnel parameters. A connected WireGuard tunnel is reconfigured in place if
+    /// only its key and peers change, otherwise this is the same as `Connect`.
+    Reconfigure,
     /// Close tunnel connection.
     Disconnect,
     /// Disconnect any open tunnel and block all network access
     Block(ErrorStateCause),
+    /// Collect diagnostics from the firewall, DNS, routing and offline monitor.
+    GetDiagnostics(oneshot::Sender<TunnelDiagnostics>),
+    /// Read the traffic statistics of the current tunnel. Only WireGuard tunnels have
+    /// statistics.
+    GetTunnelStats(oneshot::Sender<Option<Stats>>),
+}
+
+/// Reads the traffic statistics of a tunnel, if it provides any.
+fn tunnel_stats(stats_handle: &Option<wireguard::StatsHandle>) -> Option<Stats> {
+    match stats_handle.as_ref()?.get()? {
+        Ok(stats) => Some(stats),
+        Err(error) => {
+            log::error!(
+                "{}",
+                error.display_chain_with_msg("Failed to read tunnel statistics")
+            );
+            None
+        }
+    }
 }
 
 /// Asynchronous handling of the tunnel state machine.
@@ -216,7 +263,11 @@ impl TunnelStateMachine {
     fn new(
         allow_lan: bool,
         block_when_disconnected: bool,
+        allowed_endpoint: Option<Endpoint>,
+        gateway: Option<GatewayOptions>,
+        tunnel_socks_proxy: Option<TunnelSocksProxy>,
         is_offline: bool,
+        offline_monitor: offline::MonitorHandle,
         tunnel_parameters_generator: impl TunnelParametersGenerator,
         tun_provider: TunProvider,
         log_dir: Option<PathBuf>,
@@ -235,7 +286,10 @@ impl TunnelStateMachine {
                 allow_lan: None,
             }
         };
-        let firewall = Firewall::new(args).map_err(Error::InitFirewallError)?;
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut firewall = Firewall::new(args).map_err(Error::InitFirewallError)?;
+        #[cfg(target_os = "linux")]
+        firewall.set_gateway(gateway.clone());
         let dns_monitor = DnsMonitor::new(cache_dir).map_err(Error::InitDnsMonitorError)?;
         let route_manager =
             RouteManager::new(HashSet::new()).map_err(Error::InitRouteManagerError)?;
@@ -243,7 +297,11 @@ impl TunnelStateMachine {
             firewall,
             dns_monitor,
             route_manager,
+            offline_monitor,
             allow_lan,
+            allowed_endpoint,
+            gateway,
+            tunnel_socks_proxy,
             block_when_disconnected,
             is_offline,
             tunnel_parameters_generator: Box::new(tunnel_parameters_generator),
@@ -327,8 +385,15 @@ struct SharedTunnelStateValues {
     firewall: Firewall,
     dns_monitor: DnsMonitor,
     route_manager: RouteManager,
+    offline_monitor: offline::MonitorHandle,
     /// Should LAN access be allowed outside the tunnel.
     allow_lan: bool,
+    /// Endpoint that the firewall should allow traffic to in addition to the tunnel.
+    allowed_endpoint: Option<Endpoint>,
+    /// Local network that the tunnel is shared with while connected.
+    gateway: Option<GatewayOptions>,
+    /// Local SOCKS5 proxy that is run through the tunnel while connected.
+    tunnel_socks_proxy: Option<TunnelSocksProxy>,
     /// Should network access be allowed when in the disconnected state.
     block_when_disconnected: bool,
     /// True when the computer is known to be offline.
@@ -365,6 +430,53 @@ impl SharedTunnelStateValues {
 
         Ok(())
     }
+
+    /// Updates the gateway configuration. The firewall policy has to be reapplied by the caller
+    /// for the change to take effect.
+    pub fn set_gateway(&mut self, gateway: Option<GatewayOptions>) {
+        #[cfg(target_os = "linux")]
+        self.firewall.set_gateway(gateway.clone());
+        self.gateway = gateway;
+    }
+
+    /// Collects diagnostics from the subsystems. Failures are recorded in the report rather than
+    /// aborting the collection.
+    pub fn diagnostics(&mut self) -> TunnelDiagnostics {
+        let mut diagnostics = TunnelDiagnostics::default();
+
+        match self.firewall.diagnostics() {
+            Ok(firewall) => diagnostics.firewall = firewall,
+            Err(error) => diagnostics
+                .errors
+                .push(error.display_chain_with_msg("Failed to collect firewall diagnostics")),
+        }
+
+        diagnostics.dns = self.dns_monitor.diagnostics();
+
+        #[cfg(target_os = "linux")]
+        match self.route_manager.get_default_routes() {
+            Ok(default_routes) => diagnostics.routing.default_routes = Some(default_routes),
+            Err(error) => diagnostics
+                .errors
+                .push(error.display_chain_with_msg("Failed to get default routes")),
+        }
+
+        let links = match self.offline_monitor.links() {
+            Ok(links) => links,
+            Err(error) => {
+                diagnostics.errors.push(
+                    error.display_chain_with_msg("Failed to list links in the offline monitor"),
+                );
+                None
+            }
+        };
+        diagnostics.offline_monitor = OfflineMonitorDiagnostics {
+            is_offline: self.is_offline,
+            links,
+        };
+
+        diagnostics
+    }
 }
 
 /// Asynchronous result of an attempt to progress a state.
@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// Snapshot of the system integration managed by the tunnel state machine, used to debug issues
+/// without having to read the logs.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TunnelDiagnostics {
+    pub firewall: FirewallDiagnostics,
+    pub dns: DnsDiagnostics,
+    pub routing: RoutingDiagnostics,
+    pub offline_monitor: OfflineMonitorDiagnostics,
+    /// Errors encountered while collecting the diagnostics. The corresponding fields are left
+    /// empty.
+    pub errors: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FirewallDiagnostics {
+    /// Description of the firewall policy currently applied, if any.
+    pub active_policy: Option<String>,
+    /// Names of all netfilter tables present in the system. Only collected on Linux.
+    pub netfilter_tables: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DnsDiagnostics {
+    /// The system integration used to set DNS, if DNS is currently set.
+    pub backend: Option<String>,
+    /// The DNS servers currently set.
+    pub servers: Vec<IpAddr>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingDiagnostics {
+    /// Default routes currently seen by the route manager. Only collected on Linux.
+    pub default_routes: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OfflineMonitorDiagnostics {
+    /// Whether the device is considered to be offline.
+    pub is_offline: bool,
+    /// Network links seen by the offline monitor. Only collected on Linux.
+    pub links: Option<Vec<NetworkLink>>,
+}
+
+/// A network link as seen by the offline monitor.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NetworkLink {
+    pub index: u32,
+    pub name: String,
+    /// Whether the link is taken into account when deciding if the device is offline.
+    pub provides_connectivity: bool,
+    /// Whether any IP address is assigned to the link.
+    pub has_addresses: bool,
+}
@@ -28,11 +28,13 @@ impl TunnelParameters {
                 tunnel_type: TunnelType::OpenVpn,
                 endpoint: params.config.endpoint,
                 proxy: params.proxy.as_ref().map(|proxy| proxy.get_endpoint()),
+                network_namespace: None,
             },
             TunnelParameters::Wireguard(params) => TunnelEndpoint {
                 tunnel_type: TunnelType::Wireguard,
                 endpoint: params.connection.get_endpoint(),
                 proxy: None,
+                network_namespace: params.options.network_namespace.clone(),
             },
         }
     }
@@ -43,6 +45,39 @@ impl TunnelParameters {
             TunnelParameters::Wireguard(params) => &params.generic_options,
         }
     }
+
+    /// Returns the addresses of the relays, proxies, tunnel gateways and DNS servers used by the
+    /// tunnel. The gateway of an OpenVPN tunnel is pushed by the server and only known once it
+    /// is up.
+    pub fn get_relay_and_gateway_addresses(&self) -> Vec<IpAddr> {
+        match self {
+            TunnelParameters::OpenVpn(params) => {
+                let mut addresses = vec![params.config.endpoint.address.ip()];
+                if let Some(proxy) = &params.proxy {
+                    addresses.push(proxy.get_endpoint().endpoint.address.ip());
+                }
+                addresses
+            }
+            TunnelParameters::Wireguard(params) => {
+                let connection = &params.connection;
+                let mut addresses: Vec<IpAddr> =
+                    connection.peers().map(|peer| peer.endpoint.ip()).collect();
+                addresses.push(IpAddr::from(connection.ipv4_gateway));
+                if let Some(ipv6_gateway) = connection.ipv6_gateway {
+                    addresses.push(IpAddr::from(ipv6_gateway));
+                }
+                addresses.extend(connection.dns_servers.iter().cloned());
+                addresses
+            }
+        }
+    }
+
+    /// Returns an excluded destination that covers an address used by the tunnel, together with
+    /// that address.
+    pub fn find_excluded_destination_overlap(&self) -> Option<(ipnetwork::IpNetwork, IpAddr)> {
+        self.get_generic_options()
+            .find_excluded_destination(&self.get_relay_and_gateway_addresses())
+    }
 }
 
 impl From<wireguard::TunnelParameters> for TunnelParameters {
@@ -79,7 +114,7 @@ impl fmt::Display for TunnelType {
 
 /// A tunnel endpoint is broadcast during the connecting and connected states of the tunnel state
 /// machine.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[cfg_attr(target_os = "android", derive(IntoJava))]
 #[cfg_attr(target_os = "android", jnix(package = "net.mullvad.talpid.net"))]
 pub struct TunnelEndpoint {
@@ -89,6 +124,10 @@ pub struct TunnelEndpoint {
     pub tunnel_type: TunnelType,
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub proxy: Option<proxy::ProxyEndpoint>,
+    /// Network namespace that the tunnel interface lives in, if not the host's.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network_namespace: Option<String>,
 }
 
 impl fmt::Display for TunnelEndpoint {
@@ -101,6 +140,9 @@ impl fmt::Display for TunnelEndpoint {
                 proxy.proxy_type, proxy.endpoint.address, proxy.endpoint.protocol
             )?;
         }
+        if let Some(ref network_namespace) = self.network_namespace {
+            write!(f, " in network namespace {}", network_namespace)?;
+        }
         Ok(())
     }
 }
@@ -184,6 +226,50 @@ pub struct GenericTunnelOptions {
     /// Enable configuration of IPv6 on the tunnel interface, allowing IPv6 communication to be
     /// forwarded through the tunnel.
     pub enable_ipv6: bool,
+    /// Destination networks that should bypass the tunnel and be reached through the default
+    /// route instead.
+    #[serde(default)]
+    pub excluded_destinations: Vec<ipnetwork::IpNetwork>,
+}
+
+impl GenericTunnelOptions {
+    /// Returns the first excluded destination that contains any of `addresses`, together with
+    /// the address it contains.
+    pub fn find_excluded_destination(
+        &self,
+        addresses: &[IpAddr],
+    ) -> Option<(ipnetwork::IpNetwork, IpAddr)> {
+        self.excluded_destinations
+            .iter()
+            .filter_map(|destination| {
+                addresses
+                    .iter()
+                    .find(|address| destination.contains(**address))
+                    .map(|address| (*destination, *address))
+            })
+            .next()
+    }
+}
+
+/// Configuration for sharing the tunnel with the devices on a local network, by forwarding and
+/// masquerading their traffic through the tunnel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct GatewayOptions {
+    /// Name of the interface connected to the network whose traffic should be forwarded.
+    pub lan_interface: String,
+    /// Whether to answer DNS queries from the local network by forwarding them through the tunnel.
+    #[serde(default)]
+    pub dns_forwarder: bool,
+}
+
+impl fmt::Display for GatewayOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "forwarding traffic from {}", self.lan_interface)?;
+        if self.dns_forwarder {
+            write!(f, " with DNS forwarding")?;
+        }
+        Ok(())
+    }
 }
 
 /// Returns a vector of IP networks representing all of the internet, 0.0.0.0/0.
@@ -195,3 +281,149 @@ pub fn all_of_the_internet() -> Vec<ipnetwork::IpNetwork> {
         "::0/0".parse().expect("Failed to parse ipv6 network"),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(excluded_destinations: &[&str]) -> GenericTunnelOptions {
+        GenericTunnelOptions {
+            enable_ipv6: true,
+            excluded_destinations: excluded_destinations
+                .iter()
+                .map(|network| network.parse().unwrap())
+                .collect(),
+        }
+    }
+
+    fn addresses(addresses: &[&str]) -> Vec<IpAddr> {
+        addresses
+            .iter()
+            .map(|address| address.parse().unwrap())
+            .collect()
+    }
+
+    fn wireguard_parameters(excluded_destinations: &[&str]) -> TunnelParameters {
+        let peer = |endpoint: &str| wireguard::PeerConfig {
+            public_key: wireguard::PrivateKey::new_from_random().public_key(),
+            allowed_ips: vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+            endpoint: endpoint.parse().unwrap(),
+            persistent_keepalive: None,
+            psk: None,
+        };
+        TunnelParameters::Wireguard(wireguard::TunnelParameters {
+            connection: wireguard::ConnectionConfig {
+                tunnel: wireguard::TunnelConfig {
+                    private_key: wireguard::PrivateKey::new_from_random(),
+                    addresses: addresses(&["10.64.0.2", "fc00:bbbb:bbbb:bb01::2"]),
+                },
+                peer: peer("185.65.134.10:51820"),
+                additional_peers: vec![peer("[2a03:1b20:3:f011::a10]:51820")],
+                ipv4_gateway: "10.64.0.1".parse().unwrap(),
+                ipv6_gateway: Some("fc00:bbbb:bbbb:bb01::1".parse().unwrap()),
+                dns_servers: vec![],
+                obfuscation: None,
+            },
+            options: wireguard::TunnelOptions {
+                mtu: None,
+                automatic_rotation: None,
+                use_policy_routing: false,
+                network_namespace: None,
+            },
+            generic_options: options(excluded_destinations),
+        })
+    }
+
+    #[test]
+    fn test_excluded_network_contains_address() {
+        let used = addresses(&["185.65.134.10", "2a03:1b20:3:f011::a10"]);
+
+        let (network, address) = options(&["185.0.0.0/8"])
+            .find_excluded_destination(&used)
+            .unwrap();
+        assert_eq!(
+            network,
+            "185.0.0.0/8".parse::<ipnetwork::IpNetwork>().unwrap()
+        );
+        assert_eq!(address, used[0]);
+
+        let (network, address) = options(&["2a03:1b20::/32"])
+            .find_excluded_destination(&used)
+            .unwrap();
+        assert_eq!(
+            network,
+            "2a03:1b20::/32".parse::<ipnetwork::IpNetwork>().unwrap()
+        );
+        assert_eq!(address, used[1]);
+    }
+
+    #[test]
+    fn test_excluded_host_is_used_address() {
+        let used = addresses(&["185.65.134.10", "2a03:1b20:3:f011::a10"]);
+
+        assert!(options(&["185.65.134.10/32"])
+            .find_excluded_destination(&used)
+            .is_some());
+        assert!(options(&["2a03:1b20:3:f011::a10/128"])
+            .find_excluded_destination(&used)
+            .is_some());
+    }
+
+    #[test]
+    fn test_disjoint_excluded_destinations() {
+        let used = addresses(&["185.65.134.10", "2a03:1b20:3:f011::a10"]);
+
+        // Neighbouring networks and hosts
+        let excluded = options(&[
+            "185.65.135.0/24",
+            "185.65.134.11/32",
+            "2a03:1b20:3:f012::/64",
+            "2a03:1b20:3:f011::a11/128",
+        ]);
+        assert_eq!(excluded.find_excluded_destination(&used), None);
+        assert_eq!(options(&[]).find_excluded_destination(&used), None);
+    }
+
+    #[test]
+    fn test_excluded_destinations_of_other_family() {
+        // An IPv4-mapped IPv6 network does not cover the IPv4 address
+        let used = addresses(&["185.65.134.10"]);
+        assert_eq!(
+            options(&["::ffff:0:0/96", "::/0"]).find_excluded_destination(&used),
+            None
+        );
+
+        let used = addresses(&["2a03:1b20:3:f011::a10"]);
+        assert_eq!(
+            options(&["0.0.0.0/0"]).find_excluded_destination(&used),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tunnel_parameters_overlap() {
+        let overlap = |excluded: &[&str]| {
+            wireguard_parameters(excluded)
+                .find_excluded_destination_overlap()
+                .map(|(_, address)| address.to_string())
+        };
+
+        assert_eq!(overlap(&["192.168.0.0/16", "fd00::/8"]), None);
+        assert_eq!(
+            overlap(&["185.65.134.0/24"]),
+            Some("185.65.134.10".to_string())
+        );
+        // Additional peers and the gateways are checked as well
+        assert_eq!(
+            overlap(&["2a03:1b20:3::/48"]),
+            Some("2a03:1b20:3:f011::a10".to_string())
+        );
+        assert_eq!(overlap(&["10.64.0.0/10"]), Some("10.64.0.1".to_string()));
+        assert_eq!(
+            overlap(&["fc00::/7"]),
+            Some("fc00:bbbb:bbbb:bb01::1".to_string())
+        );
+        // The tunnel interface addresses are not used to reach anything outside the tunnel
+        assert_eq!(overlap(&["10.64.0.2/32"]), None);
+    }
+}
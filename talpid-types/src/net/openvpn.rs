@@ -3,7 +3,7 @@ use crate::net::{
     Endpoint, GenericTunnelOptions, TransportProtocol,
 };
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{collections::BTreeMap, fmt, net::SocketAddr, ops::RangeInclusive, str::FromStr};
 
 /// Information needed by `OpenVpnMonitor` to establish a tunnel connection.
 /// See [`crate::net::TunnelParameters`].
@@ -42,6 +42,160 @@ pub struct TunnelOptions {
     /// Optional argument for openvpn to try and limit TCP packet size,
     /// as discussed [here](https://openvpn.net/archive/openvpn-users/2003-11/msg00154.html)
     pub mssfix: Option<u16>,
+    /// Extra OpenVPN options and their values, which are passed to OpenVPN after the options
+    /// that are always set, overriding those.
+    #[serde(default)]
+    pub extra_options: BTreeMap<ExtraOption, u32>,
+}
+
+/// Seconds of inactivity after which a ping is sent, unless `--ping` is set as an extra option.
+pub const DEFAULT_PING: u32 = 4;
+/// Seconds without traffic after which OpenVPN exits, unless `--ping-exit` is set as an extra
+/// option.
+pub const DEFAULT_PING_EXIT: u32 = 25;
+
+impl TunnelOptions {
+    /// Returns the `--ping` and `--ping-exit` values that OpenVPN is started with.
+    pub fn ping_intervals(&self) -> (u32, u32) {
+        let ping = self.extra_options.get(&ExtraOption::Ping);
+        let ping_exit = self.extra_options.get(&ExtraOption::PingExit);
+        (
+            ping.copied().unwrap_or(DEFAULT_PING),
+            ping_exit.copied().unwrap_or(DEFAULT_PING_EXIT),
+        )
+    }
+
+    /// Checks that every extra option has an allowed value, and that they make sense together
+    /// with each other and with the defaults.
+    pub fn validate_extra_options(&self) -> Result<(), ExtraOptionError> {
+        for (option, value) in &self.extra_options {
+            option.validate(*value)?;
+        }
+        let (ping, ping_exit) = self.ping_intervals();
+        if ping >= ping_exit {
+            return Err(ExtraOptionError::PingNotBelowPingExit { ping, ping_exit });
+        }
+        Ok(())
+    }
+}
+
+/// OpenVPN options that may be set in addition to the ones the daemon passes to OpenVPN.
+///
+/// This is an allow-list. Options that can run scripts, load plugins, change routes or affect
+/// how credentials are handled, such as `--up` or `--script-security`, are never accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExtraOption {
+    /// Fragment UDP datagrams larger than this many bytes. Ignored for tunnels over TCP.
+    Fragment,
+    /// Size of the socket send buffer, in bytes.
+    Sndbuf,
+    /// Size of the socket receive buffer, in bytes.
+    Rcvbuf,
+    /// MTU of the tunnel device.
+    TunMtu,
+    /// Seconds of inactivity after which a ping is sent to the server.
+    Ping,
+    /// Seconds without receiving anything from the server after which OpenVPN exits.
+    PingExit,
+}
+
+impl ExtraOption {
+    /// All options that are allowed.
+    pub const ALL: &'static [ExtraOption] = &[
+        ExtraOption::Fragment,
+        ExtraOption::Sndbuf,
+        ExtraOption::Rcvbuf,
+        ExtraOption::TunMtu,
+        ExtraOption::Ping,
+        ExtraOption::PingExit,
+    ];
+
+    /// Returns the name of the option, without the leading dashes.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExtraOption::Fragment => "fragment",
+            ExtraOption::Sndbuf => "sndbuf",
+            ExtraOption::Rcvbuf => "rcvbuf",
+            ExtraOption::TunMtu => "tun-mtu",
+            ExtraOption::Ping => "ping",
+            ExtraOption::PingExit => "ping-exit",
+        }
+    }
+
+    /// Returns the range of values that are accepted for the option.
+    pub fn allowed_values(&self) -> RangeInclusive<u32> {
+        match self {
+            ExtraOption::Fragment => 576..=1500,
+            ExtraOption::Sndbuf | ExtraOption::Rcvbuf => 0..=16 * 1024 * 1024,
+            ExtraOption::TunMtu => 576..=9000,
+            ExtraOption::Ping => 1..=60,
+            ExtraOption::PingExit => 5..=300,
+        }
+    }
+
+    /// Checks that `value` is accepted for the option.
+    pub fn validate(&self, value: u32) -> Result<(), ExtraOptionError> {
+        let allowed_values = self.allowed_values();
+        if allowed_values.contains(&value) {
+            Ok(())
+        } else {
+            Err(ExtraOptionError::OutOfRange {
+                option: *self,
+                value,
+                min: *allowed_values.start(),
+                max: *allowed_values.end(),
+            })
+        }
+    }
+}
+
+impl fmt::Display for ExtraOption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "--{}", self.name())
+    }
+}
+
+impl FromStr for ExtraOption {
+    type Err = ExtraOptionError;
+
+    /// Parses an option name, with or without the leading dashes.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let trimmed_name = name.trim_start_matches('-');
+        ExtraOption::ALL
+            .iter()
+            .find(|option| option.name() == trimmed_name)
+            .copied()
+            .ok_or_else(|| ExtraOptionError::NotAllowed(trimmed_name.to_owned()))
+    }
+}
+
+/// Errors for extra OpenVPN options that are refused.
+#[derive(err_derive::Error, Debug, Clone, PartialEq, Eq)]
+pub enum ExtraOptionError {
+    #[error(display = "The OpenVPN option --{} is not allowed", _0)]
+    NotAllowed(String),
+
+    #[error(
+        display = "{} is not a valid value for {}, it must be between {} and {}",
+        value,
+        option,
+        min,
+        max
+    )]
+    OutOfRange {
+        option: ExtraOption,
+        value: u32,
+        min: u32,
+        max: u32,
+    },
+
+    #[error(
+        display = "--ping ({}) must be less than --ping-exit ({}), or the tunnel would time out",
+        ping,
+        ping_exit
+    )]
+    PingNotBelowPingExit { ping: u32, ping_exit: u32 },
 }
 
 /// Proxy server options to be used by `OpenVpnMonitor` when starting a tunnel.
@@ -89,7 +243,8 @@ impl LocalProxySettings {
     }
 }
 
-/// Options for a generic proxy running on remote host.
+/// Options for a generic SOCKS5 proxy running on remote host, with optional username and
+/// password authentication.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize)]
 pub struct RemoteProxySettings {
     pub address: SocketAddr,
@@ -132,27 +287,10 @@ impl ShadowsocksProxySettings {
 /// List of ciphers usable by a Shadowsocks proxy.
 /// Cf. [`ShadowsocksProxySettings::cipher`].
 pub static SHADOWSOCKS_CIPHERS: &[&str] = &[
-    // Stream ciphers.
-    "aes-128-cfb",
-    "aes-128-cfb1",
-    "aes-128-cfb8",
-    "aes-128-cfb128",
-    "aes-256-cfb",
-    "aes-256-cfb1",
-    "aes-256-cfb8",
-    "aes-256-cfb128",
-    "rc4",
-    "rc4-md5",
-    "chacha20",
-    "salsa20",
-    "chacha20-ietf",
     // AEAD ciphers.
     "aes-128-gcm",
     "aes-256-gcm",
     "chacha20-ietf-poly1305",
-    "xchacha20-ietf-poly1305",
-    "aes-128-pmac-siv",
-    "aes-256-pmac-siv",
 ];
 
 /// Checks whether the proxy settings to be used by `OpenVpnMonitor` are valid.
@@ -1,4 +1,4 @@
-use crate::net::Endpoint;
+use crate::net::{openvpn::ProxyAuth, Endpoint};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 
@@ -28,3 +28,24 @@ pub struct ProxyEndpoint {
     pub endpoint: Endpoint,
     pub proxy_type: ProxyType,
 }
+
+/// Local SOCKS5 server, bound to localhost, whose outgoing connections are made through the
+/// tunnel interface. It only accepts connections while the tunnel is up.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TunnelSocksProxy {
+    /// Port to listen on.
+    pub port: u16,
+    /// Credentials that clients have to authenticate with, if any.
+    #[serde(default)]
+    pub auth: Option<ProxyAuth>,
+}
+
+impl fmt::Display for TunnelSocksProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SOCKS5 proxy on localhost port {}", self.port)?;
+        if let Some(ref auth) = self.auth {
+            write!(f, " for user {}", auth.username)?;
+        }
+        Ok(())
+    }
+}
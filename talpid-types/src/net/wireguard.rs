@@ -25,16 +25,79 @@ pub struct TunnelParameters {
 pub struct ConnectionConfig {
     pub tunnel: TunnelConfig,
     pub peer: PeerConfig,
+    /// Peers in addition to `peer`, each reachable at its own endpoint. Only used by custom
+    /// tunnels.
+    #[serde(default)]
+    pub additional_peers: Vec<PeerConfig>,
     /// Gateway used by the tunnel (a private address).
     pub ipv4_gateway: Ipv4Addr,
     pub ipv6_gateway: Option<Ipv6Addr>,
+    /// DNS servers to use instead of the gateways. Only set for custom tunnels whose DNS
+    /// servers are not the gateway.
+    #[serde(default)]
+    pub dns_servers: Vec<IpAddr>,
+    /// Obfuscation to wrap the WireGuard traffic in. The peer endpoint is then the address of
+    /// the relay-side obfuscation service rather than of the WireGuard server itself.
+    #[serde(default)]
+    pub obfuscation: Option<ObfuscationType>,
 }
 
 impl ConnectionConfig {
+    /// Returns the endpoint that traffic is sent to when leaving the device.
     pub fn get_endpoint(&self) -> Endpoint {
         Endpoint {
             address: self.peer.endpoint,
-            protocol: TransportProtocol::Udp,
+            protocol: self.transport_protocol(),
+        }
+    }
+
+    /// Returns the endpoints of all peers, starting with the primary peer.
+    pub fn get_endpoints(&self) -> Vec<Endpoint> {
+        let protocol = self.transport_protocol();
+        self.peers()
+            .map(|peer| Endpoint {
+                address: peer.endpoint,
+                protocol,
+            })
+            .collect()
+    }
+
+    /// Returns the DNS servers of the tunnel, in order of preference. These are the gateways
+    /// unless `dns_servers` is set.
+    pub fn get_dns_servers(&self) -> Vec<IpAddr> {
+        if !self.dns_servers.is_empty() {
+            return self.dns_servers.clone();
+        }
+        let mut dns_servers = vec![IpAddr::from(self.ipv4_gateway)];
+        dns_servers.extend(self.ipv6_gateway.map(IpAddr::from));
+        dns_servers
+    }
+
+    /// Returns all peers, starting with the primary peer.
+    pub fn peers(&self) -> impl Iterator<Item = &PeerConfig> {
+        std::iter::once(&self.peer).chain(self.additional_peers.iter())
+    }
+
+    fn transport_protocol(&self) -> TransportProtocol {
+        match self.obfuscation {
+            Some(ObfuscationType::Udp2Tcp) => TransportProtocol::Tcp,
+            None => TransportProtocol::Udp,
+        }
+    }
+}
+
+/// Protocols that can be used to obfuscate WireGuard traffic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ObfuscationType {
+    /// Tunnels the WireGuard UDP datagrams over a TCP stream.
+    Udp2Tcp,
+}
+
+impl fmt::Display for ObfuscationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObfuscationType::Udp2Tcp => write!(f, "UDP-over-TCP"),
         }
     }
 }
@@ -47,6 +110,13 @@ pub struct PeerConfig {
     pub allowed_ips: Vec<IpNetwork>,
     /// IP address of the WireGuard server.
     pub endpoint: SocketAddr,
+    /// Interval, in seconds, at which to send keepalive packets to the peer. Disabled if unset.
+    #[serde(default)]
+    pub persistent_keepalive: Option<u16>,
+    /// Symmetric key mixed into the handshake with the peer, in addition to the key pairs. Only
+    /// custom tunnels have one, since the relays don't support negotiating a preshared key.
+    #[serde(default)]
+    pub psk: Option<PresharedKey>,
 }
 
 #[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
@@ -73,6 +143,18 @@ pub struct TunnelOptions {
     /// Interval used for automatic key rotation, in hours
     #[cfg_attr(target_os = "android", jnix(skip))]
     pub automatic_rotation: Option<u32>,
+    /// Route tunnel traffic through a dedicated routing table, selected by routing policy rules
+    /// that match on a firewall mark set on the tunnel socket, instead of adding routes to the
+    /// main table. Only used on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub use_policy_routing: bool,
+    /// Name of a network namespace to move the tunnel interface into. When set, routes, DNS and
+    /// firewall rules are only applied inside that namespace and the host is left untouched.
+    /// Only used on Linux.
+    #[cfg_attr(target_os = "android", jnix(skip))]
+    #[serde(default)]
+    pub network_namespace: Option<String>,
 }
 
 /// Wireguard x25519 private key
@@ -211,6 +293,54 @@ impl fmt::Display for PublicKey {
     }
 }
 
+/// Wireguard symmetric preshared key
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PresharedKey([u8; 32]);
+
+impl PresharedKey {
+    /// Get the preshared key as bytes
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl From<[u8; 32]> for PresharedKey {
+    fn from(key: [u8; 32]) -> PresharedKey {
+        PresharedKey(key)
+    }
+}
+
+impl Serialize for PresharedKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_key(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for PresharedKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserialize_key(deserializer)
+    }
+}
+
+// Preshared keys are secret, so they are left out of logged tunnel parameters.
+impl fmt::Debug for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl fmt::Display for PresharedKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", &base64::encode(&self.0))
+    }
+}
+
 fn serialize_key<S>(key: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
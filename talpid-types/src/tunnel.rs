@@ -109,6 +109,12 @@ pub enum ParameterGenerationError {
     /// Failure to resolve the hostname of a custom tunnel configuration
     #[error(display = "Can't resolve hostname for custom tunnel host")]
     CustomTunnelHostResultionError,
+    /// The maximum number of consecutive connection attempts has been reached.
+    #[error(display = "Maximum number of connection attempts reached")]
+    RetryAttemptsExhausted,
+    /// An excluded destination covers a relay, bridge or tunnel gateway used by the tunnel.
+    #[error(display = "An excluded destination overlaps an address used by the tunnel")]
+    ExcludedDestinationOverlap,
 }
 
 impl fmt::Display for ErrorStateCause {
@@ -8,6 +8,7 @@
 #include "rules/baseline/permitdhcp.h"
 #include "rules/baseline/permitndp.h"
 #include "rules/baseline/permitdhcpserver.h"
+#include "rules/baseline/permitendpoint.h"
 #include "rules/baseline/permitlan.h"
 #include "rules/baseline/permitlanservice.h"
 #include "rules/baseline/permitloopback.h"
@@ -99,6 +100,39 @@ void AppendNetBlockedRules(FwContext::Ruleset &ruleset)
 	ruleset.emplace_back(std::make_unique<baseline::PermitLoopback>());
 }
 
+baseline::PermitEndpoint::Protocol TranslateEndpointProtocol(WinFwProtocol protocol)
+{
+	switch (protocol)
+	{
+		case Tcp: return baseline::PermitEndpoint::Protocol::Tcp;
+		case Udp: return baseline::PermitEndpoint::Protocol::Udp;
+		default:
+		{
+			THROW_ERROR("Missing case handler in switch clause");
+		}
+	};
+}
+
+void AppendAllowedEndpointRules
+(
+	FwContext::Ruleset &ruleset,
+	const std::optional<WinFwEndpoint> &allowedEndpoint
+)
+{
+	if (false == allowedEndpoint.has_value())
+	{
+		return;
+	}
+
+	const auto &endpoint = allowedEndpoint.value();
+
+	ruleset.emplace_back(std::make_unique<baseline::PermitEndpoint>(
+		wfp::IpAddress(endpoint.ip),
+		endpoint.port,
+		TranslateEndpointProtocol(endpoint.protocol)
+	));
+}
+
 std::optional<std::vector<wfp::IpAddress> >
 CreateRelayDnsExclusion(const WinFwRelay &relay)
 {
@@ -160,6 +194,7 @@ bool FwContext::applyPolicyConnecting
 (
 	const WinFwSettings &settings,
 	const WinFwRelay &relay,
+	const std::optional<WinFwEndpoint> &allowedEndpoint,
 	const std::optional<PingableHosts> &pingableHosts
 )
 {
@@ -174,6 +209,8 @@ bool FwContext::applyPolicyConnecting
 		TranslateProtocol(relay.protocol)
 	));
 
+	AppendAllowedEndpointRules(ruleset, allowedEndpoint);
+
 	//
 	// Permit pinging the gateway inside the tunnel.
 	//
@@ -201,6 +238,7 @@ bool FwContext::applyPolicyConnected
 (
 	const WinFwSettings &settings,
 	const WinFwRelay &relay,
+	const std::optional<WinFwEndpoint> &allowedEndpoint,
 	const std::wstring &tunnelInterfaceAlias,
 	const std::vector<wfp::IpAddress> &tunnelDnsServers
 )
@@ -224,6 +262,8 @@ bool FwContext::applyPolicyConnected
 		TranslateProtocol(relay.protocol)
 	));
 
+	AppendAllowedEndpointRules(ruleset, allowedEndpoint);
+
 	ruleset.emplace_back(std::make_unique<baseline::PermitVpnTunnel>(
 		tunnelInterfaceAlias
 	));
@@ -242,9 +282,13 @@ bool FwContext::applyPolicyConnected
 	return status;
 }
 
-bool FwContext::applyPolicyBlocked(const WinFwSettings &settings)
+bool FwContext::applyPolicyBlocked
+(
+	const WinFwSettings &settings,
+	const std::optional<WinFwEndpoint> &allowedEndpoint
+)
 {
-	const auto status = applyRuleset(composePolicyBlocked(settings));
+	const auto status = applyRuleset(composePolicyBlocked(settings, allowedEndpoint));
 
 	if (status)
 	{
@@ -274,12 +318,17 @@ FwContext::Policy FwContext::activePolicy() const
 	return m_activePolicy;
 }
 
-FwContext::Ruleset FwContext::composePolicyBlocked(const WinFwSettings &settings)
+FwContext::Ruleset FwContext::composePolicyBlocked
+(
+	const WinFwSettings &settings,
+	const std::optional<WinFwEndpoint> &allowedEndpoint
+)
 {
 	Ruleset ruleset;
 
 	AppendNetBlockedRules(ruleset);
 	AppendSettingsRules(ruleset, settings);
+	AppendAllowedEndpointRules(ruleset, allowedEndpoint);
 
 	return ruleset;
 }
@@ -308,7 +357,7 @@ bool FwContext::applyBlockedBaseConfiguration(const WinFwSettings &settings, uin
 		//
 		checkpoint = controller.peekCheckpoint();
 
-		return applyRulesetDirectly(composePolicyBlocked(settings), controller);
+		return applyRulesetDirectly(composePolicyBlocked(settings, std::nullopt), controller);
 	});
 }
 
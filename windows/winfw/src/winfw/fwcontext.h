@@ -28,6 +28,7 @@ public:
 	(
 		const WinFwSettings &settings,
 		const WinFwRelay &relay,
+		const std::optional<WinFwEndpoint> &allowedEndpoint,
 		const std::optional<PingableHosts> &pingableHosts
 	);
 
@@ -35,11 +36,16 @@ public:
 	(
 		const WinFwSettings &settings,
 		const WinFwRelay &relay,
+		const std::optional<WinFwEndpoint> &allowedEndpoint,
 		const std::wstring &tunnelInterfaceAlias,
 		const std::vector<wfp::IpAddress> &tunnelDnsServers
 	);
 
-	bool applyPolicyBlocked(const WinFwSettings &settings);
+	bool applyPolicyBlocked
+	(
+		const WinFwSettings &settings,
+		const std::optional<WinFwEndpoint> &allowedEndpoint
+	);
 
 	bool reset();
 
@@ -60,7 +66,11 @@ private:
 	FwContext(const FwContext &) = delete;
 	FwContext &operator=(const FwContext &) = delete;
 
-	Ruleset composePolicyBlocked(const WinFwSettings &settings);
+	Ruleset composePolicyBlocked
+	(
+		const WinFwSettings &settings,
+		const std::optional<WinFwEndpoint> &allowedEndpoint
+	);
 
 	bool applyBaseConfiguration();
 	bool applyBlockedBaseConfiguration(const WinFwSettings &settings, uint32_t &checkpoint);
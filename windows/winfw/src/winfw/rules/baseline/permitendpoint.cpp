@@ -0,0 +1,81 @@
+#include "stdafx.h"
+#include "permitendpoint.h"
+#include <winfw/mullvadguids.h>
+#include <libwfp/filterbuilder.h>
+#include <libwfp/conditionbuilder.h>
+#include <libwfp/conditions/conditionprotocol.h>
+#include <libwfp/conditions/conditionip.h>
+#include <libwfp/conditions/conditionport.h>
+#include <libcommon/error.h>
+
+using namespace wfp::conditions;
+
+namespace rules::baseline
+{
+
+namespace
+{
+
+const GUID &LayerFromIp(const wfp::IpAddress &ip)
+{
+	switch (ip.type())
+	{
+		case wfp::IpAddress::Type::Ipv4: return FWPM_LAYER_ALE_AUTH_CONNECT_V4;
+		case wfp::IpAddress::Type::Ipv6: return FWPM_LAYER_ALE_AUTH_CONNECT_V6;
+		default:
+		{
+			THROW_ERROR("Missing case handler in switch clause");
+		}
+	};
+}
+
+std::unique_ptr<ConditionProtocol> CreateProtocolCondition(PermitEndpoint::Protocol protocol)
+{
+	switch (protocol)
+	{
+		case PermitEndpoint::Protocol::Tcp: return ConditionProtocol::Tcp();
+		case PermitEndpoint::Protocol::Udp: return ConditionProtocol::Udp();
+		default:
+		{
+			THROW_ERROR("Missing case handler in switch clause");
+		}
+	};
+}
+
+} // anonymous namespace
+
+PermitEndpoint::PermitEndpoint(const wfp::IpAddress &address, uint16_t port, Protocol protocol)
+	: m_address(address)
+	, m_port(port)
+	, m_protocol(protocol)
+{
+}
+
+bool PermitEndpoint::apply(IObjectInstaller &objectInstaller)
+{
+	wfp::FilterBuilder filterBuilder;
+
+	//
+	// #1 Permit outbound connections to endpoint.
+	//
+
+	filterBuilder
+		.key(MullvadGuids::Filter_Baseline_PermitEndpoint())
+		.name(L"Permit outbound connections to allowed endpoint")
+		.description(L"This filter is part of a rule that permits communication with an endpoint outside the tunnel")
+		.provider(MullvadGuids::Provider())
+		.layer(LayerFromIp(m_address))
+		.sublayer(MullvadGuids::SublayerBaseline())
+		.weight(wfp::FilterBuilder::WeightClass::Max)
+		.permit();
+
+	wfp::ConditionBuilder conditionBuilder(LayerFromIp(m_address));
+
+	conditionBuilder.add_condition(ConditionIp::Remote(m_address));
+	conditionBuilder.add_condition(ConditionPort::Remote(m_port));
+	conditionBuilder.add_condition(CreateProtocolCondition(m_protocol));
+
+	return objectInstaller.addFilter(filterBuilder, conditionBuilder);
+}
+
+}
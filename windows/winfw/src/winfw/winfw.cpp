@@ -42,6 +42,21 @@ std::optional<FwContext::PingableHosts> ConvertPingableHosts(const PingableHosts
 	return converted;
 }
 
+std::optional<WinFwEndpoint> ConvertEndpoint(const WinFwEndpoint *endpoint)
+{
+	if (nullptr == endpoint)
+	{
+		return {};
+	}
+
+	if (nullptr == endpoint->ip)
+	{
+		THROW_ERROR("Invalid WinFwEndpoint structure");
+	}
+
+	return *endpoint;
+}
+
 } // anonymous namespace
 
 WINFW_LINKAGE
@@ -181,6 +196,7 @@ WINFW_API
 WinFw_ApplyPolicyConnecting(
 	const WinFwSettings *settings,
 	const WinFwRelay *relay,
+	const WinFwEndpoint *allowedEndpoint,
 	const PingableHosts *pingableHosts
 )
 {
@@ -201,7 +217,12 @@ WinFw_ApplyPolicyConnecting(
 			THROW_ERROR("Invalid argument: relay");
 		}
 
-		return g_fwContext->applyPolicyConnecting(*settings, *relay, ConvertPingableHosts(pingableHosts));
+		return g_fwContext->applyPolicyConnecting(
+			*settings,
+			*relay,
+			ConvertEndpoint(allowedEndpoint),
+			ConvertPingableHosts(pingableHosts)
+		);
 	}
 	catch (std::exception &err)
 	{
@@ -224,6 +245,7 @@ WINFW_API
 WinFw_ApplyPolicyConnected(
 	const WinFwSettings *settings,
 	const WinFwRelay *relay,
+	const WinFwEndpoint *allowedEndpoint,
 	const wchar_t *tunnelInterfaceAlias,
 	const wchar_t *v4DnsHost,
 	const wchar_t *v6DnsHost
@@ -266,6 +288,7 @@ WinFw_ApplyPolicyConnected(
 		return g_fwContext->applyPolicyConnected(
 			*settings,
 			*relay,
+			ConvertEndpoint(allowedEndpoint),
 			tunnelInterfaceAlias,
 			tunnelDnsServers
 		);
@@ -289,7 +312,8 @@ WINFW_LINKAGE
 bool
 WINFW_API
 WinFw_ApplyPolicyBlocked(
-	const WinFwSettings *settings
+	const WinFwSettings *settings,
+	const WinFwEndpoint *allowedEndpoint
 )
 {
 	if (nullptr == g_fwContext)
@@ -304,7 +328,7 @@ WinFw_ApplyPolicyBlocked(
 			THROW_ERROR("Invalid argument: settings");
 		}
 
-		return g_fwContext->applyPolicyBlocked(*settings);
+		return g_fwContext->applyPolicyBlocked(*settings, ConvertEndpoint(allowedEndpoint));
 	}
 	catch (std::exception &err)
 	{
@@ -45,6 +45,14 @@ typedef struct tag_WinFwRelay
 }
 WinFwRelay;
 
+typedef struct tag_WinFwEndpoint
+{
+	const wchar_t *ip;
+	uint16_t port;
+	WinFwProtocol protocol;
+}
+WinFwEndpoint;
+
 #pragma pack(pop)
 
 ///////////////////////////////////////////////////////////////////////////////
@@ -138,6 +146,7 @@ PingableHosts;
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
 // - Communication with the relay server
+// - Communication with the allowed endpoint, if one is specified
 // - ICMP (for ping) to/from tunnel gateway
 //
 extern "C"
@@ -147,6 +156,7 @@ WINFW_API
 WinFw_ApplyPolicyConnecting(
 	const WinFwSettings *settings,
 	const WinFwRelay *relay,
+	const WinFwEndpoint *allowedEndpoint,
 	const PingableHosts *pingableHosts
 );
 
@@ -156,11 +166,14 @@ WinFw_ApplyPolicyConnecting(
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
 // - Communication with the relay server
+// - Communication with the allowed endpoint, if one is specified
 // - Non-DNS traffic inside the VPN tunnel
 // - DNS requests inside the VPN tunnel, to the specified DNS server
 //
 // Parameters:
 //
+// allowedEndpoint:
+//   Optional endpoint that may be reached outside the tunnel
 // tunnelInterfaceAlias:
 //   Friendly name of VPN tunnel interface
 // v4DnsHost/v6DnsHost:
@@ -173,6 +186,7 @@ WINFW_API
 WinFw_ApplyPolicyConnected(
 	const WinFwSettings *settings,
 	const WinFwRelay *relay,
+	const WinFwEndpoint *allowedEndpoint,
 	const wchar_t *tunnelInterfaceAlias,
 	const wchar_t *v4DnsHost,
 	const wchar_t *v6DnsHost
@@ -183,13 +197,15 @@ WinFw_ApplyPolicyConnected(
 //
 // Apply restrictions in the firewall that block all traffic, except:
 // - What is specified by settings
+// - Communication with the allowed endpoint, if one is specified
 //
 extern "C"
 WINFW_LINKAGE
 bool
 WINFW_API
 WinFw_ApplyPolicyBlocked(
-	const WinFwSettings *settings
+	const WinFwSettings *settings,
+	const WinFwEndpoint *allowedEndpoint
 );
 
 //
@@ -13,6 +13,7 @@ import (
 	"bufio"
 	"bytes"
 	"runtime"
+	"strings"
 	"unsafe"
 
 	"github.com/mullvad/mullvadvpn-app/wireguard/libwg/tunnelcontainer"
@@ -61,6 +62,26 @@ func wgGetConfig(tunnelHandle int32) *C.char {
 	return C.CString(settings.String())
 }
 
+//export wgSetConfig
+func wgSetConfig(tunnelHandle int32, cSettings *C.char) int32 {
+	tunnel, err := tunnels.Get(tunnelHandle)
+	if err != nil {
+		return ERROR_GENERAL_FAILURE
+	}
+	if cSettings == nil {
+		tunnel.Logger.Error.Println("cSettings is null")
+		return ERROR_GENERAL_FAILURE
+	}
+	settings := C.GoString(cSettings)
+
+	setErr := tunnel.Device.IpcSetOperation(bufio.NewReader(strings.NewReader(settings)))
+	if setErr != nil {
+		tunnel.Logger.Error.Println("Failed to set config for tunnel: ", setErr)
+		return ERROR_GENERAL_FAILURE
+	}
+	return 0
+}
+
 //export wgFreePtr
 func wgFreePtr(ptr unsafe.Pointer) {
 	C.free(ptr)